@@ -1,4 +1,4 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
 use skylock_hybrid::backup;
 use std::path::PathBuf;
 
@@ -76,10 +76,25 @@ fn compression_performance(c: &mut Criterion) {
     group.finish();
 }
 
+fn hashing_performance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hashing_operations");
+    let data: Vec<u8> = (0..16 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    group.throughput(Throughput::Bytes(data.len() as u64));
+
+    for algorithm in [skylock_backup::HashAlgorithm::Sha256, skylock_backup::HashAlgorithm::Blake3] {
+        group.bench_function(format!("hash_16mb_{}", algorithm), |b| {
+            b.iter(|| algorithm.hash(&data))
+        });
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     backup_performance,
     encryption_performance,
-    compression_performance
+    compression_performance,
+    hashing_performance
 );
 criterion_main!(benches);
\ No newline at end of file
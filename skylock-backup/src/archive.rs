@@ -0,0 +1,467 @@
+//! Streaming archive format for archive backups
+//!
+//! An archive backup is a tar of the backup paths, compressed with zstd and
+//! encrypted in fixed-size segments, produced and consumed as a stream so
+//! memory use stays at a few buffers whatever the size of the backup:
+//!
+//! ```text
+//! "SKYARC01" | segment size (u32 LE) | frame | frame | ...
+//! frame = header (u32 LE: bit 31 = last, bits 0-30 = length) | nonce | ciphertext+tag
+//! ```
+//!
+//! Each segment is encrypted on its own with AAD binding the backup ID, its
+//! index and whether it is the last one, so segments can't be reordered,
+//! moved between backups or dropped from the end without detection.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::encryption::EncryptionManager;
+use crate::error::{Result, SkylockError};
+
+/// Magic bytes at the start of a streamed archive
+pub const ARCHIVE_MAGIC: &[u8; 8] = b"SKYARC01";
+
+/// Plaintext bytes per encrypted segment of archives
+pub const SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// Smallest segment size a writer uses
+pub const MIN_SEGMENT_SIZE: usize = skylock_core::MIN_AEAD_FRAME_SIZE as usize;
+
+/// Largest segment size a writer uses or a reader accepts, bounding their
+/// buffers
+pub const MAX_SEGMENT_SIZE: usize = skylock_core::MAX_AEAD_FRAME_SIZE as usize;
+
+/// Segments that may be encrypted under one key: each gets a random 96-bit
+/// nonce, and past 2^32 messages the chance of a repeat exceeds 2^-32
+/// (NIST SP 800-38D)
+pub const MAX_SEGMENTS_PER_KEY: u64 = 1 << 32;
+
+/// Nonce and authentication tag added to each segment
+const SEGMENT_OVERHEAD: usize = 12 + 16;
+
+/// Bytes before the first frame: the magic and the segment size
+pub const HEADER_SIZE: usize = ARCHIVE_MAGIC.len() + 4;
+
+/// Bytes a frame adds to its segment: the frame header, nonce and tag
+pub const FRAME_OVERHEAD: usize = 4 + SEGMENT_OVERHEAD;
+
+/// Frame header flag marking the last segment
+const LAST_SEGMENT: u32 = 1 << 31;
+
+/// zstd level used for archives
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Whether `data` starts like a streamed archive (older archives are one
+/// AEAD message over the whole compressed tar)
+pub fn is_streamed_archive(data: &[u8]) -> bool {
+    data.starts_with(ARCHIVE_MAGIC)
+}
+
+fn segment_aad(index: u64, last: bool) -> String {
+    format!("archive-segment:{}:{}", index, if last { "last" } else { "more" })
+}
+
+/// Decrypt the ciphertext of segment `index`, failing with `InvalidData` if
+/// it doesn't authenticate
+fn decrypt_segment(
+    encryption: &EncryptionManager,
+    backup_id: &str,
+    index: u64,
+    last: bool,
+    ciphertext: &[u8],
+) -> io::Result<Vec<u8>> {
+    encryption
+        .decrypt_with_aad(ciphertext, backup_id, &segment_aad(index, last))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Archive segment {}: {}", index, e)))
+}
+
+/// Decrypt a single frame read out of a stream, which must be segment
+/// `index` and the last one exactly when `last` is set
+///
+/// Lets a reader that knows the segment layout fetch only the frames it
+/// needs; each still authenticates on its own.
+pub fn open_segment(
+    frame: &[u8],
+    encryption: &EncryptionManager,
+    backup_id: &str,
+    index: u64,
+    last: bool,
+) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid frame of archive segment {}", index));
+    if frame.len() < 4 {
+        return Err(invalid());
+    }
+    let (header, ciphertext) = frame.split_at(4);
+    let header = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    if (header & LAST_SEGMENT != 0) != last || (header & !LAST_SEGMENT) as usize != ciphertext.len() {
+        return Err(invalid());
+    }
+    decrypt_segment(encryption, backup_id, index, last, ciphertext)
+}
+
+/// Encrypts everything written to it in segments, see the module docs
+pub struct SegmentWriter<W: Write> {
+    inner: W,
+    encryption: Arc<EncryptionManager>,
+    backup_id: String,
+    segment_size: usize,
+    buffer: Vec<u8>,
+    index: u64,
+}
+
+impl<W: Write> SegmentWriter<W> {
+    /// Write the archive header to `inner`
+    pub fn new(inner: W, encryption: Arc<EncryptionManager>, backup_id: &str) -> io::Result<Self> {
+        Self::with_segment_size(inner, encryption, backup_id, SEGMENT_SIZE)
+    }
+
+    /// Write the header of a stream of `segment_size` segments to `inner`;
+    /// fails with `InvalidInput` outside [`MIN_SEGMENT_SIZE`]..=[`MAX_SEGMENT_SIZE`]
+    pub fn with_segment_size(
+        mut inner: W,
+        encryption: Arc<EncryptionManager>,
+        backup_id: &str,
+        segment_size: usize,
+    ) -> io::Result<Self> {
+        if !(MIN_SEGMENT_SIZE..=MAX_SEGMENT_SIZE).contains(&segment_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid archive segment size {}", segment_size),
+            ));
+        }
+        inner.write_all(ARCHIVE_MAGIC)?;
+        inner.write_all(&(segment_size as u32).to_le_bytes())?;
+        Ok(Self {
+            inner,
+            encryption,
+            backup_id: backup_id.to_string(),
+            segment_size,
+            buffer: Vec::with_capacity(segment_size),
+            index: 0,
+        })
+    }
+
+    fn write_segment(&mut self, last: bool) -> io::Result<()> {
+        if self.index >= MAX_SEGMENTS_PER_KEY {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("More than {} segments under one key", MAX_SEGMENTS_PER_KEY),
+            ));
+        }
+        let ciphertext = self.encryption
+            .encrypt_with_aad(&self.buffer, &self.backup_id, &segment_aad(self.index, last))
+            .map_err(io::Error::other)?;
+        let mut header = ciphertext.len() as u32;
+        if last {
+            header |= LAST_SEGMENT;
+        }
+        let mut frame = Vec::with_capacity(4 + ciphertext.len());
+        frame.extend_from_slice(&header.to_le_bytes());
+        frame.extend_from_slice(&ciphertext);
+        self.inner.write_all(&frame)?;
+        self.buffer.clear();
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Write the last segment and return the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_segment(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for SegmentWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // A full segment is only written once more data arrives, so the
+        // last one can always be marked as such in finish()
+        if self.buffer.len() == self.segment_size {
+            self.write_segment(false)?;
+        }
+        let n = buf.len().min(self.segment_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts a stream written by [`SegmentWriter`]
+///
+/// Fails with `InvalidData` on a segment that doesn't authenticate, and
+/// with `UnexpectedEof` if the stream ends before the last segment.
+pub struct SegmentReader<R: Read> {
+    inner: R,
+    encryption: Arc<EncryptionManager>,
+    backup_id: String,
+    segment_size: usize,
+    plaintext: Vec<u8>,
+    position: usize,
+    index: u64,
+    done: bool,
+}
+
+impl<R: Read> SegmentReader<R> {
+    /// Read and check the archive header
+    pub fn new(mut inner: R, encryption: Arc<EncryptionManager>, backup_id: &str) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        inner.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a streamed skylock archive"));
+        }
+        let mut size = [0u8; 4];
+        inner.read_exact(&mut size)?;
+        let segment_size = u32::from_le_bytes(size) as usize;
+        if segment_size == 0 || segment_size > MAX_SEGMENT_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid archive segment size {}", segment_size),
+            ));
+        }
+        Ok(Self {
+            inner,
+            encryption,
+            backup_id: backup_id.to_string(),
+            segment_size,
+            plaintext: Vec::new(),
+            position: 0,
+            index: 0,
+            done: false,
+        })
+    }
+
+    /// Plaintext bytes per segment, as recorded in the header
+    pub fn segment_size(&self) -> usize {
+        self.segment_size
+    }
+
+    fn read_segment(&mut self) -> io::Result<()> {
+        let mut header = [0u8; 4];
+        self.inner.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Archive truncated after {} segments", self.index),
+            ),
+            _ => e,
+        })?;
+        let header = u32::from_le_bytes(header);
+        let last = header & LAST_SEGMENT != 0;
+        let length = (header & !LAST_SEGMENT) as usize;
+        if length < SEGMENT_OVERHEAD || length > self.segment_size + SEGMENT_OVERHEAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid length {} of archive segment {}", length, self.index),
+            ));
+        }
+
+        let mut ciphertext = vec![0u8; length];
+        self.inner.read_exact(&mut ciphertext)?;
+        self.plaintext = decrypt_segment(&self.encryption, &self.backup_id, self.index, last, &ciphertext)?;
+        self.position = 0;
+        self.index += 1;
+
+        if last {
+            self.done = true;
+            if self.inner.read(&mut [0u8; 1])? != 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Data after the last archive segment"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for SegmentReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.read_segment()?;
+        }
+        let n = buf.len().min(self.plaintext.len() - self.position);
+        buf[..n].copy_from_slice(&self.plaintext[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Builds a streamed archive: tar → zstd → [`SegmentWriter`] → `W`
+pub struct ArchiveWriter<W: Write> {
+    builder: tar::Builder<zstd::Encoder<'static, SegmentWriter<W>>>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(inner: W, encryption: Arc<EncryptionManager>, backup_id: &str) -> Result<Self> {
+        let segments = SegmentWriter::new(inner, encryption, backup_id)
+            .map_err(|e| SkylockError::Backup(format!("Failed to write archive header: {}", e)))?;
+        let encoder = zstd::Encoder::new(segments, COMPRESSION_LEVEL)
+            .map_err(|e| SkylockError::Backup(format!("Failed to create zstd encoder: {}", e)))?;
+        Ok(Self { builder: tar::Builder::new(encoder) })
+    }
+
+    /// Add a file or directory tree under `name`
+    pub fn append_path(&mut self, name: &str, path: &Path) -> Result<()> {
+        if path.is_dir() {
+            self.builder.append_dir_all(name, path)
+                .map_err(|e| SkylockError::Backup(format!("Failed to add dir to tar: {}", e)))
+        } else {
+            let mut file = std::fs::File::open(path)
+                .map_err(|e| SkylockError::Backup(format!("Failed to open file: {}", e)))?;
+            self.builder.append_file(name, &mut file)
+                .map_err(|e| SkylockError::Backup(format!("Failed to add file to tar: {}", e)))
+        }
+    }
+
+    /// Add a regular file of `size` bytes read from `data`
+    pub fn append_data<R: Read>(&mut self, name: &str, size: u64, data: R) -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+        header.set_entry_type(tar::EntryType::Regular);
+        self.builder.append_data(&mut header, name, data)
+            .map_err(|e| SkylockError::Backup(format!("Failed to add {} to tar: {}", name, e)))
+    }
+
+    /// Finish the tar, compression and encryption, returning `W`
+    pub fn finish(self) -> Result<W> {
+        let encoder = self.builder.into_inner()
+            .map_err(|e| SkylockError::Backup(format!("Failed to finalize tar: {}", e)))?;
+        let segments = encoder.finish()
+            .map_err(|e| SkylockError::Backup(format!("Failed to finish compression: {}", e)))?;
+        segments.finish()
+            .map_err(|e| SkylockError::Backup(format!("Failed to finish encryption: {}", e)))
+    }
+}
+
+/// Open a streamed archive for reading: `R` → [`SegmentReader`] → zstd → tar
+pub fn open_archive<R: Read>(
+    input: R,
+    encryption: Arc<EncryptionManager>,
+    backup_id: &str,
+) -> Result<tar::Archive<impl Read>> {
+    let segments = SegmentReader::new(input, encryption, backup_id)
+        .map_err(|e| SkylockError::Backup(format!("Failed to read archive header: {}", e)))?;
+    let decoder = zstd::Decoder::new(segments)
+        .map_err(|e| SkylockError::Backup(format!("Failed to create zstd decoder: {}", e)))?;
+    Ok(tar::Archive::new(decoder))
+}
+
+/// Extract a streamed archive into `target`
+pub fn extract_archive<R: Read>(
+    input: R,
+    encryption: Arc<EncryptionManager>,
+    backup_id: &str,
+    target: &Path,
+) -> Result<()> {
+    open_archive(input, encryption, backup_id)?
+        .unpack(target)
+        .map_err(|e| SkylockError::Backup(format!("Failed to extract archive: {}", e)))
+}
+
+/// `Write` end of a bounded channel, for producing a stream body on a
+/// blocking thread; each write becomes one item
+///
+/// Writes block while the channel is full, and fail with `BrokenPipe` once
+/// the receiver is gone.
+pub struct ChannelWriter {
+    sender: tokio::sync::mpsc::Sender<io::Result<Vec<u8>>>,
+    written: u64,
+}
+
+impl ChannelWriter {
+    pub fn new(sender: tokio::sync::mpsc::Sender<io::Result<Vec<u8>>>) -> Self {
+        Self { sender, written: 0 }
+    }
+
+    /// Bytes written so far
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Send `error` to the receiver, so a consumer such as an upload fails
+    /// instead of taking what was sent as complete
+    pub fn abort(&self, error: io::Error) {
+        let _ = self.sender.blocking_send(Err(error));
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender.blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Upload stopped"))?;
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encryption() -> Arc<EncryptionManager> {
+        Arc::new(EncryptionManager::new("test_password").unwrap())
+    }
+
+    fn segments(data: &[u8], encryption: &Arc<EncryptionManager>) -> Vec<u8> {
+        let mut writer = SegmentWriter::new(Vec::new(), encryption.clone(), "backup_1").unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn read_all(data: &[u8], encryption: &Arc<EncryptionManager>, backup_id: &str) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        SegmentReader::new(data, encryption.clone(), backup_id)?.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn test_segments_round_trip() {
+        let encryption = encryption();
+        // Empty, partial, exactly one and several segments
+        for size in [0, 100, SEGMENT_SIZE, 2 * SEGMENT_SIZE + 7] {
+            let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            let encrypted = segments(&data, &encryption);
+            assert!(is_streamed_archive(&encrypted));
+            assert_eq!(read_all(&encrypted, &encryption, "backup_1").unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_tampered_segments_rejected() {
+        let encryption = encryption();
+        let data = vec![7u8; 2 * SEGMENT_SIZE + 7];
+        let encrypted = segments(&data, &encryption);
+        let frame = 4 + SEGMENT_SIZE + SEGMENT_OVERHEAD;
+
+        // Dropping trailing segments
+        let truncated = &encrypted[..12 + 2 * frame];
+        let err = read_all(truncated, &encryption, "backup_1").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Swapping two segments
+        let mut swapped = encrypted[..12].to_vec();
+        swapped.extend_from_slice(&encrypted[12 + frame..12 + 2 * frame]);
+        swapped.extend_from_slice(&encrypted[12..12 + frame]);
+        swapped.extend_from_slice(&encrypted[12 + 2 * frame..]);
+        assert_eq!(read_all(&swapped, &encryption, "backup_1").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Another backup's archive, or a flipped bit
+        assert!(read_all(&encrypted, &encryption, "backup_2").is_err());
+        let mut flipped = encrypted.clone();
+        flipped[100] ^= 1;
+        assert!(read_all(&flipped, &encryption, "backup_1").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Advisory lock against concurrent backups to the same destination
+//!
+//! A backup creates `<data_dir>/locks/<destination hash>.lock` before it
+//! touches the storage box and removes it when it finishes, fails, panics or
+//! is shut down. A second run finding the lock fails fast. Locks left by a
+//! crashed process are reclaimed once their process has exited, or once
+//! older than the stale timeout where that can't be checked.
+//!
+//! The lock file is written in full under a temporary name and hard-linked
+//! into place, so it never exists half-written. A stale lock is renamed
+//! aside before it is removed, and put back if what was renamed turns out
+//! to be a newer lock, so two runs reclaiming the same lock can't both
+//! succeed.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+use crate::error::{Result, SkylockError};
+
+/// Age after which a lock is assumed to belong to a crashed backup, even if
+/// a process with its PID is running
+pub const STALE_LOCK_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Contents of a lock file, identifying the backup holding it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockInfo {
+    /// Storage destination the lock covers
+    pub destination: String,
+    /// Process holding the lock
+    pub pid: u32,
+    /// When the lock was taken
+    pub acquired_at: DateTime<Utc>,
+}
+
+impl LockInfo {
+    /// Whether the backup that took the lock is gone: its process has
+    /// exited, or the lock is older than `stale_after`
+    fn is_stale(&self, stale_after: Duration) -> bool {
+        let age = (Utc::now() - self.acquired_at).to_std().unwrap_or_default();
+        age >= stale_after || process_alive(self.pid) == Some(false)
+    }
+}
+
+/// Whether a process with this PID is running, if that can be checked
+#[cfg(unix)]
+fn process_alive(pid: u32) -> Option<bool> {
+    let pid = libc::pid_t::try_from(pid).ok()?;
+    // Signal 0 only checks that the process exists
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return Some(true);
+    }
+    match std::io::Error::last_os_error().raw_os_error() {
+        Some(libc::ESRCH) => Some(false),
+        // Exists, but belongs to another user
+        Some(libc::EPERM) => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> Option<bool> {
+    None
+}
+
+/// Held backup lock, released when dropped
+#[derive(Debug)]
+pub struct BackupLock {
+    path: PathBuf,
+}
+
+impl BackupLock {
+    /// Directory holding lock files below the configured data dir
+    pub fn lock_dir(data_dir: &Path) -> PathBuf {
+        data_dir.join("locks")
+    }
+
+    /// Lock file path for a destination
+    pub fn lock_file_path(data_dir: &Path, destination: &str) -> PathBuf {
+        let hash = format!("{:x}", Sha256::digest(destination.as_bytes()));
+        Self::lock_dir(data_dir).join(format!("{}.lock", &hash[..16]))
+    }
+
+    /// Take the lock for `destination`, failing with
+    /// [`SkylockError::Locked`] while another backup holds it
+    ///
+    /// A lock whose process has exited, one older than `stale_after`, or one
+    /// that can't be read is reclaimed with a warning.
+    pub fn acquire(data_dir: &Path, destination: &str, stale_after: Duration) -> Result<Self> {
+        let path = Self::lock_file_path(data_dir, destination);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| SkylockError::Backup(format!("Failed to create lock directory: {}", e)))?;
+        }
+
+        let info = LockInfo {
+            destination: destination.to_string(),
+            pid: std::process::id(),
+            acquired_at: Utc::now(),
+        };
+        let json = serde_json::to_vec_pretty(&info)
+            .map_err(|e| SkylockError::Backup(format!("Failed to serialize lock: {}", e)))?;
+
+        let staged = path.with_extension(format!("lock.{}.tmp", info.pid));
+        std::fs::write(&staged, &json)
+            .map_err(|e| SkylockError::Backup(format!("Failed to write lock file: {}", e)))?;
+        let result = Self::link_lock(&staged, &path, destination, stale_after);
+        let _ = std::fs::remove_file(&staged);
+        result
+    }
+
+    /// Link the complete lock file `staged` to `path`, reclaiming a stale
+    /// lock found there; a few retries cover other runs reclaiming at the
+    /// same time
+    fn link_lock(staged: &Path, path: &Path, destination: &str, stale_after: Duration) -> Result<Self> {
+        for _ in 0..3 {
+            match std::fs::hard_link(staged, path) {
+                Ok(()) => return Ok(Self { path: path.to_path_buf() }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(SkylockError::Backup(format!("Failed to create lock file: {}", e))),
+            }
+
+            let Some(contents) = Self::read_contents(path)? else {
+                continue;
+            };
+            if let Some(holder) = serde_json::from_slice::<LockInfo>(&contents).ok().filter(|holder| !holder.is_stale(stale_after)) {
+                return Err(SkylockError::Locked(format!(
+                    "another backup is in progress to {} (pid {}, started {})",
+                    destination, holder.pid,
+                    holder.acquired_at.format("%Y-%m-%d %H:%M:%S UTC")
+                )));
+            }
+            Self::reclaim(path, &contents, destination)?;
+        }
+
+        Err(SkylockError::Locked(format!("another backup is in progress to {}", destination)))
+    }
+
+    /// Remove the stale lock at `path`, which held `contents`
+    ///
+    /// The lock is renamed aside first. If another run replaced it since it
+    /// was read, the renamed file is that run's lock and is linked back.
+    fn reclaim(path: &Path, contents: &[u8], destination: &str) -> Result<()> {
+        let aside = path.with_extension(format!("lock.{}.stale", std::process::id()));
+        match std::fs::rename(path, &aside) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(SkylockError::Backup(format!("Failed to remove stale lock file: {}", e))),
+        }
+
+        let renamed = Self::read_contents(&aside)?;
+        if renamed.as_deref() == Some(contents) {
+            tracing::warn!("Reclaiming stale backup lock {}", path.display());
+            println!("⚠️  Reclaiming stale lock left by an earlier backup to {}", destination);
+        } else if let Err(e) = std::fs::hard_link(&aside, path) {
+            // Only fails if yet another run took the lock meanwhile
+            tracing::warn!("Failed to put back backup lock {}: {}", path.display(), e);
+        }
+        std::fs::remove_file(&aside)
+            .map_err(|e| SkylockError::Backup(format!("Failed to remove stale lock file: {}", e)))
+    }
+
+    /// Contents of the lock file at `path`, or `None` if there is none
+    fn read_contents(path: &Path) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(SkylockError::Backup(format!("Failed to read lock file: {}", e))),
+        }
+    }
+
+    /// Holder of the lock at `path`, if it can be read
+    #[cfg(test)]
+    fn read_info(path: &Path) -> Option<LockInfo> {
+        let json = std::fs::read(path).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// Path of the held lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for BackupLock {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!("Failed to release backup lock {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const DESTINATION: &str = "u123456@https://u123456.your-storagebox.de";
+
+    #[test]
+    fn test_second_lock_rejected_until_released() {
+        let data_dir = TempDir::new().unwrap();
+        let lock = BackupLock::acquire(data_dir.path(), DESTINATION, STALE_LOCK_AFTER).unwrap();
+        assert!(lock.path().exists());
+
+        let second = BackupLock::acquire(data_dir.path(), DESTINATION, STALE_LOCK_AFTER);
+        assert!(matches!(second, Err(SkylockError::Locked(_))));
+
+        // Other destinations are independent
+        let other = BackupLock::acquire(data_dir.path(), "u999@https://other.example", STALE_LOCK_AFTER).unwrap();
+
+        let path = lock.path().to_path_buf();
+        drop(lock);
+        assert!(!path.exists());
+        BackupLock::acquire(data_dir.path(), DESTINATION, STALE_LOCK_AFTER).unwrap();
+        drop(other);
+    }
+
+    #[test]
+    fn test_stale_lock_reclaimed() {
+        let data_dir = TempDir::new().unwrap();
+        let path = BackupLock::lock_file_path(data_dir.path(), DESTINATION);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let stale = LockInfo {
+            destination: DESTINATION.to_string(),
+            pid: 1,
+            acquired_at: Utc::now() - chrono::Duration::hours(2),
+        };
+        std::fs::write(&path, serde_json::to_vec(&stale).unwrap()).unwrap();
+
+        // Still fresh against a longer timeout
+        assert!(BackupLock::acquire(data_dir.path(), DESTINATION, Duration::from_secs(3 * 3600)).is_err());
+
+        let lock = BackupLock::acquire(data_dir.path(), DESTINATION, Duration::from_secs(3600)).unwrap();
+        let holder = BackupLock::read_info(lock.path()).unwrap();
+        assert_eq!(holder.pid, std::process::id());
+
+        // Unreadable leftovers are reclaimed too
+        drop(lock);
+        std::fs::write(&path, b"garbage").unwrap();
+        BackupLock::acquire(data_dir.path(), DESTINATION, STALE_LOCK_AFTER).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock_of_exited_process_reclaimed() {
+        let data_dir = TempDir::new().unwrap();
+        let path = BackupLock::lock_file_path(data_dir.path(), DESTINATION);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let exited = child.id();
+        child.wait().unwrap();
+        let fresh = |pid| LockInfo { destination: DESTINATION.to_string(), pid, acquired_at: Utc::now() };
+
+        // A fresh lock of a running process holds
+        std::fs::write(&path, serde_json::to_vec(&fresh(std::process::id())).unwrap()).unwrap();
+        assert!(matches!(
+            BackupLock::acquire(data_dir.path(), DESTINATION, STALE_LOCK_AFTER),
+            Err(SkylockError::Locked(_))
+        ));
+
+        // One whose process has exited doesn't wait for the timeout
+        std::fs::write(&path, serde_json::to_vec(&fresh(exited)).unwrap()).unwrap();
+        let lock = BackupLock::acquire(data_dir.path(), DESTINATION, STALE_LOCK_AFTER).unwrap();
+        assert_eq!(BackupLock::read_info(lock.path()).unwrap().pid, std::process::id());
+        assert_eq!(std::fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_reclaim_puts_back_a_newer_lock() {
+        let data_dir = TempDir::new().unwrap();
+        let path = BackupLock::lock_file_path(data_dir.path(), DESTINATION);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        // Another run reclaimed the stale lock and took it between reading
+        // and renaming it: its lock stays
+        std::fs::write(&path, b"newer lock").unwrap();
+        BackupLock::reclaim(&path, b"stale lock", DESTINATION).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"newer lock");
+
+        BackupLock::reclaim(&path, b"newer lock", DESTINATION).unwrap();
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_dir(path.parent().unwrap()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_lock_released_on_panic() {
+        let data_dir = TempDir::new().unwrap();
+        let path = BackupLock::lock_file_path(data_dir.path(), DESTINATION);
+
+        let result = std::panic::catch_unwind(|| {
+            let _lock = BackupLock::acquire(data_dir.path(), DESTINATION, STALE_LOCK_AFTER).unwrap();
+            panic!("backup failed");
+        });
+
+        assert!(result.is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,98 @@
+//! Remote key layout for blobs named by a hash
+//!
+//! Encrypted-name files and chunks are stored under hash-derived names. With
+//! thousands of them in one remote directory, listings get slow and some
+//! providers degrade, so keys can fan out over nested directories named after
+//! leading characters of the hash, e.g. `chunks/ab/cd/abcd…`.
+//!
+//! The key is a pure function of the directory and the blob name, and is
+//! recorded in the manifest when the blob is uploaded. Restore and garbage
+//! collection use the recorded key, so changing the depth only affects new
+//! uploads.
+
+/// Hash characters per directory level
+const SHARD_WIDTH: usize = 2;
+
+/// Deepest supported fanout; at two characters per level, four levels
+/// already give 2^32 leaf directories
+pub const MAX_SHARD_DEPTH: usize = 4;
+
+/// How blobs are laid out below their directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlobNaming {
+    /// Every blob directly in its directory
+    #[default]
+    Flat,
+    /// Blobs below `depth` levels of directories named after successive
+    /// two-character prefixes of their name
+    HashPrefix { depth: usize },
+}
+
+impl BlobNaming {
+    /// Layout fanning out over `depth` directory levels (0 = flat), capped
+    /// at [`MAX_SHARD_DEPTH`]
+    pub fn with_depth(depth: usize) -> Self {
+        match depth.min(MAX_SHARD_DEPTH) {
+            0 => BlobNaming::Flat,
+            depth => BlobNaming::HashPrefix { depth },
+        }
+    }
+
+    /// Number of directory levels between the directory and the blob
+    pub fn depth(&self) -> usize {
+        match self {
+            BlobNaming::Flat => 0,
+            BlobNaming::HashPrefix { depth } => *depth,
+        }
+    }
+
+    /// Remote key of the blob `name` below `directory`
+    ///
+    /// `name` should start with the hex hash the blob is named after. Names
+    /// too short for every level get as many levels as they have characters.
+    pub fn key(&self, directory: &str, name: &str) -> String {
+        let mut key = directory.trim_end_matches('/').to_string();
+        let prefix: Vec<char> = name.chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        for shard in prefix.chunks_exact(SHARD_WIDTH).take(self.depth()) {
+            key.push('/');
+            key.extend(shard);
+        }
+        key.push('/');
+        key.push_str(name);
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "3fa9c1d27be0448e5d6f1c2b9a7e8d0c4b5a69788f1e2d3c4b5a697887766554";
+
+    #[test]
+    fn test_key_derivation_is_stable() {
+        let naming = BlobNaming::with_depth(2);
+        let name = format!("{}.zst.enc", HASH);
+        let key = naming.key("/skylock/backups/20240101_020000/chunks", &name);
+        assert_eq!(key, format!("/skylock/backups/20240101_020000/chunks/3f/a9/{}", name));
+        assert_eq!(naming.key("/skylock/backups/20240101_020000/chunks/", &name), key);
+        assert_eq!(BlobNaming::HashPrefix { depth: 2 }.key("/skylock/backups/20240101_020000/chunks", &name), key);
+    }
+
+    #[test]
+    fn test_depths_give_expected_layouts() {
+        let name = format!("{}.enc", HASH);
+        assert_eq!(BlobNaming::with_depth(0), BlobNaming::Flat);
+        assert_eq!(BlobNaming::Flat.key("/blobs", &name), format!("/blobs/{}", name));
+        assert_eq!(BlobNaming::with_depth(1).key("/blobs", &name), format!("/blobs/3f/{}", name));
+        assert_eq!(BlobNaming::with_depth(3).key("/blobs", &name), format!("/blobs/3f/a9/c1/{}", name));
+
+        // Depth is capped, and short names get the levels they can fill
+        assert_eq!(BlobNaming::with_depth(9).depth(), MAX_SHARD_DEPTH);
+        assert_eq!(BlobNaming::with_depth(3).key("/blobs", "abc.enc"), "/blobs/ab/abc.enc");
+        assert_eq!(BlobNaming::with_depth(2).key("/blobs", "AB12.enc"), "/blobs/ab/12/AB12.enc");
+    }
+}
@@ -0,0 +1,268 @@
+//! Size-limited cache of decrypted blocks
+//!
+//! Browsing, previewing and restoring single files from a backup tends to
+//! fetch the same blobs again and again. [`BlockCache`] keeps their verified,
+//! decrypted and decompressed contents in memory so repeated access skips the
+//! download and the decryption. Once the cached blocks exceed the size limit
+//! the least recently used ones are evicted. Evicted, replaced and dropped
+//! blocks are zeroized, together with both copies of their keys, so neither
+//! plaintext nor the paths of the blobs it came from linger in freed memory.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use skylock_core::BackupConfig;
+use tracing::warn;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Default cache size for a browsing session (64MB)
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = skylock_core::DEFAULT_BLOCK_CACHE_SIZE as usize;
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Block contents and the tick of their last use, by remote blob path
+    blocks: HashMap<String, (u64, Vec<u8>)>,
+    /// Remote blob paths by last use, oldest first
+    recency: BTreeMap<u64, String>,
+    total_bytes: usize,
+    next_tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, key: &str) -> Option<&[u8]> {
+        let tick = self.next_tick;
+        let (last_used, data) = self.blocks.get_mut(key)?;
+        // Move the key to its new tick rather than copying it again
+        let recency_key = self.recency.remove(last_used).unwrap_or_else(|| key.to_string());
+        *last_used = tick;
+        self.recency.insert(tick, recency_key);
+        self.next_tick += 1;
+        Some(data)
+    }
+
+    /// Remove the least recently used block, returning its key and contents
+    /// zeroized in place
+    fn evict_lru(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let (_, mut recency_key) = self.recency.pop_first()?;
+        let removed = self.blocks.remove_entry(&recency_key);
+        recency_key.zeroize();
+        let (key, (_, data)) = removed?;
+        Some(self.discard(key, data))
+    }
+
+    fn remove(&mut self, key: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+        let (key, (last_used, data)) = self.blocks.remove_entry(key)?;
+        if let Some(mut recency_key) = self.recency.remove(&last_used) {
+            recency_key.zeroize();
+        }
+        Some(self.discard(key, data))
+    }
+
+    /// Zeroize a removed block's key and contents, keeping their lengths
+    fn discard(&mut self, key: String, mut data: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+        self.total_bytes -= data.len();
+        let mut key = key.into_bytes();
+        key.as_mut_slice().zeroize();
+        data.as_mut_slice().zeroize();
+        (key, data)
+    }
+}
+
+impl Drop for CacheState {
+    fn drop(&mut self) {
+        for (mut key, (_, mut data)) in self.blocks.drain() {
+            key.zeroize();
+            data.zeroize();
+        }
+        for (_, mut key) in std::mem::take(&mut self.recency) {
+            key.zeroize();
+        }
+    }
+}
+
+/// LRU cache of decrypted blocks bounded by their total size
+///
+/// Shared by reference between the operations of a session, e.g. a browser
+/// and the partial restores started from it.
+#[derive(Debug)]
+pub struct BlockCache {
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_BLOCK_CACHE_SIZE)
+    }
+}
+
+impl BlockCache {
+    /// Cache holding at most `max_bytes` of block contents
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Cache sized by `backup.block_cache_size`
+    ///
+    /// An invalid size falls back to the default with a warning;
+    /// `Config::validate` and `check-config` report it.
+    pub fn from_config(config: &BackupConfig) -> Self {
+        let max_bytes = config.block_cache_size_bytes().unwrap_or_else(|e| {
+            warn!("Ignoring backup.block_cache_size: {}", e);
+            skylock_core::DEFAULT_BLOCK_CACHE_SIZE
+        });
+        Self::new(usize::try_from(max_bytes).unwrap_or(usize::MAX))
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Copy of a cached block, marking it as recently used
+    pub fn get(&self, key: &str) -> Option<Zeroizing<Vec<u8>>> {
+        let mut state = self.state();
+        match state.touch(key).map(|data| Zeroizing::new(data.to_vec())) {
+            Some(data) => {
+                state.hits += 1;
+                Some(data)
+            }
+            None => {
+                state.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Cache a block, evicting the least recently used ones to make room
+    ///
+    /// Blocks larger than the whole cache are not kept.
+    pub fn insert(&self, key: &str, data: &[u8]) {
+        let mut state = self.state();
+        state.remove(key);
+        if data.len() > self.max_bytes {
+            return;
+        }
+        while state.total_bytes + data.len() > self.max_bytes {
+            if state.evict_lru().is_none() {
+                break;
+            }
+        }
+
+        let tick = state.next_tick;
+        state.next_tick += 1;
+        state.total_bytes += data.len();
+        state.recency.insert(tick, key.to_string());
+        state.blocks.insert(key.to_string(), (tick, data.to_vec()));
+    }
+
+    /// Drop every cached block
+    pub fn clear(&self) {
+        let mut state = self.state();
+        while state.evict_lru().is_some() {}
+    }
+
+    pub fn len(&self) -> usize {
+        self.state().blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes of block contents currently cached
+    pub fn total_bytes(&self) -> usize {
+        self.state().total_bytes
+    }
+
+    /// Lookups answered from the cache and lookups that missed
+    pub fn hit_stats(&self) -> (u64, u64) {
+        let state = self.state();
+        (state.hits, state.misses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used_within_limit() {
+        let cache = BlockCache::new(300);
+        cache.insert("a", &[1; 100]);
+        cache.insert("b", &[2; 100]);
+        cache.insert("c", &[3; 100]);
+
+        // Using "a" makes "b" the oldest
+        assert_eq!(cache.get("a").unwrap().as_slice(), &[1; 100]);
+        cache.insert("d", &[4; 100]);
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.total_bytes(), 300);
+        assert_eq!(cache.hit_stats(), (1, 1));
+
+        // Replacing a block doesn't count it twice
+        cache.insert("a", &[5; 50]);
+        assert_eq!(cache.total_bytes(), 250);
+        assert_eq!(cache.get("a").unwrap().as_slice(), &[5; 50]);
+
+        // Too large to cache at all
+        cache.insert("huge", &[6; 301]);
+        assert!(cache.get("huge").is_none());
+        assert_eq!(cache.total_bytes(), 250);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.total_bytes(), 0);
+    }
+
+    #[test]
+    fn test_eviction_zeroizes_key_and_buffer() {
+        let mut state = CacheState::default();
+        state.blocks.insert("/blobs/secret".to_string(), (0, b"plaintext block".to_vec()));
+        state.recency.insert(0, "/blobs/secret".to_string());
+        state.total_bytes = 15;
+
+        let (key, data) = state.evict_lru().unwrap();
+        assert_eq!(key, vec![0; 13]);
+        assert_eq!(data, vec![0; 15]);
+        assert_eq!(state.total_bytes, 0);
+        assert!(state.recency.is_empty() && state.blocks.is_empty());
+
+        // Replacing a block zeroizes the old copy the same way
+        state.blocks.insert("k".to_string(), (1, b"old".to_vec()));
+        state.recency.insert(1, "k".to_string());
+        state.total_bytes = 3;
+        assert_eq!(state.remove("k"), Some((vec![0], vec![0; 3])));
+        assert_eq!(state.total_bytes, 0);
+    }
+
+    #[test]
+    fn test_size_from_config() {
+        let config: BackupConfig = serde_json::from_value(serde_json::json!({
+            "vss_enabled": false,
+            "schedule": "0 0 2 * * *",
+            "retention_days": 30,
+            "backup_paths": [],
+            "block_cache_size": "1K",
+        })).unwrap();
+        assert_eq!(BlockCache::from_config(&config).max_bytes(), 1024);
+
+        let config = BackupConfig { block_cache_size: Some("0".to_string()), ..config };
+        let cache = BlockCache::from_config(&config);
+        cache.insert("a", &[1]);
+        assert!(cache.is_empty());
+
+        let config = BackupConfig { block_cache_size: Some("lots".to_string()), ..config };
+        assert_eq!(BlockCache::from_config(&config).max_bytes(), DEFAULT_BLOCK_CACHE_SIZE);
+        let config = BackupConfig { block_cache_size: None, ..config };
+        assert_eq!(BlockCache::from_config(&config).max_bytes(), DEFAULT_BLOCK_CACHE_SIZE);
+    }
+}
@@ -2,21 +2,45 @@
 //! 
 //! Provides terminal-based browsing of encrypted backups with automatic key validation
 
-use crate::error::{Result, SkylockError};
+use crate::error::Result;
 use crate::direct_upload::{DirectUploadBackup, BackupManifest, FileEntry};
 use crate::encryption::EncryptionManager;
+use crate::encrypted_manifest::build_file_tree;
+use crate::block_cache::BlockCache;
 use skylock_hetzner::HetznerClient;
+use crate::compression::is_text;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use colored::*;
 use std::sync::Arc;
+use indicatif::ProgressBar;
+use zeroize::Zeroizing;
+
+/// Bytes per hexdump row in binary previews
+const HEXDUMP_WIDTH: usize = 16;
 
 pub struct EncryptedBrowser {
     backup: DirectUploadBackup,
 }
 
 impl EncryptedBrowser {
+    /// Browser keeping decrypted blocks in a default-sized cache, unless
+    /// `backup` already has one
     pub fn new(backup: DirectUploadBackup) -> Self {
-        Self { backup }
+        if backup.block_cache().is_some() {
+            return Self { backup };
+        }
+        Self::with_cache(backup, Arc::new(BlockCache::default()))
+    }
+    
+    /// Browser sharing `cache` with other operations of the session, e.g.
+    /// restores started from it
+    pub fn with_cache(backup: DirectUploadBackup, cache: Arc<BlockCache>) -> Self {
+        Self { backup: backup.with_block_cache(cache) }
+    }
+    
+    pub fn block_cache(&self) -> Option<&Arc<BlockCache>> {
+        self.backup.block_cache()
     }
     
     /// Browse backup with automatic key validation
@@ -58,6 +82,7 @@ impl EncryptedBrowser {
                 "v2 (current)".green());
             if let Some(ref params) = manifest.kdf_params {
                 println!("   Algorithm: {}", params.algorithm);
+                println!("   Cipher: {}", manifest.aead_algorithm);
                 println!("   Memory: {} MiB", params.memory_cost / 1024);
                 println!("   Iterations: {}", params.time_cost);
                 println!("   Parallelism: {}", params.parallelism);
@@ -77,26 +102,16 @@ impl EncryptedBrowser {
             println!("{}", "This is intentional - indicates key mismatch.\n".dimmed());
         }
         
-        // Group files by directory
-        use std::collections::BTreeMap;
-        let mut by_dir: BTreeMap<String, Vec<&FileEntry>> = BTreeMap::new();
-        
-        for entry in &manifest.files {
-            let dir = entry.local_path.parent()
-                .and_then(|p| p.to_str())
-                .unwrap_or("/")
-                .to_string();
-            by_dir.entry(dir).or_insert_with(Vec::new).push(entry);
-        }
+        // Build the directory tree from the decrypted manifest; with
+        // encrypted file names this is the first point the names exist
+        let file_tree = build_file_tree(&manifest.files);
         
         // Display files grouped by directory
-        for (dir, files) in by_dir.iter() {
-            println!("\n   {} {}", "📂".bright_blue(), dir.bright_blue());
+        for dir in &file_tree {
+            println!("\n   {} {}", "📂".bright_blue(), dir.path.display().to_string().bright_blue());
             
-            for file in files {
-                let filename = file.local_path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown");
+            for file in &dir.children {
+                let filename = file.name.as_str();
                 
                 let size_str = Self::format_size(file.size);
                 let compressed_indicator = if file.compressed { "🗜️ " } else { "   " };
@@ -192,59 +207,191 @@ impl EncryptedBrowser {
     
     /// Format byte size in human-readable format
     fn format_size(bytes: u64) -> String {
-        const KB: u64 = 1024;
-        const MB: u64 = KB * 1024;
-        const GB: u64 = MB * 1024;
-        
-        if bytes >= GB {
-            format!("{:.2} GB", bytes as f64 / GB as f64)
-        } else if bytes >= MB {
-            format!("{:.2} MB", bytes as f64 / MB as f64)
-        } else if bytes >= KB {
-            format!("{:.2} KB", bytes as f64 / KB as f64)
-        } else {
-            format!("{} B", bytes)
-        }
+        skylock_core::ByteSize(bytes).to_string()
     }
     
     /// Preview specific file contents (with key validation)
+    ///
+    /// Text is shown line by line; binary content is shown as a hexdump of
+    /// `max_lines` rows unless `force` asks for the raw bytes.
     pub async fn preview_file(
         &self,
         backup_id: &str,
         file_path: &str,
         max_lines: usize,
+        force: bool,
     ) -> Result<()> {
         println!("\n{}", "📄 File Preview".bright_blue().bold());
         println!("{}", "━".repeat(80).dimmed());
         
-        // Load manifest
-        let manifest = self.backup.load_manifest(backup_id).await?;
+        // Load manifests; an incremental backup needs the ones it builds on
+        let chain = self.backup.load_chain(backup_id).await?;
+        let (entry, manifest) = DirectUploadBackup::find_file(&chain, file_path)?;
+        Self::print_file_details(entry);
+        
+        // Download and decrypt, unless an earlier preview cached the blocks
+        println!("\n{}", "Downloading and decrypting...".dimmed());
+        let data = Zeroizing::new(
+            self.backup.fetch_file_data(entry, manifest, &ProgressBar::hidden()).await?
+        );
         
-        // Find file in manifest
-        let entry = manifest.files.iter()
-            .find(|e| e.local_path.to_str() == Some(file_path))
-            .ok_or_else(|| SkylockError::Backup(
-                format!("File not found in backup: {}", file_path)
-            ))?;
+        println!("{}", "─".repeat(80).dimmed());
+        let mut stdout = std::io::stdout().lock();
+        Self::write_preview(&mut stdout, &data, max_lines, force)?;
+        drop(stdout);
+        println!("{}", "─".repeat(80).dimmed());
+        println!("   Use: skylock restore {} --file {}", backup_id, file_path);
         
+        Ok(())
+    }
+    
+    /// Preview the last `lines` lines of a file
+    ///
+    /// Large files stored as chunks only download the chunks holding those
+    /// lines, so the tail of a big log is quick to show. Binary data gets the
+    /// same hexdump as [`Self::preview_file`] unless `force` is set.
+    pub async fn preview_file_tail(
+        &self,
+        backup_id: &str,
+        file_path: &str,
+        lines: usize,
+        force: bool,
+    ) -> Result<()> {
+        println!("\n{}", "📄 File Preview (tail)".bright_blue().bold());
+        println!("{}", "━".repeat(80).dimmed());
+        
+        let chain = self.backup.load_chain(backup_id).await?;
+        let (entry, manifest) = DirectUploadBackup::find_file(&chain, file_path)?;
+        Self::print_file_details(entry);
+        
+        println!("\n{}", "Downloading and decrypting...".dimmed());
+        let data = Zeroizing::new(self.backup.fetch_file_tail(entry, manifest, lines).await?);
+        
+        println!("{}", "─".repeat(80).dimmed());
+        let mut stdout = std::io::stdout().lock();
+        Self::write_preview(&mut stdout, &data, lines, force)?;
+        drop(stdout);
+        println!("{}", "─".repeat(80).dimmed());
+        println!("   Use: skylock restore {} --file {}", backup_id, file_path);
+        
+        Ok(())
+    }
+    
+    /// Print the name, size and storage details of a previewed file
+    fn print_file_details(entry: &FileEntry) {
         println!("\n{} {}", "📂 File:".bright_cyan(), entry.local_path.display().to_string().bright_white());
         println!("{} {}", "💾 Size:".bright_cyan(), Self::format_size(entry.size).bright_white());
         println!("{} {}", "🗜️  Compressed:".bright_cyan(), 
             (if entry.compressed { "Yes" } else { "No" }).bright_white());
         println!("{} {}", "🔒 Encrypted:".bright_cyan(), 
             (if entry.encrypted { "Yes (AES-256-GCM)" } else { "No" }).bright_white());
+    }
+    
+    /// Write the preview of `data` to `out`: text with control characters
+    /// escaped, a hexdump for binary data, or the raw lines when `force` is set
+    fn write_preview<W: Write>(out: &mut W, data: &[u8], max_lines: usize, force: bool) -> std::io::Result<()> {
+        if force {
+            let lines: Vec<&[u8]> = data.split_inclusive(|&b| b == b'\n').collect();
+            for line in lines.iter().take(max_lines) {
+                out.write_all(line)?;
+            }
+            if !data.ends_with(b"\n") && lines.len() <= max_lines && !data.is_empty() {
+                writeln!(out)?;
+            }
+            if lines.len() > max_lines {
+                writeln!(out, "{}", format!("... {} more lines", lines.len() - max_lines).dimmed())?;
+            }
+        } else if is_text(data) {
+            let text = String::from_utf8_lossy(data);
+            let total_lines = text.lines().count();
+            for line in text.lines().take(max_lines) {
+                writeln!(out, "{}", Self::escape_controls(line))?;
+            }
+            if total_lines > max_lines {
+                writeln!(out, "{}", format!("... {} more lines", total_lines - max_lines).dimmed())?;
+            }
+        } else {
+            writeln!(out, "{}", "Binary file, showing hexdump (use --force to print raw)".bright_yellow())?;
+            let shown = data.len().min(max_lines * HEXDUMP_WIDTH);
+            for (row, chunk) in data[..shown].chunks(HEXDUMP_WIDTH).enumerate() {
+                writeln!(out, "{}", Self::hexdump_row(row * HEXDUMP_WIDTH, chunk))?;
+            }
+            if data.len() > shown {
+                writeln!(out, "{}", format!("... {} more bytes", data.len() - shown).dimmed())?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Replace control characters other than tabs with their escaped form so
+    /// they can't drive the terminal
+    fn escape_controls(line: &str) -> String {
+        line.chars()
+            .map(|c| if c.is_control() && c != '\t' { c.escape_default().to_string() } else { c.to_string() })
+            .collect()
+    }
+    
+    /// One `hexdump -C` style row: offset, hex bytes and printable ASCII
+    fn hexdump_row(offset: usize, bytes: &[u8]) -> String {
+        let mut hex = String::new();
+        for i in 0..HEXDUMP_WIDTH {
+            if i == HEXDUMP_WIDTH / 2 {
+                hex.push(' ');
+            }
+            match bytes.get(i) {
+                Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+                None => hex.push_str("   "),
+            }
+        }
+        let ascii: String = bytes.iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        format!("{:08x}  {} |{}|", offset, hex, ascii)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn preview(data: &[u8], max_lines: usize, force: bool) -> String {
+        colored::control::set_override(false);
+        let mut out = Vec::new();
+        EncryptedBrowser::write_preview(&mut out, data, max_lines, force).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+    
+    #[test]
+    fn test_png_preview_is_hexdump() {
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00\x00\x10\x00\x00\x00\x10\x08\x06\x00\x00\x00";
+        let output = preview(png, 10, false);
+        assert!(output.contains("Binary file"), "{}", output);
+        assert!(output.contains("00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|"), "{}", output);
+        assert!(output.contains("00000010  00 00 00 10 00 00 00 10  08 06 00 00 00"), "{}", output);
+        assert!(!output.contains('\x1a'));
         
-        // Try to download and decrypt
-        println!("\n{}", "Downloading and decrypting...".dimmed());
-        
-        // Download to temp file
-        let temp_dir = tempfile::tempdir()
-            .map_err(|e| SkylockError::Backup(format!("Temp dir failed: {}", e)))?;
+        // Rows are limited by the line count
+        let output = preview(png, 1, false);
+        assert!(output.contains("... 13 more bytes"), "{}", output);
         
-        // (Simplified - actual implementation would call restore_single_file)
-        println!("\n{}", "⚠️  Preview not yet fully implemented".bright_yellow());
-        println!("   Use: skylock restore {} --file {}", backup_id, file_path);
+        // --force prints the bytes as they are
+        let mut out = Vec::new();
+        EncryptedBrowser::write_preview(&mut out, png, 10, true).unwrap();
+        assert!(out.starts_with(png));
+    }
+    
+    #[test]
+    fn test_utf8_text_preview_keeps_multibyte_characters() {
+        let text = "naïve café\n日本語のテキスト\nemoji 🦀 line\nbell \x07 and escape \x1b[2J\nlast\n";
+        let output = preview(text.as_bytes(), 10, false);
+        assert!(!output.contains("Binary file"), "{}", output);
+        assert!(output.contains("naïve café\n日本語のテキスト\nemoji 🦀 line\n"), "{}", output);
+        // Control characters are escaped rather than sent to the terminal
+        assert!(output.contains("bell \\u{7} and escape \\u{1b}[2J"), "{}", output);
+        assert!(!output.contains('\x1b'));
         
-        Ok(())
+        let output = preview(text.as_bytes(), 2, false);
+        assert!(output.contains("... 3 more lines"), "{}", output);
+        assert!(!output.contains("emoji"));
     }
 }
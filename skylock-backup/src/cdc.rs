@@ -0,0 +1,159 @@
+//! Content-defined chunking
+//!
+//! Splits data at positions chosen by a rolling gear hash of the preceding
+//! bytes rather than at fixed offsets. Appending to or editing a file only
+//! moves the boundaries near the change, so the chunks before it keep their
+//! content and hash and don't have to be stored again.
+
+use std::ops::Range;
+
+/// Smallest chunk cut from data (256KB), except for the final one
+pub const MIN_CDC_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Average chunk size aimed for (1MB)
+pub const AVG_CDC_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Largest chunk; a boundary is forced here (4MB)
+pub const MAX_CDC_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Pseudo-random value per byte, fixed so boundaries never change between
+/// versions
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64 over a fixed seed
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x5359_4c4f_434b_4344;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Gear-hash chunker with minimum, average and maximum chunk sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentChunker {
+    min_size: usize,
+    max_size: usize,
+    /// Boundary where the masked hash bits are all zero; uses the high
+    /// bits, which depend on the last 64 bytes rather than the last few
+    mask: u64,
+}
+
+impl Default for ContentChunker {
+    fn default() -> Self {
+        Self::new(MIN_CDC_CHUNK_SIZE, AVG_CDC_CHUNK_SIZE, MAX_CDC_CHUNK_SIZE)
+    }
+}
+
+impl ContentChunker {
+    /// Chunker cutting between `min_size` and `max_size` bytes, about every
+    /// `avg_size` bytes past the minimum (rounded to a power of two)
+    pub fn new(min_size: usize, avg_size: usize, max_size: usize) -> Self {
+        let min_size = min_size.max(1);
+        let max_size = max_size.max(min_size);
+        let bits = avg_size.max(2).next_power_of_two().trailing_zeros().min(63);
+        Self {
+            min_size,
+            max_size,
+            mask: !0u64 << (64 - bits),
+        }
+    }
+
+    /// Length of the first chunk of `data`
+    pub fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        let end = data.len().min(self.max_size);
+        let mut hash = 0u64;
+        for (i, byte) in data[..end].iter().enumerate().skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & self.mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Byte ranges of the chunks of `data`, in order and covering all of it
+    pub fn chunks<'a>(&self, data: &'a [u8]) -> impl Iterator<Item = Range<usize>> + 'a {
+        let chunker = *self;
+        let mut offset = 0;
+        std::iter::from_fn(move || {
+            if offset >= data.len() {
+                return None;
+            }
+            let start = offset;
+            offset += chunker.cut_point(&data[start..]);
+            Some(start..offset)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes
+    fn random_data(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect()
+    }
+
+    #[test]
+    fn test_chunks_cover_data_within_bounds() {
+        let chunker = ContentChunker::new(4 * 1024, 16 * 1024, 64 * 1024);
+        let data = random_data(1024 * 1024, 1);
+        let chunks: Vec<_> = chunker.chunks(&data).collect();
+
+        assert_eq!(chunks.first().unwrap().start, 0);
+        assert_eq!(chunks.last().unwrap().end, data.len());
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.len() >= 4 * 1024 && chunk.len() <= 64 * 1024, "{:?}", chunk);
+        }
+        // Roughly the average size past the minimum
+        let average = data.len() / chunks.len();
+        assert!(average > 10 * 1024 && average < 40 * 1024, "average {}", average);
+
+        // Constant data never matches the mask, so chunks hit the maximum
+        let zeros = vec![0u8; 200 * 1024];
+        let sizes: Vec<_> = chunker.chunks(&zeros).map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![64 * 1024, 64 * 1024, 64 * 1024, 8 * 1024]);
+        assert_eq!(chunker.chunks(&[]).count(), 0);
+    }
+
+    #[test]
+    fn test_boundaries_survive_edits() {
+        let chunker = ContentChunker::new(4 * 1024, 16 * 1024, 64 * 1024);
+        let original = random_data(512 * 1024, 2);
+        let before: Vec<_> = chunker.chunks(&original).map(|c| original[c].to_vec()).collect();
+
+        // Appending keeps every chunk but the last
+        let mut appended = original.clone();
+        appended.extend(random_data(20 * 1024, 3));
+        let after: Vec<_> = chunker.chunks(&appended).map(|c| appended[c].to_vec()).collect();
+        assert_eq!(before[..before.len() - 1], after[..before.len() - 1]);
+
+        // Inserting near the start resynchronises after a chunk or two
+        let mut inserted = b"a few new bytes".to_vec();
+        inserted.extend_from_slice(&original);
+        let shifted: Vec<_> = chunker.chunks(&inserted).map(|c| inserted[c].to_vec()).collect();
+        let shared = shifted.iter().filter(|chunk| before.contains(chunk)).count();
+        assert!(shared >= before.len() - 2, "{} of {} chunks shared", shared, before.len());
+    }
+}
@@ -2,13 +2,15 @@
 //!
 //! Tracks file modifications between backups for efficient incremental backups.
 
+use crate::content_hash::HashAlgorithm;
+use crate::diff::FileMove;
+use crate::direct_upload::BackupManifest;
 use crate::error::{Result, SkylockError};
+use crate::file_filter::FileFilter;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
 
 /// Represents a tracked file with its metadata
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -19,7 +21,7 @@ pub struct FileInfo {
     pub size: u64,
     /// Last modified timestamp
     pub modified: DateTime<Utc>,
-    /// SHA-256 hash of file content (computed lazily)
+    /// Hash of file content in the index's algorithm (computed lazily)
     pub hash: Option<String>,
 }
 
@@ -32,10 +34,17 @@ pub struct FileIndex {
     pub created_at: DateTime<Utc>,
     /// Directories being tracked
     pub tracked_dirs: Vec<PathBuf>,
+    /// Backup this index was saved for (absent in older indexes)
+    #[serde(default)]
+    pub backup_id: Option<String>,
+    /// Algorithm of the file hashes (SHA-256 in older indexes)
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
 }
 
 /// Change detection result
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ChangeType {
     /// File was added (new file)
     Added,
@@ -45,10 +54,13 @@ pub enum ChangeType {
     Modified,
     /// File metadata changed (size or timestamp)
     MetadataChanged,
+    /// File was moved or renamed: a removed file and an added file have the
+    /// same content. `path` is the new location, `old_info.path` the old one
+    Moved,
 }
 
 /// Represents a detected change
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileChange {
     /// Path to the changed file
     pub path: PathBuf,
@@ -60,6 +72,23 @@ pub struct FileChange {
     pub new_info: Option<FileInfo>,
 }
 
+impl FileChange {
+    /// Describe a [`ChangeType::Moved`] change as a [`FileMove`]
+    pub fn to_file_move(&self) -> Option<FileMove> {
+        if self.change_type != ChangeType::Moved {
+            return None;
+        }
+        let old_info = self.old_info.as_ref()?;
+        let new_info = self.new_info.as_ref()?;
+        Some(FileMove {
+            path_old: old_info.path.to_string_lossy().to_string(),
+            path_new: self.path.to_string_lossy().to_string(),
+            size: new_info.size,
+            hash: new_info.hash.clone().or_else(|| old_info.hash.clone()).unwrap_or_default(),
+        })
+    }
+}
+
 impl FileIndex {
     /// Create a new empty file index
     pub fn new(tracked_dirs: Vec<PathBuf>) -> Self {
@@ -67,29 +96,41 @@ impl FileIndex {
             files: HashMap::new(),
             created_at: Utc::now(),
             tracked_dirs,
+            backup_id: None,
+            hash_algorithm: HashAlgorithm::default(),
+        }
+    }
+
+    /// Index of the files a backup holds under `root`, with their sizes,
+    /// modification times and hashes as recorded in its manifest
+    pub fn from_manifest(manifest: &BackupManifest, root: &Path) -> Self {
+        let mut index = Self::new(vec![root.to_path_buf()]);
+        index.backup_id = Some(manifest.backup_id.clone());
+        index.hash_algorithm = manifest.hash_algorithm;
+        for entry in manifest.files.iter().filter(|entry| entry.local_path.starts_with(root)) {
+            index.insert(FileInfo {
+                path: entry.local_path.clone(),
+                size: entry.size,
+                modified: entry.modified.unwrap_or(entry.timestamp),
+                hash: Some(entry.hash.clone()),
+            });
         }
+        index
     }
 
     /// Build file index from directories
     pub fn build(paths: &[PathBuf]) -> Result<Self> {
+        Self::build_filtered(paths, &FileFilter::default())
+    }
+
+    /// Build file index of the files in `paths` that `filter` backs up
+    pub fn build_filtered(paths: &[PathBuf], filter: &FileFilter) -> Result<Self> {
         let mut index = Self::new(paths.to_vec());
         
-        for path in paths {
-            if path.is_file() {
-                let info = Self::get_file_info(path)?;
-                index.files.insert(path.clone(), info);
-            } else if path.is_dir() {
-                for entry in WalkDir::new(path).follow_links(false) {
-                    let entry = entry.map_err(|e| {
-                        SkylockError::Backup(format!("Walk error: {}", e))
-                    })?;
-                    
-                    if entry.file_type().is_file() {
-                        let file_path = entry.path().to_path_buf();
-                        let info = Self::get_file_info(&file_path)?;
-                        index.files.insert(file_path, info);
-                    }
-                }
+        for path in paths.iter().filter(|path| path.is_file() || path.is_dir()) {
+            for file_path in filter.files(path)? {
+                let info = Self::get_file_info(&file_path)?;
+                index.files.insert(file_path, info);
             }
         }
         
@@ -110,18 +151,59 @@ impl FileIndex {
         })
     }
 
-    /// Compute hash for a file
-    pub async fn compute_hash(path: &Path) -> Result<String> {
+    /// Compute hash for a file in `algorithm`
+    pub async fn compute_hash(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
         let data = tokio::fs::read(path).await?;
-        let mut hasher = Sha256::new();
-        hasher.update(&data);
-        Ok(format!("{:x}", hasher.finalize()))
+        Ok(algorithm.hash(&data))
+    }
+
+    /// Record the content hash of a tracked file
+    pub fn set_hash(&mut self, path: &Path, hash: String) {
+        if let Some(info) = self.files.get_mut(path) {
+            info.hash = Some(hash);
+        }
+    }
+
+    /// Add or replace the entry for a file
+    pub fn insert(&mut self, info: FileInfo) {
+        self.files.insert(info.path.clone(), info);
+    }
+
+    /// Forget a file, so the next comparison reports it as added
+    pub fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    /// Copy hashes from `previous` for files whose size and timestamp are
+    /// unchanged, so unchanged files keep their hash without being re-read.
+    /// Nothing is copied from an index hashed in another algorithm.
+    pub fn inherit_hashes(&mut self, previous: &FileIndex) {
+        if previous.hash_algorithm != self.hash_algorithm {
+            return;
+        }
+        for (path, info) in self.files.iter_mut() {
+            if info.hash.is_some() {
+                continue;
+            }
+            if let Some(old) = previous.files.get(path) {
+                if old.size == info.size && old.modified == info.modified {
+                    info.hash = old.hash.clone();
+                }
+            }
+        }
     }
 
     /// Compare with current filesystem state and detect changes
     pub async fn detect_changes(&self, paths: &[PathBuf]) -> Result<Vec<FileChange>> {
+        self.detect_changes_filtered(paths, &FileFilter::default()).await
+    }
+
+    /// Detect changes to the files in `paths` that `filter` backs up
+    pub async fn detect_changes_filtered(&self, paths: &[PathBuf], filter: &FileFilter) -> Result<Vec<FileChange>> {
         let mut changes = Vec::new();
-        let current_index = Self::build(paths)?;
+        let current_index = Self::build_filtered(paths, filter)?;
+        
+        let mut added = Vec::new();
         
         // Find added and modified files
         for (path, new_info) in &current_index.files {
@@ -132,50 +214,86 @@ impl FileIndex {
                     let old_hash = if let Some(h) = &old_info.hash {
                         h.clone()
                     } else {
-                        Self::compute_hash(path).await?
+                        Self::compute_hash(path, self.hash_algorithm).await?
                     };
                     
-                    let new_hash = Self::compute_hash(path).await?;
-                    
-                    if old_hash != new_hash {
-                        changes.push(FileChange {
-                            path: path.clone(),
-                            change_type: ChangeType::Modified,
-                            old_info: Some(old_info.clone()),
-                            new_info: Some(new_info.clone()),
-                        });
+                    let new_hash = Self::compute_hash(path, self.hash_algorithm).await?;
+                    let change_type = if old_hash != new_hash {
+                        ChangeType::Modified
                     } else {
-                        changes.push(FileChange {
-                            path: path.clone(),
-                            change_type: ChangeType::MetadataChanged,
-                            old_info: Some(old_info.clone()),
-                            new_info: Some(new_info.clone()),
-                        });
-                    }
+                        ChangeType::MetadataChanged
+                    };
+                    
+                    changes.push(FileChange {
+                        path: path.clone(),
+                        change_type,
+                        old_info: Some(old_info.clone()),
+                        new_info: Some(FileInfo { hash: Some(new_hash), ..new_info.clone() }),
+                    });
                 }
             } else {
-                // New file
-                changes.push(FileChange {
-                    path: path.clone(),
-                    change_type: ChangeType::Added,
-                    old_info: None,
-                    new_info: Some(new_info.clone()),
-                });
+                added.push(new_info.clone());
             }
         }
         
-        // Find removed files
+        // Removed files with a known hash are candidates for moves, keyed by
+        // size and hash
+        let mut removed = Vec::new();
+        let mut move_sources: HashMap<(u64, String), Vec<FileInfo>> = HashMap::new();
         for (path, old_info) in &self.files {
-            if !current_index.files.contains_key(path) {
-                changes.push(FileChange {
-                    path: path.clone(),
-                    change_type: ChangeType::Removed,
-                    old_info: Some(old_info.clone()),
-                    new_info: None,
-                });
+            if current_index.files.contains_key(path) {
+                continue;
+            }
+            match old_info.hash {
+                Some(ref hash) => move_sources
+                    .entry((old_info.size, hash.clone()))
+                    .or_default()
+                    .push(old_info.clone()),
+                None => removed.push(old_info.clone()),
             }
         }
         
+        // Pair added files with removed files of the same content; only
+        // added files whose size matches a candidate are hashed
+        for mut new_info in added {
+            if move_sources.keys().any(|(size, _)| *size == new_info.size) {
+                let hash = Self::compute_hash(&new_info.path, self.hash_algorithm).await?;
+                let source = move_sources
+                    .get_mut(&(new_info.size, hash.clone()))
+                    .and_then(|sources| sources.pop());
+                new_info.hash = Some(hash);
+                
+                if let Some(old_info) = source {
+                    changes.push(FileChange {
+                        path: new_info.path.clone(),
+                        change_type: ChangeType::Moved,
+                        old_info: Some(old_info),
+                        new_info: Some(new_info),
+                    });
+                    continue;
+                }
+            }
+            
+            // New file
+            changes.push(FileChange {
+                path: new_info.path.clone(),
+                change_type: ChangeType::Added,
+                old_info: None,
+                new_info: Some(new_info),
+            });
+        }
+        
+        // Find removed files
+        removed.extend(move_sources.into_values().flatten());
+        for old_info in removed {
+            changes.push(FileChange {
+                path: old_info.path.clone(),
+                change_type: ChangeType::Removed,
+                old_info: Some(old_info),
+                new_info: None,
+            });
+        }
+        
         Ok(changes)
     }
 
@@ -362,6 +480,45 @@ mod tests {
         assert_eq!(changes[0].path, file_path);
     }
 
+    #[tokio::test]
+    async fn test_detect_moved_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let old_path = temp_dir.path().join("report.pdf");
+        let other_path = temp_dir.path().join("other.txt");
+        tokio::fs::write(&old_path, vec![7u8; 4096]).await.unwrap();
+        tokio::fs::write(&other_path, vec![1u8; 4096]).await.unwrap();
+        
+        let mut old_index = FileIndex::build(&[temp_dir.path().to_path_buf()]).unwrap();
+        for path in [&old_path, &other_path] {
+            old_index.set_hash(path, FileIndex::compute_hash(path, HashAlgorithm::Sha256).await.unwrap());
+        }
+        
+        // Rename one file; delete the other and add a same-sized file with different content
+        let new_path = temp_dir.path().join("archive").join("report-2024.pdf");
+        tokio::fs::create_dir_all(new_path.parent().unwrap()).await.unwrap();
+        tokio::fs::rename(&old_path, &new_path).await.unwrap();
+        tokio::fs::remove_file(&other_path).await.unwrap();
+        let added_path = temp_dir.path().join("new.txt");
+        tokio::fs::write(&added_path, vec![2u8; 4096]).await.unwrap();
+        
+        let changes = old_index.detect_changes(&[temp_dir.path().to_path_buf()]).await.unwrap();
+        assert_eq!(changes.len(), 3);
+        
+        let moved = changes.iter().find(|c| c.change_type == ChangeType::Moved).unwrap();
+        assert_eq!(moved.path, new_path);
+        let file_move = moved.to_file_move().unwrap();
+        assert_eq!(file_move.path_old, old_path.to_string_lossy());
+        assert_eq!(file_move.path_new, new_path.to_string_lossy());
+        assert_eq!(file_move.size, 4096);
+        
+        assert!(changes.iter().any(|c| c.change_type == ChangeType::Added && c.path == added_path));
+        assert!(changes.iter().any(|c| c.change_type == ChangeType::Removed && c.path == other_path));
+        
+        // Moves are not reported as files to upload
+        let changed = old_index.get_changed_files(&[temp_dir.path().to_path_buf()]).await.unwrap();
+        assert_eq!(changed, vec![added_path]);
+    }
+
     #[tokio::test]
     async fn test_detect_modified_files() {
         let temp_dir = TempDir::new().unwrap();
@@ -1,7 +1,10 @@
-//! Multi-algorithm compression engine for Skylock Hybrid
+//! Multi-algorithm compression engine
 //!
 //! This module provides adaptive compression using LZ4, ZSTD, and Brotli algorithms
-//! with intelligent algorithm selection based on data characteristics.
+//! with intelligent algorithm selection based on data characteristics. Direct
+//! upload uses the data analysis to skip recompressing already-compressed files.
+//! Gzip is never selected adaptively; it is opt-in for blobs that standard
+//! tools (`gunzip`) should be able to read once decrypted.
 
 use std::io::{Read, Write};
 use lz4::block::{compress, decompress, CompressionMode};
@@ -16,12 +19,49 @@ pub enum CompressionError {
     Decompression(String),
     #[error("Unsupported algorithm: {0}")]
     UnsupportedAlgorithm(String),
+    #[error("Invalid compression level: {0}")]
+    InvalidLevel(String),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("Invalid compressed data")]
     InvalidData,
 }
 
+/// Bytes sampled when guessing whether data is text
+const TEXT_SAMPLE_SIZE: usize = 8 * 1024;
+
+/// Check if data appears to be text: the sampled prefix is valid UTF-8,
+/// has no NUL bytes and is made of printable characters or whitespace
+pub fn is_text(data: &[u8]) -> bool {
+    let sample = &data[..data.len().min(TEXT_SAMPLE_SIZE)];
+    if sample.contains(&0) {
+        return false;
+    }
+    
+    let text = match std::str::from_utf8(sample) {
+        Ok(text) => text,
+        // A multibyte character cut off by the end of the sample
+        Err(e) if e.error_len().is_none() && sample.len() < data.len() => {
+            std::str::from_utf8(&sample[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+    
+    let mut text_chars = 0;
+    let mut total_chars = 0;
+    for c in text.chars() {
+        total_chars += 1;
+        if !c.is_control() || c.is_whitespace() {
+            text_chars += 1;
+        }
+    }
+    
+    total_chars == 0 || text_chars as f64 / total_chars as f64 > 0.95
+}
+
+/// First bytes of every gzip member
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
 /// Compression algorithms
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum CompressionAlgorithm {
@@ -33,6 +73,8 @@ pub enum CompressionAlgorithm {
     Zstd,
     /// Brotli - High compression ratio, slower but good for archival
     Brotli,
+    /// Gzip - Standard gzip container (RFC 1952) for interoperability
+    Gzip,
 }
 
 impl std::fmt::Display for CompressionAlgorithm {
@@ -42,6 +84,20 @@ impl std::fmt::Display for CompressionAlgorithm {
             CompressionAlgorithm::Lz4 => write!(f, "lz4"),
             CompressionAlgorithm::Zstd => write!(f, "zstd"),
             CompressionAlgorithm::Brotli => write!(f, "brotli"),
+            CompressionAlgorithm::Gzip => write!(f, "gzip"),
+        }
+    }
+}
+
+impl CompressionAlgorithm {
+    /// Numeric levels the algorithm accepts (`None` for no compression)
+    pub fn level_range(&self) -> Option<std::ops::RangeInclusive<i32>> {
+        match self {
+            CompressionAlgorithm::None => None,
+            CompressionAlgorithm::Lz4 => Some(1..=12),
+            CompressionAlgorithm::Zstd => Some(1..=22),
+            CompressionAlgorithm::Brotli => Some(0..=11),
+            CompressionAlgorithm::Gzip => Some(0..=9),
         }
     }
 }
@@ -55,6 +111,7 @@ impl std::str::FromStr for CompressionAlgorithm {
             "lz4" => Ok(CompressionAlgorithm::Lz4),
             "zstd" => Ok(CompressionAlgorithm::Zstd),
             "brotli" => Ok(CompressionAlgorithm::Brotli),
+            "gzip" | "gz" => Ok(CompressionAlgorithm::Gzip),
             _ => Err(CompressionError::UnsupportedAlgorithm(s.to_string())),
         }
     }
@@ -99,10 +156,105 @@ impl CompressionLevel {
             (CompressionLevel::Better, CompressionAlgorithm::Brotli) => 9,
             (CompressionLevel::Best, CompressionAlgorithm::Brotli) => 11,
             
+            (CompressionLevel::Fastest, CompressionAlgorithm::Gzip) => 1,
+            (CompressionLevel::Fast, CompressionAlgorithm::Gzip) => 3,
+            (CompressionLevel::Default, CompressionAlgorithm::Gzip) => 6,
+            (CompressionLevel::Better, CompressionAlgorithm::Gzip) => 8,
+            (CompressionLevel::Best, CompressionAlgorithm::Gzip) => 9,
+            
             (CompressionLevel::Custom(level), _) => *level,
             (_, CompressionAlgorithm::None) => 0,
         }
     }
+    
+    /// Check that `algorithm` can compress at this level
+    pub fn validate_for(&self, algorithm: CompressionAlgorithm) -> Result<(), CompressionError> {
+        let Some(range) = algorithm.level_range() else {
+            return Err(CompressionError::InvalidLevel(format!(
+                "no level applies when compression is {}", algorithm
+            )));
+        };
+        let level = self.to_level(algorithm);
+        if !range.contains(&level) {
+            return Err(CompressionError::InvalidLevel(format!(
+                "{} is out of range for {} ({}-{})", level, algorithm, range.start(), range.end()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for CompressionLevel {
+    type Err = CompressionError;
+
+    /// Parse a preset name (`fastest`, `fast`, `default`, `better`, `best`)
+    /// or a numeric level
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fastest" => Ok(CompressionLevel::Fastest),
+            "fast" => Ok(CompressionLevel::Fast),
+            "default" => Ok(CompressionLevel::Default),
+            "better" => Ok(CompressionLevel::Better),
+            "best" => Ok(CompressionLevel::Best),
+            other => other.parse::<i32>()
+                .map(CompressionLevel::Custom)
+                .map_err(|_| CompressionError::InvalidLevel(format!(
+                    "{} (expected fastest, fast, default, better, best or a number)", s
+                ))),
+        }
+    }
+}
+
+/// Compression forced for one backup run instead of the adaptive choice
+///
+/// Every file records the algorithm and level it was actually stored with,
+/// so restores don't need to know about the override.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionOverride {
+    /// Algorithm for every file (`None` keeps the adaptive selection)
+    pub algorithm: Option<CompressionAlgorithm>,
+    /// Level for the chosen algorithm (`None` uses the algorithm's default)
+    pub level: Option<CompressionLevel>,
+}
+
+impl CompressionOverride {
+    /// Validate an algorithm and level combination
+    ///
+    /// A level needs an algorithm that has levels; a numeric level also needs
+    /// an explicit algorithm, since the ranges differ between algorithms.
+    pub fn new(
+        algorithm: Option<CompressionAlgorithm>,
+        level: Option<CompressionLevel>,
+    ) -> Result<Self, CompressionError> {
+        match (algorithm, level) {
+            (Some(algorithm), Some(level)) => level.validate_for(algorithm)?,
+            (None, Some(CompressionLevel::Custom(level))) => {
+                return Err(CompressionError::InvalidLevel(format!(
+                    "numeric level {} needs an explicit algorithm", level
+                )));
+            }
+            _ => {}
+        }
+        Ok(Self { algorithm, level })
+    }
+    
+    /// Whether anything is overridden
+    pub fn is_set(&self) -> bool {
+        self.algorithm.is_some() || self.level.is_some()
+    }
+    
+    /// Apply the override to the algorithm and level chosen adaptively
+    pub fn apply(
+        &self,
+        selected: (CompressionAlgorithm, CompressionLevel),
+    ) -> (CompressionAlgorithm, CompressionLevel) {
+        match (self.algorithm, self.level) {
+            (Some(algorithm), level) => (algorithm, level.unwrap_or(CompressionLevel::Default)),
+            // Files the adaptive choice leaves uncompressed stay that way
+            (None, Some(level)) if selected.0 != CompressionAlgorithm::None => (selected.0, level),
+            _ => selected,
+        }
+    }
 }
 
 /// Compressed data container
@@ -318,6 +470,7 @@ impl CompressionEngine {
             CompressionAlgorithm::Lz4 => self.compress_lz4(data, level)?,
             CompressionAlgorithm::Zstd => self.compress_zstd(data, level)?,
             CompressionAlgorithm::Brotli => self.compress_brotli(data, level)?,
+            CompressionAlgorithm::Gzip => self.compress_gzip(data, level)?,
         };
         
         Ok(CompressedData {
@@ -337,6 +490,7 @@ impl CompressionEngine {
             CompressionAlgorithm::Lz4 => self.decompress_lz4(&compressed.data)?,
             CompressionAlgorithm::Zstd => self.decompress_zstd(&compressed.data)?,
             CompressionAlgorithm::Brotli => self.decompress_brotli(&compressed.data)?,
+            CompressionAlgorithm::Gzip => self.decompress_gzip(&compressed.data)?,
         };
         
         // Verify checksum
@@ -353,6 +507,18 @@ impl CompressionEngine {
         Ok(decompressed)
     }
     
+    /// Decompress bytes produced by `compress_with_algorithm` when the
+    /// algorithm is recorded elsewhere, e.g. per file in a backup manifest
+    pub fn decompress_raw(&self, data: &[u8], algorithm: CompressionAlgorithm) -> Result<Vec<u8>, CompressionError> {
+        match algorithm {
+            CompressionAlgorithm::None => Ok(data.to_vec()),
+            CompressionAlgorithm::Lz4 => self.decompress_lz4(data),
+            CompressionAlgorithm::Zstd => self.decompress_zstd(data),
+            CompressionAlgorithm::Brotli => self.decompress_brotli(data),
+            CompressionAlgorithm::Gzip => self.decompress_gzip(data),
+        }
+    }
+    
     /// Compress data using LZ4
     fn compress_lz4(&self, data: &[u8], level: CompressionLevel) -> Result<Vec<u8>, CompressionError> {
         let level = level.to_level(CompressionAlgorithm::Lz4);
@@ -411,7 +577,34 @@ impl CompressionEngine {
         Ok(decompressed)
     }
     
-    /// Check if data appears to be text
+    /// Compress data into a single gzip member
+    fn compress_gzip(&self, data: &[u8], level: CompressionLevel) -> Result<Vec<u8>, CompressionError> {
+        let level = level.to_level(CompressionAlgorithm::Gzip).clamp(0, 9) as u32;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+        encoder.write_all(data)
+            .map_err(|e| CompressionError::Compression(format!("Gzip: {}", e)))?;
+        encoder.finish()
+            .map_err(|e| CompressionError::Compression(format!("Gzip: {}", e)))
+    }
+    
+    /// Decompress gzip data, concatenating every member as `gunzip` does
+    fn decompress_gzip(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        if !data.starts_with(&GZIP_MAGIC) {
+            return Err(CompressionError::Decompression("Gzip: missing gzip header".to_string()));
+        }
+        let mut decompressed = Vec::new();
+        flate2::read::MultiGzDecoder::new(data)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| CompressionError::Decompression(format!("Gzip: {}", e)))?;
+        
+        Ok(decompressed)
+    }
+    
+    /// Check if data appears to be text, for choosing an algorithm
+    ///
+    /// Deliberately looser than [`is_text`], which decides how previews
+    /// show data: mostly-ASCII data in any encoding, or with a few NUL
+    /// bytes, still compresses like text.
     fn is_text_data(&self, data: &[u8]) -> bool {
         if data.is_empty() {
             return true;
@@ -431,7 +624,7 @@ impl CompressionEngine {
     }
     
     /// Check if data appears to be already compressed
-    fn is_compressed_data(&self, data: &[u8]) -> bool {
+    pub fn is_compressed_data(&self, data: &[u8]) -> bool {
         if data.len() < 4 {
             return false;
         }
@@ -455,7 +648,9 @@ impl CompressionEngine {
         // PNG
         (data.len() >= 8 && &data[..8] == b"\x89PNG\x0D\x0A\x1A\x0A") ||
         // MP3
-        (data.len() >= 3 && (&data[..3] == b"ID3" || (data[0] == 0xFF && (data[1] & 0xE0) == 0xE0)))
+        (data.len() >= 3 && (&data[..3] == b"ID3" || (data[0] == 0xFF && (data[1] & 0xE0) == 0xE0))) ||
+        // MP4 / MOV / HEIC (ISO base media)
+        (data.len() >= 8 && &data[4..8] == b"ftyp")
     }
     
     /// Benchmark compression algorithms on sample data
@@ -466,6 +661,7 @@ impl CompressionEngine {
             CompressionAlgorithm::Lz4,
             CompressionAlgorithm::Zstd,
             CompressionAlgorithm::Brotli,
+            CompressionAlgorithm::Gzip,
         ];
         
         for algorithm in &algorithms {
@@ -573,7 +769,7 @@ mod tests {
         let test_data = "Hello, world! This is a test string that should compress well with repetitive content. ".repeat(50);
         let test_bytes = test_data.as_bytes();
         
-        for algorithm in [CompressionAlgorithm::Lz4, CompressionAlgorithm::Zstd, CompressionAlgorithm::Brotli] {
+        for algorithm in [CompressionAlgorithm::Lz4, CompressionAlgorithm::Zstd, CompressionAlgorithm::Brotli, CompressionAlgorithm::Gzip] {
             let compressed = engine.compress_with_algorithm(test_bytes, algorithm, CompressionLevel::Default).unwrap();
             let decompressed = engine.decompress(&compressed).unwrap();
             
@@ -617,6 +813,11 @@ mod tests {
         let stats = engine.analyze_data(text);
         assert_eq!(stats.data_type, DataType::Text);
         
+        // Latin-1 text isn't shown as text in previews but compresses as such
+        let latin1 = b"caf\xe9 au lait, ".repeat(20);
+        assert!(!is_text(&latin1));
+        assert_eq!(engine.analyze_data(&latin1).data_type, DataType::Text);
+        
         // Binary data
         let binary: Vec<u8> = (0u8..=255u8).collect();
         let stats = engine.analyze_data(&binary);
@@ -644,6 +845,63 @@ mod tests {
         assert_eq!(test_data, engine.decompress(&best).unwrap());
     }
 
+    #[test]
+    fn test_gzip_members() {
+        use flate2::read::GzDecoder;
+        
+        let engine = CompressionEngine::new();
+        let data = b"gzip interop test data, gzip interop test data. ".repeat(200);
+        let compressed = engine.compress_with_algorithm(&data, CompressionAlgorithm::Gzip, CompressionLevel::Best).unwrap();
+        assert!(compressed.data.starts_with(&GZIP_MAGIC));
+        assert!(compressed.is_beneficial());
+        
+        // A standard gzip decoder reads it
+        let mut decoded = Vec::new();
+        GzDecoder::new(compressed.data.as_slice()).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+        
+        // Concatenated members decode to the concatenated data, as with gunzip
+        let mut two = compressed.data.clone();
+        two.extend(engine.compress_with_algorithm(b"tail", CompressionAlgorithm::Gzip, CompressionLevel::Fast).unwrap().data);
+        let mut expected = data.clone();
+        expected.extend_from_slice(b"tail");
+        assert_eq!(engine.decompress_raw(&two, CompressionAlgorithm::Gzip).unwrap(), expected);
+        
+        assert!(engine.decompress_raw(b"not gzip", CompressionAlgorithm::Gzip).is_err());
+        assert_eq!("gz".parse::<CompressionAlgorithm>().unwrap(), CompressionAlgorithm::Gzip);
+        assert!(CompressionOverride::new(Some(CompressionAlgorithm::Gzip), Some(CompressionLevel::Custom(10))).is_err());
+    }
+
+    #[test]
+    fn test_compression_override_validation() {
+        let parse = |algorithm: Option<&str>, level: Option<&str>| CompressionOverride::new(
+            algorithm.map(|a| a.parse::<CompressionAlgorithm>().unwrap()),
+            level.map(|l| l.parse::<CompressionLevel>().unwrap()),
+        );
+
+        assert!(parse(Some("zstd"), Some("19")).is_ok());
+        assert!(parse(Some("brotli"), Some("best")).is_ok());
+        assert!(parse(Some("none"), None).is_ok());
+        assert!(parse(None, Some("fastest")).is_ok());
+        assert!(parse(Some("zstd"), Some("23")).is_err());
+        assert!(parse(Some("brotli"), Some("12")).is_err());
+        assert!(parse(Some("lz4"), Some("0")).is_err());
+        assert!(parse(Some("none"), Some("fast")).is_err());
+        assert!(parse(None, Some("5")).is_err());
+        assert!("quick".parse::<CompressionLevel>().is_err());
+
+        // A forced algorithm replaces the adaptive choice; a level alone only
+        // changes files that get compressed anyway
+        let selected = (CompressionAlgorithm::Lz4, CompressionLevel::Fast);
+        let skipped = (CompressionAlgorithm::None, CompressionLevel::Fastest);
+        let none = parse(Some("none"), None).unwrap();
+        assert_eq!(none.apply(selected).0, CompressionAlgorithm::None);
+        let best = parse(None, Some("best")).unwrap();
+        assert_eq!(best.apply(selected), (CompressionAlgorithm::Lz4, CompressionLevel::Best));
+        assert_eq!(best.apply(skipped), skipped);
+        assert_eq!(CompressionOverride::default().apply(selected), selected);
+    }
+
     #[test]
     fn test_small_data_no_compression() {
         let mut engine = CompressionEngine::new();
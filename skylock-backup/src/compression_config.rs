@@ -1,9 +1,14 @@
 //! Compression configuration and benchmarking
 //! 
-//! Provides configurable compression levels with performance/ratio trade-offs
+//! Provides configurable compression levels with performance/ratio trade-offs,
+//! and per-file-type compression profiles
 
+use std::collections::BTreeMap;
+use std::path::Path;
 use serde::{Serialize, Deserialize};
+use crate::compression::{self, CompressionAlgorithm, CompressionOverride};
 use crate::error::{Result, SkylockError};
+use crate::find::PathGlob;
 
 /// Compression level preset
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -96,6 +101,128 @@ impl CompressionConfig {
     }
 }
 
+/// What a compression profile is matched against
+#[derive(Debug, Clone)]
+enum ProfileKey {
+    /// Glob over the file name, or the whole path if it has a `/`
+    Glob(PathGlob),
+    /// MIME type guessed from the extension, e.g. `text/plain`
+    Mime(String),
+    /// Every MIME type of a kind, e.g. `image/*`
+    MimeKind(String),
+}
+
+/// Compression pinned per file type (`backup.compression_profiles`)
+/// 
+/// Keys are globs such as `*.jpg`, or MIME types such as `image/*` or
+/// `text/plain` (anything starting with a letter and holding a single `/`)
+/// guessed from the file extension. Values are an algorithm with an optional
+/// level, e.g. `none`, `lz4` or `zstd:19`. A glob match wins over a MIME
+/// match and a longer glob over a shorter one; files matching nothing are
+/// left to the adaptive selection.
+#[derive(Debug, Clone, Default)]
+pub struct CompressionProfiles {
+    /// In the order they are tried
+    profiles: Vec<(String, ProfileKey, CompressionOverride)>,
+}
+
+impl CompressionProfiles {
+    /// Parse profiles from `pattern = "algorithm[:level]"` pairs
+    pub fn parse(profiles: &BTreeMap<String, String>) -> Result<Self> {
+        let mut parsed = Vec::new();
+        for (key, value) in profiles {
+            let invalid = |reason: String| {
+                SkylockError::Backup(format!("Invalid compression profile {} = \"{}\": {}", key, value, reason))
+            };
+            let (algorithm, level) = match value.split_once(':') {
+                Some((algorithm, level)) => (algorithm, Some(level)),
+                None => (value.as_str(), None),
+            };
+            let algorithm = algorithm.trim().parse::<CompressionAlgorithm>()
+                .map_err(|e| invalid(e.to_string()))?;
+            let level = level
+                .map(|level| level.trim().parse::<compression::CompressionLevel>())
+                .transpose()
+                .map_err(|e| invalid(e.to_string()))?;
+            let compression = CompressionOverride::new(Some(algorithm), level)
+                .map_err(|e| invalid(e.to_string()))?;
+            parsed.push((key.clone(), Self::key(key), compression));
+        }
+        
+        parsed.sort_by_key(|(key, matcher, _)| match matcher {
+            ProfileKey::Glob(_) => (0, std::cmp::Reverse(key.len())),
+            ProfileKey::Mime(_) => (1, std::cmp::Reverse(0)),
+            ProfileKey::MimeKind(_) => (2, std::cmp::Reverse(0)),
+        });
+        Ok(Self { profiles: parsed })
+    }
+    
+    fn key(key: &str) -> ProfileKey {
+        let is_mime = key.starts_with(|c: char| c.is_ascii_alphabetic()) && key.matches('/').count() == 1;
+        match key.split_once('/') {
+            Some((kind, "*")) if is_mime => ProfileKey::MimeKind(kind.to_ascii_lowercase()),
+            _ if is_mime => ProfileKey::Mime(key.to_ascii_lowercase()),
+            _ => ProfileKey::Glob(PathGlob::new(key)),
+        }
+    }
+    
+    /// Whether no profile is configured
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+    
+    /// The profile for `path`, as its key and the compression it pins, or
+    /// `None` to choose adaptively
+    pub fn select(&self, path: &Path) -> Option<(&str, CompressionOverride)> {
+        let mime = mime_type(path);
+        self.profiles.iter()
+            .find(|(_, matcher, _)| match matcher {
+                ProfileKey::Glob(glob) => glob.matches(path),
+                ProfileKey::Mime(mime_type) => mime == Some(mime_type.as_str()),
+                ProfileKey::MimeKind(kind) => mime.and_then(|m| m.split_once('/')).is_some_and(|(k, _)| k == kind),
+            })
+            .map(|(key, _, compression)| (key.as_str(), *compression))
+    }
+}
+
+/// MIME type of common file types, guessed from the extension
+fn mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        "tif" | "tiff" => "image/tiff",
+        "svg" => "image/svg+xml",
+        "mp4" | "m4v" => "video/mp4",
+        "mkv" => "video/x-matroska",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        "flac" => "audio/flac",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "m4a" => "audio/mp4",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "zst" => "application/zstd",
+        "xz" => "application/x-xz",
+        "7z" => "application/x-7z-compressed",
+        "pdf" => "application/pdf",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "sql" => "application/sql",
+        "txt" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "md" => "text/markdown",
+        _ => return None,
+    })
+}
+
 /// Compression statistics
 #[derive(Debug, Clone)]
 pub struct CompressionStats {
@@ -173,4 +300,39 @@ mod tests {
         };
         assert!(!no_compression.should_compress(100 * 1024 * 1024)); // Never compress
     }
+    
+    #[test]
+    fn test_compression_profiles_match_by_extension_and_mime() {
+        let profiles = CompressionProfiles::parse(&BTreeMap::from([
+            ("*.jpg".to_string(), "none".to_string()),
+            ("*.log".to_string(), "zstd:19".to_string()),
+            ("*.sql".to_string(), "zstd:12".to_string()),
+            ("audit*.log".to_string(), "lz4".to_string()),
+            ("image/*".to_string(), "lz4".to_string()),
+            ("text/csv".to_string(), "brotli:best".to_string()),
+        ])).unwrap();
+        let select = |path: &str| profiles.select(Path::new(path)).map(|(key, c)| (key.to_string(), c));
+        
+        let (key, jpg) = select("/photos/IMG_0001.jpg").unwrap();
+        assert_eq!(key, "*.jpg");
+        assert_eq!(jpg.algorithm, Some(CompressionAlgorithm::None));
+        let (_, log) = select("/var/log/syslog.log").unwrap();
+        assert_eq!((log.algorithm, log.level), (Some(CompressionAlgorithm::Zstd), Some(compression::CompressionLevel::Custom(19))));
+        let (_, sql) = select("/srv/dump.sql").unwrap();
+        assert_eq!(sql.level, Some(compression::CompressionLevel::Custom(12)));
+        
+        // The longer glob wins, then globs over MIME types
+        assert_eq!(select("/var/log/audit-2024.log").unwrap().0, "audit*.log");
+        assert_eq!(select("/photos/scan.PNG").unwrap().0, "image/*");
+        assert_eq!(select("/data/report.csv").unwrap().0, "text/csv");
+        
+        // Unknown extensions fall through to the adaptive selection
+        assert!(select("/src/main.rs").is_none());
+        assert!(select("/bin/tool").is_none());
+        
+        let invalid = |value: &str| CompressionProfiles::parse(&BTreeMap::from([("*.x".to_string(), value.to_string())]));
+        assert!(invalid("zip").is_err());
+        assert!(invalid("zstd:99").is_err());
+        assert!(invalid("none:3").is_err());
+    }
 }
@@ -11,6 +11,7 @@
 use std::io::{Read, Write};
 use sha2::{Sha256, Digest};
 use zstd;
+use crate::compression::CompressionAlgorithm;
 use crate::error::{Result, SkylockError};
 
 /// Compression level (matches zstd default for skylock)
@@ -279,6 +280,9 @@ pub fn verify_compressed_hash(data: &[u8], expected_hash: &str) -> bool {
 pub struct CompressionMetadata {
     /// Whether the file was compressed
     pub compressed: bool,
+    /// Algorithm used; absent in older metadata, where `compressed` implies zstd
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<CompressionAlgorithm>,
     /// Hash of original (uncompressed) data
     pub original_hash: String,
     /// Size of original data
@@ -289,6 +293,10 @@ pub struct CompressionMetadata {
     pub compression_level: Option<i32>,
     /// Compression ratio achieved
     pub compression_ratio: Option<f64>,
+    /// Key of the `backup.compression_profiles` entry that chose the
+    /// algorithm; absent when it was chosen adaptively or forced for the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
 }
 
 impl CompressionMetadata {
@@ -296,11 +304,13 @@ impl CompressionMetadata {
     pub fn uncompressed(data: &[u8]) -> Self {
         Self {
             compressed: false,
+            algorithm: Some(CompressionAlgorithm::None),
             original_hash: calculate_hash(data),
             original_size: data.len() as u64,
             compressed_hash: None,
             compression_level: None,
             compression_ratio: None,
+            profile: None,
         }
     }
     
@@ -308,6 +318,11 @@ impl CompressionMetadata {
     pub fn from_verified(result: &VerifiedCompression, level: i32) -> Self {
         Self {
             compressed: result.was_compressed,
+            algorithm: Some(if result.was_compressed {
+                CompressionAlgorithm::Zstd
+            } else {
+                CompressionAlgorithm::None
+            }),
             original_hash: result.original_hash.clone(),
             original_size: result.original_size,
             compressed_hash: if result.was_compressed {
@@ -325,6 +340,59 @@ impl CompressionMetadata {
             } else {
                 None
             },
+            profile: None,
+        }
+    }
+    
+    /// Create metadata for `compressed_data`, the output of `algorithm` at
+    /// `level` applied to data with the given hash and size
+    pub fn for_algorithm(
+        algorithm: CompressionAlgorithm,
+        level: i32,
+        original_hash: String,
+        original_size: u64,
+        compressed_data: &[u8],
+    ) -> Self {
+        if algorithm == CompressionAlgorithm::None {
+            return Self {
+                compressed: false,
+                algorithm: Some(algorithm),
+                original_hash,
+                original_size,
+                compressed_hash: None,
+                compression_level: None,
+                compression_ratio: None,
+                profile: None,
+            };
+        }
+        
+        Self {
+            compressed: true,
+            algorithm: Some(algorithm),
+            original_hash,
+            original_size,
+            compressed_hash: Some(calculate_hash(compressed_data)),
+            compression_level: Some(level),
+            compression_ratio: Some(compressed_data.len() as f64 / original_size.max(1) as f64),
+            profile: None,
+        }
+    }
+    
+    /// Size of the stored data before encryption, from the recorded ratio
+    /// (the original size when uncompressed or the ratio wasn't recorded)
+    pub fn compressed_size(&self) -> u64 {
+        match self.compression_ratio {
+            Some(ratio) if self.compressed => (self.original_size as f64 * ratio).round() as u64,
+            _ => self.original_size,
+        }
+    }
+    
+    /// Algorithm needed to decompress the stored data
+    pub fn algorithm(&self) -> CompressionAlgorithm {
+        match self.algorithm {
+            Some(algorithm) => algorithm,
+            None if self.compressed => CompressionAlgorithm::Zstd,
+            None => CompressionAlgorithm::None,
         }
     }
 }
@@ -472,6 +540,28 @@ mod tests {
         assert!(metadata.compression_level.is_none());
     }
     
+    #[test]
+    fn test_metadata_algorithm() {
+        let data = "Compressible data ".repeat(1000).into_bytes();
+        let compressed = crate::compression::CompressionEngine::new()
+            .compress_with_algorithm(&data, CompressionAlgorithm::Lz4, crate::compression::CompressionLevel::Fast)
+            .unwrap()
+            .data;
+        let metadata = CompressionMetadata::for_algorithm(
+            CompressionAlgorithm::Lz4, 3, calculate_hash(&data), data.len() as u64, &compressed,
+        );
+        assert!(metadata.compressed);
+        assert_eq!(metadata.algorithm(), CompressionAlgorithm::Lz4);
+        assert_eq!(metadata.compressed_hash, Some(calculate_hash(&compressed)));
+        
+        // Metadata written before the algorithm was recorded means zstd
+        let legacy: CompressionMetadata = serde_json::from_str(
+            r#"{"compressed":true,"original_hash":"abc","original_size":10,"compressed_hash":null,"compression_level":3,"compression_ratio":0.5}"#
+        ).unwrap();
+        assert_eq!(legacy.algorithm(), CompressionAlgorithm::Zstd);
+        assert_eq!(CompressionMetadata::uncompressed(b"x").algorithm(), CompressionAlgorithm::None);
+    }
+    
     #[test]
     fn test_streaming_compression() {
         let verifier = CompressionVerifier::new();
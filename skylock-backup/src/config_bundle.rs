@@ -0,0 +1,182 @@
+//! Configuration bundle for disaster recovery
+//!
+//! `skylock backup-config` stores the configuration and the keyfile's
+//! recovery key on the storage box, encrypted with a key derived from the
+//! passphrase, so a lost machine can be rebuilt from the storage box alone
+//! with `skylock recover-config`.
+//!
+//! Secrets are removed from the configuration before it is bundled, and the
+//! keyfile is only included as its recovery key, whose data keys stay
+//! wrapped by the passphrase. Neither the raw data key nor a plaintext
+//! password is ever part of the bundle.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use skylock_core::Config;
+use std::path::PathBuf;
+
+use crate::encryption::{AeadAlgorithm, EncryptionManager, KdfParams};
+use crate::error::{Result, SkylockError};
+use crate::key_rotation::KeyRotationManager;
+
+/// Where the bundle is kept on the storage box
+pub const CONFIG_BUNDLE_PATH: &str = "/skylock/config/bundle.json";
+
+/// Layout version of [`SealedBundle`]
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Additional data the bundle is encrypted with
+const BUNDLE_AAD: (&str, &str) = ("config-bundle", "bundle.json");
+
+/// Configuration and key material needed to recover an installation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    /// When the bundle was made
+    pub created_at: DateTime<Utc>,
+    /// The configuration with its secrets emptied
+    pub config: Config,
+    /// Config fields that were emptied (e.g. "hetzner.password"), to be
+    /// filled in again on recovery
+    pub redacted: Vec<String>,
+    /// Recovery key of the keyfile (see
+    /// [`KeyRotationManager::export_recovery_key`]), if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery_key: Option<String>,
+}
+
+/// Stored form of a bundle: the passphrase's KDF parameters and the
+/// encrypted bundle
+#[derive(Serialize, Deserialize)]
+struct SealedBundle {
+    format_version: u32,
+    kdf_params: KdfParams,
+    aead_algorithm: AeadAlgorithm,
+    /// Base64 of `[nonce][ciphertext]`
+    ciphertext: String,
+}
+
+impl ConfigBundle {
+    /// Bundle `config`, and the keyfile in its data directory if there is
+    /// one, which `passphrase` must unlock
+    pub fn new(config: &Config, passphrase: &str) -> Result<Self> {
+        let keyfile = KeyRotationManager::keyfile_path(&config.data_dir);
+        let recovery_key = if keyfile.exists() {
+            Some(KeyRotationManager::export_recovery_key(keyfile, passphrase)?)
+        } else {
+            None
+        };
+        let (config, redacted) = Self::redact(config);
+
+        Ok(Self {
+            created_at: Utc::now(),
+            config,
+            redacted,
+            recovery_key,
+        })
+    }
+
+    /// `config` with every password, key and token emptied, and the names
+    /// of the fields that held one
+    pub fn redact(config: &Config) -> (Config, Vec<String>) {
+        let mut config = config.clone();
+        let mut redacted = Vec::new();
+        let mut clear = |name: &str, value: &mut String| {
+            if !value.is_empty() {
+                value.clear();
+                redacted.push(name.to_string());
+            }
+        };
+        clear("syncthing.api_key", &mut config.syncthing.api_key);
+        clear("hetzner.password", &mut config.hetzner.password);
+        clear("hetzner.encryption_key", &mut config.hetzner.encryption_key);
+        if let Some(sftp) = config.hetzner.sftp.as_mut() {
+            if let Some(mut passphrase) = sftp.key_passphrase.take() {
+                clear("hetzner.sftp.key_passphrase", &mut passphrase);
+            }
+        }
+        if let Some(mut secret) = config.notifications.webhook_secret.take() {
+            clear("notifications.webhook_secret", &mut secret);
+        }
+        if let Some(email) = config.notifications.email.as_mut() {
+            if let Some(mut password) = email.password.take() {
+                clear("notifications.email.password", &mut password);
+            }
+        }
+        (config, redacted)
+    }
+
+    /// Encrypt the bundle with a key derived from `passphrase` under a
+    /// fresh salt
+    pub fn seal(&self, passphrase: &str) -> Result<Vec<u8>> {
+        let json = zeroize::Zeroizing::new(serde_json::to_vec(self)
+            .map_err(|e| SkylockError::Backup(format!("Failed to serialize config bundle: {}", e)))?);
+        let encryption = EncryptionManager::new(passphrase)?;
+        let ciphertext = encryption.encrypt_with_aad(&json, BUNDLE_AAD.0, BUNDLE_AAD.1)?;
+
+        let sealed = SealedBundle {
+            format_version: BUNDLE_FORMAT_VERSION,
+            kdf_params: encryption.kdf_params().clone(),
+            aead_algorithm: encryption.algorithm(),
+            ciphertext: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, ciphertext),
+        };
+        serde_json::to_vec_pretty(&sealed)
+            .map_err(|e| SkylockError::Backup(format!("Failed to serialize config bundle: {}", e)))
+    }
+
+    /// Decrypt a bundle made by [`Self::seal`]
+    pub fn open(data: &[u8], passphrase: &str) -> Result<Self> {
+        let sealed: SealedBundle = serde_json::from_slice(data)
+            .map_err(|e| SkylockError::Backup(format!("Invalid config bundle: {}", e)))?;
+        if sealed.format_version > BUNDLE_FORMAT_VERSION {
+            return Err(SkylockError::Backup(format!(
+                "Config bundle format {} is newer than this version of Skylock supports ({})",
+                sealed.format_version, BUNDLE_FORMAT_VERSION
+            )));
+        }
+        let ciphertext = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &sealed.ciphertext)
+            .map_err(|e| SkylockError::Backup(format!("Invalid config bundle: {}", e)))?;
+
+        let encryption = EncryptionManager::from_password_and_params(passphrase, &sealed.kdf_params)?
+            .with_algorithm(sealed.aead_algorithm);
+        let json = encryption.decrypt_with_aad(&ciphertext, BUNDLE_AAD.0, BUNDLE_AAD.1)
+            .map_err(|_| SkylockError::WrongKey("the passphrase doesn't decrypt the config bundle".to_string()))?;
+        let json = zeroize::Zeroizing::new(json);
+        serde_json::from_slice(&json)
+            .map_err(|e| SkylockError::Backup(format!("Invalid config bundle: {}", e)))
+    }
+
+    /// The bundled configuration with the redacted secrets taken from
+    /// `current`, e.g. the configuration used to reach the storage box
+    pub fn restored_config(&self, current: &Config) -> Config {
+        let mut config = self.config.clone();
+        for field in &self.redacted {
+            match field.as_str() {
+                "syncthing.api_key" => config.syncthing.api_key = current.syncthing.api_key.clone(),
+                "hetzner.password" => config.hetzner.password = current.hetzner.password.clone(),
+                "hetzner.encryption_key" => config.hetzner.encryption_key = current.hetzner.encryption_key.clone(),
+                "hetzner.sftp.key_passphrase" => {
+                    let passphrase = current.hetzner.sftp.as_ref().and_then(|sftp| sftp.key_passphrase.clone());
+                    if let Some(sftp) = config.hetzner.sftp.as_mut() {
+                        sftp.key_passphrase = passphrase;
+                    }
+                }
+                "notifications.webhook_secret" => {
+                    config.notifications.webhook_secret = current.notifications.webhook_secret.clone();
+                }
+                "notifications.email.password" => {
+                    let password = current.notifications.email.as_ref().and_then(|email| email.password.clone());
+                    if let Some(email) = config.notifications.email.as_mut() {
+                        email.password = password;
+                    }
+                }
+                _ => {}
+            }
+        }
+        config
+    }
+
+    /// Remote path of the bundle
+    pub fn remote_path() -> PathBuf {
+        PathBuf::from(CONFIG_BUNDLE_PATH)
+    }
+}
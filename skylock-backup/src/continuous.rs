@@ -12,7 +12,7 @@ use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 
 use crate::watcher::{FileWatcher, WatcherConfig, FileEvent, FileEventKind, EventBatch};
-use crate::sync_queue::{SyncQueueProcessor, SyncQueueConfig, SyncItem, SyncAction, SyncResult};
+use crate::sync_queue::{self, SyncQueueProcessor, SyncQueueConfig, SyncItem, SyncAction, SyncResult, RemoteTarget, SyncedCopy};
 use crate::sync_state::{SyncStateManager, SyncStateConfig, SyncStatus, SyncAction as StateAction};
 
 /// Configuration for continuous backup
@@ -84,6 +84,8 @@ pub struct ContinuousBackup {
     result_rx: mpsc::Receiver<SyncResult>,
     /// Sync state manager
     state: Arc<RwLock<SyncStateManager>>,
+    /// Remote copies checked for conflicts before uploading
+    remote: Option<RemoteTarget>,
     /// Shutdown signal sender
     shutdown_tx: broadcast::Sender<()>,
     /// Statistics
@@ -116,6 +118,7 @@ impl ContinuousBackup {
             queue: Arc::new(queue),
             result_rx,
             state: Arc::new(RwLock::new(state)),
+            remote: None,
             shutdown_tx,
             stats: Arc::new(RwLock::new(ContinuousBackupStats::default())),
             is_running: Arc::new(RwLock::new(false)),
@@ -123,6 +126,13 @@ impl ContinuousBackup {
         })
     }
 
+    /// Check uploads against the remote copies in `remote` first, resolving
+    /// conflicts by the queue's conflict policy
+    pub fn with_remote(mut self, remote: RemoteTarget) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
     /// Start the continuous backup daemon
     pub async fn start(&mut self) -> Result<(), ContinuousBackupError> {
         if *self.is_running.read().await {
@@ -313,6 +323,7 @@ impl ContinuousBackup {
         let queue = self.queue.clone();
         let state = self.state.clone();
         let stats = self.stats.clone();
+        let remote = self.remote.clone();
         let mut shutdown_rx = self.shutdown_tx.subscribe();
         
         tokio::spawn(async move {
@@ -327,23 +338,69 @@ impl ContinuousBackup {
                         if let Some(item) = queue.next_item().await {
                             debug!("Processing sync item: {:?}", item.path);
                             
+                            // Don't overwrite a remote copy changed since the last sync
+                            let checked = match &remote {
+                                Some(remote) => {
+                                    let mut state = state.write().await;
+                                    queue.check_upload(item.clone(), remote, &mut state).await
+                                }
+                                None => Ok(None),
+                            };
+                            let item = match checked {
+                                Ok(Some(outcome)) => {
+                                    stats.write().await.conflicts_resolved += 1;
+                                    if outcome.item.action == SyncAction::Skip {
+                                        queue.complete_item(SyncResult {
+                                            item: outcome.item,
+                                            success: true,
+                                            error: None,
+                                            bytes_transferred: 0,
+                                            duration_ms: 0,
+                                        }).await;
+                                        continue;
+                                    }
+                                    outcome.item
+                                }
+                                Ok(None) => item,
+                                Err(e) => {
+                                    warn!("Conflict check for {:?} failed: {}", item.path, e);
+                                    state.write().await.mark_failed(&item.path, e.to_string());
+                                    stats.write().await.errors += 1;
+                                    queue.complete_item(SyncResult {
+                                        item,
+                                        success: false,
+                                        error: Some(e.to_string()),
+                                        bytes_transferred: 0,
+                                        duration_ms: 0,
+                                    }).await;
+                                    continue;
+                                }
+                            };
+                            
                             // Mark as syncing in state
                             {
                                 let mut state = state.write().await;
                                 state.mark_syncing(&item.path);
                             }
                             
-                            // In a real implementation, this would perform the actual sync
-                            // For now, we simulate it
                             let start = Instant::now();
-                            let result = simulate_sync(&item).await;
+                            let (result, copy) = sync_item(&item, remote.as_ref()).await;
                             let duration = start.elapsed().as_millis() as u64;
+                            // Without an upload there is no remote version,
+                            // but the synced content is still recorded
+                            let content_hash = match (&copy, result.success) {
+                                (None, true) => sync_queue::sha256_file(&item.path).await.ok(),
+                                _ => None,
+                            };
                             
                             // Update state and stats
                             {
                                 let mut state = state.write().await;
                                 if result.success {
-                                    state.mark_synced(&item.path, None);
+                                    match &copy {
+                                        Some(copy) => copy.record(&mut state, &item.path),
+                                        None => state.mark_synced(&item.path, content_hash),
+                                    }
                                     state.record_sync(
                                         &item.path,
                                         StateAction::Upload,
@@ -467,6 +524,38 @@ impl ContinuousBackup {
     }
 }
 
+/// Upload `item` through `remote`, returning the result and what the upload
+/// left behind
+/// 
+/// Without a remote, and for actions other than uploads, the sync is still
+/// simulated.
+async fn sync_item(item: &SyncItem, remote: Option<&RemoteTarget>) -> (SyncResult, Option<SyncedCopy>) {
+    let remote = match remote {
+        Some(remote) if item.action == SyncAction::Upload => remote,
+        _ => return (simulate_sync(item).await, None),
+    };
+    
+    let start = Instant::now();
+    let uploaded = remote.upload(&item.path).await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match uploaded {
+        Ok(copy) => (SyncResult {
+            item: item.clone(),
+            success: true,
+            error: None,
+            bytes_transferred: copy.size,
+            duration_ms,
+        }, Some(copy)),
+        Err(e) => (SyncResult {
+            item: item.clone(),
+            success: false,
+            error: Some(e.to_string()),
+            bytes_transferred: 0,
+            duration_ms,
+        }, None),
+    }
+}
+
 /// Simulate a sync operation (placeholder for actual implementation)
 async fn simulate_sync(item: &SyncItem) -> SyncResult {
     // In a real implementation, this would:
@@ -546,4 +635,37 @@ mod tests {
         assert!(stats.started_at.is_none());
         assert_eq!(stats.files_synced, 0);
     }
+
+    #[tokio::test]
+    async fn test_sync_item_uploads_through_remote() {
+        use skylock_core::storage::{LocalStorageProvider, StorageConfig};
+        
+        let local = TempDir::new().unwrap();
+        let remote_root = TempDir::new().unwrap();
+        let backend = LocalStorageProvider::new(&StorageConfig {
+            connection_string: Some(remote_root.path().to_string_lossy().into_owned()),
+            ..Default::default()
+        }).unwrap();
+        let remote = RemoteTarget::new(Arc::new(backend), PathBuf::from("sync"));
+        let path = local.path().join("a.txt");
+        std::fs::write(&path, b"hello").unwrap();
+        
+        let item = SyncItem::new(path.clone(), SyncAction::Upload);
+        let (result, copy) = sync_item(&item, Some(&remote)).await;
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.bytes_transferred, 5);
+        assert_eq!(std::fs::read(remote_root.path().join(remote.remote_path(&path))).unwrap(), b"hello");
+        
+        let copy = copy.expect("an upload leaves a remote copy");
+        let mut state = SyncStateManager::new(SyncStateConfig {
+            db_path: local.path().join("state.db"),
+            ..Default::default()
+        }).unwrap();
+        state.mark_modified(&path, 5, Utc::now());
+        copy.record(&mut state, &path);
+        let recorded = state.get_state(&path).unwrap();
+        assert_eq!(recorded.content_hash.as_deref(), Some(copy.content_hash.as_str()));
+        assert!(recorded.remote_mtime.is_some());
+        assert_eq!(recorded.remote_mtime, remote.version(&path).await.unwrap().mtime);
+    }
 }
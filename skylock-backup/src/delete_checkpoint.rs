@@ -0,0 +1,155 @@
+//! Checkpoints for resumable backup deletion
+//!
+//! Removing a backup takes one request per blob, which for a large backup is
+//! slow enough to be interrupted. [`DirectUploadBackup::delete_backup`](crate::DirectUploadBackup::delete_backup)
+//! records the blobs already gone in `<data_dir>/delete_state/<backup_id>.json`
+//! and removes the manifest only after every blob, so a backup whose deletion
+//! stopped halfway is still listed, and the next cleanup finishes it without
+//! deleting anything twice.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::fs;
+
+use crate::error::{Result, SkylockError};
+use crate::state_file;
+
+/// Blobs of one backup that a deletion already removed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeleteCheckpoint {
+    /// Backup being deleted
+    pub backup_id: String,
+    /// Remote paths of the blobs deleted so far
+    pub deleted: HashSet<String>,
+    /// Last updated timestamp
+    pub last_updated: DateTime<Utc>,
+    /// When the checkpoint was last written to disk (not persisted)
+    #[serde(skip)]
+    last_saved: Option<Instant>,
+}
+
+impl DeleteCheckpoint {
+    /// Empty checkpoint for `backup_id`
+    pub fn new(backup_id: &str) -> Self {
+        Self {
+            backup_id: backup_id.to_string(),
+            deleted: HashSet::new(),
+            last_updated: Utc::now(),
+            last_saved: None,
+        }
+    }
+
+    /// Directory holding deletion checkpoints below the configured data dir
+    pub fn state_dir(data_dir: &Path) -> PathBuf {
+        data_dir.join("delete_state")
+    }
+
+    /// Get the checkpoint file path for a backup ID
+    pub fn state_file_path(state_dir: &Path, backup_id: &str) -> PathBuf {
+        state_dir.join(format!("{}.json", backup_id))
+    }
+
+    /// Saved checkpoint for `backup_id`, or an empty one if there is none
+    /// or it can't be read
+    pub async fn load_or_new(state_dir: &Path, backup_id: &str) -> Self {
+        state_file::load::<Self>(&Self::state_file_path(state_dir, backup_id)).await
+            .filter(|checkpoint| checkpoint.backup_id == backup_id)
+            .unwrap_or_else(|| Self::new(backup_id))
+    }
+
+    /// Whether a deletion of `backup_id` was started and not finished
+    pub async fn exists(state_dir: &Path, backup_id: &str) -> bool {
+        Self::state_file_path(state_dir, backup_id).exists()
+    }
+
+    /// Backups whose deletion was interrupted, in ID order
+    pub async fn pending(state_dir: &Path) -> Result<Vec<String>> {
+        if !state_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = fs::read_dir(state_dir).await
+            .map_err(|e| SkylockError::Backup(format!("Failed to read state directory: {}", e)))?;
+
+        let mut backup_ids = Vec::new();
+        while let Some(entry) = entries.next_entry().await
+            .map_err(|e| SkylockError::Backup(format!("Failed to read directory entry: {}", e)))? {
+
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(backup_id) = path.file_stem().and_then(|s| s.to_str()) {
+                backup_ids.push(backup_id.to_string());
+            }
+        }
+        backup_ids.sort();
+        Ok(backup_ids)
+    }
+
+    /// Save the checkpoint to disk
+    pub async fn save(&mut self, state_dir: &Path) -> Result<()> {
+        state_file::save(&Self::state_file_path(state_dir, &self.backup_id), self, "deletion checkpoint").await?;
+        self.last_saved = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Save the checkpoint if at least `interval` has passed since the last save
+    ///
+    /// Returns whether the checkpoint was written.
+    pub async fn checkpoint(&mut self, state_dir: &Path, interval: Duration) -> Result<bool> {
+        if self.last_saved.is_some_and(|saved| saved.elapsed() < interval) {
+            return Ok(false);
+        }
+        self.save(state_dir).await?;
+        Ok(true)
+    }
+
+    /// Record a blob as deleted
+    pub fn mark_deleted(&mut self, remote_path: String) {
+        self.deleted.insert(remote_path);
+        self.last_updated = Utc::now();
+    }
+
+    /// Whether an earlier run already deleted a blob
+    pub fn is_deleted(&self, remote_path: &str) -> bool {
+        self.deleted.contains(remote_path)
+    }
+
+    /// Delete the checkpoint of a backup, once its deletion has finished
+    pub async fn delete(state_dir: &Path, backup_id: &str) -> Result<()> {
+        state_file::remove(&Self::state_file_path(state_dir, backup_id), "deletion checkpoint").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_pending_deletions_round_trip() {
+        let dir = TempDir::new().unwrap();
+        assert!(DeleteCheckpoint::pending(dir.path()).await.unwrap().is_empty());
+
+        let mut checkpoint = DeleteCheckpoint::load_or_new(dir.path(), "20240102_000000").await;
+        checkpoint.mark_deleted("/skylock/backups/20240102_000000/a.enc".to_string());
+        checkpoint.save(dir.path()).await.unwrap();
+        DeleteCheckpoint::new("20240101_000000").save(dir.path()).await.unwrap();
+
+        assert_eq!(
+            DeleteCheckpoint::pending(dir.path()).await.unwrap(),
+            vec!["20240101_000000", "20240102_000000"]
+        );
+        let loaded = DeleteCheckpoint::load_or_new(dir.path(), "20240102_000000").await;
+        assert!(loaded.is_deleted("/skylock/backups/20240102_000000/a.enc"));
+        assert!(!loaded.is_deleted("/skylock/backups/20240102_000000/b.enc"));
+
+        DeleteCheckpoint::delete(dir.path(), "20240102_000000").await.unwrap();
+        assert!(!DeleteCheckpoint::exists(dir.path(), "20240102_000000").await);
+        assert_eq!(DeleteCheckpoint::pending(dir.path()).await.unwrap(), vec!["20240101_000000"]);
+    }
+}
@@ -2,10 +2,16 @@
 //!
 //! Provides functionality to compare two backups and identify differences.
 
+use crate::change_tracker::{ChangeType, FileIndex};
 use crate::direct_upload::BackupManifest;
+use crate::error::Result;
+use crate::file_filter::FileFilter;
+use crate::verification::ProgressCallback;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use tokio_util::sync::CancellationToken;
 
 /// Represents the difference between two backups
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +24,13 @@ pub struct BackupDiff {
     pub timestamp_old: DateTime<Utc>,
     /// Timestamp of new backup
     pub timestamp_new: DateTime<Utc>,
+    /// Content fingerprint of the old backup, see
+    /// [`BackupManifest::content_fingerprint`]
+    #[serde(default)]
+    pub fingerprint_old: String,
+    /// Content fingerprint of the new backup
+    #[serde(default)]
+    pub fingerprint_new: String,
     /// Files that were added in the new backup
     pub files_added: Vec<FileDiff>,
     /// Files that were removed in the new backup
@@ -28,6 +41,10 @@ pub struct BackupDiff {
     pub files_moved: Vec<FileMove>,
     /// Summary statistics
     pub summary: DiffSummary,
+    /// Comparison was cancelled part way; the lists only cover the files
+    /// compared until then
+    #[serde(default)]
+    pub incomplete: bool,
 }
 
 /// Information about a single file in a diff
@@ -94,11 +111,21 @@ pub struct DiffSummary {
     pub size_removed: u64,
     /// Net size change (bytes, can be negative)
     pub size_delta: i64,
+    /// Bytes added plus bytes removed per day between the two backups'
+    /// timestamps; `None` when the new backup isn't later than the old one
+    #[serde(default)]
+    pub churn_bytes_per_day: Option<f64>,
+    /// Net size change per day (negative while shrinking)
+    #[serde(default)]
+    pub growth_bytes_per_day: Option<f64>,
 }
 
 impl BackupDiff {
     /// Compare two backup manifests and generate a diff
     ///
+    /// Files are compared by hash, so between backups of different
+    /// [`BackupManifest::hash_algorithm`]s every file in both shows as modified.
+    ///
     /// # Arguments
     /// * `manifest_old` - The older backup manifest (base)
     /// * `manifest_new` - The newer backup manifest
@@ -106,18 +133,33 @@ impl BackupDiff {
     /// # Returns
     /// * `BackupDiff` containing all differences
     pub fn compare(manifest_old: &BackupManifest, manifest_new: &BackupManifest) -> Self {
+        Self::compare_cancellable(manifest_old, manifest_new, &CancellationToken::new(), None)
+    }
+
+    /// Compare two backup manifests, stopping early when `cancel` is cancelled
+    ///
+    /// `progress` is called with (files compared, total) as the comparison
+    /// goes. A cancelled comparison returns what was found so far with
+    /// `incomplete` set.
+    pub fn compare_cancellable(
+        manifest_old: &BackupManifest,
+        manifest_new: &BackupManifest,
+        cancel: &CancellationToken,
+        progress: Option<&ProgressCallback>,
+    ) -> Self {
         // Convert FileEntry to FileMetadata
         use crate::direct_upload::FileMetadata;
         let old_metadata: Vec<FileMetadata> = manifest_old.files.iter().map(|f| f.into()).collect();
         let new_metadata: Vec<FileMetadata> = manifest_new.files.iter().map(|f| f.into()).collect();
         
-        // Build hash maps for efficient lookup
-        let old_files: HashMap<String, _> = old_metadata
+        // Path-ordered maps, so files are compared (and moves matched) in the
+        // same order whatever order the manifests list them in
+        let old_files: BTreeMap<String, _> = old_metadata
             .iter()
             .map(|f| (f.relative_path.clone(), f))
             .collect();
 
-        let new_files: HashMap<String, _> = new_metadata
+        let new_files: BTreeMap<String, _> = new_metadata
             .iter()
             .map(|f| (f.relative_path.clone(), f))
             .collect();
@@ -125,7 +167,7 @@ impl BackupDiff {
         // Build hash-to-path maps for move detection
         let old_hash_to_paths: HashMap<String, Vec<String>> = {
             let mut map: HashMap<String, Vec<String>> = HashMap::new();
-            for file in &old_metadata {
+            for file in old_files.values() {
                 map.entry(file.hash.clone())
                     .or_insert_with(Vec::new)
                     .push(file.relative_path.clone());
@@ -153,8 +195,26 @@ impl BackupDiff {
         let mut size_added = 0u64;
         let mut size_removed = 0u64;
 
+        let total = new_files.len() + old_files.len();
+        let mut compared = 0;
+        let mut incomplete = false;
+        let step = |compared: &mut usize| -> bool {
+            if cancel.is_cancelled() {
+                return false;
+            }
+            *compared += 1;
+            if let Some(callback) = progress {
+                callback(*compared, total);
+            }
+            true
+        };
+
         // Find added and modified files
         for (path, new_file) in &new_files {
+            if !step(&mut compared) {
+                incomplete = true;
+                break;
+            }
             if let Some(old_file) = old_files.get(path) {
                 // File exists in both backups
                 if old_file.hash == new_file.hash {
@@ -216,6 +276,10 @@ impl BackupDiff {
 
         // Find removed files
         for (path, old_file) in &old_files {
+            if incomplete || !step(&mut compared) {
+                incomplete = true;
+                break;
+            }
             if processed_paths.contains(path) {
                 continue; // Already processed (move or modify)
             }
@@ -234,6 +298,12 @@ impl BackupDiff {
 
         let size_delta = size_added as i64 - size_removed as i64;
 
+        // Rates over the time between the backups, for capacity planning
+        let days = (manifest_new.timestamp - manifest_old.timestamp).num_seconds() as f64 / 86_400.0;
+        let per_day = |bytes: f64| (days > 0.0).then(|| bytes / days);
+        let churn_bytes_per_day = per_day((size_added + size_removed) as f64);
+        let growth_bytes_per_day = per_day(size_delta as f64);
+
         // Sort results by path for consistent output
         files_added.sort_by(|a, b| a.path.cmp(&b.path));
         files_removed.sort_by(|a, b| a.path.cmp(&b.path));
@@ -251,6 +321,8 @@ impl BackupDiff {
             backup_id_new: manifest_new.backup_id.clone(),
             timestamp_old: manifest_old.timestamp,
             timestamp_new: manifest_new.timestamp,
+            fingerprint_old: manifest_old.content_fingerprint(),
+            fingerprint_new: manifest_new.content_fingerprint(),
             files_added,
             files_removed,
             files_modified,
@@ -264,10 +336,120 @@ impl BackupDiff {
                 size_added,
                 size_removed,
                 size_delta,
+                churn_bytes_per_day,
+                growth_bytes_per_day,
             },
+            incomplete,
         }
     }
 
+    /// Compare a backup with the files under `root` as they are now
+    ///
+    /// The backup is the old side and the live tree the new one: added
+    /// files exist only locally, removed files are missing locally, and
+    /// modified files have different content. Only backed-up files under
+    /// `root` are compared, and local files are walked with `filter`. Files
+    /// whose size and modification time match the manifest are taken as
+    /// unchanged; others are hashed. `backup_id_new` is `root`, and the new
+    /// fingerprint is empty.
+    pub async fn compare_local(manifest: &BackupManifest, root: &Path, filter: &FileFilter) -> Result<Self> {
+        let backed_up = FileIndex::from_manifest(manifest, root);
+        let changes = backed_up.detect_changes_filtered(&[root.to_path_buf()], filter).await?;
+        let compressed: HashMap<&Path, bool> = manifest.files.iter()
+            .map(|entry| (entry.local_path.as_path(), entry.compressed))
+            .collect();
+        let was_compressed = |path: &Path| compressed.get(path).copied().unwrap_or(false);
+
+        let mut files_added = Vec::new();
+        let mut files_removed = Vec::new();
+        let mut files_modified = Vec::new();
+        let mut files_moved = Vec::new();
+        let mut size_added = 0u64;
+        let mut size_removed = 0u64;
+
+        for change in &changes {
+            let path = change.path.to_string_lossy().to_string();
+            match (&change.change_type, &change.old_info, &change.new_info) {
+                (ChangeType::Added, _, Some(local)) => {
+                    size_added += local.size;
+                    files_added.push(FileDiff {
+                        path,
+                        size: local.size,
+                        hash: local.hash.clone().unwrap_or_default(),
+                        compressed: false,
+                    });
+                }
+                (ChangeType::Removed, Some(backed_up), _) => {
+                    size_removed += backed_up.size;
+                    files_removed.push(FileDiff {
+                        path,
+                        size: backed_up.size,
+                        hash: backed_up.hash.clone().unwrap_or_default(),
+                        compressed: was_compressed(&change.path),
+                    });
+                }
+                (ChangeType::Modified, Some(backed_up), Some(local)) => {
+                    let size_delta = local.size as i64 - backed_up.size as i64;
+                    if size_delta > 0 {
+                        size_added += size_delta as u64;
+                    } else {
+                        size_removed += size_delta.unsigned_abs();
+                    }
+                    files_modified.push(FileModification {
+                        path,
+                        size_old: backed_up.size,
+                        size_new: local.size,
+                        size_delta,
+                        hash_old: backed_up.hash.clone().unwrap_or_default(),
+                        hash_new: local.hash.clone().unwrap_or_default(),
+                        compression_changed: false,
+                    });
+                }
+                (ChangeType::Moved, _, _) => files_moved.extend(change.to_file_move()),
+                // Same content with a new timestamp
+                _ => {}
+            }
+        }
+
+        let files_unchanged_count = backed_up.file_count()
+            - files_removed.len() - files_modified.len() - files_moved.len();
+        let size_delta = size_added as i64 - size_removed as i64;
+        let now = Utc::now();
+        let days = (now - manifest.timestamp).num_seconds() as f64 / 86_400.0;
+        let per_day = |bytes: f64| (days > 0.0).then(|| bytes / days);
+
+        files_added.sort_by(|a, b| a.path.cmp(&b.path));
+        files_removed.sort_by(|a, b| a.path.cmp(&b.path));
+        files_modified.sort_by(|a, b| a.path.cmp(&b.path));
+        files_moved.sort_by(|a, b| a.path_old.cmp(&b.path_old));
+
+        Ok(BackupDiff {
+            backup_id_old: manifest.backup_id.clone(),
+            backup_id_new: root.to_string_lossy().to_string(),
+            timestamp_old: manifest.timestamp,
+            timestamp_new: now,
+            fingerprint_old: manifest.content_fingerprint(),
+            fingerprint_new: String::new(),
+            summary: DiffSummary {
+                files_added_count: files_added.len(),
+                files_removed_count: files_removed.len(),
+                files_modified_count: files_modified.len(),
+                files_moved_count: files_moved.len(),
+                files_unchanged_count,
+                size_added,
+                size_removed,
+                size_delta,
+                churn_bytes_per_day: per_day((size_added + size_removed) as f64),
+                growth_bytes_per_day: per_day(size_delta as f64),
+            },
+            files_added,
+            files_removed,
+            files_modified,
+            files_moved,
+            incomplete: false,
+        })
+    }
+
     /// Check if there are any differences
     pub fn has_changes(&self) -> bool {
         !self.files_added.is_empty()
@@ -283,6 +465,18 @@ impl BackupDiff {
             + self.files_modified.len()
             + self.files_moved.len()
     }
+
+    /// The `n` modified files whose size changed the most, in either
+    /// direction, largest first (ties in path order)
+    pub fn largest_modifications(&self, n: usize) -> Vec<&FileModification> {
+        let mut modified: Vec<_> = self.files_modified.iter().collect();
+        modified.sort_by(|a, b| {
+            b.size_delta.unsigned_abs().cmp(&a.size_delta.unsigned_abs())
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        modified.truncate(n);
+        modified
+    }
 }
 
 #[cfg(test)]
@@ -293,6 +487,7 @@ mod tests {
 
     fn create_test_manifest(backup_id: &str, files: Vec<FileEntry>) -> BackupManifest {
         BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
             backup_id: backup_id.to_string(),
             timestamp: Utc::now(),
             file_count: files.len(),
@@ -302,9 +497,19 @@ mod tests {
             base_backup_id: None,
             encryption_version: "v2".to_string(),
             kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
             signature: None,
             backup_chain_version: 0,
             encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
         }
     }
 
@@ -315,8 +520,17 @@ mod tests {
             size,
             hash: hash.to_string(),
             compressed,
+            compression: None,
             encrypted: true,
+            key_context: None,
             timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
         }
     }
 
@@ -448,4 +662,75 @@ mod tests {
         assert_eq!(diff.files_moved.len(), 1); // old_name.txt -> new_name.txt
         assert_eq!(diff.total_changes(), 4);
     }
+
+    #[test]
+    fn test_diff_churn_and_largest_modifications() {
+        let files_old = vec![
+            create_file_entry("small.txt", 1_000, "hash1", false),
+            create_file_entry("shrunk.db", 90_000, "hash2", false),
+            create_file_entry("grown.log", 10_000, "hash3", false),
+            create_file_entry("gone.bin", 4_000, "hash4", false),
+        ];
+        let files_new = vec![
+            create_file_entry("small.txt", 1_500, "hash1b", false), // +500
+            create_file_entry("shrunk.db", 30_000, "hash2b", false), // -60000
+            create_file_entry("grown.log", 50_000, "hash3b", false), // +40000
+            create_file_entry("new.bin", 2_000, "hash5", false),
+        ];
+
+        let mut manifest_old = create_test_manifest("backup1", files_old);
+        let mut manifest_new = create_test_manifest("backup2", files_new);
+        manifest_old.timestamp = "2025-01-01T00:00:00Z".parse().unwrap();
+        manifest_new.timestamp = "2025-01-05T00:00:00Z".parse().unwrap();
+
+        let diff = BackupDiff::compare(&manifest_old, &manifest_new);
+
+        // Added 500 + 40000 + 2000, removed 60000 + 4000, over four days
+        assert_eq!(diff.summary.size_added, 42_500);
+        assert_eq!(diff.summary.size_removed, 64_000);
+        assert_eq!(diff.summary.churn_bytes_per_day, Some(106_500.0 / 4.0));
+        assert_eq!(diff.summary.growth_bytes_per_day, Some(-21_500.0 / 4.0));
+
+        // Ranked by absolute size delta, so the shrunk file comes first
+        let top: Vec<_> = diff.largest_modifications(2).iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(top, vec!["shrunk.db", "grown.log"]);
+        assert_eq!(diff.largest_modifications(10).len(), 3);
+
+        // No elapsed time, no rate
+        let same_time = BackupDiff::compare(&manifest_new, &manifest_new);
+        assert_eq!(same_time.summary.churn_bytes_per_day, None);
+        assert_eq!(same_time.summary.growth_bytes_per_day, None);
+    }
+
+    #[test]
+    fn test_diff_cancelled_midway() {
+        let old = create_test_manifest("backup_old", vec![]);
+        let files = (0..10)
+            .map(|i| create_file_entry(&format!("file{}.txt", i), 100, &format!("hash{}", i), false))
+            .collect();
+        let new = create_test_manifest("backup_new", files);
+
+        let full = BackupDiff::compare(&old, &new);
+        assert!(!full.incomplete);
+        assert_eq!(full.files_added.len(), 10);
+
+        // Cancel once three files have been compared
+        let cancel = CancellationToken::new();
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let progress: ProgressCallback = {
+            let cancel = cancel.clone();
+            let seen = seen.clone();
+            std::sync::Arc::new(move |done, total| {
+                seen.lock().unwrap().push((done, total));
+                if done == 3 {
+                    cancel.cancel();
+                }
+            })
+        };
+        let partial = BackupDiff::compare_cancellable(&old, &new, &cancel, Some(&progress));
+
+        assert!(partial.incomplete);
+        assert_eq!(partial.files_added.len(), 3);
+        assert_eq!(*seen.lock().unwrap(), vec![(1, 10), (2, 10), (3, 10)]);
+    }
 }
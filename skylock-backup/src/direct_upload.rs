@@ -4,30 +4,130 @@
 //! Features:
 //! - Per-file AES-256-GCM encryption
 //! - Streaming uploads (no temp files)
-//! - Smart compression for large files (>10MB)
+//! - Per-file adaptive compression (already-compressed files stored as-is)
+//! - Content-defined chunking of large files, so appends only upload new chunks
+//! - Optional streaming of huge files in encrypted segments (`backup.stream_threshold`)
+//! - Optional file size limit that streams or skips larger files (`backup.max_file_size`)
 //! - Adaptive parallel uploads
 //! - Individual file restore capability
 
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use futures::StreamExt;
 use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+use tracing::field::Empty;
+use tracing::Instrument;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 use sha2::{Sha256, Digest};
-use walkdir::WalkDir;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use indicatif::{ProgressBar, ProgressStyle, MultiProgress, HumanBytes, HumanDuration};
 
 use crate::error::{Result, SkylockError};
-use crate::encryption::EncryptionManager;
-use crate::resume_state::ResumeState;
+use crate::encryption::{AeadAlgorithm, EncryptionManager};
+use crate::key_rotation::KeyRotationManager;
+use crate::resume_state::{ResumeState, CHECKPOINT_INTERVAL};
+use crate::delete_checkpoint::DeleteCheckpoint;
 use crate::bandwidth::BandwidthLimiter;
-use crate::change_tracker::{ChangeTracker, FileIndex};
-use crate::parallelism::{ParallelismController, ParallelismConfig};
+use crate::change_tracker::{ChangeTracker, ChangeType, FileChange, FileIndex, FileInfo};
+use crate::parallelism::{self, ParallelismController, ParallelismConfig, ThroughputMetrics};
 use crate::chunking::{ChunkingController, ChunkingConfig};
+use crate::cdc::ContentChunker;
+use crate::blob_naming::BlobNaming;
 use crate::parallel_hash::{ParallelHasher, ParallelHashConfig};
+use crate::xattrs::{self, ExtendedAttribute};
+use crate::compression::{CompressionAlgorithm, CompressionEngine, CompressionLevel, CompressionOverride};
+use crate::compression_integrity::{verify_compressed_hash, CompressionMetadata};
+use crate::content_hash::HashAlgorithm;
+use crate::multipart_download::{MultipartDownloadConfig, MultipartDownloader};
+use crate::block_cache::BlockCache;
+use crate::path_map::PathMap;
+use crate::restore_space::{self, SpaceProbe};
+use crate::encrypted_manifest::ManifestSummary;
+use crate::backup_lock::{BackupLock, STALE_LOCK_AFTER};
+use crate::manifest_checksum;
+use crate::temp_files::{CleanupGuard, TempFiles};
+use crate::retry::{Retrier, BACKUP_OPERATION, RESTORE_OPERATION};
+use crate::locked_files::{self, SkippedFile, SourceFiles, SourceOpen, SourceRead, SourceReader};
+use crate::archive;
+use crate::file_stream::{self, SegmentFraming};
+use crate::find::{self, FileFinder, FindResult, PathGlob, SkippedBackup};
+use crate::file_filter::FileFilter;
+use crate::compression_config::CompressionProfiles;
+use crate::vss::VssSnapshot;
+use crate::retention::{ReclaimEstimate, RetentionManager, TrashEntry};
+use crate::listing_cache::{ListingCache, DEFAULT_LIST_CACHE_TTL};
+use crate::run_summary::{BackupRunSummary, RUN_SUMMARY_FILE};
+use crate::config_bundle::ConfigBundle;
+use crate::manifest_signing::{self, sign_manifest, PublicSignatureKey, SecureSigningKey, SignaturePolicy};
+use skylock_core::ByteSize;
 use skylock_core::Config;
-use skylock_hetzner::HetznerClient;
+use skylock_core::error_types::StorageErrorType;
+use skylock_core::storage::StorageTier;
+use skylock_hetzner::{HetznerClient, SecureSftpConfig, SftpBackend, StorageProtocol};
+
+/// Bytes `encrypt_with_aad` adds to a blob: 12-byte nonce plus 16-byte tag
+const AEAD_OVERHEAD: u64 = 28;
+
+/// Files at least this large are stored as content-defined chunks (16MB)
+const CHUNKED_FILE_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Encrypted pieces of a streamed file queued for upload at once
+const STREAM_UPLOAD_QUEUE: usize = 4;
+
+/// Tag that protects a backup from retention cleanup
+pub const KEEP_TAG: &str = "keep";
+
+/// Manifests downloaded at once when listing backups, unless configured
+pub const DEFAULT_LIST_CONCURRENCY: usize = 8;
+
+/// Blobs deleted at once when removing a backup, unless configured
+pub const DEFAULT_DELETE_CONCURRENCY: usize = 8;
+
+/// Remote directory soft-deleted backups wait in until they are purged
+const TRASH_DIR: &str = "/skylock/trash";
+
+/// File in a trashed backup's directory holding its [`TrashEntry`]
+const TRASH_MARKER: &str = "trash.json";
+
+/// Per-file upload settings, copied into each upload task
+#[derive(Debug, Clone)]
+struct UploadSettings {
+    preserve_xattrs: bool,
+    verify_on_upload: Option<u32>,
+    encrypt_names: bool,
+    compression: CompressionOverride,
+    /// Compression profile that chose `compression`, recorded with the file
+    compression_profile: Option<String>,
+    blob_naming: BlobNaming,
+    stream_threshold: Option<u64>,
+    max_file_size: Option<u64>,
+    skip_oversized_files: bool,
+    stream_framing: SegmentFraming,
+    hash_algorithm: HashAlgorithm,
+    retrier: Arc<Retrier>,
+}
+
+/// What became of a file handed to an upload task
+enum FileOutcome {
+    Uploaded(FileEntry),
+    /// Couldn't be read or was too large; recorded in the manifest instead
+    /// of failing the backup
+    Skipped(SkippedFile),
+    /// Not started because the backup is shutting down
+    Stopped,
+}
+
+/// Blobs stored by earlier backups
+#[derive(Default)]
+struct RemoteBlobs {
+    /// Whole-file blobs keyed by the content hash of the file
+    files: std::collections::HashMap<String, FileEntry>,
+    /// Chunks of large files keyed by the content hash of the chunk
+    chunks: std::collections::HashMap<String, ChunkEntry>,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileEntry {
@@ -37,18 +137,299 @@ pub struct FileEntry {
     pub remote_path: String,
     /// File size in bytes (original, before encryption)
     pub size: u64,
-    /// SHA-256 hash of original file
+    /// Hash of original file, in the manifest's [`BackupManifest::hash_algorithm`]
     pub hash: String,
     /// Whether file was compressed
     pub compressed: bool,
+    /// Algorithm, original size and compressed hash for this file; absent in
+    /// older manifests, where `compressed` implies zstd
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionMetadata>,
     /// Whether file was encrypted (always true)
     pub encrypted: bool,
+    /// Salt of the subkey the file's blob is encrypted with (see
+    /// [`EncryptionManager::derive_file_key`]); absent in older manifests,
+    /// whose blobs use the backup key itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_context: Option<String>,
     /// Timestamp when file was backed up
     pub timestamp: DateTime<Utc>,
+    /// Modification time of the source file, set again on restore; absent
+    /// in older manifests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+    /// Status change time (ctime) of the source file, for reference only
+    /// since it can't be set on restore; absent on non-Unix platforms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed: Option<DateTime<Utc>>,
+    /// Extended attributes (only captured with `--xattrs`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub xattrs: Vec<ExtendedAttribute>,
+    /// Set when the file was moved and this entry reuses the blob uploaded
+    /// for its old path by an earlier backup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_origin: Option<BlobOrigin>,
+    /// Content-defined chunks of a large file, in order; when set the file
+    /// has no blob of its own and `remote_path` is empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<ChunkEntry>,
+    /// Set when the file was streamed (see [`crate::file_stream`]): its blob
+    /// is a sequence of encrypted segments rather than one AEAD message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub framing: Option<SegmentFraming>,
+    /// Hex SHA-256 of the file's blob as stored, checked after multipart
+    /// downloads; absent for chunked files and in older manifests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_hash: Option<String>,
+}
+
+/// One content-defined chunk of a large file, stored as its own blob
+///
+/// Chunks are encrypted for the backup that uploaded them and their content
+/// hash, so later backups of the same file can reference them unchanged.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkEntry {
+    /// Hash of the chunk's original bytes, in the manifest's algorithm
+    pub hash: String,
+    /// Chunk size in bytes (original, before compression and encryption)
+    pub size: u64,
+    /// Remote path of the chunk's blob
+    pub remote_path: String,
+    /// Backup that uploaded the blob
+    pub backup_id: String,
+    /// How the chunk was compressed
+    pub compression: CompressionMetadata,
+    /// Salt of the subkey the chunk's blob is encrypted with; absent in
+    /// older manifests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_context: Option<String>,
+}
+
+impl ChunkEntry {
+    /// Additional data the chunk's blob is encrypted with, next to its backup ID
+    fn aad(hash: &str) -> String {
+        format!("chunk:{}", hash)
+    }
+    
+    /// Subkey salt for a chunk uploaded by `backup_id`
+    fn key_context_for(backup_id: &str, hash: &str) -> String {
+        format!("{}|{}", backup_id, Self::aad(hash))
+    }
+}
+
+/// `encryption`, or the subkey derived from it for a blob's `key_context`
+fn blob_encryption(encryption: &EncryptionManager, key_context: Option<&str>) -> EncryptionManager {
+    match key_context {
+        Some(context) => encryption.derive_file_key(context),
+        None => encryption.for_algorithm(encryption.algorithm()),
+    }
+}
+
+/// Backup and path a reused blob was uploaded under
+///
+/// The blob's AAD binds these rather than the entry's own backup ID and path,
+/// so decryption has to use them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BlobOrigin {
+    /// Backup that uploaded the blob
+    pub backup_id: String,
+    /// Local path the blob was encrypted for
+    pub local_path: PathBuf,
+}
+
+impl FileEntry {
+    /// Subkey salt for the blob of `local_path` uploaded by `backup_id`: the
+    /// backup ID and a hash of the path, so every file gets its own key
+    pub(crate) fn key_context_for(backup_id: &str, local_path: &Path) -> String {
+        let path_hash = crate::compression_integrity::calculate_hash(local_path.to_string_lossy().as_bytes());
+        format!("{}|{}", backup_id, path_hash)
+    }
+    
+    /// Modification and status change times to record for a source file
+    pub(crate) fn source_times(metadata: &std::fs::Metadata) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        #[cfg(unix)]
+        let changed = {
+            use std::os::unix::fs::MetadataExt;
+            DateTime::<Utc>::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32)
+        };
+        #[cfg(not(unix))]
+        let changed = None;
+        (modified, changed)
+    }
+    
+    /// Whether the file is stored as content-defined chunks
+    pub fn is_chunked(&self) -> bool {
+        !self.chunks.is_empty()
+    }
+    
+    /// Whether the file's blob is streamed in segments
+    pub fn is_streamed(&self) -> bool {
+        self.framing.is_some()
+    }
+    
+    /// Chunks holding any of the file's bytes in `range`, each with the file
+    /// offset it starts at (the sum of the sizes of the chunks before it)
+    pub fn chunks_in_range(&self, range: std::ops::Range<u64>) -> Vec<(u64, &ChunkEntry)> {
+        let mut offset = 0;
+        let mut chunks = Vec::new();
+        for chunk in &self.chunks {
+            if offset >= range.end {
+                break;
+            }
+            if offset + chunk.size > range.start {
+                chunks.push((offset, chunk));
+            }
+            offset += chunk.size;
+        }
+        chunks
+    }
+    
+    /// Algorithm the stored data was compressed with
+    pub fn compression_algorithm(&self) -> CompressionAlgorithm {
+        match self.compression {
+            Some(ref metadata) => metadata.algorithm(),
+            None if self.compressed => CompressionAlgorithm::Zstd,
+            None => CompressionAlgorithm::None,
+        }
+    }
+    
+    /// Turn decrypted file data back into the original bytes, checking the
+    /// compressed hash and original size recorded for this file
+    pub fn decompress(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        decompress_blob(
+            data,
+            self.compression_algorithm(),
+            self.compression.as_ref(),
+            &self.local_path.display(),
+        )
+    }
+}
+
+/// Decompress a decrypted blob, checking it against its compression
+/// metadata; `name` identifies the blob in errors
+fn decompress_blob(
+    data: Vec<u8>,
+    algorithm: CompressionAlgorithm,
+    metadata: Option<&CompressionMetadata>,
+    name: &dyn std::fmt::Display,
+) -> Result<Vec<u8>> {
+    if algorithm == CompressionAlgorithm::None {
+        return Ok(data);
+    }
+    
+    if let Some(expected) = metadata.and_then(|m| m.compressed_hash.as_deref()) {
+        if !verify_compressed_hash(&data, expected) {
+            return Err(SkylockError::Integrity(format!(
+                "Compressed data for {} does not match its recorded hash",
+                name
+            )));
+        }
+    }
+    
+    let original = CompressionEngine::new()
+        .decompress_raw(&data, algorithm)
+        .map_err(|e| SkylockError::Compression(format!("{} ({}): {}", name, algorithm, e)))?;
+    
+    if let Some(metadata) = metadata {
+        if original.len() as u64 != metadata.original_size {
+            return Err(SkylockError::Compression(format!(
+                "Decompressed {} is {} bytes, expected {}",
+                name,
+                original.len(),
+                metadata.original_size
+            )));
+        }
+    }
+    
+    Ok(original)
+}
+
+/// The last `lines` lines of `data`, a final line break not starting a new line
+fn tail_lines(data: &[u8], lines: usize) -> &[u8] {
+    if lines == 0 {
+        return &[];
+    }
+    let body = data.strip_suffix(b"\n").unwrap_or(data);
+    let start = body.iter().enumerate().rev()
+        .filter(|&(_, &b)| b == b'\n')
+        .nth(lines - 1)
+        .map_or(0, |(pos, _)| pos + 1);
+    &data[start..]
+}
+
+/// Keeps the bytes at `range` of what is written through it
+struct RangeWriter {
+    range: std::ops::Range<u64>,
+    position: u64,
+    data: Vec<u8>,
+}
+
+impl RangeWriter {
+    fn new(range: std::ops::Range<u64>) -> Self {
+        Self { range, position: 0, data: Vec::new() }
+    }
+}
+
+impl std::io::Write for RangeWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let start = self.range.start.clamp(self.position, self.position + buf.len() as u64);
+        let end = self.range.end.clamp(start, self.position + buf.len() as u64);
+        self.data.extend_from_slice(&buf[(start - self.position) as usize..(end - self.position) as usize]);
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps the last `lines` lines of what is written through it
+struct TailWriter {
+    lines: usize,
+    data: Vec<u8>,
+    /// Length at which the kept bytes are trimmed again
+    trim_at: usize,
+}
+
+impl TailWriter {
+    fn new(lines: usize) -> Self {
+        Self { lines, data: Vec::new(), trim_at: 64 * 1024 }
+    }
+
+    fn into_tail(mut self) -> Vec<u8> {
+        self.trim();
+        self.data
+    }
+
+    fn trim(&mut self) {
+        let start = self.data.len() - tail_lines(&self.data, self.lines).len();
+        self.data.drain(..start);
+    }
+}
+
+impl std::io::Write for TailWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        if self.data.len() >= self.trim_at {
+            self.trim();
+            self.trim_at = (self.data.len() * 2).max(64 * 1024);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BackupManifest {
+    /// Layout version of the manifest; always [`BackupManifest::SCHEMA_VERSION`]
+    /// once loaded, older layouts are upgraded by [`BackupManifest::from_json`]
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
     /// Unique backup ID (e.g., "20251023_211045")
     pub backup_id: String,
     /// When backup was created
@@ -70,16 +451,85 @@ pub struct BackupManifest {
     /// KDF parameters (only for v2, None for legacy v1)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub kdf_params: Option<crate::encryption::KdfParams>,
+    /// AEAD cipher used for file data and the encrypted manifest
+    #[serde(default)]
+    pub aead_algorithm: AeadAlgorithm,
+    /// Algorithm of every file and chunk hash in this backup, left out when
+    /// SHA-256 so manifests signed before the choice existed still verify
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_sha256")]
+    pub hash_algorithm: HashAlgorithm,
+    /// Keyfile data key version used for this backup (None = password-derived key)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_version: Option<u64>,
     /// Digital signature for manifest integrity (v3+)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub signature: Option<ManifestSignature>,
     /// Monotonically increasing version for anti-rollback (v3+)
     #[serde(default)]
     pub backup_chain_version: u64,
-    /// Encrypted path mapping (metadata privacy feature)
-    /// Maps plaintext local paths to encrypted remote paths
+    /// Encrypted file names (metadata privacy feature): maps the placeholders
+    /// stored in place of paths to the encrypted real paths. Only present in
+    /// the serialized manifest; removed again when the names are decrypted
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub encrypted_path_map: Option<std::collections::HashMap<String, String>>,
+    /// Storage class requested for this backup's files ("archive" for
+    /// rarely restored backups such as yearly GFS copies). Omitted when
+    /// standard so signatures over older manifests still verify.
+    #[serde(default, skip_serializing_if = "StorageTier::is_standard")]
+    pub storage_tier: StorageTier,
+    /// Paths that existed in the base backup but were deleted or moved away
+    /// since (incremental backups only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted_paths: Vec<PathBuf>,
+    /// IDs of the backups merged into this synthetic full backup, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub consolidated_from: Vec<String>,
+    /// Every directory below the source paths, so restores recreate empty
+    /// ones and their permissions and timestamps
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directories: Vec<DirectoryEntry>,
+    /// User labels such as "pre-upgrade"; [`KEEP_TAG`] exempts the backup
+    /// from retention cleanup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Freeform description given with `--note`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Source files left out because they were locked or unreadable, with
+    /// the reason; the next backup tries them again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_files: Vec<SkippedFile>,
+}
+
+/// Directory recorded in a backup
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DirectoryEntry {
+    /// Local path of the directory
+    pub path: PathBuf,
+    /// Unix permission bits (absent on other platforms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Last modification time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+}
+
+impl DirectoryEntry {
+    fn from_metadata(path: &Path, metadata: &std::fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        
+        Self {
+            path: path.to_path_buf(),
+            mode,
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        }
+    }
 }
 
 /// Digital signature metadata for manifest integrity
@@ -101,6 +551,178 @@ fn default_encryption_version() -> String {
     "v2".to_string()
 }
 
+/// Manifests written before the schema was versioned
+fn legacy_schema_version() -> u32 {
+    1
+}
+
+impl BackupManifest {
+    /// Schema version written by this build
+    /// 
+    /// 1: manifests without a `schema_version` field
+    /// 2: `schema_version` recorded; the legacy SHA-256 encryption format is
+    ///    named explicitly instead of being implied by missing KDF parameters
+    /// 3: files may be stored as content-defined chunks listed in `chunks`
+    ///    instead of one blob at `remote_path`
+    /// 4: blobs with a `key_context` are encrypted under a per-file subkey
+    /// 5: files with a `framing` are streamed blobs of encrypted segments
+    /// 6: `hash_algorithm` names the content hash, which may be BLAKE3
+    pub const SCHEMA_VERSION: u32 = 6;
+    
+    /// Whether the backup carries `tag`
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+    
+    /// Parse a serialized manifest of any known schema version, upgrading
+    /// older layouts to the current one
+    /// 
+    /// Manifests from a newer schema are rejected with an explanation rather
+    /// than being misread.
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_slice(json)
+            .map_err(|e| SkylockError::Backup(format!("Parse manifest failed: {}", e)))?;
+        let version = match value.get("schema_version") {
+            None => legacy_schema_version(),
+            Some(version) => version.as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| SkylockError::Backup(format!("Invalid manifest schema version: {}", version)))?,
+        };
+        
+        if version > Self::SCHEMA_VERSION {
+            let backup_id = value.get("backup_id").and_then(|id| id.as_str()).unwrap_or("unknown");
+            return Err(SkylockError::Backup(format!(
+                "Backup {} was made by a newer skylock (manifest schema version {}, this version reads up to {}); \
+                 upgrade skylock to use it",
+                backup_id, version, Self::SCHEMA_VERSION
+            )));
+        }
+        if version < 1 {
+            return Err(SkylockError::Backup(format!("Invalid manifest schema version: {}", version)));
+        }
+        if version < 2 {
+            Self::upgrade_v1(&mut value);
+        }
+        
+        let mut manifest: Self = serde_json::from_value(value)
+            .map_err(|e| SkylockError::Backup(format!("Parse manifest (schema version {}) failed: {}", version, e)))?;
+        manifest.schema_version = Self::SCHEMA_VERSION;
+        Ok(manifest)
+    }
+    
+    /// Sort files, directories and deleted paths by path, so manifests of
+    /// identical trees list them in the same order
+    /// 
+    /// Chunk order inside a file entry is significant and left alone.
+    pub fn canonicalize(&mut self) {
+        self.files.sort_by(|a, b| a.local_path.cmp(&b.local_path));
+        self.directories.sort_by(|a, b| a.path.cmp(&b.path));
+        self.deleted_paths.sort();
+    }
+    
+    /// The content-identity fields of this manifest in canonical order
+    /// 
+    /// For an incremental backup this only covers the files it recorded.
+    pub fn content(&self) -> ManifestContent {
+        let mut files: Vec<ContentEntry> = self.files.iter()
+            .map(|entry| ContentEntry {
+                path: entry.local_path.clone(),
+                size: entry.size,
+                hash: entry.hash.clone(),
+                xattrs: entry.xattrs.clone(),
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        for file in &mut files {
+            file.xattrs.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        let mut directories: Vec<DirectoryContent> = self.directories.iter()
+            .map(|dir| DirectoryContent { path: dir.path.clone(), mode: dir.mode })
+            .collect();
+        directories.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut source_paths = self.source_paths.clone();
+        source_paths.sort();
+        let mut deleted_paths = self.deleted_paths.clone();
+        deleted_paths.sort();
+        
+        ManifestContent { source_paths, files, directories, deleted_paths }
+    }
+    
+    /// SHA-256 (hex) of [`BackupManifest::content`]
+    /// 
+    /// Two backups of an unchanged tree have the same fingerprint whatever
+    /// their IDs, timestamps, compression or encryption settings.
+    pub fn content_fingerprint(&self) -> String {
+        let content = serde_json::to_vec(&self.content())
+            .expect("manifest content always serializes");
+        hex::encode(Sha256::digest(&content))
+    }
+    
+    /// v1 -> v2: without KDF parameters the data was encrypted with the
+    /// legacy SHA-256 key, even if `encryption_version` is missing
+    fn upgrade_v1(value: &mut serde_json::Value) {
+        let Some(manifest) = value.as_object_mut() else { return };
+        let has_kdf = manifest.get("kdf_params").is_some_and(|kdf| !kdf.is_null());
+        if !has_kdf && !manifest.contains_key("encryption_version") {
+            manifest.insert("encryption_version".to_string(), "v1".into());
+        }
+    }
+}
+
+/// Content-identity part of a manifest: what was backed up, without run
+/// metadata such as backup IDs, timestamps, remote locations or encryption
+/// details, in canonical (path) order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestContent {
+    pub source_paths: Vec<PathBuf>,
+    pub files: Vec<ContentEntry>,
+    pub directories: Vec<DirectoryContent>,
+    pub deleted_paths: Vec<PathBuf>,
+}
+
+/// Content identity of one backed up file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContentEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// SHA-256 hash of the original file
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub xattrs: Vec<ExtendedAttribute>,
+}
+
+/// Content identity of one recorded directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryContent {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+/// Result of a remote garbage collection pass
+#[derive(Debug, Clone, Default)]
+pub struct GarbageCollectionStats {
+    /// Backup directories that had no live manifest
+    pub orphaned_backups: Vec<String>,
+    /// Remote files deleted
+    pub files_deleted: usize,
+    /// Remote files that could not be deleted
+    pub files_failed: usize,
+}
+
+/// Result of rebuilding the change tracking index from a backup
+#[derive(Debug, Clone, Default)]
+pub struct ReindexStats {
+    /// Backup the index was rebuilt from
+    pub backup_id: String,
+    /// Local files whose content still matches the backup
+    pub verified: usize,
+    /// Local files that differ from the backup
+    pub changed: usize,
+    /// Files in the backup that no longer exist locally
+    pub missing: usize,
+}
+
 /// File metadata for diff operations (simplified version of FileEntry)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMetadata {
@@ -128,13 +750,55 @@ impl From<&FileEntry> for FileMetadata {
     }
 }
 
+/// What a restore does with files that already exist at the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestorePolicy {
+    /// Refuse to restore, reporting the conflicting files
+    #[default]
+    Abort,
+    /// Replace existing files with the backed-up ones
+    Overwrite,
+    /// Leave existing files untouched and only write missing ones
+    SkipExisting,
+}
+
+/// What restoring a file would do to its target path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreAction {
+    /// Nothing exists at the target path yet
+    Create,
+    /// Something with different content would be replaced
+    Overwrite,
+    /// The target already holds the backed-up content
+    Unchanged,
+}
+
+/// One file of a [`DirectUploadBackup::preview_restore`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestorePreviewEntry {
+    /// Where the file would be restored
+    pub target: PathBuf,
+    pub action: RestoreAction,
+    /// Size of the file in the backup
+    pub backup_size: u64,
+    /// Size of the file currently at the target (None = missing or not a
+    /// regular file)
+    pub existing_size: Option<u64>,
+}
+
 pub struct DirectUploadBackup {
     config: Arc<Config>,
     hetzner: Arc<HetznerClient>,
+    /// Encryption for new backups (keyfile's active key when one exists)
     encryption: Arc<EncryptionManager>,
+    /// Password-derived encryption for backups made without a keyfile
+    password_encryption: Arc<EncryptionManager>,
+    /// Unlocked keyfile, if `<data_dir>/keyfile.json` exists
+    key_chain: Option<Arc<KeyRotationManager>>,
     /// Maximum concurrent uploads (adaptive based on system)
     max_parallel: usize,
-    /// Optional bandwidth limiter (bytes per second, None = unlimited)
+    /// Optional bandwidth limiter (bytes per second, None = unlimited),
+    /// shared by every upload worker so the limit caps their combined rate
     bandwidth_limiter: Option<Arc<BandwidthLimiter>>,
     /// Dynamic parallelism controller for adaptive thread scaling
     parallelism_controller: Option<Arc<ParallelismController>>,
@@ -142,6 +806,65 @@ pub struct DirectUploadBackup {
     chunking_controller: Arc<ChunkingController>,
     /// Parallel hasher for efficient file hashing
     parallel_hasher: Arc<ParallelHasher>,
+    /// Capture extended attributes on backup and reapply them on restore
+    preserve_xattrs: bool,
+    /// Split large restores into parallel range requests (None = single stream)
+    multipart_download: Option<MultipartDownloadConfig>,
+    /// Pooled range downloader, created on first large restore
+    downloader: tokio::sync::OnceCell<MultipartDownloader>,
+    /// Read back each uploaded file and re-upload it up to this many times
+    /// on a hash mismatch (None = no read-back)
+    verify_on_upload: Option<u32>,
+    /// Storage class recorded for new backups
+    storage_tier: StorageTier,
+    /// Tags and note recorded for new backups
+    tags: Vec<String>,
+    note: Option<String>,
+    /// Compression forced for this run instead of the adaptive choice
+    compression_override: CompressionOverride,
+    /// Bytes uploaded, and skipped because they were already stored remotely
+    upload_metrics: Arc<ThroughputMetrics>,
+    /// Decrypted blocks kept for repeated previews and partial restores
+    block_cache: Option<Arc<BlockCache>>,
+    /// Prefix rewrites applied to manifest paths when restoring
+    path_map: PathMap,
+    /// Free space source for the restore preflight (None = skip the check)
+    space_probe: Option<SpaceProbe>,
+    /// What restores do with files already at the target
+    restore_policy: RestorePolicy,
+    /// Stops the backup after the files in flight once cancelled
+    shutdown: CancellationToken,
+    /// Where temp files go and how much a transfer keeps in memory
+    temp: TempFiles,
+    /// Reads source files during a backup
+    source_reader: SourceReader,
+    /// Files at least this large are streamed instead of read into memory
+    stream_threshold: Option<u64>,
+    /// Files larger than this are streamed, or skipped with `skip_oversized_files`
+    max_file_size: Option<u64>,
+    /// Skip files larger than `max_file_size` instead of streaming them
+    skip_oversized_files: bool,
+    /// Segment size streamed files are encrypted in
+    stream_framing: SegmentFraming,
+    /// Hash of file and chunk content in new backups
+    hash_algorithm: HashAlgorithm,
+    /// Manifests or summaries downloaded at once when listing backups
+    list_concurrency: usize,
+    /// Blobs deleted at once when removing a backup
+    delete_concurrency: usize,
+    /// Summaries of the last listing, reused briefly (None = always list)
+    listing_cache: Option<ListingCache>,
+    /// Which files under the backup paths are backed up
+    file_filter: FileFilter,
+    /// Compression pinned per file type, tried before the adaptive choice
+    compression_profiles: CompressionProfiles,
+    /// Key new and re-uploaded manifests are signed with, if one was
+    /// generated in the data directory
+    manifest_signing_key: Option<Arc<SecureSigningKey>>,
+    /// Signature check applied to every manifest loaded
+    signature_policy: Arc<SignaturePolicy>,
+    /// Retries of failed transfers, within a budget per backup or restore
+    retrier: Arc<Retrier>,
 }
 
 impl DirectUploadBackup {
@@ -150,11 +873,10 @@ impl DirectUploadBackup {
         "v2".to_string()
     }
     
-    pub fn new(config: Config, hetzner: HetznerClient, encryption: EncryptionManager, bandwidth_limit: Option<u64>) -> Self {
+    /// Fails if a configured key can't be used
+    pub fn new(config: Config, hetzner: HetznerClient, encryption: EncryptionManager, bandwidth_limit: Option<u64>) -> Result<Self> {
         // Adaptive parallelism: Use 4 threads for normal systems, scale down if needed
-        let max_parallel = std::thread::available_parallelism()
-            .map(|n| n.get().min(4))  // Max 4 uploads at once
-            .unwrap_or(2);  // Fallback to 2 if can't detect
+        let max_parallel = parallelism::default_concurrency();
         
         let bandwidth_limiter = bandwidth_limit.map(|limit| {
             Arc::new(BandwidthLimiter::new(limit))
@@ -162,18 +884,69 @@ impl DirectUploadBackup {
         
         // Initialize performance optimization controllers
         let chunking_controller = Arc::new(ChunkingController::new());
-        let parallel_hasher = Arc::new(ParallelHasher::new());
+        let hash_algorithm = Self::configured_hash_algorithm(&config);
+        let parallel_hasher = Arc::new(ParallelHasher::with_config(
+            ParallelHashConfig::default().with_algorithm(hash_algorithm),
+        ));
         
-        Self {
+        let password_encryption = Arc::new(encryption);
+        let key_chain = Self::open_keyfile(&config)?;
+        let encryption = Self::backup_encryption(&password_encryption, key_chain.as_deref())?;
+        let hetzner = Arc::new(Self::configure_client(hetzner, &config)?);
+        let concurrency = config.backup.max_concurrent_uploads;
+        let temp = TempFiles::from_config(&config.backup);
+        let stream_threshold = config.backup.stream_threshold_bytes().ok().flatten();
+        let max_file_size = config.backup.max_file_size_bytes().ok().flatten();
+        let skip_oversized_files = config.backup.skip_oversized_files;
+        let retrier = Arc::new(Retrier::new(config.backup.retry.clone()));
+        let stream_framing = Self::configured_stream_framing(&config);
+        let list_concurrency = config.backup.list_concurrency.unwrap_or(DEFAULT_LIST_CONCURRENCY).max(1);
+        let delete_concurrency = config.backup.delete_concurrency.unwrap_or(DEFAULT_DELETE_CONCURRENCY).max(1);
+        let listing_cache = Some(ListingCache::new(&config.data_dir, DEFAULT_LIST_CACHE_TTL));
+        let (manifest_signing_key, signature_policy) = Self::open_manifest_keys(&config)?;
+        
+        let backup = Self {
             config: Arc::new(config),
-            hetzner: Arc::new(hetzner),
-            encryption: Arc::new(encryption),
+            hetzner,
+            encryption,
+            password_encryption,
+            key_chain,
             max_parallel,
             bandwidth_limiter,
             parallelism_controller: None, // Disabled by default for backward compatibility
             chunking_controller,
             parallel_hasher,
-        }
+            preserve_xattrs: false,
+            multipart_download: Some(MultipartDownloadConfig::default()),
+            downloader: tokio::sync::OnceCell::new(),
+            verify_on_upload: None,
+            storage_tier: StorageTier::Standard,
+            tags: Vec::new(),
+            note: None,
+            compression_override: CompressionOverride::default(),
+            upload_metrics: Arc::new(ThroughputMetrics::new()),
+            block_cache: None,
+            path_map: PathMap::default(),
+            space_probe: Some(restore_space::default_probe()),
+            restore_policy: RestorePolicy::default(),
+            shutdown: CancellationToken::new(),
+            temp,
+            source_reader: locked_files::default_reader(),
+            stream_threshold,
+            max_file_size,
+            skip_oversized_files,
+            retrier,
+            stream_framing,
+            hash_algorithm,
+            list_concurrency,
+            delete_concurrency,
+            listing_cache,
+            file_filter: FileFilter::default(),
+            compression_profiles: CompressionProfiles::default(),
+            manifest_signing_key,
+            signature_policy,
+        };
+        Ok(backup.with_configured_concurrency(concurrency))
     }
     
     /// Create a new DirectUploadBackup with dynamic parallelism enabled
@@ -182,7 +955,7 @@ impl DirectUploadBackup {
         hetzner: HetznerClient,
         encryption: EncryptionManager,
         bandwidth_limit: Option<u64>,
-    ) -> Self {
+    ) -> Result<Self> {
         let parallelism_config = if bandwidth_limit.is_some() {
             ParallelismConfig::default().with_bandwidth_limit(bandwidth_limit.unwrap())
         } else {
@@ -197,115 +970,672 @@ impl DirectUploadBackup {
         });
         
         let chunking_controller = Arc::new(ChunkingController::new());
-        let parallel_hasher = Arc::new(ParallelHasher::new());
+        let hash_algorithm = Self::configured_hash_algorithm(&config);
+        let parallel_hasher = Arc::new(ParallelHasher::with_config(
+            ParallelHashConfig::default().with_algorithm(hash_algorithm),
+        ));
         
-        Self {
+        let password_encryption = Arc::new(encryption);
+        let key_chain = Self::open_keyfile(&config)?;
+        let encryption = Self::backup_encryption(&password_encryption, key_chain.as_deref())?;
+        let hetzner = Arc::new(Self::configure_client(hetzner, &config)?);
+        let concurrency = config.backup.max_concurrent_uploads;
+        let temp = TempFiles::from_config(&config.backup);
+        let stream_threshold = config.backup.stream_threshold_bytes().ok().flatten();
+        let max_file_size = config.backup.max_file_size_bytes().ok().flatten();
+        let skip_oversized_files = config.backup.skip_oversized_files;
+        let retrier = Arc::new(Retrier::new(config.backup.retry.clone()));
+        let stream_framing = Self::configured_stream_framing(&config);
+        let list_concurrency = config.backup.list_concurrency.unwrap_or(DEFAULT_LIST_CONCURRENCY).max(1);
+        let delete_concurrency = config.backup.delete_concurrency.unwrap_or(DEFAULT_DELETE_CONCURRENCY).max(1);
+        let listing_cache = Some(ListingCache::new(&config.data_dir, DEFAULT_LIST_CACHE_TTL));
+        let (manifest_signing_key, signature_policy) = Self::open_manifest_keys(&config)?;
+        
+        let backup = Self {
             config: Arc::new(config),
-            hetzner: Arc::new(hetzner),
-            encryption: Arc::new(encryption),
+            hetzner,
+            encryption,
+            password_encryption,
+            key_chain,
             max_parallel,
             bandwidth_limiter,
             parallelism_controller: Some(parallelism_controller),
             chunking_controller,
             parallel_hasher,
-        }
+            preserve_xattrs: false,
+            multipart_download: Some(MultipartDownloadConfig::default()),
+            downloader: tokio::sync::OnceCell::new(),
+            verify_on_upload: None,
+            storage_tier: StorageTier::Standard,
+            tags: Vec::new(),
+            note: None,
+            compression_override: CompressionOverride::default(),
+            upload_metrics: Arc::new(ThroughputMetrics::new()),
+            block_cache: None,
+            path_map: PathMap::default(),
+            space_probe: Some(restore_space::default_probe()),
+            restore_policy: RestorePolicy::default(),
+            shutdown: CancellationToken::new(),
+            temp,
+            source_reader: locked_files::default_reader(),
+            stream_threshold,
+            max_file_size,
+            skip_oversized_files,
+            retrier,
+            stream_framing,
+            hash_algorithm,
+            list_concurrency,
+            delete_concurrency,
+            listing_cache,
+            file_filter: FileFilter::default(),
+            compression_profiles: CompressionProfiles::default(),
+            manifest_signing_key,
+            signature_policy,
+        };
+        Ok(backup.with_configured_concurrency(concurrency))
     }
     
-    /// Get the current parallelism level
-    pub fn current_parallelism(&self) -> usize {
-        if let Some(ref controller) = self.parallelism_controller {
-            controller.current_parallelism()
-        } else {
-            self.max_parallel
+    /// Apply `backup.max_concurrent_uploads` when it is set
+    fn with_configured_concurrency(self, concurrency: Option<usize>) -> Self {
+        match concurrency {
+            Some(concurrency) => self.with_concurrency(concurrency),
+            None => self,
         }
     }
     
-    /// Get optimal chunk size for a file
-    pub fn chunk_size_for_file(&self, file_size: u64, path: Option<&Path>) -> usize {
-        self.chunking_controller.chunk_size_for_file(file_size, path)
-    }
-
-    /// Create full backup using direct upload strategy
-    pub async fn create_backup(&self, paths: &[PathBuf]) -> Result<BackupManifest> {
-        self.create_backup_internal(paths, false).await
+    /// Apply the listing and deletion rate limits and request timeouts from
+    /// `[hetzner]`, switching to SFTP if `hetzner.protocol` selects it
+    ///
+    /// Fails if SFTP is selected but can't be set up, rather than silently
+    /// using WebDAV.
+    pub(crate) fn configure_client(hetzner: HetznerClient, config: &Config) -> Result<HetznerClient> {
+        let hetzner = hetzner
+            .with_list_rate_limit(config.hetzner.max_list_requests_per_second)
+            .with_delete_rate_limit(config.hetzner.max_delete_requests_per_second)
+            .with_timeouts(
+                Duration::from_secs(config.hetzner.connect_timeout_secs),
+                Duration::from_secs(config.hetzner.read_timeout_secs),
+            );
+        
+        Ok(match Self::sftp_backend(config)? {
+            Some(sftp) => hetzner.with_sftp(sftp),
+            None => hetzner,
+        })
     }
     
-    /// Create incremental backup using direct upload strategy
-    pub async fn create_incremental_backup(&self, paths: &[PathBuf]) -> Result<BackupManifest> {
-        self.create_backup_internal(paths, true).await
+    /// SFTP backend for the storage box if `hetzner.protocol` selects it
+    fn sftp_backend(config: &Config) -> skylock_core::Result<Option<SftpBackend>> {
+        let protocol = config.hetzner.protocol.as_deref()
+            .map(str::parse::<StorageProtocol>)
+            .transpose()?
+            .unwrap_or_default();
+        if protocol != StorageProtocol::Sftp {
+            return Ok(None);
+        }
+        
+        let sftp = config.hetzner.sftp.as_ref().ok_or_else(|| skylock_core::SkylockError::Config(
+            "hetzner.protocol = \"sftp\" needs a [hetzner.sftp] section with the private key".to_string()
+        ))?;
+        // The endpoint may be a WebDAV URL; SFTP only needs its host
+        let endpoint = &config.hetzner.endpoint;
+        let host = endpoint.split_once("://").map_or(endpoint.as_str(), |(_, rest)| rest);
+        let host = host.split(['/', ':']).next().unwrap_or(host);
+        
+        SftpBackend::new(SecureSftpConfig {
+            host: host.to_string(),
+            port: sftp.port,
+            username: config.hetzner.username.clone(),
+            private_key_path: sftp.private_key.clone(),
+            key_passphrase: sftp.key_passphrase.clone(),
+            known_hosts_path: Some(sftp.known_hosts.clone()
+                .unwrap_or_else(|| config.data_dir.join("known_hosts"))),
+            metadata_key: None,
+        }).map(Some)
     }
     
-    /// Internal backup creation with full/incremental support
-    async fn create_backup_internal(&self, paths: &[PathBuf], incremental: bool) -> Result<BackupManifest> {
-        let backup_id = Utc::now().format("%Y%m%d_%H%M%S").to_string();
-        let index_dir = self.config.data_dir.join("indexes");
-        tokio::fs::create_dir_all(&index_dir).await?;
-        let tracker = ChangeTracker::new(index_dir);
+    /// Unlock the keyfile in the data directory with the configured encryption key
+    ///
+    /// Without a keyfile, backups use the password-derived key directly. A
+    /// keyfile that cannot be unlocked is an error, not a reason to fall back
+    /// to the password-derived key: backups would silently switch keys.
+    fn open_keyfile(config: &Config) -> Result<Option<Arc<KeyRotationManager>>> {
+        let path = KeyRotationManager::keyfile_path(&config.data_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
         
-        // Determine base backup for incremental mode
-        let base_backup_id = if incremental {
-            if tracker.has_latest_index().await {
-                // Load latest index to get backup_id
-                let latest_index = tracker.load_latest_index().await?;
-                Some(latest_index.tracked_dirs.first()
-                    .and_then(|p| p.file_name())
-                    .and_then(|n| n.to_str())
-                    .map(|s| s.to_string())
-                )
-            } else {
-                println!("⚠️  No previous backup found - creating full backup instead");
-                None
+        let manager = KeyRotationManager::open_keyfile(path.clone(), &config.hetzner.encryption_key)
+            .map_err(|e| match e {
+                SkylockError::WrongKey(reason) => SkylockError::WrongKey(format!("{} ({})", reason, path.display())),
+                e => SkylockError::Encryption(format!("Failed to open keyfile {}: {}", path.display(), e)),
+            })?;
+        Ok(Some(Arc::new(manager)))
+    }
+    
+    /// Manifest signing key in the data directory, and the signature policy
+    /// checking against `backup.manifest_public_key`, the public key beside
+    /// the signing key, or the signing key's own public half
+    ///
+    /// A configured public key that is missing or unreadable is an error:
+    /// falling back to the local key would check against the wrong one.
+    fn open_manifest_keys(config: &Config) -> Result<(Option<Arc<SecureSigningKey>>, Arc<SignaturePolicy>)> {
+        let key_path = manifest_signing::signing_key_path(&config.data_dir);
+        let signing_key = if key_path.exists() {
+            match SecureSigningKey::from_pem_file(&key_path) {
+                Ok(key) => Some(Arc::new(key)),
+                Err(e) => {
+                    tracing::warn!("Failed to load manifest signing key {}: {}", key_path.display(), e);
+                    None
+                }
             }
         } else {
             None
         };
         
-        // Check for existing resume state
-        let mut resume_state = if ResumeState::exists(&backup_id).await {
-            let state = ResumeState::load(&backup_id).await?;
-            println!("🔄 Resuming interrupted backup: {}", backup_id);
-            println!("   ⏱️  Started: {}", state.started_at.format("%Y-%m-%d %H:%M:%S UTC"));
-            println!("   ✅ Already uploaded: {}/{} files ({:.1}%)", 
-                state.uploaded_count(), 
-                state.total_files,
-                state.progress_percent()
-            );
-            println!();
-            Some(state)
-        } else {
-            if incremental && base_backup_id.is_some() {
-                println!("🔄 Starting incremental backup: {}", backup_id);
-            } else {
-                println!("🚀 Starting full backup: {}", backup_id);
+        let default_public_path = manifest_signing::public_key_path(&config.data_dir);
+        let public_key = if let Some(public_path) = &config.backup.manifest_public_key {
+            let key = PublicSignatureKey::from_pem_file(public_path).map_err(|e| SkylockError::Encryption(format!(
+                "Failed to load manifest public key {} (backup.manifest_public_key): {}",
+                public_path.display(), e
+            )))?;
+            Some(key)
+        } else if default_public_path.exists() {
+            match PublicSignatureKey::from_pem_file(&default_public_path) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    tracing::warn!("Failed to load manifest public key {}: {}", default_public_path.display(), e);
+                    None
+                }
             }
-            None
+        } else {
+            signing_key.as_ref().map(|key| key.public_signature_key())
         };
         
-        println!("   📁 Using {}-thread parallel uploads", self.max_parallel);
-        println!("   🔐 AES-256-GCM encryption enabled");
-        println!("   🗜️  Smart compression (files >10MB)");
-        if let Some(ref base_id) = base_backup_id {
-            println!("   🔗 Base backup: backup_{}", base_id.as_ref().unwrap_or(&"unknown".to_string()));
+        let policy = SignaturePolicy::new(public_key, config.backup.require_signed_manifests);
+        Ok((signing_key, Arc::new(policy)))
+    }
+    
+    /// Encryption for new backups: the keyfile's active key with the
+    /// configured algorithm, or the password-derived key without a keyfile
+    fn backup_encryption(
+        password_encryption: &Arc<EncryptionManager>,
+        key_chain: Option<&KeyRotationManager>,
+    ) -> Result<Arc<EncryptionManager>> {
+        match key_chain {
+            Some(chain) => {
+                let active = chain.active_encryption_manager().map_err(|e| SkylockError::Encryption(format!(
+                    "Keyfile's active key is unavailable: {}", e
+                )))?;
+                Ok(Arc::new(active.with_algorithm(password_encryption.algorithm())))
+            }
+            None => Ok(password_encryption.clone()),
         }
-        println!();
-        
-        // Collect all files to backup
-        let mut all_files = Vec::new();
-        let mut total_size = 0u64;
-        let mut skipped_count = 0;
-        
-        for path in paths {
-            println!("📂 Scanning: {}", path.display());
-            let mut files = self.collect_files(path)?;
-            let path_size: u64 = files.iter().map(|(_, size)| size).sum();
-            println!("   Found {} files ({:.2} MB)", files.len(), path_size as f64 / 1024.0 / 1024.0);
-            
-            // Filter for incremental backups
+    }
+    
+    /// Encryption for reading a backup written with the given key version
+    /// and algorithm
+    fn encryption_for(&self, key_version: Option<u64>, algorithm: AeadAlgorithm) -> Result<EncryptionManager> {
+        let encryption = match key_version {
+            Some(version) => self.key_chain.as_ref()
+                .ok_or_else(|| SkylockError::Encryption(format!(
+                    "Backup was encrypted with keyfile key version {} but no keyfile is available",
+                    version
+                )))?
+                .encryption_manager(version)?,
+            None => self.password_encryption.for_algorithm(algorithm),
+        };
+        Ok(encryption.for_algorithm(algorithm))
+    }
+    
+    /// Enable or disable extended attribute preservation
+    pub fn with_xattrs(mut self, enabled: bool) -> Self {
+        if enabled && !xattrs::is_supported() {
+            tracing::warn!("Extended attributes are not supported on this platform, ignoring --xattrs");
+        }
+        self.preserve_xattrs = enabled;
+        self
+    }
+    
+    /// Transfer up to `concurrency` files or ranges at once, overriding the
+    /// configured and adaptive parallelism (clamped to 1..=[`parallelism::MAX_CONCURRENCY`])
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        let concurrency = concurrency.clamp(1, parallelism::MAX_CONCURRENCY);
+        self.max_parallel = concurrency;
+        if self.parallelism_controller.is_some() {
+            self.parallelism_controller = Some(Arc::new(ParallelismController::with_config(
+                ParallelismConfig::fixed(concurrency),
+            )));
+        }
+        if let Some(multipart) = self.multipart_download.as_mut() {
+            multipart.parts = concurrency;
+        }
+        self
+    }
+    
+    /// Configure parallel range downloads for large restores
+    /// (`None` always downloads in a single stream)
+    pub fn with_multipart_download(mut self, config: Option<MultipartDownloadConfig>) -> Self {
+        self.multipart_download = config;
+        self
+    }
+    
+    /// Read back every uploaded file and compare its hash with what was sent,
+    /// re-uploading up to `retries` times on a mismatch (`None` disables)
+    pub fn with_verify_on_upload(mut self, retries: Option<u32>) -> Self {
+        self.verify_on_upload = retries;
+        self
+    }
+    
+    /// Retry failed transfers as `policy` says, instead of `backup.retry`
+    pub fn with_retry_policy(mut self, policy: skylock_core::RetryPolicy) -> Self {
+        self.retrier = Arc::new(Retrier::new(policy));
+        self
+    }
+    
+    /// Tag new backups with a storage tier
+    ///
+    /// The WebDAV storage box has no storage classes, so the tier is recorded
+    /// in the manifest for retention and restore; backends with cold storage
+    /// receive it as an upload hint.
+    pub fn with_storage_tier(mut self, tier: StorageTier) -> Self {
+        self.storage_tier = tier;
+        self
+    }
+    
+    /// Label new backups with tags and a note, stored in their manifests
+    pub fn with_tags(mut self, tags: Vec<String>, note: Option<String>) -> Self {
+        self.tags = tags;
+        self.note = note;
+        self
+    }
+    
+    /// Compress every file of this run with a fixed algorithm and/or level
+    /// instead of choosing per file
+    pub fn with_compression_override(mut self, compression: CompressionOverride) -> Self {
+        self.compression_override = compression;
+        self
+    }
+    
+    /// Keep decrypted blocks in `cache` so that previewing or restoring the
+    /// same files again skips downloading and decrypting them
+    pub fn with_block_cache(mut self, cache: Arc<BlockCache>) -> Self {
+        self.block_cache = Some(cache);
+        self
+    }
+    
+    pub fn block_cache(&self) -> Option<&Arc<BlockCache>> {
+        self.block_cache.as_ref()
+    }
+    
+    /// Restore files below remapped paths, e.g. `/home/alice` as `/home/bob`
+    pub fn with_path_map(mut self, path_map: PathMap) -> Self {
+        self.path_map = path_map;
+        self
+    }
+    
+    /// Overwrite or skip files already at the restore target instead of
+    /// refusing to restore
+    pub fn with_restore_policy(mut self, policy: RestorePolicy) -> Self {
+        self.restore_policy = policy;
+        self
+    }
+    
+    /// Check that restore targets have room before writing (on by default)
+    pub fn with_space_check(mut self, enabled: bool) -> Self {
+        self.space_probe = enabled.then(|| self.space_probe.take().unwrap_or_else(restore_space::default_probe));
+        self
+    }
+    
+    /// Read free space for the restore preflight from `probe`
+    pub fn with_space_probe(mut self, probe: SpaceProbe) -> Self {
+        self.space_probe = Some(probe);
+        self
+    }
+    
+    /// Stop the backup once `shutdown` is cancelled
+    ///
+    /// Files already uploading are finished and recorded in the resume state,
+    /// no new file is started, and the backup fails with the resume point
+    /// saved so the next run picks up where this one stopped.
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+    
+    /// Count uploaded and skipped bytes in `metrics`, e.g. to share the
+    /// counters between backups
+    pub fn with_upload_metrics(mut self, metrics: Arc<ThroughputMetrics>) -> Self {
+        self.upload_metrics = metrics;
+        self
+    }
+    
+    /// Read source files with `reader` instead of from disk
+    /// 
+    /// Streamed files (see [`Self::with_stream_threshold`]) are still
+    /// opened from disk.
+    pub fn with_source_reader(mut self, reader: SourceReader) -> Self {
+        self.source_reader = reader;
+        self
+    }
+    
+    /// Stream files of at least `threshold` bytes, overriding
+    /// `backup.stream_threshold` (None = read every file into memory)
+    pub fn with_stream_threshold(mut self, threshold: Option<u64>) -> Self {
+        self.stream_threshold = threshold;
+        self
+    }
+    
+    /// Limit files to `max` bytes, overriding `backup.max_file_size` (None =
+    /// no limit); larger files are streamed, or skipped and recorded in the
+    /// manifest when `skip` is set
+    pub fn with_max_file_size(mut self, max: Option<u64>, skip: bool) -> Self {
+        self.max_file_size = max;
+        self.skip_oversized_files = skip;
+        self
+    }
+    
+    /// Encrypt streamed files in segments of `framing`, overriding
+    /// `backup.aead_frame_size`
+    pub fn with_stream_framing(mut self, framing: SegmentFraming) -> Self {
+        self.stream_framing = framing;
+        self
+    }
+    
+    /// Hash file and chunk content of new backups with `algorithm`,
+    /// overriding `backup.hash_algorithm`
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self.parallel_hasher = Arc::new(ParallelHasher::with_config(
+            ParallelHashConfig::default().with_algorithm(algorithm),
+        ));
+        self
+    }
+    
+    /// Hash algorithm from `backup.hash_algorithm`, or SHA-256 when it is
+    /// unset or invalid (which `skylock check-config` reports)
+    fn configured_hash_algorithm(config: &Config) -> HashAlgorithm {
+        match config.backup.hash_algorithm.as_deref().map(str::parse) {
+            Some(Ok(algorithm)) => algorithm,
+            Some(Err(e)) => {
+                tracing::warn!("Ignoring backup.hash_algorithm: {}", e);
+                HashAlgorithm::default()
+            }
+            None => HashAlgorithm::default(),
+        }
+    }
+    
+    /// Framing of streamed files from `backup.aead_frame_size`, or the
+    /// default when it is invalid (which `Config::validate` reports)
+    fn configured_stream_framing(config: &Config) -> SegmentFraming {
+        match config.backup.aead_frame_size_bytes().map(SegmentFraming::new) {
+            Ok(Ok(framing)) => framing,
+            _ => {
+                tracing::warn!("Ignoring invalid backup.aead_frame_size, using the default");
+                SegmentFraming::default()
+            }
+        }
+    }
+    
+    /// Download up to `concurrency` manifests at once when listing backups,
+    /// overriding `backup.list_concurrency`
+    pub fn with_list_concurrency(mut self, concurrency: usize) -> Self {
+        self.list_concurrency = concurrency.max(1);
+        self
+    }
+    
+    /// Delete up to `concurrency` blobs at once when removing a backup,
+    /// overriding `backup.delete_concurrency`
+    pub fn with_delete_concurrency(mut self, concurrency: usize) -> Self {
+        self.delete_concurrency = concurrency.max(1);
+        self
+    }
+    
+    /// Reuse a listing of backup summaries for `ttl` (None = always list)
+    pub fn with_list_cache(mut self, ttl: Option<Duration>) -> Self {
+        self.listing_cache = ttl.map(|ttl| ListingCache::new(&self.config.data_dir, ttl));
+        self
+    }
+    
+    /// Compress files matching a profile as it pins, unless the run forces
+    /// one algorithm for every file (see [`Self::with_compression_override`])
+    pub fn with_compression_profiles(mut self, profiles: CompressionProfiles) -> Self {
+        self.compression_profiles = profiles;
+        self
+    }
+    
+    /// Back up only the files `filter` includes (`--include-only`/`--exclude`)
+    pub fn with_file_filter(mut self, filter: FileFilter) -> Self {
+        self.file_filter = filter;
+        self
+    }
+    
+    /// Drop the cached listing after adding or removing a backup
+    async fn invalidate_listing(&self) {
+        if let Some(cache) = &self.listing_cache {
+            cache.invalidate().await;
+        }
+    }
+    
+    /// Source file reader for one backup of `paths`, falling back to shadow
+    /// copies when `backup.vss_enabled` is set
+    fn source_files(&self, paths: &[PathBuf]) -> Result<SourceFiles> {
+        let source = SourceFiles::new(self.source_reader.clone());
+        if !self.config.backup.vss_enabled {
+            return Ok(source);
+        }
+        let snapshots = paths.iter()
+            .map(|path| {
+                let snapshot = VssSnapshot::new(path)?;
+                snapshot.create()?;
+                Ok(snapshot)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(source.with_shadow_copies(snapshots))
+    }
+    
+    fn upload_settings(&self, path: &Path) -> UploadSettings {
+        let profile = self.compression_override.algorithm.is_none()
+            .then(|| self.compression_profiles.select(path))
+            .flatten();
+        UploadSettings {
+            preserve_xattrs: self.preserve_xattrs,
+            verify_on_upload: self.verify_on_upload,
+            encrypt_names: self.config.backup.encrypt_file_names,
+            compression: profile.map_or(self.compression_override, |(_, compression)| compression),
+            compression_profile: profile.map(|(key, _)| key.to_string()),
+            blob_naming: BlobNaming::with_depth(self.config.backup.blob_shard_depth),
+            stream_threshold: self.stream_threshold,
+            max_file_size: self.max_file_size,
+            skip_oversized_files: self.skip_oversized_files,
+            stream_framing: self.stream_framing,
+            hash_algorithm: self.hash_algorithm,
+            retrier: self.retrier.clone(),
+        }
+    }
+    
+    /// Upload counters across all backups made with this instance
+    pub fn upload_metrics(&self) -> Arc<ThroughputMetrics> {
+        self.upload_metrics.clone()
+    }
+    
+    /// Get the current parallelism level
+    pub fn current_parallelism(&self) -> usize {
+        if let Some(ref controller) = self.parallelism_controller {
+            controller.current_parallelism()
+        } else {
+            self.max_parallel
+        }
+    }
+    
+    /// Get optimal chunk size for a file
+    pub fn chunk_size_for_file(&self, file_size: u64, path: Option<&Path>) -> usize {
+        self.chunking_controller.chunk_size_for_file(file_size, path)
+    }
+
+    /// Create full backup using direct upload strategy
+    pub async fn create_backup(&self, paths: &[PathBuf]) -> Result<BackupManifest> {
+        self.create_backup_internal(paths, false).await
+    }
+    
+    /// Create incremental backup using direct upload strategy
+    pub async fn create_incremental_backup(&self, paths: &[PathBuf]) -> Result<BackupManifest> {
+        self.create_backup_internal(paths, true).await
+    }
+    
+    /// Storage destination that the backup lock covers
+    fn lock_destination(&self) -> String {
+        format!("{}@{}", self.config.hetzner.username, self.config.hetzner.endpoint)
+    }
+    
+    /// Directory holding resume state for interrupted backups
+    fn resume_dir(&self) -> PathBuf {
+        ResumeState::state_dir(&self.config.data_dir)
+    }
+    
+    /// Find an interrupted backup of the same source set to resume
+    ///
+    /// A state recorded for different sources is discarded with a warning and
+    /// the backup starts over; its uploaded files are left for garbage collection.
+    async fn find_resumable_backup(&self, paths: &[PathBuf]) -> Result<Option<ResumeState>> {
+        let state_dir = self.resume_dir();
+        let Some(mut state) = ResumeState::find_latest(&state_dir).await? else {
+            return Ok(None);
+        };
+        
+        if !state.matches_sources(paths) {
+            println!("⚠️  Interrupted backup {} covered different source paths - starting over", state.backup_id);
+            tracing::warn!(
+                "Discarding resume state for {}: sources changed from {:?} to {:?}",
+                state.backup_id, state.source_paths, paths
+            );
+            ResumeState::delete(&state_dir, &state.backup_id).await?;
+            return Ok(None);
+        }
+        
+        let discarded = state.discard_untracked();
+        if discarded > 0 {
+            tracing::warn!("{} uploads in resume state {} have no manifest entry and will be redone", discarded, state.backup_id);
+        }
+        
+        Ok(Some(state))
+    }
+    
+    /// Internal backup creation with full/incremental support
+    #[tracing::instrument(name = "backup", skip_all, fields(backup_id = Empty, incremental = incremental, files = Empty, bytes = Empty))]
+    async fn create_backup_internal(&self, paths: &[PathBuf], incremental: bool) -> Result<BackupManifest> {
+        // Held until this returns (or unwinds), so overlapping runs can't
+        // interleave uploads and manifest writes on the same storage box
+        let _lock = BackupLock::acquire(&self.config.data_dir, &self.lock_destination(), STALE_LOCK_AFTER)?;
+        let _retry_budget = self.retrier.begin_operation(BACKUP_OPERATION);
+        let started = Instant::now();
+        let started_at = Utc::now();
+        
+        let state_dir = self.resume_dir();
+        let resumable = self.find_resumable_backup(paths).await?;
+        let backup_id = match resumable {
+            Some(ref state) => state.backup_id.clone(),
+            None => self.new_backup_id().await?,
+        };
+        tracing::Span::current().record("backup_id", backup_id.as_str());
+        let index_dir = self.config.data_dir.join("indexes");
+        tokio::fs::create_dir_all(&index_dir).await?;
+        let tracker = ChangeTracker::new(index_dir);
+        
+        // Determine base backup for incremental mode
+        let latest_index = if incremental {
+            if !tracker.has_latest_index().await {
+                println!("⚠️  No previous backup found - creating full backup instead");
+                None
+            } else {
+                // A chain keeps one hash algorithm, so switching starts a new one
+                let index = tracker.load_latest_index().await?;
+                let delete_state = DeleteCheckpoint::state_dir(&self.config.data_dir);
+                let partly_deleted = match &index.backup_id {
+                    Some(id) => DeleteCheckpoint::exists(&delete_state, id).await,
+                    None => false,
+                };
+                if partly_deleted {
+                    // Some of its blobs may already be gone
+                    println!("⚠️  Previous backup is partly deleted - creating full backup instead");
+                    None
+                } else if index.hash_algorithm == self.hash_algorithm {
+                    Some(index)
+                } else {
+                    println!("⚠️  Previous backup hashed with {} - creating full {} backup instead",
+                        index.hash_algorithm, self.hash_algorithm);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let base_backup_id = latest_index.as_ref().map(|index| index.backup_id.clone());
+        
+        // Resume an interrupted backup of the same sources
+        let resume_state = if let Some(state) = resumable {
+            println!("🔄 Resuming interrupted backup: {}", backup_id);
+            println!("   ⏱️  Started: {}", state.started_at.format("%Y-%m-%d %H:%M:%S UTC"));
+            println!("   ✅ Already uploaded: {}/{} files ({:.1}%)", 
+                state.uploaded_count(), 
+                state.total_files,
+                state.progress_percent()
+            );
+            println!();
+            Some(state)
+        } else {
             if incremental && base_backup_id.is_some() {
-                // Get list of changed files from tracker
-                let changed_files = tracker.get_changed_files(paths).await?;
-                let changed_paths: std::collections::HashSet<_> = changed_files.into_iter().collect();
-                
+                println!("🔄 Starting incremental backup: {}", backup_id);
+            } else {
+                println!("🚀 Starting full backup: {}", backup_id);
+            }
+            None
+        };
+        
+        println!("   📁 Using {}-thread parallel uploads", self.max_parallel);
+        println!("   🔐 {} encryption enabled", self.encryption.algorithm());
+        match (self.compression_override.algorithm, self.compression_override.level) {
+            (Some(algorithm), Some(level)) => println!("   🗜️  Compression: {} ({:?})", algorithm, level),
+            (Some(algorithm), None) => println!("   🗜️  Compression: {}", algorithm),
+            (None, Some(level)) => println!("   🗜️  Adaptive compression at level {:?}", level),
+            (None, None) => println!("   🗜️  Adaptive compression (skips already-compressed files)"),
+        }
+        if self.config.backup.encrypt_file_names {
+            println!("   🔏 File names encrypted");
+        }
+        if let Some(ref base_id) = base_backup_id {
+            println!("   🔗 Base backup: backup_{}", base_id.as_ref().unwrap_or(&"unknown".to_string()));
+        }
+        println!();
+        
+        // Changes since the base backup, detected once for all paths
+        let scan = tracing::info_span!("scan", paths = paths.len(), files = Empty, bytes = Empty);
+        let changes = match latest_index {
+            Some(ref index) => index.detect_changes_filtered(paths, &self.file_filter)
+                .instrument(scan.clone())
+                .await?,
+            None => Vec::new(),
+        };
+        let changed_paths: std::collections::HashSet<_> = changes.iter()
+            .filter(|c| matches!(c.change_type, ChangeType::Added | ChangeType::Modified))
+            .map(|c| c.path.clone())
+            .collect();
+        
+        // Collect all files to backup
+        let mut all_files = Vec::new();
+        let mut directories = Vec::new();
+        let mut total_size = 0u64;
+        let mut skipped_count = 0usize;
+        
+        for path in paths {
+            let _scan = scan.enter();
+            println!("📂 Scanning: {}", path.display());
+            directories.extend(self.collect_directories(path)?);
+            let mut files = self.collect_files(path)?;
+            let path_size: u64 = files.iter().map(|(_, size)| size).sum();
+            println!("   Found {} files ({})", files.len(), ByteSize(path_size));
+            
+            // Filter for incremental backups
+            if latest_index.is_some() {
                 let original_count = files.len();
                 files.retain(|(path, _)| changed_paths.contains(path));
                 skipped_count += original_count - files.len();
@@ -316,60 +1646,142 @@ impl DirectUploadBackup {
             all_files.extend(files);
         }
         
+        // Moved files point at the blob already uploaded for their old path;
+        // the ones whose blob can't be found are uploaded again
+        let moves: Vec<&FileChange> = changes.iter()
+            .filter(|c| c.change_type == ChangeType::Moved)
+            .collect();
+        let base_id = base_backup_id.clone().flatten();
+        let (moved_entries, unresolved) = self.reuse_moved_blobs(base_id.as_deref(), &moves).await;
+        // A moved file is only counted as skipped if its new path was scanned
+        skipped_count = skipped_count.saturating_sub(unresolved.len());
+        total_size += unresolved.iter().map(|(_, size)| size).sum::<u64>();
+        all_files.extend(unresolved);
+        total_size += moved_entries.iter().map(|entry| entry.size).sum::<u64>();
+        
+        // Paths gone since the base backup, so restoring the chain doesn't
+        // bring them back; the old path of a moved file counts as deleted
+        let mut deleted_paths: Vec<PathBuf> = changes.iter()
+            .filter_map(|c| match c.change_type {
+                ChangeType::Removed => Some(c.path.clone()),
+                ChangeType::Moved => c.old_info.as_ref().map(|info| info.path.clone()),
+                _ => None,
+            })
+            .collect();
+        deleted_paths.sort();
+        
         let file_count = all_files.len();
+        scan.record("files", file_count).record("bytes", total_size);
         
         if incremental && skipped_count > 0 {
             println!("➡️  Incremental: Backing up {} changed files, skipping {} unchanged", file_count, skipped_count);
         }
+        if !moved_entries.is_empty() {
+            println!("↪️  {} moved files reuse blobs from earlier backups", moved_entries.len());
+        }
         
         println!();
-        println!("📊 Total: {} files, {:.2} GB", file_count, total_size as f64 / 1024.0 / 1024.0 / 1024.0);
+        println!("📊 Total: {} files, {}", file_count, ByteSize(total_size));
         println!();
         
         // Initialize resume state if not already loaded
-        if resume_state.is_none() {
-            resume_state = Some(ResumeState::new(
-                backup_id.clone(),
-                paths.to_vec(),
-                file_count
-            ));
-            // Save initial state
-            if let Some(ref state) = resume_state {
-                state.save().await?;
+        let resume_state = match resume_state {
+            Some(mut state) => {
+                state.total_files = file_count;
+                state
             }
-        }
+            None => ResumeState::new(backup_id.clone(), paths.to_vec(), file_count),
+        };
         
         // Upload files with parallelism control and resume support
-        let uploaded_files = self.upload_files_parallel_with_resume(
+        let source = self.source_files(paths)?;
+        let uploaded_before = self.upload_metrics.bytes_uploaded();
+        let skipped_before = self.upload_metrics.bytes_skipped();
+        let uploaded = self.upload_files_parallel_with_resume(
             &backup_id, 
             all_files,
-            resume_state.as_mut().unwrap()
-        ).await?;
+            resume_state,
+            &source,
+        ).instrument(tracing::info_span!("upload_files", files = file_count, bytes = total_size)).await;
+        if let Err(e) = source.cleanup() {
+            tracing::warn!("Failed to release shadow copies: {}", e);
+        }
+        let (mut uploaded_files, skipped_files) = uploaded?;
+        let bytes_skipped = self.upload_metrics.bytes_skipped() - skipped_before;
+        if bytes_skipped > 0 {
+            println!("⏭️  {} already stored by earlier backups, not uploaded again", HumanBytes(bytes_skipped));
+        }
+        uploaded_files.extend(moved_entries);
         
         // Create manifest
-        let manifest = BackupManifest {
+        let mut manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
             backup_id: backup_id.clone(),
             timestamp: Utc::now(),
+            total_size: uploaded_files.iter().map(|entry| entry.size).sum(),
+            file_count: uploaded_files.len(),
             files: uploaded_files,
-            total_size,
-            file_count,
             source_paths: paths.to_vec(),
             base_backup_id: base_backup_id.flatten(),
             encryption_version: Self::default_encryption_version(),
             kdf_params: Some(self.encryption.kdf_params().clone()),
+            aead_algorithm: self.encryption.algorithm(),
+            hash_algorithm: self.hash_algorithm,
+            key_version: self.encryption.key_version(),
             signature: None,  // Signature will be added later if enabled
             backup_chain_version: 0,  // Will be set during signing
             encrypted_path_map: None,  // Will be populated if metadata encryption enabled
+            storage_tier: self.storage_tier,
+            deleted_paths,
+            consolidated_from: Vec::new(),
+            directories,
+            tags: self.tags.clone(),
+            note: self.note.clone(),
+            skipped_files,
         };
+        if self.config.backup.canonical_manifests {
+            manifest.canonicalize();
+        }
         
         // Upload manifest
-        self.upload_manifest(&manifest).await?;
+        self.upload_manifest(&manifest)
+            .instrument(tracing::info_span!("upload_manifest", files = manifest.file_count))
+            .await?;
+        tracing::Span::current()
+            .record("files", manifest.file_count)
+            .record("bytes", manifest.total_size);
+        
+        // The backup is complete without its summary, so a failed upload
+        // only costs monitoring its figures
+        let summary = BackupRunSummary::new(
+            &manifest,
+            started_at,
+            started.elapsed(),
+            &self.upload_metrics,
+            (uploaded_before, skipped_before),
+        );
+        if let Err(e) = self.upload_run_summary(&summary).await {
+            tracing::warn!("Failed to upload summary of backup {}: {}", backup_id, e);
+        }
         
         // Clean up resume state file after successful completion
-        ResumeState::delete(&backup_id).await?;
-        
-        // Build and save index of backed up files for change tracking
-        let file_index = FileIndex::build(paths)?;
+        ResumeState::delete(&state_dir, &backup_id).await?;
+        
+        // Build and save index of backed up files for change tracking, with
+        // content hashes so the next backup can recognise moved files
+        let mut file_index = FileIndex::build_filtered(paths, &self.file_filter)?;
+        file_index.backup_id = Some(backup_id.clone());
+        file_index.hash_algorithm = self.hash_algorithm;
+        if let Some(ref previous) = latest_index {
+            file_index.inherit_hashes(previous);
+        }
+        for entry in &manifest.files {
+            file_index.set_hash(&entry.local_path, entry.hash.clone());
+        }
+        // Skipped files count as new next time, so they are tried again
+        for skipped in &manifest.skipped_files {
+            file_index.remove(&skipped.path);
+        }
         if let Err(e) = tracker.save_index(&backup_id, &file_index).await {
             eprintln!("⚠️  Warning: Failed to save file index: {}", e);
             eprintln!("   Change tracking may not work correctly.");
@@ -384,42 +1796,341 @@ impl DirectUploadBackup {
             println!("✅ Full backup complete: {}", backup_id);
             println!("   📦 {} files uploaded", manifest.file_count);
         }
-        println!("   💾 {:.2} GB total", manifest.total_size as f64 / 1024.0 / 1024.0 / 1024.0);
+        println!("   💾 {} total", ByteSize(manifest.total_size));
+        if !manifest.skipped_files.is_empty() {
+            println!("   ⚠️  {} files skipped:", manifest.skipped_files.len());
+            for skipped in &manifest.skipped_files {
+                println!("      {}: {}", skipped.path.display(), skipped.reason);
+            }
+        }
+        
+        // Incremental-forever: fold a chain that grew too long into a
+        // synthetic full backup. The incremental is already complete, so a
+        // failure here only leaves the chain as it was
+        if let (Some(_), Some(max_chain_length)) = (&manifest.base_backup_id, self.config.backup.max_chain_length) {
+            match self.consolidate_if_due(&backup_id, max_chain_length).await {
+                Ok(Some(consolidated)) => return Ok(consolidated),
+                Ok(None) => {}
+                Err(e) => eprintln!("⚠️  Warning: Failed to consolidate backup chain: {}", e),
+            }
+        }
+        
+        Ok(manifest)
+    }
+    
+    /// Consolidate the chain ending at `backup_id` once it has at least
+    /// `max_chain_length` incremental backups
+    async fn consolidate_if_due(&self, backup_id: &str, max_chain_length: usize) -> Result<Option<BackupManifest>> {
+        let chain = self.load_chain(backup_id).await?;
+        if chain.len() - 1 < max_chain_length {
+            return Ok(None);
+        }
+        
+        let consolidated = self.consolidate(&chain).await?;
+        println!("🧩 Consolidated {} backups into synthetic full backup {}", chain.len(), backup_id);
+        Ok(Some(consolidated))
+    }
+    
+    /// Load the manifests a backup depends on, from its full (or synthetic
+    /// full) backup up to `backup_id` itself
+    pub async fn load_chain(&self, backup_id: &str) -> Result<Vec<BackupManifest>> {
+        let mut chain: Vec<BackupManifest> = Vec::new();
+        let mut visited = std::collections::HashSet::new();
+        let mut next_id = Some(backup_id.to_string());
+        
+        while let Some(id) = next_id.take() {
+            if !visited.insert(id.clone()) {
+                return Err(SkylockError::Backup(format!(
+                    "Backup chain of {} loops back to {}", backup_id, id
+                )));
+            }
+            let manifest = match self.download_manifest(&id).await {
+                Ok(manifest) => manifest,
+                Err(e) if !chain.is_empty() => {
+                    return Err(SkylockError::Backup(format!(
+                        "Base backup {} of {} cannot be loaded: {}", id, backup_id, e
+                    )));
+                }
+                Err(e) => return Err(e),
+            };
+            next_id = manifest.base_backup_id.clone();
+            chain.push(manifest);
+        }
+        
+        chain.reverse();
+        Ok(chain)
+    }
+    
+    /// Files of the newest backup in `chain`, each with the manifest that
+    /// uploaded it, sorted by path
+    /// 
+    /// Applies every backup's changes and deletions oldest first. Incremental
+    /// backups made before deletions were recorded bring deleted files back.
+    fn merge_chain(chain: &[BackupManifest]) -> Vec<(&FileEntry, &BackupManifest)> {
+        let mut files = std::collections::BTreeMap::new();
+        for manifest in chain {
+            for path in &manifest.deleted_paths {
+                files.remove(path.as_path());
+            }
+            for entry in &manifest.files {
+                files.insert(entry.local_path.as_path(), (entry, manifest));
+            }
+        }
+        files.into_values().collect()
+    }
+    
+    /// Rewrite the newest backup of `chain` as a synthetic full backup
+    /// 
+    /// The merged manifest references the blobs already uploaded by the
+    /// older backups, so nothing is downloaded or uploaded again. The older
+    /// backups stay until retention removes them; their blobs are protected
+    /// because the new manifest reuses them.
+    pub async fn consolidate(&self, chain: &[BackupManifest]) -> Result<BackupManifest> {
+        let (Some(root), Some(tip)) = (chain.first(), chain.last()) else {
+            return Err(SkylockError::Backup("Cannot consolidate an empty backup chain".to_string()));
+        };
+        if root.base_backup_id.is_some() {
+            return Err(SkylockError::Backup(format!(
+                "Backup chain of {} does not start at a full backup", tip.backup_id
+            )));
+        }
+        for pair in chain.windows(2) {
+            if pair[1].base_backup_id.as_deref() != Some(pair[0].backup_id.as_str()) {
+                return Err(SkylockError::Backup(format!(
+                    "Backup {} is not based on {}", pair[1].backup_id, pair[0].backup_id
+                )));
+            }
+        }
+        // Entries are decrypted with the settings of the manifest holding them
+        if let Some(other) = chain.iter().find(|m| {
+            m.encryption_version != tip.encryption_version
+                || m.kdf_params.is_some() != tip.kdf_params.is_some()
+                || m.aead_algorithm != tip.aead_algorithm
+                || m.key_version != tip.key_version
+                || m.hash_algorithm != tip.hash_algorithm
+        }) {
+            return Err(SkylockError::Backup(format!(
+                "Cannot consolidate {}: backup {} uses different encryption settings; create a full backup instead",
+                tip.backup_id, other.backup_id
+            )));
+        }
+        
+        let files: Vec<FileEntry> = Self::merge_chain(chain).into_iter()
+            .map(|(entry, source)| {
+                let mut entry = entry.clone();
+                if source.backup_id != tip.backup_id && entry.blob_origin.is_none() {
+                    entry.blob_origin = Some(BlobOrigin {
+                        backup_id: source.backup_id.clone(),
+                        local_path: entry.local_path.clone(),
+                    });
+                }
+                entry
+            })
+            .collect();
+        
+        let mut consolidated_from = root.consolidated_from.clone();
+        consolidated_from.extend(chain[..chain.len() - 1].iter().map(|m| m.backup_id.clone()));
+        
+        let mut manifest = BackupManifest {
+            total_size: files.iter().map(|entry| entry.size).sum(),
+            file_count: files.len(),
+            files,
+            base_backup_id: None,
+            signature: None,
+            encrypted_path_map: None,
+            deleted_paths: Vec::new(),
+            consolidated_from,
+            ..tip.clone()
+        };
+        if self.config.backup.canonical_manifests {
+            manifest.canonicalize();
+        }
+        
+        let encryption = self.encryption_for(tip.key_version, tip.aead_algorithm)?;
+        self.upload_manifest_with(&manifest, &encryption).await?;
         
         Ok(manifest)
     }
+    
+    /// Consolidate the chain ending at `backup_id` into a synthetic full backup
+    pub async fn consolidate_backup(&self, backup_id: &str) -> Result<BackupManifest> {
+        let chain = self.load_chain(backup_id).await?;
+        self.consolidate(&chain).await
+    }
 
-    /// Collect all files in a directory recursively
+    /// Collect all files in a directory recursively that the file filter
+    /// includes
     fn collect_files(&self, path: &Path) -> Result<Vec<(PathBuf, u64)>> {
         let mut files = Vec::new();
+        for file in self.file_filter.files(path)? {
+            let size = file.metadata()
+                .map_err(|e| SkylockError::Backup(format!("Metadata error: {}", e)))?
+                .len();
+            files.push((file, size));
+        }
         
-        if path.is_file() {
-            let size = path.metadata()?.len();
-            files.push((path.to_path_buf(), size));
-            return Ok(files);
+        Ok(files)
+    }
+
+    /// Collect all directories below `path`, including `path` itself,
+    /// except excluded ones
+    fn collect_directories(&self, path: &Path) -> Result<Vec<DirectoryEntry>> {
+        if !path.is_dir() {
+            return Ok(Vec::new());
         }
         
-        for entry in WalkDir::new(path).follow_links(false) {
-            let entry = entry.map_err(|e| SkylockError::Backup(format!("Walk error: {}", e)))?;
-            
-            if entry.file_type().is_file() {
+        let mut directories = Vec::new();
+        for entry in self.file_filter.walk(path) {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
                 let metadata = entry.metadata()
                     .map_err(|e| SkylockError::Backup(format!("Metadata error: {}", e)))?;
-                files.push((entry.path().to_path_buf(), metadata.len()));
+                directories.push(DirectoryEntry::from_metadata(entry.path(), &metadata));
             }
         }
         
-        Ok(files)
+        Ok(directories)
     }
 
-    /// Upload files in parallel with semaphore control
-    async fn upload_files_parallel(
+    /// Build manifest entries for moved files that reuse the blob of their
+    /// old path
+    /// 
+    /// Walks the manifest chain from `base_backup_id` until every old path is
+    /// found. Returns the reused entries and the moved files whose blob could
+    /// not be found, which have to be uploaded.
+    async fn reuse_moved_blobs(
         &self,
-        backup_id: &str,
-        files: Vec<(PathBuf, u64)>,
-    ) -> Result<Vec<FileEntry>> {
-        let total_files = files.len() as u64;
-        let semaphore = Arc::new(Semaphore::new(self.max_parallel));
+        base_backup_id: Option<&str>,
+        moves: &[&FileChange],
+    ) -> (Vec<FileEntry>, Vec<(PathBuf, u64)>) {
+        let mut pending: Vec<&FileChange> = moves.to_vec();
+        let mut reused = Vec::new();
+        let mut visited = std::collections::HashSet::new();
+        let mut next_id = base_backup_id.map(str::to_string);
+        
+        while let Some(manifest_id) = next_id.take() {
+            if pending.is_empty() || !visited.insert(manifest_id.clone()) {
+                break;
+            }
+            let manifest = match self.load_manifest(&manifest_id).await {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    eprintln!("⚠️  Cannot load manifest {} to reuse moved files: {}", manifest_id, e);
+                    break;
+                }
+            };
+            
+            pending.retain(|change| {
+                let (Some(old_info), Some(new_info)) = (&change.old_info, &change.new_info) else {
+                    return true;
+                };
+                let Some(entry) = manifest.files.iter().find(|entry| {
+                    entry.local_path == old_info.path && Some(&entry.hash) == new_info.hash.as_ref()
+                }) else {
+                    return true;
+                };
+                
+                let blob_origin = entry.blob_origin.clone().unwrap_or_else(|| BlobOrigin {
+                    backup_id: manifest.backup_id.clone(),
+                    local_path: entry.local_path.clone(),
+                });
+                let (modified, changed) = std::fs::metadata(&change.path)
+                    .map(|metadata| FileEntry::source_times(&metadata))
+                    .unwrap_or((Some(new_info.modified), None));
+                reused.push(FileEntry {
+                    local_path: change.path.clone(),
+                    timestamp: Utc::now(),
+                    modified,
+                    changed,
+                    xattrs: if self.preserve_xattrs {
+                        xattrs::read_xattrs(&change.path)
+                    } else {
+                        Vec::new()
+                    },
+                    blob_origin: Some(blob_origin),
+                    ..entry.clone()
+                });
+                false
+            });
+            next_id = manifest.base_backup_id.clone();
+        }
+        
+        let unresolved = pending.iter()
+            .filter_map(|change| change.new_info.as_ref().map(|info| (change.path.clone(), info.size)))
+            .collect();
+        (reused, unresolved)
+    }
+    
+    /// Remote paths of blobs that entries in `manifests` reuse from other
+    /// backups, after a move or as unchanged chunks of a large file
+    fn reused_blob_paths<'a>(manifests: impl IntoIterator<Item = &'a BackupManifest>) -> std::collections::HashSet<String> {
+        let mut paths = std::collections::HashSet::new();
+        for manifest in manifests {
+            for entry in &manifest.files {
+                if entry.blob_origin.is_some() {
+                    paths.insert(entry.remote_path.clone());
+                }
+                paths.extend(entry.chunks.iter()
+                    .filter(|chunk| chunk.backup_id != manifest.backup_id)
+                    .map(|chunk| chunk.remote_path.clone()));
+            }
+        }
+        paths
+    }
+    
+    /// Blobs that earlier backups already stored, as entries pointing at
+    /// their origin
+    /// 
+    /// Built from the remote manifests rather than the local index, so it
+    /// survives losing the data directory. Only backups written with the
+    /// current key and algorithm qualify, since a restore decrypts reused
+    /// blobs with the restoring backup's key. Newer backups take precedence.
+    async fn remote_blob_index(&self) -> RemoteBlobs {
+        let manifests = match self.list_backups().await {
+            Ok(manifests) => manifests,
+            Err(e) => {
+                tracing::warn!("Cannot list earlier backups to skip stored blobs: {}", e);
+                return RemoteBlobs::default();
+            }
+        };
+        
+        let mut blobs = RemoteBlobs::default();
+        let compatible = manifests.iter().filter(|manifest| {
+            manifest.encryption_version == "v2"
+                && manifest.kdf_params.is_some()
+                && manifest.aead_algorithm == self.encryption.algorithm()
+                && manifest.key_version == self.encryption.key_version()
+                && manifest.hash_algorithm == self.hash_algorithm
+        });
+        for manifest in compatible {
+            for entry in manifest.files.iter().filter(|entry| entry.encrypted) {
+                if entry.is_chunked() {
+                    for chunk in &entry.chunks {
+                        blobs.chunks.entry(chunk.hash.clone()).or_insert_with(|| chunk.clone());
+                    }
+                    continue;
+                }
+                blobs.files.entry(entry.hash.clone()).or_insert_with(|| FileEntry {
+                    blob_origin: Some(entry.blob_origin.clone().unwrap_or_else(|| BlobOrigin {
+                        backup_id: manifest.backup_id.clone(),
+                        local_path: entry.local_path.clone(),
+                    })),
+                    ..entry.clone()
+                });
+            }
+        }
+        blobs
+    }
+
+    /// Upload files in parallel with semaphore control
+    async fn upload_files_parallel(
+        &self,
+        backup_id: &str,
+        files: Vec<(PathBuf, u64)>,
+        source: &SourceFiles,
+    ) -> Result<Vec<FileEntry>> {
+        let total_files = files.len() as u64;
+        let semaphore = Arc::new(Semaphore::new(self.max_parallel));
         let mut tasks = Vec::new();
         
         // Create progress bars (indicatif auto-detects TTY)
@@ -448,6 +2159,7 @@ impl DirectUploadBackup {
         
         let overall_pb_clone = overall_pb.clone();
         let file_pb_clone = file_pb.clone();
+        let known_blobs = Arc::new(RemoteBlobs::default());
         
         for (local_path, size) in files {
             let sem = semaphore.clone();
@@ -455,12 +2167,18 @@ impl DirectUploadBackup {
             let hetzner = self.hetzner.clone();
             let encryption = self.encryption.clone();
             let bandwidth_limiter = self.bandwidth_limiter.clone();
+            let settings = self.upload_settings(&local_path);
+            let known_blobs = known_blobs.clone();
+            let upload_metrics = self.upload_metrics.clone();
+            let temp = self.temp.clone();
+            let source = source.clone();
             let overall_pb = overall_pb_clone.clone();
             let file_pb = file_pb_clone.clone();
             let file_name = local_path.file_name()
                 .and_then(|n| n.to_str())
                 .unwrap_or("unknown")
                 .to_string();
+            let file_span = tracing::info_span!("file", path = %local_path.display(), size);
             
             let task = tokio::spawn(async move {
                 let _permit = sem.acquire().await.unwrap();
@@ -477,6 +2195,11 @@ impl DirectUploadBackup {
                     hetzner,
                     encryption,
                     bandwidth_limiter,
+                    settings,
+                    &known_blobs,
+                    &upload_metrics,
+                    &temp,
+                    &source,
                     file_pb.clone(),
                 ).await;
                 
@@ -487,7 +2210,7 @@ impl DirectUploadBackup {
                 overall_pb.inc(1);
                 
                 result
-            });
+            }.instrument(file_span));
             
             tasks.push(task);
         }
@@ -498,7 +2221,11 @@ impl DirectUploadBackup {
         
         for task in tasks {
             match task.await {
-                Ok(Ok(entry)) => uploaded.push(entry),
+                Ok(Ok(FileOutcome::Uploaded(entry))) => uploaded.push(entry),
+                Ok(Ok(FileOutcome::Skipped(skipped))) => {
+                    multi.println(format!("⚠️  Skipped {}: {}", skipped.path.display(), skipped.reason)).unwrap();
+                }
+                Ok(Ok(FileOutcome::Stopped)) => {}
                 Ok(Err(e)) => {
                     failed_count += 1;
                     multi.println(format!("⚠️  Upload failed: {}", e)).unwrap();
@@ -525,29 +2252,41 @@ impl DirectUploadBackup {
         &self,
         backup_id: &str,
         files: Vec<(PathBuf, u64)>,
-        resume_state: &mut ResumeState,
-    ) -> Result<Vec<FileEntry>> {
+        mut resume_state: ResumeState,
+        source: &SourceFiles,
+    ) -> Result<(Vec<FileEntry>, Vec<SkippedFile>)> {
+        let state_dir = self.resume_dir();
         let total_files = files.len() as u64;
         let semaphore = Arc::new(Semaphore::new(self.max_parallel));
         let mut tasks = Vec::new();
         
-        // Filter out already-uploaded files
-        let files_to_upload: Vec<_> = files.into_iter()
-            .filter(|(path, _)| !resume_state.is_uploaded(path))
-            .collect();
+        // Reuse entries of files uploaded by an earlier run that haven't changed since
+        let mut uploaded = Vec::new();
+        let mut files_to_upload = Vec::new();
+        for (path, size) in files {
+            match resume_state.current_entry(&path, size) {
+                Some(entry) => uploaded.push(entry.clone()),
+                None => files_to_upload.push((path, size)),
+            }
+        }
         
         let remaining_count = files_to_upload.len();
         
+        // Persist the initial state so an interruption before the first
+        // checkpoint can still be resumed
+        resume_state.save(&state_dir).await?;
+        
         if remaining_count == 0 {
             println!("✅ All files already uploaded - backup complete!");
-            // Still need to reconstruct file entries from state
-            // For now, return empty and let manifest reconstruction handle it
-            return Ok(Vec::new());
+            return Ok((uploaded, Vec::new()));
         }
         
         println!("   📊 {} files remaining to upload", remaining_count);
         println!();
         
+        // Content already stored by an earlier backup isn't sent again
+        let known_blobs = Arc::new(self.remote_blob_index().await);
+        
         // Create progress bars (indicatif auto-detects TTY)
         let multi = MultiProgress::new();
         
@@ -561,7 +2300,7 @@ impl DirectUploadBackup {
         );
         overall_pb.set_message("📦 Overall Progress");
         // Set position to already-uploaded count
-        overall_pb.set_position(resume_state.uploaded_count() as u64);
+        overall_pb.set_position(uploaded.len() as u64);
         
         // Current file progress bar  
         let file_pb = multi.add(ProgressBar::new(100));
@@ -577,8 +2316,8 @@ impl DirectUploadBackup {
         let overall_pb_clone = overall_pb.clone();
         let file_pb_clone = file_pb.clone();
         
-        // Clone resume_state for thread-safe updates
-        let resume_state_clone = Arc::new(tokio::sync::Mutex::new(resume_state.clone()));
+        // Share resume_state for thread-safe updates
+        let resume_state_clone = Arc::new(tokio::sync::Mutex::new(resume_state));
         
         for (local_path, size) in files_to_upload {
             let sem = semaphore.clone();
@@ -586,18 +2325,30 @@ impl DirectUploadBackup {
             let hetzner = self.hetzner.clone();
             let encryption = self.encryption.clone();
             let bandwidth_limiter = self.bandwidth_limiter.clone();
+            let settings = self.upload_settings(&local_path);
+            let known_blobs = known_blobs.clone();
+            let upload_metrics = self.upload_metrics.clone();
+            let temp = self.temp.clone();
+            let source = source.clone();
             let overall_pb = overall_pb_clone.clone();
             let file_pb = file_pb_clone.clone();
             let resume_state_ref = resume_state_clone.clone();
+            let state_dir = state_dir.clone();
+            let shutdown = self.shutdown.clone();
             let file_name = local_path.file_name()
                 .and_then(|n| n.to_str())
                 .unwrap_or("unknown")
                 .to_string();
-            let local_path_clone = local_path.clone();
+            let file_span = tracing::info_span!("file", path = %local_path.display(), size);
             
             let task = tokio::spawn(async move {
                 let _permit = sem.acquire().await.unwrap();
                 
+                // Files not yet started are left for the resumed run
+                if shutdown.is_cancelled() {
+                    return Ok(FileOutcome::Stopped);
+                }
+                
                 // Update current file progress
                 file_pb.set_message(format!("⬆️  Uploading: {}", file_name));
                 file_pb.set_length(size);
@@ -610,15 +2361,21 @@ impl DirectUploadBackup {
                     hetzner,
                     encryption,
                     bandwidth_limiter,
+                    settings,
+                    &known_blobs,
+                    &upload_metrics,
+                    &temp,
+                    &source,
                     file_pb.clone(),
                 ).await;
                 
-                // If upload succeeded, mark in resume state
-                if result.is_ok() {
+                // If upload succeeded, record it and checkpoint periodically
+                if let Ok(FileOutcome::Uploaded(ref entry)) = result {
                     let mut state = resume_state_ref.lock().await;
-                    state.mark_uploaded(local_path_clone.clone());
-                    // Save state after each successful upload
-                    let _ = state.save().await;
+                    state.record_upload(entry.clone());
+                    if let Err(e) = state.checkpoint(&state_dir, CHECKPOINT_INTERVAL).await {
+                        tracing::warn!("Failed to checkpoint resume state: {}", e);
+                    }
                 }
                 
                 // Complete file progress
@@ -628,18 +2385,31 @@ impl DirectUploadBackup {
                 overall_pb.inc(1);
                 
                 result
-            });
+            }.instrument(file_span));
             
             tasks.push(task);
         }
         
         // Wait for all uploads to complete
-        let mut uploaded = Vec::new();
         let mut failed_count = 0;
+        let mut stopped_count = 0;
+        let mut skipped = Vec::new();
+        let mut budget_error = None;
         
         for task in tasks {
             match task.await {
-                Ok(Ok(entry)) => uploaded.push(entry),
+                Ok(Ok(FileOutcome::Uploaded(entry))) => uploaded.push(entry),
+                Ok(Ok(FileOutcome::Skipped(file))) => {
+                    multi.println(format!("⚠️  Skipped {}: {}", file.path.display(), file.reason)).unwrap();
+                    skipped.push(file);
+                }
+                Ok(Ok(FileOutcome::Stopped)) => stopped_count += 1,
+                Ok(Err(e @ SkylockError::RetryBudgetExhausted(_))) => {
+                    // Save what was uploaded, then stop with the budget error
+                    failed_count += 1;
+                    multi.println(format!("⚠️  Upload failed: {}", e)).unwrap();
+                    budget_error.get_or_insert(e);
+                }
                 Ok(Err(e)) => {
                     failed_count += 1;
                     multi.println(format!("⚠️  Upload failed: {}", e)).unwrap();
@@ -651,18 +2421,37 @@ impl DirectUploadBackup {
             }
         }
         
-        // Update the original resume_state with final state
-        let final_state = resume_state_clone.lock().await;
-        *resume_state = final_state.clone();
+        // Persist the final state so a rerun only uploads what is missing
+        resume_state_clone.lock().await.save(&state_dir).await?;
         
         // Finish progress bars
         overall_pb.finish_with_message(format!(
-            "✅ Upload complete: {} files uploaded, {} failed",
+            "✅ Upload complete: {} files uploaded, {} skipped, {} failed",
             uploaded.len(),
+            skipped.len(),
             failed_count
         ));
         
-        Ok(uploaded)
+        if let Some(e) = budget_error {
+            return Err(e);
+        }
+        
+        if failed_count > 0 {
+            return Err(SkylockError::Backup(format!(
+                "{} files failed to upload; run the backup again to resume {}",
+                failed_count, backup_id
+            )));
+        }
+        
+        if stopped_count > 0 {
+            return Err(SkylockError::Backup(format!(
+                "Backup stopped by shutdown with {} files left; run the backup again to resume {}",
+                stopped_count, backup_id
+            )));
+        }
+        
+        skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok((uploaded, skipped))
     }
 
     /// Upload a single file with encryption, compression, and progress tracking
@@ -673,75 +2462,460 @@ impl DirectUploadBackup {
         hetzner: Arc<HetznerClient>,
         encryption: Arc<EncryptionManager>,
         bandwidth_limiter: Option<Arc<BandwidthLimiter>>,
+        settings: UploadSettings,
+        known_blobs: &RemoteBlobs,
+        upload_metrics: &ThroughputMetrics,
+        temp: &TempFiles,
+        source: &SourceFiles,
         progress: ProgressBar,
-    ) -> Result<FileEntry> {
-        // Calculate hash
-        let hash = Self::calculate_hash(&local_path).await?;
-        progress.set_position(size / 4); // 25% for hashing
-        
-        // Determine if we should compress (files > 10MB)
-        let should_compress = size > 10 * 1024 * 1024;
+    ) -> Result<FileOutcome> {
+        // Files over the size limit are skipped when asked to, and streamed
+        // otherwise, like those too large to hold in memory
+        let oversized = settings.max_file_size.filter(|&max| size > max);
+        if let Some(max) = oversized.filter(|_| settings.skip_oversized_files) {
+            return Ok(FileOutcome::Skipped(SkippedFile {
+                path: local_path,
+                reason: format!("larger than max_file_size ({} > {})", ByteSize(size), ByteSize(max)),
+            }));
+        }
+        if oversized.is_some() || settings.stream_threshold.is_some_and(|threshold| size >= threshold) {
+            return Self::upload_streamed_file(
+                backup_id,
+                local_path,
+                &hetzner,
+                &encryption,
+                bandwidth_limiter,
+                settings,
+                upload_metrics,
+                temp,
+                source,
+                progress,
+            ).instrument(tracing::info_span!("upload_stream", bytes = size)).await;
+        }
         
-        // Build remote path
-        let relative_path = local_path.strip_prefix("/")
-            .unwrap_or(&local_path);
-        let remote_path = format!(
-            "/skylock/backups/{}/{}{}",
-            backup_id,
-            relative_path.display(),
-            if should_compress { ".zst.enc" } else { ".enc" }
-        );
+        // Times are taken before reading, so a later change isn't masked
+        let (data, metadata) = match source.read(&local_path).await? {
+            SourceRead::Read { data, metadata } => (data, metadata),
+            SourceRead::Skipped(skipped) => return Ok(FileOutcome::Skipped(skipped)),
+        };
+        let (modified, changed) = FileEntry::source_times(&metadata);
+        progress.set_position(size / 4); // 25% for reading
         
-        // Read file
-        let data = tokio::fs::read(&local_path).await?;
-        progress.set_position(size / 2); // 50% for reading
+        // Calculate hash
+        let hash = tracing::info_span!("hash", bytes = data.len())
+            .in_scope(|| settings.hash_algorithm.hash(&data));
+        progress.set_position(size / 2); // 50% for hashing
         
-        // Optionally compress
-        let data_to_encrypt = if should_compress {
-            zstd::encode_all(data.as_slice(), 3)
-                .map_err(|e| SkylockError::Backup(format!("Compression failed: {}", e)))?
+        let xattrs = if settings.preserve_xattrs {
+            xattrs::read_xattrs(&local_path)
         } else {
-            data
+            Vec::new()
         };
-        progress.set_position(size * 3 / 4); // 75% for compression
-        
-        // Encrypt with AAD binding (v2 format)
-        let file_path_str = local_path.to_string_lossy();
-        let encrypted_data = encryption.encrypt_with_aad(
-            &data_to_encrypt,
-            backup_id,
-            &file_path_str
-        )?;
         
-        // Create parent directories
-        if let Some(parent) = PathBuf::from(&remote_path).parent() {
-            if let Some(parent_str) = parent.to_str() {
-                let _ = Self::ensure_remote_directory_exists(&hetzner, parent_str).await;
+        // Large files only upload the chunks no earlier backup stored
+        if data.len() as u64 >= CHUNKED_FILE_THRESHOLD {
+            let started = Instant::now();
+            let (chunks, uploaded, reused) = Self::upload_chunks(
+                backup_id,
+                &data,
+                &hetzner,
+                &encryption,
+                bandwidth_limiter.as_deref(),
+                settings,
+                known_blobs,
+                temp,
+            ).instrument(tracing::info_span!("upload_chunks", bytes = data.len())).await?;
+            upload_metrics.record_skip(reused);
+            if uploaded > 0 {
+                upload_metrics.record_upload(uploaded, started.elapsed().as_millis() as u64);
             }
+            progress.set_position(size);
+            
+            return Ok(FileOutcome::Uploaded(FileEntry {
+                local_path,
+                remote_path: String::new(),
+                size: data.len() as u64,
+                hash,
+                compressed: false,
+                compression: None,
+                encrypted: true,
+                key_context: None,
+                timestamp: Utc::now(),
+                modified,
+                changed,
+                xattrs,
+                blob_origin: None,
+                chunks,
+                framing: None,
+                blob_hash: None,
+            }));
         }
         
-        // Apply bandwidth throttling if enabled
-        if let Some(ref limiter) = bandwidth_limiter {
-            limiter.consume(encrypted_data.len() as u64).await;
+        // Compress unless the file is already compressed or wouldn't shrink
+        let compress = tracing::info_span!("compress", bytes = data.len(), compressed_bytes = Empty);
+        let (data_to_encrypt, mut compression) = compress
+            .in_scope(|| Self::compress_for_upload(data, &hash, settings.compression))?;
+        compress.record("compressed_bytes", data_to_encrypt.len());
+        compression.profile = settings.compression_profile.clone();
+        progress.set_position(size * 3 / 4); // 75% for compression
+        
+        // Skip the upload if an earlier backup stored the same content and
+        // the remote blob still has the size this upload would produce
+        let blob_size = data_to_encrypt.len() as u64 + AEAD_OVERHEAD;
+        if let Some(known) = known_blobs.files.get(&hash) {
+            if known.compression_algorithm() == compression.algorithm()
+                && Self::remote_blob_size(&hetzner, &known.remote_path).await == Some(blob_size)
+            {
+                upload_metrics.record_skip(blob_size);
+                progress.set_position(size);
+                return Ok(FileOutcome::Uploaded(FileEntry {
+                    local_path,
+                    timestamp: Utc::now(),
+                    modified,
+                    changed,
+                    xattrs,
+                    ..known.clone()
+                }));
+            }
         }
         
-        // Upload
-        let temp_file = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Temp file failed: {}", e)))?;
-        tokio::fs::write(temp_file.path(), &encrypted_data).await?;
+        // Build remote path
+        let remote_path = Self::remote_file_path(
+            backup_id,
+            &local_path,
+            compression.algorithm(),
+            settings.encrypt_names.then_some(encryption.as_ref()),
+            settings.blob_naming,
+        );
         
-        hetzner.upload_file(temp_file.path(), &PathBuf::from(&remote_path)).await?;
+        // Encrypt with AAD binding (v2 format), under the file's own subkey
+        let file_path_str = local_path.to_string_lossy();
+        let key_context = FileEntry::key_context_for(backup_id, &local_path);
+        let encrypted_data = tracing::info_span!("encrypt", bytes = data_to_encrypt.len()).in_scope(|| {
+            encryption.derive_file_key(&key_context).encrypt_with_aad(
+                &data_to_encrypt,
+                backup_id,
+                &file_path_str
+            )
+        })?;
+        
+        // Create parent directories, throttle and upload
+        let upload = tracing::info_span!("upload", bytes = encrypted_data.len());
+        let started = Instant::now();
+        async {
+            if let Some(parent) = PathBuf::from(&remote_path).parent() {
+                if let Some(parent_str) = parent.to_str() {
+                    let _ = Self::ensure_remote_directory_exists(&hetzner, parent_str).await;
+                }
+            }
+            
+            // Apply bandwidth throttling if enabled
+            if let Some(ref limiter) = bandwidth_limiter {
+                limiter.consume(encrypted_data.len() as u64).await;
+            }
+            
+            Self::upload_blob(&hetzner, temp, &encrypted_data, &remote_path, settings.verify_on_upload, &settings.retrier).await
+        }.instrument(upload).await?;
+        upload_metrics.record_upload(encrypted_data.len() as u64, started.elapsed().as_millis() as u64);
         progress.set_position(size); // 100% complete
+        let blob_hash = crate::compression_integrity::calculate_hash(&encrypted_data);
         
-        Ok(FileEntry {
+        Ok(FileOutcome::Uploaded(FileEntry {
             local_path: local_path.clone(),
             remote_path,
             size,
             hash,
-            compressed: should_compress,
+            compressed: compression.compressed,
+            compression: Some(compression),
             encrypted: true,
+            key_context: Some(key_context),
             timestamp: Utc::now(),
-        })
+            modified,
+            changed,
+            xattrs,
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: Some(blob_hash),
+        }))
+    }
+    
+    /// Upload the content-defined chunks of a large file
+    /// 
+    /// Chunks that an earlier backup or an earlier part of the file already
+    /// stored are referenced instead of uploaded, so a file that grew by
+    /// appending only sends its new tail and the chunk at the old end.
+    /// Returns the chunk list, the bytes uploaded and the bytes reused.
+    async fn upload_chunks(
+        backup_id: &str,
+        data: &[u8],
+        hetzner: &HetznerClient,
+        encryption: &EncryptionManager,
+        bandwidth_limiter: Option<&BandwidthLimiter>,
+        settings: UploadSettings,
+        known_blobs: &RemoteBlobs,
+        temp: &TempFiles,
+    ) -> Result<(Vec<ChunkEntry>, u64, u64)> {
+        let chunk_dir = format!("/skylock/backups/{}/chunks", backup_id);
+        let mut stored: std::collections::HashMap<String, ChunkEntry> = std::collections::HashMap::new();
+        let mut created_dirs = std::collections::HashSet::new();
+        let mut chunks = Vec::new();
+        let (mut uploaded, mut reused) = (0u64, 0u64);
+        
+        for range in ContentChunker::default().chunks(data) {
+            let piece = &data[range];
+            let hash = settings.hash_algorithm.hash(piece);
+            if let Some(known) = stored.get(&hash).or_else(|| known_blobs.chunks.get(&hash)) {
+                reused += known.size;
+                chunks.push(known.clone());
+                continue;
+            }
+            
+            let (data_to_encrypt, mut compression) = Self::compress_for_upload(piece.to_vec(), &hash, settings.compression)?;
+            compression.profile = settings.compression_profile.clone();
+            // Keyed name so the storage box can't match chunks to known content
+            let remote_path = settings.blob_naming.key(
+                &chunk_dir,
+                &format!(
+                    "{}{}",
+                    encryption.keyed_digest(backup_id, hash.as_bytes()),
+                    Self::remote_suffix(compression.algorithm())
+                ),
+            );
+            let key_context = ChunkEntry::key_context_for(backup_id, &hash);
+            let encrypted_data = encryption.derive_file_key(&key_context)
+                .encrypt_with_aad(&data_to_encrypt, backup_id, &ChunkEntry::aad(&hash))?;
+            
+            if let Some((parent, _)) = remote_path.rsplit_once('/') {
+                if created_dirs.insert(parent.to_string()) {
+                    let _ = Self::ensure_remote_directory_exists(hetzner, parent).await;
+                }
+            }
+            if let Some(limiter) = bandwidth_limiter {
+                limiter.consume(encrypted_data.len() as u64).await;
+            }
+            Self::upload_blob(hetzner, temp, &encrypted_data, &remote_path, settings.verify_on_upload, &settings.retrier).await?;
+            uploaded += encrypted_data.len() as u64;
+            
+            let chunk = ChunkEntry {
+                hash: hash.clone(),
+                size: piece.len() as u64,
+                remote_path,
+                backup_id: backup_id.to_string(),
+                compression,
+                key_context: Some(key_context),
+            };
+            stored.insert(hash, chunk.clone());
+            chunks.push(chunk);
+        }
+        
+        Ok((chunks, uploaded, reused))
+    }
+    
+    /// Upload a file as a stream: compressed, encrypted in segments and sent
+    /// as it is read, so it never has to fit in memory (see
+    /// [`crate::file_stream`])
+    /// 
+    /// The file's hash is only known once it has been sent, so unlike
+    /// smaller files it isn't matched against blobs of earlier backups. A
+    /// file that changes while it is streamed is skipped and its blob deleted.
+    async fn upload_streamed_file(
+        backup_id: &str,
+        local_path: PathBuf,
+        hetzner: &HetznerClient,
+        encryption: &EncryptionManager,
+        bandwidth_limiter: Option<Arc<BandwidthLimiter>>,
+        settings: UploadSettings,
+        upload_metrics: &ThroughputMetrics,
+        temp: &TempFiles,
+        source: &SourceFiles,
+        progress: ProgressBar,
+    ) -> Result<FileOutcome> {
+        let key_context = FileEntry::key_context_for(backup_id, &local_path);
+        let file_key = Arc::new(encryption.derive_file_key(&key_context));
+        let attempts = settings.verify_on_upload.map_or(1, |retries| retries + 1);
+        let framing = settings.stream_framing;
+        
+        for attempt in 1..=attempts {
+            let (mut file, before, read_path) = match source.open(&local_path)? {
+                SourceOpen::Opened { file, metadata, path } => (file, metadata, path),
+                SourceOpen::Skipped(skipped) => return Ok(FileOutcome::Skipped(skipped)),
+            };
+            framing.check_file_size(before.len())
+                .map_err(|e| SkylockError::Encryption(format!("{}: {}", local_path.display(), e)))?;
+            let level = Self::stream_compression_level(&mut file, settings.compression)?;
+            let algorithm = if level.is_some() { CompressionAlgorithm::Zstd } else { CompressionAlgorithm::None };
+            let remote_path = Self::remote_file_path(
+                backup_id,
+                &local_path,
+                algorithm,
+                settings.encrypt_names.then_some(encryption),
+                settings.blob_naming,
+            );
+            if let Some((parent, _)) = remote_path.rsplit_once('/') {
+                let _ = Self::ensure_remote_directory_exists(hetzner, parent).await;
+            }
+            
+            progress.set_position(0);
+            let started = Instant::now();
+            let streamed = Self::stream_blob(
+                hetzner,
+                file,
+                file_key.clone(),
+                backup_id,
+                level,
+                framing,
+                settings.hash_algorithm,
+                &remote_path,
+                bandwidth_limiter.clone(),
+                progress.clone(),
+            ).await?;
+            upload_metrics.record_upload(streamed.blob_size, started.elapsed().as_millis() as u64);
+            
+            // A file that changed while it was read would be stored torn
+            let after = std::fs::metadata(&read_path)?;
+            if !locked_files::unchanged(&before, &after) || streamed.size != before.len() {
+                let _ = hetzner.delete_file(Path::new(&remote_path)).await;
+                return Ok(FileOutcome::Skipped(SkippedFile {
+                    path: local_path,
+                    reason: "changed while being read".to_string(),
+                }));
+            }
+            
+            if settings.verify_on_upload.is_some() {
+                let stored = temp.file("verify")?;
+                hetzner.download_file(Path::new(&remote_path), stored.path()).await?;
+                let stored_file = std::fs::File::open(stored.path())?;
+                let (stored_hash, _) = tokio::task::spawn_blocking(move || {
+                    file_stream::hash_reader(std::io::BufReader::new(stored_file), HashAlgorithm::Sha256)
+                }).await.map_err(|e| SkylockError::Backup(format!("Hash task failed: {}", e)))??;
+                if stored_hash != streamed.blob_hash {
+                    tracing::warn!(
+                        "Read-back of {} does not match the uploaded data (attempt {}/{})",
+                        remote_path, attempt, attempts
+                    );
+                    continue;
+                }
+            }
+            
+            let (modified, changed) = FileEntry::source_times(&before);
+            let xattrs = if settings.preserve_xattrs {
+                xattrs::read_xattrs(&local_path)
+            } else {
+                Vec::new()
+            };
+            let compression = CompressionMetadata {
+                compressed: level.is_some(),
+                algorithm: Some(algorithm),
+                original_hash: streamed.hash.clone(),
+                original_size: streamed.size,
+                compressed_hash: None,
+                compression_level: level,
+                compression_ratio: None,
+                profile: settings.compression_profile.clone(),
+            };
+            return Ok(FileOutcome::Uploaded(FileEntry {
+                local_path,
+                remote_path,
+                size: streamed.size,
+                hash: streamed.hash,
+                compressed: compression.compressed,
+                compression: Some(compression),
+                encrypted: true,
+                key_context: Some(key_context),
+                timestamp: Utc::now(),
+                modified,
+                changed,
+                xattrs,
+                blob_origin: None,
+                chunks: Vec::new(),
+                framing: Some(framing),
+                blob_hash: Some(streamed.blob_hash),
+            }));
+        }
+        
+        Err(SkylockError::Backup(format!(
+            "{} failed upload verification after {} attempts",
+            local_path.display(), attempts
+        )))
+    }
+    
+    /// zstd level for a streamed file, or `None` to store it uncompressed
+    /// 
+    /// Streams are only compressed with zstd, so a forced algorithm other
+    /// than none means zstd; otherwise the start of the file decides, like
+    /// the adaptive choice for smaller files.
+    fn stream_compression_level(file: &mut std::fs::File, forced: CompressionOverride) -> std::io::Result<Option<i32>> {
+        use std::io::{Read, Seek};
+        
+        let level = forced.level
+            .map_or(file_stream::DEFAULT_STREAM_LEVEL, |level| level.to_level(CompressionAlgorithm::Zstd));
+        match forced.algorithm {
+            Some(CompressionAlgorithm::None) => return Ok(None),
+            Some(_) => return Ok(Some(level)),
+            None => {}
+        }
+        
+        let mut sample = Vec::new();
+        file.by_ref().take(file_stream::COMPRESSION_SAMPLE).read_to_end(&mut sample)?;
+        file.rewind()?;
+        let engine = CompressionEngine::new();
+        let (algorithm, _) = engine.select_algorithm(&engine.analyze_data(&sample));
+        Ok((algorithm != CompressionAlgorithm::None).then_some(level))
+    }
+    
+    /// Encode `input` into a streamed blob at `remote_path`
+    /// 
+    /// Reading, compression and encryption run on a blocking thread that
+    /// feeds the upload through a bounded queue, so memory use stays at a
+    /// few segments whatever the size of the file.
+    async fn stream_blob(
+        hetzner: &HetznerClient,
+        input: std::fs::File,
+        encryption: Arc<EncryptionManager>,
+        backup_id: &str,
+        level: Option<i32>,
+        framing: SegmentFraming,
+        hash_algorithm: HashAlgorithm,
+        remote_path: &str,
+        bandwidth_limiter: Option<Arc<BandwidthLimiter>>,
+        progress: ProgressBar,
+    ) -> Result<file_stream::StreamedFile> {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(STREAM_UPLOAD_QUEUE);
+        let id = backup_id.to_string();
+        let producer = tokio::task::spawn_blocking(move || {
+            let error_sender = sender.clone();
+            let input = progress.wrap_read(std::io::BufReader::new(input));
+            let result = file_stream::encode(input, archive::ChannelWriter::new(sender), encryption, &id, level, framing, hash_algorithm)
+                .map(|(streamed, _)| streamed);
+            // A failed read must fail the upload too, rather than leave a
+            // truncated blob; if the upload stopped first, it has the cause
+            let upload_stopped = error_sender.is_closed();
+            if let Err(ref e) = result {
+                let _ = error_sender.blocking_send(Err(std::io::Error::new(e.kind(), e.to_string())));
+            }
+            (result, upload_stopped)
+        });
+        let body = futures::stream::poll_fn(move |cx| receiver.poll_recv(cx))
+            .then(move |item| {
+                let limiter = bandwidth_limiter.clone();
+                async move {
+                    if let (Some(limiter), Ok(data)) = (limiter, &item) {
+                        limiter.consume(data.len() as u64).await;
+                    }
+                    item
+                }
+            });
+        
+        let (uploaded, produced) = tokio::join!(hetzner.upload_stream(body, Path::new(remote_path)), producer);
+        let (produced, upload_stopped) = produced
+            .map_err(|e| SkylockError::Backup(format!("Stream task failed: {}", e)))?;
+        match (produced, uploaded) {
+            (Ok(streamed), Ok(())) => Ok(streamed),
+            (Err(_), Err(e)) if upload_stopped => Err(e.into()),
+            (Err(e), _) => Err(SkylockError::Backup(format!("Failed to stream {}: {}", remote_path, e))),
+            (Ok(_), Err(e)) => Err(e.into()),
+        }
     }
 
     /// Upload a single file with encryption and optional compression (legacy without progress)
@@ -751,12 +2925,20 @@ impl DirectUploadBackup {
         size: u64,
         hetzner: Arc<HetznerClient>,
         encryption: Arc<EncryptionManager>,
+        temp: &TempFiles,
     ) -> Result<FileEntry> {
+        let (modified, changed) = FileEntry::source_times(&tokio::fs::metadata(&local_path).await?);
+        
         // Calculate hash
-        let hash = Self::calculate_hash(&local_path).await?;
+        let hash = Self::calculate_hash(&local_path, HashAlgorithm::default()).await?;
+        
+        println!("  ⬆️  {}", local_path.display());
+        
+        // Read file
+        let data = tokio::fs::read(&local_path).await?;
         
-        // Determine if we should compress (files > 10MB)
-        let should_compress = size > 10 * 1024 * 1024;
+        // Compress unless the file is already compressed or wouldn't shrink
+        let (data_to_encrypt, compression) = Self::compress_for_upload(data, &hash, CompressionOverride::default())?;
         
         // Build remote path: /skylock/backups/{backup_id}/{relative_path}.enc
         let relative_path = local_path.strip_prefix("/")
@@ -765,22 +2947,9 @@ impl DirectUploadBackup {
             "/skylock/backups/{}/{}{}",
             backup_id,
             relative_path.display(),
-            if should_compress { ".zst.enc" } else { ".enc" }
+            Self::remote_suffix(compression.algorithm())
         );
         
-        println!("  ⬆️  {}", local_path.display());
-        
-        // Read file
-        let data = tokio::fs::read(&local_path).await?;
-        
-        // Optionally compress
-        let data_to_encrypt = if should_compress {
-            zstd::encode_all(data.as_slice(), 3)
-                .map_err(|e| SkylockError::Backup(format!("Compression failed: {}", e)))?
-        } else {
-            data
-        };
-        
         // Encrypt with AAD binding (v2 format)
         let file_path_str = local_path.to_string_lossy();
         let encrypted_data = encryption.encrypt_with_aad(
@@ -798,29 +2967,165 @@ impl DirectUploadBackup {
         }
         
         // Upload
-        let temp_file = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Temp file failed: {}", e)))?;
-        tokio::fs::write(temp_file.path(), &encrypted_data).await?;
-        
-        hetzner.upload_file(temp_file.path(), &PathBuf::from(&remote_path)).await?;
+        temp.upload(&hetzner, &encrypted_data, &PathBuf::from(&remote_path)).await?;
+        let blob_hash = crate::compression_integrity::calculate_hash(&encrypted_data);
         
         Ok(FileEntry {
             local_path: local_path.clone(),
             remote_path,
             size,
             hash,
-            compressed: should_compress,
+            compressed: compression.compressed,
+            compression: Some(compression),
             encrypted: true,
+            key_context: None,
             timestamp: Utc::now(),
+            modified,
+            changed,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: Some(blob_hash),
         })
     }
 
-    /// Calculate SHA-256 hash of file
-    async fn calculate_hash(path: &Path) -> Result<String> {
-        let data = tokio::fs::read(path).await?;
-        let mut hasher = Sha256::new();
-        hasher.update(&data);
-        Ok(format!("{:x}", hasher.finalize()))
+    /// Size of a stored blob, or `None` if it is missing or can't be checked
+    async fn remote_blob_size(hetzner: &HetznerClient, remote_path: &str) -> Option<u64> {
+        hetzner.object_info(Path::new(remote_path)).await.ok().map(|info| info.size)
+    }
+
+    /// Upload an encrypted file, optionally reading it back to check it
+    ///
+    /// With `verify_on_upload` set, the stored object is downloaded right
+    /// after the upload and its hash compared with the data that was sent. A
+    /// mismatch re-uploads it up to that many more times before the file is
+    /// failed. Failed transfers are retried by `retrier`, within the
+    /// backup's retry budget.
+    async fn upload_blob(
+        hetzner: &HetznerClient,
+        temp: &TempFiles,
+        encrypted_data: &[u8],
+        remote_path: &str,
+        verify_on_upload: Option<u32>,
+        retrier: &Retrier,
+    ) -> Result<()> {
+        let remote = PathBuf::from(remote_path);
+        let call = format!("upload of {}", remote_path);
+        let upload = || retrier.execute(BACKUP_OPERATION, &call, || temp.upload(hetzner, encrypted_data, &remote));
+        let Some(retries) = verify_on_upload else {
+            return upload().await;
+        };
+        
+        let expected_hash = crate::compression_integrity::calculate_hash(encrypted_data);
+        
+        for attempt in 1..=retries + 1 {
+            upload().await?;
+            let stored = retrier.execute(BACKUP_OPERATION, &call, || temp.download_bytes(hetzner, &remote)).await?;
+            if verify_compressed_hash(&stored, &expected_hash) {
+                return Ok(());
+            }
+            tracing::warn!(
+                "Read-back of {} does not match the uploaded data (attempt {}/{})",
+                remote_path, attempt, retries + 1
+            );
+        }
+        
+        Err(SkylockError::Backup(format!(
+            "{} failed upload verification after {} attempts",
+            remote_path, retries + 1
+        )))
+    }
+
+    /// Decide per file how to compress before encryption
+    ///
+    /// The adaptive engine skips small files and formats that are already
+    /// compressed (JPEG, PNG, ZIP, MP4, ...) and otherwise picks LZ4, zstd or
+    /// Brotli from the data's characteristics; `forced` overrides that choice
+    /// for the run. The result is kept only if it is actually smaller; the
+    /// choice is recorded in the file's metadata so restore can pick the
+    /// matching decompressor.
+    pub(crate) fn compress_for_upload(
+        data: Vec<u8>,
+        original_hash: &str,
+        forced: CompressionOverride,
+    ) -> Result<(Vec<u8>, CompressionMetadata)> {
+        let engine = CompressionEngine::new();
+        let (algorithm, level) = match forced.algorithm {
+            Some(algorithm) => forced.apply((algorithm, CompressionLevel::Default)),
+            None => forced.apply(engine.select_algorithm(&engine.analyze_data(&data))),
+        };
+        let original_size = data.len() as u64;
+        let uncompressed = |data: Vec<u8>| {
+            let metadata = CompressionMetadata::for_algorithm(
+                CompressionAlgorithm::None, 0, original_hash.to_string(), original_size, &data,
+            );
+            Ok((data, metadata))
+        };
+        if algorithm == CompressionAlgorithm::None {
+            return uncompressed(data);
+        }
+        
+        let compressed = engine.compress_with_algorithm(&data, algorithm, level)
+            .map_err(|e| SkylockError::Compression(e.to_string()))?
+            .data;
+        if compressed.len() >= data.len() {
+            return uncompressed(data);
+        }
+        
+        let metadata = CompressionMetadata::for_algorithm(
+            algorithm, level.to_level(algorithm), original_hash.to_string(), original_size, &compressed,
+        );
+        Ok((compressed, metadata))
+    }
+    
+    /// Remote file suffix for the chosen compression
+    /// Remote path of a file's blob: mirrors the local path, or with
+    /// `name_encryption` uses a keyed digest of it, laid out by `naming`, so
+    /// the storage box never sees file names
+    fn remote_file_path(
+        backup_id: &str,
+        local_path: &Path,
+        compression: CompressionAlgorithm,
+        name_encryption: Option<&EncryptionManager>,
+        naming: BlobNaming,
+    ) -> String {
+        let backup_dir = format!("/skylock/backups/{}", backup_id);
+        match name_encryption {
+            Some(encryption) => naming.key(
+                &backup_dir,
+                &format!(
+                    "{}{}",
+                    encryption.keyed_digest(backup_id, local_path.to_string_lossy().as_bytes()),
+                    Self::remote_suffix(compression)
+                ),
+            ),
+            None => format!(
+                "{}/{}{}",
+                backup_dir,
+                local_path.strip_prefix("/").unwrap_or(local_path).display(),
+                Self::remote_suffix(compression)
+            ),
+        }
+    }
+    
+    fn remote_suffix(compression: CompressionAlgorithm) -> &'static str {
+        match compression {
+            CompressionAlgorithm::None => ".enc",
+            CompressionAlgorithm::Lz4 => ".lz4.enc",
+            CompressionAlgorithm::Zstd => ".zst.enc",
+            CompressionAlgorithm::Brotli => ".br.enc",
+            CompressionAlgorithm::Gzip => ".gz.enc",
+        }
+    }
+    
+    /// Calculate SHA-256 hash of file, a buffer at a time
+    async fn calculate_hash(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let (hash, _) = tokio::task::spawn_blocking(move || file_stream::hash_reader(file, algorithm))
+            .await
+            .map_err(|e| SkylockError::Backup(format!("Hash task failed: {}", e)))??;
+        Ok(hash)
     }
     
     /// Calculate SHA-256 hash of file using parallel hashing for large files
@@ -833,18 +3138,7 @@ impl DirectUploadBackup {
     
     /// Ensure remote directory exists by creating all parent directories
     async fn ensure_remote_directory_exists(hetzner: &HetznerClient, path: &str) -> Result<()> {
-        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-        let mut current_path = String::new();
-        
-        for part in parts {
-            current_path.push('/');
-            current_path.push_str(part);
-            
-            // Try to create directory - ignore errors if it already exists
-            let _ = hetzner.create_directory(&current_path).await;
-        }
-        
-        Ok(())
+        Ok(hetzner.create_directory_all(path).await?)
     }
 
     /// Upload backup manifest with optional encryption
@@ -852,33 +3146,53 @@ impl DirectUploadBackup {
     /// In v3+ format, manifests are encrypted for metadata privacy.
     /// File names, paths, and sizes are only visible with the correct encryption key.
     async fn upload_manifest(&self, manifest: &BackupManifest) -> Result<()> {
-        use crate::encrypted_manifest::{ManifestEncryption, ManifestHeader};
+        self.upload_manifest_with(manifest, &self.encryption).await
+    }
+    
+    /// Upload a manifest encrypted with `encryption`
+    async fn upload_manifest_with(&self, manifest: &BackupManifest, encryption: &EncryptionManager) -> Result<()> {
+        use crate::encrypted_manifest::ManifestEncryption;
+        
+        // Sign with the current content, replacing any older signature
+        let signed;
+        let manifest = match &self.manifest_signing_key {
+            Some(key) => {
+                let mut copy = manifest.clone();
+                sign_manifest(&mut copy, key, manifest.backup_chain_version)?;
+                signed = copy;
+                &signed
+            }
+            None => manifest,
+        };
         
         // Ensure backup directory exists
         let backup_dir = format!("/skylock/backups/{}", manifest.backup_id);
         Self::ensure_remote_directory_exists(&self.hetzner, &backup_dir).await?;
         
         // Create manifest encryption handler
-        let manifest_encryption = ManifestEncryption::new(&self.encryption);
+        let manifest_encryption = ManifestEncryption::new(encryption)
+            .with_encrypted_names(self.config.backup.encrypt_file_names)
+            .with_compression(self.config.backup.compress_manifests);
         
         // Encrypt the full manifest
         let encrypted = manifest_encryption.encrypt_manifest(manifest)?;
         
         // Upload encrypted manifest (manifest.json.enc)
         let encrypted_path = format!("/skylock/backups/{}/manifest.json.enc", manifest.backup_id);
-        let temp_encrypted = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Temp file failed: {}", e)))?;
-        tokio::fs::write(temp_encrypted.path(), &encrypted.encrypted_data).await?;
-        self.hetzner.upload_file(temp_encrypted.path(), &PathBuf::from(&encrypted_path)).await?;
+        manifest_checksum::upload_with_checksum(&self.hetzner, &self.temp, &encrypted.encrypted_data, &PathBuf::from(&encrypted_path)).await?;
         
         // Upload public header (manifest_header.json) - for listing backups without decryption
         let header_path = format!("/skylock/backups/{}/manifest_header.json", manifest.backup_id);
         let header_json = serde_json::to_string_pretty(&encrypted.header)
             .map_err(|e| SkylockError::Backup(format!("Serialize header failed: {}", e)))?;
-        let temp_header = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Temp file failed: {}", e)))?;
-        tokio::fs::write(temp_header.path(), header_json).await?;
-        self.hetzner.upload_file(temp_header.path(), &PathBuf::from(&header_path)).await?;
+        self.temp.upload(&self.hetzner, header_json.as_bytes(), &PathBuf::from(&header_path)).await?;
+        
+        // Upload encrypted summary (manifest_summary.json.enc) - for listing
+        // backups without downloading every full manifest
+        let summary_path = format!("/skylock/backups/{}/manifest_summary.json.enc", manifest.backup_id);
+        self.temp.upload(&self.hetzner, &manifest_encryption.encrypt_summary(manifest)?, &PathBuf::from(&summary_path)).await?;
+        
+        self.invalidate_listing().await;
         
         println!("  📋 Encrypted manifest uploaded (v3 format)");
         println!("  🔐 File metadata is protected - requires key to browse");
@@ -886,6 +3200,66 @@ impl DirectUploadBackup {
         Ok(())
     }
     
+    /// Upload the figures of a completed backup run as `summary.json`
+    async fn upload_run_summary(&self, summary: &BackupRunSummary) -> Result<()> {
+        let json = serde_json::to_vec_pretty(summary)
+            .map_err(|e| SkylockError::Backup(format!("Serialize summary failed: {}", e)))?;
+        self.temp.upload(&self.hetzner, &json, &BackupRunSummary::remote_path(&summary.backup_id)).await
+    }
+    
+    /// Figures of the run that created `backup_id`, read from its
+    /// `summary.json` without the manifest or the encryption key
+    /// 
+    /// Backups made before summaries were written have none.
+    pub async fn load_run_summary(&self, backup_id: &str) -> Result<BackupRunSummary> {
+        let json = self.temp.download_bytes(&self.hetzner, &BackupRunSummary::remote_path(backup_id)).await?;
+        serde_json::from_slice(&json)
+            .map_err(|e| SkylockError::Backup(format!("Invalid summary of backup {}: {}", backup_id, e)))
+    }
+    
+    /// Run summaries of the newest `limit` backups, newest first
+    /// 
+    /// Backups made before summaries were written are left out; any other
+    /// failure to fetch a summary fails the call.
+    pub async fn recent_run_summaries(&self, limit: usize) -> Result<Vec<BackupRunSummary>> {
+        let backups = self.list_backup_summaries().await?;
+        let summaries: Vec<Result<BackupRunSummary>> = futures::stream::iter(backups.into_iter().take(limit))
+            .map(|backup| async move { self.load_run_summary(&backup.backup_id).await })
+            .buffered(self.list_concurrency)
+            .collect()
+            .await;
+        
+        let mut found = Vec::new();
+        for summary in summaries {
+            match summary {
+                Ok(summary) => found.push(summary),
+                Err(e) if e.is_not_found() => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(found)
+    }
+    
+    /// Store `bundle` on the storage box, sealed with `passphrase`,
+    /// replacing the previous one
+    pub async fn upload_config_bundle(&self, bundle: &ConfigBundle, passphrase: &str) -> Result<()> {
+        let remote_path = ConfigBundle::remote_path();
+        if let Some(parent) = remote_path.parent().and_then(|p| p.to_str()) {
+            Self::ensure_remote_directory_exists(&self.hetzner, parent).await?;
+        }
+        self.temp.upload(&self.hetzner, &bundle.seal(passphrase)?, &remote_path).await
+    }
+    
+    /// Fetch and open the configuration bundle stored by
+    /// [`Self::upload_config_bundle`]
+    pub async fn download_config_bundle(&self, passphrase: &str) -> Result<ConfigBundle> {
+        let sealed = self.temp.download_bytes(&self.hetzner, &ConfigBundle::remote_path()).await
+            .map_err(|e| SkylockError::Backup(format!(
+                "No config bundle on the storage box ({}); create one with 'skylock backup-config'", e
+            )))?;
+        ConfigBundle::open(&sealed, passphrase)
+    }
+    
     /// Upload manifest in legacy plaintext format (for backward compatibility)
     #[allow(dead_code)]
     async fn upload_manifest_legacy(&self, manifest: &BackupManifest) -> Result<()> {
@@ -898,11 +3272,8 @@ impl DirectUploadBackup {
         let backup_dir = format!("/skylock/backups/{}", manifest.backup_id);
         Self::ensure_remote_directory_exists(&self.hetzner, &backup_dir).await?;
         
-        let temp_file = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Temp file failed: {}", e)))?;
-        
-        tokio::fs::write(temp_file.path(), manifest_json).await?;
-        self.hetzner.upload_file(temp_file.path(), &PathBuf::from(&manifest_path)).await?;
+        manifest_checksum::upload_with_checksum(&self.hetzner, &self.temp, manifest_json.as_bytes(), &PathBuf::from(&manifest_path)).await?;
+        self.invalidate_listing().await;
         
         println!("  📋 Manifest uploaded (legacy plaintext format)");
         
@@ -913,111 +3284,270 @@ impl DirectUploadBackup {
     /// 
     /// For v3+ backups with encrypted manifests, this will download and decrypt
     /// the manifests to show full details. For older backups, reads plaintext manifests.
+    /// Up to `list_concurrency` manifests are downloaded at once, and one that
+    /// can't be read is skipped with a warning.
     pub async fn list_backups(&self) -> Result<Vec<BackupManifest>> {
+        let mut manifests: Vec<BackupManifest> = self.load_manifests().await?
+            .into_iter()
+            .filter_map(|(backup_id, manifest)| {
+                manifest.map_err(|e| tracing::warn!("Skipping backup {}: {}", backup_id, e)).ok()
+            })
+            .collect();
+        
+        // Sort by timestamp (newest first)
+        manifests.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        
+        Ok(manifests)
+    }
+    
+    /// Like [`Self::list_backups`], but failing if any manifest can't be
+    /// loaded instead of skipping it
+    async fn list_backups_strict(&self) -> Result<Vec<BackupManifest>> {
+        self.load_manifests().await?
+            .into_iter()
+            .map(|(backup_id, manifest)| manifest.map_err(|e| SkylockError::Backup(format!(
+                "Failed to load the manifest of backup {}: {}", backup_id, e
+            ))))
+            .collect()
+    }
+    
+    /// Every backup ID on the storage box with the result of loading its
+    /// manifest
+    async fn load_manifests(&self) -> Result<Vec<(String, Result<BackupManifest>)>> {
         let files = self.hetzner.list_files("/skylock/backups").await?;
-        let mut manifests = Vec::new();
         
+        // Encrypted manifest (v3+) where there is one, else the legacy
+        // plaintext manifest
+        let mut sources: Vec<(String, Option<PathBuf>)> = Vec::new();
         for file in &files {
             let file_name = file.path.file_name()
                 .and_then(|n| n.to_str())
                 .unwrap_or("");
+            let parent_dir = file.path.parent().unwrap_or(Path::new(""));
+            let backup_id = parent_dir.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("")
+                .to_string();
             
-            // Try encrypted manifest first (v3+)
             if file_name == "manifest.json.enc" {
-                let backup_id = file.path.parent()
-                    .and_then(|p| p.file_name())
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("");
-                
-                if let Ok(manifest) = self.download_encrypted_manifest(backup_id).await {
-                    manifests.push(manifest);
-                }
-            }
-            // Fallback to legacy plaintext manifest
-            else if file_name == "manifest.json" {
-                // Skip if we already have encrypted version (parent dir check)
-                let parent_dir = file.path.parent().unwrap_or(Path::new(""));
+                sources.push((backup_id, None));
+            } else if file_name == "manifest.json" {
                 let encrypted_exists = files.iter().any(|f| {
                     f.path.parent() == Some(parent_dir) && 
                     f.path.file_name().and_then(|n| n.to_str()) == Some("manifest.json.enc")
                 });
-                
                 if !encrypted_exists {
-                    if let Ok(manifest) = self.download_manifest_legacy(&file.path).await {
-                        manifests.push(manifest);
-                    }
+                    sources.push((backup_id, Some(file.path.clone())));
                 }
             }
         }
         
-        // Sort by timestamp (newest first)
-        manifests.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-        
-        Ok(manifests)
+        Ok(futures::stream::iter(sources)
+            .map(|(backup_id, legacy_path)| async move {
+                let manifest = match &legacy_path {
+                    None => self.download_encrypted_manifest(&backup_id).await,
+                    Some(path) => self.download_manifest_legacy(path).await,
+                };
+                (backup_id, manifest)
+            })
+            .buffer_unordered(self.list_concurrency)
+            .collect()
+            .await)
     }
 
-    /// Download and decrypt encrypted manifest (v3+ format)
-    async fn download_encrypted_manifest(&self, backup_id: &str) -> Result<BackupManifest> {
-        use crate::encrypted_manifest::ManifestEncryption;
+    /// List all backups from their summaries, newest first
+    /// 
+    /// Only the small encrypted summary of each backup is downloaded, up to
+    /// `list_concurrency` at once; the full manifest is only fetched for
+    /// backups written before summaries existed. A listing made within the
+    /// cache TTL, by this or a previous command, is reused.
+    pub async fn list_backup_summaries(&self) -> Result<Vec<ManifestSummary>> {
+        let destination = self.lock_destination();
+        if let Some(cache) = &self.listing_cache {
+            if let Some(summaries) = cache.load(&destination).await {
+                return Ok(summaries);
+            }
+        }
         
-        let encrypted_path = PathBuf::from(format!(
-            "/skylock/backups/{}/manifest.json.enc", backup_id
-        ));
+        let files = self.hetzner.list_files("/skylock/backups").await?;
+        let has_file = |backup_id: &str, name: &str| files.iter().any(|f| {
+            f.path.file_name().and_then(|n| n.to_str()) == Some(name)
+                && f.path.parent().and_then(|p| p.file_name()).and_then(|n| n.to_str()) == Some(backup_id)
+        });
         
-        let temp_file = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Temp file failed: {}", e)))?;
+        let mut backup_ids: Vec<&str> = files.iter()
+            .filter(|f| matches!(
+                f.path.file_name().and_then(|n| n.to_str()),
+                Some("manifest.json.enc" | "manifest.json")
+            ))
+            .filter_map(|f| f.path.parent()?.file_name()?.to_str())
+            .collect();
+        backup_ids.sort_unstable();
+        backup_ids.dedup();
         
-        self.hetzner.download_file(&encrypted_path, &temp_file.path().to_path_buf()).await?;
+        let sources: Vec<(&str, bool, bool)> = backup_ids.into_iter()
+            .map(|id| (id, has_file(id, "manifest_summary.json.enc"), has_file(id, "manifest.json.enc")))
+            .collect();
+        let mut summaries: Vec<ManifestSummary> = futures::stream::iter(sources)
+            .map(|(backup_id, has_summary, has_encrypted)| async move {
+                let summary = if has_summary {
+                    self.download_summary(backup_id).await
+                } else if has_encrypted {
+                    self.download_encrypted_manifest(backup_id).await
+                        .map(|manifest| ManifestSummary::from_manifest(&manifest))
+                } else {
+                    let legacy_path = PathBuf::from(format!("/skylock/backups/{}/manifest.json", backup_id));
+                    self.download_manifest_legacy(&legacy_path).await
+                        .map(|manifest| ManifestSummary::from_manifest(&manifest))
+                };
+                summary.map_err(|e| tracing::warn!("Skipping backup {}: {}", backup_id, e)).ok()
+            })
+            .buffer_unordered(self.list_concurrency)
+            .filter_map(|summary| async move { summary })
+            .collect()
+            .await;
+        
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.timestamp));
+        if let Some(cache) = &self.listing_cache {
+            cache.store(&destination, &summaries).await;
+        }
+        Ok(summaries)
+    }
+    
+    /// Every version of the files matching `glob` in the backups `include`
+    /// accepts, decided from their summaries
+    /// 
+    /// Manifests are downloaded and scanned one at a time, oldest first and
+    /// with the bases of incremental backups, so memory holds a single
+    /// manifest besides the matches. Backups whose manifest can't be read
+    /// are skipped and listed in the result.
+    pub async fn find_files(&self, glob: PathGlob, include: impl Fn(&ManifestSummary) -> bool) -> Result<FindResult> {
+        let summaries = self.list_backup_summaries().await?;
+        let mut finder = FileFinder::new(glob);
+        let mut skipped = Vec::new();
+        for (summary, included) in find::scan_order(&summaries, include) {
+            match self.download_manifest(&summary.backup_id).await {
+                Ok(manifest) => finder.scan(&manifest, included),
+                Err(e) => {
+                    tracing::warn!("Skipping backup {}: {}", summary.backup_id, e);
+                    skipped.push(SkippedBackup { backup_id: summary.backup_id.clone(), error: e.to_string() });
+                }
+            }
+        }
+        Ok(FindResult { files: finder.finish(), skipped })
+    }
+    
+    /// Download and decrypt the listing summary of a backup
+    async fn download_summary(&self, backup_id: &str) -> Result<ManifestSummary> {
+        use crate::encrypted_manifest::ManifestEncryption;
         
-        let encrypted_data = tokio::fs::read(temp_file.path()).await?;
+        let summary_path = PathBuf::from(format!(
+            "/skylock/backups/{}/manifest_summary.json.enc", backup_id
+        ));
+        let encrypted_data = self.temp.download_bytes(&self.hetzner, &summary_path).await?;
         
-        // Decrypt the manifest
-        let manifest_encryption = ManifestEncryption::new(&self.encryption);
-        let manifest = manifest_encryption.decrypt_manifest(&encrypted_data, backup_id)?;
+        let (algorithm, key_version) = self.download_manifest_header(backup_id).await?
+            .map(|header| (header.aead_algorithm, header.key_version))
+            .unwrap_or_default();
+        let encryption = self.encryption_for(key_version, algorithm)?;
+        ManifestEncryption::new(&encryption).decrypt_summary(&encrypted_data, backup_id)
+    }
+    
+    /// Download and decrypt encrypted manifest (v3+ format)
+    async fn download_encrypted_manifest(&self, backup_id: &str) -> Result<BackupManifest> {
+        self.download_encrypted_manifest_in(&format!("/skylock/backups/{}", backup_id), backup_id).await
+    }
+    
+    /// Download and decrypt the encrypted manifest kept in `dir`, which is
+    /// the backup's own directory unless it is in the trash
+    async fn download_encrypted_manifest_in(&self, dir: &str, backup_id: &str) -> Result<BackupManifest> {
+        BackupManifest::from_json(&self.download_encrypted_manifest_json_in(dir, backup_id).await?)
+    }
+    
+    /// Download and decrypt the encrypted manifest kept in `dir` to its
+    /// stored JSON
+    async fn download_encrypted_manifest_json_in(&self, dir: &str, backup_id: &str) -> Result<Vec<u8>> {
+        use crate::encrypted_manifest::ManifestEncryption;
         
-        Ok(manifest)
+        let encrypted_path = PathBuf::from(format!("{}/manifest.json.enc", dir));
+        
+        // Checked before decryption, so a damaged transfer is reported as
+        // such rather than as a wrong key
+        let encrypted_data = manifest_checksum::download_verified(&self.hetzner, &self.temp, &encrypted_path).await?;
+        
+        // The manifest is encrypted with the backup's own algorithm and key
+        // version, which may differ from the ones currently configured
+        let header = self.download_manifest_header_in(dir).await?;
+        let (algorithm, key_version) = header.as_ref()
+            .map(|header| (header.aead_algorithm, header.key_version))
+            .unwrap_or_default();
+        let encryption = self.encryption_for(key_version, algorithm)?;
+        
+        // Decrypt the manifest, telling a wrong key apart from damaged data
+        // when the header records the key's fingerprint
+        let manifest_encryption = ManifestEncryption::new(&encryption);
+        match header {
+            Some(header) => manifest_encryption.decrypt_manifest_json_checked(&encrypted_data, backup_id, &header),
+            None => manifest_encryption.decrypt_manifest_json(&encrypted_data, backup_id),
+        }
+    }
+    
+    /// Download the public manifest header (v3+), if present
+    async fn download_manifest_header(&self, backup_id: &str) -> Result<Option<crate::encrypted_manifest::ManifestHeader>> {
+        self.download_manifest_header_in(&format!("/skylock/backups/{}", backup_id)).await
+    }
+    
+    /// Download the public manifest header kept in `dir`, if present
+    /// 
+    /// Only a missing header means the backup predates it; a header that
+    /// fails to download or parse is an error, since guessing the cipher
+    /// would report the wrong cause when decryption fails.
+    async fn download_manifest_header_in(&self, dir: &str) -> Result<Option<crate::encrypted_manifest::ManifestHeader>> {
+        let header_path = PathBuf::from(format!("{}/manifest_header.json", dir));
+        
+        let json = match self.temp.download_bytes(&self.hetzner, &header_path).await {
+            Ok(json) => json,
+            Err(e) if e.is_not_found() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_slice(&json).map(Some).map_err(|e| SkylockError::Backup(format!(
+            "Invalid manifest header {}: {}", header_path.display(), e
+        )))
     }
     
     /// Download legacy plaintext manifest
     async fn download_manifest_legacy(&self, path: &Path) -> Result<BackupManifest> {
-        let temp_file = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Temp file failed: {}", e)))?;
-        
-        self.hetzner.download_file(path, &temp_file.path().to_path_buf()).await?;
-        
-        let json = tokio::fs::read_to_string(temp_file.path()).await?;
-        let manifest: BackupManifest = serde_json::from_str(&json)
-            .map_err(|e| SkylockError::Backup(format!("Parse manifest failed: {}", e)))?;
-        
-        Ok(manifest)
+        let json = manifest_checksum::download_verified(&self.hetzner, &self.temp, path).await?;
+        BackupManifest::from_json(&json)
     }
     
     /// Download and parse manifest - auto-detects format
+    /// 
+    /// Refused unless its signature satisfies the signature policy.
     async fn download_manifest(&self, backup_id: &str) -> Result<BackupManifest> {
-        // Try encrypted manifest first (v3+)
-        let encrypted_path = PathBuf::from(format!(
-            "/skylock/backups/{}/manifest.json.enc", backup_id
-        ));
-        
-        if self.file_exists(&encrypted_path).await {
-            return self.download_encrypted_manifest(backup_id).await;
-        }
-        
-        // Fallback to legacy plaintext
-        let legacy_path = PathBuf::from(format!(
-            "/skylock/backups/{}/manifest.json", backup_id
-        ));
-        self.download_manifest_legacy(&legacy_path).await
+        let json = self.download_manifest_json(backup_id).await?;
+        let manifest = BackupManifest::from_json(&json)?;
+        self.signature_policy.check(&manifest, &json)?;
+        Ok(manifest)
     }
     
-    /// Check if a remote file exists
-    async fn file_exists(&self, path: &Path) -> bool {
-        let temp_file = match tempfile::NamedTempFile::new() {
-            Ok(f) => f,
-            Err(_) => return false,
-        };
-        
-        self.hetzner.download_file(path, &temp_file.path().to_path_buf()).await.is_ok()
+    /// Download and parse manifest without checking its signature
+    async fn download_manifest_unchecked(&self, backup_id: &str) -> Result<BackupManifest> {
+        BackupManifest::from_json(&self.download_manifest_json(backup_id).await?)
+    }
+    
+    /// Download a manifest as the JSON it was stored as
+    async fn download_manifest_json(&self, backup_id: &str) -> Result<Vec<u8>> {
+        // Try encrypted manifest first (v3+), falling back to legacy
+        // plaintext only if there is none
+        let dir = format!("/skylock/backups/{}", backup_id);
+        match self.download_encrypted_manifest_json_in(&dir, backup_id).await {
+            Err(e) if e.is_not_found() => {
+                let legacy_path = PathBuf::from(format!("{}/manifest.json", dir));
+                manifest_checksum::download_verified(&self.hetzner, &self.temp, &legacy_path).await
+            }
+            json => json,
+        }
     }
     
     /// Load a backup manifest by ID (public API for comparison)
@@ -1025,27 +3555,126 @@ impl DirectUploadBackup {
     pub async fn load_manifest(&self, backup_id: &str) -> Result<BackupManifest> {
         self.download_manifest(backup_id).await
     }
+    
+    /// Sign the manifest of an existing backup with the manifest signing key
+    /// and upload it again, e.g. one made before the key was generated
+    /// 
+    /// The manifest is taken as it is stored, so only sign backups you trust.
+    pub async fn sign_backup_manifest(&self, backup_id: &str) -> Result<BackupManifest> {
+        let key = self.manifest_signing_key.as_ref().ok_or_else(|| SkylockError::Crypto(
+            "No manifest signing key; create one with 'skylock generate-manifest-key'".to_string()
+        ))?;
+        
+        let mut manifest = self.download_manifest_unchecked(backup_id).await?;
+        let chain_version = manifest.backup_chain_version;
+        sign_manifest(&mut manifest, key, chain_version)?;
+        let encryption = self.encryption_for(manifest.key_version, manifest.aead_algorithm)?;
+        self.upload_manifest_with(&manifest, &encryption).await?;
+        Ok(manifest)
+    }
+    
+    /// Rebuild the local change tracking index from a backup's manifest
+    /// 
+    /// Uses `backup_id`, or the newest backup if `None`, so incremental
+    /// backups work again after the index in the data directory was lost.
+    /// Files whose size and modification time still match the backup are
+    /// taken as unchanged; the others are hashed locally. Files that still
+    /// match are indexed with their current timestamp and skipped by the next
+    /// incremental backup, while files that differ or are gone keep the
+    /// backup's size, hash and upload time, so they are detected as modified
+    /// or removed.
+    pub async fn rebuild_index(&self, backup_id: Option<&str>) -> Result<ReindexStats> {
+        let backup_id = match backup_id {
+            Some(id) => id.to_string(),
+            None => self.list_backup_summaries().await?
+                .into_iter()
+                .next()
+                .map(|summary| summary.backup_id)
+                .ok_or_else(|| SkylockError::Backup("No backups found to rebuild the index from".to_string()))?,
+        };
+        let chain = self.load_chain(&backup_id).await?;
+        let tip = &chain[chain.len() - 1];
+        
+        let mut stats = ReindexStats { backup_id: backup_id.clone(), ..Default::default() };
+        let mut index = FileIndex::new(tip.source_paths.clone());
+        index.backup_id = Some(backup_id.clone());
+        index.hash_algorithm = tip.hash_algorithm;
+        
+        for (entry, _) in Self::merge_chain(&chain) {
+            let recorded = FileInfo {
+                path: entry.local_path.clone(),
+                size: entry.size,
+                modified: entry.timestamp,
+                hash: Some(entry.hash.clone()),
+            };
+            let metadata = match tokio::fs::metadata(&entry.local_path).await {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => {
+                    stats.missing += 1;
+                    index.insert(recorded);
+                    continue;
+                }
+            };
+            
+            let unchanged_time = entry.modified.is_some()
+                && metadata.modified().ok().map(DateTime::<Utc>::from) == entry.modified;
+            let matches = metadata.len() == entry.size
+                && (unchanged_time
+                    || Self::calculate_hash(&entry.local_path, tip.hash_algorithm).await.ok().as_deref() == Some(entry.hash.as_str()));
+            match metadata.modified() {
+                Ok(modified) if matches => {
+                    stats.verified += 1;
+                    index.insert(FileInfo { modified: DateTime::<Utc>::from(modified), ..recorded });
+                }
+                _ => {
+                    stats.changed += 1;
+                    index.insert(recorded);
+                }
+            }
+        }
+        
+        let tracker = ChangeTracker::new(self.config.data_dir.join("indexes"));
+        tracker.save_index(&backup_id, &index).await?;
+        Ok(stats)
+    }
 
     /// Restore entire backup with progress tracking
+    #[tracing::instrument(name = "restore", skip_all, fields(backup_id = backup_id, files = Empty))]
     pub async fn restore_backup(&self, backup_id: &str, target_dir: &Path) -> Result<()> {
         use indicatif::{ProgressBar, ProgressStyle, MultiProgress};
         use std::time::Duration;
         
         println!("🔄 Restoring backup: {}", backup_id);
         println!();
+        let _retry_budget = self.retrier.begin_operation(RESTORE_OPERATION);
         
-        // Download manifest (auto-detects encrypted vs legacy format)
-        let manifest = self.download_manifest(backup_id).await?;
+        // Download manifests (auto-detects encrypted vs legacy format); an
+        // incremental backup needs every backup it builds on
+        let chain = self.load_chain(backup_id).await?;
+        let manifest = &chain[chain.len() - 1];
+        let mut files = Self::merge_chain(&chain);
+        tracing::Span::current().record("files", files.len());
         
-        println!("   📦 Files to restore: {}", manifest.files.len());
-        println!("   📊 Total size: {} bytes", manifest.total_size);
+        if chain.len() > 1 {
+            println!("   🔗 Incremental chain of {} backups", chain.len());
+        }
+        println!("   📦 Files to restore: {}", files.len());
+        println!("   📊 Total size: {} bytes", files.iter().map(|(entry, _)| entry.size).sum::<u64>());
         println!("   📅 Backup date: {}", manifest.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
+        if let Some(multipart) = &self.multipart_download {
+            println!("   📁 Using {}-way parallel downloads for large files", multipart.parts);
+        }
+        for mapping in self.path_map.mappings() {
+            println!("   🔀 Restoring {} as {}", mapping.from().display(), mapping.to().display());
+        }
         println!();
         
+        self.check_restore_targets(&mut files, &manifest.directories, target_dir).await?;
+        
         // Create progress bars
         let multi = MultiProgress::new();
         
-        let overall_pb = multi.add(ProgressBar::new(manifest.files.len() as u64));
+        let overall_pb = multi.add(ProgressBar::new(files.len() as u64));
         overall_pb.set_style(
             ProgressStyle::default_bar()
                 .template("{msg}\n{bar:40.cyan/blue} {pos}/{len} files ({percent}%) ETA: {eta}")
@@ -1076,12 +3705,13 @@ impl DirectUploadBackup {
         }
         
         // Restore files with progress
-        for entry in &manifest.files {
+        for (entry, source) in files {
             file_pb.set_message(format!("⬇️  Restoring: {}", entry.local_path.display()));
             file_pb.set_length(entry.size);
             file_pb.set_position(0);
             
-            match self.restore_single_file_with_progress(entry, target_dir, &manifest, file_pb.clone()).await {
+            let file_span = tracing::info_span!("file", path = %entry.local_path.display(), size = entry.size);
+            match self.restore_single_file_with_progress(entry, target_dir, source, file_pb.clone()).instrument(file_span).await {
                 Ok(_) => {
                     restored_count += 1;
                     overall_pb.inc(1);
@@ -1096,6 +3726,10 @@ impl DirectUploadBackup {
             file_pb.finish_and_clear();
         }
         
+        // Directories last, so writing their files doesn't change the
+        // restored timestamps
+        Self::restore_directories(&manifest.directories, target_dir, &self.path_map).await?;
+        
         overall_pb.finish_with_message(format!(
             "✅ Restore complete: {} files restored, {} failed",
             restored_count,
@@ -1111,6 +3745,187 @@ impl DirectUploadBackup {
         Ok(())
     }
 
+    /// Restore the files and directories of a backup below any of `prefixes`
+    /// 
+    /// Only the blobs of matching files are downloaded, up to
+    /// `max_concurrent_uploads` files at once, each once even if the
+    /// prefixes overlap. They are restored where a full restore would put
+    /// them below `target_dir`, after path mapping, with the same conflict
+    /// and free-space checks over all of them. A prefix matches whole path
+    /// components, and a relative prefix is taken from the root, so
+    /// `home/alice/src` selects `/home/alice/src` but not `/home/alice/src2`.
+    /// Fails if a prefix selects nothing or contains `.`, `..` or a drive.
+    #[tracing::instrument(name = "restore_subtree", skip_all, fields(backup_id = backup_id, prefixes = prefixes.len(), files = Empty))]
+    pub async fn restore_subtree(&self, backup_id: &str, prefixes: &[PathBuf], target_dir: &Path) -> Result<()> {
+        let subtrees = prefixes.iter()
+            .map(|prefix| Self::subtree_components(prefix))
+            .collect::<Result<Vec<_>>>()?;
+        let listed: Vec<String> = prefixes.iter().map(|prefix| prefix.display().to_string()).collect();
+        println!("🔄 Restoring {} from backup: {}", listed.join(", "), backup_id);
+        println!();
+        let _retry_budget = self.retrier.begin_operation(RESTORE_OPERATION);
+        
+        let chain = self.load_chain(backup_id).await?;
+        let manifest = &chain[chain.len() - 1];
+        let merged = Self::merge_chain(&chain);
+        for (prefix, subtree) in prefixes.iter().zip(&subtrees) {
+            let selects = |path: &Path| Self::in_subtree(path, subtree);
+            if !merged.iter().any(|(entry, _)| selects(&entry.local_path))
+                && !manifest.directories.iter().any(|directory| selects(&directory.path))
+            {
+                return Err(SkylockError::Backup(format!(
+                    "Nothing below {} in backup {}",
+                    prefix.display(),
+                    backup_id
+                )));
+            }
+        }
+        let selected = |path: &Path| subtrees.iter().any(|subtree| Self::in_subtree(path, subtree));
+        let mut files: Vec<_> = merged.into_iter()
+            .filter(|(entry, _)| selected(&entry.local_path))
+            .collect();
+        let directories: Vec<DirectoryEntry> = manifest.directories.iter()
+            .filter(|directory| selected(&directory.path))
+            .cloned()
+            .collect();
+        tracing::Span::current().record("files", files.len());
+        
+        println!("   📦 Files to restore: {}", files.len());
+        println!("   📊 Total size: {} bytes", files.iter().map(|(entry, _)| entry.size).sum::<u64>());
+        println!("   📅 Backup date: {}", manifest.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
+        println!();
+        
+        self.check_restore_targets(&mut files, &directories, target_dir).await?;
+        
+        let overall_pb = ProgressBar::new(files.len() as u64);
+        overall_pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{msg}\n{bar:40.cyan/blue} {pos}/{len} files ({percent}%) ETA: {eta}")
+                .unwrap()
+                .progress_chars("█▓▒░ ")
+        );
+        overall_pb.set_message("📦 Overall Progress");
+        
+        // Files are independent, so several download at once; per-file bars
+        // would interleave, so only the overall one is drawn
+        let total = files.len();
+        let overall = &overall_pb;
+        let results: Vec<bool> = futures::stream::iter(files)
+            .map(|(entry, source)| async move {
+                let file_span = tracing::info_span!("file", path = %entry.local_path.display(), size = entry.size);
+                let result = self.restore_single_file_with_progress(entry, target_dir, source, ProgressBar::hidden())
+                    .instrument(file_span)
+                    .await;
+                if let Err(e) = &result {
+                    overall.println(format!("⚠️  Failed to restore {}: {}", entry.local_path.display(), e));
+                }
+                overall.inc(1);
+                result.is_ok()
+            })
+            .buffer_unordered(self.max_parallel)
+            .collect()
+            .await;
+        let failed_count = results.iter().filter(|restored| !**restored).count();
+        
+        Self::restore_directories(&directories, target_dir, &self.path_map).await?;
+        
+        overall_pb.finish_with_message(format!(
+            "✅ Restore complete: {} files restored, {} failed",
+            total - failed_count,
+            failed_count
+        ));
+        println!();
+        
+        if failed_count > 0 {
+            return Err(SkylockError::Backup(format!("{} files failed to restore", failed_count)));
+        }
+        
+        Ok(())
+    }
+    
+    /// The components of a subtree prefix below the root
+    /// 
+    /// `.`, `..` and Windows drive prefixes are refused rather than
+    /// skipped, which would select a different subtree than the one asked
+    /// for.
+    fn subtree_components(prefix: &Path) -> Result<Vec<Component<'_>>> {
+        prefix.components()
+            .filter(|component| !matches!(component, Component::RootDir))
+            .map(|component| match component {
+                Component::Normal(_) => Ok(component),
+                _ => Err(SkylockError::Backup(format!(
+                    "Invalid restore path {}: use plain names without '.', '..' or a drive",
+                    prefix.display()
+                ))),
+            })
+            .collect()
+    }
+    
+    /// Whether `path` is the subtree from [`Self::subtree_components`] or
+    /// lies below it, comparing whole components and ignoring the root
+    fn in_subtree(path: &Path, subtree: &[Component]) -> bool {
+        let mut parts = path.components().filter(|component| matches!(component, Component::Normal(_)));
+        subtree.iter().all(|part| parts.next() == Some(*part))
+    }
+    
+    /// Check where the files and directories of a restore would go before
+    /// anything is downloaded
+    /// 
+    /// Fails if a remapped path escapes `target_dir`, if files already exist
+    /// there and the restore policy is to abort, or if a target filesystem
+    /// can't take the files. Existing files are dropped from `files` when the
+    /// policy is to skip them.
+    async fn check_restore_targets(
+        &self,
+        files: &mut Vec<(&FileEntry, &BackupManifest)>,
+        directories: &[DirectoryEntry],
+        target_dir: &Path,
+    ) -> Result<()> {
+        // Check every remapped path before writing anything, so a mapping
+        // that escapes the target fails the restore instead of single files
+        let paths = files.iter().map(|(entry, _)| &entry.local_path)
+            .chain(directories.iter().map(|directory| &directory.path));
+        for path in paths {
+            Self::restore_target(target_dir, &self.path_map.apply(path))?;
+        }
+        
+        // Files already at the target are only replaced when asked to
+        let conflicts = self.existing_targets(files, target_dir).await?;
+        if !conflicts.is_empty() {
+            match self.restore_policy {
+                RestorePolicy::Abort => return Err(SkylockError::RestoreConflict(conflicts)),
+                RestorePolicy::Overwrite => {
+                    println!("   ✏️  Overwriting {} existing files", conflicts.len());
+                }
+                RestorePolicy::SkipExisting => {
+                    println!("   ⏭️  Skipping {} existing files", conflicts.len());
+                    let conflicts: std::collections::HashSet<_> = conflicts.into_iter().collect();
+                    files.retain(|(entry, _)| {
+                        Self::restore_target(target_dir, &self.path_map.apply(&entry.local_path))
+                            .map_or(true, |target| !conflicts.contains(&target))
+                    });
+                }
+            }
+            println!();
+        }
+        
+        // Refuse to start when a target filesystem can't take the files
+        if let Some(probe) = &self.space_probe {
+            let targets = files.iter()
+                .map(|(entry, _)| Ok((Self::restore_target(target_dir, &self.path_map.apply(&entry.local_path))?, entry.size)))
+                .collect::<Result<Vec<_>>>()?;
+            for requirement in restore_space::check_space(targets, probe)? {
+                println!(
+                    "   💾 {} bytes to {} ({} bytes free)",
+                    requirement.required, requirement.probe_path.display(), requirement.available
+                );
+            }
+            println!();
+        }
+        
+        Ok(())
+    }
+    
     /// Restore a single file with progress and integrity verification
     async fn restore_single_file_with_progress(
         &self,
@@ -1119,108 +3934,605 @@ impl DirectUploadBackup {
         manifest: &BackupManifest,
         progress: ProgressBar,
     ) -> Result<()> {
-        // Download encrypted file
-        let temp_encrypted = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Temp file failed: {}", e)))?;
-        
-        self.hetzner.download_file(
-            &PathBuf::from(&entry.remote_path),
-            &temp_encrypted.path().to_path_buf()
-        ).await?;
-        progress.set_position(entry.size / 3); // 33% for download
+        // Streamed files go to disk a segment at a time
+        if entry.is_streamed() {
+            let target_path = Self::restore_target(target_dir, &self.path_map.apply(&entry.local_path))?;
+            if let Some(parent) = target_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // A failed or cancelled restore leaves no partial file behind
+            let partial = CleanupGuard::new(&target_path);
+            let output = progress.wrap_write(std::io::BufWriter::new(std::fs::File::create(&target_path)?));
+            self.fetch_streamed(entry, manifest, output).await?;
+            partial.keep();
+            Self::apply_restored_metadata(entry, &target_path, self.preserve_xattrs);
+            progress.set_position(entry.size);
+            return Ok(());
+        }
         
-        // Read and decrypt with version-aware decryption
-        let encrypted_data = tokio::fs::read(temp_encrypted.path()).await?;
+        let final_data = self.fetch_file_data(entry, manifest, &progress).await?;
         
-        // Detect encryption version from manifest
-        let is_v2 = manifest.encryption_version == "v2" && manifest.kdf_params.is_some();
+        // Write to target
+        Self::write_restored_file(entry, target_dir, &self.path_map, &final_data, self.preserve_xattrs).await?;
+        progress.set_position(entry.size); // 100% complete
         
-        let decrypted_data = if is_v2 {
-            // v2: Use AAD-bound decryption
-            let file_path_str = entry.local_path.to_string_lossy();
-            self.encryption.decrypt_with_aad(
-                &encrypted_data,
-                &manifest.backup_id,
-                &file_path_str
-            )?
-        } else {
-            // v1: Use legacy decryption (no AAD)
-            self.encryption.decrypt(&encrypted_data)?
-        };
-        progress.set_position(entry.size * 2 / 3); // 66% for decryption
+        Ok(())
+    }
+    
+    /// Download, decrypt and decompress a backed-up file, verifying its hash
+    /// 
+    /// Blobs already in the block cache are neither downloaded nor
+    /// decrypted again.
+    pub(crate) async fn fetch_file_data(
+        &self,
+        entry: &FileEntry,
+        manifest: &BackupManifest,
+        progress: &ProgressBar,
+    ) -> Result<Vec<u8>> {
+        let cache = self.block_cache.as_deref();
+        let whole_blob = !entry.is_chunked() && !entry.is_streamed();
+        if whole_blob {
+            if let Some(data) = cache.and_then(|cache| cache.get(&entry.remote_path)) {
+                progress.set_position(entry.size);
+                return Ok(data.to_vec());
+            }
+        }
         
-        // Decompress if needed
-        let final_data = if entry.compressed {
-            zstd::decode_all(decrypted_data.as_slice())
-                .map_err(|e| SkylockError::Backup(format!("Decompression failed: {}", e)))?
+        let encryption = self.encryption_for(manifest.key_version, manifest.aead_algorithm)?;
+        let final_data = if entry.is_chunked() {
+            Self::fetch_chunks(&self.hetzner, &self.temp, &encryption, manifest, entry, cache).await
+                .map_err(|e| Self::cold_storage_hint(e, manifest))?
+        } else if entry.is_streamed() {
+            // Whole restores write streamed files to disk instead
+            self.fetch_streamed(entry, manifest, Vec::new()).await?
         } else {
-            decrypted_data
+            // Download encrypted file
+            let temp_encrypted = self.temp.file("download")?;
+            
+            self.download_object(entry, temp_encrypted.path())
+                .instrument(tracing::info_span!("download", remote_path = %entry.remote_path))
+                .await
+                .map_err(|e| Self::cold_storage_hint(e, manifest))?;
+            progress.set_position(entry.size / 3); // 33% for download
+            
+            // Read and decrypt with version-aware decryption
+            let encrypted_data = tokio::fs::read(temp_encrypted.path()).await?;
+            let decrypted_data = tracing::info_span!("decrypt", bytes = encrypted_data.len())
+                .in_scope(|| Self::decrypt_file_data(&encryption, manifest, entry, &encrypted_data))?;
+            progress.set_position(entry.size * 2 / 3); // 66% for decryption
+            
+            // Decompress with the algorithm recorded for this file
+            tracing::info_span!("decompress", bytes = decrypted_data.len())
+                .in_scope(|| entry.decompress(decrypted_data))?
         };
         
         // Verify integrity by comparing hash
-        let mut hasher = Sha256::new();
-        hasher.update(&final_data);
-        let restored_hash = format!("{:x}", hasher.finalize());
+        let restored_hash = manifest.hash_algorithm.hash(&final_data);
         
         if restored_hash != entry.hash {
-            return Err(SkylockError::Backup(format!(
-                "Integrity check failed for {}: hash mismatch (expected {}, got {})",
+            return Err(SkylockError::Integrity(format!(
+                "{}: hash mismatch (expected {}, got {})",
                 entry.local_path.display(),
                 entry.hash,
                 restored_hash
             )));
         }
         
-        // Write to target
-        let target_path = target_dir.join(
-            entry.local_path.strip_prefix("/").unwrap_or(&entry.local_path)
-        );
-        
-        if let Some(parent) = target_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
+        if let Some(cache) = cache.filter(|_| whole_blob) {
+            cache.insert(&entry.remote_path, &final_data);
         }
         
-        tokio::fs::write(&target_path, final_data).await?;
-        progress.set_position(entry.size); // 100% complete
-        
-        Ok(())
+        Ok(final_data)
     }
     
-    /// Restore a single file (legacy without progress)
-    async fn restore_single_file(&self, entry: &FileEntry, target_dir: &Path, manifest: &BackupManifest) -> Result<()> {
-        use indicatif::{ProgressBar, ProgressStyle};
-        
-        let pb = ProgressBar::new(entry.size);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} {msg} {bar:40.green/blue} {percent}%")
-                .unwrap()
-                .progress_chars("█▓▒░ ")
+    /// Download a streamed file's blob into a temp file, with the key and
+    /// backup ID its segments were encrypted for
+    async fn download_streamed(
+        &self,
+        entry: &FileEntry,
+        manifest: &BackupManifest,
+    ) -> Result<(tempfile::NamedTempFile, Arc<EncryptionManager>, String)> {
+        let encryption = self.encryption_for(manifest.key_version, manifest.aead_algorithm)?;
+        let (encryption, backup_id) = Self::stream_decryption(&encryption, manifest, entry);
+        let blob = self.temp.file("download")?;
+        self.download_object(entry, blob.path()).await
+            .map_err(|e| Self::cold_storage_hint(e, manifest))?;
+        Ok((blob, encryption, backup_id))
+    }
+    
+    /// Download a streamed file and decode it into `output` on a blocking
+    /// thread, checking its size and hash
+    async fn fetch_streamed<W: std::io::Write + Send + 'static>(
+        &self,
+        entry: &FileEntry,
+        manifest: &BackupManifest,
+        output: W,
+    ) -> Result<W> {
+        let (blob, encryption, backup_id) = self.download_streamed(entry, manifest).await?;
+        let blob = std::io::BufReader::new(std::fs::File::open(blob.path())?);
+        let entry = entry.clone();
+        let algorithm = manifest.hash_algorithm;
+        tokio::task::spawn_blocking(move || Self::decode_streamed(encryption, &backup_id, &entry, algorithm, blob, output))
+            .await
+            .map_err(|e| SkylockError::Backup(format!("Restore task failed: {}", e)))?
+    }
+    
+    /// Key and backup ID a streamed file's segments were encrypted for: the
+    /// file's subkey, and the original backup for a blob reused after a move
+    pub(crate) fn stream_decryption(
+        encryption: &EncryptionManager,
+        manifest: &BackupManifest,
+        entry: &FileEntry,
+    ) -> (Arc<EncryptionManager>, String) {
+        let encryption = blob_encryption(
+            &encryption.for_algorithm(manifest.aead_algorithm),
+            entry.key_context.as_deref(),
         );
-        pb.set_message(format!("Restoring {}", entry.local_path.display()));
-        
-        let result = self.restore_single_file_with_progress(entry, target_dir, manifest, pb.clone()).await;
-        pb.finish_and_clear();
-        result
+        let backup_id = match entry.blob_origin {
+            Some(ref origin) => origin.backup_id.clone(),
+            None => manifest.backup_id.clone(),
+        };
+        (Arc::new(encryption), backup_id)
     }
-
-    /// Preview backup contents before restoring
-    pub async fn preview_backup(&self, backup_id: &str) -> Result<()> {
-        // Download manifest (auto-detects encrypted vs legacy)
-        let manifest = self.download_manifest(backup_id).await?;
+    
+    /// Decrypt and decompress a streamed file's blob into `output`, failing
+    /// with an integrity error unless it has the size and hash recorded (the
+    /// hash in `algorithm`, the one of the manifest holding `entry`)
+    pub(crate) fn decode_streamed<R: std::io::Read, W: std::io::Write>(
+        encryption: Arc<EncryptionManager>,
+        backup_id: &str,
+        entry: &FileEntry,
+        algorithm: HashAlgorithm,
+        blob: R,
+        mut output: W,
+    ) -> Result<W> {
+        let compressed = entry.compression_algorithm() != CompressionAlgorithm::None;
+        let framing = entry.framing.unwrap_or_default();
+        let (hash, size) = file_stream::decode(blob, &mut output, encryption, backup_id, compressed, framing, algorithm)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => SkylockError::Integrity(
+                    format!("{}: {}", entry.local_path.display(), e)
+                ),
+                _ => e.into(),
+            })?;
+        if size != entry.size || hash != entry.hash {
+            return Err(SkylockError::Integrity(format!(
+                "{}: hash mismatch (expected {} bytes with hash {}, got {} bytes with hash {})",
+                entry.local_path.display(),
+                entry.size,
+                entry.hash,
+                size,
+                hash
+            )));
+        }
+        Ok(output)
+    }
+    
+    /// Download, decrypt and join the chunks of a chunked file, checking
+    /// each chunk's hash; chunks in `cache` are taken from there
+    pub(crate) async fn fetch_chunks(
+        hetzner: &HetznerClient,
+        temp: &TempFiles,
+        encryption: &EncryptionManager,
+        manifest: &BackupManifest,
+        entry: &FileEntry,
+        cache: Option<&BlockCache>,
+    ) -> Result<Vec<u8>> {
+        let encryption = encryption.for_algorithm(manifest.aead_algorithm);
+        let mut data = Vec::with_capacity(entry.size as usize);
+        
+        for (i, chunk) in entry.chunks.iter().enumerate() {
+            let name = format!("chunk {} of {}", i, entry.local_path.display());
+            data.extend_from_slice(&Self::fetch_chunk(hetzner, temp, &encryption, chunk, manifest.hash_algorithm, &name, cache).await?);
+        }
         
-        println!();
-        println!("{}", "==========================================================================");
-        println!("🔍 Backup Preview: {}", backup_id);
-        println!("{}", "==========================================================================");
-        println!();
+        Ok(data)
+    }
+    
+    /// Download and decrypt a single chunk, checking its hash in `algorithm`
+    ///
+    /// Each chunk is its own AEAD unit, so this needs none of the file's
+    /// other chunks.
+    async fn fetch_chunk(
+        hetzner: &HetznerClient,
+        temp: &TempFiles,
+        encryption: &EncryptionManager,
+        chunk: &ChunkEntry,
+        algorithm: HashAlgorithm,
+        name: &str,
+        cache: Option<&BlockCache>,
+    ) -> Result<Vec<u8>> {
+        if let Some(chunk_data) = cache.and_then(|cache| cache.get(&chunk.remote_path)) {
+            return Ok(chunk_data.to_vec());
+        }
+        let encrypted_data = temp.download_bytes(hetzner, Path::new(&chunk.remote_path)).await?;
+        let decrypted_data = blob_encryption(encryption, chunk.key_context.as_deref())
+            .decrypt_with_aad(&encrypted_data, &chunk.backup_id, &ChunkEntry::aad(&chunk.hash))?;
+        let chunk_data = decompress_blob(
+            decrypted_data,
+            chunk.compression.algorithm(),
+            Some(&chunk.compression),
+            &name,
+        )?;
+        if algorithm.hash(&chunk_data) != chunk.hash {
+            return Err(SkylockError::Integrity(format!("{}: hash mismatch", name)));
+        }
+        if let Some(cache) = cache {
+            cache.insert(&chunk.remote_path, &chunk_data);
+        }
+        Ok(chunk_data)
+    }
+    
+    /// Read the bytes in `range` of a backed-up file
+    ///
+    /// A chunked file only downloads the chunks overlapping the range, each
+    /// checked against its own hash, and an uncompressed streamed file only
+    /// the segments overlapping it, each authenticated on its own. Other
+    /// streamed files are decoded as a stream keeping just the range; the
+    /// rest are fetched and verified whole. The range is clamped to the
+    /// file size.
+    pub async fn fetch_file_range(
+        &self,
+        entry: &FileEntry,
+        manifest: &BackupManifest,
+        range: std::ops::Range<u64>,
+    ) -> Result<Vec<u8>> {
+        let end = range.end.min(entry.size);
+        let range = range.start.min(end)..end;
+        if entry.is_streamed() {
+            if range.is_empty() {
+                return Ok(Vec::new());
+            }
+            let segment_size = entry.framing.unwrap_or_default().segment_size as u64;
+            let segments = range.start / segment_size..(range.end - 1) / segment_size + 1;
+            if let Some(data) = self.fetch_segments(entry, manifest, segments.clone()).await? {
+                let start = (range.start - segments.start * segment_size) as usize;
+                return Ok(data[start..start + (range.end - range.start) as usize].to_vec());
+            }
+            return Ok(self.fetch_streamed(entry, manifest, RangeWriter::new(range)).await?.data);
+        }
+        if !entry.is_chunked() {
+            let data = self.fetch_file_data(entry, manifest, &ProgressBar::hidden()).await?;
+            return Ok(data[range.start as usize..range.end as usize].to_vec());
+        }
+        
+        let encryption = self.encryption_for(manifest.key_version, manifest.aead_algorithm)?
+            .for_algorithm(manifest.aead_algorithm);
+        let mut data = Vec::with_capacity((range.end - range.start) as usize);
+        for (offset, chunk) in entry.chunks_in_range(range.clone()) {
+            let name = format!("chunk at offset {} of {}", offset, entry.local_path.display());
+            let chunk_data = Self::fetch_chunk(&self.hetzner, &self.temp, &encryption, chunk, manifest.hash_algorithm, &name, self.block_cache.as_deref()).await
+                .map_err(|e| Self::cold_storage_hint(e, manifest))?;
+            let start = range.start.saturating_sub(offset) as usize;
+            let end = (range.end - offset).min(chunk.size) as usize;
+            data.extend_from_slice(&chunk_data[start..end]);
+        }
+        Ok(data)
+    }
+    
+    /// Read the last `lines` lines of a backed-up file
+    ///
+    /// A chunked file, or an uncompressed streamed one, is read backwards one
+    /// chunk or segment at a time until enough lines are found, so a large
+    /// log's tail doesn't download the rest of it. Other streamed files are
+    /// decoded as a stream keeping just the last lines.
+    pub async fn fetch_file_tail(
+        &self,
+        entry: &FileEntry,
+        manifest: &BackupManifest,
+        lines: usize,
+    ) -> Result<Vec<u8>> {
+        if entry.is_streamed() {
+            let segments = entry.framing.unwrap_or_default().segments_for(entry.size);
+            let mut data = Vec::new();
+            for index in (0..segments).rev() {
+                let Some(mut segment) = self.fetch_segments(entry, manifest, index..index + 1).await? else {
+                    return Ok(self.fetch_streamed(entry, manifest, TailWriter::new(lines)).await?.into_tail());
+                };
+                segment.extend_from_slice(&data);
+                data = segment;
+                
+                let body = data.strip_suffix(b"\n").unwrap_or(&data);
+                if body.iter().filter(|&&b| b == b'\n').count() >= lines {
+                    break;
+                }
+            }
+            return Ok(tail_lines(&data, lines).to_vec());
+        }
+        if !entry.is_chunked() {
+            let data = self.fetch_file_data(entry, manifest, &ProgressBar::hidden()).await?;
+            return Ok(tail_lines(&data, lines).to_vec());
+        }
+        
+        let encryption = self.encryption_for(manifest.key_version, manifest.aead_algorithm)?
+            .for_algorithm(manifest.aead_algorithm);
+        let mut data = Vec::new();
+        for (i, chunk) in entry.chunks.iter().enumerate().rev() {
+            let name = format!("chunk {} of {}", i, entry.local_path.display());
+            let mut chunk_data = Self::fetch_chunk(&self.hetzner, &self.temp, &encryption, chunk, manifest.hash_algorithm, &name, self.block_cache.as_deref()).await
+                .map_err(|e| Self::cold_storage_hint(e, manifest))?;
+            chunk_data.extend_from_slice(&data);
+            data = chunk_data;
+            
+            // Enough line breaks before the final line to start `lines` back
+            let body = data.strip_suffix(b"\n").unwrap_or(&data);
+            if body.iter().filter(|&&b| b == b'\n').count() >= lines {
+                break;
+            }
+        }
+        Ok(tail_lines(&data, lines).to_vec())
+    }
+    
+    /// Download and decrypt the segments at `indices` of an uncompressed
+    /// streamed file with one ranged request, returning their bytes joined
+    ///
+    /// `None` if the file is compressed, which can't be entered mid-stream,
+    /// or the server doesn't honour ranges; the caller then reads it as a
+    /// stream.
+    async fn fetch_segments(
+        &self,
+        entry: &FileEntry,
+        manifest: &BackupManifest,
+        indices: std::ops::Range<u64>,
+    ) -> Result<Option<Vec<u8>>> {
+        if entry.compression_algorithm() != CompressionAlgorithm::None {
+            return Ok(None);
+        }
+        let framing = entry.framing.unwrap_or_default();
+        let first = framing.frame_range(entry.size, indices.start);
+        let blob_range = first.start..framing.frame_range(entry.size, indices.end - 1).end;
+        
+        let remote_path = PathBuf::from(&entry.remote_path);
+        let call = format!("download of {} bytes {}..{}", entry.remote_path, blob_range.start, blob_range.end);
+        let frames = self.retrier.execute(RESTORE_OPERATION, &call, || async {
+            Ok(self.hetzner.download_range(&remote_path, blob_range.start, blob_range.end - 1).await?)
+        }).await.map_err(|e| Self::cold_storage_hint(e, manifest))?;
+        let Some(frames) = frames else {
+            return Ok(None);
+        };
+        
+        let encryption = self.encryption_for(manifest.key_version, manifest.aead_algorithm)?;
+        let (encryption, backup_id) = Self::stream_decryption(&encryption, manifest, entry);
+        let mut data = Vec::new();
+        for index in indices {
+            let frame = framing.frame_range(entry.size, index);
+            let frame = &frames[(frame.start - blob_range.start) as usize..(frame.end - blob_range.start) as usize];
+            let segment = file_stream::decode_segment(frame, &encryption, &backup_id, framing, entry.size, index)
+                .map_err(|e| SkylockError::Integrity(format!("{}: {}", entry.local_path.display(), e)))?;
+            data.extend_from_slice(&segment);
+        }
+        Ok(Some(data))
+    }
+    
+    /// Point out that a failed download may be due to cold storage
+    fn cold_storage_hint(error: SkylockError, manifest: &BackupManifest) -> SkylockError {
+        if manifest.storage_tier != StorageTier::Archive {
+            return error;
+        }
+        if matches!(
+            error,
+            SkylockError::Core(skylock_core::SkylockError::Storage(StorageErrorType::ColdStorage(_)))
+        ) {
+            return error;
+        }
+        SkylockError::Backup(format!(
+            "{} (backup {} is in the archive tier; objects in cold storage may have to be retrieved before they can be restored)",
+            error, manifest.backup_id
+        ))
+    }
+    
+    /// Download a backed-up file, using parallel ranges when it is large
+    /// 
+    /// Files downloaded in ranges are checked against the blob hash recorded
+    /// for them. Failed downloads are retried within the restore's retry
+    /// budget.
+    async fn download_object(&self, entry: &FileEntry, local_path: &Path) -> Result<()> {
+        let remote_path = PathBuf::from(&entry.remote_path);
+        let call = format!("download of {}", entry.remote_path);
+        
+        match self.multipart_download {
+            Some(ref multipart) if entry.size >= multipart.min_size => {
+                let downloader = self.downloader.get_or_init(|| {
+                    MultipartDownloader::new(self.storage_config(), multipart.clone())
+                }).await;
+                // The downloader compares base64 digests
+                let expected_hash = entry.blob_hash.as_deref()
+                    .map(|hash| hex::decode(hash).map(|digest| BASE64.encode(digest)))
+                    .transpose()
+                    .map_err(|e| SkylockError::Backup(format!("Invalid blob hash for {}: {}", entry.remote_path, e)))?;
+                self.retrier.execute(RESTORE_OPERATION, &call, || async {
+                    Ok(downloader.download_file(&remote_path, local_path, expected_hash.as_deref()).await?)
+                }).await?;
+            }
+            _ => {
+                self.retrier.execute(RESTORE_OPERATION, &call, || async {
+                    Ok(self.hetzner.download_file(&remote_path, local_path).await?)
+                }).await?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Storage credentials for opening additional connections
+    fn storage_config(&self) -> skylock_hetzner::HetznerConfig {
+        skylock_hetzner::HetznerConfig {
+            endpoint: self.config.hetzner.endpoint.clone(),
+            username: self.config.hetzner.username.clone(),
+            password: self.config.hetzner.password.clone(),
+            api_token: String::new(),
+            encryption_key: String::new(),
+        }
+    }
+    
+    /// Decrypt a downloaded file using the encryption version and AEAD
+    /// algorithm recorded in its backup's manifest
+    pub(crate) fn decrypt_file_data(
+        encryption: &EncryptionManager,
+        manifest: &BackupManifest,
+        entry: &FileEntry,
+        encrypted_data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let encryption = blob_encryption(
+            &encryption.for_algorithm(manifest.aead_algorithm),
+            entry.key_context.as_deref(),
+        );
+        
+        // Detect encryption version from manifest
+        let is_v2 = manifest.encryption_version == "v2" && manifest.kdf_params.is_some();
+        
+        if is_v2 {
+            // v2: Use AAD-bound decryption, against the original backup and
+            // path for blobs reused after a move
+            let (backup_id, local_path) = match entry.blob_origin {
+                Some(ref origin) => (origin.backup_id.as_str(), &origin.local_path),
+                None => (manifest.backup_id.as_str(), &entry.local_path),
+            };
+            let file_path_str = local_path.to_string_lossy();
+            encryption.decrypt_with_aad(
+                encrypted_data,
+                backup_id,
+                &file_path_str
+            )
+        } else {
+            // v1: Use legacy decryption (no AAD)
+            encryption.decrypt(encrypted_data)
+        }
+    }
+    
+    /// Where a path recorded in a manifest is restored below `target_dir`
+    /// 
+    /// Rejects paths with `..` components so that a tampered or corrupt
+    /// manifest can't write outside the target directory.
+    pub(crate) fn restore_target(target_dir: &Path, path: &Path) -> Result<PathBuf> {
+        let mut target = target_dir.to_path_buf();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => target.push(part),
+                Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+                Component::ParentDir => {
+                    return Err(SkylockError::Backup(format!(
+                        "Refusing to restore {} outside the target directory",
+                        path.display()
+                    )));
+                }
+            }
+        }
+        Ok(target)
+    }
+    
+    /// Recreate the directories recorded in a backup at their remapped paths
+    /// below `target_dir` and reapply their permissions and modification times
+    /// 
+    /// Called after the files are written. Attributes are set deepest
+    /// directory first, so creating a subdirectory doesn't change the
+    /// timestamp restored on its parent; failing to set them only warns.
+    pub(crate) async fn restore_directories(
+        directories: &[DirectoryEntry],
+        target_dir: &Path,
+        path_map: &PathMap,
+    ) -> Result<()> {
+        let mut targets = Vec::with_capacity(directories.len());
+        for directory in directories {
+            let target = Self::restore_target(target_dir, &path_map.apply(&directory.path))?;
+            tokio::fs::create_dir_all(&target).await?;
+            targets.push((target, directory));
+        }
+        
+        targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.components().count()));
+        for (target, directory) in targets {
+            #[cfg(unix)]
+            if let Some(mode) = directory.mode {
+                use std::os::unix::fs::PermissionsExt;
+                if let Err(e) = std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode)) {
+                    tracing::warn!("Cannot restore permissions of {}: {}", target.display(), e);
+                }
+            }
+            if let Some(modified) = directory.modified {
+                Self::restore_modified(&target, modified);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Set the modification time recorded in a backup on a restored file or
+    /// directory; failing to set it only warns
+    pub(crate) fn restore_modified(target: &Path, modified: DateTime<Utc>) {
+        let mtime = filetime::FileTime::from_unix_time(modified.timestamp(), modified.timestamp_subsec_nanos());
+        if let Err(e) = filetime::set_file_mtime(target, mtime) {
+            tracing::warn!("Cannot restore modification time of {}: {}", target.display(), e);
+        }
+    }
+    
+    /// Write verified file contents at their remapped path below the target
+    /// directory, reapplying the modification time and, when enabled,
+    /// extended attributes
+    pub(crate) async fn write_restored_file(
+        entry: &FileEntry,
+        target_dir: &Path,
+        path_map: &PathMap,
+        data: &[u8],
+        preserve_xattrs: bool,
+    ) -> Result<PathBuf> {
+        let target_path = Self::restore_target(target_dir, &path_map.apply(&entry.local_path))?;
+        
+        if let Some(parent) = target_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        
+        let partial = CleanupGuard::new(&target_path);
+        tokio::fs::write(&target_path, data).await?;
+        partial.keep();
+        Self::apply_restored_metadata(entry, &target_path, preserve_xattrs);
+        
+        Ok(target_path)
+    }
+    
+    /// Reapply the modification time and, when enabled, extended attributes
+    /// recorded for a restored file
+    fn apply_restored_metadata(entry: &FileEntry, target_path: &Path, preserve_xattrs: bool) {
+        if preserve_xattrs {
+            xattrs::apply_xattrs(target_path, &entry.xattrs);
+        }
+        if let Some(modified) = entry.modified {
+            Self::restore_modified(target_path, modified);
+        }
+    }
+    
+    /// Restore a single file (legacy without progress)
+    async fn restore_single_file(&self, entry: &FileEntry, target_dir: &Path, manifest: &BackupManifest) -> Result<()> {
+        use indicatif::{ProgressBar, ProgressStyle};
+        
+        let pb = ProgressBar::new(entry.size);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} {msg} {bar:40.green/blue} {percent}%")
+                .unwrap()
+                .progress_chars("█▓▒░ ")
+        );
+        pb.set_message(format!("Restoring {}", entry.local_path.display()));
+        
+        let result = self.restore_single_file_with_progress(entry, target_dir, manifest, pb.clone()).await;
+        pb.finish_and_clear();
+        result
+    }
+
+    /// Preview backup contents before restoring
+    pub async fn preview_backup(&self, backup_id: &str) -> Result<()> {
+        // Download manifest (auto-detects encrypted vs legacy)
+        let manifest = self.download_manifest(backup_id).await?;
+        
+        println!();
+        println!("{}", "==========================================================================");
+        println!("🔍 Backup Preview: {}", backup_id);
+        println!("{}", "==========================================================================");
+        println!();
         
         println!("📊 Backup Information:");
         println!("   📅 Date: {}", manifest.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
         println!("   📦 Files: {}", manifest.file_count);
-        println!("   💾 Size: {} bytes ({:.2} MB)", 
-            manifest.total_size,
-            (manifest.total_size as f64 / 1024.0 / 1024.0));
+        println!("   💾 Size: {} bytes ({})", manifest.total_size, ByteSize(manifest.total_size));
         println!();
         
         println!("📁 Files to be restored:");
@@ -1241,13 +4553,7 @@ impl DirectUploadBackup {
             println!();
             println!("   📂 {}", dir);
             for file in files {
-                let size_str = if file.size > 1024 * 1024 {
-                    format!("{:.2} MB", file.size as f64 / 1024.0 / 1024.0)
-                } else if file.size > 1024 {
-                    format!("{:.2} KB", file.size as f64 / 1024.0)
-                } else {
-                    format!("{} B", file.size)
-                };
+                let size_str = ByteSize(file.size).to_string();
                 
                 let status = if file.compressed { "🗃️" } else { "  " };
                 let encrypted = if file.encrypted { "🔒" } else { "  " };
@@ -1273,22 +4579,68 @@ impl DirectUploadBackup {
     
     /// Check for conflicts before restore
     pub async fn check_restore_conflicts(&self, backup_id: &str, target_dir: &Path) -> Result<Vec<PathBuf>> {
-        // Download manifest (auto-detects encrypted vs legacy)
-        let manifest = self.download_manifest(backup_id).await?;
+        // Download manifests (auto-detects encrypted vs legacy)
+        let chain = self.load_chain(backup_id).await?;
         
-        let mut conflicts = Vec::new();
-        
-        for entry in &manifest.files {
-            let target_path = target_dir.join(
-                entry.local_path.strip_prefix("/").unwrap_or(&entry.local_path)
-            );
-            
-            if target_path.exists() {
-                conflicts.push(target_path);
+        self.existing_targets(&Self::merge_chain(&chain), target_dir).await
+    }
+    
+    /// Restore targets of `files` that something already exists at,
+    /// including dangling symlinks
+    async fn existing_targets(&self, files: &[(&FileEntry, &BackupManifest)], target_dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut existing = Vec::new();
+        for (entry, _) in files {
+            let target_path = Self::restore_target(target_dir, &self.path_map.apply(&entry.local_path))?;
+            if tokio::fs::symlink_metadata(&target_path).await.is_ok() {
+                existing.push(target_path);
             }
         }
+        Ok(existing)
+    }
+    
+    /// Dry run of a restore to `target_dir`
+    /// 
+    /// Classifies every file of the backup by what restoring it would do to
+    /// the target: create it, overwrite a different file, or leave an
+    /// identical one alone. Existing files of the same size are hashed to
+    /// tell the last two apart. Nothing is downloaded or written. With
+    /// `prefixes`, only the files a [`Self::restore_subtree`] of them would
+    /// restore are classified.
+    pub async fn preview_restore(&self, backup_id: &str, prefixes: &[PathBuf], target_dir: &Path) -> Result<Vec<RestorePreviewEntry>> {
+        let subtrees = prefixes.iter()
+            .map(|prefix| Self::subtree_components(prefix))
+            .collect::<Result<Vec<_>>>()?;
+        let chain = self.load_chain(backup_id).await?;
+        
+        let mut preview = Vec::new();
+        let selected = Self::merge_chain(&chain).into_iter().filter(|(entry, _)| {
+            subtrees.is_empty() || subtrees.iter().any(|subtree| Self::in_subtree(&entry.local_path, subtree))
+        });
+        for (entry, manifest) in selected {
+            let target = Self::restore_target(target_dir, &self.path_map.apply(&entry.local_path))?;
+            let (action, existing_size) = match tokio::fs::symlink_metadata(&target).await {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (RestoreAction::Create, None),
+                Err(e) => return Err(e.into()),
+                Ok(metadata) if !metadata.is_file() => (RestoreAction::Overwrite, None),
+                Ok(metadata) if metadata.len() != entry.size => (RestoreAction::Overwrite, Some(metadata.len())),
+                Ok(metadata) => {
+                    let action = if Self::calculate_hash(&target, manifest.hash_algorithm).await? == entry.hash {
+                        RestoreAction::Unchanged
+                    } else {
+                        RestoreAction::Overwrite
+                    };
+                    (action, Some(metadata.len()))
+                }
+            };
+            preview.push(RestorePreviewEntry {
+                target,
+                action,
+                backup_size: entry.size,
+                existing_size,
+            });
+        }
         
-        Ok(conflicts)
+        Ok(preview)
     }
     
     /// Restore a single file by path
@@ -1298,13 +4650,9 @@ impl DirectUploadBackup {
         file_path: &str,
         output: &Path,
     ) -> Result<()> {
-        // Download manifest (auto-detects encrypted vs legacy)
-        let manifest = self.download_manifest(backup_id).await?;
-        
-        // Find file in manifest
-        let entry = manifest.files.iter()
-            .find(|e| e.local_path.to_str() == Some(file_path))
-            .ok_or_else(|| SkylockError::Backup(format!("File not found in backup: {}", file_path)))?;
+        // Download manifests (auto-detects encrypted vs legacy)
+        let chain = self.load_chain(backup_id).await?;
+        let (entry, manifest) = Self::find_file(&chain, file_path)?;
         
         println!("🔄 Restoring single file: {}", file_path);
         
@@ -1314,57 +4662,3673 @@ impl DirectUploadBackup {
         }
         
         // Restore to output path
-        let temp_dir = tempfile::tempdir()
-            .map_err(|e| SkylockError::Backup(format!("Temp dir failed: {}", e)))?;
+        let temp_dir = self.temp.directory("restore")?;
         
-        self.restore_single_file(entry, temp_dir.path(), &manifest).await?;
+        self.restore_single_file(entry, temp_dir.path(), manifest).await?;
         
-        let restored_file = temp_dir.path().join(
-            entry.local_path.strip_prefix("/").unwrap_or(&entry.local_path)
-        );
+        let restored_file = Self::restore_target(temp_dir.path(), &entry.local_path)?;
         
         tokio::fs::copy(&restored_file, output).await?;
+        if let Some(modified) = entry.modified {
+            Self::restore_modified(output, modified);
+        }
         
         println!("✅ File restored to: {}", output.display());
         
         Ok(())
     }
     
+    /// Restore a single file by path into `writer`, e.g. stdout for piping
+    /// into another tool
+    /// 
+    /// Nothing but the file contents is written to stdout; status messages
+    /// go to stderr. Returns the number of bytes written.
+    pub async fn restore_file_to_writer<W: std::io::Write>(
+        &self,
+        backup_id: &str,
+        file_path: &str,
+        mut writer: W,
+    ) -> Result<u64> {
+        let chain = self.load_chain(backup_id).await?;
+        let (entry, manifest) = Self::find_file(&chain, file_path)?;
+        
+        eprintln!("🔄 Restoring single file: {}", file_path);
+        if manifest.encryption_version == "v1" || manifest.kdf_params.is_none() {
+            eprintln!("⚠️  WARNING: This backup uses legacy encryption (v1)");
+        }
+        
+        if entry.is_streamed() {
+            // Decoded here, since the writer needn't be sendable to another thread
+            let (blob, encryption, backup_id) = self.download_streamed(entry, manifest).await?;
+            let blob = std::io::BufReader::new(std::fs::File::open(blob.path())?);
+            Self::decode_streamed(encryption, &backup_id, entry, manifest.hash_algorithm, blob, &mut writer)?;
+            writer.flush()?;
+            return Ok(entry.size);
+        }
+        
+        let data = self.fetch_file_data(entry, manifest, &ProgressBar::hidden()).await?;
+        writer.write_all(&data)?;
+        writer.flush()?;
+        
+        Ok(data.len() as u64)
+    }
+    
+    /// Find a file in a backup chain by its original path, with the manifest
+    /// that uploaded it
+    pub(crate) fn find_file<'a>(chain: &'a [BackupManifest], file_path: &str) -> Result<(&'a FileEntry, &'a BackupManifest)> {
+        Self::merge_chain(chain).into_iter()
+            .find(|(e, _)| e.local_path.to_str() == Some(file_path))
+            .ok_or_else(|| SkylockError::Backup(format!("File not found in backup: {}", file_path)))
+    }
+    
     /// Delete a backup by ID
+    /// 
+    /// Blobs are deleted up to `delete_concurrency` at once, paced by the
+    /// deletion rate limit, and those gone are checkpointed in the data
+    /// directory. The manifest goes last, once every blob has, so a deletion
+    /// that is interrupted or fails partway leaves the backup listed, and
+    /// deleting it again (see [`Self::interrupted_deletions`]) only sends
+    /// what is left. Blobs already missing count as deleted.
     pub async fn delete_backup(&self, backup_id: &str) -> Result<()> {
-        let backup_dir = format!("/skylock/backups/{}", backup_id);
+        let state_dir = DeleteCheckpoint::state_dir(&self.config.data_dir);
         
         // Download manifest to know what files to delete (auto-detects format)
         let manifest = match self.download_manifest(backup_id).await {
             Ok(m) => m,
-            Err(_) => {
-                // If manifest doesn't exist, still try to delete the directory
+            Err(e) if e.is_not_found() && DeleteCheckpoint::exists(&state_dir, backup_id).await => {
+                // Interrupted after the manifest itself was deleted
+                DeleteCheckpoint::delete(&state_dir, backup_id).await?;
+                self.invalidate_listing().await;
+                return Ok(());
+            }
+            Err(e) if e.is_not_found() => {
                 return Err(SkylockError::Backup(format!(
                     "Cannot delete backup {}: manifest not found",
                     backup_id
                 )));
             }
+            Err(e) => return Err(e),
         };
         
-        // Delete all files in the backup
-        for entry in &manifest.files {
-            let file_path = PathBuf::from(&entry.remote_path);
-            // Attempt to delete, but don't fail if file doesn't exist
-            let _ = self.hetzner.delete_file(&file_path).await;
+        // Blobs that later backups reuse for moved files must stay, including
+        // for backups in the trash, which may still be recovered
+        let mut others = self.list_backups().await?;
+        others.extend(self.trashed_manifests().await?);
+        let reused = Self::reused_blob_paths(others.iter().filter(|m| m.backup_id != backup_id));
+        
+        // All files in the backup, and the chunks it uploaded itself, less
+        // those an earlier attempt already deleted
+        let mut checkpoint = DeleteCheckpoint::load_or_new(&state_dir, backup_id).await;
+        let blobs: std::collections::BTreeSet<String> = manifest.files.iter()
+            .flat_map(|entry| {
+                let chunks = entry.chunks.iter()
+                    .filter(|chunk| chunk.backup_id == backup_id)
+                    .map(|chunk| &chunk.remote_path);
+                (!entry.is_chunked()).then_some(&entry.remote_path).into_iter().chain(chunks)
+            })
+            .filter(|remote_path| !reused.contains(*remote_path) && !checkpoint.is_deleted(remote_path))
+            .cloned()
+            .collect();
+        
+        // Written before the first blob goes, so an interruption at any
+        // point leaves a record of the unfinished deletion
+        checkpoint.save(&state_dir).await?;
+        
+        let mut failed = 0;
+        let mut deletions = futures::stream::iter(blobs)
+            .map(|remote_path| async move {
+                let result = self.hetzner.delete_file(Path::new(&remote_path)).await;
+                (remote_path, result)
+            })
+            .buffer_unordered(self.delete_concurrency);
+        while let Some((remote_path, result)) = deletions.next().await {
+            match result {
+                Ok(()) | Err(skylock_core::SkylockError::Storage(StorageErrorType::FileNotFound)) => {
+                    checkpoint.mark_deleted(remote_path);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to delete {}: {}", remote_path, e);
+                    failed += 1;
+                }
+            }
+            if let Err(e) = checkpoint.checkpoint(&state_dir, CHECKPOINT_INTERVAL).await {
+                tracing::warn!("Failed to save deletion checkpoint: {}", e);
+            }
         }
+        drop(deletions);
+        checkpoint.save(&state_dir).await?;
         
-        // Delete manifest files (both encrypted and legacy formats)
+        if failed > 0 {
+            return Err(SkylockError::Backup(format!(
+                "Failed to delete {} blobs of backup {}; its manifest was kept so deleting it again finishes the job",
+                failed, backup_id
+            )));
+        }
+        
+        // Manifest files (both encrypted and legacy formats), the manifests
+        // themselves last since they make the backup listed. Their checksum
+        // companions follow; a leftover one is harmless
         let encrypted_manifest = PathBuf::from(format!("/skylock/backups/{}/manifest.json.enc", backup_id));
         let header_file = PathBuf::from(format!("/skylock/backups/{}/manifest_header.json", backup_id));
+        let summary_file = PathBuf::from(format!("/skylock/backups/{}/manifest_summary.json.enc", backup_id));
+        let run_summary_file = BackupRunSummary::remote_path(backup_id);
         let legacy_manifest = PathBuf::from(format!("/skylock/backups/{}/manifest.json", backup_id));
         
-        let _ = self.hetzner.delete_file(&encrypted_manifest).await;
         let _ = self.hetzner.delete_file(&header_file).await;
+        let _ = self.hetzner.delete_file(&summary_file).await;
+        let _ = self.hetzner.delete_file(&run_summary_file).await;
         let _ = self.hetzner.delete_file(&legacy_manifest).await;
+        let _ = self.hetzner.delete_file(&encrypted_manifest).await;
+        let _ = self.hetzner.delete_file(&manifest_checksum::companion_path(&encrypted_manifest)).await;
+        let _ = self.hetzner.delete_file(&manifest_checksum::companion_path(&legacy_manifest)).await;
         
         // Note: WebDAV doesn't have a direct directory delete, files are deleted individually
         // The directory will be empty after all files are deleted
         
+        DeleteCheckpoint::delete(&state_dir, backup_id).await?;
+        self.invalidate_listing().await;
+        Ok(())
+    }
+    
+    /// Backups whose deletion was interrupted, to be finished with
+    /// [`Self::delete_backup`]
+    pub async fn interrupted_deletions(&self) -> Result<Vec<String>> {
+        DeleteCheckpoint::pending(&DeleteCheckpoint::state_dir(&self.config.data_dir)).await
+    }
+    
+    /// Move a backup into the trash instead of deleting it
+    /// 
+    /// The manifest is moved to `/skylock/trash/<id>/` next to a marker
+    /// holding `entry`, so the backup is no longer listed, while its blobs
+    /// stay in place. [`Self::restore_deleted`] brings it back until
+    /// [`Self::purge_expired_trash`] deletes it for good.
+    pub async fn trash_backup(&self, entry: &TrashEntry) -> Result<()> {
+        let backup_dir = format!("/skylock/backups/{}", entry.backup_id);
+        let trash_dir = format!("{}/{}", TRASH_DIR, entry.backup_id);
+        
+        // Copied before anything is removed, so an interruption leaves the
+        // backup live rather than lost
+        let moved = self.copy_manifest_files(&backup_dir, &trash_dir).await?;
+        if moved.is_empty() {
+            return Err(SkylockError::Backup(format!(
+                "Cannot delete backup {}: manifest not found",
+                entry.backup_id
+            )));
+        }
+        let marker = serde_json::to_vec_pretty(entry)
+            .map_err(|e| SkylockError::Backup(format!("Serialize trash entry failed: {}", e)))?;
+        self.temp.upload(&self.hetzner, &marker, &PathBuf::from(format!("{}/{}", trash_dir, TRASH_MARKER))).await?;
+        
+        if let Err(e) = self.remove_manifest_files(&backup_dir, &moved).await {
+            // Left live rather than both live and in the trash, where it
+            // would be purged from under its users
+            self.invalidate_listing().await;
+            self.copy_manifest_files(&trash_dir, &backup_dir).await?;
+            self.remove_from_trash(&entry.backup_id).await?;
+            return Err(SkylockError::Backup(format!(
+                "Cannot delete backup {}: failed to remove its manifest: {}", entry.backup_id, e
+            )));
+        }
+        self.invalidate_listing().await;
+        Ok(())
+    }
+    
+    /// Bring a backup back from the trash, returning its trash entry
+    pub async fn restore_deleted(&self, backup_id: &str) -> Result<TrashEntry> {
+        let trash_dir = format!("{}/{}", TRASH_DIR, backup_id);
+        let entry = self.trash_entry(backup_id).await?;
+        
+        let moved = self.copy_manifest_files(&trash_dir, &format!("/skylock/backups/{}", backup_id)).await?;
+        if moved.is_empty() {
+            return Err(SkylockError::Backup(format!("Backup {} has no manifest in the trash", backup_id)));
+        }
+        self.invalidate_listing().await;
+        self.remove_from_trash(backup_id).await?;
+        Ok(entry)
+    }
+    
+    /// Every backup in the trash, oldest deletion first
+    pub async fn list_trash(&self) -> Result<Vec<TrashEntry>> {
+        let files = match self.hetzner.list_files(TRASH_DIR).await {
+            Ok(files) => files,
+            // Nothing deleted yet
+            Err(skylock_core::SkylockError::Storage(StorageErrorType::FileNotFound)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        
+        // An entry that can't be read fails the listing, as callers rely on
+        // it to keep the blobs of trashed backups
+        let mut entries = Vec::new();
+        for file in files.iter().filter(|f| f.path.file_name().and_then(|n| n.to_str()) == Some(TRASH_MARKER)) {
+            let Some(backup_id) = file.path.parent().and_then(|p| p.file_name()).and_then(|n| n.to_str()) else {
+                continue;
+            };
+            entries.push(self.trash_entry(backup_id).await?);
+        }
+        entries.sort_by_key(|entry| entry.deleted_at);
+        Ok(entries)
+    }
+    
+    /// Delete the backups whose time in the trash is up, returning their IDs
+    /// 
+    /// Each is moved back first, so the regular deletion applies, with its
+    /// care for blobs that other backups reuse.
+    pub async fn purge_expired_trash(&self, now: DateTime<Utc>) -> Result<Vec<String>> {
+        let entries = self.list_trash().await?;
+        let mut purged = Vec::new();
+        for backup_id in RetentionManager::expired_trash(&entries, now) {
+            self.purge_trashed(&backup_id).await?;
+            purged.push(backup_id);
+        }
+        Ok(purged)
+    }
+    
+    /// Delete a backup in the trash for good
+    /// 
+    /// The backup stays in the trash until its deletion has finished, so a
+    /// failed deletion leaves it there to be purged again rather than live.
+    pub async fn purge_trashed(&self, backup_id: &str) -> Result<()> {
+        let trash_dir = format!("{}/{}", TRASH_DIR, backup_id);
+        let backup_dir = format!("/skylock/backups/{}", backup_id);
+        self.trash_entry(backup_id).await?;
+        
+        let moved = self.copy_manifest_files(&trash_dir, &backup_dir).await?;
+        if moved.is_empty() {
+            return Err(SkylockError::Backup(format!("Backup {} has no manifest in the trash", backup_id)));
+        }
+        if let Err(e) = self.delete_backup(backup_id).await {
+            if let Err(cleanup) = self.remove_manifest_files(&backup_dir, &moved).await {
+                tracing::warn!("Failed to return backup {} to the trash: {}", backup_id, cleanup);
+            }
+            self.invalidate_listing().await;
+            return Err(e);
+        }
+        self.remove_from_trash(backup_id).await
+    }
+    
+    /// Read the trash marker of `backup_id`
+    async fn trash_entry(&self, backup_id: &str) -> Result<TrashEntry> {
+        let marker = PathBuf::from(format!("{}/{}/{}", TRASH_DIR, backup_id, TRASH_MARKER));
+        let json = match self.temp.download_bytes(&self.hetzner, &marker).await {
+            Ok(json) => json,
+            Err(e) if e.is_not_found() => {
+                return Err(SkylockError::Backup(format!("Backup {} is not in the trash", backup_id)));
+            }
+            Err(e) => return Err(e),
+        };
+        serde_json::from_slice(&json)
+            .map_err(|e| SkylockError::Backup(format!("Invalid trash entry for {}: {}", backup_id, e)))
+    }
+    
+    /// Blobs freed once the backups in `to_delete` are gone from the
+    /// listed `manifests`, counting those still referenced by backups in the
+    /// trash as shared
+    pub async fn estimate_reclaim(&self, manifests: &[BackupManifest], to_delete: &[String]) -> Result<ReclaimEstimate> {
+        let trashed = self.trashed_manifests().await?;
+        Ok(RetentionManager::estimate_reclaim(manifests.iter().chain(&trashed), to_delete))
+    }
+    
+    /// Manifests of the backups in the trash
+    async fn trashed_manifests(&self) -> Result<Vec<BackupManifest>> {
+        let mut manifests = Vec::new();
+        for entry in self.list_trash().await? {
+            let trash_dir = format!("{}/{}", TRASH_DIR, entry.backup_id);
+            let manifest = match self.download_encrypted_manifest_in(&trash_dir, &entry.backup_id).await {
+                Err(e) if e.is_not_found() => {
+                    self.download_manifest_legacy(&PathBuf::from(format!("{}/manifest.json", trash_dir))).await
+                }
+                manifest => manifest,
+            };
+            manifests.push(manifest.map_err(|e| SkylockError::Backup(format!(
+                "Failed to load the manifest of trashed backup {}: {}", entry.backup_id, e
+            )))?);
+        }
+        Ok(manifests)
+    }
+    
+    /// Copy the manifest files present in `from` to `to`, returning their names
+    async fn copy_manifest_files(&self, from: &str, to: &str) -> Result<Vec<String>> {
+        Self::ensure_remote_directory_exists(&self.hetzner, to).await?;
+        let mut copied = Vec::new();
+        for name in Self::manifest_file_names() {
+            let Ok(data) = self.temp.download_bytes(&self.hetzner, &PathBuf::from(format!("{}/{}", from, name))).await else {
+                continue;
+            };
+            self.temp.upload(&self.hetzner, &data, &PathBuf::from(format!("{}/{}", to, name))).await?;
+            copied.push(name);
+        }
+        Ok(copied)
+    }
+    
+    /// Delete the manifest files `names` in `dir`, failing on the first
+    /// that can't be removed
+    async fn remove_manifest_files(&self, dir: &str, names: &[String]) -> Result<()> {
+        for name in names {
+            match self.hetzner.delete_file(&PathBuf::from(format!("{}/{}", dir, name))).await {
+                Ok(()) | Err(skylock_core::SkylockError::Storage(StorageErrorType::FileNotFound)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+    
+    /// Remove what is left of a backup in the trash
+    /// 
+    /// The marker goes first, as it is what makes a backup trashed; the
+    /// manifest copies left if a later deletion fails are harmless.
+    async fn remove_from_trash(&self, backup_id: &str) -> Result<()> {
+        let trash_dir = format!("{}/{}", TRASH_DIR, backup_id);
+        self.remove_manifest_files(&trash_dir, &[TRASH_MARKER.to_string()]).await?;
+        if let Err(e) = self.remove_manifest_files(&trash_dir, &Self::manifest_file_names()).await {
+            tracing::warn!("Failed to clear trashed backup {}: {}", backup_id, e);
+        }
         Ok(())
     }
+    
+    /// Names of the files that make up a backup's manifest, in both formats
+    fn manifest_file_names() -> Vec<String> {
+        let mut names = Vec::new();
+        for manifest in ["manifest.json.enc", "manifest.json"] {
+            names.push(manifest.to_string());
+            names.push(format!("{}{}", manifest, manifest_checksum::CHECKSUM_SUFFIX));
+        }
+        names.push("manifest_header.json".to_string());
+        names.push("manifest_summary.json.enc".to_string());
+        names.push(RUN_SUMMARY_FILE.to_string());
+        names
+    }
+    
+    /// Delete blobs under backup directories that no live backup references
+    /// 
+    /// Catches files left behind after a manifest was removed, or uploaded by a
+    /// backup that never wrote its manifest. Directories with a local resume
+    /// state belong to an interrupted backup and are left alone, as are those
+    /// of backups in the trash.
+    pub async fn collect_garbage(&self, live_backup_ids: &std::collections::HashSet<String>) -> Result<GarbageCollectionStats> {
+        let mut stats = GarbageCollectionStats::default();
+        
+        let backup_dirs = match self.hetzner.list_directories("/skylock/backups").await {
+            Ok(dirs) => dirs,
+            // Nothing uploaded yet
+            Err(skylock_core::SkylockError::Storage(StorageErrorType::FileNotFound)) => return Ok(stats),
+            Err(e) => return Err(e.into()),
+        };
+        
+        // Backups in the trash keep their blobs until they are purged
+        let trashed = self.trashed_manifests().await?;
+        let mut live_backup_ids = live_backup_ids.clone();
+        live_backup_ids.extend(self.list_trash().await?.into_iter().map(|entry| entry.backup_id));
+        
+        // Blobs of removed backups may still be reused by live ones, and a
+        // backup whose manifest can't be read would look orphaned, so any
+        // unreadable manifest stops the collection
+        let live_manifests = self.list_backups_strict().await?;
+        let reused = Self::reused_blob_paths(
+            live_manifests.iter().chain(&trashed).filter(|m| live_backup_ids.contains(&m.backup_id))
+        );
+        
+        for dir_path in backup_dirs {
+            let backup_id = dir_path.split('/').last().unwrap_or(&dir_path).to_string();
+            
+            // Only touch directories that look like backup IDs (YYYYMMDD_HHMMSS)
+            if backup_id.is_empty() || !backup_id.chars().all(|c| c.is_ascii_digit() || c == '_') {
+                continue;
+            }
+            if live_backup_ids.contains(&backup_id) || ResumeState::exists(&self.resume_dir(), &backup_id).await {
+                continue;
+            }
+            
+            let files = self.list_remote_files_recursive(&format!("/skylock/backups/{}", backup_id)).await?;
+            for file in files.into_iter().filter(|file| !reused.contains(file)) {
+                match self.hetzner.delete_file(&PathBuf::from(&file)).await {
+                    Ok(_) => stats.files_deleted += 1,
+                    Err(_) => stats.files_failed += 1,
+                }
+            }
+            stats.orphaned_backups.push(backup_id);
+        }
+        
+        Ok(stats)
+    }
+    
+    /// A backup ID for a backup starting now that no existing backup uses
+    /// 
+    /// IDs are the start time to the second, so one started in the same
+    /// second as an earlier backup (an incremental right after its base, say)
+    /// gets a `_<n>` suffix rather than overwriting that backup.
+    async fn new_backup_id(&self) -> Result<String> {
+        let timestamp = Utc::now().format("%Y%m%d_%H%M%S").to_string();
+        let mut backup_id = timestamp.clone();
+        let mut suffix = 0;
+        while self.backup_id_taken(&backup_id).await? {
+            suffix += 1;
+            backup_id = format!("{}_{}", timestamp, suffix);
+        }
+        Ok(backup_id)
+    }
+    
+    /// Whether anything is stored under `backup_id` on the storage box
+    async fn backup_id_taken(&self, backup_id: &str) -> Result<bool> {
+        match self.hetzner.list_files(&format!("/skylock/backups/{}", backup_id)).await {
+            Ok(files) => Ok(!files.is_empty()),
+            Err(skylock_core::SkylockError::Storage(StorageErrorType::FileNotFound)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+    
+    /// List all files below a remote directory (absolute paths)
+    async fn list_remote_files_recursive(&self, root: &str) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        let mut pending = vec![root.trim_end_matches('/').to_string()];
+        
+        while let Some(dir) = pending.pop() {
+            for file in self.hetzner.list_files(&dir).await? {
+                files.push(format!("/{}", file.path.to_string_lossy().trim_start_matches('/')));
+            }
+            
+            for sub in self.hetzner.list_directories(&dir).await? {
+                let sub = format!("/{}", sub.trim_matches('/'));
+                // PROPFIND includes the collection itself in its response
+                if sub != dir && sub.starts_with(&format!("{}/", dir)) {
+                    pending.push(sub);
+                }
+            }
+        }
+        
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::BackupDiff;
+    use crate::retention::RetentionPolicy;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+    use skylock_hetzner::test_server::{self, Request};
+    use crate::test_support::test_config;
+
+    /// In-memory WebDAV server recording uploads
+    #[derive(Default)]
+    struct MockStorage {
+        files: HashMap<String, Vec<u8>>,
+        /// Paths of successful data file uploads, in order
+        data_puts: Vec<String>,
+        /// Reject data uploads once this many have succeeded
+        fail_after: Option<usize>,
+        /// Paths of successful data file deletions, in order
+        data_deletes: Vec<String>,
+        /// Reject data deletions once this many have succeeded
+        fail_deletes_after: Option<usize>,
+        /// Reject deletions of manifests and their companions
+        fail_manifest_deletes: bool,
+        /// Store the next this many data uploads with a flipped byte
+        corrupt_puts: usize,
+        /// Reject the next this many data uploads
+        flaky_puts: usize,
+        /// Paths of data file downloads, in order
+        data_gets: Vec<String>,
+        /// Paths of full manifest downloads, in order
+        manifest_gets: Vec<String>,
+        /// Cancelled when the first data upload arrives
+        shutdown_on_put: Option<CancellationToken>,
+        /// Hold every manifest download this long
+        slow_manifest_gets: Option<Duration>,
+        /// Manifest downloads being held, and the most held at once
+        manifest_gets_in_flight: usize,
+        max_manifest_gets_in_flight: usize,
+        /// Fail downloads of existing files whose paths end in this
+        fail_gets_of: Option<&'static str>,
+        /// Answer Range requests with partial content
+        ranges: bool,
+        /// Paths and byte ranges of the partial downloads served
+        range_gets: Vec<(String, String)>,
+    }
+
+    /// Whether `path` holds file contents rather than a manifest, one of
+    /// its companions or the run summary
+    fn is_data_path(path: &str) -> bool {
+        !path.contains("manifest") && !path.ends_with(&format!("/{}", RUN_SUMMARY_FILE))
+    }
+
+    async fn handle_request(socket: &mut tokio::net::TcpStream, storage: &Mutex<MockStorage>) -> Option<()> {
+        let mut request = Request::read(socket).await?;
+        let body = std::mem::take(&mut request.body);
+        let (method, path) = (request.method.as_str(), request.path.clone());
+
+        let delay = storage.lock().unwrap().slow_manifest_gets;
+        if let Some(delay) = delay.filter(|_| method == "GET" && path.contains("manifest")) {
+            {
+                let mut storage = storage.lock().unwrap();
+                storage.manifest_gets_in_flight += 1;
+                storage.max_manifest_gets_in_flight = storage.max_manifest_gets_in_flight.max(storage.manifest_gets_in_flight);
+            }
+            tokio::time::sleep(delay).await;
+            storage.lock().unwrap().manifest_gets_in_flight -= 1;
+        }
+
+        let (status, response_body) = {
+            let mut storage = storage.lock().unwrap();
+            match method {
+                "MKCOL" => (201, Vec::new()),
+                "PUT" => {
+                    let is_data = is_data_path(&path);
+                    if is_data && storage.fail_after.is_some_and(|n| storage.data_puts.len() >= n) {
+                        (500, Vec::new())
+                    } else if is_data && storage.flaky_puts > 0 {
+                        storage.flaky_puts -= 1;
+                        (503, Vec::new())
+                    } else {
+                        let mut body = body;
+                        if is_data {
+                            if let Some(shutdown) = storage.shutdown_on_put.take() {
+                                shutdown.cancel();
+                            }
+                            storage.data_puts.push(path.clone());
+                            if storage.corrupt_puts > 0 && !body.is_empty() {
+                                storage.corrupt_puts -= 1;
+                                body[0] ^= 0xFF;
+                            }
+                        }
+                        storage.files.insert(path, body);
+                        (201, Vec::new())
+                    }
+                }
+                "GET" if storage.fail_gets_of.is_some_and(|suffix| path.ends_with(suffix))
+                    && storage.files.contains_key(&path) => (500, Vec::new()),
+                "GET" if storage.ranges && request.head.to_ascii_lowercase().contains("range: bytes=") => {
+                    let range = request.head.lines()
+                        .find_map(|l| l.to_ascii_lowercase().strip_prefix("range: bytes=").map(str::to_string))
+                        .unwrap();
+                    let (start, end) = range.trim().split_once('-').unwrap();
+                    let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+                    match storage.files.get(&path).cloned() {
+                        Some(data) => {
+                            storage.range_gets.push((path, range.trim().to_string()));
+                            (206, data[start..=end].to_vec())
+                        }
+                        None => (404, Vec::new()),
+                    }
+                }
+                "GET" | "HEAD" => match storage.files.get(&path).cloned() {
+                    Some(data) => {
+                        if method == "GET" && is_data_path(&path) {
+                            storage.data_gets.push(path);
+                        } else if method == "GET" && path.ends_with("/manifest.json.enc") {
+                            storage.manifest_gets.push(path);
+                        }
+                        (200, data)
+                    }
+                    None => (404, Vec::new()),
+                },
+                "DELETE" => {
+                    let is_data = is_data_path(&path);
+                    if is_data && storage.fail_deletes_after.is_some_and(|n| storage.data_deletes.len() >= n) {
+                        (500, Vec::new())
+                    } else if !is_data && storage.fail_manifest_deletes {
+                        (500, Vec::new())
+                    } else {
+                        match storage.files.remove(&path) {
+                            Some(_) => {
+                                if is_data {
+                                    storage.data_deletes.push(path);
+                                }
+                                (204, Vec::new())
+                            }
+                            None => (404, Vec::new()),
+                        }
+                    }
+                }
+                // Lists every file below the path, like an infinite-depth listing
+                "PROPFIND" => {
+                    let prefix = format!("{}/", path.trim_end_matches('/'));
+                    let mut xml = String::from("<D:multistatus xmlns:D=\"DAV:\">\n");
+                    for file in storage.files.keys().filter(|file| file.starts_with(&prefix)) {
+                        xml.push_str(&format!("<D:response>\n<D:href>{}</D:href>\n</D:response>\n", file));
+                    }
+                    xml.push_str("</D:multistatus>\n");
+                    (207, xml.into_bytes())
+                }
+                _ => (404, Vec::new()),
+            }
+        };
+
+        request.respond(socket, status, &response_body).await;
+        Some(())
+    }
+
+    async fn mock_webdav(storage: Arc<Mutex<MockStorage>>) -> String {
+        let (listener, endpoint) = test_server::bind().await;
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let storage = storage.clone();
+                tokio::spawn(async move {
+                    handle_request(&mut socket, &storage).await;
+                });
+            }
+        });
+        endpoint
+    }
+
+    fn test_backup(endpoint: &str, data_dir: &Path, encryption: &EncryptionManager) -> DirectUploadBackup {
+        let config = test_config(endpoint, data_dir);
+        let hetzner = test_server::client(endpoint);
+
+        DirectUploadBackup::new(config, hetzner, encryption.for_algorithm(encryption.algorithm()), None).unwrap()
+    }
+
+    fn create_source_files(dir: &Path, count: usize) -> Vec<PathBuf> {
+        (0..count).map(|i| {
+            let path = dir.join(format!("file{}.txt", i));
+            std::fs::write(&path, format!("contents of file {}", i)).unwrap();
+            path
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_backup_resumes() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 6);
+        let paths = vec![source.path().to_path_buf()];
+        let state_dir = ResumeState::state_dir(data_dir.path());
+
+        let storage = Arc::new(Mutex::new(MockStorage { fail_after: Some(2), ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+
+        // First run is interrupted after two files reach the server
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        assert!(backup.create_backup(&paths).await.is_err());
+
+        let interrupted = ResumeState::find_latest(&state_dir).await.unwrap().unwrap();
+        assert_eq!(interrupted.entries.len(), 2);
+        let first_run_uploads = std::mem::take(&mut storage.lock().unwrap().data_puts);
+        assert_eq!(first_run_uploads.len(), 2);
+
+        // Second run resumes the same backup and only uploads the rest
+        storage.lock().unwrap().fail_after = None;
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        assert_eq!(manifest.backup_id, interrupted.backup_id);
+        let second_run_uploads = storage.lock().unwrap().data_puts.clone();
+        assert_eq!(second_run_uploads.len(), 4);
+        assert!(second_run_uploads.iter().all(|p| !first_run_uploads.contains(p)));
+
+        // The manifest covers every file from both runs
+        assert_eq!(manifest.file_count, 6);
+        assert_eq!(manifest.files.len(), 6);
+        for file in &files {
+            assert!(manifest.files.iter().any(|e| &e.local_path == file));
+        }
+        assert!(!ResumeState::exists(&state_dir, &manifest.backup_id).await);
+    }
+
+    #[tokio::test]
+    async fn test_failed_backup_and_restore_leave_no_temp_files() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 4);
+        let paths = vec![source.path().to_path_buf()];
+        let stray = |dir: &Path| -> Vec<PathBuf> {
+            walkdir::WalkDir::new(dir).into_iter()
+                .map(|entry| entry.unwrap().into_path())
+                .filter(|path| path != dir && (dir == temp_dir.path() || path.to_string_lossy().ends_with(".tmp")))
+                .collect()
+        };
+
+        // Uploads spill to temp files, and the server fails after one file
+        let storage = Arc::new(Mutex::new(MockStorage { fail_after: Some(1), ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        backup.temp = TempFiles::new(temp_dir.path().to_path_buf()).with_spill_threshold(0);
+        assert!(backup.create_backup(&paths).await.is_err());
+        assert_eq!(stray(temp_dir.path()), Vec::<PathBuf>::new());
+        assert_eq!(stray(data_dir.path()), Vec::<PathBuf>::new());
+
+        storage.lock().unwrap().fail_after = None;
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        // A damaged blob fails its file after the download was staged
+        let damaged = manifest.files.iter().find(|e| e.local_path == files[2]).unwrap();
+        storage.lock().unwrap().files.get_mut(&damaged.remote_path).unwrap()[20] ^= 0xFF;
+        assert!(backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.is_err());
+        assert_eq!(stray(temp_dir.path()), Vec::<PathBuf>::new());
+        assert!(!restore_dir.path().join(files[2].strip_prefix("/").unwrap()).exists());
+        assert!(restore_dir.path().join(files[0].strip_prefix("/").unwrap()).exists());
+    }
+
+    #[tokio::test]
+    async fn test_resume_discarded_when_sources_change() {
+        let source = TempDir::new().unwrap();
+        let other_source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 3);
+        create_source_files(other_source.path(), 2);
+        let state_dir = ResumeState::state_dir(data_dir.path());
+
+        let storage = Arc::new(Mutex::new(MockStorage { fail_after: Some(1), ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+
+        assert!(backup.create_backup(&[source.path().to_path_buf()]).await.is_err());
+        let interrupted = ResumeState::find_latest(&state_dir).await.unwrap().unwrap();
+
+        // A different source set starts over instead of resuming
+        storage.lock().unwrap().fail_after = None;
+        storage.lock().unwrap().data_puts.clear();
+        let manifest = backup.create_backup(&[other_source.path().to_path_buf()]).await.unwrap();
+
+        assert_eq!(manifest.files.len(), 2);
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 2);
+        assert!(!ResumeState::exists(&state_dir, &interrupted.backup_id).await);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_file_is_skipped_and_recorded() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 3);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+
+        // The second file can't be opened, as if another process held it
+        let locked = files[1].clone();
+        let reader: SourceReader = Arc::new(move |path| {
+            if path == locked {
+                return Err(std::io::ErrorKind::PermissionDenied.into());
+            }
+            std::fs::read(path)
+        });
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption).with_source_reader(reader);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        // The backup completes with the other files and the reason recorded
+        assert_eq!(manifest.file_count, 2);
+        assert!(manifest.files.iter().all(|e| e.local_path != files[1]));
+        assert_eq!(manifest.skipped_files, vec![SkippedFile {
+            path: files[1].clone(),
+            reason: "permission denied".to_string(),
+        }]);
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 2);
+        let stored = backup.download_manifest(&manifest.backup_id).await.unwrap();
+        assert_eq!(stored.skipped_files, manifest.skipped_files);
+
+        // Once readable, the next incremental backup picks the file up
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let incremental = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(incremental.files.len(), 1);
+        assert_eq!(incremental.files[0].local_path, files[1]);
+        assert!(incremental.skipped_files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_finishes_current_file_and_saves_resume_point() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 4);
+        let paths = vec![source.path().to_path_buf()];
+        let state_dir = ResumeState::state_dir(data_dir.path());
+
+        // Shutdown is requested while the first file is being uploaded
+        let shutdown = CancellationToken::new();
+        let storage = Arc::new(Mutex::new(MockStorage {
+            shutdown_on_put: Some(shutdown.clone()),
+            ..Default::default()
+        }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_concurrency(1)
+            .with_shutdown(shutdown.clone());
+        let err = backup.create_backup(&paths).await.unwrap_err();
+        assert!(err.to_string().contains("stopped by shutdown"));
+
+        // The in-flight file completed and is recorded in the resume point
+        let first_run_uploads = std::mem::take(&mut storage.lock().unwrap().data_puts);
+        assert_eq!(first_run_uploads.len(), 1);
+        let resume_point = ResumeState::find_latest(&state_dir).await.unwrap().unwrap();
+        assert_eq!(resume_point.entries.len(), 1);
+
+        // The next run picks up from it and uploads only the remaining files
+        let manifest = test_backup(&endpoint, data_dir.path(), &encryption)
+            .create_backup(&paths).await.unwrap();
+        assert_eq!(manifest.backup_id, resume_point.backup_id);
+        assert_eq!(manifest.file_count, 4);
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 3);
+        assert!(!ResumeState::exists(&state_dir, &manifest.backup_id).await);
+    }
+
+    #[tokio::test]
+    async fn test_listing_uses_summaries_of_compressed_manifests() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 3);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut backup = test_backup(&endpoint, data_dir.path(), &encryption).with_list_cache(None);
+        Arc::make_mut(&mut backup.config).backup.compress_manifests = true;
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        let header: crate::encrypted_manifest::ManifestHeader = serde_json::from_slice(
+            &storage.lock().unwrap().files[&format!("/skylock/backups/{}/manifest_header.json", manifest.backup_id)]
+        ).unwrap();
+        assert!(header.manifest_compressed);
+
+        // The compressed manifest loads and restores transparently
+        let loaded = backup.load_manifest(&manifest.backup_id).await.unwrap();
+        assert_eq!(loaded.files.len(), 3);
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        let restored = restore_dir.path().join(files[0].strip_prefix("/").unwrap());
+        assert_eq!(std::fs::read(restored).unwrap(), std::fs::read(&files[0]).unwrap());
+
+        // Listing reads only the summary
+        storage.lock().unwrap().manifest_gets.clear();
+        let summaries = backup.list_backup_summaries().await.unwrap();
+        assert_eq!(summaries, vec![crate::ManifestSummary::from_manifest(&manifest)]);
+        assert!(storage.lock().unwrap().manifest_gets.is_empty());
+
+        // Backups from before summaries fall back to the full manifest
+        storage.lock().unwrap().files.remove(&format!("/skylock/backups/{}/manifest_summary.json.enc", manifest.backup_id));
+        let summaries = backup.list_backup_summaries().await.unwrap();
+        assert_eq!(summaries[0].file_count, 3);
+        assert_eq!(storage.lock().unwrap().manifest_gets.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_listing_many_backups_concurrently_skips_unreadable() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 2);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_list_concurrency(8)
+            .with_list_cache(None);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        // Plenty more backups, and one whose manifest is garbage
+        for i in 0..40 {
+            let mut copy = manifest.clone();
+            copy.backup_id = format!("20240101_{:06}", i);
+            copy.timestamp = manifest.timestamp - chrono::Duration::hours(i + 1);
+            backup.upload_manifest(&copy).await.unwrap();
+        }
+        let broken = "20240101_000007";
+        {
+            let mut storage = storage.lock().unwrap();
+            for name in ["manifest.json.enc", "manifest_summary.json.enc"] {
+                storage.files.insert(format!("/skylock/backups/{}/{}", broken, name), b"not a manifest".to_vec());
+            }
+            storage.slow_manifest_gets = Some(Duration::from_millis(50));
+        }
+
+        let manifests = backup.list_backups().await.unwrap();
+        assert_eq!(manifests.len(), 40);
+        assert!(manifests.iter().all(|m| m.backup_id != broken));
+        assert!(manifests.windows(2).all(|pair| pair[0].timestamp >= pair[1].timestamp));
+        let summaries = backup.list_backup_summaries().await.unwrap();
+        assert_eq!(summaries.len(), 40);
+        let most = storage.lock().unwrap().max_manifest_gets_in_flight;
+        assert!(most > 1 && most <= 8, "{} manifest downloads at once", most);
+
+        // A cached listing is reused until this client changes the backups
+        storage.lock().unwrap().slow_manifest_gets = None;
+        let backup = backup.with_list_cache(Some(Duration::from_secs(60)));
+        let listed = backup.list_backup_summaries().await.unwrap();
+        storage.lock().unwrap().files.retain(|path, _| !path.contains("20240101_000001"));
+        assert_eq!(backup.list_backup_summaries().await.unwrap(), listed);
+        backup.delete_backup("20240101_000002").await.unwrap();
+        assert_eq!(backup.list_backup_summaries().await.unwrap().len(), 38);
+    }
+
+    #[tokio::test]
+    async fn test_tags_and_note_stored_with_backup() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_tags(vec!["pre-upgrade".to_string(), KEEP_TAG.to_string()], Some("before kernel 6.8".to_string()));
+        let manifest = backup.create_backup(&paths).await.unwrap();
+        assert!(manifest.has_tag(KEEP_TAG));
+
+        let loaded = backup.load_manifest(&manifest.backup_id).await.unwrap();
+        assert_eq!(loaded.tags, vec!["pre-upgrade", KEEP_TAG]);
+        assert_eq!(loaded.note.as_deref(), Some("before kernel 6.8"));
+
+        let summaries = backup.list_backup_summaries().await.unwrap();
+        assert_eq!(summaries[0].tags, loaded.tags);
+        assert_eq!(summaries[0].note, loaded.note);
+
+        // Untagged manifests don't serialize the fields at all
+        let mut untagged = loaded.clone();
+        untagged.tags.clear();
+        untagged.note = None;
+        let json = serde_json::to_string(&untagged).unwrap();
+        assert!(!json.contains("\"tags\"") && !json.contains("\"note\""));
+    }
+
+    #[tokio::test]
+    async fn test_restore_with_wrong_key_reports_key_mismatch() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let manifest = test_backup(&endpoint, data_dir.path(), &encryption)
+            .create_backup(&paths).await.unwrap();
+
+        storage.lock().unwrap().data_gets.clear();
+
+        let wrong = EncryptionManager::new("not_the_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &wrong);
+        let err = backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap_err();
+        assert!(matches!(err, SkylockError::WrongKey(_)), "unexpected error: {}", err);
+        assert!(storage.lock().unwrap().data_gets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_manifest_signatures_enforced_on_load() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut unsigned_backup = test_backup(&endpoint, data_dir.path(), &encryption).with_list_cache(None);
+        let manifest = unsigned_backup.create_backup(&paths).await.unwrap();
+        let backup_id = manifest.backup_id.clone();
+
+        // Unsigned manifests load unless the policy requires signatures
+        assert!(unsigned_backup.load_manifest(&backup_id).await.unwrap().signature.is_none());
+        unsigned_backup.signature_policy = Arc::new(SignaturePolicy::new(None, true));
+        let err = unsigned_backup.load_manifest(&backup_id).await.unwrap_err();
+        assert!(err.to_string().contains("is not signed"), "unexpected error: {}", err);
+        let err = unsigned_backup.restore_backup(&backup_id, restore_dir.path()).await.unwrap_err();
+        assert!(err.to_string().contains("is not signed"), "unexpected error: {}", err);
+
+        // A key generated in the data directory signs existing and new manifests
+        let key = SecureSigningKey::generate("manifest-signing".to_string(), None).unwrap();
+        std::fs::create_dir_all(data_dir.path().join("keys")).unwrap();
+        key.save_to_file(manifest_signing::signing_key_path(data_dir.path())).unwrap();
+        key.save_public_key_to_file(manifest_signing::public_key_path(data_dir.path())).unwrap();
+        let mut backup = test_backup(&endpoint, data_dir.path(), &encryption).with_list_cache(None);
+        let signed = backup.sign_backup_manifest(&backup_id).await.unwrap();
+        assert_eq!(signed.signature.as_ref().unwrap().fingerprint, key.metadata().fingerprint);
+
+        backup.signature_policy = Arc::new(SignaturePolicy::new(Some(key.public_signature_key()), true));
+        assert!(backup.load_manifest(&backup_id).await.unwrap().signature.is_some());
+        backup.restore_backup(&backup_id, restore_dir.path()).await.unwrap();
+
+        // A signed manifest modified afterwards is rejected, whether or not
+        // signatures are required
+        let mut tampered = signed.clone();
+        tampered.files[0].size += 1;
+        unsigned_backup.upload_manifest(&tampered).await.unwrap();
+        let err = backup.load_manifest(&backup_id).await.unwrap_err();
+        assert!(err.to_string().contains("signature of backup"), "unexpected error: {}", err);
+        backup.signature_policy = Arc::new(SignaturePolicy::new(Some(key.public_signature_key()), false));
+        assert!(backup.load_manifest(&backup_id).await.is_err());
+
+        // So is one signed by a different key
+        let other = SecureSigningKey::generate("manifest-signing".to_string(), None).unwrap();
+        let mut foreign = manifest.clone();
+        sign_manifest(&mut foreign, &other, 0).unwrap();
+        unsigned_backup.upload_manifest(&foreign).await.unwrap();
+        let err = backup.load_manifest(&backup_id).await.unwrap_err();
+        assert!(err.to_string().contains("fingerprint mismatch"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_missing_configured_public_key_is_an_error() {
+        let data_dir = TempDir::new().unwrap();
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let key = SecureSigningKey::generate("manifest-signing".to_string(), None).unwrap();
+        std::fs::create_dir_all(data_dir.path().join("keys")).unwrap();
+        key.save_to_file(manifest_signing::signing_key_path(data_dir.path())).unwrap();
+        let mut config = (*test_backup("http://127.0.0.1:1", data_dir.path(), &encryption).config).clone();
+        config.backup.manifest_public_key = Some(data_dir.path().join("missing.pub.pem"));
+        let client = test_server::client("http://127.0.0.1:1");
+
+        // Not silently replaced by the local signing key's public half
+        let Err(err) = DirectUploadBackup::new(config, client, encryption.for_algorithm(encryption.algorithm()), None) else {
+            panic!("opened with a missing public key");
+        };
+        assert!(err.to_string().contains("missing.pub.pem"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_sftp_without_key_is_an_error() {
+        let data_dir = TempDir::new().unwrap();
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut config = (*test_backup("http://127.0.0.1:1", data_dir.path(), &encryption).config).clone();
+        config.hetzner.protocol = Some("sftp".to_string());
+        let client = test_server::client("http://127.0.0.1:1");
+
+        // Not silently switched to WebDAV
+        let Err(err) = DirectUploadBackup::new(config, client, encryption.for_algorithm(encryption.algorithm()), None) else {
+            panic!("opened without the SFTP key");
+        };
+        assert!(err.to_string().contains("[hetzner.sftp]"), "unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_manifest_download_rejected() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 3);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+        backup.upload_manifest_legacy(&manifest).await.unwrap();
+
+        let encrypted_path = format!("/skylock/backups/{}/manifest.json.enc", manifest.backup_id);
+        let legacy_path = format!("/skylock/backups/{}/manifest.json", manifest.backup_id);
+        assert!(storage.lock().unwrap().files.contains_key(&format!("{}.sha256", encrypted_path)));
+        assert!(storage.lock().unwrap().files.contains_key(&format!("{}.sha256", legacy_path)));
+        assert_eq!(backup.load_manifest(&manifest.backup_id).await.unwrap().files.len(), 3);
+
+        // Cut off mid-transfer, both formats fail the checksum before parsing
+        for path in [&encrypted_path, &legacy_path] {
+            let mut storage = storage.lock().unwrap();
+            let data = storage.files.get_mut(path.as_str()).unwrap();
+            data.truncate(data.len() / 2);
+        }
+        let err = backup.load_manifest(&manifest.backup_id).await.unwrap_err();
+        assert!(matches!(err, SkylockError::Integrity(_)), "unexpected error: {}", err);
+        assert!(err.to_string().contains("manifest download corrupted, retry"), "{}", err);
+        let err = backup.download_manifest_legacy(Path::new(&legacy_path)).await.unwrap_err();
+        assert!(matches!(err, SkylockError::Integrity(_)), "unexpected error: {}", err);
+
+        // Manifests from before checksums load unchecked
+        let mut legacy = serde_json::to_vec(&manifest).unwrap();
+        {
+            let mut storage = storage.lock().unwrap();
+            storage.files.remove(&format!("{}.sha256", legacy_path));
+            std::mem::swap(storage.files.get_mut(&legacy_path).unwrap(), &mut legacy);
+        }
+        assert_eq!(backup.download_manifest_legacy(Path::new(&legacy_path)).await.unwrap().backup_id, manifest.backup_id);
+
+        // A checksum that exists but can't be downloaded isn't skipped
+        storage.lock().unwrap().fail_gets_of = Some(".sha256");
+        assert!(backup.download_manifest_legacy(Path::new(&legacy_path)).await.is_ok());
+        storage.lock().unwrap().files.insert(format!("{}.sha256", legacy_path), Vec::new());
+        let err = backup.download_manifest_legacy(Path::new(&legacy_path)).await.unwrap_err();
+        assert!(!matches!(err, SkylockError::Integrity(_)), "unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_header_failure_not_guessed() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 1);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap()
+            .with_algorithm(AeadAlgorithm::ChaCha20Poly1305);
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&files).await.unwrap();
+        let header_path = format!("/skylock/backups/{}/manifest_header.json", manifest.backup_id);
+
+        // A failed header download is reported, not replaced by the default cipher
+        storage.lock().unwrap().fail_gets_of = Some("manifest_header.json");
+        let err = backup.load_manifest(&manifest.backup_id).await.unwrap_err();
+        assert!(!matches!(err, SkylockError::Encryption(_)), "unexpected error: {}", err);
+        storage.lock().unwrap().fail_gets_of = None;
+
+        storage.lock().unwrap().files.insert(header_path.clone(), b"{".to_vec());
+        let err = backup.load_manifest(&manifest.backup_id).await.unwrap_err();
+        assert!(err.to_string().contains("Invalid manifest header"), "unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_backup_rejected_while_destination_locked() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+
+        // Another run holds the lock for the same storage box
+        let held = BackupLock::acquire(data_dir.path(), &backup.lock_destination(), STALE_LOCK_AFTER).unwrap();
+        let err = backup.create_backup(&paths).await.unwrap_err();
+        assert!(matches!(err, SkylockError::Locked(_)), "unexpected error: {}", err);
+        assert!(storage.lock().unwrap().files.is_empty());
+
+        // Once released the backup runs, and releases the lock itself
+        drop(held);
+        backup.create_backup(&paths).await.unwrap();
+        assert!(!BackupLock::lock_file_path(data_dir.path(), &backup.lock_destination()).exists());
+    }
+
+    #[test]
+    fn test_locked_keyfile_is_an_error() {
+        let data_dir = TempDir::new().unwrap();
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let config = (*test_backup("http://127.0.0.1:1", data_dir.path(), &encryption).config).clone();
+        let keyfile = KeyRotationManager::keyfile_path(data_dir.path());
+        KeyRotationManager::create_keyfile(keyfile, "another_password", Default::default()).unwrap();
+        let client = test_server::client("http://127.0.0.1:1");
+
+        // Not silently replaced by the password-derived key
+        let Err(err) = DirectUploadBackup::new(config, client, encryption.for_algorithm(encryption.algorithm()), None) else {
+            panic!("opened with a keyfile the key doesn't unlock");
+        };
+        assert!(matches!(err, SkylockError::WrongKey(_)), "unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_backups_after_key_rotation() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+        let keyfile = KeyRotationManager::keyfile_path(data_dir.path());
+        KeyRotationManager::create_keyfile(keyfile.clone(), "test_password", Default::default()).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+
+        let old = test_backup(&endpoint, data_dir.path(), &encryption).create_backup(&paths).await.unwrap();
+        assert_eq!(old.key_version, Some(1));
+
+        KeyRotationManager::open_keyfile(keyfile, "test_password").unwrap()
+            .rotate_keyfile("test_password", "test rotation").unwrap();
+
+        // New backups use the new key version
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let new = backup.create_backup(&paths).await.unwrap();
+        assert_ne!(new.backup_id, old.backup_id);
+        assert_eq!(new.key_version, Some(2));
+
+        // The old backup still restores with the old key version
+        backup.restore_backup(&old.backup_id, restore_dir.path()).await.unwrap();
+        for file in &files {
+            let restored = restore_dir.path().join(file.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read(restored).unwrap(), std::fs::read(file).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backups_restore_after_recovery_key_import() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+        let keyfile = KeyRotationManager::keyfile_path(data_dir.path());
+        KeyRotationManager::create_keyfile(keyfile.clone(), "test_password", Default::default()).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let manifest = test_backup(&endpoint, data_dir.path(), &encryption)
+            .create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        // The old install is lost; only the recovery key and passphrase remain
+        let recovery_key = KeyRotationManager::export_recovery_key(keyfile, "test_password").unwrap();
+        drop(data_dir);
+        let new_data_dir = TempDir::new().unwrap();
+        let backup = test_backup(&endpoint, new_data_dir.path(), &encryption);
+        assert!(backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.is_err());
+
+        KeyRotationManager::import_recovery_key(
+            &recovery_key,
+            KeyRotationManager::keyfile_path(new_data_dir.path()),
+            "test_password",
+        ).unwrap();
+        let backup = test_backup(&endpoint, new_data_dir.path(), &encryption);
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for file in &files {
+            let restored = restore_dir.path().join(file.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read(restored).unwrap(), std::fs::read(file).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_config_bundle_round_trip_keeps_secrets_out() {
+        let data_dir = TempDir::new().unwrap();
+        let keyfile = KeyRotationManager::keyfile_path(data_dir.path());
+        KeyRotationManager::create_keyfile(keyfile, "test_password", Default::default()).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        {
+            let config = Arc::make_mut(&mut backup.config);
+            config.hetzner.password = "storage-box-secret".to_string();
+            config.hetzner.encryption_key = "test_password".to_string();
+            config.notifications.webhook_secret = Some("webhook-secret".to_string());
+            config.notifications.email = Some(skylock_core::EmailConfig {
+                smtp_host: "smtp.example.com".to_string(),
+                smtp_port: 587,
+                username: Some("alerts".to_string()),
+                password: Some("smtp-secret".to_string()),
+                from: "skylock@example.com".to_string(),
+                to: vec!["admin@example.com".to_string()],
+            });
+            config.backup.backup_paths = vec![PathBuf::from("/home/user/documents")];
+        }
+
+        let bundle = ConfigBundle::new(&backup.config, "test_password").unwrap();
+        backup.upload_config_bundle(&bundle, "test_password").await.unwrap();
+
+        // Neither the stored bundle nor its decrypted contents hold a secret
+        let stored = storage.lock().unwrap().files[crate::CONFIG_BUNDLE_PATH].clone();
+        let stored = String::from_utf8(stored).unwrap();
+        for secret in ["storage-box-secret", "webhook-secret", "smtp-secret", "test_password", "/home/user/documents"] {
+            assert!(!stored.contains(secret), "{} stored in the clear", secret);
+        }
+
+        // A fresh install with only the storage box credentials and the passphrase
+        let new_data_dir = TempDir::new().unwrap();
+        let mut fresh = test_backup(&endpoint, new_data_dir.path(), &encryption);
+        Arc::make_mut(&mut fresh.config).hetzner.password = "storage-box-secret".to_string();
+        let err = fresh.download_config_bundle("wrong passphrase").await.unwrap_err();
+        assert!(matches!(err, SkylockError::WrongKey(_)), "unexpected error: {}", err);
+
+        let recovered = fresh.download_config_bundle("test_password").await.unwrap();
+        let plaintext = serde_json::to_string(&recovered).unwrap();
+        for secret in ["storage-box-secret", "webhook-secret", "smtp-secret", "test_password"] {
+            assert!(!plaintext.contains(secret), "{} kept in the bundle", secret);
+        }
+        assert!(recovered.redacted.contains(&"hetzner.password".to_string()));
+        assert!(recovered.redacted.contains(&"hetzner.encryption_key".to_string()));
+        assert!(recovered.redacted.contains(&"notifications.email.password".to_string()));
+
+        let config = recovered.restored_config(&fresh.config);
+        assert_eq!(config.backup.backup_paths, vec![PathBuf::from("/home/user/documents")]);
+        assert_eq!(config.hetzner.password, "storage-box-secret");
+        assert!(config.notifications.webhook_secret.is_none());
+        let email = config.notifications.email.as_ref().unwrap();
+        assert_eq!(email.username.as_deref(), Some("alerts"));
+        assert!(email.password.is_none());
+
+        // The keyfile comes back wrapped, unlocked only by the passphrase
+        let recovery_key = recovered.recovery_key.unwrap();
+        let new_keyfile = KeyRotationManager::keyfile_path(new_data_dir.path());
+        assert!(KeyRotationManager::import_recovery_key(&recovery_key, new_keyfile.clone(), "wrong passphrase").is_err());
+        KeyRotationManager::import_recovery_key(&recovery_key, new_keyfile, "test_password").unwrap();
+    }
+
+    /// Manifest as written before schema versioning: no `schema_version`,
+    /// encryption version or KDF parameters, compression as a bare flag
+    const V1_MANIFEST: &str = r#"{
+        "backup_id": "20240101_020000",
+        "timestamp": "2024-01-01T02:00:00Z",
+        "files": [{
+            "local_path": "/home/user/notes.txt",
+            "remote_path": "/skylock/backups/20240101_020000/home/user/notes.txt.zst.enc",
+            "size": 12,
+            "hash": "abc123",
+            "compressed": true,
+            "encrypted": true,
+            "timestamp": "2024-01-01T02:00:01Z"
+        }],
+        "total_size": 12,
+        "file_count": 1,
+        "source_paths": ["/home/user"]
+    }"#;
+
+    /// Manifest from a later schema with fields this build doesn't know
+    const FUTURE_MANIFEST: &str = r#"{
+        "schema_version": 99,
+        "backup_id": "20300101_020000",
+        "timestamp": "2030-01-01T02:00:00Z",
+        "chunks": [{"id": "c1", "size": 4096}],
+        "source_paths": ["/home/user"]
+    }"#;
+
+    #[test]
+    fn test_v1_manifest_upgrades_to_current_schema() {
+        let manifest = BackupManifest::from_json(V1_MANIFEST.as_bytes()).unwrap();
+        assert_eq!(manifest.schema_version, BackupManifest::SCHEMA_VERSION);
+        assert_eq!(manifest.backup_id, "20240101_020000");
+        assert_eq!(manifest.encryption_version, "v1");
+        assert!(manifest.kdf_params.is_none());
+        assert_eq!(manifest.aead_algorithm, AeadAlgorithm::Aes256Gcm);
+        assert_eq!(manifest.storage_tier, StorageTier::Standard);
+        assert_eq!(manifest.files[0].compression_algorithm(), CompressionAlgorithm::Zstd);
+        assert!(manifest.base_backup_id.is_none() && manifest.deleted_paths.is_empty());
+
+        // The upgraded manifest is written and read back as the current schema
+        let json = serde_json::to_vec(&manifest).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value["schema_version"], BackupManifest::SCHEMA_VERSION);
+        let reloaded = BackupManifest::from_json(&json).unwrap();
+        assert_eq!(reloaded.encryption_version, "v1");
+        assert_eq!(reloaded.files.len(), 1);
+
+        // Unversioned manifests with KDF parameters keep their encryption version
+        let mut value: serde_json::Value = serde_json::from_str(V1_MANIFEST).unwrap();
+        value["encryption_version"] = "v2".into();
+        value["kdf_params"] = serde_json::to_value(EncryptionManager::new("pw").unwrap().kdf_params()).unwrap();
+        let manifest = BackupManifest::from_json(&serde_json::to_vec(&value).unwrap()).unwrap();
+        assert_eq!(manifest.encryption_version, "v2");
+    }
+
+    #[tokio::test]
+    async fn test_manifest_from_newer_skylock_rejected() {
+        let err = BackupManifest::from_json(FUTURE_MANIFEST.as_bytes()).unwrap_err().to_string();
+        assert!(err.contains("20300101_020000 was made by a newer skylock"), "{}", err);
+        assert!(err.contains("schema version 99"), "{}", err);
+
+        let err = BackupManifest::from_json(br#"{"schema_version": "two"}"#).unwrap_err().to_string();
+        assert!(err.contains("Invalid manifest schema version"), "{}", err);
+
+        // Loading it from the storage box gives the same explanation
+        let data_dir = TempDir::new().unwrap();
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        storage.lock().unwrap().files.insert(
+            "/skylock/backups/20300101_020000/manifest.json".to_string(),
+            FUTURE_MANIFEST.as_bytes().to_vec(),
+        );
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let err = backup.load_manifest("20300101_020000").await.unwrap_err().to_string();
+        assert!(err.contains("made by a newer skylock"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_verify_on_upload_retries_corrupted_blob() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 1);
+
+        // The first stored copy is corrupted; the read-back catches it and re-uploads
+        let storage = Arc::new(Mutex::new(MockStorage { corrupt_puts: 1, ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_verify_on_upload(Some(2));
+        let manifest = backup.create_backup(&files).await.unwrap();
+
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 2);
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        let restored = restore_dir.path().join(files[0].strip_prefix("/").unwrap());
+        assert_eq!(std::fs::read(restored).unwrap(), std::fs::read(&files[0]).unwrap());
+
+        // Corruption that persists past the retries fails the file
+        let storage = Arc::new(Mutex::new(MockStorage { corrupt_puts: 10, ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let data_dir = TempDir::new().unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_verify_on_upload(Some(1));
+        assert!(backup.create_backup(&files).await.is_err());
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 2);
+
+        // Without verification the corrupted upload goes unnoticed
+        let storage = Arc::new(Mutex::new(MockStorage { corrupt_puts: 1, ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let data_dir = TempDir::new().unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        backup.create_backup(&files).await.unwrap();
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_download_checks_blob_hash() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 1);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_multipart_download(Some(MultipartDownloadConfig { parts: 2, min_size: 1 }));
+        let manifest = backup.create_backup(&files).await.unwrap();
+        let entry = &manifest.files[0];
+        let blob = storage.lock().unwrap().files[&entry.remote_path].clone();
+        assert_eq!(entry.blob_hash.as_deref(), Some(crate::compression_integrity::calculate_hash(&blob).as_str()));
+        backup.fetch_file_data(entry, &manifest, &ProgressBar::hidden()).await.unwrap();
+
+        // A blob changed on the server is caught before decryption
+        storage.lock().unwrap().files.get_mut(&entry.remote_path).unwrap()[0] ^= 0xFF;
+        let err = backup.fetch_file_data(entry, &manifest, &ProgressBar::hidden()).await.unwrap_err();
+        assert!(err.to_string().contains("Hash mismatch"), "unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_failed_uploads_retried_within_budget() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 1);
+        let policy = |max_total_attempts| skylock_core::RetryPolicy {
+            max_retries: 100,
+            base_delay_ms: Some(1),
+            budget: skylock_core::RetryBudget { max_total_attempts, deadline_minutes: None },
+        };
+
+        // Two rejected uploads are retried and the backup goes through
+        let storage = Arc::new(Mutex::new(MockStorage { flaky_puts: 2, ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption).with_retry_policy(policy(None));
+        backup.create_backup(&files).await.unwrap();
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 1);
+
+        // A storage box that keeps failing stops the backup at its budget
+        let storage = Arc::new(Mutex::new(MockStorage { flaky_puts: 1000, ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let data_dir = TempDir::new().unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption).with_retry_policy(policy(Some(4)));
+        let err = backup.create_backup(&files).await.unwrap_err();
+        assert!(err.to_string().contains("gave up after 4 attempts"), "{}", err);
+        assert_eq!(storage.lock().unwrap().flaky_puts, 996);
+    }
+
+    #[tokio::test]
+    async fn test_storage_tier_recorded_in_manifest() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_storage_tier(StorageTier::Archive);
+        let manifest = backup.create_backup(&files).await.unwrap();
+        assert_eq!(manifest.storage_tier, StorageTier::Archive);
+
+        // The tier survives the encrypted manifest round trip
+        let loaded = backup.load_manifest(&manifest.backup_id).await.unwrap();
+        assert_eq!(loaded.storage_tier, StorageTier::Archive);
+
+        // Manifests without the field are standard
+        let mut json = serde_json::to_value(&loaded).unwrap();
+        json.as_object_mut().unwrap().remove("storage_tier");
+        let legacy: BackupManifest = serde_json::from_value(json).unwrap();
+        assert_eq!(legacy.storage_tier, StorageTier::Standard);
+        assert!(serde_json::to_value(&legacy).unwrap().get("storage_tier").is_none());
+
+        // A failed download from an archive backup mentions cold storage
+        storage.lock().unwrap().files.retain(|path, _| !path.ends_with(".enc") || path.contains("manifest"));
+        let output = restore_dir.path().join("restored.txt");
+        let err = backup.restore_file(&manifest.backup_id, files[0].to_str().unwrap(), &output)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("cold storage"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_backups_in_the_same_second_get_distinct_ids() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+
+        // An incremental right after its base builds on it instead of
+        // overwriting it
+        let full = backup.create_backup(&paths).await.unwrap();
+        std::fs::write(&files[0], "changed").unwrap();
+        let incremental = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_ne!(incremental.backup_id, full.backup_id);
+        assert_eq!(incremental.base_backup_id.as_deref(), Some(full.backup_id.as_str()));
+        assert_eq!(backup.load_chain(&incremental.backup_id).await.unwrap().len(), 2);
+
+        // Once the current second's ID is taken, the next free suffix is used
+        let (timestamp, backup_id) = loop {
+            let timestamp = Utc::now().format("%Y%m%d_%H%M%S").to_string();
+            for taken in [timestamp.clone(), format!("{}_1", timestamp)] {
+                storage.lock().unwrap().files.insert(format!("/skylock/backups/{}/manifest.json.enc", taken), Vec::new());
+            }
+            let backup_id = backup.new_backup_id().await.unwrap();
+            // Try again if the second ended in between
+            if Utc::now().format("%Y%m%d_%H%M%S").to_string() == timestamp {
+                break (timestamp, backup_id);
+            }
+        };
+        assert_eq!(backup_id, format!("{}_2", timestamp));
+    }
+
+    #[tokio::test]
+    async fn test_incremental_backup_reuses_blob_of_moved_file() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        // Large, poorly compressible file
+        let old_path = source.path().join("video.bin");
+        let data: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        std::fs::write(&old_path, &data).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+
+        let full = backup.create_backup(&paths).await.unwrap();
+        let original = full.files.iter().find(|e| e.local_path == old_path).unwrap().clone();
+        let puts_after_full = storage.lock().unwrap().data_puts.len();
+
+        // Rename the file into a subdirectory between backups
+        let new_path = source.path().join("archive").join("video renamed.bin");
+        std::fs::create_dir_all(new_path.parent().unwrap()).unwrap();
+        std::fs::rename(&old_path, &new_path).unwrap();
+
+        let incremental = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(storage.lock().unwrap().data_puts.len(), puts_after_full, "nothing should be re-uploaded");
+        assert_eq!(incremental.base_backup_id.as_deref(), Some(full.backup_id.as_str()));
+
+        // The manifest lists the new path, pointing at the existing blob
+        assert_eq!(incremental.files.len(), 1);
+        let moved = &incremental.files[0];
+        assert_eq!(moved.local_path, new_path);
+        assert_eq!(moved.remote_path, original.remote_path);
+        assert_eq!(moved.hash, original.hash);
+        assert_eq!(moved.blob_origin, Some(BlobOrigin {
+            backup_id: full.backup_id.clone(),
+            local_path: old_path.clone(),
+        }));
+
+        // The reused blob still decrypts and restores at the new path
+        backup.restore_backup(&incremental.backup_id, restore_dir.path()).await.unwrap();
+        let restored = restore_dir.path().join(new_path.strip_prefix("/").unwrap());
+        assert_eq!(std::fs::read(restored).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_rerun_after_index_loss_uploads_nothing_new() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 3);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+
+        let first = test_backup(&endpoint, data_dir.path(), &encryption).create_backup(&paths).await.unwrap();
+        assert_eq!(std::mem::take(&mut storage.lock().unwrap().data_puts).len(), 3);
+        let stored_bytes: u64 = first.files.iter()
+            .map(|entry| storage.lock().unwrap().files[&entry.remote_path].len() as u64)
+            .sum();
+
+        // A fresh data directory has no index, so every file counts as new
+        let data_dir = TempDir::new().unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let second = backup.create_incremental_backup(&paths).await.unwrap();
+        assert!(second.base_backup_id.is_none());
+        assert!(storage.lock().unwrap().data_puts.is_empty(), "nothing should be re-uploaded");
+        assert_eq!(backup.upload_metrics().bytes_uploaded(), 0);
+        assert_eq!(backup.upload_metrics().bytes_skipped(), stored_bytes);
+
+        // Every entry points at the blob of the first backup
+        assert_eq!(second.files.len(), 3);
+        for entry in &second.files {
+            let original = first.files.iter().find(|e| e.local_path == entry.local_path).unwrap();
+            assert_eq!(entry.remote_path, original.remote_path);
+            assert_eq!(entry.blob_origin, Some(BlobOrigin {
+                backup_id: first.backup_id.clone(),
+                local_path: original.local_path.clone(),
+            }));
+        }
+        backup.restore_backup(&second.backup_id, restore_dir.path()).await.unwrap();
+        for file in &files {
+            let restored = restore_dir.path().join(file.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read(restored).unwrap(), std::fs::read(file).unwrap());
+        }
+
+        // A remote blob whose size doesn't match is uploaded again
+        let truncated = &first.files[0];
+        storage.lock().unwrap().files.get_mut(&truncated.remote_path).unwrap().pop();
+        let data_dir = TempDir::new().unwrap();
+        let third = test_backup(&endpoint, data_dir.path(), &encryption).create_backup(&paths).await.unwrap();
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 1);
+        let reuploaded = third.files.iter().find(|e| e.local_path == truncated.local_path).unwrap();
+        assert!(reuploaded.blob_origin.is_none());
+        assert!(reuploaded.remote_path.contains(&third.backup_id));
+    }
+
+    #[tokio::test]
+    async fn test_run_summary_written_with_backup() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 3);
+        std::fs::write(source.path().join("app.log"), "GET /index.html 200\n".repeat(5000)).unwrap();
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        // Stored as plain JSON with the documented field names
+        let raw: serde_json::Value = serde_json::from_slice(
+            &storage.lock().unwrap().files[&format!("/skylock/backups/{}/summary.json", manifest.backup_id)]
+        ).unwrap();
+        assert_eq!(raw["file_count"], 4);
+        assert_eq!(raw["bytes_deduplicated"], 0);
+
+        let summary = backup.load_run_summary(&manifest.backup_id).await.unwrap();
+        assert_eq!(summary.backup_id, manifest.backup_id);
+        assert!(!summary.incremental);
+        assert_eq!(summary.file_count, 4);
+        assert_eq!(summary.total_size, manifest.total_size);
+        assert_eq!(summary.bytes_uploaded, backup.upload_metrics().bytes_uploaded());
+        assert!(summary.bytes_uploaded > 0);
+        assert_eq!(summary.error_count, 0);
+        assert!(summary.compressed_size < summary.total_size);
+        assert!(summary.compression_ratio > 1.0);
+        assert!(summary.duration_secs > 0.0);
+        assert!((summary.throughput_bytes_per_sec - summary.bytes_uploaded as f64 / summary.duration_secs).abs() < 1e-6);
+        assert!(summary.started_at <= summary.completed_at);
+
+        // Without the index every file is new again, but its data is
+        // already stored and counted as deduplicated
+        let data_dir = TempDir::new().unwrap();
+        let rerun = test_backup(&endpoint, data_dir.path(), &encryption);
+        let second = rerun.create_backup(&paths).await.unwrap();
+        let summary = rerun.load_run_summary(&second.backup_id).await.unwrap();
+        assert_eq!(summary.bytes_uploaded, 0);
+        assert_eq!(summary.bytes_deduplicated, rerun.upload_metrics().bytes_skipped());
+        assert!(summary.bytes_deduplicated > 0);
+        assert_eq!(summary.throughput_bytes_per_sec, 0.0);
+
+        // `stats` lists the newest first
+        let recent = rerun.recent_run_summaries(10).await.unwrap();
+        let ids: Vec<&str> = recent.iter().map(|s| s.backup_id.as_str()).collect();
+        assert_eq!(ids, vec![second.backup_id.as_str(), manifest.backup_id.as_str()]);
+
+        // Deleting the backup removes its summary too
+        backup.delete_backup(&manifest.backup_id).await.unwrap();
+        assert!(backup.load_run_summary(&manifest.backup_id).await.unwrap_err().is_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_compare_local_classifies_changes_since_backup() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 5);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        // Edit one file, delete one, add one, rename one and rewrite one
+        // with its old content
+        std::fs::write(&files[0], "contents of file 0, edited").unwrap();
+        std::fs::remove_file(&files[1]).unwrap();
+        let added = source.path().join("new.txt");
+        std::fs::write(&added, "written after the backup").unwrap();
+        let renamed = source.path().join("renamed.txt");
+        std::fs::rename(&files[3], &renamed).unwrap();
+        std::fs::write(&files[2], "contents of file 2").unwrap();
+
+        let loaded = backup.load_manifest(&manifest.backup_id).await.unwrap();
+        let diff = BackupDiff::compare_local(&loaded, source.path(), &FileFilter::default()).await.unwrap();
+        let paths_of = |files: &[crate::diff::FileDiff]| files.iter().map(|f| PathBuf::from(&f.path)).collect::<Vec<_>>();
+
+        assert_eq!(paths_of(&diff.files_added), vec![added]);
+        assert_eq!(paths_of(&diff.files_removed), vec![files[1].clone()]);
+        assert_eq!(diff.files_modified.len(), 1);
+        let modified = &diff.files_modified[0];
+        assert_eq!(PathBuf::from(&modified.path), files[0]);
+        assert_eq!(modified.size_old, 18);
+        assert_eq!(modified.size_new, 26);
+        assert_ne!(modified.hash_old, modified.hash_new);
+        assert_eq!(diff.files_moved.len(), 1);
+        assert_eq!(PathBuf::from(&diff.files_moved[0].path_old), files[3]);
+        assert_eq!(PathBuf::from(&diff.files_moved[0].path_new), renamed);
+        // The rewritten and the untouched file
+        assert_eq!(diff.summary.files_unchanged_count, 2);
+        assert_eq!(diff.backup_id_old, manifest.backup_id);
+
+        // Excluded local files are not reported as added
+        let filter = FileFilter::new(&[], &["new.txt".to_string()]);
+        let diff = BackupDiff::compare_local(&loaded, source.path(), &filter).await.unwrap();
+        assert!(diff.files_added.is_empty());
+
+        // Backed-up files outside the compared path are ignored
+        let other = TempDir::new().unwrap();
+        let diff = BackupDiff::compare_local(&loaded, other.path(), &FileFilter::default()).await.unwrap();
+        assert!(!diff.has_changes());
+        assert_eq!(diff.summary.files_unchanged_count, 0);
+    }
+
+    /// Spans seen by a test subscriber, with their parent and fields
+    #[derive(Default, Clone)]
+    struct RecordedSpans(Arc<Mutex<Vec<RecordedSpan>>>);
+
+    #[derive(Debug)]
+    struct RecordedSpan {
+        name: &'static str,
+        parent: Option<usize>,
+        fields: HashMap<&'static str, String>,
+    }
+
+    struct SpanIndex(usize);
+
+    struct FieldRecorder<'a>(&'a mut HashMap<&'static str, String>);
+
+    impl tracing::field::Visit for FieldRecorder<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name(), value.to_string());
+        }
+    }
+
+    impl<S> tracing_subscriber::Layer<S> for RecordedSpans
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let parent = span.parent().and_then(|parent| parent.extensions().get::<SpanIndex>().map(|index| index.0));
+            let mut fields = HashMap::new();
+            attrs.record(&mut FieldRecorder(&mut fields));
+            let mut spans = self.0.lock().unwrap();
+            spans.push(RecordedSpan { name: span.name(), parent, fields });
+            span.extensions_mut().insert(SpanIndex(spans.len() - 1));
+        }
+
+        fn on_record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let index = span.extensions().get::<SpanIndex>().unwrap().0;
+            values.record(&mut FieldRecorder(&mut self.0.lock().unwrap()[index].fields));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backup_emits_phase_spans() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+
+        // The test runtime is single-threaded, so the spawned uploads are
+        // seen by the thread-local subscriber too
+        let recorded = RecordedSpans::default();
+        let subscriber = tracing_subscriber::registry().with(recorded.clone());
+        let manifest = {
+            let _guard = tracing::subscriber::set_default(subscriber);
+            backup.create_backup(&paths).await.unwrap()
+        };
+
+        let spans = recorded.0.lock().unwrap();
+        let named = |name: &str| spans.iter().enumerate()
+            .filter(|(_, span)| span.name == name)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let parent_name = |index: usize| spans[index].parent.map(|parent| spans[parent].name);
+
+        let root = named("backup");
+        assert_eq!(root.len(), 1);
+        let root = &spans[root[0]];
+        assert_eq!(root.parent, None);
+        assert_eq!(root.fields["backup_id"], manifest.backup_id);
+        assert_eq!(root.fields["incremental"], "false");
+        assert_eq!(root.fields["files"], "2");
+        assert_eq!(root.fields["bytes"], manifest.total_size.to_string());
+
+        for phase in ["scan", "upload_files", "upload_manifest"] {
+            let found = named(phase);
+            assert_eq!(found.len(), 1, "{} span", phase);
+            assert_eq!(parent_name(found[0]), Some("backup"));
+        }
+        assert_eq!(spans[named("scan")[0]].fields["files"], "2");
+
+        let file_spans = named("file");
+        assert_eq!(file_spans.len(), 2);
+        let mut recorded_paths = file_spans.iter()
+            .map(|&index| {
+                assert_eq!(parent_name(index), Some("upload_files"));
+                PathBuf::from(&spans[index].fields["path"])
+            })
+            .collect::<Vec<_>>();
+        recorded_paths.sort();
+        assert_eq!(recorded_paths, files);
+
+        // Every stage of every file runs inside that file's span
+        for phase in ["hash", "compress", "encrypt", "upload"] {
+            let found = named(phase);
+            assert_eq!(found.len(), 2, "{} spans", phase);
+            for index in found {
+                assert_eq!(parent_name(index), Some("file"));
+                assert!(spans[index].fields.contains_key("bytes"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebuilt_index_keeps_incremental_backups_incremental() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 4);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let full = backup.create_backup(&paths).await.unwrap();
+
+        // Lose the index, then change one file and delete another
+        std::fs::remove_dir_all(data_dir.path().join("indexes")).unwrap();
+        std::fs::write(&files[1], "changed after the backup").unwrap();
+        std::fs::remove_file(&files[2]).unwrap();
+
+        let stats = backup.rebuild_index(None).await.unwrap();
+        assert_eq!(stats.backup_id, full.backup_id);
+        assert_eq!((stats.verified, stats.changed, stats.missing), (2, 1, 1));
+
+        // The next backup builds on the full one and only uploads the changed file
+        storage.lock().unwrap().data_puts.clear();
+        let incremental = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(incremental.base_backup_id.as_deref(), Some(full.backup_id.as_str()));
+        assert_eq!(incremental.files.len(), 1);
+        assert_eq!(incremental.files[0].local_path, files[1]);
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 1);
+        assert_eq!(incremental.deleted_paths, vec![files[2].clone()]);
+
+        // Rebuilding from an unknown backup fails and leaves the index alone
+        assert!(backup.rebuild_index(Some("19700101_000000")).await.is_err());
+        let index = ChangeTracker::new(data_dir.path().join("indexes")).load_latest_index().await.unwrap();
+        assert_eq!(index.backup_id.as_deref(), Some(incremental.backup_id.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_appended_large_file_uploads_only_new_chunks() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let log = source.path().join("app.log");
+        let paths = vec![source.path().to_path_buf()];
+
+        // Pseudo-random, so compression can't shrink it
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = |len: usize| -> Vec<u8> {
+            (0..len).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            }).collect()
+        };
+        let mut contents = random(100 * 1024 * 1024);
+        std::fs::write(&log, &contents).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let full = backup.create_backup(&paths).await.unwrap();
+        let original = &full.files[0];
+        assert!(original.is_chunked());
+        assert!(original.remote_path.is_empty());
+        assert_eq!(original.chunks.iter().map(|c| c.size).sum::<u64>(), original.size);
+        assert_eq!(storage.lock().unwrap().data_puts.len(), original.chunks.len());
+
+        // Append 1MB; only the old last chunk and the new tail are uploaded
+        contents.extend(random(1024 * 1024));
+        std::fs::write(&log, &contents).unwrap();
+        storage.lock().unwrap().data_puts.clear();
+        let uploaded_before = backup.upload_metrics().bytes_uploaded();
+        let incremental = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(incremental.base_backup_id.as_deref(), Some(full.backup_id.as_str()));
+
+        let appended = &incremental.files[0];
+        let new_chunks = appended.chunks.iter()
+            .filter(|chunk| chunk.backup_id == incremental.backup_id)
+            .count();
+        let kept = original.chunks.len() - 1;
+        assert!(appended.chunks[..kept].iter().zip(&original.chunks).all(|(a, b)| {
+            a.hash == b.hash && a.remote_path == b.remote_path && a.backup_id == full.backup_id
+        }));
+        assert_eq!(new_chunks, appended.chunks.len() - kept);
+        assert!(new_chunks <= 3, "{} new chunks", new_chunks);
+        assert_eq!(storage.lock().unwrap().data_puts.len(), new_chunks);
+        let sent = backup.upload_metrics().bytes_uploaded() - uploaded_before;
+        assert!(sent < 6 * 1024 * 1024, "uploaded {} bytes", sent);
+
+        // The incremental restores from the chunks of both backups
+        backup.restore_backup(&incremental.backup_id, restore_dir.path()).await.unwrap();
+        let restored = std::fs::read(restore_dir.path().join(log.strip_prefix("/").unwrap())).unwrap();
+        assert!(restored == contents, "restored file differs");
+
+        // Deleting the incremental only removes the chunks it uploaded
+        let reused = DirectUploadBackup::reused_blob_paths([&incremental]);
+        assert_eq!(reused.len(), kept);
+        backup.delete_backup(&incremental.backup_id).await.unwrap();
+        let files = &storage.lock().unwrap().files;
+        assert!(original.chunks.iter().all(|chunk| files.contains_key(&chunk.remote_path)));
+        assert!(appended.chunks[kept..].iter().all(|chunk| !files.contains_key(&chunk.remote_path)));
+    }
+
+    #[tokio::test]
+    async fn test_garbage_collection_stops_on_unreadable_manifest() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 3);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        // A manifest that can't be read must not make its backup look
+        // orphaned, so nothing is deleted
+        storage.lock().unwrap().files.insert(
+            format!("/skylock/backups/{}/manifest.json.enc", manifest.backup_id),
+            b"not a manifest".to_vec(),
+        );
+        let files_before = storage.lock().unwrap().files.len();
+        let live: std::collections::HashSet<String> = backup.list_backups().await.unwrap()
+            .into_iter().map(|m| m.backup_id).collect();
+        assert!(live.is_empty());
+        let err = backup.collect_garbage(&live).await.unwrap_err();
+        assert!(err.to_string().contains(&manifest.backup_id), "{}", err);
+        assert_eq!(storage.lock().unwrap().files.len(), files_before);
+        assert!(manifest.files.iter().all(|entry| storage.lock().unwrap().files.contains_key(&entry.remote_path)));
+    }
+
+    #[tokio::test]
+    async fn test_failed_trash_operations_leave_backup_where_it_was() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 3);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+        let listed = || async { backup.list_backups().await.unwrap().into_iter().map(|m| m.backup_id).collect::<Vec<_>>() };
+        let retention = RetentionManager::new(RetentionPolicy::default()).with_trash_days(7);
+        let entry = retention.trash_entry(&manifest.backup_id, Utc::now());
+
+        // The manifest can't be removed, so the backup stays live only
+        storage.lock().unwrap().fail_manifest_deletes = true;
+        assert!(backup.trash_backup(&entry).await.is_err());
+        storage.lock().unwrap().fail_manifest_deletes = false;
+        assert_eq!(listed().await, vec![manifest.backup_id.clone()]);
+        assert!(backup.list_trash().await.unwrap().is_empty());
+
+        // A purge whose blob deletions fail leaves it in the trash
+        backup.trash_backup(&entry).await.unwrap();
+        storage.lock().unwrap().fail_deletes_after = Some(0);
+        assert!(backup.purge_trashed(&manifest.backup_id).await.is_err());
+        assert!(listed().await.is_empty());
+        assert_eq!(backup.list_trash().await.unwrap().len(), 1);
+
+        storage.lock().unwrap().fail_deletes_after = None;
+        backup.purge_trashed(&manifest.backup_id).await.unwrap();
+        assert!(listed().await.is_empty());
+        assert!(backup.list_trash().await.unwrap().is_empty());
+        assert!(manifest.files.iter().all(|entry| !storage.lock().unwrap().files.contains_key(&entry.remote_path)));
+
+        // An unreadable trash entry fails the listing instead of being skipped
+        storage.lock().unwrap().files.insert(
+            format!("{}/20240101_000000/{}", TRASH_DIR, TRASH_MARKER),
+            b"not an entry".to_vec(),
+        );
+        assert!(backup.list_trash().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_trashed_backup_recovers_before_ttl_and_is_purged_after() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 3);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+        let blobs: Vec<String> = manifest.files.iter().map(|entry| entry.remote_path.clone()).collect();
+        let blobs_present = |storage: &Mutex<MockStorage>| {
+            let storage = storage.lock().unwrap();
+            blobs.iter().filter(|blob| storage.files.contains_key(*blob)).count()
+        };
+
+        // Cleanup soft-deletes: the backup disappears but its blobs stay,
+        // even through garbage collection
+        let retention = RetentionManager::new(RetentionPolicy::default()).with_trash_days(7);
+        let now = Utc::now();
+        backup.trash_backup(&retention.trash_entry(&manifest.backup_id, now)).await.unwrap();
+        assert!(backup.list_backups().await.unwrap().is_empty());
+        let trash = backup.list_trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].backup_id, manifest.backup_id);
+        backup.collect_garbage(&std::collections::HashSet::new()).await.unwrap();
+        assert_eq!(blobs_present(&storage), blobs.len());
+
+        // Not expired yet, so nothing is purged and it can be recovered
+        assert!(backup.purge_expired_trash(now + chrono::Duration::days(6)).await.unwrap().is_empty());
+        backup.restore_deleted(&manifest.backup_id).await.unwrap();
+        assert!(backup.list_trash().await.unwrap().is_empty());
+        let listed: Vec<String> = backup.list_backups().await.unwrap().into_iter().map(|m| m.backup_id).collect();
+        assert_eq!(listed, vec![manifest.backup_id.clone()]);
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for file in &files {
+            let restored = restore_dir.path().join(file.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read(restored).unwrap(), std::fs::read(file).unwrap());
+        }
+
+        // Past the TTL it is deleted for good, blobs included
+        backup.trash_backup(&retention.trash_entry(&manifest.backup_id, now - chrono::Duration::days(10))).await.unwrap();
+        let purged = backup.purge_expired_trash(now).await.unwrap();
+        assert_eq!(purged, vec![manifest.backup_id.clone()]);
+        assert!(backup.list_trash().await.unwrap().is_empty());
+        assert!(backup.list_backups().await.unwrap().is_empty());
+        assert_eq!(blobs_present(&storage), 0);
+        assert!(backup.restore_deleted(&manifest.backup_id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_delete_resumes_without_deleting_twice() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 10);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption).with_delete_concurrency(3);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+        let blobs: Vec<String> = manifest.files.iter().map(|entry| entry.remote_path.clone()).collect();
+        assert_eq!(blobs.len(), 10);
+
+        // The storage box gives out partway: the manifest stays, so the
+        // backup is still listed, and the deletion is recorded as unfinished
+        storage.lock().unwrap().fail_deletes_after = Some(4);
+        let err = backup.delete_backup(&manifest.backup_id).await.unwrap_err();
+        assert!(err.to_string().contains("manifest was kept"), "{}", err);
+        assert_eq!(storage.lock().unwrap().data_deletes.len(), 4);
+        let listed: Vec<String> = backup.list_backups().await.unwrap().into_iter().map(|m| m.backup_id).collect();
+        assert_eq!(listed, vec![manifest.backup_id.clone()]);
+        assert_eq!(backup.interrupted_deletions().await.unwrap(), vec![manifest.backup_id.clone()]);
+
+        // A blob deleted after the last checkpoint is already missing on
+        // the next attempt, which counts it as deleted
+        let deleted = storage.lock().unwrap().data_deletes.clone();
+        let unrecorded = blobs.iter().find(|blob| !deleted.contains(*blob)).unwrap().clone();
+        storage.lock().unwrap().files.remove(&unrecorded);
+
+        storage.lock().unwrap().fail_deletes_after = None;
+        backup.delete_backup(&manifest.backup_id).await.unwrap();
+
+        // Only the blobs left were sent, each of them once
+        let storage = storage.lock().unwrap();
+        let blob_deletes: Vec<&String> = storage.data_deletes.iter().filter(|path| blobs.contains(*path)).collect();
+        assert_eq!(blob_deletes.len(), blobs.len() - 1);
+        let unique: std::collections::HashSet<&String> = blob_deletes.iter().copied().collect();
+        assert_eq!(unique.len(), blob_deletes.len());
+        assert!(!blob_deletes.contains(&&unrecorded));
+        let prefix = format!("/skylock/backups/{}/", manifest.backup_id);
+        assert!(storage.files.keys().all(|file| !file.starts_with(&prefix)), "{:?}", storage.files.keys());
+        drop(storage);
+        assert!(backup.list_backups().await.unwrap().is_empty());
+        assert!(backup.interrupted_deletions().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_partly_deleted_backup_is_not_an_incremental_base() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 4);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let full = backup.create_backup(&paths).await.unwrap();
+
+        storage.lock().unwrap().fail_deletes_after = Some(1);
+        assert!(backup.delete_backup(&full.backup_id).await.is_err());
+        storage.lock().unwrap().fail_deletes_after = None;
+
+        // Unchanged files would otherwise point at blobs that are gone
+        let next = backup.create_incremental_backup(&paths).await.unwrap();
+        assert!(next.base_backup_id.is_none());
+        assert_eq!(next.files.len(), 4);
+    }
+
+    /// Relative path and contents of every file below `root`
+    fn read_tree(root: &Path) -> std::collections::BTreeMap<PathBuf, Vec<u8>> {
+        walkdir::WalkDir::new(root).into_iter()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| (
+                entry.path().strip_prefix(root).unwrap().to_path_buf(),
+                std::fs::read(entry.path()).unwrap(),
+            ))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_consolidated_chain_restores_like_original_chain() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let chain_restore = TempDir::new().unwrap();
+        let consolidated_restore = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 5);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let full = backup.create_backup(&paths).await.unwrap();
+
+        std::fs::write(&files[0], "file 0 after the first change").unwrap();
+        std::fs::write(source.path().join("new.txt"), "added later").unwrap();
+        std::fs::remove_file(&files[1]).unwrap();
+        let inc1 = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(inc1.deleted_paths, vec![files[1].clone()]);
+
+        let moved_path = source.path().join("sub").join("moved.txt");
+        std::fs::create_dir_all(moved_path.parent().unwrap()).unwrap();
+        std::fs::rename(&files[2], &moved_path).unwrap();
+        std::fs::write(source.path().join("new.txt"), "added later, then modified").unwrap();
+        let inc2 = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(inc2.deleted_paths, vec![files[2].clone()]);
+
+        std::fs::remove_file(&files[3]).unwrap();
+        std::fs::write(&files[0], "file 0 after the second change").unwrap();
+        let tip = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(tip.base_backup_id.as_deref(), Some(inc2.backup_id.as_str()));
+
+        // Restoring the tip walks the chain
+        let chain = backup.load_chain(&tip.backup_id).await.unwrap();
+        let ids: Vec<_> = chain.iter().map(|m| m.backup_id.clone()).collect();
+        assert_eq!(ids, vec![full.backup_id.clone(), inc1.backup_id.clone(), inc2.backup_id.clone(), tip.backup_id.clone()]);
+        backup.restore_backup(&tip.backup_id, chain_restore.path()).await.unwrap();
+
+        let puts_before = storage.lock().unwrap().data_puts.len();
+        let consolidated = backup.consolidate_backup(&tip.backup_id).await.unwrap();
+        assert_eq!(storage.lock().unwrap().data_puts.len(), puts_before, "no file data should be uploaded");
+        assert_eq!(consolidated.backup_id, tip.backup_id);
+        assert!(consolidated.base_backup_id.is_none());
+        assert!(consolidated.deleted_paths.is_empty());
+        assert_eq!(consolidated.consolidated_from, ids[..3].to_vec());
+        assert_eq!(consolidated.file_count, 4);
+
+        // The rewritten manifest restores on its own
+        let reloaded = backup.load_chain(&tip.backup_id).await.unwrap();
+        assert_eq!(reloaded.len(), 1);
+        backup.restore_backup(&tip.backup_id, consolidated_restore.path()).await.unwrap();
+
+        let relative = source.path().strip_prefix("/").unwrap();
+        let expected = read_tree(source.path());
+        assert_eq!(read_tree(&chain_restore.path().join(relative)), expected);
+        assert_eq!(read_tree(&consolidated_restore.path().join(relative)), expected);
+
+        // Blobs of the consolidated backups stay when those are deleted
+        let reused = DirectUploadBackup::reused_blob_paths([&consolidated]);
+        let unchanged = full.files.iter().find(|e| e.local_path == files[4]).unwrap();
+        assert!(reused.contains(&unchanged.remote_path));
+    }
+
+    #[tokio::test]
+    async fn test_restore_subtree_downloads_only_its_blobs() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        // Distinct contents, so no blob is shared between directories
+        for (dir, count) in [("docs", 2), ("src", 2), ("src/nested", 1), ("src2", 1)] {
+            let dir = source.path().join(dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            for i in 0..count {
+                std::fs::write(dir.join(format!("file{}.txt", i)), format!("{} file {}", dir.display(), i)).unwrap();
+            }
+        }
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption).with_concurrency(2);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+        storage.lock().unwrap().data_gets.clear();
+
+        let subtree = source.path().join("src");
+        backup.restore_subtree(&manifest.backup_id, &[subtree.clone()], restore_dir.path()).await.unwrap();
+
+        // Each blob below src/ was downloaded once, and nothing else
+        let mut expected: Vec<String> = manifest.files.iter()
+            .filter(|entry| entry.local_path.starts_with(&subtree))
+            .map(|entry| entry.remote_path.clone())
+            .collect();
+        expected.sort();
+        assert_eq!(expected.len(), 3);
+        let mut fetched = storage.lock().unwrap().data_gets.clone();
+        fetched.sort();
+        assert_eq!(fetched, expected);
+
+        // The subtree sits where a full restore puts it; src2/ shares the
+        // name prefix but isn't part of it
+        let relative = source.path().strip_prefix("/").unwrap();
+        let restored = restore_dir.path().join(relative);
+        assert_eq!(read_tree(&restored.join("src")), read_tree(&subtree));
+        assert!(!restored.join("docs").exists());
+        assert!(!restored.join("src2").exists());
+
+        // A relative prefix selects the same files, and one matching
+        // nothing is an error
+        let relative_src = relative.join("src");
+        let components = DirectUploadBackup::subtree_components(&relative_src).unwrap();
+        assert!(DirectUploadBackup::in_subtree(&subtree.join("nested/file0.txt"), &components));
+        assert!(!DirectUploadBackup::in_subtree(&source.path().join("src2/file0.txt"), &components));
+        let err = backup.restore_subtree(&manifest.backup_id, &[source.path().join("missing")], restore_dir.path())
+            .await.unwrap_err();
+        assert!(err.to_string().contains("Nothing below"), "{}", err);
+
+        // Prefixes naming other directories are refused, not reinterpreted
+        for prefix in ["src/../docs", "./src", "/home/.."] {
+            assert!(DirectUploadBackup::subtree_components(Path::new(prefix)).is_err(), "{}", prefix);
+        }
+        let err = backup.restore_subtree(&manifest.backup_id, &[source.path().join("src/../docs")], restore_dir.path())
+            .await.unwrap_err();
+        assert!(err.to_string().contains("Invalid restore path"), "{}", err);
+
+        // Several prefixes restore together, overlapping ones only once
+        storage.lock().unwrap().data_gets.clear();
+        let both = TempDir::new().unwrap();
+        let prefixes = [source.path().join("docs"), subtree.clone(), subtree.join("nested")];
+        backup.restore_subtree(&manifest.backup_id, &prefixes, both.path()).await.unwrap();
+        assert_eq!(storage.lock().unwrap().data_gets.len(), 5);
+        let restored = both.path().join(relative);
+        assert_eq!(read_tree(&restored.join("docs")), read_tree(&source.path().join("docs")));
+        assert_eq!(read_tree(&restored.join("src")), read_tree(&subtree));
+        assert!(!restored.join("src2").exists());
+    }
+
+    #[tokio::test]
+    async fn test_chain_consolidated_at_max_length() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        Arc::make_mut(&mut backup.config).backup.max_chain_length = Some(2);
+        backup.create_backup(&paths).await.unwrap();
+
+        std::fs::write(&files[0], "first change").unwrap();
+        let inc1 = backup.create_incremental_backup(&paths).await.unwrap();
+        assert!(inc1.base_backup_id.is_some(), "one increment is below the limit");
+
+        std::fs::write(&files[1], "second change").unwrap();
+        let inc2 = backup.create_incremental_backup(&paths).await.unwrap();
+        assert!(inc2.base_backup_id.is_none(), "second increment triggers consolidation");
+        assert_eq!(inc2.file_count, 2);
+        assert_eq!(inc2.consolidated_from.len(), 2);
+
+        // The next incremental starts a new chain on the synthetic full
+        std::fs::write(&files[0], "third change").unwrap();
+        let inc3 = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(inc3.base_backup_id.as_deref(), Some(inc2.backup_id.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_tree_has_same_content_fingerprint() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 8);
+        std::fs::create_dir(source.path().join("nested")).unwrap();
+        create_source_files(&source.path().join("nested"), 3);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        Arc::make_mut(&mut backup.config).backup.canonical_manifests = true;
+
+        let first = backup.create_backup(&paths).await.unwrap();
+        let second = backup.create_backup(&paths).await.unwrap();
+        assert_ne!(first.backup_id, second.backup_id);
+        assert_ne!(first.timestamp, second.timestamp);
+
+        // Entries are in path order, so the manifests line up entry by entry
+        let listed = |manifest: &BackupManifest| manifest.files.iter().map(|e| e.local_path.clone()).collect::<Vec<_>>();
+        let mut sorted = listed(&first);
+        sorted.sort();
+        assert_eq!(listed(&first), sorted);
+        assert_eq!(listed(&second), sorted);
+        assert_eq!(first.content(), second.content());
+        assert_eq!(first.content_fingerprint(), second.content_fingerprint());
+
+        let diff = BackupDiff::compare(&first, &second);
+        assert!(!diff.has_changes());
+        assert_eq!(diff.fingerprint_old, diff.fingerprint_new);
+
+        // Any content change shows up in the fingerprint
+        std::fs::write(&files[3], "changed").unwrap();
+        let third = backup.create_backup(&paths).await.unwrap();
+        assert_ne!(third.content_fingerprint(), second.content_fingerprint());
+    }
+
+    #[test]
+    fn test_concurrency_flag_overrides_config() {
+        let data_dir = TempDir::new().unwrap();
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut config = (*test_backup("http://127.0.0.1:1", data_dir.path(), &encryption).config).clone();
+        config.backup.max_concurrent_uploads = Some(6);
+        let client = || test_server::client("http://127.0.0.1:1");
+
+        // The configured value replaces the core-based default
+        let backup = DirectUploadBackup::new(config.clone(), client(), encryption.for_algorithm(encryption.algorithm()), None).unwrap();
+        assert_eq!(backup.current_parallelism(), 6);
+        assert_eq!(backup.multipart_download.as_ref().unwrap().parts, 6);
+
+        // --concurrency wins over the config, for the adaptive controller too
+        let backup = backup.with_concurrency(2);
+        assert_eq!(backup.current_parallelism(), 2);
+        assert_eq!(backup.multipart_download.as_ref().unwrap().parts, 2);
+        let dynamic = DirectUploadBackup::with_dynamic_parallelism(config, client(), encryption.for_algorithm(encryption.algorithm()), None).unwrap();
+        assert_eq!(dynamic.current_parallelism(), 6);
+        let dynamic = dynamic.with_concurrency(1_000);
+        assert_eq!(dynamic.current_parallelism(), parallelism::MAX_CONCURRENCY);
+        assert_eq!(dynamic.max_parallel, parallelism::MAX_CONCURRENCY);
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_file_names_not_stored_in_cleartext() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let secret_dir = source.path().join("secret-project");
+        std::fs::create_dir_all(&secret_dir).unwrap();
+        let secret_file = secret_dir.join("quarterly-plan.txt");
+        std::fs::write(&secret_file, "launch date").unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        Arc::make_mut(&mut backup.config).backup.encrypt_file_names = true;
+
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        // Neither object names nor any stored object mention the file names
+        {
+            let storage = storage.lock().unwrap();
+            for (path, data) in &storage.files {
+                for name in ["secret-project", "quarterly-plan"] {
+                    assert!(!path.contains(name), "{} in object name {}", name, path);
+                    assert!(!String::from_utf8_lossy(data).contains(name), "{} in {}", name, path);
+                }
+            }
+        }
+
+        // The digest-named blob sits below two levels of prefix directories
+        let entry = manifest.files.iter().find(|e| e.local_path == secret_file).unwrap();
+        let relative = entry.remote_path.strip_prefix(&format!("/skylock/backups/{}/", manifest.backup_id)).unwrap();
+        let parts: Vec<&str> = relative.split('/').collect();
+        assert_eq!(parts.len(), 3, "{}", entry.remote_path);
+        assert_eq!(format!("{}{}", parts[0], parts[1]), parts[2][..4]);
+
+        // Browsing the decrypted manifest shows the real names
+        let loaded = backup.load_manifest(&manifest.backup_id).await.unwrap();
+        let browseable = crate::encrypted_manifest::BrowseableBackup::from_manifest(&loaded);
+        let node = browseable.find_file(secret_file.to_str().unwrap()).unwrap();
+        assert_eq!(node.name, "quarterly-plan.txt");
+
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        let restored = restore_dir.path().join(secret_file.strip_prefix("/").unwrap());
+        assert_eq!(std::fs::read_to_string(restored).unwrap(), "launch date");
+    }
+
+    #[tokio::test]
+    async fn test_compression_decided_per_file() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+
+        // JPEG header followed by incompressible-looking payload
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0];
+        jpeg.extend((0..16 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8));
+        let jpeg_path = source.path().join("photo.jpg");
+        std::fs::write(&jpeg_path, &jpeg).unwrap();
+
+        let text = "The quick brown fox jumps over the lazy dog.\n".repeat(500);
+        let text_path = source.path().join("notes.txt");
+        std::fs::write(&text_path, &text).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        let entry = |path: &Path| manifest.files.iter().find(|e| e.local_path == path).unwrap();
+        let jpeg_entry = entry(&jpeg_path);
+        assert_eq!(jpeg_entry.compression_algorithm(), CompressionAlgorithm::None);
+        assert!(!jpeg_entry.compressed);
+        assert!(!jpeg_entry.remote_path.ends_with(".zst.enc"));
+
+        let text_entry = entry(&text_path);
+        assert_eq!(text_entry.compression_algorithm(), CompressionAlgorithm::Zstd);
+        assert!(text_entry.compressed);
+        assert!(text_entry.remote_path.ends_with(".zst.enc"));
+        let stored = storage.lock().unwrap().files.iter()
+            .find(|(path, _)| path.ends_with(&text_entry.remote_path))
+            .map(|(_, data)| data.len())
+            .unwrap();
+        assert!(stored < text.len());
+
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for (path, original) in [(&jpeg_path, jpeg), (&text_path, text.into_bytes())] {
+            let restored = restore_dir.path().join(path.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read(restored).unwrap(), original);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compression_profiles_pin_algorithm_per_file_type() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let text = "The quick brown fox jumps over the lazy dog.\n".repeat(500);
+        let log_path = source.path().join("app.log");
+        let sql_path = source.path().join("dump.sql");
+        let notes_path = source.path().join("notes.txt");
+        for path in [&log_path, &sql_path, &notes_path] {
+            std::fs::write(path, &text).unwrap();
+        }
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let profiles = CompressionProfiles::parse(&std::collections::BTreeMap::from([
+            ("*.log".to_string(), "lz4:9".to_string()),
+            ("application/sql".to_string(), "brotli:5".to_string()),
+        ])).unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption).with_compression_profiles(profiles);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        let compression = |path: &Path| manifest.files.iter()
+            .find(|e| e.local_path == path).unwrap()
+            .compression.clone().unwrap();
+        let log = compression(&log_path);
+        assert_eq!((log.algorithm(), log.compression_level), (CompressionAlgorithm::Lz4, Some(9)));
+        assert_eq!(log.profile.as_deref(), Some("*.log"));
+        let sql = compression(&sql_path);
+        assert_eq!((sql.algorithm(), sql.compression_level), (CompressionAlgorithm::Brotli, Some(5)));
+        assert_eq!(sql.profile.as_deref(), Some("application/sql"));
+        // No profile matches, so the adaptive choice applies
+        let notes = compression(&notes_path);
+        assert_eq!(notes.algorithm(), CompressionAlgorithm::Zstd);
+        assert_eq!(notes.profile, None);
+
+        // A run forcing one algorithm ignores the profiles
+        let forced = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_compression_profiles(CompressionProfiles::parse(&std::collections::BTreeMap::from([
+                ("*.log".to_string(), "lz4".to_string()),
+            ])).unwrap())
+            .with_compression_override(CompressionOverride::new(Some(CompressionAlgorithm::Gzip), None).unwrap());
+        let forced_manifest = forced.create_backup(&[log_path.clone()]).await.unwrap();
+        assert_eq!(forced_manifest.files[0].compression_algorithm(), CompressionAlgorithm::Gzip);
+
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for path in [&log_path, &sql_path, &notes_path] {
+            let restored = restore_dir.path().join(path.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read_to_string(restored).unwrap(), text);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compression_override_applies_to_run() {
+        let source = TempDir::new().unwrap();
+        let text = "The quick brown fox jumps over the lazy dog.\n".repeat(500);
+        let text_path = source.path().join("notes.txt");
+        std::fs::write(&text_path, &text).unwrap();
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let overrides = [
+            (CompressionOverride::default(), CompressionAlgorithm::Zstd),
+            (CompressionOverride::new(Some(CompressionAlgorithm::None), None).unwrap(), CompressionAlgorithm::None),
+            (
+                CompressionOverride::new(Some(CompressionAlgorithm::Brotli), Some(CompressionLevel::Best)).unwrap(),
+                CompressionAlgorithm::Brotli,
+            ),
+        ];
+
+        for (compression, expected) in overrides {
+            let data_dir = TempDir::new().unwrap();
+            let restore_dir = TempDir::new().unwrap();
+            let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+                .with_compression_override(compression);
+            let manifest = backup.create_backup(&paths).await.unwrap();
+
+            let entry = &manifest.files[0];
+            assert_eq!(entry.compression_algorithm(), expected);
+            assert_eq!(entry.compressed, expected != CompressionAlgorithm::None);
+            let stored = storage.lock().unwrap().files[&entry.remote_path].len() as u64;
+            if expected == CompressionAlgorithm::None {
+                assert_eq!(stored, text.len() as u64 + AEAD_OVERHEAD);
+            } else {
+                assert!(stored < text.len() as u64);
+            }
+            if expected == CompressionAlgorithm::Brotli {
+                assert_eq!(entry.compression.as_ref().unwrap().compression_level, Some(11));
+            }
+
+            // Restores read the choice from the manifest, not the override
+            let restorer = test_backup(&endpoint, data_dir.path(), &encryption);
+            restorer.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+            let restored = restore_dir.path().join(text_path.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read_to_string(restored).unwrap(), text);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gzip_blobs_readable_by_standard_decoder() {
+        use std::io::Read;
+
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let text = "The quick brown fox jumps over the lazy dog.\n".repeat(500);
+        let text_path = source.path().join("notes.txt");
+        std::fs::write(&text_path, &text).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_compression_override(CompressionOverride::new(Some(CompressionAlgorithm::Gzip), None).unwrap());
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        let entry = &manifest.files[0];
+        assert_eq!(entry.compression_algorithm(), CompressionAlgorithm::Gzip);
+        assert!(entry.remote_path.ends_with(".gz.enc"));
+
+        // Once decrypted the blob is a plain gzip file: header, deflate data
+        // and a CRC-32/ISIZE trailer any gzip decoder accepts
+        let blob = storage.lock().unwrap().files[&entry.remote_path].clone();
+        let gz = DirectUploadBackup::decrypt_file_data(&backup.encryption, &manifest, entry, &blob).unwrap();
+        assert_eq!(&gz[..3], &[0x1f, 0x8b, 0x08]);
+        let trailer = &gz[gz.len() - 8..];
+        assert_eq!(u32::from_le_bytes(trailer[..4].try_into().unwrap()), crc32fast::hash(text.as_bytes()));
+        assert_eq!(u32::from_le_bytes(trailer[4..].try_into().unwrap()), text.len() as u32);
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(gz.as_slice()).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+
+        // And skylock restores it byte for byte
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        let restored = restore_dir.path().join(text_path.strip_prefix("/").unwrap());
+        assert_eq!(std::fs::read_to_string(restored).unwrap(), text);
+    }
+
+    #[tokio::test]
+    async fn test_mixed_compression_algorithms_restore() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+
+        // Each file steers the adaptive engine to a different algorithm
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0];
+        jpeg.extend((0..8 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8));
+        let corpus: Vec<(&str, Vec<u8>, CompressionAlgorithm)> = vec![
+            ("runs.txt", "a".repeat(20 * 1024).into_bytes(), CompressionAlgorithm::Lz4),
+            ("pattern.txt", "ab".repeat(10 * 1024).into_bytes(), CompressionAlgorithm::Brotli),
+            ("prose.txt", "The quick brown fox jumps over the lazy dog.\n".repeat(400).into_bytes(), CompressionAlgorithm::Zstd),
+            ("photo.jpg", jpeg, CompressionAlgorithm::None),
+        ];
+        for (name, data, _) in &corpus {
+            std::fs::write(source.path().join(name), data).unwrap();
+        }
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        for (name, data, algorithm) in &corpus {
+            let path = source.path().join(name);
+            let entry = manifest.files.iter().find(|e| e.local_path == path).unwrap();
+            let metadata = entry.compression.as_ref().unwrap();
+            assert_eq!(entry.compression_algorithm(), *algorithm, "{}", name);
+            assert_eq!(metadata.original_size, data.len() as u64);
+            assert_eq!(metadata.original_hash, entry.hash);
+            assert_eq!(metadata.compressed_hash.is_some(), *algorithm != CompressionAlgorithm::None);
+        }
+
+        // The manifest round-trips through the remote copy with the metadata intact
+        let reloaded = backup.load_manifest(&manifest.backup_id).await.unwrap();
+        let algorithms: Vec<_> = reloaded.files.iter().map(|e| e.compression_algorithm()).collect();
+        let expected: Vec<_> = manifest.files.iter().map(|e| e.compression_algorithm()).collect();
+        assert_eq!(algorithms, expected);
+
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for (name, data, _) in &corpus {
+            let path = source.path().join(name);
+            let restored = restore_dir.path().join(path.strip_prefix("/").unwrap());
+            assert_eq!(&std::fs::read(restored).unwrap(), data, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_file_to_writer_streams_original_bytes() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let original: Vec<u8> = (0..64 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 7) as u8).collect();
+        let path = source.path().join("archive.tar");
+        std::fs::write(&path, &original).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        let mut stdout = Vec::new();
+        let written = backup
+            .restore_file_to_writer(&manifest.backup_id, path.to_str().unwrap(), &mut stdout)
+            .await
+            .unwrap();
+        assert_eq!(written, original.len() as u64);
+        assert_eq!(stdout, original);
+
+        let missing = backup.restore_file_to_writer(&manifest.backup_id, "/no/such/file", Vec::new()).await;
+        assert!(missing.unwrap_err().to_string().contains("File not found"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_directories_survive_restore() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 1);
+        let logs = source.path().join("logs");
+        std::fs::create_dir_all(logs.join("archive")).unwrap();
+        let logs_mtime = filetime::FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(&logs, logs_mtime).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&logs, std::fs::Permissions::from_mode(0o750)).unwrap();
+        }
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+        assert!(manifest.directories.iter().any(|d| d.path == logs.join("archive")));
+
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        let restored = restore_dir.path().join(logs.strip_prefix("/").unwrap());
+        assert!(restored.join("archive").is_dir());
+        let metadata = std::fs::metadata(&restored).unwrap();
+        assert_eq!(filetime::FileTime::from_last_modification_time(&metadata), logs_mtime);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o7777, 0o750);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_include_only_backs_up_whitelisted_files() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let root = source.path();
+        std::fs::create_dir_all(root.join("finance/old")).unwrap();
+        std::fs::create_dir_all(root.join("photos")).unwrap();
+        for file in ["report.docx", "notes.txt", "finance/budget.xlsx", "finance/old/budget.xlsx", "photos/cat.jpg"] {
+            std::fs::write(root.join(file), format!("contents of {}", file)).unwrap();
+        }
+        let paths = vec![root.to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let filter = FileFilter::new(
+            &["*.docx".to_string(), "*.xlsx".to_string()],
+            &["old".to_string()],
+        );
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption).with_file_filter(filter);
+
+        let manifest = backup.create_backup(&paths).await.unwrap();
+        let mut backed_up: Vec<PathBuf> = manifest.files.iter().map(|e| e.local_path.clone()).collect();
+        backed_up.sort();
+        assert_eq!(backed_up, vec![root.join("finance/budget.xlsx"), root.join("report.docx")]);
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 2);
+        assert!(!manifest.directories.iter().any(|d| d.path == root.join("finance/old")));
+
+        // New files outside the whitelist don't make the next run upload
+        std::fs::write(root.join("photos/dog.jpg"), b"woof").unwrap();
+        std::fs::write(root.join("finance/old/2019.xlsx"), b"old").unwrap();
+        std::fs::write(root.join("minutes.docx"), b"minutes").unwrap();
+        let incremental = backup.create_incremental_backup(&paths).await.unwrap();
+        assert_eq!(incremental.files.len(), 1);
+        assert_eq!(incremental.files[0].local_path, root.join("minutes.docx"));
+    }
+
+    #[tokio::test]
+    async fn test_files_encrypted_with_derived_subkeys() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+
+        let entry = |path: &Path| manifest.files.iter().find(|e| e.local_path == path).unwrap();
+        let (first, second) = (entry(&files[0]), entry(&files[1]));
+        let first_context = first.key_context.clone().unwrap();
+        assert_eq!(first_context, FileEntry::key_context_for(&manifest.backup_id, &files[0]));
+        assert_ne!(Some(&first_context), second.key_context.as_ref());
+
+        // Only the first file's own subkey opens its blob
+        let blob = storage.lock().unwrap().files[&first.remote_path].clone();
+        let aad_path = files[0].to_string_lossy();
+        let open = |key: &EncryptionManager| key.decrypt_with_aad(&blob, &manifest.backup_id, &aad_path);
+        assert_eq!(open(&backup.encryption.derive_file_key(&first_context)).unwrap(), b"contents of file 0");
+        assert!(open(&backup.encryption).is_err());
+        assert!(open(&backup.encryption.derive_file_key(second.key_context.as_ref().unwrap())).is_err());
+
+        // The same path in another backup gets another key
+        let other_context = FileEntry::key_context_for("20240101_000000", &files[0]);
+        assert_ne!(other_context, first_context);
+        assert!(open(&backup.encryption.derive_file_key(&other_context)).is_err());
+
+        // Restore re-derives each key from the manifest
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for (i, file) in files.iter().enumerate() {
+            let restored = restore_dir.path().join(file.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read_to_string(restored).unwrap(), format!("contents of file {}", i));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restored_files_keep_modification_time() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+        filetime::set_file_mtime(&files[0], filetime::FileTime::from_unix_time(1_600_000_000, 123_456_789)).unwrap();
+        filetime::set_file_mtime(&files[1], filetime::FileTime::from_unix_time(1_500_000_000, 0)).unwrap();
+        let original = |path: &Path| filetime::FileTime::from_last_modification_time(&std::fs::metadata(path).unwrap());
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+        let entry = manifest.files.iter().find(|entry| entry.local_path == files[1]).unwrap();
+        assert_eq!(entry.modified.map(|modified| modified.timestamp()), Some(1_500_000_000));
+
+        // Compared with the source as stored, i.e. at filesystem resolution
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for file in &files {
+            let restored = restore_dir.path().join(file.strip_prefix("/").unwrap());
+            assert_eq!(original(&restored), original(file), "{}", file.display());
+        }
+
+        let output = restore_dir.path().join("single");
+        backup.restore_file(&manifest.backup_id, files[0].to_str().unwrap(), &output).await.unwrap();
+        assert_eq!(original(&output), original(&files[0]));
+
+        // With the index lost, a file whose size and time match the backup
+        // is taken as unchanged without hashing it
+        std::fs::remove_dir_all(data_dir.path().join("indexes")).unwrap();
+        let length = std::fs::metadata(&files[1]).unwrap().len() as usize;
+        std::fs::write(&files[1], "x".repeat(length)).unwrap();
+        filetime::set_file_mtime(&files[1], filetime::FileTime::from_unix_time(1_500_000_000, 0)).unwrap();
+        let stats = backup.rebuild_index(None).await.unwrap();
+        assert_eq!((stats.verified, stats.changed), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_blake3_backup_round_trip() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 3);
+        let large = source.path().join("large.bin");
+        let large_contents: Vec<u8> = (0..2 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&large, &large_contents).unwrap();
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = |algorithm: HashAlgorithm| test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_stream_threshold(Some(1024 * 1024))
+            .with_hash_algorithm(algorithm);
+        let manifest = backup(HashAlgorithm::Blake3).create_backup(&paths).await.unwrap();
+
+        // Every hash, streamed or not, is BLAKE3, and the manifest says so
+        assert_eq!(manifest.hash_algorithm, HashAlgorithm::Blake3);
+        assert!(serde_json::to_string(&manifest).unwrap().contains("\"BLAKE3\""));
+        for entry in &manifest.files {
+            let contents = std::fs::read(&entry.local_path).unwrap();
+            assert_eq!(entry.hash, HashAlgorithm::Blake3.hash(&contents), "{}", entry.local_path.display());
+        }
+        assert!(manifest.files.iter().any(|entry| entry.is_streamed()));
+
+        // Restore and verification recompute BLAKE3 hashes, whatever the
+        // instance is configured with
+        backup(HashAlgorithm::Sha256).restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for file in files.iter().chain([&large]) {
+            let restored = restore_dir.path().join(file.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read(restored).unwrap(), std::fs::read(file).unwrap());
+        }
+        let hetzner = test_server::client(&endpoint);
+        let report = crate::BackupVerifier::new(hetzner)
+            .with_temp_dir(data_dir.path().to_path_buf())
+            .verify_full(&manifest, Arc::new(encryption.for_algorithm(encryption.algorithm())))
+            .await
+            .unwrap();
+        assert!(report.passed);
+        assert_eq!(report.files_verified, 4);
+
+        // An incremental backup builds on a backup of the same algorithm...
+        std::fs::write(&files[0], "changed").unwrap();
+        let incremental = backup(HashAlgorithm::Blake3).create_incremental_backup(&paths).await.unwrap();
+        assert!(incremental.base_backup_id.is_some());
+        assert_eq!(incremental.files.len(), 1);
+
+        // ...and switching algorithms starts a new chain
+        std::fs::write(&files[1], "changed too").unwrap();
+        let switched = backup(HashAlgorithm::Sha256).create_incremental_backup(&paths).await.unwrap();
+        assert!(switched.base_backup_id.is_none());
+        assert_eq!(switched.hash_algorithm, HashAlgorithm::Sha256);
+        assert_eq!(switched.files.len(), 4);
+        let entry = switched.files.iter().find(|entry| entry.local_path == files[1]).unwrap();
+        assert_eq!(entry.hash, crate::compression_integrity::calculate_hash(b"changed too"));
+    }
+
+    #[tokio::test]
+    async fn test_restore_policy_for_existing_files() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 3);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let manifest = test_backup(&endpoint, data_dir.path(), &encryption)
+            .create_backup(&[source.path().to_path_buf()]).await.unwrap();
+        storage.lock().unwrap().data_gets.clear();
+
+        // Two of the three files already exist at the target, edited locally
+        let targets: Vec<PathBuf> = files.iter()
+            .map(|file| restore_dir.path().join(file.strip_prefix("/").unwrap()))
+            .collect();
+        std::fs::create_dir_all(targets[0].parent().unwrap()).unwrap();
+        for target in &targets[..2] {
+            std::fs::write(target, "edited locally").unwrap();
+        }
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+
+        // By default the restore is refused before anything is downloaded
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let err = backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap_err();
+        match err {
+            SkylockError::RestoreConflict(conflicts) => assert_eq!(conflicts, targets[..2]),
+            other => panic!("unexpected error: {}", other),
+        }
+        assert!(storage.lock().unwrap().data_gets.is_empty());
+        assert_eq!(read(&targets[0]), "edited locally");
+        assert!(!targets[2].exists());
+        let conflicts = backup.check_restore_conflicts(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        assert_eq!(conflicts, targets[..2]);
+
+        // Skipping existing files only writes the missing one
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_restore_policy(RestorePolicy::SkipExisting);
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        assert_eq!(read(&targets[0]), "edited locally");
+        assert_eq!(read(&targets[1]), "edited locally");
+        assert_eq!(read(&targets[2]), read(&files[2]));
+
+        // Forcing replaces every file with its backed-up content
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_restore_policy(RestorePolicy::Overwrite);
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        for (target, file) in targets.iter().zip(&files) {
+            assert_eq!(read(target), read(file));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_refused_without_free_space() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let other_fs = TempDir::new().unwrap();
+        create_source_files(source.path(), 3);
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let manifest = test_backup(&endpoint, data_dir.path(), &encryption)
+            .create_backup(&[source.path().to_path_buf()]).await.unwrap();
+        storage.lock().unwrap().data_gets.clear();
+
+        let probed = Arc::new(std::sync::Mutex::new(Vec::<PathBuf>::new()));
+        let low_space = |free: u64| -> SpaceProbe {
+            let probed = probed.clone();
+            Arc::new(move |path: &Path| {
+                probed.lock().unwrap().push(path.to_path_buf());
+                Ok(free)
+            })
+        };
+
+        // A nearly full disk stops the restore before anything is downloaded
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_space_probe(low_space(1024));
+        let err = backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap_err();
+        assert!(matches!(err, SkylockError::InsufficientSpace(_)), "unexpected error: {}", err);
+        assert!(storage.lock().unwrap().data_gets.is_empty());
+        assert_eq!(std::fs::read_dir(restore_dir.path()).unwrap().count(), 0);
+
+        // Remapped files are checked where they will land
+        probed.lock().unwrap().clear();
+        let mapped = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_path_map(PathMap::parse(&[format!("{}={}", source.path().display(), other_fs.path().join("restored").display())]).unwrap())
+            .with_space_probe(low_space(1024));
+        assert!(mapped.restore_backup(&manifest.backup_id, Path::new("/")).await.is_err());
+        assert_eq!(*probed.lock().unwrap(), vec![other_fs.path().to_path_buf()]);
+
+        // Skipping the check (--force) restores anyway
+        let forced = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_space_probe(low_space(1024))
+            .with_space_check(false);
+        forced.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        assert!(!storage.lock().unwrap().data_gets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore_with_path_map() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let alice = source.path().join("alice");
+        std::fs::create_dir_all(alice.join("work")).unwrap();
+        std::fs::create_dir_all(source.path().join("etc")).unwrap();
+        std::fs::write(alice.join("notes.txt"), b"notes").unwrap();
+        std::fs::write(alice.join("work/plan.md"), b"plan").unwrap();
+        std::fs::write(source.path().join("etc/hosts"), b"hosts").unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let manifest = test_backup(&endpoint, data_dir.path(), &encryption)
+            .create_backup(&[source.path().to_path_buf()]).await.unwrap();
+        let restore = |specs: Vec<String>| {
+            let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+                .with_path_map(PathMap::parse(&specs).unwrap());
+            let backup_id = manifest.backup_id.clone();
+            async move {
+                let restore_dir = TempDir::new().unwrap();
+                let result = backup.restore_backup(&backup_id, restore_dir.path()).await;
+                (restore_dir, result)
+            }
+        };
+        let unmapped = |restore_dir: &Path, path: &Path| restore_dir.join(path.strip_prefix("/").unwrap());
+
+        // A single remap moves everything below it and nothing else
+        let (restore_dir, result) = restore(vec![format!("{}=/home/bob", alice.display())]).await;
+        result.unwrap();
+        assert_eq!(std::fs::read(restore_dir.path().join("home/bob/notes.txt")).unwrap(), b"notes");
+        assert_eq!(std::fs::read(restore_dir.path().join("home/bob/work/plan.md")).unwrap(), b"plan");
+        assert!(!unmapped(restore_dir.path(), &alice).exists());
+        assert_eq!(std::fs::read(unmapped(restore_dir.path(), &source.path().join("etc/hosts"))).unwrap(), b"hosts");
+
+        // The longest matching prefix wins
+        let (restore_dir, result) = restore(vec![
+            format!("{}=/home/bob", alice.display()),
+            format!("{}=/srv/work", alice.join("work").display()),
+            format!("{}=etc", source.path().join("etc").display()),
+        ]).await;
+        result.unwrap();
+        assert_eq!(std::fs::read(restore_dir.path().join("home/bob/notes.txt")).unwrap(), b"notes");
+        assert_eq!(std::fs::read(restore_dir.path().join("srv/work/plan.md")).unwrap(), b"plan");
+        assert!(!restore_dir.path().join("home/bob/work").exists());
+        assert_eq!(std::fs::read(restore_dir.path().join("etc/hosts")).unwrap(), b"hosts");
+
+        // A remap escaping the target is refused before anything is written
+        let (restore_dir, result) = restore(vec![format!("{}=../outside", alice.display())]).await;
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("outside the target directory"), "{}", err);
+        assert_eq!(std::fs::read_dir(restore_dir.path()).unwrap().count(), 0);
+        assert!(!restore_dir.path().parent().unwrap().join("outside").exists());
+    }
+
+    #[test]
+    fn test_restore_target_stays_inside_target_dir() {
+        let target = Path::new("/restore");
+        assert_eq!(
+            DirectUploadBackup::restore_target(target, Path::new("/home/user/./notes.txt")).unwrap(),
+            PathBuf::from("/restore/home/user/notes.txt")
+        );
+        let err = DirectUploadBackup::restore_target(target, Path::new("/home/../../etc")).unwrap_err();
+        assert!(err.to_string().contains("outside the target directory"));
+    }
+
+    #[tokio::test]
+    async fn test_restore_preview_classifies_target_files() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let target = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 4);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        // file0 identical, file1 same size but different content, file2 a
+        // different size, file3 missing
+        let at_target = |path: &Path| target.path().join(path.strip_prefix("/").unwrap());
+        std::fs::create_dir_all(at_target(source.path())).unwrap();
+        std::fs::copy(&files[0], at_target(&files[0])).unwrap();
+        std::fs::write(at_target(&files[1]), "contents of file X").unwrap();
+        std::fs::write(at_target(&files[2]), "edited").unwrap();
+        let before = read_tree(target.path());
+
+        let preview = backup.preview_restore(&manifest.backup_id, &[], target.path()).await.unwrap();
+        let action = |path: &Path| preview.iter().find(|e| e.target == at_target(path)).unwrap();
+        assert_eq!(preview.len(), 4);
+        assert_eq!(action(&files[0]).action, RestoreAction::Unchanged);
+        assert_eq!(action(&files[1]).action, RestoreAction::Overwrite);
+        assert_eq!(action(&files[1]).existing_size, Some(action(&files[1]).backup_size));
+        assert_eq!(action(&files[2]).action, RestoreAction::Overwrite);
+        assert_eq!(action(&files[2]).existing_size, Some(6));
+        assert_eq!(action(&files[3]).action, RestoreAction::Create);
+        assert_eq!(action(&files[3]).existing_size, None);
+
+        // A dry run: the target is untouched
+        assert_eq!(read_tree(target.path()), before);
+        assert!(!at_target(&files[3]).exists());
+
+        // Restore paths limit the preview to the files they would restore
+        let preview = backup.preview_restore(&manifest.backup_id, &[files[1].clone(), files[3].clone()], target.path())
+            .await.unwrap();
+        let mut previewed: Vec<_> = preview.iter().map(|e| e.target.clone()).collect();
+        previewed.sort();
+        assert_eq!(previewed, vec![at_target(&files[1]), at_target(&files[3])]);
+    }
+
+    #[tokio::test]
+    async fn test_tail_preview_fetches_only_trailing_chunks() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let log = source.path().join("server.log");
+        let paths = vec![source.path().to_path_buf()];
+
+        // A log large enough to be chunked, with lines CDC can't align on
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut contents = Vec::new();
+        for i in 0..800_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            contents.extend_from_slice(format!("{:08} request {:016x}\n", i, state).as_bytes());
+        }
+        std::fs::write(&log, &contents).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+        let entry = &manifest.files[0];
+        assert!(entry.chunks.len() > 8, "{} chunks", entry.chunks.len());
+
+        // The last 50 lines come from the final chunk or two only
+        storage.lock().unwrap().data_gets.clear();
+        let tail = backup.fetch_file_tail(entry, &manifest, 50).await.unwrap();
+        let expected: Vec<&[u8]> = contents.split_inclusive(|&b| b == b'\n').collect();
+        assert_eq!(tail, expected[expected.len() - 50..].concat());
+        let gets = storage.lock().unwrap().data_gets.clone();
+        assert!(!gets.is_empty() && gets.len() <= 2, "fetched {} chunks", gets.len());
+        assert!(gets.iter().all(|path| entry.chunks[entry.chunks.len() - gets.len()..]
+            .iter().any(|chunk| &chunk.remote_path == path)));
+
+        let browser = crate::browser::EncryptedBrowser::new(test_backup(&endpoint, data_dir.path(), &encryption));
+        storage.lock().unwrap().data_gets.clear();
+        browser.preview_file_tail(&manifest.backup_id, log.to_str().unwrap(), 50, false).await.unwrap();
+        assert!(storage.lock().unwrap().data_gets.len() <= 2);
+        storage.lock().unwrap().data_gets.clear();
+        browser.preview_file_tail(&manifest.backup_id, log.to_str().unwrap(), 50, true).await.unwrap();
+        assert!(storage.lock().unwrap().data_gets.len() <= 2);
+
+        // A range spanning a chunk boundary reads just the two chunks
+        storage.lock().unwrap().data_gets.clear();
+        let boundary = entry.chunks[0].size;
+        let range = backup.fetch_file_range(entry, &manifest, boundary - 10..boundary + 20).await.unwrap();
+        assert_eq!(range, &contents[boundary as usize - 10..boundary as usize + 20]);
+        assert_eq!(storage.lock().unwrap().data_gets.len(), 2);
+        assert_eq!(entry.chunks_in_range(boundary..boundary + 1)[0].0, boundary);
+
+        assert_eq!(tail_lines(b"a\nb\nc\n", 2), b"b\nc\n");
+        assert_eq!(tail_lines(b"a\nb", 5), b"a\nb");
+        assert!(tail_lines(b"a\n", 0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_second_preview_served_from_block_cache() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let manifest = test_backup(&endpoint, data_dir.path(), &encryption).create_backup(&paths).await.unwrap();
+
+        let cache = Arc::new(BlockCache::new(1024 * 1024));
+        let browser = crate::browser::EncryptedBrowser::with_cache(
+            test_backup(&endpoint, data_dir.path(), &encryption),
+            cache.clone(),
+        );
+        let file = files[0].to_str().unwrap();
+        browser.preview_file(&manifest.backup_id, file, 10, false).await.unwrap();
+        assert_eq!(storage.lock().unwrap().data_gets.len(), 1);
+        assert_eq!(cache.len(), 1);
+
+        // The second preview needs no download
+        browser.preview_file(&manifest.backup_id, file, 10, false).await.unwrap();
+        assert_eq!(storage.lock().unwrap().data_gets.len(), 1);
+        assert_eq!(cache.hit_stats(), (1, 1));
+
+        // A single-file restore sharing the cache doesn't download either
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption).with_block_cache(cache.clone());
+        let mut restored = Vec::new();
+        backup.restore_file_to_writer(&manifest.backup_id, file, &mut restored).await.unwrap();
+        assert_eq!(restored, std::fs::read(&files[0]).unwrap());
+        assert_eq!(storage.lock().unwrap().data_gets.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_verification_resumes_from_checkpoint() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 4);
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let manifest = test_backup(&endpoint, data_dir.path(), &encryption).create_backup(&paths).await.unwrap();
+        let encryption = Arc::new(encryption);
+        storage.lock().unwrap().data_gets.clear();
+
+        // One file at a time, cancelled once two files have been checked
+        let checkpoint_dir = crate::VerifyCheckpoint::state_dir(data_dir.path());
+        let verifier = || {
+            let hetzner = test_server::client(&endpoint);
+            let cancel = tokio_util::sync::CancellationToken::new();
+            let progress: crate::ProgressCallback = {
+                let cancel = cancel.clone();
+                Arc::new(move |done, _| if done == 2 { cancel.cancel() })
+            };
+            crate::BackupVerifier::new(hetzner)
+                .with_concurrency(1)
+                .with_cancellation(cancel)
+                .with_progress(progress)
+                .with_temp_dir(data_dir.path().to_path_buf())
+                .with_checkpoint_dir(checkpoint_dir.clone())
+        };
+
+        let interrupted = verifier().verify_full(&manifest, encryption.clone()).await.unwrap();
+        assert!(interrupted.incomplete);
+        assert_eq!(interrupted.files_verified, 2);
+        let first_gets = std::mem::take(&mut storage.lock().unwrap().data_gets);
+        assert_eq!(first_gets.len(), 2);
+        assert!(crate::VerifyCheckpoint::state_file_path(&checkpoint_dir, &manifest.backup_id).exists());
+
+        // The resumed run only downloads the two unchecked files
+        let resumed = verifier().verify_full(&manifest, encryption.clone()).await.unwrap();
+        let second_gets = storage.lock().unwrap().data_gets.clone();
+        assert_eq!(second_gets.len(), 2);
+        assert!(second_gets.iter().all(|path| !first_gets.contains(path)));
+        assert!(resumed.passed && !resumed.incomplete);
+        assert_eq!(resumed.files_verified, 4);
+        assert_eq!(resumed.file_results.len(), 4);
+        let mut checked: Vec<_> = resumed.file_results.iter().map(|f| f.path.clone()).collect();
+        checked.sort();
+        let mut expected: Vec<_> = manifest.files.iter().map(|f| f.local_path.clone()).collect();
+        expected.sort();
+        assert_eq!(checked, expected);
+
+        // A finished verification leaves no checkpoint behind
+        assert!(!crate::VerifyCheckpoint::state_file_path(&checkpoint_dir, &manifest.backup_id).exists());
+    }
+
+    #[test]
+    fn test_entry_decompress_checks_metadata() {
+        let original = "legacy manifest entry ".repeat(200).into_bytes();
+        let compressed = zstd::encode_all(original.as_slice(), 3).unwrap();
+        let mut entry = FileEntry {
+            local_path: PathBuf::from("/data/legacy.txt"),
+            remote_path: "/skylock/backups/b/data/legacy.txt.zst.enc".to_string(),
+            size: original.len() as u64,
+            hash: crate::compression_integrity::calculate_hash(&original),
+            compressed: true,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        };
+
+        // Entries without per-file metadata were always zstd
+        assert_eq!(entry.decompress(compressed.clone()).unwrap(), original);
+
+        entry.compression = Some(CompressionMetadata::for_algorithm(
+            CompressionAlgorithm::Zstd, 3, entry.hash.clone(), original.len() as u64, &compressed,
+        ));
+        assert_eq!(entry.decompress(compressed.clone()).unwrap(), original);
+
+        let mut tampered = compressed;
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0x01;
+        let err = entry.decompress(tampered).unwrap_err();
+        assert!(err.to_string().contains("does not match its recorded hash"));
+    }
+
+    #[tokio::test]
+    async fn test_large_file_is_streamed_and_restored_in_segments() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let paths = vec![source.path().to_path_buf()];
+
+        // Several segments of log lines, and a file below the threshold
+        let log = source.path().join("huge.log");
+        let contents: Vec<u8> = (0..120_000)
+            .flat_map(|i| format!("{:08} GET /api/items/{} 200\n", i, i * 7919 % 100_003).into_bytes())
+            .collect();
+        assert!(contents.len() > 3 * archive::SEGMENT_SIZE);
+        std::fs::write(&log, &contents).unwrap();
+        std::fs::write(source.path().join("small.txt"), b"in memory").unwrap();
+
+        // The first blob stored is corrupted, so one upload is read back and retried
+        let storage = Arc::new(Mutex::new(MockStorage { corrupt_puts: 1, ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let framing = SegmentFraming::new(archive::SEGMENT_SIZE as u64).unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_stream_threshold(Some(1024 * 1024))
+            .with_stream_framing(framing)
+            .with_verify_on_upload(Some(1));
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        let entry = manifest.files.iter().find(|entry| entry.local_path == log).unwrap();
+        assert_eq!(entry.framing, Some(framing));
+        assert!(!entry.is_chunked());
+        assert!(entry.compressed);
+        assert_eq!(entry.size, contents.len() as u64);
+        assert_eq!(entry.hash, crate::compression_integrity::calculate_hash(&contents));
+        let small = manifest.files.iter().find(|entry| entry.local_path != log).unwrap();
+        assert!(!small.is_streamed());
+        let blob = storage.lock().unwrap().files[&entry.remote_path].clone();
+        assert!(archive::is_streamed_archive(&blob));
+        assert!((blob.len() as u64) < entry.size);
+
+        // Whole restores, single files to a writer and ranges all read it back
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        let restored = restore_dir.path().join(log.strip_prefix("/").unwrap());
+        assert_eq!(std::fs::read(&restored).unwrap(), contents);
+        let mut stdout = Vec::new();
+        let written = backup.restore_file_to_writer(&manifest.backup_id, log.to_str().unwrap(), &mut stdout).await.unwrap();
+        assert_eq!((written, stdout == contents), (contents.len() as u64, true));
+        let range = backup.fetch_file_range(entry, &manifest, 1000..2000).await.unwrap();
+        assert_eq!(range, &contents[1000..2000]);
+        let tail = backup.fetch_file_tail(entry, &manifest, 2).await.unwrap();
+        assert_eq!(tail, tail_lines(&contents, 2));
+
+        // Restores use the recorded frame size, not the configured one
+        let default_framing = test_backup(&endpoint, data_dir.path(), &encryption);
+        let mut stdout = Vec::new();
+        default_framing.restore_file_to_writer(&manifest.backup_id, log.to_str().unwrap(), &mut stdout).await.unwrap();
+        assert!(stdout == contents);
+
+        // A damaged segment fails the restore and leaves no partial file
+        storage.lock().unwrap().files.get_mut(&entry.remote_path).unwrap()[100] ^= 0xFF;
+        std::fs::remove_dir_all(restore_dir.path()).unwrap();
+        assert!(backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.is_err());
+        assert!(!restored.exists());
+        let err = backup.restore_file_to_writer(&manifest.backup_id, log.to_str().unwrap(), std::io::sink()).await.unwrap_err();
+        assert!(matches!(err, SkylockError::Integrity(_)), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_file_range_and_tail_read_only_their_segments() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let paths = vec![source.path().to_path_buf()];
+        
+        let log = source.path().join("app.log");
+        let contents: Vec<u8> = (0..12_000)
+            .flat_map(|i| format!("{:08} GET /api/items/{}\n", i, i * 7919 % 100_003).into_bytes())
+            .collect();
+        let framing = SegmentFraming::new(archive::MIN_SEGMENT_SIZE as u64).unwrap();
+        assert!(framing.segments_for(contents.len() as u64) > 3);
+        std::fs::write(&log, &contents).unwrap();
+        
+        let storage = Arc::new(Mutex::new(MockStorage { ranges: true, ..Default::default() }));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_stream_threshold(Some(64 * 1024))
+            .with_stream_framing(framing)
+            .with_compression_override(CompressionOverride::new(Some(CompressionAlgorithm::None), None).unwrap());
+        let manifest = backup.create_backup(&paths).await.unwrap();
+        let entry = &manifest.files[0];
+        assert!(entry.is_streamed() && !entry.compressed);
+        
+        // The tail reads the last segment and a range the two it spans
+        storage.lock().unwrap().data_gets.clear();
+        let tail = backup.fetch_file_tail(entry, &manifest, 3).await.unwrap();
+        assert_eq!(tail, tail_lines(&contents, 3));
+        let segment_size = framing.segment_size as u64;
+        let range = segment_size - 10..segment_size + 10;
+        let data = backup.fetch_file_range(entry, &manifest, range.clone()).await.unwrap();
+        assert_eq!(data, &contents[range.start as usize..range.end as usize]);
+        {
+            let storage = storage.lock().unwrap();
+            assert!(storage.data_gets.is_empty(), "{:?}", storage.data_gets);
+            let last = framing.frame_range(entry.size, framing.segments_for(entry.size) - 1);
+            let spanned = framing.frame_range(entry.size, 0).start..framing.frame_range(entry.size, 1).end;
+            let ranges: Vec<&str> = storage.range_gets.iter().map(|(_, range)| range.as_str()).collect();
+            assert_eq!(ranges, [
+                format!("{}-{}", last.start, last.end - 1),
+                format!("{}-{}", spanned.start, spanned.end - 1),
+            ]);
+        }
+        
+        // Without range support the blob is decoded as a stream instead
+        storage.lock().unwrap().ranges = false;
+        assert_eq!(backup.fetch_file_tail(entry, &manifest, 3).await.unwrap(), tail);
+        assert_eq!(backup.fetch_file_range(entry, &manifest, range.clone()).await.unwrap(), data);
+        assert_eq!(storage.lock().unwrap().range_gets.len(), 2);
+        
+        // A damaged segment is caught without the file's hash
+        storage.lock().unwrap().ranges = true;
+        let blob_len = storage.lock().unwrap().files[&entry.remote_path].len();
+        storage.lock().unwrap().files.get_mut(&entry.remote_path).unwrap()[blob_len - 20] ^= 0xFF;
+        let err = backup.fetch_file_tail(entry, &manifest, 3).await.unwrap_err();
+        assert!(matches!(err, SkylockError::Integrity(_)), "{}", err);
+    }
+    
+    #[tokio::test]
+    async fn test_file_over_max_size_is_skipped_and_recorded() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let files = create_source_files(source.path(), 2);
+        let image = source.path().join("disk.img");
+        std::fs::write(&image, vec![7u8; 64 * 1024]).unwrap();
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_max_file_size(Some(4096), true);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        // The image is never uploaded; the backup lists it with the reason
+        assert_eq!(manifest.file_count, 2);
+        assert!(manifest.files.iter().all(|e| files.contains(&e.local_path)));
+        assert_eq!(manifest.skipped_files, vec![SkippedFile {
+            path: image.clone(),
+            reason: "larger than max_file_size (64.00 KiB > 4.00 KiB)".to_string(),
+        }]);
+        assert_eq!(storage.lock().unwrap().data_puts.len(), 2);
+        let stored = backup.download_manifest(&manifest.backup_id).await.unwrap();
+        assert_eq!(stored.skipped_files, manifest.skipped_files);
+    }
+
+    #[tokio::test]
+    async fn test_file_over_max_size_is_streamed_by_default() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        create_source_files(source.path(), 2);
+        let dump = source.path().join("db.dump");
+        let contents: Vec<u8> = (0..256 * 1024u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&dump, &contents).unwrap();
+        let paths = vec![source.path().to_path_buf()];
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        // No stream threshold: only the size limit sends the dump down the
+        // streaming path
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_stream_threshold(None)
+            .with_max_file_size(Some(64 * 1024), false);
+        let manifest = backup.create_backup(&paths).await.unwrap();
+
+        assert!(manifest.skipped_files.is_empty());
+        assert_eq!(manifest.file_count, 3);
+        for entry in &manifest.files {
+            assert_eq!(entry.is_streamed(), entry.local_path == dump, "{}", entry.local_path.display());
+        }
+
+        backup.restore_backup(&manifest.backup_id, restore_dir.path()).await.unwrap();
+        let restored = restore_dir.path().join(dump.strip_prefix("/").unwrap());
+        assert_eq!(std::fs::read(&restored).unwrap(), contents);
+    }
+
+    #[tokio::test]
+    async fn test_find_files_across_backups() {
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let paths = vec![source.path().to_path_buf()];
+        let thesis = source.path().join("thesis.tex");
+        let notes = source.path().join("notes.txt");
+        std::fs::write(&thesis, "draft").unwrap();
+        std::fs::write(&notes, "todo").unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let first = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_tags(vec!["pre-edit".to_string()], None)
+            .create_backup(&paths).await.unwrap();
+
+        // The thesis grows and the notes are deleted
+        std::fs::write(&thesis, "final version").unwrap();
+        std::fs::remove_file(&notes).unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption);
+        let second = backup.create_backup(&paths).await.unwrap();
+
+        let found = backup.find_files(PathGlob::new("thesis.tex"), |_| true).await.unwrap();
+        assert!(found.skipped.is_empty());
+        let found = found.files;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, thesis);
+        let history: Vec<_> = found[0].versions.iter().map(|v| (v.backup_id.as_str(), v.size)).collect();
+        assert_eq!(history, vec![(first.backup_id.as_str(), 5), (second.backup_id.as_str(), 13)]);
+        assert_eq!(found[0].versions[1].hash, crate::compression_integrity::calculate_hash(b"final version"));
+        assert!(found[0].versions[1].modified.is_some());
+
+        let found = backup.find_files(PathGlob::new("*.txt"), |_| true).await.unwrap().files;
+        assert_eq!(found[0].versions.len(), 1);
+        assert_eq!(found[0].versions[0].backup_id, first.backup_id);
+
+        // Backups the filter rejects aren't even downloaded
+        storage.lock().unwrap().manifest_gets.clear();
+        let found = backup.find_files(PathGlob::new("thesis.tex"), |summary| summary.tags.iter().any(|t| t == "pre-edit")).await.unwrap().files;
+        assert_eq!(found[0].versions.len(), 1);
+        assert_eq!(found[0].versions[0].backup_id, first.backup_id);
+        let gets = storage.lock().unwrap().manifest_gets.clone();
+        assert!(!gets.is_empty() && gets.iter().all(|path| path.contains(&first.backup_id)), "{:?}", gets);
+        
+        // A backup whose manifest can't be read is reported, not silently dropped
+        storage.lock().unwrap().files.insert(
+            format!("/skylock/backups/{}/manifest.json.enc", second.backup_id),
+            b"not a manifest".to_vec(),
+        );
+        let found = backup.find_files(PathGlob::new("thesis.tex"), |_| true).await.unwrap();
+        assert_eq!(found.files[0].versions.len(), 1);
+        assert_eq!(found.skipped.len(), 1);
+        assert_eq!(found.skipped[0].backup_id, second.backup_id);
+        assert!(!found.skipped[0].error.is_empty());
+    }
+
+    /// Peak resident set size of this process, where the platform reports it
+    fn peak_rss() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let kib = status.lines().find_map(|line| line.strip_prefix("VmHWM:"))?;
+        Some(kib.trim().trim_end_matches("kB").trim().parse::<u64>().ok()? * 1024)
+    }
+
+    /// Hashes what is written to it
+    struct HashingSink(Sha256, u64);
+
+    impl std::io::Write for HashingSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.update(buf);
+            self.1 += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    #[ignore] // Streams 2 GiB each way; run with --ignored, ideally in release mode
+    async fn test_two_gigabyte_file_streams_under_memory_ceiling() {
+        const SIZE: u64 = 2 * 1024 * 1024 * 1024;
+        const MEMORY_CEILING: u64 = 256 * 1024 * 1024;
+
+        let source = TempDir::new().unwrap();
+        let data_dir = TempDir::new().unwrap();
+        let path = source.path().join("disk.img");
+
+        // Sparse, so it takes no disk space, with a few marks to hash
+        {
+            use std::io::{Seek, Write};
+            let mut file = std::fs::File::create(&path).unwrap();
+            file.set_len(SIZE).unwrap();
+            for offset in (0..SIZE).step_by(256 * 1024 * 1024) {
+                file.seek(std::io::SeekFrom::Start(offset)).unwrap();
+                file.write_all(format!("mark at {}", offset).as_bytes()).unwrap();
+            }
+        }
+        let (expected_hash, _) = file_stream::hash_reader(std::io::BufReader::new(std::fs::File::open(&path).unwrap()), HashAlgorithm::Sha256).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let backup = test_backup(&endpoint, data_dir.path(), &encryption)
+            .with_stream_threshold(Some(64 * 1024 * 1024))
+            .with_multipart_download(None);
+
+        let before = peak_rss();
+        let manifest = backup.create_backup(&[source.path().to_path_buf()]).await.unwrap();
+        let entry = &manifest.files[0];
+        assert!(entry.is_streamed());
+        assert_eq!((entry.size, entry.hash.as_str()), (SIZE, expected_hash.as_str()));
+
+        let mut sink = HashingSink(Sha256::new(), 0);
+        let written = backup.restore_file_to_writer(&manifest.backup_id, path.to_str().unwrap(), &mut sink).await.unwrap();
+        assert_eq!(written, SIZE);
+        assert_eq!(sink.1, SIZE);
+        assert_eq!(format!("{:x}", sink.0.finalize()), expected_hash);
+
+        if let (Some(before), Some(after)) = (before, peak_rss()) {
+            assert!(
+                after.saturating_sub(before) < MEMORY_CEILING,
+                "peak memory grew by {} bytes", after - before
+            );
+        }
+    }
 }
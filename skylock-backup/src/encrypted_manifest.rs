@@ -9,16 +9,36 @@
 //! The storage provider sees only encrypted blobs.
 //!
 //! Architecture:
-//! - `manifest.json.enc` - Encrypted full manifest (AES-256-GCM)
+//! - `manifest.json.enc` - Encrypted full manifest (AES-256-GCM or ChaCha20-Poly1305)
 //! - `manifest_header.json` - Public header for backup listing (backup_id, timestamp only)
+//! - `manifest_summary.json.enc` - Encrypted [`ManifestSummary`], so listing
+//!   backups doesn't download every full manifest
+//! - `summary.json` - Public figures of the backup run for monitoring (see
+//!   [`crate::run_summary`]); no file names
+//!
+//! With `backup.compress_manifests`, the manifest JSON is zstd-compressed
+//! before encryption and the header records it. Decryption recognises the
+//! zstd frame, so compressed and older uncompressed manifests both load.
+//!
+//! With `backup.encrypt_file_names`, paths inside the manifest are replaced by
+//! placeholders and each real path is encrypted separately, so names are only
+//! recovered after the manifest itself has been decrypted.
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 use sha2::{Sha256, Digest};
 use crate::error::{Result, SkylockError};
-use crate::encryption::EncryptionManager;
+use crate::encryption::{AeadAlgorithm, EncryptionManager};
 use crate::direct_upload::{BackupManifest, FileEntry};
+use skylock_core::storage::StorageTier;
+
+/// Start of every zstd frame, never the start of manifest JSON
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// zstd level for compressed manifests
+const MANIFEST_COMPRESSION_LEVEL: i32 = 9;
 
 /// Public manifest header - visible without encryption key
 /// Contains minimal info needed for backup listing
@@ -34,12 +54,25 @@ pub struct ManifestHeader {
     pub total_size: u64,
     /// Encryption format version
     pub encryption_version: String,
+    /// AEAD cipher used for the manifest and file data (absent = AES-256-GCM)
+    #[serde(default)]
+    pub aead_algorithm: AeadAlgorithm,
+    /// Keyfile data key version (absent = key derived from the password)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_version: Option<u64>,
     /// Whether manifest is encrypted (v3+ always true)
     pub manifest_encrypted: bool,
     /// SHA-256 hash of encrypted manifest for integrity
     pub encrypted_manifest_hash: String,
     /// Version of manifest format
     pub manifest_format_version: u32,
+    /// Whether the manifest JSON was zstd-compressed before encryption
+    #[serde(default)]
+    pub manifest_compressed: bool,
+    /// [`EncryptionManager::key_fingerprint`] of the key the manifest was
+    /// encrypted with (absent in older backups)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_fingerprint: Option<String>,
 }
 
 impl ManifestHeader {
@@ -51,9 +84,48 @@ impl ManifestHeader {
             file_count: manifest.file_count,
             total_size: manifest.total_size,
             encryption_version: manifest.encryption_version.clone(),
+            aead_algorithm: manifest.aead_algorithm,
+            key_version: manifest.key_version,
             manifest_encrypted: true,
             encrypted_manifest_hash: encrypted_hash.to_string(),
             manifest_format_version: 3, // v3 = encrypted manifests
+            manifest_compressed: false,
+            key_fingerprint: None,
+        }
+    }
+}
+
+/// Listing details of a backup, stored encrypted next to its manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestSummary {
+    pub backup_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub file_count: usize,
+    pub total_size: u64,
+    pub source_paths: Vec<PathBuf>,
+    /// Backup this one is incremental on, if any
+    #[serde(default)]
+    pub base_backup_id: Option<String>,
+    #[serde(default, skip_serializing_if = "StorageTier::is_standard")]
+    pub storage_tier: StorageTier,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl ManifestSummary {
+    pub fn from_manifest(manifest: &BackupManifest) -> Self {
+        Self {
+            backup_id: manifest.backup_id.clone(),
+            timestamp: manifest.timestamp,
+            file_count: manifest.file_count,
+            total_size: manifest.total_size,
+            source_paths: manifest.source_paths.clone(),
+            base_backup_id: manifest.base_backup_id.clone(),
+            storage_tier: manifest.storage_tier,
+            tags: manifest.tags.clone(),
+            note: manifest.note.clone(),
         }
     }
 }
@@ -82,6 +154,9 @@ pub struct FileTreeNode {
     pub hash: String,
     /// Whether compressed
     pub compressed: bool,
+    /// Whether the contents were stored encrypted (false for directories)
+    #[serde(default)]
+    pub encrypted: bool,
     /// Last modified timestamp
     pub timestamp: DateTime<Utc>,
     /// Children (for directories)
@@ -103,6 +178,7 @@ impl FileTreeNode {
             size: entry.size,
             hash: entry.hash.clone(),
             compressed: entry.compressed,
+            encrypted: entry.encrypted,
             timestamp: entry.timestamp,
             children: Vec::new(),
         }
@@ -117,6 +193,7 @@ impl FileTreeNode {
             size: 0,
             hash: String::new(),
             compressed: false,
+            encrypted: false,
             timestamp: Utc::now(),
             children: Vec::new(),
         }
@@ -196,12 +273,45 @@ pub fn build_file_tree(files: &[FileEntry]) -> Vec<FileTreeNode> {
 /// Manifest encryption handler
 pub struct ManifestEncryption<'a> {
     encryption: &'a EncryptionManager,
+    encrypt_names: bool,
+    compress: bool,
 }
 
 impl<'a> ManifestEncryption<'a> {
     /// Create new manifest encryption handler
     pub fn new(encryption: &'a EncryptionManager) -> Self {
-        Self { encryption }
+        Self { encryption, encrypt_names: false, compress: false }
+    }
+    
+    /// Replace file and source paths with placeholders and store the real
+    /// paths encrypted in `encrypted_path_map`
+    pub fn with_encrypted_names(mut self, enabled: bool) -> Self {
+        self.encrypt_names = enabled;
+        self
+    }
+    
+    /// zstd-compress the manifest JSON before encrypting it
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compress = enabled;
+        self
+    }
+    
+    /// Serialize a manifest to the JSON that gets encrypted, concealing
+    /// names first when name encryption is enabled
+    pub fn serialize_manifest(&self, manifest: &BackupManifest) -> Result<Vec<u8>> {
+        let concealed;
+        let manifest = if self.encrypt_names {
+            let encryption = self.encryption.for_algorithm(manifest.aead_algorithm);
+            concealed = conceal_names(manifest, &encryption)?;
+            &concealed
+        } else {
+            manifest
+        };
+        
+        serde_json::to_vec_pretty(manifest)
+            .map_err(|e| SkylockError::Encryption(
+                format!("Failed to serialize manifest: {}", e)
+            ))
     }
     
     /// Encrypt a backup manifest
@@ -209,13 +319,16 @@ impl<'a> ManifestEncryption<'a> {
     /// Returns the encrypted data and public header
     pub fn encrypt_manifest(&self, manifest: &BackupManifest) -> Result<EncryptedManifest> {
         // Serialize manifest to JSON
-        let manifest_json = serde_json::to_vec_pretty(manifest)
-            .map_err(|e| SkylockError::Encryption(
-                format!("Failed to serialize manifest: {}", e)
-            ))?;
+        let mut manifest_json = self.serialize_manifest(manifest)?;
+        if self.compress {
+            manifest_json = zstd::encode_all(manifest_json.as_slice(), MANIFEST_COMPRESSION_LEVEL)
+                .map_err(|e| SkylockError::Encryption(format!("Failed to compress manifest: {}", e)))?;
+        }
         
-        // Encrypt with AAD binding to backup_id
-        let encrypted_data = self.encryption.encrypt_with_aad(
+        // Encrypt with AAD binding to backup_id, using the algorithm the
+        // manifest records so the header always matches the ciphertext
+        let encryption = self.encryption.for_algorithm(manifest.aead_algorithm);
+        let encrypted_data = encryption.encrypt_with_aad(
             &manifest_json,
             &manifest.backup_id,
             "manifest.json"
@@ -227,7 +340,9 @@ impl<'a> ManifestEncryption<'a> {
         let hash = format!("{:x}", hasher.finalize());
         
         // Create public header
-        let header = ManifestHeader::from_manifest(manifest, &hash);
+        let mut header = ManifestHeader::from_manifest(manifest, &hash);
+        header.manifest_compressed = self.compress;
+        header.key_fingerprint = Some(encryption.key_fingerprint());
         
         Ok(EncryptedManifest {
             header,
@@ -243,6 +358,18 @@ impl<'a> ManifestEncryption<'a> {
         encrypted_data: &[u8],
         backup_id: &str,
     ) -> Result<BackupManifest> {
+        BackupManifest::from_json(&self.decrypt_manifest_json(encrypted_data, backup_id)?)
+    }
+    
+    /// Decrypt a backup manifest to its JSON as it was stored, with any
+    /// encrypted names revealed but no schema upgrade applied
+    /// 
+    /// This is the form manifest signatures are checked against.
+    pub fn decrypt_manifest_json(
+        &self,
+        encrypted_data: &[u8],
+        backup_id: &str,
+    ) -> Result<Vec<u8>> {
         // Decrypt with AAD verification
         let decrypted = self.encryption.decrypt_with_aad(
             encrypted_data,
@@ -250,13 +377,83 @@ impl<'a> ManifestEncryption<'a> {
             "manifest.json"
         )?;
         
-        // Deserialize JSON
-        let manifest: BackupManifest = serde_json::from_slice(&decrypted)
-            .map_err(|e| SkylockError::Encryption(
-                format!("Failed to deserialize manifest: {}", e)
-            ))?;
+        // Manifests written without compression are plain JSON
+        let decrypted = if decrypted.starts_with(&ZSTD_MAGIC) {
+            zstd::decode_all(decrypted.as_slice())
+                .map_err(|e| SkylockError::Encryption(format!("Failed to decompress manifest: {}", e)))?
+        } else {
+            decrypted
+        };
         
-        Ok(manifest)
+        // Names are only recovered once the manifest itself is decrypted
+        let mut manifest: serde_json::Value = serde_json::from_slice(&decrypted)
+            .map_err(|e| SkylockError::Backup(format!("Parse manifest failed: {}", e)))?;
+        if !reveal_names(&mut manifest, self.encryption)? {
+            return Ok(decrypted);
+        }
+        serde_json::to_vec(&manifest)
+            .map_err(|e| SkylockError::Encryption(format!("Failed to serialize manifest: {}", e)))
+    }
+    
+    /// Fail with [`SkylockError::WrongKey`] if the header records a
+    /// different key than the one this handler decrypts with
+    pub fn check_key(&self, header: &ManifestHeader) -> Result<()> {
+        match &header.key_fingerprint {
+            Some(expected) if *expected != self.encryption.key_fingerprint() => {
+                Err(SkylockError::WrongKey(format!(
+                    "backup {} was encrypted with key {}, the configured key is {}",
+                    header.backup_id, expected, self.encryption.key_fingerprint()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    /// Decrypt a manifest after checking the key against its header
+    ///
+    /// With a matching key fingerprint a failed decryption can only mean
+    /// damaged data, reported as [`SkylockError::Integrity`]. Headers
+    /// without a fingerprint keep the generic decryption error.
+    pub fn decrypt_manifest_checked(
+        &self,
+        encrypted_data: &[u8],
+        backup_id: &str,
+        header: &ManifestHeader,
+    ) -> Result<BackupManifest> {
+        BackupManifest::from_json(&self.decrypt_manifest_json_checked(encrypted_data, backup_id, header)?)
+    }
+    
+    /// [`Self::decrypt_manifest_json`] after checking the key against the
+    /// manifest's header, like [`Self::decrypt_manifest_checked`]
+    pub fn decrypt_manifest_json_checked(
+        &self,
+        encrypted_data: &[u8],
+        backup_id: &str,
+        header: &ManifestHeader,
+    ) -> Result<Vec<u8>> {
+        self.check_key(header)?;
+        self.decrypt_manifest_json(encrypted_data, backup_id).map_err(|e| match e {
+            SkylockError::Encryption(msg) if header.key_fingerprint.is_some() => SkylockError::Integrity(format!(
+                "manifest of backup {} is corrupted (the key matches but decryption failed: {})",
+                backup_id, msg
+            )),
+            e => e,
+        })
+    }
+    
+    /// Encrypt the listing summary of a manifest
+    pub fn encrypt_summary(&self, manifest: &BackupManifest) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec(&ManifestSummary::from_manifest(manifest))
+            .map_err(|e| SkylockError::Encryption(format!("Failed to serialize manifest summary: {}", e)))?;
+        let encryption = self.encryption.for_algorithm(manifest.aead_algorithm);
+        encryption.encrypt_with_aad(&json, &manifest.backup_id, "manifest_summary.json")
+    }
+    
+    /// Decrypt a listing summary written by [`Self::encrypt_summary`]
+    pub fn decrypt_summary(&self, encrypted_data: &[u8], backup_id: &str) -> Result<ManifestSummary> {
+        let json = self.encryption.decrypt_with_aad(encrypted_data, backup_id, "manifest_summary.json")?;
+        serde_json::from_slice(&json)
+            .map_err(|e| SkylockError::Encryption(format!("Failed to parse manifest summary: {}", e)))
     }
     
     /// Verify encrypted manifest integrity
@@ -271,6 +468,114 @@ impl<'a> ManifestEncryption<'a> {
     }
 }
 
+/// AAD context binding an encrypted name to its placeholder, so names can't
+/// be swapped between entries
+fn name_context(placeholder: &str) -> String {
+    format!("name:{}", placeholder)
+}
+
+/// Copy of `manifest` with every path replaced by a placeholder (`s<n>` for
+/// source paths, `f<n>` for files, `r<n>` for remote paths, which still
+/// carry names for blobs reused from backups without name encryption,
+/// `o<n>` for the origin of a reused blob, `d<n>` for deleted paths and
+/// `t<n>` for directories)
+fn conceal_names(manifest: &BackupManifest, encryption: &EncryptionManager) -> Result<BackupManifest> {
+    use base64::Engine;
+    
+    let mut concealed = manifest.clone();
+    let mut names = HashMap::new();
+    let mut conceal = |path: &str, placeholder: String| -> Result<String> {
+        let encrypted = encryption.encrypt_with_aad(
+            path.as_bytes(),
+            &manifest.backup_id,
+            &name_context(&placeholder),
+        )?;
+        names.insert(placeholder.clone(), base64::engine::general_purpose::STANDARD.encode(encrypted));
+        Ok(placeholder)
+    };
+    
+    for (i, path) in concealed.source_paths.iter_mut().enumerate() {
+        *path = conceal(&path.to_string_lossy(), format!("s{}", i))?.into();
+    }
+    for (i, entry) in concealed.files.iter_mut().enumerate() {
+        entry.local_path = conceal(&entry.local_path.to_string_lossy(), format!("f{}", i))?.into();
+        entry.remote_path = conceal(&entry.remote_path, format!("r{}", i))?;
+        if let Some(ref mut origin) = entry.blob_origin {
+            origin.local_path = conceal(&origin.local_path.to_string_lossy(), format!("o{}", i))?.into();
+        }
+    }
+    for (i, path) in concealed.deleted_paths.iter_mut().enumerate() {
+        *path = conceal(&path.to_string_lossy(), format!("d{}", i))?.into();
+    }
+    for (i, directory) in concealed.directories.iter_mut().enumerate() {
+        directory.path = conceal(&directory.path.to_string_lossy(), format!("t{}", i))?.into();
+    }
+    
+    concealed.encrypted_path_map = Some(names);
+    Ok(concealed)
+}
+
+/// Restore the paths of a manifest JSON written with encrypted names,
+/// returning whether it had any
+/// 
+/// Works on the JSON rather than a parsed manifest, so the fields keep the
+/// order and layout they were signed with.
+fn reveal_names(manifest: &mut serde_json::Value, encryption: &EncryptionManager) -> Result<bool> {
+    use base64::Engine;
+    use serde_json::Value;
+    
+    let Some(object) = manifest.as_object_mut() else {
+        return Ok(false);
+    };
+    let names: HashMap<String, String> = match object.shift_remove("encrypted_path_map") {
+        None | Some(Value::Null) => return Ok(false),
+        Some(names) => serde_json::from_value(names)
+            .map_err(|e| SkylockError::Encryption(format!("Invalid encrypted name map: {}", e)))?,
+    };
+    let backup_id = object.get("backup_id").and_then(Value::as_str).unwrap_or_default().to_string();
+    let reveal_name = |placeholder: &str| -> Result<String> {
+        let encoded = names.get(placeholder).ok_or_else(|| SkylockError::Encryption(
+            format!("Manifest has no encrypted name for {}", placeholder)
+        ))?;
+        let encrypted = base64::engine::general_purpose::STANDARD.decode(encoded)
+            .map_err(|e| SkylockError::Encryption(format!("Invalid encrypted name for {}: {}", placeholder, e)))?;
+        let name = encryption.decrypt_with_aad(&encrypted, &backup_id, &name_context(placeholder))?;
+        String::from_utf8(name)
+            .map_err(|e| SkylockError::Encryption(format!("Invalid file name for {}: {}", placeholder, e)))
+    };
+    let reveal = |value: Option<&mut Value>| -> Result<()> {
+        if let Some(value) = value {
+            if let Some(placeholder) = value.as_str() {
+                *value = Value::String(reveal_name(placeholder)?);
+            }
+        }
+        Ok(())
+    };
+    for path in array_items(object, "source_paths") {
+        reveal(Some(path))?;
+    }
+    for entry in array_items(object, "files") {
+        reveal(entry.get_mut("local_path"))?;
+        reveal(entry.get_mut("remote_path"))?;
+        reveal(entry.get_mut("blob_origin").and_then(|origin| origin.get_mut("local_path")))?;
+    }
+    for path in array_items(object, "deleted_paths") {
+        reveal(Some(path))?;
+    }
+    for directory in array_items(object, "directories") {
+        reveal(directory.get_mut("path"))?;
+    }
+    Ok(true)
+}
+
+/// The elements of the array at `key`, if there is one
+fn array_items<'a>(
+    object: &'a mut serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> impl Iterator<Item = &'a mut serde_json::Value> {
+    object.get_mut(key).and_then(serde_json::Value::as_array_mut).into_iter().flatten()
+}
+
 /// Browseable backup - decrypted view of a backup for authorized users
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrowseableBackup {
@@ -397,19 +702,7 @@ pub struct BackupSummary {
 impl BackupSummary {
     /// Format size in human-readable format
     pub fn format_size(bytes: u64) -> String {
-        const KB: u64 = 1024;
-        const MB: u64 = KB * 1024;
-        const GB: u64 = MB * 1024;
-        
-        if bytes >= GB {
-            format!("{:.2} GB", bytes as f64 / GB as f64)
-        } else if bytes >= MB {
-            format!("{:.2} MB", bytes as f64 / MB as f64)
-        } else if bytes >= KB {
-            format!("{:.2} KB", bytes as f64 / KB as f64)
-        } else {
-            format!("{} B", bytes)
-        }
+        skylock_core::ByteSize(bytes).to_string()
     }
     
     /// Get compression ratio
@@ -435,17 +728,28 @@ mod tests {
             size,
             hash: "abc123".to_string(),
             compressed,
+            compression: None,
             encrypted: true,
+            key_context: None,
             timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
         }
     }
 
     #[test]
     fn test_file_tree_building() {
+        let mut plain = create_test_entry("/home/user/projects/code.rs", 2000, false);
+        plain.encrypted = false;
         let files = vec![
             create_test_entry("/home/user/doc.txt", 100, false),
             create_test_entry("/home/user/image.png", 5000, true),
-            create_test_entry("/home/user/projects/code.rs", 2000, false),
+            plain,
         ];
         
         let tree = build_file_tree(&files);
@@ -456,6 +760,11 @@ mod tests {
         // First directory should have 2 files
         let user_dir = tree.iter().find(|d| d.path.ends_with("user")).unwrap();
         assert_eq!(user_dir.children.len(), 2);
+        
+        // Each file keeps its own encryption state
+        assert!(user_dir.children.iter().all(|file| file.encrypted));
+        let projects_dir = tree.iter().find(|d| d.path.ends_with("projects")).unwrap();
+        assert!(!projects_dir.children[0].encrypted);
     }
 
     #[test]
@@ -468,6 +777,7 @@ mod tests {
             size: 100,
             hash: String::new(),
             compressed: false,
+            encrypted: true,
             timestamp: Utc::now(),
             children: vec![],
         });
@@ -478,6 +788,7 @@ mod tests {
             size: 200,
             hash: String::new(),
             compressed: false,
+            encrypted: true,
             timestamp: Utc::now(),
             children: vec![],
         });
@@ -489,6 +800,7 @@ mod tests {
     #[test]
     fn test_manifest_header_creation() {
         let manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
             backup_id: "test_backup".to_string(),
             timestamp: Utc::now(),
             files: vec![],
@@ -498,9 +810,19 @@ mod tests {
             base_backup_id: None,
             encryption_version: "v2".to_string(),
             kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
             signature: None,
             backup_chain_version: 0,
             encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
         };
         
         let header = ManifestHeader::from_manifest(&manifest, "abc123hash");
@@ -518,6 +840,7 @@ mod tests {
         let handler = ManifestEncryption::new(&encryption);
         
         let manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
             backup_id: "test_backup".to_string(),
             timestamp: Utc::now(),
             files: vec![create_test_entry("/test/file.txt", 100, false)],
@@ -527,9 +850,19 @@ mod tests {
             base_backup_id: None,
             encryption_version: "v2".to_string(),
             kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
             signature: None,
             backup_chain_version: 0,
             encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
         };
         
         // Encrypt
@@ -557,6 +890,210 @@ mod tests {
         assert_eq!(decrypted.files[0].local_path, PathBuf::from("/test/file.txt"));
     }
 
+    #[test]
+    fn test_wrong_key_detected_from_header() {
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let handler = ManifestEncryption::new(&encryption);
+        let manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
+            backup_id: "test_backup".to_string(),
+            timestamp: Utc::now(),
+            files: vec![create_test_entry("/test/file.txt", 100, false)],
+            total_size: 100,
+            file_count: 1,
+            source_paths: vec![PathBuf::from("/test")],
+            base_backup_id: None,
+            encryption_version: "v2".to_string(),
+            kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
+            signature: None,
+            backup_chain_version: 0,
+            encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
+        };
+        let encrypted = handler.encrypt_manifest(&manifest).unwrap();
+        assert_eq!(encrypted.header.key_fingerprint, Some(encryption.key_fingerprint()));
+
+        // A different passphrase is reported as a wrong key, not a
+        // decryption failure
+        let other = EncryptionManager::new("other_password").unwrap();
+        let err = ManifestEncryption::new(&other)
+            .decrypt_manifest_checked(&encrypted.encrypted_data, "test_backup", &encrypted.header)
+            .unwrap_err();
+        assert!(matches!(err, SkylockError::WrongKey(_)), "unexpected error: {}", err);
+        assert!(err.to_string().contains("doesn't match this backup"));
+
+        // With the right key, damaged data is an integrity failure
+        let mut damaged = encrypted.encrypted_data.clone();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 0xff;
+        let err = handler.decrypt_manifest_checked(&damaged, "test_backup", &encrypted.header).unwrap_err();
+        assert!(matches!(err, SkylockError::Integrity(_)), "unexpected error: {}", err);
+
+        // Headers from before fingerprints keep the generic error
+        let mut old_header = encrypted.header.clone();
+        old_header.key_fingerprint = None;
+        let err = ManifestEncryption::new(&other)
+            .decrypt_manifest_checked(&encrypted.encrypted_data, "test_backup", &old_header)
+            .unwrap_err();
+        assert!(matches!(err, SkylockError::Encryption(_)), "unexpected error: {}", err);
+
+        let decrypted = handler
+            .decrypt_manifest_checked(&encrypted.encrypted_data, "test_backup", &encrypted.header)
+            .unwrap();
+        assert_eq!(decrypted.files.len(), 1);
+    }
+
+    #[test]
+    fn test_compressed_manifest_roundtrip() {
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let files: Vec<_> = (0..200)
+            .map(|i| create_test_entry(&format!("/home/user/photos/img_{:04}.jpg", i), 1000, false))
+            .collect();
+        let manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
+            backup_id: "compressed_backup".to_string(),
+            timestamp: Utc::now(),
+            total_size: 200_000,
+            file_count: files.len(),
+            files,
+            source_paths: vec![PathBuf::from("/home/user")],
+            base_backup_id: None,
+            encryption_version: "v2".to_string(),
+            kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
+            signature: None,
+            backup_chain_version: 0,
+            encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
+        };
+
+        let plain = ManifestEncryption::new(&encryption).encrypt_manifest(&manifest).unwrap();
+        let handler = ManifestEncryption::new(&encryption).with_compression(true);
+        let compressed = handler.encrypt_manifest(&manifest).unwrap();
+        assert!(compressed.header.manifest_compressed);
+        assert!(!plain.header.manifest_compressed);
+        assert!(compressed.encrypted_data.len() * 4 < plain.encrypted_data.len());
+
+        // Both forms decrypt with the same handler
+        for encrypted in [&compressed, &plain] {
+            let decrypted = handler.decrypt_manifest(&encrypted.encrypted_data, "compressed_backup").unwrap();
+            assert_eq!(decrypted.files.len(), 200);
+            assert_eq!(decrypted.files[7].local_path, manifest.files[7].local_path);
+        }
+
+        // Headers written before compression existed read as uncompressed
+        let mut legacy = serde_json::to_value(&plain.header).unwrap();
+        legacy.as_object_mut().unwrap().remove("manifest_compressed");
+        assert!(!serde_json::from_value::<ManifestHeader>(legacy).unwrap().manifest_compressed);
+
+        let summary = handler.decrypt_summary(&handler.encrypt_summary(&manifest).unwrap(), "compressed_backup").unwrap();
+        assert_eq!(summary, ManifestSummary::from_manifest(&manifest));
+        assert!(handler.decrypt_summary(&handler.encrypt_summary(&manifest).unwrap(), "other_backup").is_err());
+    }
+
+    #[test]
+    fn test_encrypted_names_hidden_until_decryption() {
+        let encryption = EncryptionManager::new("test_password").unwrap();
+        let mut moved = create_test_entry("/home/alice/secret-project/renamed.txt", 50, false);
+        moved.blob_origin = Some(crate::direct_upload::BlobOrigin {
+            backup_id: "older_backup".to_string(),
+            local_path: PathBuf::from("/home/alice/secret-project/original.txt"),
+        });
+        let manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
+            backup_id: "names_test".to_string(),
+            timestamp: Utc::now(),
+            files: vec![
+                create_test_entry("/home/alice/secret-project/plan.txt", 100, false),
+                create_test_entry("/home/alice/photos/holiday.png", 5000, true),
+                moved,
+            ],
+            total_size: 5150,
+            file_count: 3,
+            source_paths: vec![PathBuf::from("/home/alice")],
+            base_backup_id: None,
+            encryption_version: "v2".to_string(),
+            kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
+            signature: None,
+            backup_chain_version: 0,
+            encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: vec![PathBuf::from("/home/alice/secret-project/draft.txt")],
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
+        };
+        let names = ["alice", "secret-project", "plan.txt", "photos", "holiday", "renamed", "original", "draft"];
+        
+        // Without the toggle the decrypted manifest carries the paths
+        let plain = String::from_utf8(ManifestEncryption::new(&encryption).serialize_manifest(&manifest).unwrap()).unwrap();
+        assert!(plain.contains("secret-project"));
+        
+        let handler = ManifestEncryption::new(&encryption).with_encrypted_names(true);
+        let serialized = String::from_utf8(handler.serialize_manifest(&manifest).unwrap()).unwrap();
+        for name in names {
+            assert!(!serialized.contains(name), "{} found in serialized manifest", name);
+        }
+        
+        // Decryption recovers every path, and browsing shows the real names
+        let encrypted = handler.encrypt_manifest(&manifest).unwrap();
+        let decrypted = handler.decrypt_manifest(&encrypted.encrypted_data, "names_test").unwrap();
+        assert!(decrypted.encrypted_path_map.is_none());
+        assert_eq!(decrypted.source_paths, manifest.source_paths);
+        assert_eq!(decrypted.files[1].remote_path, manifest.files[1].remote_path);
+        assert_eq!(decrypted.files[2].blob_origin, manifest.files[2].blob_origin);
+        assert_eq!(decrypted.deleted_paths, manifest.deleted_paths);
+        
+        // A signature over the real names checks out against the decrypted JSON
+        let key = crate::manifest_signing::SecureSigningKey::generate("names".to_string(), None).unwrap();
+        let mut signed = manifest.clone();
+        crate::manifest_signing::sign_manifest(&mut signed, &key, 1).unwrap();
+        let encrypted_signed = handler.encrypt_manifest(&signed).unwrap();
+        let json = handler.decrypt_manifest_json(&encrypted_signed.encrypted_data, "names_test").unwrap();
+        assert!(crate::manifest_signing::verify_manifest_json(&json, &key.public_signature_key()).unwrap());
+        
+        let browseable = BrowseableBackup::from_manifest(&decrypted);
+        let plan = browseable.find_file("/home/alice/secret-project/plan.txt").unwrap();
+        assert_eq!(plan.name, "plan.txt");
+        assert_eq!(plan.size, 100);
+        let dirs: Vec<_> = browseable.directories().iter().map(|d| d.path.clone()).collect();
+        assert_eq!(dirs, vec![
+            PathBuf::from("/home/alice/photos"),
+            PathBuf::from("/home/alice/secret-project"),
+        ]);
+        
+        // Names can't be moved between entries
+        let mut tampered: BackupManifest = serde_json::from_str(&serialized).unwrap();
+        let map = tampered.encrypted_path_map.as_mut().unwrap();
+        let f0 = map["f0"].clone();
+        map.insert("f1".to_string(), f0);
+        let tampered_json = serde_json::to_vec(&tampered).unwrap();
+        let reencrypted = encryption.encrypt_with_aad(&tampered_json, "names_test", "manifest.json").unwrap();
+        assert!(handler.decrypt_manifest(&reencrypted, "names_test").is_err());
+    }
+
     #[test]
     fn test_wrong_key_fails() {
         let encryption1 = EncryptionManager::new("password1").unwrap();
@@ -566,6 +1103,7 @@ mod tests {
         let handler2 = ManifestEncryption::new(&encryption2);
         
         let manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
             backup_id: "test".to_string(),
             timestamp: Utc::now(),
             files: vec![],
@@ -575,9 +1113,19 @@ mod tests {
             base_backup_id: None,
             encryption_version: "v2".to_string(),
             kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
             signature: None,
             backup_chain_version: 0,
             encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
         };
         
         let encrypted = handler1.encrypt_manifest(&manifest).unwrap();
@@ -587,9 +1135,85 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_chacha_backup_restores_after_switching_to_aes() {
+        use crate::direct_upload::DirectUploadBackup;
+        
+        // Backup written while the config selected ChaCha20-Poly1305
+        let backup_encryption = EncryptionManager::new("test_password")
+            .unwrap()
+            .with_algorithm(AeadAlgorithm::ChaCha20Poly1305);
+        let kdf_params = backup_encryption.kdf_params().clone();
+        
+        let entry = create_test_entry("/test/file.txt", 8, false);
+        let file_data = backup_encryption
+            .encrypt_with_aad(b"contents", "chacha_backup", "/test/file.txt")
+            .unwrap();
+        
+        let manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
+            backup_id: "chacha_backup".to_string(),
+            timestamp: Utc::now(),
+            files: vec![entry],
+            total_size: 8,
+            file_count: 1,
+            source_paths: vec![PathBuf::from("/test")],
+            base_backup_id: None,
+            encryption_version: "v2".to_string(),
+            kdf_params: Some(kdf_params.clone()),
+            aead_algorithm: backup_encryption.algorithm(),
+            hash_algorithm: Default::default(),
+            key_version: None,
+            signature: None,
+            backup_chain_version: 0,
+            encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
+        };
+        let encrypted = ManifestEncryption::new(&backup_encryption).encrypt_manifest(&manifest).unwrap();
+        let header_json = serde_json::to_string(&encrypted.header).unwrap();
+        
+        // Config later switched back to the AES-256-GCM default
+        let restore_encryption = EncryptionManager::from_password_and_params("test_password", &kdf_params).unwrap();
+        assert_eq!(restore_encryption.algorithm(), AeadAlgorithm::Aes256Gcm);
+        assert!(ManifestEncryption::new(&restore_encryption)
+            .decrypt_manifest(&encrypted.encrypted_data, "chacha_backup")
+            .is_err());
+        
+        // Restore picks the algorithm up from the public header
+        let header: ManifestHeader = serde_json::from_str(&header_json).unwrap();
+        assert_eq!(header.aead_algorithm, AeadAlgorithm::ChaCha20Poly1305);
+        let manifest_encryption = restore_encryption.for_algorithm(header.aead_algorithm);
+        let restored = ManifestEncryption::new(&manifest_encryption)
+            .decrypt_manifest(&encrypted.encrypted_data, "chacha_backup")
+            .unwrap();
+        assert_eq!(restored.aead_algorithm, AeadAlgorithm::ChaCha20Poly1305);
+        
+        let data = DirectUploadBackup::decrypt_file_data(
+            &restore_encryption,
+            &restored,
+            &restored.files[0],
+            &file_data,
+        ).unwrap();
+        assert_eq!(data, b"contents");
+    }
+
+    #[test]
+    fn test_legacy_header_defaults_to_aes() {
+        let json = r#"{"backup_id":"old","timestamp":"2024-01-01T00:00:00Z","file_count":1,"total_size":1,"encryption_version":"v2","manifest_encrypted":true,"encrypted_manifest_hash":"h","manifest_format_version":3}"#;
+        let header: ManifestHeader = serde_json::from_str(json).unwrap();
+        assert_eq!(header.aead_algorithm, AeadAlgorithm::Aes256Gcm);
+    }
+
     #[test]
     fn test_browseable_backup() {
         let manifest = BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
             backup_id: "browse_test".to_string(),
             timestamp: Utc::now(),
             files: vec![
@@ -602,9 +1226,19 @@ mod tests {
             base_backup_id: None,
             encryption_version: "v2".to_string(),
             kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
             signature: None,
             backup_chain_version: 0,
             encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
         };
         
         let browseable = BrowseableBackup::from_manifest(&manifest);
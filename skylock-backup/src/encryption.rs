@@ -1,24 +1,31 @@
-//! AES-256-GCM encryption for backup archives
+//! Authenticated encryption for backup archives
 //!
-//! This module provides authenticated encryption using AES-256-GCM with:
+//! This module provides authenticated encryption using AES-256-GCM (default)
+//! or ChaCha20-Poly1305 with:
 //! - 256-bit keys derived from user password via Argon2id (RFC 9106)
 //! - Random 96-bit nonces for each encryption operation
 //! - Authentication tags to verify data integrity
 //! - Associated authenticated data (AAD) binding for metadata
 
 use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng, Payload},
+    aead::{self, consts::U12, Aead, KeyInit, OsRng, Payload},
     Aes256Gcm, Nonce,
 };
+use chacha20poly1305::ChaCha20Poly1305;
 use argon2::{
     Algorithm, Argon2, Params, Version,
     password_hash::{SaltString, PasswordHasher},
 };
 use rand::RngCore;
 use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::str::FromStr;
 use zeroize::Zeroizing;
 use crate::error::{Result, SkylockError};
 
+/// HKDF info for per-file subkeys; a new derivation gets a new version
+const FILE_KEY_INFO: &[u8] = b"skylock-file-key-v1";
+
 /// Argon2id KDF parameters (RFC 9106 compliant)
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct KdfParams {
@@ -63,9 +70,89 @@ impl KdfParams {
     }
 }
 
+/// AEAD cipher used to encrypt backup data
+///
+/// Recorded per backup in the manifest header so restore always uses the
+/// algorithm the backup was written with, regardless of current config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum AeadAlgorithm {
+    /// AES-256-GCM (fastest with AES-NI / ARMv8 crypto extensions)
+    #[default]
+    #[serde(rename = "AES-256-GCM")]
+    Aes256Gcm,
+    /// ChaCha20-Poly1305 (faster on CPUs without AES acceleration)
+    #[serde(rename = "ChaCha20-Poly1305")]
+    ChaCha20Poly1305,
+}
+
+impl AeadAlgorithm {
+    /// Canonical name, also bound into the AAD
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AeadAlgorithm::Aes256Gcm => "AES-256-GCM",
+            AeadAlgorithm::ChaCha20Poly1305 => "ChaCha20-Poly1305",
+        }
+    }
+}
+
+impl fmt::Display for AeadAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AeadAlgorithm {
+    type Err = SkylockError;
+
+    /// Parse a config value such as "aes-256-gcm" or "chacha20-poly1305"
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "aes256gcm" | "aes" => Ok(AeadAlgorithm::Aes256Gcm),
+            "chacha20poly1305" | "chacha20" | "chacha" => Ok(AeadAlgorithm::ChaCha20Poly1305),
+            _ => Err(SkylockError::Encryption(format!(
+                "Unknown encryption algorithm '{}' (expected aes-256-gcm or chacha20-poly1305)", s
+            ))),
+        }
+    }
+}
+
+/// Cipher instance for the selected algorithm
+enum AeadCipher {
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(ChaCha20Poly1305),
+}
+
+impl AeadCipher {
+    fn new(algorithm: AeadAlgorithm, key: &[u8; 32]) -> Self {
+        match algorithm {
+            AeadAlgorithm::Aes256Gcm => AeadCipher::Aes256Gcm(Box::new(Aes256Gcm::new(key.into()))),
+            AeadAlgorithm::ChaCha20Poly1305 => AeadCipher::ChaCha20Poly1305(ChaCha20Poly1305::new(key.into())),
+        }
+    }
+
+    fn encrypt<'m, 'a>(&self, nonce: &Nonce<U12>, payload: impl Into<Payload<'m, 'a>>) -> aead::Result<Vec<u8>> {
+        match self {
+            AeadCipher::Aes256Gcm(cipher) => cipher.encrypt(nonce, payload),
+            AeadCipher::ChaCha20Poly1305(cipher) => cipher.encrypt(nonce, payload),
+        }
+    }
+
+    fn decrypt<'m, 'a>(&self, nonce: &Nonce<U12>, payload: impl Into<Payload<'m, 'a>>) -> aead::Result<Vec<u8>> {
+        match self {
+            AeadCipher::Aes256Gcm(cipher) => cipher.decrypt(nonce, payload),
+            AeadCipher::ChaCha20Poly1305(cipher) => cipher.decrypt(nonce, payload),
+        }
+    }
+}
+
 pub struct EncryptionManager {
-    cipher: Aes256Gcm,
+    cipher: AeadCipher,
+    algorithm: AeadAlgorithm,
+    /// Derived key, kept so the same key can drive another algorithm on restore
+    key: Zeroizing<[u8; 32]>,
     kdf_params: KdfParams,
+    /// Keyfile data key version (None for password-derived keys)
+    key_version: Option<u64>,
 }
 
 impl EncryptionManager {
@@ -126,24 +213,93 @@ impl EncryptionManager {
             .hash_password_into(password.as_bytes(), salt.as_str().as_bytes(), &mut *key_bytes)
             .map_err(|e| SkylockError::Encryption(format!("Key derivation failed: {}", e)))?;
         
-        // Create cipher
-        let cipher = Aes256Gcm::new_from_slice(&*key_bytes)
-            .map_err(|e| SkylockError::Encryption(format!("Failed to create cipher: {}", e)))?;
+        // Create cipher (AES-256-GCM unless overridden with `with_algorithm`)
+        let algorithm = AeadAlgorithm::default();
+        let cipher = AeadCipher::new(algorithm, &key_bytes);
         
         // Key bytes automatically zeroized when dropped
         
         Ok(Self { 
             cipher,
+            algorithm,
+            key: key_bytes,
             kdf_params: params.clone(),
+            key_version: None,
         })
     }
     
+    /// Create an encryption manager from a data key unwrapped from a keyfile
+    ///
+    /// `kdf_params` describe how the keyfile protecting the key is derived.
+    pub fn from_data_key(key: &[u8; 32], key_version: u64, kdf_params: KdfParams) -> Self {
+        let algorithm = AeadAlgorithm::default();
+        Self {
+            cipher: AeadCipher::new(algorithm, key),
+            algorithm,
+            key: Zeroizing::new(*key),
+            kdf_params,
+            key_version: Some(key_version),
+        }
+    }
+    
+    /// Use a different AEAD algorithm with the same derived key
+    pub fn with_algorithm(self, algorithm: AeadAlgorithm) -> Self {
+        self.for_algorithm(algorithm)
+    }
+    
+    /// Copy of this manager using the given algorithm, e.g. the one
+    /// recorded in a backup's manifest header
+    pub fn for_algorithm(&self, algorithm: AeadAlgorithm) -> Self {
+        Self {
+            cipher: AeadCipher::new(algorithm, &self.key),
+            algorithm,
+            key: self.key.clone(),
+            kdf_params: self.kdf_params.clone(),
+            key_version: self.key_version,
+        }
+    }
+    
+    /// Manager for a subkey derived from this key with HKDF-SHA256, using
+    /// `context` as the salt
+    ///
+    /// Backups encrypt each file under its own subkey, so a nonce-reuse bug
+    /// only exposes single files, and a file's key can be handed out or
+    /// revoked without the master key. The same key and context always give
+    /// the same subkey; algorithm and key version are kept.
+    pub fn derive_file_key(&self, context: &str) -> Self {
+        use hkdf::Hkdf;
+        use sha2::Sha256;
+        
+        let hkdf = Hkdf::<Sha256>::new(Some(context.as_bytes()), self.key.as_ref());
+        let mut key = Zeroizing::new([0u8; 32]);
+        hkdf.expand(FILE_KEY_INFO, key.as_mut())
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        
+        Self {
+            cipher: AeadCipher::new(self.algorithm, &key),
+            algorithm: self.algorithm,
+            key,
+            kdf_params: self.kdf_params.clone(),
+            key_version: self.key_version,
+        }
+    }
+    
+    /// Get the AEAD algorithm used for encryption
+    pub fn algorithm(&self) -> AeadAlgorithm {
+        self.algorithm
+    }
+    
+    /// Keyfile key version, if the key came from a keyfile
+    pub fn key_version(&self) -> Option<u64> {
+        self.key_version
+    }
+    
     /// Get the KDF parameters (needed for decryption)
     pub fn kdf_params(&self) -> &KdfParams {
         &self.kdf_params
     }
     
-    /// Encrypt data with AES-256-GCM using Associated Authenticated Data (AAD)
+    /// Encrypt data with the configured AEAD using Associated Authenticated Data (AAD)
     /// 
     /// AAD binds metadata to the ciphertext, preventing:
     /// - Ciphertext transplant between backups
@@ -163,7 +319,7 @@ impl EncryptionManager {
         let nonce = Nonce::from_slice(&nonce_bytes);
         
         // Construct AAD: backup_id|algorithm|version|file_path
-        let aad = format!("{}|AES-256-GCM|v2|{}", backup_id, file_path);
+        let aad = format!("{}|{}|v2|{}", backup_id, self.algorithm, file_path);
         
         // Create payload with AAD
         let payload = Payload {
@@ -184,7 +340,7 @@ impl EncryptionManager {
         Ok(result)
     }
     
-    /// Decrypt data encrypted with AES-256-GCM and AAD
+    /// Decrypt data encrypted with the configured AEAD and AAD
     /// 
     /// Expects: [12-byte nonce][encrypted data with auth tag]
     /// The same AAD used during encryption must be provided
@@ -205,7 +361,7 @@ impl EncryptionManager {
         let nonce = Nonce::from_slice(nonce_bytes);
         
         // Reconstruct AAD (must match encryption)
-        let aad = format!("{}|AES-256-GCM|v2|{}", backup_id, file_path);
+        let aad = format!("{}|{}|v2|{}", backup_id, self.algorithm, file_path);
         
         // Create payload with AAD
         let payload = Payload {
@@ -261,6 +417,32 @@ impl EncryptionManager {
         
         Ok(plaintext)
     }
+    
+    /// Short identifier of the key, stored in plaintext manifest headers so
+    /// a wrong key is detected before anything is decrypted
+    ///
+    /// Derived with [`Self::keyed_digest`], so it reveals nothing about the
+    /// key and is the same for every AEAD algorithm.
+    pub fn key_fingerprint(&self) -> String {
+        self.keyed_digest("key-fingerprint", &[])[..16].to_string()
+    }
+    
+    /// HMAC-SHA256 of `data` under the encryption key, as hex
+    /// 
+    /// Stable for the same key, context and data, but reveals nothing about
+    /// `data` without the key. Used for storage names that must not leak
+    /// file names.
+    pub fn keyed_digest(&self, context: &str, data: &[u8]) -> String {
+        use hmac::{Hmac, Mac};
+        use sha2::Sha256;
+        
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.key.as_ref())
+            .expect("HMAC accepts keys of any length");
+        mac.update(context.as_bytes());
+        mac.update(b"|");
+        mac.update(data);
+        hex::encode(mac.finalize().into_bytes())
+    }
 }
 
 #[cfg(test)]
@@ -306,4 +488,74 @@ mod tests {
         // Wrong password should fail to decrypt
         assert!(manager2.decrypt(&encrypted).is_err());
     }
+    
+    #[test]
+    fn test_round_trip_both_algorithms() {
+        let base = EncryptionManager::new("test_password_123").unwrap();
+        let plaintext = b"Hello, this is a secret message!";
+        
+        for algorithm in [AeadAlgorithm::Aes256Gcm, AeadAlgorithm::ChaCha20Poly1305] {
+            let manager = base.for_algorithm(algorithm);
+            assert_eq!(manager.algorithm(), algorithm);
+            
+            let encrypted = manager.encrypt_with_aad(plaintext, "backup_1", "/data/file.txt").unwrap();
+            assert_eq!(
+                manager.decrypt_with_aad(&encrypted, "backup_1", "/data/file.txt").unwrap(),
+                plaintext
+            );
+            
+            let legacy = manager.encrypt(plaintext).unwrap();
+            assert_eq!(manager.decrypt(&legacy).unwrap(), plaintext);
+        }
+    }
+    
+    #[test]
+    fn test_algorithms_are_not_interchangeable() {
+        let aes = EncryptionManager::new("test_password_123").unwrap();
+        assert_eq!(aes.algorithm(), AeadAlgorithm::Aes256Gcm);
+        let chacha = aes.for_algorithm(AeadAlgorithm::ChaCha20Poly1305);
+        
+        let encrypted = chacha.encrypt_with_aad(b"secret", "backup_1", "/data/file.txt").unwrap();
+        assert!(aes.decrypt_with_aad(&encrypted, "backup_1", "/data/file.txt").is_err());
+        
+        // Switching back to the recorded algorithm decrypts with the same key
+        let restored = aes.for_algorithm(AeadAlgorithm::ChaCha20Poly1305);
+        assert_eq!(restored.decrypt_with_aad(&encrypted, "backup_1", "/data/file.txt").unwrap(), b"secret");
+    }
+    
+    #[test]
+    fn test_derived_file_keys() {
+        let master = EncryptionManager::new("test_password_123").unwrap();
+        let first = master.derive_file_key("backup_1|file-a");
+        let encrypted = first.encrypt_with_aad(b"secret", "backup_1", "/data/a.txt").unwrap();
+        
+        // Re-deriving with the same context decrypts; the master key and
+        // other contexts don't
+        let again = master.derive_file_key("backup_1|file-a");
+        assert_eq!(again.decrypt_with_aad(&encrypted, "backup_1", "/data/a.txt").unwrap(), b"secret");
+        assert!(master.decrypt_with_aad(&encrypted, "backup_1", "/data/a.txt").is_err());
+        for context in ["backup_1|file-b", "backup_2|file-a"] {
+            let other = master.derive_file_key(context);
+            assert_ne!(*other.key, *first.key);
+            assert!(other.decrypt_with_aad(&encrypted, "backup_1", "/data/a.txt").is_err());
+        }
+        
+        // The subkey follows the master's algorithm and key version
+        let chacha = master.for_algorithm(AeadAlgorithm::ChaCha20Poly1305).derive_file_key("backup_1|file-a");
+        assert_eq!(chacha.algorithm(), AeadAlgorithm::ChaCha20Poly1305);
+        assert_eq!(*chacha.key, *first.key);
+        assert_eq!(chacha.key_version(), master.key_version());
+    }
+    
+    #[test]
+    fn test_parse_algorithm() {
+        assert_eq!("aes-256-gcm".parse::<AeadAlgorithm>().unwrap(), AeadAlgorithm::Aes256Gcm);
+        assert_eq!("AES-256-GCM".parse::<AeadAlgorithm>().unwrap(), AeadAlgorithm::Aes256Gcm);
+        assert_eq!("chacha20-poly1305".parse::<AeadAlgorithm>().unwrap(), AeadAlgorithm::ChaCha20Poly1305);
+        assert_eq!("ChaCha20_Poly1305".parse::<AeadAlgorithm>().unwrap(), AeadAlgorithm::ChaCha20Poly1305);
+        assert!("blowfish".parse::<AeadAlgorithm>().is_err());
+        
+        assert_eq!(serde_json::to_string(&AeadAlgorithm::ChaCha20Poly1305).unwrap(), "\"ChaCha20-Poly1305\"");
+        assert_eq!(AeadAlgorithm::default(), AeadAlgorithm::Aes256Gcm);
+    }
 }
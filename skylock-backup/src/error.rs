@@ -13,6 +13,30 @@ pub enum SkylockError {
     #[error("Compression error: {0}")]
     Compression(String),
 
+    /// Data read back doesn't match the hash recorded for it
+    #[error("Integrity check failed: {0}")]
+    Integrity(String),
+
+    /// The configured key isn't the one the backup was encrypted with
+    #[error("The encryption key doesn't match this backup: {0}")]
+    WrongKey(String),
+
+    /// A restore target lacks room for the restored files
+    #[error("Not enough disk space: {0}")]
+    InsufficientSpace(String),
+
+    /// Files the restore would write already exist at the target
+    #[error("{} files already exist at the restore target", .0.len())]
+    RestoreConflict(Vec<std::path::PathBuf>),
+
+    /// Another backup holds the lock on the same destination
+    #[error("Backup locked: {0}")]
+    Locked(String),
+
+    /// A backup or restore spent its retry budget and gave up
+    #[error("Retry budget exhausted: {0}")]
+    RetryBudgetExhausted(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -31,3 +55,16 @@ pub enum SkylockError {
 }
 
 pub type Result<T> = std::result::Result<T, SkylockError>;
+
+impl SkylockError {
+    /// Whether the storage box reported that a remote file doesn't exist,
+    /// as opposed to failing to answer
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            SkylockError::Core(skylock_core::SkylockError::Storage(
+                skylock_core::error_types::StorageErrorType::FileNotFound
+            ))
+        )
+    }
+}
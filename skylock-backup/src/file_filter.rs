@@ -0,0 +1,118 @@
+//! Selecting which files under the backup paths are backed up
+//!
+//! `--exclude` drops matching files and directories, and `--include-only`
+//! keeps only files matching one of its globs. Both take the globs of
+//! [`PathGlob`]: `*.docx` matches file names anywhere, a pattern with a `/`
+//! the whole path. An excluded directory is not descended into, and an
+//! exclude wins over an include matching the same file.
+
+use std::path::{Path, PathBuf};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::error::{Result, SkylockError};
+use crate::find::PathGlob;
+
+/// Include and exclude globs applied to the file walk
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    include_only: Vec<PathGlob>,
+    exclude: Vec<PathGlob>,
+}
+
+impl FileFilter {
+    /// Filter keeping files matching any of `include_only` (all files when
+    /// empty), minus those matching any of `exclude`
+    pub fn new(include_only: &[String], exclude: &[String]) -> Self {
+        Self {
+            include_only: include_only.iter().map(|p| PathGlob::new(p)).collect(),
+            exclude: exclude.iter().map(|p| PathGlob::new(p)).collect(),
+        }
+    }
+
+    /// Whether the filter keeps every file
+    pub fn is_empty(&self) -> bool {
+        self.include_only.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether `path`, a file or directory, matches an exclude
+    pub fn excludes(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|glob| glob.matches(path))
+    }
+
+    /// Whether the file at `path` is backed up
+    pub fn includes_file(&self, path: &Path) -> bool {
+        !self.excludes(path)
+            && (self.include_only.is_empty() || self.include_only.iter().any(|glob| glob.matches(path)))
+    }
+
+    /// Walk `root`, skipping excluded directories
+    ///
+    /// Directories are yielded whether or not any file below them is
+    /// included; use [`Self::includes_file`] on the files.
+    pub fn walk<'a>(&'a self, root: &Path) -> impl Iterator<Item = Result<DirEntry>> + 'a {
+        WalkDir::new(root)
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(move |entry| entry.depth() == 0 || !self.excludes(entry.path()))
+            .map(|entry| entry.map_err(|e| SkylockError::Backup(format!("Walk error: {}", e))))
+    }
+
+    /// The backed-up files under `root`, or `root` itself if it is a file
+    pub fn files(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        if root.is_file() {
+            return Ok(if self.includes_file(root) { vec![root.to_path_buf()] } else { Vec::new() });
+        }
+        let mut files = Vec::new();
+        for entry in self.walk(root) {
+            let entry = entry?;
+            if entry.file_type().is_file() && self.includes_file(entry.path()) {
+                files.push(entry.into_path());
+            }
+        }
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_include_only_with_exclude() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("reports/drafts")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        for file in [
+            "budget.xlsx",
+            "notes.txt",
+            "reports/q1.docx",
+            "reports/photo.jpg",
+            "reports/drafts/q2.docx",
+            "node_modules/pkg/readme.docx",
+        ] {
+            std::fs::write(root.join(file), b"x").unwrap();
+        }
+
+        let relative = |filter: &FileFilter| {
+            let mut files: Vec<String> = filter.files(root).unwrap().iter()
+                .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().to_string())
+                .collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(relative(&FileFilter::default()).len(), 6);
+
+        let whitelist = ["*.docx".to_string(), "*.xlsx".to_string()];
+        assert_eq!(
+            relative(&FileFilter::new(&whitelist, &[])),
+            vec!["budget.xlsx", "node_modules/pkg/readme.docx", "reports/drafts/q2.docx", "reports/q1.docx"],
+        );
+
+        // Excluded directories are pruned and excludes win over includes
+        let filter = FileFilter::new(&whitelist, &["node_modules".to_string(), "q2.*".to_string()]);
+        assert_eq!(relative(&filter), vec!["budget.xlsx", "reports/q1.docx"]);
+    }
+}
@@ -0,0 +1,413 @@
+//! Streamed blobs for large files in direct-upload backups
+//!
+//! Files of at least `backup.stream_threshold` bytes are never held in
+//! memory whole: they are hashed, compressed with zstd and encrypted as they
+//! are read, using the segment framing of streamed archives (see
+//! [`crate::archive`]), and the segments are uploaded as they are produced.
+//! Restore reverses this a segment at a time and checks the size and hash
+//! once the stream ends, so memory use stays at a few segments either way.
+//!
+//! Segments are encrypted under the file's own subkey, which binds them to
+//! the backup and path next to the segment index and last flag.
+//!
+//! The segment size (`backup.aead_frame_size`, 16 MiB by default) is
+//! recorded in the blob header and the file entry, so restores use the size
+//! the file was written with whatever the current setting. It trades memory
+//! for overhead: reading or writing a stream holds a few segments, and each
+//! segment adds a 32-byte frame and one AEAD message under the file's key.
+//! A file needing more than [`MAX_SEGMENTS_PER_KEY`] segments is refused,
+//! as random nonces are only safe for that many messages per key.
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{
+    self, SegmentReader, SegmentWriter, FRAME_OVERHEAD, HEADER_SIZE, MAX_SEGMENTS_PER_KEY, MAX_SEGMENT_SIZE,
+    MIN_SEGMENT_SIZE,
+};
+use crate::content_hash::{ContentHasher, HashAlgorithm};
+use crate::encryption::EncryptionManager;
+
+/// zstd level for streamed files when none is forced
+pub const DEFAULT_STREAM_LEVEL: i32 = 3;
+
+/// Bytes looked at to decide whether a streamed file is worth compressing
+pub const COMPRESSION_SAMPLE: u64 = 1024 * 1024;
+
+/// Layout of a streamed blob, recorded in its file entry so restore knows
+/// to read it back segment by segment
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentFraming {
+    /// Plaintext bytes per encrypted segment
+    pub segment_size: u32,
+}
+
+impl SegmentFraming {
+    /// Framing with `segment_size` byte segments; fails with
+    /// `InvalidInput` outside [`MIN_SEGMENT_SIZE`]..=[`MAX_SEGMENT_SIZE`]
+    pub fn new(segment_size: u64) -> io::Result<Self> {
+        if !(MIN_SEGMENT_SIZE as u64..=MAX_SEGMENT_SIZE as u64).contains(&segment_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "AEAD frame size {} is outside {}..={} bytes",
+                    segment_size, MIN_SEGMENT_SIZE, MAX_SEGMENT_SIZE
+                ),
+            ));
+        }
+        Ok(Self { segment_size: segment_size as u32 })
+    }
+
+    /// Segments a stream of `size` bytes takes; a full last segment is not
+    /// followed by an empty one, but an empty stream still takes one
+    pub fn segments_for(&self, size: u64) -> u64 {
+        size.saturating_sub(1) / self.segment_size as u64 + 1
+    }
+
+    /// Plaintext bytes segment `index` of a `size` byte stream holds: every
+    /// segment but the last is full
+    pub fn segment_len(&self, size: u64, index: u64) -> u64 {
+        let segment_size = self.segment_size as u64;
+        if index + 1 < self.segments_for(size) {
+            segment_size
+        } else {
+            size - index * segment_size
+        }
+    }
+
+    /// Blob bytes holding the frame of segment `index` of an uncompressed
+    /// `size` byte stream
+    pub fn frame_range(&self, size: u64, index: u64) -> std::ops::Range<u64> {
+        let start = HEADER_SIZE as u64 + index * (self.segment_size as u64 + FRAME_OVERHEAD as u64);
+        start..start + FRAME_OVERHEAD as u64 + self.segment_len(size, index)
+    }
+
+    /// Fail with `InvalidInput` if a file of `size` bytes would take more
+    /// segments than may be encrypted under one key
+    pub fn check_file_size(&self, size: u64) -> io::Result<()> {
+        let segments = self.segments_for(size);
+        if segments > MAX_SEGMENTS_PER_KEY {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "A {} byte file takes {} segments of {} bytes, more than the {} that are safe under one key; \
+                     raise backup.aead_frame_size",
+                    size, segments, self.segment_size, MAX_SEGMENTS_PER_KEY
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for SegmentFraming {
+    fn default() -> Self {
+        Self { segment_size: skylock_core::DEFAULT_AEAD_FRAME_SIZE as u32 }
+    }
+}
+
+/// What streaming a file produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamedFile {
+    /// Hash of the file's bytes, in the backup's algorithm
+    pub hash: String,
+    /// Bytes read from the file
+    pub size: u64,
+    /// SHA-256 hash of the blob, for checking a read-back
+    pub blob_hash: String,
+    /// Bytes in the blob
+    pub blob_size: u64,
+}
+
+/// Hashes and counts the bytes passing through a reader or writer
+struct Hashing<T> {
+    inner: T,
+    hasher: ContentHasher,
+    len: u64,
+}
+
+impl<T> Hashing<T> {
+    fn new(inner: T, algorithm: HashAlgorithm) -> Self {
+        Self { inner, hasher: algorithm.hasher(), len: 0 }
+    }
+
+    fn finish(self) -> (T, String, u64) {
+        (self.inner, self.hasher.finalize(), self.len)
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Hashing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hash in `algorithm` and length of everything `input` yields
+pub fn hash_reader<R: Read>(input: R, algorithm: HashAlgorithm) -> io::Result<(String, u64)> {
+    let mut input = Hashing::new(input, algorithm);
+    io::copy(&mut input, &mut io::sink())?;
+    let (_, hash, size) = input.finish();
+    Ok((hash, size))
+}
+
+/// Compress `input` at zstd `level` (`None` stores it as is) and encrypt
+/// it into `output` in segments of `framing`, returning the output writer
+///
+/// The file is hashed in `algorithm`; the blob hash is always SHA-256.
+pub fn encode<R: Read, W: Write>(
+    input: R,
+    output: W,
+    encryption: Arc<EncryptionManager>,
+    backup_id: &str,
+    level: Option<i32>,
+    framing: SegmentFraming,
+    algorithm: HashAlgorithm,
+) -> io::Result<(StreamedFile, W)> {
+    let mut input = Hashing::new(input, algorithm);
+    let mut segments = SegmentWriter::with_segment_size(
+        Hashing::new(output, HashAlgorithm::Sha256),
+        encryption,
+        backup_id,
+        framing.segment_size as usize,
+    )?;
+    let segments = match level {
+        Some(level) => {
+            let mut encoder = zstd::Encoder::new(segments, level)?;
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        None => {
+            io::copy(&mut input, &mut segments)?;
+            segments
+        }
+    };
+    let (output, blob_hash, blob_size) = segments.finish()?.finish();
+    let (_, hash, size) = input.finish();
+    Ok((StreamedFile { hash, size, blob_hash, blob_size }, output))
+}
+
+/// Decrypt and, when `compressed`, decompress a blob written by [`encode`]
+/// with `framing` into `output`, returning the hash in `algorithm` and the
+/// size of what was written
+///
+/// Fails with `InvalidData` on a segment that doesn't authenticate or a
+/// header whose segment size isn't the recorded one, and with
+/// `UnexpectedEof` on a truncated blob; `output` may then hold part of the
+/// file.
+pub fn decode<R: Read, W: Write>(
+    input: R,
+    output: W,
+    encryption: Arc<EncryptionManager>,
+    backup_id: &str,
+    compressed: bool,
+    framing: SegmentFraming,
+    algorithm: HashAlgorithm,
+) -> io::Result<(String, u64)> {
+    let mut segments = SegmentReader::new(input, encryption, backup_id)?;
+    if segments.segment_size() != framing.segment_size as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Blob has {} byte segments, but its file entry records {}",
+                segments.segment_size(), framing.segment_size
+            ),
+        ));
+    }
+    let mut output = Hashing::new(output, algorithm);
+    if compressed {
+        io::copy(&mut zstd::Decoder::new(segments)?, &mut output)?;
+    } else {
+        io::copy(&mut segments, &mut output)?;
+    }
+    output.flush()?;
+    let (_, hash, size) = output.finish();
+    Ok((hash, size))
+}
+
+/// Decrypt segment `index` of an uncompressed `size` byte stream from its
+/// frame, as located by [`SegmentFraming::frame_range`]
+///
+/// Fails with `InvalidData` if the frame doesn't authenticate as that
+/// segment or holds a different number of bytes than the layout says.
+pub fn decode_segment(
+    frame: &[u8],
+    encryption: &EncryptionManager,
+    backup_id: &str,
+    framing: SegmentFraming,
+    size: u64,
+    index: u64,
+) -> io::Result<Vec<u8>> {
+    let last = index + 1 == framing.segments_for(size);
+    let segment = archive::open_segment(frame, encryption, backup_id, index, last)?;
+    if segment.len() as u64 != framing.segment_len(size, index) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Segment {} holds {} bytes, expected {}", index, segment.len(), framing.segment_len(size, index)),
+        ));
+    }
+    Ok(segment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encryption() -> Arc<EncryptionManager> {
+        Arc::new(EncryptionManager::new("test_password").unwrap())
+    }
+
+    /// Poorly compressible bytes
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545F4914F6CDD1Du64;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect()
+    }
+
+    #[test]
+    fn test_round_trip_with_and_without_compression() {
+        let encryption = encryption();
+        let framing = SegmentFraming::new(1024 * 1024).unwrap();
+        let data = noise(2 * 1024 * 1024 + 77);
+        for level in [Some(DEFAULT_STREAM_LEVEL), None] {
+            let (streamed, blob) = encode(&data[..], Vec::new(), encryption.clone(), "backup_1", level, framing, HashAlgorithm::Sha256).unwrap();
+            assert_eq!(streamed.size, data.len() as u64);
+            assert_eq!(streamed.hash, crate::compression_integrity::calculate_hash(&data));
+            assert_eq!(streamed.blob_size, blob.len() as u64);
+            assert_eq!(streamed.blob_hash, crate::compression_integrity::calculate_hash(&blob));
+            assert_eq!((streamed.blob_hash.clone(), streamed.blob_size), hash_reader(&blob[..], HashAlgorithm::Sha256).unwrap());
+            assert!(crate::archive::is_streamed_archive(&blob));
+
+            let mut restored = Vec::new();
+            let (hash, size) = decode(&blob[..], &mut restored, encryption.clone(), "backup_1", level.is_some(), framing, HashAlgorithm::Sha256).unwrap();
+            assert_eq!(restored, data);
+            assert_eq!((hash, size), (streamed.hash.clone(), streamed.size));
+        }
+
+        // Empty files still get a last segment
+        let (streamed, blob) = encode(&b""[..], Vec::new(), encryption.clone(), "backup_1", None, framing, HashAlgorithm::Sha256).unwrap();
+        assert_eq!(streamed.size, 0);
+        assert_eq!(decode(&blob[..], io::sink(), encryption, "backup_1", false, framing, HashAlgorithm::Sha256).unwrap().1, 0);
+    }
+
+    #[test]
+    fn test_file_hashed_in_chosen_algorithm() {
+        let encryption = encryption();
+        let framing = SegmentFraming::new(1024 * 1024).unwrap();
+        let data = noise(1024 * 1024 + 5);
+        let (streamed, blob) = encode(&data[..], Vec::new(), encryption.clone(), "backup_1", None, framing, HashAlgorithm::Blake3).unwrap();
+        assert_eq!(streamed.hash, HashAlgorithm::Blake3.hash(&data));
+        // The blob hash only checks read-backs and stays SHA-256
+        assert_eq!(streamed.blob_hash, HashAlgorithm::Sha256.hash(&blob));
+
+        let (hash, _) = decode(&blob[..], io::sink(), encryption, "backup_1", false, framing, HashAlgorithm::Blake3).unwrap();
+        assert_eq!(hash, streamed.hash);
+    }
+
+    #[test]
+    fn test_round_trip_at_several_frame_sizes() {
+        let encryption = encryption();
+        for segment_size in [MIN_SEGMENT_SIZE, 256 * 1024, 4 * 1024 * 1024] {
+            let framing = SegmentFraming::new(segment_size as u64).unwrap();
+            // Exactly one segment, and a partial one past two
+            for len in [segment_size, 2 * segment_size + 13] {
+                let data = noise(len);
+                let (streamed, blob) = encode(&data[..], Vec::new(), encryption.clone(), "backup_1", None, framing, HashAlgorithm::Sha256).unwrap();
+                // Magic and size, then a 32-byte frame around every segment
+                let segments = framing.segments_for(len as u64);
+                assert_eq!(blob.len() as u64, 12 + len as u64 + 32 * segments);
+                assert_eq!(streamed.blob_size, blob.len() as u64);
+
+                let mut restored = Vec::new();
+                decode(&blob[..], &mut restored, encryption.clone(), "backup_1", false, framing, HashAlgorithm::Sha256).unwrap();
+                assert_eq!(restored, data, "{} byte frames, {} bytes", segment_size, len);
+
+                // Each segment also decodes on its own from its frame
+                let frame = |index| {
+                    let range = framing.frame_range(len as u64, index);
+                    &blob[range.start as usize..range.end as usize]
+                };
+                for index in 0..segments {
+                    let segment = decode_segment(frame(index), &encryption, "backup_1", framing, len as u64, index).unwrap();
+                    assert_eq!(segment, &data[index as usize * segment_size..][..segment.len()]);
+                }
+                assert_eq!(framing.frame_range(len as u64, segments - 1).end, blob.len() as u64);
+                assert!(decode_segment(frame(0), &encryption, "backup_1", framing, len as u64, 1).is_err());
+            }
+        }
+
+        // Restores must use the size the blob was written with
+        let data = noise(300 * 1024);
+        let written = SegmentFraming::new(MIN_SEGMENT_SIZE as u64).unwrap();
+        let (_, blob) = encode(&data[..], Vec::new(), encryption.clone(), "backup_1", None, written, HashAlgorithm::Sha256).unwrap();
+        let err = decode(&blob[..], io::sink(), encryption, "backup_1", false, SegmentFraming::default(), HashAlgorithm::Sha256).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_frame_size_bounds() {
+        assert_eq!(SegmentFraming::default().segment_size as u64, skylock_core::DEFAULT_AEAD_FRAME_SIZE);
+        for size in [0, MIN_SEGMENT_SIZE as u64 - 1, MAX_SEGMENT_SIZE as u64 + 1] {
+            assert_eq!(SegmentFraming::new(size).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+        let encryption = encryption();
+        let too_small = SegmentWriter::with_segment_size(Vec::new(), encryption, "backup_1", 4096);
+        assert!(too_small.is_err());
+    }
+
+    #[test]
+    fn test_frame_size_rejected_past_safe_segments_per_key() {
+        // 2^32 segments of 64 KiB hold 256 TiB; a file of exactly that
+        // size ends on a full last segment, one byte more needs another
+        let framing = SegmentFraming::new(MIN_SEGMENT_SIZE as u64).unwrap();
+        let limit = MAX_SEGMENTS_PER_KEY * MIN_SEGMENT_SIZE as u64;
+        assert_eq!(framing.segments_for(0), 1);
+        assert_eq!(framing.segments_for(MIN_SEGMENT_SIZE as u64), 1);
+        assert_eq!(framing.segments_for(MIN_SEGMENT_SIZE as u64 + 1), 2);
+        assert!(framing.check_file_size(limit).is_ok());
+        let err = framing.check_file_size(limit + 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("aead_frame_size"));
+
+        // The same file is fine with the default 16 MiB frames
+        assert!(SegmentFraming::default().check_file_size(limit).is_ok());
+        assert!(SegmentFraming::default().check_file_size(u64::MAX / 2).is_err());
+    }
+
+    #[test]
+    fn test_tampered_or_misbound_blob_is_refused() {
+        let encryption = encryption();
+        let framing = SegmentFraming::new(1024 * 1024).unwrap();
+        let data = noise(1024 * 1024 + 1);
+        let (_, blob) = encode(&data[..], Vec::new(), encryption.clone(), "backup_1", None, framing, HashAlgorithm::Sha256).unwrap();
+
+        // Another backup ID, a flipped byte and a missing last segment
+        assert!(decode(&blob[..], io::sink(), encryption.clone(), "backup_2", false, framing, HashAlgorithm::Sha256).is_err());
+        let mut tampered = blob.clone();
+        tampered[100] ^= 0xFF;
+        assert_eq!(decode(&tampered[..], io::sink(), encryption.clone(), "backup_1", false, framing, HashAlgorithm::Sha256).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let truncated = &blob[..blob.len() - 100];
+        assert!(decode(truncated, io::sink(), encryption, "backup_1", false, framing, HashAlgorithm::Sha256).is_err());
+    }
+}
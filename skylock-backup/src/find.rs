@@ -0,0 +1,433 @@
+//! Finding files across backups
+//!
+//! `skylock find <glob>` answers "which backups hold this file, and how did
+//! it change": every manifest that passes the backup filters is scanned for
+//! paths matching the glob, and each match is listed with its size, hash
+//! and modification time in each backup holding it. Manifests are scanned
+//! one at a time and dropped, so only the matches are kept in memory; the
+//! bases of incremental backups are scanned as well, since an incremental
+//! manifest lists only the files that changed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::direct_upload::BackupManifest;
+use crate::encrypted_manifest::ManifestSummary;
+
+/// A glob over backed-up paths
+///
+/// `*` and `?` match within one path component and `**` across any number
+/// of them. A pattern without a `/` is matched against file names, so
+/// `*.tex` finds TeX files anywhere; otherwise it must match the whole path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathGlob {
+    pattern: Vec<char>,
+    name_only: bool,
+}
+
+impl PathGlob {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+            name_only: !pattern.contains('/'),
+        }
+    }
+
+    /// Whether `path` matches the glob
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.name_only {
+            let Some(name) = path.file_name() else { return false };
+            let name: Vec<char> = name.to_string_lossy().chars().collect();
+            return glob_match(&self.pattern, &name);
+        }
+        let path: Vec<char> = path.to_string_lossy().chars().collect();
+        glob_match(&self.pattern, &path)
+    }
+}
+
+impl std::fmt::Display for PathGlob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.pattern.iter().collect::<String>())
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) if rest.first() == Some(&'*') => {
+            // `**/` also matches no directories at all
+            let rest = &rest[1..];
+            if let Some(after_slash) = rest.strip_prefix(&['/']) {
+                if glob_match(after_slash, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|skip| glob_match(rest, &text[skip..]))
+        }
+        Some(('*', rest)) => {
+            let within = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=within).any(|skip| glob_match(rest, &text[skip..]))
+        }
+        Some(('?', rest)) => matches!(text.first(), Some(&c) if c != '/') && glob_match(rest, &text[1..]),
+        Some((&c, rest)) => text.first() == Some(&c) && glob_match(rest, &text[1..]),
+    }
+}
+
+/// A file as stored in one backup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileVersion {
+    pub backup_id: String,
+    /// When the backup was taken
+    pub backup_time: DateTime<Utc>,
+    pub size: u64,
+    /// SHA-256 hash of the file
+    pub hash: String,
+    /// Modification time of the source file, if recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// A matching path and the backups holding it, oldest first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FoundFile {
+    pub path: PathBuf,
+    pub versions: Vec<FileVersion>,
+}
+
+impl FoundFile {
+    /// Whether version `index` differs from the one before it
+    pub fn is_change(&self, index: usize) -> bool {
+        index == 0 || self.versions[index].hash != self.versions[index - 1].hash
+    }
+}
+
+/// A backup left out of a search because its manifest couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedBackup {
+    pub backup_id: String,
+    pub error: String,
+}
+
+/// Result of a search across backups
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FindResult {
+    /// Matching files by path, each with its versions oldest first
+    pub files: Vec<FoundFile>,
+    /// Backups whose manifests couldn't be read. Their files are missing
+    /// from the result, as are the unchanged files of incremental backups
+    /// built on them.
+    pub skipped: Vec<SkippedBackup>,
+}
+
+/// Collects the versions of matching files, one manifest at a time
+///
+/// Incremental manifests only list what changed, so the finder keeps the
+/// matching files as of every backup it scanned and applies each
+/// incremental backup on top of its base. Bases must be scanned before the
+/// backups built on them (see [`scan_order`]).
+#[derive(Debug)]
+pub struct FileFinder {
+    glob: PathGlob,
+    /// Matching files as of each scanned backup
+    views: HashMap<String, BTreeMap<PathBuf, FileVersion>>,
+    found: BTreeMap<PathBuf, Vec<FileVersion>>,
+    backups_scanned: usize,
+}
+
+impl FileFinder {
+    pub fn new(glob: PathGlob) -> Self {
+        Self { glob, views: HashMap::new(), found: BTreeMap::new(), backups_scanned: 0 }
+    }
+
+    /// Apply a backup's changes to the matching files of its base, and
+    /// record the files it holds when `record` is set
+    pub fn scan(&mut self, manifest: &BackupManifest, record: bool) {
+        self.backups_scanned += 1;
+        let mut view = manifest.base_backup_id.as_ref()
+            .and_then(|base| self.views.get(base))
+            .cloned()
+            .unwrap_or_default();
+        for path in &manifest.deleted_paths {
+            view.remove(path);
+        }
+        for entry in manifest.files.iter().filter(|entry| self.glob.matches(&entry.local_path)) {
+            view.insert(entry.local_path.clone(), FileVersion {
+                backup_id: manifest.backup_id.clone(),
+                backup_time: manifest.timestamp,
+                size: entry.size,
+                hash: entry.hash.clone(),
+                modified: entry.modified,
+            });
+        }
+
+        if record {
+            for (path, version) in &view {
+                self.found.entry(path.clone()).or_default().push(FileVersion {
+                    backup_id: manifest.backup_id.clone(),
+                    backup_time: manifest.timestamp,
+                    ..version.clone()
+                });
+            }
+        }
+        self.views.insert(manifest.backup_id.clone(), view);
+    }
+
+    /// Number of backups scanned so far
+    pub fn backups_scanned(&self) -> usize {
+        self.backups_scanned
+    }
+
+    /// Matching files by path, each with its versions oldest first
+    pub fn finish(self) -> Vec<FoundFile> {
+        self.found.into_iter().map(|(path, mut versions)| {
+            versions.sort_by(|a, b| a.backup_time.cmp(&b.backup_time).then_with(|| a.backup_id.cmp(&b.backup_id)));
+            FoundFile { path, versions }
+        }).collect()
+    }
+}
+
+/// Backups to scan for the ones `include` accepts, oldest first, each with
+/// whether it was accepted
+///
+/// The bases of accepted incremental backups are scanned too, without
+/// being reported, so their unchanged files are found.
+pub fn scan_order<'a>(
+    summaries: &'a [ManifestSummary],
+    include: impl Fn(&ManifestSummary) -> bool,
+) -> Vec<(&'a ManifestSummary, bool)> {
+    let by_id: HashMap<&str, &ManifestSummary> = summaries.iter()
+        .map(|summary| (summary.backup_id.as_str(), summary))
+        .collect();
+    let mut order: HashMap<&str, bool> = HashMap::new();
+    for summary in summaries.iter().filter(|summary| include(summary)) {
+        order.insert(&summary.backup_id, true);
+        let mut base = summary.base_backup_id.as_deref();
+        while let Some(id) = base {
+            if order.contains_key(id) {
+                break;
+            }
+            let Some(base_summary) = by_id.get(id) else { break };
+            order.insert(id, false);
+            base = base_summary.base_backup_id.as_deref();
+        }
+    }
+
+    let mut order: Vec<(&ManifestSummary, bool)> = order.into_iter()
+        .map(|(id, included)| (by_id[id], included))
+        .collect();
+    order.sort_by(|(a, _), (b, _)| a.timestamp.cmp(&b.timestamp).then_with(|| a.backup_id.cmp(&b.backup_id)));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direct_upload::FileEntry;
+    use chrono::TimeZone;
+
+    fn manifest(id: &str, day: u32, tags: &[&str], files: &[(&str, u64, &str)]) -> BackupManifest {
+        let timestamp = Utc.with_ymd_and_hms(2024, 3, day, 2, 0, 0).unwrap();
+        let files: Vec<FileEntry> = files.iter().map(|(path, size, hash)| FileEntry {
+            local_path: PathBuf::from(path),
+            remote_path: format!("/skylock/backups/{}{}.enc", id, path),
+            size: *size,
+            hash: hash.to_string(),
+            compressed: false,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp,
+            modified: Some(timestamp - chrono::Duration::hours(1)),
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        }).collect();
+
+        BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
+            backup_id: id.to_string(),
+            timestamp,
+            file_count: files.len(),
+            total_size: files.iter().map(|f| f.size).sum(),
+            files,
+            source_paths: vec![PathBuf::from("/home/me")],
+            base_backup_id: None,
+            encryption_version: "v2".to_string(),
+            kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
+            signature: None,
+            backup_chain_version: 0,
+            encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            note: None,
+            skipped_files: Vec::new(),
+        }
+    }
+
+    fn backups() -> Vec<BackupManifest> {
+        vec![
+            manifest("20240304_020000", 4, &[], &[
+                ("/home/me/thesis.tex", 1200, "h3"),
+                ("/home/me/notes.txt", 10, "n1"),
+            ]),
+            manifest("20240301_020000", 1, &[], &[("/home/me/notes.txt", 10, "n1")]),
+            manifest("20240302_020000", 2, &["keep"], &[
+                ("/home/me/thesis.tex", 800, "h1"),
+                ("/home/me/draft/thesis.tex", 300, "d1"),
+            ]),
+            manifest("20240303_020000", 3, &[], &[
+                ("/home/me/thesis.tex", 800, "h1"),
+                ("/home/me/notes.txt", 12, "n2"),
+            ]),
+        ]
+    }
+
+    fn find(glob: &str, manifests: &[BackupManifest]) -> Vec<FoundFile> {
+        let mut finder = FileFinder::new(PathGlob::new(glob));
+        for manifest in manifests {
+            finder.scan(manifest, true);
+        }
+        assert_eq!(finder.backups_scanned(), manifests.len());
+        finder.finish()
+    }
+
+    fn history(file: &FoundFile) -> Vec<(&str, u64)> {
+        file.versions.iter().map(|v| (v.backup_id.as_str(), v.size)).collect()
+    }
+
+    #[test]
+    fn test_lists_backups_holding_file_with_sizes() {
+        let found = find("/home/me/thesis.tex", &backups());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, PathBuf::from("/home/me/thesis.tex"));
+        // Oldest first, whatever order the manifests came in, and only the
+        // backups holding the file
+        assert_eq!(history(&found[0]), vec![
+            ("20240302_020000", 800),
+            ("20240303_020000", 800),
+            ("20240304_020000", 1200),
+        ]);
+        assert_eq!(
+            found[0].versions[0].modified,
+            Some(Utc.with_ymd_and_hms(2024, 3, 2, 1, 0, 0).unwrap())
+        );
+        assert_eq!(
+            (0..3).map(|i| found[0].is_change(i)).collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+
+        let found = find("/home/me/notes.txt", &backups());
+        assert_eq!(history(&found[0]), vec![
+            ("20240301_020000", 10),
+            ("20240303_020000", 12),
+            ("20240304_020000", 10),
+        ]);
+
+        assert!(find("/home/me/missing.txt", &backups()).is_empty());
+    }
+
+    #[test]
+    fn test_only_filtered_backups_are_searched() {
+        // The caller scans only the backups passing --tag or --since
+        let tagged: Vec<_> = backups().into_iter().filter(|m| m.has_tag("keep")).collect();
+        let found = find("thesis.tex", &tagged);
+        assert_eq!(found.len(), 2);
+        assert_eq!(history(&found[0]), vec![("20240302_020000", 300)]);
+        assert_eq!(history(&found[1]), vec![("20240302_020000", 800)]);
+
+        let since = Utc.with_ymd_and_hms(2024, 3, 3, 0, 0, 0).unwrap();
+        let recent: Vec<_> = backups().into_iter().filter(|m| m.timestamp >= since).collect();
+        let found = find("/home/me/thesis.tex", &recent);
+        assert_eq!(history(&found[0]), vec![("20240303_020000", 800), ("20240304_020000", 1200)]);
+    }
+
+    #[test]
+    fn test_incremental_backups_hold_their_base_files() {
+        let full = manifest("20240301_020000", 1, &[], &[
+            ("/home/me/thesis.tex", 800, "h1"),
+            ("/home/me/notes.txt", 10, "n1"),
+        ]);
+        let mut edit = manifest("20240302_020000", 2, &[], &[("/home/me/thesis.tex", 900, "h2")]);
+        edit.base_backup_id = Some(full.backup_id.clone());
+        edit.deleted_paths = vec![PathBuf::from("/home/me/notes.txt")];
+        let mut untouched = manifest("20240303_020000", 3, &[], &[("/home/me/other.txt", 1, "o1")]);
+        untouched.base_backup_id = Some(edit.backup_id.clone());
+        let next_full = manifest("20240304_020000", 4, &[], &[("/home/me/thesis.tex", 1000, "h3")]);
+        let manifests = vec![next_full, untouched, full, edit];
+        let summaries: Vec<ManifestSummary> = manifests.iter().map(ManifestSummary::from_manifest).collect();
+
+        // Only backups from the 3rd on are wanted, but their chain is read
+        let since = Utc.with_ymd_and_hms(2024, 3, 3, 0, 0, 0).unwrap();
+        let order = scan_order(&summaries, |summary| summary.timestamp >= since);
+        assert_eq!(
+            order.iter().map(|(s, included)| (s.backup_id.as_str(), *included)).collect::<Vec<_>>(),
+            vec![
+                ("20240301_020000", false),
+                ("20240302_020000", false),
+                ("20240303_020000", true),
+                ("20240304_020000", true),
+            ]
+        );
+
+        let mut finder = FileFinder::new(PathGlob::new("/home/me/*"));
+        for (summary, included) in order {
+            let manifest = manifests.iter().find(|m| m.backup_id == summary.backup_id).unwrap();
+            finder.scan(manifest, included);
+        }
+        let found = finder.finish();
+        // The notes were deleted before the 3rd; the thesis is carried into
+        // the incremental backup with the version of the 2nd
+        assert_eq!(
+            found.iter().map(|f| f.path.to_str().unwrap()).collect::<Vec<_>>(),
+            vec!["/home/me/other.txt", "/home/me/thesis.tex"]
+        );
+        assert_eq!(history(&found[1]), vec![("20240303_020000", 900), ("20240304_020000", 1000)]);
+        assert_eq!(found[1].versions[0].hash, "h2");
+        assert_eq!(
+            found[1].versions[0].backup_time,
+            Utc.with_ymd_and_hms(2024, 3, 3, 2, 0, 0).unwrap()
+        );
+
+        // Unfiltered, every backup is reported once
+        assert_eq!(scan_order(&summaries, |_| true).iter().filter(|(_, included)| *included).count(), 4);
+    }
+
+    #[test]
+    fn test_globs() {
+        let matches = |glob: &str, path: &str| PathGlob::new(glob).matches(Path::new(path));
+
+        // Without a slash the file name is matched, in any directory
+        assert!(matches("thesis.tex", "/home/me/thesis.tex"));
+        assert!(matches("*.tex", "/home/me/draft/thesis.tex"));
+        assert!(matches("thesis.?ex", "/home/me/thesis.tex"));
+        assert!(!matches("*.tex", "/home/me/thesis.tex.bak"));
+
+        // With a slash the whole path is; `*` stays within one directory
+        assert!(matches("/home/me/*.tex", "/home/me/thesis.tex"));
+        assert!(!matches("/home/me/*.tex", "/home/me/draft/thesis.tex"));
+        assert!(!matches("/home/*", "/home/me/thesis.tex"));
+        assert!(matches("/home/**", "/home/me/draft/thesis.tex"));
+        assert!(matches("/home/**/thesis.tex", "/home/me/draft/thesis.tex"));
+        assert!(matches("/home/me/**/thesis.tex", "/home/me/thesis.tex"));
+        assert!(!matches("/home/?e/thesis.tex", "/home/mee/thesis.tex"));
+        assert!(!matches("home/me/thesis.tex", "/home/me/thesis.tex"));
+
+        let found = find("/home/me/**", &backups());
+        assert_eq!(
+            found.iter().map(|f| f.path.to_str().unwrap()).collect::<Vec<_>>(),
+            vec!["/home/me/draft/thesis.tex", "/home/me/notes.txt", "/home/me/thesis.tex"]
+        );
+    }
+}
@@ -10,11 +10,23 @@
 //! 2. Derive session key from ephemeral secret + long-term key
 //! 3. Use session key for all encryption in that session
 //! 4. Zeroize ephemeral secret after session ends
+//!
+//! In hybrid mode ([`KeyExchangeMode::Hybrid`]) the session key also rests
+//! on two key agreements with a recipient key pair derived from the
+//! long-term key: X25519 with the ephemeral secret, and an ML-KEM-768
+//! encapsulation whose ciphertext is stored in the session metadata. Both
+//! shared secrets go through HKDF together, so the key stays secret as long
+//! as either primitive holds, including against a future quantum computer.
+//! A key confirmation tag in the metadata makes a tampered ciphertext or
+//! ephemeral key fail reconstruction instead of yielding a wrong key.
 
-use x25519_dalek::{EphemeralSecret, PublicKey};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use ml_kem::{KemCore, MlKem768, B32};
+use ml_kem::kem::{Decapsulate, Encapsulate};
 use sha2::Sha256;
+use subtle::ConstantTimeEq;
 use hkdf::Hkdf;
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use zeroize::Zeroize;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 use std::sync::Arc;
@@ -33,8 +45,125 @@ pub struct SessionMetadata {
     pub ephemeral_public_key: String,
     /// Key derivation info for reconstruction
     pub kdf_info: String,
-    /// Session version for protocol evolution
+    /// Session version for protocol evolution: 1 for classic sessions, 2
+    /// for hybrid ones
     pub version: u32,
+    /// ML-KEM-768 ciphertext (base64), in hybrid sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pq_ciphertext: Option<String>,
+    /// Tag proving the reconstructed key is the session's (base64), in
+    /// hybrid sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_confirmation: Option<String>,
+}
+
+/// How a session key is agreed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyExchangeMode {
+    /// The ephemeral X25519 public key and the long-term key (version 1)
+    #[default]
+    Classic,
+    /// X25519 and ML-KEM-768 combined (version 2)
+    Hybrid,
+}
+
+impl std::str::FromStr for KeyExchangeMode {
+    type Err = SkylockError;
+
+    /// Parse a config value, "classic" or "hybrid"
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "classic" => Ok(KeyExchangeMode::Classic),
+            "hybrid" => Ok(KeyExchangeMode::Hybrid),
+            _ => Err(SkylockError::Encryption(format!(
+                "Unknown key exchange '{}' (expected classic or hybrid)", s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for KeyExchangeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            KeyExchangeMode::Classic => "classic",
+            KeyExchangeMode::Hybrid => "hybrid",
+        })
+    }
+}
+
+const CLASSIC_VERSION: u32 = 1;
+const HYBRID_VERSION: u32 = 2;
+
+/// Recipient key pairs derived from the long-term key, so a hybrid session
+/// can be reconstructed from its metadata and the password alone
+struct RecipientKeys {
+    x25519: StaticSecret,
+    kem_decapsulation: <MlKem768 as KemCore>::DecapsulationKey,
+    kem_encapsulation: <MlKem768 as KemCore>::EncapsulationKey,
+}
+
+impl RecipientKeys {
+    fn derive(long_term_key: &[u8; 32]) -> Result<Self> {
+        let hkdf = Hkdf::<Sha256>::new(Some(b"skylock-pfs-recipient-v2"), long_term_key);
+        let expand_failed = |e| SkylockError::Encryption(format!("Recipient key derivation failed: {}", e));
+        
+        let mut x25519 = [0u8; 32];
+        hkdf.expand(b"x25519", &mut x25519).map_err(expand_failed)?;
+        let mut seed = [0u8; 64];
+        hkdf.expand(b"ml-kem-768", &mut seed).map_err(expand_failed)?;
+        
+        // The derived bytes are the key generation seeds d and z, in the
+        // order MlKem768::generate would draw them from an RNG
+        let (d, z) = seed.split_at(32);
+        let d: &B32 = d.try_into().expect("d is 32 bytes");
+        let z: &B32 = z.try_into().expect("z is 32 bytes");
+        let (kem_decapsulation, kem_encapsulation) = MlKem768::generate_deterministic(d, z);
+        seed.zeroize();
+        
+        let recipient = Self { x25519: StaticSecret::from(x25519), kem_decapsulation, kem_encapsulation };
+        x25519.zeroize();
+        Ok(recipient)
+    }
+}
+
+/// Session key and key confirmation tag of a hybrid session
+/// 
+/// The info binds the ephemeral public key and the ciphertext, so changing
+/// either changes both outputs.
+fn derive_hybrid_key(
+    long_term_key: &[u8; 32],
+    session_id: &str,
+    ephemeral_public_key: &[u8],
+    classic_secret: &[u8],
+    pq_secret: &[u8],
+    pq_ciphertext: &[u8],
+) -> Result<([u8; 32], [u8; 32])> {
+    let mut input_material = Vec::with_capacity(96);
+    input_material.extend_from_slice(long_term_key);
+    input_material.extend_from_slice(classic_secret);
+    input_material.extend_from_slice(pq_secret);
+    
+    let salt = format!("skylock-pfs-{}", session_id);
+    let hkdf = Hkdf::<Sha256>::new(Some(salt.as_bytes()), &input_material);
+    input_material.zeroize();
+    
+    let transcript = [ephemeral_public_key, pq_ciphertext].concat();
+    let mut session_key = [0u8; 32];
+    let mut confirmation = [0u8; 32];
+    hkdf.expand_multi_info(&[b"skylock-session-key-v2-hybrid".as_slice(), &transcript], &mut session_key)
+        .and_then(|_| hkdf.expand_multi_info(&[b"skylock-key-confirmation-v2".as_slice(), &transcript], &mut confirmation))
+        .map_err(|e| SkylockError::Encryption(format!("Session key derivation failed: {}", e)))?;
+    Ok((session_key, confirmation))
+}
+
+fn decode_base64(field: &str, value: &str) -> Result<Vec<u8>> {
+    base64::Engine::decode(&base64::engine::general_purpose::STANDARD, value)
+        .map_err(|e| SkylockError::Encryption(format!("Invalid {}: {}", field, e)))
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes)
 }
 
 /// Ephemeral key exchange for Perfect Forward Secrecy
@@ -50,6 +179,11 @@ pub struct EphemeralKeyExchange {
     session_id: String,
     /// When this exchange was created
     created_at: DateTime<Utc>,
+    /// Classic or hybrid key agreement
+    mode: KeyExchangeMode,
+    /// ML-KEM ciphertext and key confirmation tag, once a hybrid session
+    /// key was derived
+    hybrid: Option<(Vec<u8>, [u8; 32])>,
 }
 
 impl EphemeralKeyExchange {
@@ -57,6 +191,11 @@ impl EphemeralKeyExchange {
     /// 
     /// Generates a fresh X25519 keypair for this session
     pub fn new() -> Self {
+        Self::with_mode(KeyExchangeMode::Classic)
+    }
+    
+    /// Create a new ephemeral key exchange in `mode`
+    pub fn with_mode(mode: KeyExchangeMode) -> Self {
         let secret = EphemeralSecret::random_from_rng(rand::rngs::OsRng);
         let public_key = PublicKey::from(&secret);
         
@@ -68,6 +207,8 @@ impl EphemeralKeyExchange {
             public_key,
             session_id,
             created_at: Utc::now(),
+            mode,
+            hybrid: None,
         }
     }
     
@@ -89,17 +230,35 @@ impl EphemeralKeyExchange {
         &self.session_id
     }
     
+    /// Get the key agreement mode
+    pub fn mode(&self) -> KeyExchangeMode {
+        self.mode
+    }
+    
     /// Get session metadata for storage
+    /// 
+    /// Hybrid metadata is only complete once the session key was derived.
     pub fn metadata(&self) -> SessionMetadata {
-        SessionMetadata {
-            session_id: self.session_id.clone(),
-            created_at: self.created_at,
-            ephemeral_public_key: base64::Engine::encode(
-                &base64::engine::general_purpose::STANDARD,
-                self.public_key.as_bytes()
-            ),
-            kdf_info: "skylock-pfs-v1".to_string(),
-            version: 1,
+        let ephemeral_public_key = encode_base64(self.public_key.as_bytes());
+        match self.mode {
+            KeyExchangeMode::Classic => SessionMetadata {
+                session_id: self.session_id.clone(),
+                created_at: self.created_at,
+                ephemeral_public_key,
+                kdf_info: "skylock-pfs-v1".to_string(),
+                version: CLASSIC_VERSION,
+                pq_ciphertext: None,
+                key_confirmation: None,
+            },
+            KeyExchangeMode::Hybrid => SessionMetadata {
+                session_id: self.session_id.clone(),
+                created_at: self.created_at,
+                ephemeral_public_key,
+                kdf_info: "skylock-pfs-hybrid-v2".to_string(),
+                version: HYBRID_VERSION,
+                pq_ciphertext: self.hybrid.as_ref().map(|(ciphertext, _)| encode_base64(ciphertext)),
+                key_confirmation: self.hybrid.as_ref().map(|(_, tag)| encode_base64(tag)),
+            },
         }
     }
     
@@ -163,6 +322,48 @@ impl EphemeralKeyExchange {
     pub fn derive_session_key_simple(&self, long_term_key: &[u8; 32]) -> Result<SessionKey> {
         self.derive_session_key(long_term_key, None)
     }
+    
+    /// Derive the session key of a hybrid exchange, consuming the ephemeral
+    /// secret
+    /// 
+    /// Agrees one secret by X25519 and one by ML-KEM-768 encapsulation with
+    /// the recipient keys derived from `long_term_key`, and records the
+    /// ciphertext and key confirmation tag for [`Self::metadata`].
+    pub fn derive_hybrid_session_key(&mut self, long_term_key: &[u8; 32]) -> Result<SessionKey> {
+        if self.mode != KeyExchangeMode::Hybrid {
+            return Err(SkylockError::Encryption(
+                "Key exchange is not in hybrid mode".to_string()
+            ));
+        }
+        let secret = self.secret.take()
+            .ok_or_else(|| SkylockError::Encryption(
+                "Ephemeral secret already consumed".to_string()
+            ))?;
+        let recipient = RecipientKeys::derive(long_term_key)?;
+        
+        let classic_secret = secret.diffie_hellman(&PublicKey::from(&recipient.x25519));
+        let (ciphertext, pq_secret) = recipient.kem_encapsulation.encapsulate(&mut rand::rngs::OsRng)
+            .map_err(|_| SkylockError::Encryption("ML-KEM encapsulation failed".to_string()))?;
+        
+        let (key, confirmation) = derive_hybrid_key(
+            long_term_key,
+            &self.session_id,
+            self.public_key.as_bytes(),
+            classic_secret.as_bytes(),
+            pq_secret.as_slice(),
+            ciphertext.as_slice(),
+        )?;
+        self.hybrid = Some((ciphertext.to_vec(), confirmation));
+        Ok(SessionKey::new(key, self.session_id.clone()))
+    }
+    
+    /// Derive the session key the way the exchange's mode requires
+    pub fn derive_session_key_for_mode(&mut self, long_term_key: &[u8; 32]) -> Result<SessionKey> {
+        match self.mode {
+            KeyExchangeMode::Classic => self.derive_session_key_simple(long_term_key),
+            KeyExchangeMode::Hybrid => self.derive_hybrid_session_key(long_term_key),
+        }
+    }
 }
 
 impl Default for EphemeralKeyExchange {
@@ -249,6 +450,8 @@ pub struct SessionManager {
     max_session_duration: std::time::Duration,
     /// Maximum encryptions per session
     max_encryptions_per_session: u64,
+    /// Key agreement for new sessions
+    mode: KeyExchangeMode,
 }
 
 /// State for an active session
@@ -270,6 +473,7 @@ impl SessionManager {
             active_session: RwLock::new(None),
             max_session_duration: std::time::Duration::from_secs(24 * 60 * 60), // 24 hours
             max_encryptions_per_session: 10_000_000, // 10 million files per session
+            mode: KeyExchangeMode::Classic,
         }
     }
     
@@ -282,13 +486,29 @@ impl SessionManager {
             active_session: RwLock::new(None),
             max_session_duration: std::time::Duration::from_secs(max_duration_hours * 60 * 60),
             max_encryptions_per_session: max_encryptions,
+            mode: KeyExchangeMode::Classic,
         }
     }
     
+    /// Agree new session keys in `mode`
+    pub fn with_mode(mut self, mode: KeyExchangeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    
+    /// Session manager agreeing keys as `backup.key_exchange` says
+    pub fn for_config(config: &skylock_core::BackupConfig) -> Result<Self> {
+        let mode = match config.key_exchange.as_deref() {
+            Some(mode) => mode.parse()?,
+            None => KeyExchangeMode::default(),
+        };
+        Ok(Self::new().with_mode(mode))
+    }
+    
     /// Start a new session
     pub fn start_session(&self, long_term_key: &[u8; 32]) -> Result<Arc<SessionState>> {
-        let exchange = EphemeralKeyExchange::new();
-        let session_key = exchange.derive_session_key_simple(long_term_key)?;
+        let mut exchange = EphemeralKeyExchange::with_mode(self.mode);
+        let session_key = exchange.derive_session_key_for_mode(long_term_key)?;
         let metadata = exchange.metadata();
         
         let expires_at = Utc::now() + chrono::Duration::from_std(self.max_session_duration)
@@ -351,17 +571,16 @@ impl Default for SessionManager {
 /// 
 /// This is used during decryption to recreate the session key
 /// Note: This requires the same long-term key that was used during encryption
+/// 
+/// Handles classic (version 1) and hybrid (version 2) sessions; a hybrid
+/// session whose ciphertext, ephemeral key or confirmation tag was altered
+/// is refused.
 pub fn reconstruct_session_key(
     metadata: &SessionMetadata,
     long_term_key: &[u8; 32],
 ) -> Result<SessionKey> {
     // Decode the ephemeral public key
-    let ephemeral_pk_bytes = base64::Engine::decode(
-        &base64::engine::general_purpose::STANDARD,
-        &metadata.ephemeral_public_key
-    ).map_err(|e| SkylockError::Encryption(
-        format!("Invalid ephemeral public key: {}", e)
-    ))?;
+    let ephemeral_pk_bytes = decode_base64("ephemeral public key", &metadata.ephemeral_public_key)?;
     
     if ephemeral_pk_bytes.len() != 32 {
         return Err(SkylockError::Encryption(
@@ -369,10 +588,24 @@ pub fn reconstruct_session_key(
         ));
     }
     
+    match metadata.version {
+        CLASSIC_VERSION => reconstruct_classic(metadata, long_term_key, &ephemeral_pk_bytes),
+        HYBRID_VERSION => reconstruct_hybrid(metadata, long_term_key, &ephemeral_pk_bytes),
+        version => Err(SkylockError::Encryption(format!(
+            "Unsupported session version {}", version
+        ))),
+    }
+}
+
+fn reconstruct_classic(
+    metadata: &SessionMetadata,
+    long_term_key: &[u8; 32],
+    ephemeral_pk_bytes: &[u8],
+) -> Result<SessionKey> {
     // Combine key material (same as during encryption)
     let mut input_material = Vec::with_capacity(64);
     input_material.extend_from_slice(long_term_key);
-    input_material.extend_from_slice(&ephemeral_pk_bytes);
+    input_material.extend_from_slice(ephemeral_pk_bytes);
     
     // HKDF extract and expand (same parameters as encryption)
     let salt = format!("skylock-pfs-{}", metadata.session_id);
@@ -388,6 +621,48 @@ pub fn reconstruct_session_key(
     Ok(SessionKey::new(session_key_bytes, metadata.session_id.clone()))
 }
 
+fn reconstruct_hybrid(
+    metadata: &SessionMetadata,
+    long_term_key: &[u8; 32],
+    ephemeral_pk_bytes: &[u8],
+) -> Result<SessionKey> {
+    let missing = |field: &str| SkylockError::Encryption(format!("Hybrid session metadata lacks the {}", field));
+    let ciphertext = decode_base64("ML-KEM ciphertext", metadata.pq_ciphertext.as_deref().ok_or_else(|| missing("ML-KEM ciphertext"))?)?;
+    let expected_tag = decode_base64("key confirmation", metadata.key_confirmation.as_deref().ok_or_else(|| missing("key confirmation"))?)?;
+    
+    let ephemeral_pk: [u8; 32] = ephemeral_pk_bytes.try_into()
+        .map_err(|_| SkylockError::Encryption("Ephemeral public key must be 32 bytes".to_string()))?;
+    let kem_ciphertext = ml_kem::Ciphertext::<MlKem768>::try_from(ciphertext.as_slice())
+        .map_err(|_| SkylockError::Encryption(format!(
+            "ML-KEM ciphertext must be {} bytes, not {}",
+            std::mem::size_of::<ml_kem::Ciphertext<MlKem768>>(),
+            ciphertext.len()
+        )))?;
+    
+    let recipient = RecipientKeys::derive(long_term_key)?;
+    let classic_secret = recipient.x25519.diffie_hellman(&PublicKey::from(ephemeral_pk));
+    // ML-KEM rejects implicitly: a bad ciphertext decapsulates to an
+    // unrelated secret, which the confirmation tag then catches
+    let pq_secret = recipient.kem_decapsulation.decapsulate(&kem_ciphertext)
+        .map_err(|_| SkylockError::Encryption("ML-KEM decapsulation failed".to_string()))?;
+    
+    let (key, confirmation) = derive_hybrid_key(
+        long_term_key,
+        &metadata.session_id,
+        &ephemeral_pk,
+        classic_secret.as_bytes(),
+        pq_secret.as_slice(),
+        &ciphertext,
+    )?;
+    if !bool::from(confirmation.as_slice().ct_eq(&expected_tag)) {
+        return Err(SkylockError::Encryption(
+            "Hybrid session key failed confirmation: the session metadata was altered or the key is wrong".to_string()
+        ));
+    }
+    
+    Ok(SessionKey::new(key, metadata.session_id.clone()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -527,4 +802,125 @@ mod tests {
         assert_eq!(parsed.ephemeral_public_key, metadata.ephemeral_public_key);
         assert_eq!(parsed.version, metadata.version);
     }
+    
+    #[test]
+    fn test_classic_and_hybrid_sessions_round_trip() {
+        let long_term_key = [0x42u8; 32];
+        
+        for mode in [KeyExchangeMode::Classic, KeyExchangeMode::Hybrid] {
+            let manager = SessionManager::new().with_mode(mode);
+            let session = manager.start_session(&long_term_key).unwrap();
+            assert_eq!(session.exchange.mode(), mode);
+            
+            // Metadata survives storage and rebuilds the same key
+            let json = serde_json::to_string(&session.metadata).unwrap();
+            let metadata: SessionMetadata = serde_json::from_str(&json).unwrap();
+            let reconstructed = reconstruct_session_key(&metadata, &long_term_key).unwrap();
+            assert_eq!(reconstructed.key(), session.session_key.read().key());
+            
+            match mode {
+                KeyExchangeMode::Classic => {
+                    assert_eq!(metadata.version, 1);
+                    assert!(!json.contains("pq_ciphertext") && !json.contains("key_confirmation"));
+                }
+                KeyExchangeMode::Hybrid => {
+                    assert_eq!(metadata.version, 2);
+                    let ciphertext = decode_base64("ciphertext", metadata.pq_ciphertext.as_ref().unwrap()).unwrap();
+                    assert_eq!(ciphertext.len(), 1088);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn test_hybrid_exchange_derives_once() {
+        let long_term_key = [0x42u8; 32];
+        
+        let mut exchange = EphemeralKeyExchange::with_mode(KeyExchangeMode::Hybrid);
+        assert!(exchange.metadata().pq_ciphertext.is_none());
+        let key = exchange.derive_hybrid_session_key(&long_term_key).unwrap();
+        assert!(exchange.derive_hybrid_session_key(&long_term_key).is_err());
+        
+        // Hybrid keys differ from the classic key of the same exchange
+        let classic = reconstruct_session_key(
+            &SessionMetadata { version: 1, ..exchange.metadata() },
+            &long_term_key,
+        ).unwrap();
+        assert_ne!(classic.key(), key.key());
+        
+        let mut classic_exchange = EphemeralKeyExchange::new();
+        assert!(classic_exchange.derive_hybrid_session_key(&long_term_key).is_err());
+    }
+    
+    #[test]
+    fn test_tampered_hybrid_session_fails_reconstruction() {
+        let long_term_key = [0x42u8; 32];
+        let mut exchange = EphemeralKeyExchange::with_mode(KeyExchangeMode::Hybrid);
+        exchange.derive_hybrid_session_key(&long_term_key).unwrap();
+        let metadata = exchange.metadata();
+        assert!(reconstruct_session_key(&metadata, &long_term_key).is_ok());
+        
+        let reencoded = |mut bytes: Vec<u8>, change: fn(&mut Vec<u8>)| {
+            change(&mut bytes);
+            encode_base64(&bytes)
+        };
+        let ciphertext = decode_base64("ciphertext", metadata.pq_ciphertext.as_ref().unwrap()).unwrap();
+        let public_key = decode_base64("public key", &metadata.ephemeral_public_key).unwrap();
+        
+        let flipped = SessionMetadata {
+            pq_ciphertext: Some(reencoded(ciphertext.clone(), |b| b[500] ^= 0x01)),
+            ..metadata.clone()
+        };
+        let err = reconstruct_session_key(&flipped, &long_term_key).err().expect("flipped ciphertext must be rejected");
+        assert!(err.to_string().contains("confirmation"), "{}", err);
+        
+        let truncated = SessionMetadata {
+            pq_ciphertext: Some(reencoded(ciphertext, |b| b.truncate(1000))),
+            ..metadata.clone()
+        };
+        assert!(reconstruct_session_key(&truncated, &long_term_key).is_err());
+        
+        let swapped_key = SessionMetadata {
+            ephemeral_public_key: reencoded(public_key, |b| b[0] ^= 0x01),
+            ..metadata.clone()
+        };
+        assert!(reconstruct_session_key(&swapped_key, &long_term_key).is_err());
+        
+        let missing = SessionMetadata { pq_ciphertext: None, ..metadata.clone() };
+        assert!(reconstruct_session_key(&missing, &long_term_key).is_err());
+        
+        // A wrong long-term key is refused rather than giving a wrong key
+        assert!(reconstruct_session_key(&metadata, &[0x43u8; 32]).is_err());
+        
+        let future = SessionMetadata { version: 3, ..metadata };
+        assert!(reconstruct_session_key(&future, &long_term_key).is_err());
+    }
+    
+    #[test]
+    fn test_recipient_keys_fixed_vector() {
+        use ml_kem::EncodedSizeUser;
+        use sha2::Digest;
+        
+        // Hybrid sessions are only readable while this derivation is stable
+        let recipient = RecipientKeys::derive(&[0x42u8; 32]).unwrap();
+        let encapsulation_key = recipient.kem_encapsulation.as_bytes();
+        assert_eq!(encapsulation_key.len(), 1184);
+        assert_eq!(hex::encode(&encapsulation_key[..16]), "44b68470860e2a473ee947a214fb01e7");
+        assert_eq!(
+            hex::encode(Sha256::digest(encapsulation_key)),
+            "8514269e7c1fc8be43797abfd6b623ef735723ddeb30582e982df8217902542d"
+        );
+        assert_eq!(
+            hex::encode(PublicKey::from(&recipient.x25519).as_bytes()),
+            "6fb3764246bccc82bb18efc7446cc38bf633adf52908d52f55a289b9e86aab3f"
+        );
+    }
+    
+    #[test]
+    fn test_key_exchange_mode_parsing() {
+        assert_eq!("Hybrid".parse::<KeyExchangeMode>().unwrap(), KeyExchangeMode::Hybrid);
+        assert_eq!("classic".parse::<KeyExchangeMode>().unwrap(), KeyExchangeMode::Classic);
+        assert!("kyber".parse::<KeyExchangeMode>().is_err());
+        assert_eq!(KeyExchangeMode::Hybrid.to_string().parse::<KeyExchangeMode>().unwrap(), KeyExchangeMode::Hybrid);
+    }
 }
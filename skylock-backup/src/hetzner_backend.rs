@@ -0,0 +1,119 @@
+//! Hetzner Storage Box as a generic storage backend
+//!
+//! Wraps a [`HetznerClient`] in the [`StorageBackend`] trait so the storage
+//! box can sit next to other backends in a [`skylock_core::storage::MultiBackend`],
+//! e.g. to repair its blobs from a mirror. Transfers go through temp files
+//! because the WebDAV client works on local paths.
+
+use async_trait::async_trait;
+use skylock_core::error_types::StorageErrorType;
+use skylock_core::storage::{DownloadOptions, StorageBackend, StorageItem, UploadOptions};
+use skylock_core::{Result, SkylockError};
+use skylock_hetzner::HetznerClient;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+/// [`StorageBackend`] over a Hetzner Storage Box
+pub struct HetznerBackend {
+    client: Arc<HetznerClient>,
+    temp_dir: PathBuf,
+}
+
+impl std::fmt::Debug for HetznerBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HetznerBackend")
+            .field("temp_dir", &self.temp_dir)
+            .finish()
+    }
+}
+
+impl HetznerBackend {
+    pub fn new(client: Arc<HetznerClient>) -> Self {
+        Self {
+            client,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+
+    /// Stage transfers in `dir` instead of the system temp directory
+    pub fn with_temp_dir(mut self, dir: PathBuf) -> Self {
+        self.temp_dir = dir;
+        self
+    }
+
+    fn temp_file(&self) -> Result<tempfile::NamedTempFile> {
+        tempfile::NamedTempFile::new_in(&self.temp_dir)
+            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))
+    }
+
+    fn item(path: &Path, size: u64) -> StorageItem {
+        StorageItem {
+            path: path.to_path_buf(),
+            size,
+            last_modified: None,
+            metadata: None,
+            etag: None,
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for HetznerBackend {
+    async fn upload(
+        &self,
+        mut source: Pin<Box<dyn AsyncRead + Send>>,
+        destination: &PathBuf,
+        _options: Option<UploadOptions>,
+    ) -> Result<StorageItem> {
+        let temp = self.temp_file()?;
+        let mut file = tokio::fs::File::create(temp.path()).await?;
+        let size = tokio::io::copy(&mut source, &mut file).await?;
+        file.flush().await?;
+
+        if let Some(parent) = destination.parent().filter(|p| !p.as_os_str().is_empty()) {
+            self.client.create_directory_all(&parent.to_string_lossy()).await?;
+        }
+        self.client.upload_file(temp.path(), destination).await?;
+        Ok(Self::item(destination, size))
+    }
+
+    async fn download(
+        &self,
+        source: &PathBuf,
+        mut destination: Pin<Box<dyn AsyncWrite + Send>>,
+        _options: Option<DownloadOptions>,
+    ) -> Result<()> {
+        let temp = self.temp_file()?;
+        self.client.download_file(source, temp.path()).await?;
+
+        let mut file = tokio::fs::File::open(temp.path()).await?;
+        tokio::io::copy(&mut file, &mut destination).await?;
+        destination.flush().await?;
+        Ok(())
+    }
+
+    async fn delete(&self, path: &PathBuf) -> Result<()> {
+        self.client.delete_file(path).await
+    }
+
+    /// WebDAV listings cover a single directory level, so `recursive` is ignored
+    async fn list(&self, prefix: Option<&PathBuf>, _recursive: bool) -> Result<Vec<StorageItem>> {
+        let prefix = prefix.map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+        let files = self.client.list_files(&prefix).await?;
+        Ok(files.iter().map(|f| Self::item(&f.path, f.size)).collect())
+    }
+
+    async fn get_metadata(&self, path: &PathBuf) -> Result<Option<StorageItem>> {
+        let info = self.client.object_info(path).await?;
+        Ok(Some(Self::item(path, info.size)))
+    }
+
+    async fn copy(&self, source: &PathBuf, destination: &PathBuf) -> Result<StorageItem> {
+        let temp = self.temp_file()?;
+        self.client.download_file(source, temp.path()).await?;
+        let metadata = self.client.upload_file(temp.path(), destination).await?;
+        Ok(Self::item(destination, metadata.size))
+    }
+}
@@ -6,14 +6,19 @@
 //! - Grace periods for re-encryption of existing data
 //! - Key versioning and tracking
 //! - Backward compatibility with old keys
+//! - Password-wrapped keyfile: random data keys are wrapped with a key
+//!   derived from the passphrase, so rotation only re-wraps keys and never
+//!   re-encrypts backup data
+//! - Recovery keys: a printable copy of the wrapped keys and KDF parameters
+//!   that recreates the keyfile on a new install given the passphrase
 //!
 //! Security benefits:
 //! - Limits exposure window if a key is compromised
 //! - Reduces amount of data encrypted under any single key
 //! - Enables cryptographic agility for future algorithm updates
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc, Duration as ChronoDuration};
 use sha2::{Sha256, Digest};
@@ -21,7 +26,24 @@ use zeroize::{Zeroize, ZeroizeOnDrop};
 use std::sync::Arc;
 use parking_lot::RwLock;
 
+use crate::encryption::{EncryptionManager, KdfParams};
 use crate::error::{Result, SkylockError};
+use crate::temp_files::CleanupGuard;
+
+/// File name of the keyfile inside the data directory
+pub const KEYFILE_NAME: &str = "keyfile.json";
+
+/// First line of a printed recovery key
+const RECOVERY_HEADER: &str = "-----BEGIN SKYLOCK RECOVERY KEY-----";
+
+/// Last line of a printed recovery key
+const RECOVERY_FOOTER: &str = "-----END SKYLOCK RECOVERY KEY-----";
+
+/// Characters per line of a printed recovery key
+const RECOVERY_LINE_WIDTH: usize = 64;
+
+/// Bytes of SHA-256 appended to a recovery key to catch transcription errors
+const RECOVERY_CHECKSUM_LEN: usize = 4;
 
 /// Key rotation policy configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -163,6 +185,26 @@ impl KeyVersion {
     }
 }
 
+/// A recorded key rotation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RotationRecord {
+    /// Version that was active before the rotation
+    pub from_version: u64,
+    /// Newly created active version
+    pub to_version: u64,
+    /// When the rotation happened
+    pub rotated_at: DateTime<Utc>,
+    /// Why the key was rotated
+    pub reason: String,
+}
+
+/// Data key encrypted with the passphrase-derived key encryption key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrappedKey {
+    /// Base64 of [nonce][ciphertext+tag]
+    pub wrapped: String,
+}
+
 /// Key chain managing multiple key versions
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyChain {
@@ -176,6 +218,15 @@ pub struct KeyChain {
     pub last_rotation: Option<DateTime<Utc>>,
     /// Rotation policy
     pub policy: KeyRotationPolicy,
+    /// History of rotations, oldest first
+    #[serde(default)]
+    pub rotations: Vec<RotationRecord>,
+    /// KDF parameters of the key encryption key (keyfile only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kek_params: Option<KdfParams>,
+    /// Wrapped data keys by version (keyfile only)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub wrapped_keys: BTreeMap<u64, WrappedKey>,
 }
 
 impl KeyChain {
@@ -204,6 +255,9 @@ impl KeyChain {
             created_at: now,
             last_rotation: None,
             policy,
+            rotations: Vec::new(),
+            kek_params: None,
+            wrapped_keys: BTreeMap::new(),
         }
     }
     
@@ -252,6 +306,19 @@ impl KeyChain {
             ));
         }
         
+        let new_version = self.add_version(new_fingerprint, new_salt, "scheduled rotation");
+        
+        // Clean up very old versions (past grace period and not active)
+        self.cleanup_expired_versions();
+        
+        Ok(new_version)
+    }
+    
+    /// Add a new active key version and record why
+    ///
+    /// Unlike `rotate`, every older version stays available for decryption
+    /// until it is explicitly retired after its backups are migrated.
+    pub fn add_version(&mut self, new_fingerprint: &str, new_salt: &str, reason: &str) -> u64 {
         let now = Utc::now();
         let new_version = self.versions.iter().map(|v| v.version).max().unwrap_or(0) + 1;
         
@@ -279,13 +346,16 @@ impl KeyChain {
         };
         
         self.versions.insert(0, new_key_version);
+        self.rotations.push(RotationRecord {
+            from_version: self.active_version,
+            to_version: new_version,
+            rotated_at: now,
+            reason: reason.to_string(),
+        });
         self.active_version = new_version;
         self.last_rotation = Some(now);
         
-        // Clean up very old versions (past grace period and not active)
-        self.cleanup_expired_versions();
-        
-        Ok(new_version)
+        new_version
     }
     
     /// Remove versions that are past their grace period
@@ -382,12 +452,197 @@ impl KeyRotationManager {
         let data = serde_json::to_string_pretty(&*chain)
             .map_err(|e| SkylockError::Backup(format!("Failed to serialize key chain: {}", e)))?;
         
-        std::fs::write(&self.state_path, data)
+        if let Some(parent) = self.state_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| SkylockError::Backup(format!("Failed to create key chain directory: {}", e)))?;
+        }
+        
+        // Write atomically so an interrupted save never loses wrapped keys
+        let staged = CleanupGuard::new(self.state_path.with_extension("json.tmp"));
+        std::fs::write(staged.path(), data)
+            .map_err(|e| SkylockError::Backup(format!("Failed to save key chain: {}", e)))?;
+        std::fs::rename(staged.path(), &self.state_path)
             .map_err(|e| SkylockError::Backup(format!("Failed to save key chain: {}", e)))?;
+        staged.keep();
+        
+        Ok(())
+    }
+    
+    /// Location of the keyfile inside a data directory
+    pub fn keyfile_path(data_dir: &Path) -> PathBuf {
+        data_dir.join(KEYFILE_NAME)
+    }
+    
+    /// Create a keyfile with a random version 1 data key wrapped by the passphrase
+    pub fn create_keyfile(path: PathBuf, passphrase: &str, policy: KeyRotationPolicy) -> Result<Self> {
+        let data_key = Self::generate_data_key();
+        let manager = Self::new(path, &data_key, policy)?;
+        manager.wrap_keys(passphrase)?;
+        Ok(manager)
+    }
+    
+    /// Open a keyfile and unwrap every data key still valid for decryption
+    ///
+    /// Fails if the passphrase is wrong or the file was tampered with.
+    pub fn open_keyfile(path: PathBuf, passphrase: &str) -> Result<Self> {
+        let manager = Self::load(path)?;
+        manager.unlock(passphrase)?;
+        Ok(manager)
+    }
+    
+    /// Printable recovery key for the keyfile at `path`, to be stored offline
+    ///
+    /// It holds the key chain with the wrapped data keys and their KDF
+    /// parameters, never a data key itself, so it is useless without the
+    /// passphrase. The passphrase must unlock the keyfile.
+    pub fn export_recovery_key(path: PathBuf, passphrase: &str) -> Result<String> {
+        let manager = Self::open_keyfile(path, passphrase)?;
+        let mut payload = serde_json::to_vec(&*manager.key_chain.read())
+            .map_err(|e| SkylockError::Backup(format!("Failed to serialize key chain: {}", e)))?;
+        let checksum = Sha256::digest(&payload);
+        payload.extend_from_slice(&checksum[..RECOVERY_CHECKSUM_LEN]);
+        
+        let body = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &payload);
+        let mut recovery_key = format!("{}\n", RECOVERY_HEADER);
+        for line in body.as_bytes().chunks(RECOVERY_LINE_WIDTH) {
+            recovery_key.push_str(&String::from_utf8_lossy(line));
+            recovery_key.push('\n');
+        }
+        recovery_key.push_str(RECOVERY_FOOTER);
+        recovery_key.push('\n');
+        Ok(recovery_key)
+    }
+    
+    /// Recreate the keyfile at `path` from a recovery key
+    ///
+    /// Nothing is written unless the passphrase unlocks every data key in
+    /// the recovery key. An existing keyfile at `path` is replaced.
+    pub fn import_recovery_key(recovery_key: &str, path: PathBuf, passphrase: &str) -> Result<Self> {
+        let manager = Self {
+            key_chain: Arc::new(RwLock::new(Self::parse_recovery_key(recovery_key)?)),
+            state_path: path,
+            key_cache: RwLock::new(HashMap::new()),
+        };
+        manager.unlock(passphrase)?;
+        manager.save()?;
+        Ok(manager)
+    }
+    
+    /// Key chain from a printed recovery key, with or without the
+    /// header and footer lines
+    fn parse_recovery_key(recovery_key: &str) -> Result<KeyChain> {
+        let body: String = recovery_key.lines()
+            .map(str::trim)
+            .filter(|line| *line != RECOVERY_HEADER && *line != RECOVERY_FOOTER)
+            .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+            .collect();
+        let payload = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &body)
+            .map_err(|e| SkylockError::Backup(format!("Invalid recovery key: {}", e)))?;
+        if payload.len() <= RECOVERY_CHECKSUM_LEN {
+            return Err(SkylockError::Backup("Invalid recovery key: too short".to_string()));
+        }
         
+        let (chain, checksum) = payload.split_at(payload.len() - RECOVERY_CHECKSUM_LEN);
+        if Sha256::digest(chain)[..RECOVERY_CHECKSUM_LEN] != *checksum {
+            return Err(SkylockError::Backup(
+                "Recovery key checksum mismatch (was it copied incompletely or mistyped?)".to_string()
+            ));
+        }
+        serde_json::from_slice(chain)
+            .map_err(|e| SkylockError::Backup(format!("Invalid recovery key: {}", e)))
+    }
+    
+    /// Unwrap every data key still valid for decryption into the cache
+    fn unlock(&self, passphrase: &str) -> Result<()> {
+        let chain = self.key_chain.read();
+        let params = chain.kek_params.as_ref().ok_or_else(|| SkylockError::Encryption(
+            "Key chain has no wrapped keys (not a keyfile)".to_string()
+        ))?;
+        let kek = EncryptionManager::from_password_and_params(passphrase, params)?;
+        
+        let mut cache = self.key_cache.write();
+        for version in chain.decryption_versions() {
+            let wrapped = chain.wrapped_keys.get(&version.version).ok_or_else(|| SkylockError::Encryption(
+                format!("Keyfile is missing wrapped key for version {}", version.version)
+            ))?;
+            cache.insert(version.version, Self::unwrap_key(&kek, version.version, wrapped)?);
+        }
         Ok(())
     }
     
+    /// Rotate to a new random data key and re-wrap the keyfile
+    ///
+    /// No backup data is re-encrypted: existing backups keep naming the key
+    /// version they were written with, which stays in the keyfile. Manual
+    /// rotation is not subject to the policy's minimum interval.
+    pub fn rotate_keyfile(&self, passphrase: &str, reason: &str) -> Result<u64> {
+        let data_key = Self::generate_data_key();
+        let fingerprint = Self::calculate_fingerprint(&data_key);
+        let salt = Self::generate_salt();
+        
+        let new_version = self.key_chain.write().add_version(&fingerprint, &salt, reason);
+        self.key_cache.write().insert(new_version, data_key);
+        
+        self.wrap_keys(passphrase)?;
+        Ok(new_version)
+    }
+    
+    /// Encryption manager for a data key version
+    pub fn encryption_manager(&self, version: u64) -> Result<EncryptionManager> {
+        let key = self.get_key(version)?;
+        let params = self.key_chain.read().kek_params.clone().unwrap_or_default();
+        Ok(EncryptionManager::from_data_key(&key, version, params))
+    }
+    
+    /// Encryption manager for the active version (used for new backups)
+    pub fn active_encryption_manager(&self) -> Result<EncryptionManager> {
+        self.encryption_manager(self.active_version())
+    }
+    
+    /// Wrap all decryptable data keys under a freshly salted key encryption
+    /// key and save the keyfile
+    fn wrap_keys(&self, passphrase: &str) -> Result<()> {
+        let kek = EncryptionManager::new(passphrase)?;
+        
+        {
+            let mut chain = self.key_chain.write();
+            let cache = self.key_cache.read();
+            let mut wrapped_keys = BTreeMap::new();
+            for version in chain.decryption_versions() {
+                let key = cache.get(&version.version).ok_or_else(|| SkylockError::Encryption(
+                    format!("Key version {} not found in cache", version.version)
+                ))?;
+                let wrapped = kek.encrypt_with_aad(key, "keyfile", &format!("key-v{}", version.version))?;
+                wrapped_keys.insert(version.version, WrappedKey {
+                    wrapped: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, wrapped),
+                });
+            }
+            chain.wrapped_keys = wrapped_keys;
+            chain.kek_params = Some(kek.kdf_params().clone());
+        }
+        
+        self.save()
+    }
+    
+    /// Decrypt a wrapped data key
+    fn unwrap_key(kek: &EncryptionManager, version: u64, wrapped: &WrappedKey) -> Result<[u8; 32]> {
+        let data = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &wrapped.wrapped)
+            .map_err(|e| SkylockError::Encryption(format!("Invalid wrapped key: {}", e)))?;
+        let key = zeroize::Zeroizing::new(kek.decrypt_with_aad(&data, "keyfile", &format!("key-v{}", version))
+            .map_err(|_| SkylockError::WrongKey(format!("it doesn't unlock key version {} of the keyfile", version)))?);
+        
+        <[u8; 32]>::try_from(key.as_slice())
+            .map_err(|_| SkylockError::Encryption(format!("Wrapped key {} has invalid length", version)))
+    }
+    
+    /// Generate a random 256-bit data key
+    fn generate_data_key() -> [u8; 32] {
+        use rand::RngCore;
+        let mut key = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut key);
+        key
+    }
+    
     /// Calculate key fingerprint (first 16 chars of SHA-256)
     fn calculate_fingerprint(key: &[u8; 32]) -> String {
         let mut hasher = Sha256::new();
@@ -463,6 +718,8 @@ impl KeyRotationManager {
             needs_rotation: chain.needs_rotation(),
             policy: chain.policy.clone(),
             last_rotation: chain.last_rotation,
+            versions: chain.versions.clone(),
+            rotations: chain.rotations.clone(),
         }
     }
     
@@ -480,6 +737,10 @@ pub struct KeyChainInfo {
     pub needs_rotation: bool,
     pub policy: KeyRotationPolicy,
     pub last_rotation: Option<DateTime<Utc>>,
+    /// All known key versions (newest first)
+    pub versions: Vec<KeyVersion>,
+    /// Rotation history, oldest first
+    pub rotations: Vec<RotationRecord>,
 }
 
 #[cfg(test)]
@@ -626,4 +887,102 @@ mod tests {
         let fp1_again = KeyRotationManager::calculate_fingerprint(&key1);
         assert_eq!(fp1, fp1_again);
     }
+    
+    #[test]
+    fn test_keyfile_rotation_rewraps_without_losing_old_keys() {
+        let dir = tempdir().unwrap();
+        let path = KeyRotationManager::keyfile_path(dir.path());
+        
+        let manager = KeyRotationManager::create_keyfile(path.clone(), "passphrase", KeyRotationPolicy::default()).unwrap();
+        let v1 = manager.active_encryption_manager().unwrap();
+        assert_eq!(v1.key_version(), Some(1));
+        let old_backup = v1.encrypt_with_aad(b"old backup data", "backup-1", "/data/file.txt").unwrap();
+        
+        let new_version = manager.rotate_keyfile("passphrase", "suspected leak").unwrap();
+        assert_eq!(new_version, 2);
+        
+        // Reopen from disk as a later process would
+        let reopened = KeyRotationManager::open_keyfile(path.clone(), "passphrase").unwrap();
+        
+        // New backups use the new version
+        let active = reopened.active_encryption_manager().unwrap();
+        assert_eq!(active.key_version(), Some(2));
+        
+        // Old backups still decrypt with version 1, and not with version 2
+        let old = reopened.encryption_manager(1).unwrap();
+        assert_eq!(
+            old.decrypt_with_aad(&old_backup, "backup-1", "/data/file.txt").unwrap(),
+            b"old backup data"
+        );
+        assert!(active.decrypt_with_aad(&old_backup, "backup-1", "/data/file.txt").is_err());
+        
+        // The info lists both versions and records the rotation
+        let info = reopened.info();
+        assert_eq!(info.active_version, 2);
+        assert_eq!(info.total_versions, 2);
+        let mut versions: Vec<u64> = info.versions.iter().map(|v| v.version).collect();
+        versions.sort();
+        assert_eq!(versions, vec![1, 2]);
+        assert_eq!(info.rotations.len(), 1);
+        assert_eq!(info.rotations[0].from_version, 1);
+        assert_eq!(info.rotations[0].to_version, 2);
+        assert_eq!(info.rotations[0].reason, "suspected leak");
+        assert!(info.last_rotation.is_some());
+    }
+    
+    #[test]
+    fn test_keyfile_wrong_passphrase() {
+        let dir = tempdir().unwrap();
+        let path = KeyRotationManager::keyfile_path(dir.path());
+        KeyRotationManager::create_keyfile(path.clone(), "passphrase", KeyRotationPolicy::default()).unwrap();
+        
+        let Err(err) = KeyRotationManager::open_keyfile(path, "wrong") else {
+            panic!("keyfile unlocked with the wrong passphrase");
+        };
+        assert!(matches!(err, SkylockError::WrongKey(_)), "unexpected error: {}", err);
+    }
+    
+    #[test]
+    fn test_recovery_key_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = KeyRotationManager::keyfile_path(dir.path());
+        let manager = KeyRotationManager::create_keyfile(path.clone(), "passphrase", KeyRotationPolicy::default()).unwrap();
+        manager.rotate_keyfile("passphrase", "yearly").unwrap();
+        let old_data = manager.encryption_manager(1).unwrap()
+            .encrypt_with_aad(b"old backup data", "backup-1", "/data/file.txt").unwrap();
+        
+        assert!(KeyRotationManager::export_recovery_key(path.clone(), "wrong").is_err());
+        let recovery_key = KeyRotationManager::export_recovery_key(path, "passphrase").unwrap();
+        assert!(recovery_key.starts_with(RECOVERY_HEADER));
+        assert!(recovery_key.lines().all(|line| line.len() <= RECOVERY_LINE_WIDTH));
+        
+        // Only wrapped keys are exported
+        let chain = KeyRotationManager::parse_recovery_key(&recovery_key).unwrap();
+        let serialized = serde_json::to_string(&chain).unwrap();
+        for version in [1, 2] {
+            let key = manager.get_key(version).unwrap();
+            assert!(!serialized.contains(&hex::encode(key)));
+            assert!(!serialized.contains(&base64::Engine::encode(&base64::engine::general_purpose::STANDARD, key)));
+        }
+        
+        // A mistyped character fails the checksum; missing header lines are fine
+        let mistyped = recovery_key.replacen('A', "B", 1);
+        assert!(KeyRotationManager::parse_recovery_key(&mistyped).is_err());
+        let body: String = recovery_key.lines().skip(1).take_while(|line| !line.starts_with("-----")).collect();
+        
+        // Importing into a fresh data directory needs the passphrase
+        let new_dir = tempdir().unwrap();
+        let new_path = KeyRotationManager::keyfile_path(new_dir.path());
+        assert!(KeyRotationManager::import_recovery_key(&body, new_path.clone(), "wrong").is_err());
+        assert!(!new_path.exists());
+        KeyRotationManager::import_recovery_key(&body, new_path.clone(), "passphrase").unwrap();
+        
+        let imported = KeyRotationManager::open_keyfile(new_path, "passphrase").unwrap();
+        assert_eq!(imported.active_version(), 2);
+        assert_eq!(imported.info().rotations.len(), 1);
+        assert_eq!(
+            imported.encryption_manager(1).unwrap().decrypt_with_aad(&old_data, "backup-1", "/data/file.txt").unwrap(),
+            b"old backup data"
+        );
+    }
 }
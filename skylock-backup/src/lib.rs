@@ -2,29 +2,58 @@ use std::path::{Path, PathBuf};
 use std::io::{Read, Write};
 pub mod error;
 pub mod vss;
+pub mod archive;
+pub mod file_stream;
 pub mod encryption;
 pub mod hmac_integrity;
 pub mod direct_upload;
+pub mod compression;
 pub mod compression_config;
 pub mod browser;
+pub mod block_cache;
 pub mod retention;
 pub mod resume_state;
+pub mod backup_lock;
 pub mod bandwidth;
 pub mod diff;
 pub mod change_tracker;
 pub mod verification;
+pub mod verify_checkpoint;
+pub mod delete_checkpoint;
+pub mod state_file;
+pub mod retry;
+#[cfg(test)]
+mod test_support;
+pub mod hetzner_backend;
 pub mod migration;
 pub mod manifest_signing;
+pub mod manifest_checksum;
+pub mod temp_files;
+pub mod locked_files;
+pub mod xattrs;
+pub mod path_map;
+pub mod restore_space;
+pub mod scrub;
+pub mod blob_naming;
+pub mod size_estimate;
+pub mod find;
+pub mod file_filter;
+pub mod listing_cache;
+pub mod run_summary;
+pub mod config_bundle;
 
 // Performance optimization modules
 pub mod parallelism;
 pub mod chunking;
+pub mod cdc;
 pub mod connection_pool;
+pub mod multipart_download;
 pub mod parallel_hash;
 
 // Security and integrity modules
 pub mod encrypted_manifest;
 pub mod compression_integrity;
+pub mod content_hash;
 
 // Phase 3: E2E Enhancements
 pub mod forward_secrecy;
@@ -37,40 +66,66 @@ pub mod sync_queue;
 pub mod sync_state;
 pub mod continuous;
 pub use error::{Result, SkylockError};
-pub use direct_upload::{DirectUploadBackup, BackupManifest, FileEntry};
-pub use retention::{RetentionPolicy, RetentionManager, GfsPolicy};
+pub use direct_upload::{DirectUploadBackup, BackupManifest, KEEP_TAG, FileEntry, BlobOrigin, ChunkEntry, GarbageCollectionStats, ReindexStats};
+pub use direct_upload::{ManifestContent, ContentEntry, DirectoryContent, RestoreAction, RestorePolicy, RestorePreviewEntry};
+pub use retention::{RetentionPolicy, RetentionManager, GfsPolicy, GfsDecision, KeepReason, ReclaimEstimate, TrashEntry, DEFAULT_TRASH_DAYS};
 pub use resume_state::ResumeState;
+pub use backup_lock::BackupLock;
 pub use bandwidth::{BandwidthLimiter, parse_bandwidth_limit};
 pub use diff::{BackupDiff, FileDiff, FileModification, FileMove, DiffSummary};
 pub use change_tracker::{ChangeTracker, FileIndex, FileChange, ChangeType};
-pub use verification::{BackupVerifier, VerificationResult, FileVerification};
-pub use encryption::{EncryptionManager, KdfParams};
-pub use compression_config::{CompressionConfig, CompressionLevel, CompressionStats};
+pub use verification::{BackupVerifier, VerificationResult, FileVerification, ProgressCallback, RepairReport, RepairFailure};
+pub use verify_checkpoint::VerifyCheckpoint;
+pub use delete_checkpoint::DeleteCheckpoint;
+pub use scrub::{Scrubber, ScrubReport, ScrubFinding, ScrubState};
+pub use temp_files::{TempFiles, SpillBuffer, CleanupGuard};
+pub use locked_files::{SkippedFile, SourceFiles, SourceReader};
+pub use hetzner_backend::HetznerBackend;
+pub use tokio_util::sync::CancellationToken;
+pub use encryption::{AeadAlgorithm, EncryptionManager, KdfParams};
+pub use compression::{CompressionAlgorithm, CompressionEngine, CompressionOverride};
+pub use blob_naming::BlobNaming;
+pub use content_hash::HashAlgorithm;
+pub use compression_config::{CompressionConfig, CompressionLevel, CompressionProfiles, CompressionStats};
 pub use browser::EncryptedBrowser;
+pub use block_cache::BlockCache;
+pub use xattrs::ExtendedAttribute;
+pub use path_map::{PathMap, PathMapping};
+pub use size_estimate::{SizeEstimator, SizeEstimate};
+pub use find::{FileFinder, FileVersion, FindResult, FoundFile, PathGlob, SkippedBackup, scan_order};
+pub use file_filter::FileFilter;
+pub use run_summary::{BackupRunSummary, RUN_SUMMARY_FILE};
+pub use config_bundle::{ConfigBundle, CONFIG_BUNDLE_PATH};
 
 // Performance optimization exports
 pub use parallelism::{ParallelismController, ParallelismConfig, ThroughputMetrics};
 pub use chunking::{ChunkingController, ChunkingConfig, ChunkStrategy, FileChunk, ChunkIterator};
+pub use cdc::ContentChunker;
 pub use connection_pool::{ConnectionPool, ConnectionPoolConfig, ConnectionFactory, PoolStats};
-pub use parallel_hash::{ParallelHasher, ParallelHashConfig, hash_file_async, hash_files_async};
+pub use multipart_download::{MultipartDownloader, MultipartDownloadConfig, HetznerConnectionFactory};
+pub use parallel_hash::{ParallelHasher, ParallelHashConfig, FileHashResult, hash_file_async, hash_files_async};
 
 // Security and integrity exports
 pub use encrypted_manifest::{
-    ManifestHeader, EncryptedManifest, ManifestEncryption,
+    ManifestHeader, ManifestSummary, EncryptedManifest, ManifestEncryption,
     FileTreeNode, BrowseableBackup, BackupSummary, build_file_tree
 };
 pub use compression_integrity::{
     CompressionVerifier, VerifiedCompression, VerifiedDecompression,
     CompressionMetadata, calculate_hash, verify_compressed_hash
 };
+pub use manifest_signing::{
+    SecureSigningKey, PublicSignatureKey, SignaturePolicy, sign_manifest, verify_manifest, verify_manifest_json
+};
 
 // Phase 3: E2E Enhancements exports
 pub use forward_secrecy::{
-    EphemeralKeyExchange, SessionKey, SessionManager, SessionMetadata,
+    EphemeralKeyExchange, KeyExchangeMode, SessionKey, SessionManager, SessionMetadata,
     reconstruct_session_key
 };
 pub use key_rotation::{
-    KeyRotationPolicy, KeyVersion, KeyChain, KeyRotationManager, KeyChainInfo
+    KeyRotationPolicy, KeyVersion, KeyChain, KeyRotationManager, KeyChainInfo,
+    RotationRecord, WrappedKey, KEYFILE_NAME,
 };
 pub use hsm_provider::{
     HsmProvider, HsmKeyId, HsmProviderType, HsmKeyAlgorithm, HsmKeyUsage,
@@ -80,11 +135,12 @@ pub use hsm_provider::{
 // Real-time sync and continuous backup exports
 pub use watcher::{
     FileWatcher, WatcherConfig, WatcherError, WatcherStats,
-    FileEvent, FileEventKind, EventBatch, DEFAULT_DEBOUNCE_MS
+    FileEvent, FileEventKind, EventBatch, PendingEvents, DEFAULT_DEBOUNCE_MS
 };
 pub use sync_queue::{
     SyncQueueProcessor, SyncQueueConfig, SyncQueueError, SyncQueueStats,
     SyncItem, SyncAction, SyncResult, ConflictResolution, ConflictResolutionType,
+    ConflictPolicy, ConflictOutcome, RemoteVersion, RemoteTarget, SyncedCopy, conflict_path,
     DEFAULT_MAX_QUEUE_SIZE, DEFAULT_CONCURRENT_UPLOADS
 };
 pub use sync_state::{
@@ -97,12 +153,20 @@ pub use continuous::{
 
 use chrono::{DateTime, Utc};
 use skylock_core::Config;
+use skylock_core::storage::StorageTier;
 use skylock_hetzner::HetznerClient;
 use tracing::{info, warn};
 use serde::{Serialize, Deserialize};
 use std::sync::Arc;
 use crate::vss::VssSnapshot;
 
+/// Attempts at uploading a backup's metadata before the backup is abandoned
+const METADATA_UPLOAD_ATTEMPTS: u32 = 3;
+
+/// Archive writes (about one encrypted segment each) queued for upload
+/// before the archive pipeline waits for the network
+const ARCHIVE_UPLOAD_QUEUE: usize = 4;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BackupMetadata {
     pub id: String,
@@ -110,17 +174,51 @@ pub struct BackupMetadata {
     pub source_paths: Vec<PathBuf>,
     pub size: u64,
     pub is_vss: bool,
+    /// Storage class the backup was tagged with
+    #[serde(default, skip_serializing_if = "StorageTier::is_standard")]
+    pub storage_tier: StorageTier,
+    /// Labels attached with `--tag`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Free-form note attached with `--note`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl BackupMetadata {
+    /// Whether the backup carries `tag`
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Listing details of a direct upload backup from its summary
+    pub fn from_summary(summary: &ManifestSummary) -> Self {
+        Self {
+            id: summary.backup_id.clone(),
+            timestamp: summary.timestamp,
+            source_paths: summary.source_paths.clone(),
+            size: summary.total_size,
+            is_vss: false,
+            storage_tier: summary.storage_tier,
+            tags: summary.tags.clone(),
+            note: summary.note.clone(),
+        }
+    }
 }
 
 pub struct BackupManager {
     config: Arc<Config>,
     hetzner: Arc<HetznerClient>,
     vss: Option<VssSnapshot>,
-    encryption: EncryptionManager,
+    encryption: Arc<EncryptionManager>,
+    storage_tier: StorageTier,
+    tags: Vec<String>,
+    note: Option<String>,
+    temp: TempFiles,
 }
 
 impl BackupManager {
-    pub fn new(config: Config, hetzner: HetznerClient) -> Self {
+    pub fn new(config: Config, hetzner: HetznerClient) -> Result<Self> {
         // Use the encryption key from config, or generate a warning if using default
         let encryption_key = &config.hetzner.encryption_key;
         if encryption_key == "your-encryption-key" {
@@ -130,12 +228,31 @@ impl BackupManager {
         let encryption = EncryptionManager::new(encryption_key)
             .expect("Failed to initialize encryption");
         
-        Self {
+        let hetzner = DirectUploadBackup::configure_client(hetzner, &config)?;
+        let temp = TempFiles::from_config(&config.backup);
+        Ok(Self {
             config: Arc::new(config),
             hetzner: Arc::new(hetzner),
             vss: None,
-            encryption,
-        }
+            encryption: Arc::new(encryption),
+            storage_tier: StorageTier::Standard,
+            tags: Vec::new(),
+            note: None,
+            temp,
+        })
+    }
+    
+    /// Tag new backups with a storage tier
+    pub fn with_storage_tier(mut self, tier: StorageTier) -> Self {
+        self.storage_tier = tier;
+        self
+    }
+
+    /// Label new backups with tags and an optional note
+    pub fn with_tags(mut self, tags: Vec<String>, note: Option<String>) -> Self {
+        self.tags = tags;
+        self.note = note;
+        self
     }
 
     pub async fn create_backup(&mut self) -> Result<BackupMetadata> {
@@ -148,38 +265,23 @@ impl BackupManager {
             return Err(SkylockError::Backup("No backup paths configured".to_string()));
         }
         
-        // Estimate total size to warn about large backups
+        // Estimate total size to warn about large backups (time-boxed so a
+        // huge tree doesn't hold up the backup)
         println!("  📁 Estimating backup size...");
-        let mut total_size = 0u64;
-        for path in &backup_paths {
-            if let Ok(metadata) = std::fs::metadata(path) {
-                if metadata.is_file() {
-                    total_size += metadata.len();
-                } else if metadata.is_dir() {
-                    // Quick estimate using du (non-blocking)
-                    if let Ok(output) = std::process::Command::new("du")
-                        .args(&["-sb", path.to_str().unwrap_or("")])
-                        .output() {
-                        if let Ok(stdout) = String::from_utf8(output.stdout) {
-                            if let Some(size_str) = stdout.split_whitespace().next() {
-                                if let Ok(size) = size_str.parse::<u64>() {
-                                    total_size += size;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        let estimate = SizeEstimator::new().estimate_async(&backup_paths).await;
+        let total_size = estimate.bytes;
         
-        let total_gb = total_size as f64 / 1024.0 / 1024.0 / 1024.0;
-        println!("  📊 Estimated total size: {:.2} GB", total_gb);
+        let total = skylock_core::ByteSize(total_size);
+        if estimate.complete {
+            println!("  📊 Estimated total size: {}", total);
+        } else {
+            println!("  📊 Estimated total size: at least {} (estimate timed out)", total);
+        }
         
         if total_size > 20 * 1024 * 1024 * 1024 { // > 20GB
-            warn!("Large backup detected: {:.2} GB - this may take a while and use significant RAM", total_gb);
-            println!("  ⚠️  WARNING: Backing up {:.2} GB of data", total_gb);
-            println!("     This may take 30+ minutes and require significant system resources.");
-            println!("     Consider backing up directories individually if system becomes unresponsive.");
+            warn!("Large backup detected: {} - this may take a while", total);
+            println!("  ⚠️  WARNING: Backing up {} of data", total);
+            println!("     This may take 30+ minutes.");
         }
 
         // Create a single encrypted archive containing all backup paths
@@ -204,16 +306,29 @@ impl BackupManager {
             source_paths: backup_paths,
             size: archive_size,
             is_vss: self.config.backup.vss_enabled,
+            storage_tier: self.storage_tier,
+            tags: self.tags.clone(),
+            note: self.note.clone(),
         };
 
-        // Store backup metadata
-        self.store_backup_metadata(&backup_id, &metadata).await?;
+        // Without its metadata the archive is neither listed nor restorable,
+        // so a backup whose metadata can't be stored is removed again
+        if let Err(e) = self.store_backup_metadata(&backup_id, &metadata).await {
+            println!("  ❌ Metadata upload failed, removing incomplete backup");
+            self.remove_archive(&backup_id).await;
+            return Err(e);
+        }
 
         info!("Encrypted backup completed successfully: {} ({} bytes)", backup_id, archive_size);
         Ok(metadata)
     }
 
-    /// Create an encrypted, compressed tar archive and upload it
+    /// Stream an encrypted, compressed tar archive of `paths` to the
+    /// storage box, returning the uploaded size
+    /// 
+    /// tar → zstd → AEAD segments run on a blocking thread that feeds the
+    /// upload through a bounded queue, so memory use stays at a few segments
+    /// whatever the size of the backup and nothing is staged on disk.
     async fn create_encrypted_archive(
         &self,
         backup_id: &str,
@@ -222,158 +337,67 @@ impl BackupManager {
     ) -> Result<u64> {
         info!("Creating tar archive for {} paths", paths.len());
         
-        // Use custom temp dir if specified, otherwise use default
-        let temp_dir = std::env::var("SKYLOCK_TEMP_DIR").ok()
-            .map(PathBuf::from)
-            .or_else(|| std::env::var("TMPDIR").ok().map(PathBuf::from));
-        
-        if let Some(ref dir) = temp_dir {
-            info!("Using custom temp directory: {}", dir.display());
-            println!("  📂 Using temp dir: {}", dir.display());
-        }
-        
-        // Create temporary file for the tar archive
-        let temp_tar = if let Some(dir) = temp_dir.as_ref() {
-            tempfile::NamedTempFile::new_in(dir)
-                .map_err(|e| SkylockError::Backup(format!("Failed to create temp tar file in {}: {}", dir.display(), e)))?
-        } else {
-            tempfile::NamedTempFile::new()
-                .map_err(|e| SkylockError::Backup(format!("Failed to create temp tar file: {}", e)))?
-        };
-        let tar_path = temp_tar.path();
-        
-        // Create tar archive
-        {
-            let tar_file = std::fs::File::create(tar_path)
-                .map_err(|e| SkylockError::Backup(format!("Failed to open tar file: {}", e)))?;
-            let mut tar_builder = tar::Builder::new(tar_file);
+        let mut sources = Vec::new();
+        for source_path in paths {
+            let path_to_backup = if use_vss {
+                self.get_shadow_path(source_path)?
+            } else {
+                source_path.clone()
+            };
             
-            for source_path in paths {
-                let path_to_backup = if use_vss {
-                    self.get_shadow_path(source_path)?
-                } else {
-                    source_path.clone()
-                };
-                
-                if !path_to_backup.exists() {
-                    warn!("Path does not exist, skipping: {}", path_to_backup.display());
-                    continue;
-                }
-                
-                println!("  📦 Archiving: {}", source_path.display());
-                
-                // Create a relative path for the archive
-                // Use the file/dir name or a sanitized version of the full path
-                let archive_name = source_path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("backup");
-                
-                // Add to tar with relative path
-                if path_to_backup.is_dir() {
-                    tar_builder.append_dir_all(archive_name, &path_to_backup)
-                        .map_err(|e| SkylockError::Backup(format!("Failed to add dir to tar: {}", e)))?;
-                } else {
-                    let mut file = std::fs::File::open(&path_to_backup)
-                        .map_err(|e| SkylockError::Backup(format!("Failed to open file: {}", e)))?;
-                    tar_builder.append_file(archive_name, &mut file)
-                        .map_err(|e| SkylockError::Backup(format!("Failed to add file to tar: {}", e)))?;
-                }
+            if !path_to_backup.exists() {
+                warn!("Path does not exist, skipping: {}", path_to_backup.display());
+                continue;
             }
             
-            tar_builder.finish()
-                .map_err(|e| SkylockError::Backup(format!("Failed to finalize tar: {}", e)))?;
-        }
-        
-        info!("Tar archive created, compressing with zstd...");
-        println!("  🗜️  Compressing archive...");
-        
-        let original_size = std::fs::metadata(tar_path)?.len();
-        
-        // Stream compression to avoid loading entire file into RAM
-        let temp_compressed = if let Some(dir) = temp_dir.as_ref() {
-            tempfile::NamedTempFile::new_in(dir)
-                .map_err(|e| SkylockError::Backup(format!("Failed to create temp compressed file: {}", e)))?
-        } else {
-            tempfile::NamedTempFile::new()
-                .map_err(|e| SkylockError::Backup(format!("Failed to create temp compressed file: {}", e)))?
-        };
-        
-        {
-            let tar_file = std::fs::File::open(tar_path)
-                .map_err(|e| SkylockError::Backup(format!("Failed to open tar for compression: {}", e)))?;
-            let compressed_file = std::fs::File::create(temp_compressed.path())
-                .map_err(|e| SkylockError::Backup(format!("Failed to create compressed file: {}", e)))?;
-            
-            let mut encoder = zstd::Encoder::new(compressed_file, 3)
-                .map_err(|e| SkylockError::Backup(format!("Failed to create zstd encoder: {}", e)))?;
-            
-            std::io::copy(&mut std::io::BufReader::new(tar_file), &mut encoder)
-                .map_err(|e| SkylockError::Backup(format!("Failed during streaming compression: {}", e)))?;
-            
-            encoder.finish()
-                .map_err(|e| SkylockError::Backup(format!("Failed to finish compression: {}", e)))?;
+            // Archive entries are named after the file or directory
+            let archive_name = source_path.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("backup")
+                .to_string();
+            sources.push((archive_name, source_path.clone(), path_to_backup));
         }
         
-        let compressed_size = std::fs::metadata(temp_compressed.path())?.len();
-        let ratio = original_size as f64 / compressed_size as f64;
-        info!("Compressed {} bytes -> {} bytes ({}x ratio)", original_size, compressed_size, ratio);
-        println!("  ✓ Compressed: {:.1}x ratio", ratio);
-        
-        // Encrypt the compressed data in chunks to avoid loading entire file into RAM
-        info!("Encrypting with AES-256-GCM...");
-        println!("  🔐 Encrypting with AES-256-GCM (streaming)...");
-        
-        let temp_encrypted = if let Some(dir) = temp_dir.as_ref() {
-            tempfile::NamedTempFile::new_in(dir)
-                .map_err(|e| SkylockError::Backup(format!("Failed to create temp encrypted file: {}", e)))?
-        } else {
-            tempfile::NamedTempFile::new()
-                .map_err(|e| SkylockError::Backup(format!("Failed to create temp encrypted file: {}", e)))?
+        let remote_path = Self::archive_path(backup_id);
+        info!("Streaming encrypted archive to: {}", remote_path.display());
+        println!("  ⬆️  Archiving, compressing, encrypting and uploading to: {}", remote_path.display());
+        
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(ARCHIVE_UPLOAD_QUEUE);
+        let encryption = self.encryption.clone();
+        let id = backup_id.to_string();
+        let producer = tokio::task::spawn_blocking(move || {
+            let error_sender = sender.clone();
+            let result = (|| -> Result<u64> {
+                let mut archive = archive::ArchiveWriter::new(archive::ChannelWriter::new(sender), encryption, &id)?;
+                for (name, source_path, path) in &sources {
+                    println!("  📦 Archiving: {}", source_path.display());
+                    archive.append_path(name, path)?;
+                }
+                Ok(archive.finish()?.written())
+            })();
+            // A failed archive must fail the upload too, rather than leave a
+            // truncated one; if the upload stopped first, it has the cause
+            let upload_stopped = error_sender.is_closed();
+            if let Err(ref e) = result {
+                let _ = error_sender.blocking_send(Err(std::io::Error::other(e.to_string())));
+            }
+            (result, upload_stopped)
+        });
+        let body = futures::stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        
+        let (uploaded, produced) = tokio::join!(self.hetzner.upload_stream(body, &remote_path), producer);
+        let (produced, upload_stopped) = produced
+            .map_err(|e| SkylockError::Backup(format!("Archive task failed: {}", e)))?;
+        let archive_size = match (produced, uploaded) {
+            (Ok(size), Ok(())) => size,
+            (Err(_), Err(e)) if upload_stopped => return Err(e.into()),
+            (Err(e), _) => return Err(e),
+            (Ok(_), Err(e)) => return Err(e.into()),
         };
         
-        // For large files, we encrypt the entire compressed file as one unit
-        // This is necessary because AES-GCM needs to authenticate the entire message
-        // We'll use a chunked read approach to avoid loading everything into RAM at once
-        const CHUNK_SIZE: usize = 64 * 1024 * 1024; // 64 MB chunks for reading
-        
-        let compressed_file = std::fs::File::open(temp_compressed.path())
-            .map_err(|e| SkylockError::Backup(format!("Failed to open compressed file: {}", e)))?;
+        println!("  ✅ Upload complete! ({} bytes)", archive_size);
         
-        // For files larger than 512MB compressed, we need a different approach
-        if compressed_size > 512 * 1024 * 1024 {
-            // Just copy the compressed file - skip encryption for very large files
-            warn!("File is very large ({}MB), skipping encryption to prevent memory issues", compressed_size / 1024 / 1024);
-            std::fs::copy(temp_compressed.path(), temp_encrypted.path())
-                .map_err(|e| SkylockError::Backup(format!("Failed to copy large file: {}", e)))?;
-            println!("  ⚠️  Warning: File too large for encryption, stored compressed only");
-        } else {
-            // Read and encrypt in one go (file is small enough)
-            let compressed_data = std::fs::read(temp_compressed.path())
-                .map_err(|e| SkylockError::Backup(format!("Failed to read compressed data: {}", e)))?;
-            
-            let encrypted_data = self.encryption.encrypt(&compressed_data)?;
-            
-            std::fs::write(temp_encrypted.path(), &encrypted_data)
-                .map_err(|e| SkylockError::Backup(format!("Failed to write encrypted data: {}", e)))?;
-            
-            println!("  ✓ Encrypted: {} bytes", encrypted_data.len());
-        }
-        
-        let encrypted_size = std::fs::metadata(temp_encrypted.path())?.len();
-        
-        // Upload to Hetzner
-        let remote_path = format!("skylock_{}.tar.zst.enc", backup_id);
-        info!("Uploading encrypted archive to: {}", remote_path);
-        println!("  ⬆️  Uploading to storage box: {}", remote_path);
-        
-        self.hetzner.upload_file(
-            temp_encrypted.path(),
-            &PathBuf::from(&remote_path)
-        ).await?;
-        
-        println!("  ✅ Upload complete!");
-        
-        Ok(encrypted_size as u64)
+        Ok(archive_size)
     }
 
     fn create_vss_snapshot(&mut self, path: &Path) -> Result<()> {
@@ -410,6 +434,12 @@ impl BackupManager {
         Ok(PathBuf::from(format!("skylock_{}_{}", backup_id, safe_name)))
     }
 
+    /// Remote path of a backup's encrypted archive
+    fn archive_path(backup_id: &str) -> PathBuf {
+        PathBuf::from(format!("skylock_{}.tar.zst.enc", backup_id))
+    }
+
+    /// Upload a backup's metadata, retrying transient failures
     async fn store_backup_metadata(&self, backup_id: &str, metadata: &BackupMetadata) -> Result<()> {
         // Flatten metadata path to avoid nested directories
         let metadata_path = PathBuf::from(format!("skylock_{}_metadata.json", backup_id));
@@ -417,26 +447,39 @@ impl BackupManager {
         let metadata_json = serde_json::to_string_pretty(metadata)
             .map_err(|e| SkylockError::Backup(format!("Failed to serialize metadata: {}", e)))?;
 
-        // Write to temp file first
-        let temp_metadata = PathBuf::from(format!("temp_metadata_{}.json", backup_id));
-        tokio::fs::write(&temp_metadata, metadata_json).await?;
-
-        // Upload temp file to Hetzner
         println!("  📋 Uploading metadata...");
-        self.hetzner
-            .upload_file(
-                &temp_metadata,
-                &metadata_path
-            )
-            .await?;
+        let mut attempt = 1;
+        loop {
+            match manifest_checksum::upload_with_checksum(&self.hetzner, &self.temp, metadata_json.as_bytes(), &metadata_path).await {
+                Ok(()) => break,
+                Err(e) if attempt < METADATA_UPLOAD_ATTEMPTS => {
+                    warn!("Metadata upload for {} failed (attempt {}/{}): {}", backup_id, attempt, METADATA_UPLOAD_ATTEMPTS, e);
+                    tokio::time::sleep(std::time::Duration::from_millis(500 * attempt as u64)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
         println!("  ✓ Metadata saved");
 
-        // Clean up temp file
-        tokio::fs::remove_file(&temp_metadata).await?;
-
         Ok(())
     }
 
+    /// Delete an uploaded archive whose backup could not be completed
+    ///
+    /// Failing to delete it is only reported; the archive stays unlisted
+    /// because it has no metadata.
+    async fn remove_archive(&self, backup_id: &str) {
+        let remote_path = Self::archive_path(backup_id);
+        match self.hetzner.delete_file(&remote_path).await {
+            Ok(()) => info!("Removed archive of incomplete backup {}", backup_id),
+            Err(e) => {
+                warn!("Failed to remove archive of incomplete backup {}: {}", backup_id, e);
+                println!("  ⚠️  Could not remove {}: {}", remote_path.display(), e);
+            }
+        }
+    }
+
     pub async fn list_backups(&self) -> Result<Vec<BackupMetadata>> {
         let mut backups = Vec::new();
 
@@ -507,7 +550,14 @@ impl BackupManager {
             let manifest_path = PathBuf::from(format!("/skylock/backups/{}/manifest.json", dir_name));
             
             // Try to download and parse manifest
-            if let Ok(Some(manifest)) = self.load_direct_manifest(&manifest_path).await {
+            let manifest = match self.load_direct_manifest(&manifest_path).await {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    warn!("Failed to load manifest of {}: {}", dir_name, e);
+                    None
+                }
+            };
+            if let Some(manifest) = manifest {
                 // Convert manifest to BackupMetadata
                 let metadata = BackupMetadata {
                     id: format!("backup_{}", manifest.backup_id),
@@ -515,6 +565,9 @@ impl BackupManager {
                     size: manifest.total_size,
                     source_paths: manifest.source_paths,
                     is_vss: false, // Direct uploads don't use VSS currently
+                    storage_tier: manifest.storage_tier,
+                    tags: manifest.tags,
+                    note: manifest.note,
                 };
                 backups.push(metadata);
             }
@@ -527,15 +580,9 @@ impl BackupManager {
     async fn load_direct_manifest(&self, path: &Path) -> Result<Option<crate::direct_upload::BackupManifest>> {
         use crate::direct_upload::BackupManifest;
         
-        let temp_file = PathBuf::from("temp_direct_manifest.json");
-        match self.hetzner.download_file(path, &temp_file).await {
-            Ok(_) => {
-                let manifest_str = tokio::fs::read_to_string(&temp_file).await?;
-                let manifest: BackupManifest = serde_json::from_str(&manifest_str)
-                    .map_err(|e| SkylockError::Backup(format!("Failed to parse manifest: {}", e)))?;
-                tokio::fs::remove_file(&temp_file).await?;
-                Ok(Some(manifest))
-            }
+        match manifest_checksum::download_verified(&self.hetzner, &self.temp, path).await {
+            Ok(manifest_json) => Ok(Some(BackupManifest::from_json(&manifest_json)?)),
+            Err(e @ SkylockError::Integrity(_)) => Err(e),
             Err(_) => Ok(None)
         }
     }
@@ -556,41 +603,44 @@ impl BackupManager {
         println!("     - Size: {} bytes", metadata.size);
 
         // Download encrypted archive
-        let remote_path = format!("skylock_{}.tar.zst.enc", backup_id);
-        let temp_encrypted = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Failed to create temp file: {}", e)))?;
+        let temp_encrypted = self.temp.file("encrypted")?;
         
         println!("  ⬇️  Downloading encrypted archive...");
-        self.hetzner.download_file(
-            &PathBuf::from(&remote_path),
-            &temp_encrypted.path().to_path_buf()
-        ).await?;
+        self.hetzner.download_file(&Self::archive_path(backup_id), temp_encrypted.path()).await?;
         println!("  ✓ Download complete");
 
-        // Read encrypted data
-        let encrypted_data = std::fs::read(temp_encrypted.path())
-            .map_err(|e| SkylockError::Backup(format!("Failed to read encrypted file: {}", e)))?;
-
-        // Decrypt
-        println!("  🔓 Decrypting with AES-256-GCM...");
-        let compressed_data = self.encryption.decrypt(&encrypted_data)?;
-        println!("  ✓ Decryption successful");
-
-        // Decompress
-        println!("  📦 Decompressing archive...");
-        let tar_data = zstd::decode_all(compressed_data.as_slice())
-            .map_err(|e| SkylockError::Backup(format!("Decompression failed: {}", e)))?;
-        println!("  ✓ Decompression complete");
-
-        // Extract tar archive
-        println!("  📂 Extracting files to: {}", target_path.display());
-        let mut archive = tar::Archive::new(tar_data.as_slice());
-        
-        // Create target directory if it doesn't exist
         tokio::fs::create_dir_all(target_path).await?;
+        println!("  📂 Extracting files to: {}", target_path.display());
         
-        archive.unpack(target_path)
-            .map_err(|e| SkylockError::Backup(format!("Failed to extract archive: {}", e)))?;
+        let mut magic = [0u8; 8];
+        let header_len = std::fs::File::open(temp_encrypted.path())
+            .and_then(|mut file| file.read(&mut magic))
+            .map_err(|e| SkylockError::Backup(format!("Failed to read encrypted file: {}", e)))?;
+        if archive::is_streamed_archive(&magic[..header_len]) {
+            // Decrypted, decompressed and extracted as one stream
+            let file = std::fs::File::open(temp_encrypted.path())
+                .map_err(|e| SkylockError::Backup(format!("Failed to read encrypted file: {}", e)))?;
+            let (encryption, id, target) = (self.encryption.clone(), backup_id.to_string(), target_path.to_path_buf());
+            tokio::task::spawn_blocking(move || {
+                archive::extract_archive(std::io::BufReader::new(file), encryption, &id, &target)
+            }).await.map_err(|e| SkylockError::Backup(format!("Restore task failed: {}", e)))??;
+        } else {
+            // Archives made before streaming are one AEAD message
+            let encrypted_data = std::fs::read(temp_encrypted.path())
+                .map_err(|e| SkylockError::Backup(format!("Failed to read encrypted file: {}", e)))?;
+            
+            println!("  🔓 Decrypting with AES-256-GCM...");
+            let compressed_data = self.encryption.decrypt(&encrypted_data)?;
+            println!("  ✓ Decryption successful");
+            
+            println!("  📦 Decompressing archive...");
+            let tar_data = zstd::decode_all(compressed_data.as_slice())
+                .map_err(|e| SkylockError::Backup(format!("Decompression failed: {}", e)))?;
+            println!("  ✓ Decompression complete");
+            
+            tar::Archive::new(tar_data.as_slice()).unpack(target_path)
+                .map_err(|e| SkylockError::Backup(format!("Failed to extract archive: {}", e)))?;
+        }
         
         println!("  ✅ Restore completed successfully!");
         println!("  📁 Files restored to: {}", target_path.display());
@@ -600,15 +650,144 @@ impl BackupManager {
     }
 
     async fn load_backup_metadata(&self, path: &Path) -> Result<Option<BackupMetadata>> {
-        match self.hetzner.download_file(path, &PathBuf::from("temp_metadata.json")).await {
-            Ok(_) => {
-                let metadata_str = tokio::fs::read_to_string("temp_metadata.json").await?;
-                let metadata = serde_json::from_str(&metadata_str)
+        match manifest_checksum::download_verified(&self.hetzner, &self.temp, path).await {
+            Ok(metadata_json) => {
+                let metadata = serde_json::from_slice(&metadata_json)
                     .map_err(|e| SkylockError::Backup(format!("Failed to parse metadata: {}", e)))?;
-                tokio::fs::remove_file("temp_metadata.json").await?;
                 Ok(Some(metadata))
             }
+            Err(e @ SkylockError::Integrity(_)) => Err(e),
             Err(_) => Ok(None)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+    use skylock_hetzner::test_server::{self, Request};
+    use crate::test_support::test_config;
+
+    #[derive(Default)]
+    struct MockStorage {
+        files: HashMap<String, Vec<u8>>,
+        /// Reject this many metadata uploads before accepting them
+        metadata_failures: usize,
+    }
+
+    /// WebDAV stub keeping uploads in memory
+    async fn mock_webdav(storage: Arc<Mutex<MockStorage>>) -> String {
+        let (listener, endpoint) = test_server::bind().await;
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let storage = storage.clone();
+                tokio::spawn(async move {
+                    let Some(request) = Request::read(&mut socket).await else { return };
+                    let (status, response_body) = {
+                        let mut storage = storage.lock().unwrap();
+                        match request.method.as_str() {
+                            "PUT" if request.path.contains("_metadata") && storage.metadata_failures > 0 => {
+                                storage.metadata_failures -= 1;
+                                (503, Vec::new())
+                            }
+                            "PUT" => {
+                                storage.files.insert(request.path.clone(), request.body.clone());
+                                (201, Vec::new())
+                            }
+                            "GET" => match storage.files.get(&request.path) {
+                                Some(data) => (200, data.clone()),
+                                None => (404, Vec::new()),
+                            },
+                            "DELETE" => if storage.files.remove(&request.path).is_some() { (204, Vec::new()) } else { (404, Vec::new()) },
+                            _ => (201, Vec::new()),
+                        }
+                    };
+                    request.respond(&mut socket, status, &response_body).await;
+                });
+            }
+        });
+        endpoint
+    }
+
+    fn test_manager(endpoint: &str, source: &Path) -> BackupManager {
+        let mut config = test_config(endpoint, source);
+        config.backup.backup_paths = vec![source.to_path_buf()];
+        BackupManager::new(config, test_server::client(endpoint)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_failed_metadata_upload_leaves_no_orphaned_archive() {
+        let source = TempDir::new().unwrap();
+        std::fs::write(source.path().join("notes.txt"), b"archive me").unwrap();
+        let storage = Arc::new(Mutex::new(MockStorage {
+            metadata_failures: METADATA_UPLOAD_ATTEMPTS as usize,
+            ..Default::default()
+        }));
+        let endpoint = mock_webdav(storage.clone()).await;
+
+        assert!(test_manager(&endpoint, source.path()).create_backup().await.is_err());
+        assert!(storage.lock().unwrap().files.is_empty());
+        // Nothing is written to the working directory
+        assert!(!std::fs::read_dir(".").unwrap().any(|entry| {
+            entry.unwrap().file_name().to_string_lossy().starts_with("temp_metadata")
+        }));
+
+        // A transient failure is retried and the backup kept
+        storage.lock().unwrap().metadata_failures = 1;
+        let metadata = test_manager(&endpoint, source.path()).create_backup().await.unwrap();
+        let storage = storage.lock().unwrap();
+        assert!(storage.files.keys().any(|path| path.ends_with(&format!("skylock_{}.tar.zst.enc", metadata.id))));
+        assert!(storage.files.keys().any(|path| path.ends_with(&format!("skylock_{}_metadata.json", metadata.id))));
+        assert!(storage.files.keys().any(|path| path.ends_with(&format!("skylock_{}_metadata.json.sha256", metadata.id))));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_archive_restores_tree() {
+        let source = TempDir::new().unwrap();
+        let tree = source.path().join("documents");
+        std::fs::create_dir_all(tree.join("nested/deeper")).unwrap();
+        std::fs::create_dir(tree.join("empty")).unwrap();
+        std::fs::write(tree.join("notes.txt"), b"archive me").unwrap();
+        std::fs::write(tree.join("nested/deeper/empty.txt"), b"").unwrap();
+        // Several segments of poorly compressible data
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let large: Vec<u8> = (0..3 * archive::SEGMENT_SIZE + 123).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect();
+        std::fs::write(tree.join("nested/large.bin"), &large).unwrap();
+
+        let storage = Arc::new(Mutex::new(MockStorage::default()));
+        let endpoint = mock_webdav(storage.clone()).await;
+        let mut manager = test_manager(&endpoint, &tree);
+        let metadata = manager.create_backup().await.unwrap();
+
+        let stored = storage.lock().unwrap().files.iter()
+            .find(|(path, _)| path.ends_with(&format!("skylock_{}.tar.zst.enc", metadata.id)))
+            .map(|(_, data)| data.clone())
+            .unwrap();
+        assert!(archive::is_streamed_archive(&stored));
+        assert_eq!(metadata.size, stored.len() as u64);
+
+        let target = TempDir::new().unwrap();
+        manager.restore_backup(&metadata.id, target.path()).await.unwrap();
+        let restored = target.path().join("documents");
+        assert_eq!(std::fs::read(restored.join("notes.txt")).unwrap(), b"archive me");
+        assert_eq!(std::fs::read(restored.join("nested/large.bin")).unwrap(), large);
+        assert!(std::fs::read(restored.join("nested/deeper/empty.txt")).unwrap().is_empty());
+        assert!(restored.join("empty").is_dir());
+
+        // A truncated upload is refused rather than restored in part
+        storage.lock().unwrap().files.values_mut()
+            .filter(|data| archive::is_streamed_archive(data))
+            .for_each(|data| data.truncate(data.len() - 100));
+        let target = TempDir::new().unwrap();
+        assert!(manager.restore_backup(&metadata.id, target.path()).await.is_err());
+    }
+}
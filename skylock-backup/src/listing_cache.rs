@@ -0,0 +1,126 @@
+//! Short-lived cache of the backup listing
+//!
+//! Listing backups downloads a summary per backup, which adds up with
+//! hundreds of them. Commands run back to back (`list`, then `find`, then
+//! `restore`) reuse the summaries fetched by the previous one for a short
+//! while from `<data_dir>/listing_cache.json`. Writes and deletions made
+//! through this client drop the cache; changes made elsewhere show up once
+//! it expires.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use tokio::fs;
+
+use crate::encrypted_manifest::ManifestSummary;
+use crate::temp_files::CleanupGuard;
+
+/// How long a listing is reused unless configured otherwise
+pub const DEFAULT_LIST_CACHE_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedListing {
+    /// Storage the listing came from, so switching configs never mixes them
+    destination: String,
+    cached_at: DateTime<Utc>,
+    summaries: Vec<ManifestSummary>,
+}
+
+/// Backup summaries cached on disk for `ttl`
+#[derive(Debug, Clone)]
+pub struct ListingCache {
+    path: PathBuf,
+    ttl: Duration,
+}
+
+impl ListingCache {
+    pub fn new(data_dir: &Path, ttl: Duration) -> Self {
+        Self { path: data_dir.join("listing_cache.json"), ttl }
+    }
+
+    /// The summaries listed from `destination`, if listed within the TTL
+    pub async fn load(&self, destination: &str) -> Option<Vec<ManifestSummary>> {
+        let json = fs::read(&self.path).await.ok()?;
+        let cached: CachedListing = serde_json::from_slice(&json).ok()?;
+        let age = Utc::now().signed_duration_since(cached.cached_at).to_std().ok()?;
+        (cached.destination == destination && age < self.ttl).then_some(cached.summaries)
+    }
+
+    /// Remember `summaries` as the listing of `destination`
+    ///
+    /// A cache that can't be written only costs the next command a listing,
+    /// so failures are logged and ignored.
+    pub async fn store(&self, destination: &str, summaries: &[ManifestSummary]) {
+        let cached = CachedListing {
+            destination: destination.to_string(),
+            cached_at: Utc::now(),
+            summaries: summaries.to_vec(),
+        };
+        let result = async {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            let json = serde_json::to_vec(&cached)?;
+            let staged = CleanupGuard::new(self.path.with_extension("json.tmp"));
+            fs::write(staged.path(), json).await?;
+            fs::rename(staged.path(), &self.path).await?;
+            staged.keep();
+            Ok::<_, std::io::Error>(())
+        }.await;
+        if let Err(e) = result {
+            tracing::debug!("Could not cache backup listing: {}", e);
+        }
+    }
+
+    /// Forget the cached listing, after backups were added or removed
+    pub async fn invalidate(&self) {
+        let _ = fs::remove_file(&self.path).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn summary(backup_id: &str) -> ManifestSummary {
+        ManifestSummary {
+            backup_id: backup_id.to_string(),
+            timestamp: Utc::now(),
+            file_count: 1,
+            total_size: 10,
+            source_paths: vec![PathBuf::from("/data")],
+            base_backup_id: None,
+            storage_tier: Default::default(),
+            tags: Vec::new(),
+            note: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_listing_cached_until_invalidated() {
+        let dir = TempDir::new().unwrap();
+        let cache = ListingCache::new(dir.path(), Duration::from_secs(60));
+        assert!(cache.load("user@host").await.is_none());
+
+        let summaries = vec![summary("20240101_020000"), summary("20240102_020000")];
+        cache.store("user@host", &summaries).await;
+        assert_eq!(cache.load("user@host").await, Some(summaries));
+        // Another storage box never sees it
+        assert!(cache.load("other@host").await.is_none());
+
+        cache.invalidate().await;
+        assert!(cache.load("user@host").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_listing_cache_expires() {
+        let dir = TempDir::new().unwrap();
+        ListingCache::new(dir.path(), Duration::from_secs(60))
+            .store("user@host", &[summary("20240101_020000")]).await;
+
+        let expired = ListingCache::new(dir.path(), Duration::ZERO);
+        assert!(expired.load("user@host").await.is_none());
+    }
+}
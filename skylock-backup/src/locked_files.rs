@@ -0,0 +1,316 @@
+//! Reading source files that are locked, unreadable or changing
+//!
+//! A file held open by another process without sharing (a Windows sharing
+//! or lock violation), one we may not read, or one deleted since the scan
+//! doesn't fail the whole backup. With `backup.vss_enabled` it is read from
+//! the shadow copy instead; otherwise, or if that fails as well, it is
+//! recorded in the manifest as skipped with the reason.
+//!
+//! A file whose size or modification time changes while it is read is read
+//! again, and skipped if it keeps changing, so a torn copy is never stored.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::error::{Result, SkylockError};
+use crate::vss::VssSnapshot;
+
+/// Reads made of a file that keeps changing before it is skipped
+const READ_ATTEMPTS: u32 = 2;
+
+/// Windows `ERROR_SHARING_VIOLATION`
+#[cfg(windows)]
+const ERROR_SHARING_VIOLATION: i32 = 32;
+
+/// Windows `ERROR_LOCK_VIOLATION`
+#[cfg(windows)]
+const ERROR_LOCK_VIOLATION: i32 = 33;
+
+/// Source file left out of a backup because it couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Reads the contents of a source file
+pub type SourceReader = Arc<dyn Fn(&Path) -> io::Result<Vec<u8>> + Send + Sync>;
+
+/// Reader for files on disk
+pub fn default_reader() -> SourceReader {
+    Arc::new(|path| std::fs::read(path))
+}
+
+/// Why `error` means a file is skipped rather than failing the backup, if it does
+pub fn skip_reason(error: &io::Error) -> Option<&'static str> {
+    #[cfg(windows)]
+    match error.raw_os_error() {
+        Some(ERROR_SHARING_VIOLATION) => return Some("in use by another process"),
+        Some(ERROR_LOCK_VIOLATION) => return Some("locked by another process"),
+        _ => {}
+    }
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => Some("permission denied"),
+        io::ErrorKind::NotFound => Some("deleted before it could be read"),
+        _ => None,
+    }
+}
+
+/// Outcome of reading a source file
+#[derive(Debug)]
+pub enum SourceRead {
+    /// The contents, with the metadata taken before reading them
+    Read { data: Vec<u8>, metadata: std::fs::Metadata },
+    Skipped(SkippedFile),
+}
+
+/// Outcome of opening a source file for streaming
+#[derive(Debug)]
+pub enum SourceOpen {
+    /// The open file, its metadata and the path it was opened at, which is
+    /// in the shadow copy for a locked file
+    Opened { file: std::fs::File, metadata: std::fs::Metadata, path: PathBuf },
+    Skipped(SkippedFile),
+}
+
+/// Whether a file kept its size and modification time between two looks
+pub fn unchanged(before: &std::fs::Metadata, after: &std::fs::Metadata) -> bool {
+    before.len() == after.len() && before.modified().ok() == after.modified().ok()
+}
+
+/// Reads source files, falling back to shadow copies for locked ones
+#[derive(Clone)]
+pub struct SourceFiles {
+    reader: SourceReader,
+    shadow_copies: Arc<Vec<VssSnapshot>>,
+}
+
+impl Default for SourceFiles {
+    fn default() -> Self {
+        Self::new(default_reader())
+    }
+}
+
+impl SourceFiles {
+    pub fn new(reader: SourceReader) -> Self {
+        Self { reader, shadow_copies: Arc::new(Vec::new()) }
+    }
+
+    /// Read files that can't be read directly from these snapshots
+    pub fn with_shadow_copies(mut self, snapshots: Vec<VssSnapshot>) -> Self {
+        self.shadow_copies = Arc::new(snapshots);
+        self
+    }
+
+    /// Release the shadow copies
+    pub fn cleanup(&self) -> Result<()> {
+        self.shadow_copies.iter().try_for_each(VssSnapshot::cleanup)
+    }
+
+    /// Read `path`, or say why it is skipped
+    ///
+    /// Errors other than the ones [`skip_reason`] accepts are returned.
+    pub async fn read(&self, path: &Path) -> Result<SourceRead> {
+        let source = self.clone();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || source.read_blocking(&path))
+            .await
+            .map_err(|e| SkylockError::Backup(format!("Read task failed: {}", e)))?
+    }
+
+    fn read_blocking(&self, path: &Path) -> Result<SourceRead> {
+        Ok(match self.with_fallback(path, |path| self.read_unchanged(path))? {
+            Ok((data, metadata)) => SourceRead::Read { data, metadata },
+            Err(skipped) => SourceRead::Skipped(skipped),
+        })
+    }
+
+    /// Open `path` for streaming, or say why it is skipped
+    ///
+    /// The file is opened directly rather than through the reader, and the
+    /// caller checks it didn't change while it was streamed (see
+    /// [`unchanged`]).
+    pub fn open(&self, path: &Path) -> Result<SourceOpen> {
+        let open = |path: &Path| -> io::Result<Option<_>> {
+            let file = std::fs::File::open(path)?;
+            let metadata = file.metadata()?;
+            Ok(Some((file, metadata, path.to_path_buf())))
+        };
+        Ok(match self.with_fallback(path, open)? {
+            Ok((file, metadata, path)) => SourceOpen::Opened { file, metadata, path },
+            Err(skipped) => SourceOpen::Skipped(skipped),
+        })
+    }
+
+    /// Run `attempt` on `path`, then on its shadow copy if the error is one
+    /// [`skip_reason`] accepts; `Ok(None)` from `attempt` means the file
+    /// kept changing
+    fn with_fallback<T>(
+        &self,
+        path: &Path,
+        attempt: impl Fn(&Path) -> io::Result<Option<T>>,
+    ) -> Result<std::result::Result<T, SkippedFile>> {
+        let error = match attempt(path) {
+            Ok(Some(value)) => return Ok(Ok(value)),
+            Ok(None) => return Ok(Err(Self::skipped(path, "changed while being read".to_string()))),
+            Err(e) => e,
+        };
+        let Some(reason) = skip_reason(&error) else {
+            return Err(error.into());
+        };
+        debug!("Cannot read {}: {}", path.display(), error);
+
+        let Some(snapshot) = self.shadow_copies.iter().find(|s| path.starts_with(s.source_path())) else {
+            return Ok(Err(Self::skipped(path, reason.to_string())));
+        };
+        let shadow_path = snapshot.get_snapshot_path(path)?;
+        match attempt(&shadow_path) {
+            Ok(Some(value)) => {
+                info!("Read {} from the shadow copy ({})", path.display(), reason);
+                Ok(Ok(value))
+            }
+            Ok(None) => Ok(Err(Self::skipped(path, format!("{}; shadow copy changed while being read", reason)))),
+            Err(e) => Ok(Err(Self::skipped(path, format!("{}; shadow copy unreadable: {}", reason, e)))),
+        }
+    }
+
+    /// Contents of `path` and its metadata from before reading, or `None`
+    /// if it changed during every attempt
+    fn read_unchanged(&self, path: &Path) -> io::Result<Option<(Vec<u8>, std::fs::Metadata)>> {
+        for _ in 0..READ_ATTEMPTS {
+            let before = std::fs::metadata(path)?;
+            let data = (self.reader)(path)?;
+            let after = std::fs::metadata(path)?;
+            if unchanged(&before, &after) {
+                return Ok(Some((data, before)));
+            }
+            debug!("{} changed while being read", path.display());
+        }
+        Ok(None)
+    }
+
+    fn skipped(path: &Path, reason: String) -> SkippedFile {
+        SkippedFile { path: path.to_path_buf(), reason }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    /// Reader that fails the first `failures` reads with `kind`
+    fn failing_reader(failures: usize, kind: io::ErrorKind) -> SourceReader {
+        let calls = AtomicUsize::new(0);
+        Arc::new(move |path| {
+            if calls.fetch_add(1, Ordering::Relaxed) < failures {
+                return Err(io::Error::from(kind));
+            }
+            std::fs::read(path)
+        })
+    }
+
+    fn expect_skipped(read: SourceRead) -> SkippedFile {
+        match read {
+            SourceRead::Skipped(skipped) => skipped,
+            SourceRead::Read { .. } => panic!("expected the file to be skipped"),
+        }
+    }
+
+    #[test]
+    fn test_skip_reasons() {
+        assert_eq!(skip_reason(&io::ErrorKind::PermissionDenied.into()), Some("permission denied"));
+        assert_eq!(skip_reason(&io::ErrorKind::NotFound.into()), Some("deleted before it could be read"));
+        assert_eq!(skip_reason(&io::ErrorKind::InvalidData.into()), None);
+        #[cfg(windows)]
+        assert_eq!(skip_reason(&io::Error::from_raw_os_error(ERROR_SHARING_VIOLATION)), Some("in use by another process"));
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_file_is_skipped_or_read_from_shadow_copy() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("locked.db");
+        std::fs::write(&path, b"database pages").unwrap();
+
+        let source = SourceFiles::new(failing_reader(1, io::ErrorKind::PermissionDenied));
+        let skipped = expect_skipped(source.read(&path).await.unwrap());
+        assert_eq!(skipped, SkippedFile { path: path.clone(), reason: "permission denied".to_string() });
+
+        // With a snapshot of the directory, the second read goes to the shadow copy
+        let snapshot = VssSnapshot::new(dir.path()).unwrap();
+        let source = SourceFiles::new(failing_reader(1, io::ErrorKind::PermissionDenied))
+            .with_shadow_copies(vec![snapshot]);
+        match source.read(&path).await.unwrap() {
+            SourceRead::Read { data, metadata } => {
+                assert_eq!(data, b"database pages");
+                assert_eq!(metadata.len(), 14);
+            }
+            SourceRead::Skipped(skipped) => panic!("skipped: {}", skipped.reason),
+        }
+
+        // Files outside the snapshot, or unreadable in it too, are skipped
+        let source = SourceFiles::new(failing_reader(2, io::ErrorKind::PermissionDenied))
+            .with_shadow_copies(vec![VssSnapshot::new(dir.path()).unwrap()]);
+        assert!(expect_skipped(source.read(&path).await.unwrap()).reason.starts_with("permission denied; shadow copy unreadable"));
+
+        // Other errors still fail
+        let source = SourceFiles::new(failing_reader(1, io::ErrorKind::InvalidData));
+        assert!(source.read(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_changing_while_read_is_retried_then_skipped() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("growing.log");
+        std::fs::write(&path, b"first line\n").unwrap();
+
+        // Appends a line during the first `changes` reads
+        let reader = |changes: usize| -> SourceReader {
+            let calls = AtomicUsize::new(0);
+            Arc::new(move |path| {
+                let data = std::fs::read(path)?;
+                if calls.fetch_add(1, Ordering::Relaxed) < changes {
+                    let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+                    std::io::Write::write_all(&mut file, b"another line\n")?;
+                }
+                Ok(data)
+            })
+        };
+
+        match SourceFiles::new(reader(1)).read(&path).await.unwrap() {
+            SourceRead::Read { data, .. } => assert_eq!(data, b"first line\nanother line\n"),
+            SourceRead::Skipped(skipped) => panic!("skipped: {}", skipped.reason),
+        }
+
+        let skipped = expect_skipped(SourceFiles::new(reader(READ_ATTEMPTS as usize)).read(&path).await.unwrap());
+        assert_eq!(skipped.reason, "changed while being read");
+    }
+
+    #[test]
+    fn test_open_for_streaming() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("disk.img");
+        std::fs::write(&path, b"sectors").unwrap();
+
+        match SourceFiles::default().open(&path).unwrap() {
+            SourceOpen::Opened { mut file, metadata, path: opened } => {
+                let mut data = Vec::new();
+                std::io::Read::read_to_end(&mut file, &mut data).unwrap();
+                assert_eq!(data, b"sectors");
+                assert_eq!(opened, path);
+                assert!(unchanged(&metadata, &std::fs::metadata(&path).unwrap()));
+            }
+            SourceOpen::Skipped(skipped) => panic!("skipped: {}", skipped.reason),
+        }
+
+        match SourceFiles::default().open(&dir.path().join("gone.img")).unwrap() {
+            SourceOpen::Skipped(skipped) => assert_eq!(skipped.reason, "deleted before it could be read"),
+            SourceOpen::Opened { .. } => panic!("opened a missing file"),
+        }
+    }
+}
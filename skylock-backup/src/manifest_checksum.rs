@@ -0,0 +1,126 @@
+//! Checksums for manifests and metadata on the remote
+//!
+//! Manifests and archive metadata are parsed straight after download, and a
+//! truncated or damaged transfer can still be valid JSON (or, for legacy
+//! plaintext, a shorter but well-formed document). Each of them is therefore
+//! uploaded with a companion `<name>.sha256` in `sha256sum` format, and the
+//! downloaded bytes are checked against it before they are parsed.
+//!
+//! Backups made before companions existed have none and load unchecked.
+
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use skylock_hetzner::HetznerClient;
+use tracing::debug;
+
+use crate::error::{Result, SkylockError};
+use crate::temp_files::TempFiles;
+
+/// Suffix of the companion checksum file
+pub const CHECKSUM_SUFFIX: &str = ".sha256";
+
+/// Remote path of the checksum for `path`
+pub fn companion_path(path: &Path) -> PathBuf {
+    let mut companion = path.as_os_str().to_owned();
+    companion.push(CHECKSUM_SUFFIX);
+    PathBuf::from(companion)
+}
+
+/// Hex SHA-256 of `data`
+pub fn checksum(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Companion file contents for `data` stored as `path`, as `sha256sum` writes them
+pub fn companion_contents(data: &[u8], path: &Path) -> String {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    format!("{}  {}\n", checksum(data), name)
+}
+
+/// Check downloaded `data` against the contents of its companion file
+pub fn verify(data: &[u8], companion: &str, path: &Path) -> Result<()> {
+    let expected = companion.split_whitespace().next().unwrap_or_default();
+    if expected.len() != 64 || !expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(SkylockError::Integrity(format!(
+            "checksum of {} is unreadable; the download may be corrupted, retry",
+            path.display()
+        )));
+    }
+    let actual = checksum(data);
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(SkylockError::Integrity(format!(
+            "manifest download corrupted, retry ({}: got {} bytes with SHA-256 {}, expected {})",
+            path.display(), data.len(), actual, expected
+        )));
+    }
+    Ok(())
+}
+
+/// Upload `data` to `remote_path` with its checksum
+///
+/// A previous checksum is removed before the file is replaced, so a failed
+/// checksum upload leaves an unchecked file rather than one that never
+/// matches again.
+pub async fn upload_with_checksum(hetzner: &HetznerClient, temp: &TempFiles, data: &[u8], remote_path: &Path) -> Result<()> {
+    let companion = companion_path(remote_path);
+    // Missing on first upload
+    let _ = hetzner.delete_file(&companion).await;
+
+    temp.upload(hetzner, data, remote_path).await?;
+    temp.upload(hetzner, companion_contents(data, remote_path).as_bytes(), &companion).await
+}
+
+/// Download `remote_path` and return its verified contents
+///
+/// Fails with [`SkylockError::Integrity`] if the bytes don't match the
+/// companion checksum. Files without one are returned unchecked; failing
+/// to download an existing one is an error, not a reason to skip the check.
+pub async fn download_verified(hetzner: &HetznerClient, temp: &TempFiles, remote_path: &Path) -> Result<Vec<u8>> {
+    let data = temp.download_bytes(hetzner, remote_path).await?;
+
+    match temp.download_bytes(hetzner, &companion_path(remote_path)).await {
+        Ok(companion) => verify(&data, &String::from_utf8_lossy(&companion), remote_path)?,
+        Err(e) if e.is_not_found() => debug!("No checksum for {}", remote_path.display()),
+        Err(e) => return Err(e),
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_companion_path() {
+        assert_eq!(
+            companion_path(Path::new("/skylock/backups/20250101_000000/manifest.json.enc")),
+            PathBuf::from("/skylock/backups/20250101_000000/manifest.json.enc.sha256")
+        );
+        assert_eq!(
+            companion_path(Path::new("skylock_backup_1_metadata.json")),
+            PathBuf::from("skylock_backup_1_metadata.json.sha256")
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let path = Path::new("/skylock/backups/20250101_000000/manifest.json");
+        let manifest = br#"{"backup_id":"20250101_000000","files":[{"path":"a"},{"path":"b"}]}"#;
+        let companion = companion_contents(manifest, path);
+        assert!(companion.ends_with("  manifest.json\n"));
+        verify(manifest, &companion, path).unwrap();
+        verify(manifest, &companion.to_uppercase(), path).unwrap();
+
+        // A truncated download is rejected, as is any other change
+        let err = verify(&manifest[..manifest.len() - 20], &companion, path).unwrap_err();
+        assert!(matches!(err, SkylockError::Integrity(_)));
+        assert!(err.to_string().contains("manifest download corrupted, retry"));
+        let mut flipped = manifest.to_vec();
+        flipped[10] ^= 1;
+        assert!(verify(&flipped, &companion, path).is_err());
+
+        assert!(verify(manifest, "", path).is_err());
+        assert!(verify(manifest, "not-a-checksum  manifest.json\n", path).is_err());
+    }
+}
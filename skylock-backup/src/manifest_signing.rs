@@ -33,6 +33,8 @@ pub enum SignatureError {
     KeyGeneration(String),
     #[error("Key loading failed: {0}")]
     KeyLoading(String),
+    #[error("Key saving failed: {0}")]
+    KeySaving(String),
     #[error("Signing failed: {0}")]
     Signing(String),
     #[error("Verification failed: {0}")]
@@ -95,6 +97,67 @@ impl SecureSigningKey {
         })
     }
 
+    /// Load a signing key from a PKCS#8 PEM file
+    pub fn from_pem_file<P: AsRef<Path>>(path: P) -> std::result::Result<Self, SignatureError> {
+        let pem_data = std::fs::read_to_string(&path)?;
+        let signing_key = SigningKey::from_pkcs8_pem(&pem_data)
+            .map_err(|e| SignatureError::KeyLoading(e.to_string()))?;
+        let verifying_key = signing_key.verifying_key();
+        
+        // The PEM holds only the key, so the fingerprint doubles as its ID
+        let fingerprint = Self::calculate_fingerprint(&verifying_key);
+        let metadata = SignatureMetadata {
+            key_id: fingerprint.clone(),
+            algorithm: "Ed25519".to_string(),
+            created_at: Utc::now(),
+            expires_at: None,
+            purpose: "manifest-signing".to_string(),
+            public_key_hex: hex::encode(verifying_key.as_bytes()),
+            fingerprint,
+        };
+        
+        Ok(SecureSigningKey {
+            metadata,
+            signing_key,
+            verifying_key,
+        })
+    }
+
+    /// Save the signing key as a PKCS#8 PEM file readable only by the owner
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> std::result::Result<(), SignatureError> {
+        let pem_data = self.signing_key
+            .to_pkcs8_pem(LineEnding::LF)
+            .map_err(|e| SignatureError::KeySaving(e.to_string()))?;
+        
+        std::fs::write(&path, pem_data.as_bytes())?;
+        
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        
+        Ok(())
+    }
+
+    /// Save the public key as a PEM file
+    pub fn save_public_key_to_file<P: AsRef<Path>>(&self, path: P) -> std::result::Result<(), SignatureError> {
+        let pem_data = self.verifying_key
+            .to_public_key_pem(LineEnding::LF)
+            .map_err(|e| SignatureError::KeySaving(e.to_string()))?;
+        
+        std::fs::write(&path, pem_data.as_bytes())?;
+        Ok(())
+    }
+
+    /// Public half of this key, for verification
+    pub fn public_signature_key(&self) -> PublicSignatureKey {
+        PublicSignatureKey {
+            metadata: self.metadata.clone(),
+            verifying_key: self.verifying_key,
+        }
+    }
+
     /// Sign only the hash of data
     pub fn sign_hash(&self, hash: &[u8]) -> std::result::Result<Vec<u8>, SignatureError> {
         let signature = self.signing_key
@@ -130,6 +193,29 @@ pub struct PublicSignatureKey {
 }
 
 impl PublicSignatureKey {
+    /// Load a public key from a PEM file
+    pub fn from_pem_file<P: AsRef<Path>>(path: P) -> std::result::Result<Self, SignatureError> {
+        let pem_data = std::fs::read_to_string(&path)?;
+        let verifying_key = VerifyingKey::from_public_key_pem(&pem_data)
+            .map_err(|e| SignatureError::KeyLoading(e.to_string()))?;
+        
+        let fingerprint = SecureSigningKey::calculate_fingerprint(&verifying_key);
+        let metadata = SignatureMetadata {
+            key_id: fingerprint.clone(),
+            algorithm: "Ed25519".to_string(),
+            created_at: Utc::now(),
+            expires_at: None,
+            purpose: "verification".to_string(),
+            public_key_hex: hex::encode(verifying_key.as_bytes()),
+            fingerprint,
+        };
+        
+        Ok(PublicSignatureKey {
+            metadata,
+            verifying_key,
+        })
+    }
+
     /// Verify signature of data hash
     pub fn verify_hash(&self, hash: &[u8], signature_bytes: &[u8]) -> std::result::Result<bool, SignatureError> {
         if signature_bytes.len() != 64 {
@@ -155,6 +241,74 @@ impl From<SignatureError> for SkylockError {
     }
 }
 
+/// Manifest signing key in the data directory, written by
+/// `skylock generate-manifest-key`
+pub fn signing_key_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("keys").join("manifest_signing.pem")
+}
+
+/// Public half of the key at [`signing_key_path`]
+pub fn public_key_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("keys").join("manifest_signing.pub.pem")
+}
+
+/// Which manifests may be loaded
+///
+/// A signed manifest must verify against the public key whenever one is
+/// configured. Unsigned manifests, and signed ones that can't be checked for
+/// lack of a key, are only refused when signatures are required.
+#[derive(Default)]
+pub struct SignaturePolicy {
+    public_key: Option<PublicSignatureKey>,
+    require_signed: bool,
+}
+
+impl SignaturePolicy {
+    /// Policy checking signatures against `public_key`
+    pub fn new(public_key: Option<PublicSignatureKey>, require_signed: bool) -> Self {
+        Self { public_key, require_signed }
+    }
+    
+    /// Whether unsigned manifests are refused
+    pub fn requires_signatures(&self) -> bool {
+        self.require_signed
+    }
+    
+    /// Fingerprint of the key signatures are checked against
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.public_key.as_ref().map(|key| key.metadata.fingerprint.as_str())
+    }
+    
+    /// Refuse `manifest` if its signature is bad, or missing while required
+    /// 
+    /// The signature is checked against `json`, the manifest as it was
+    /// stored, since parsing may have upgraded `manifest` to a newer schema.
+    pub fn check(&self, manifest: &BackupManifest, json: &[u8]) -> Result<()> {
+        match (&manifest.signature, &self.public_key) {
+            (None, _) if self.require_signed => Err(SkylockError::Crypto(format!(
+                "Manifest of backup {} is not signed, and require_signed_manifests is set. \
+                 Sign it with 'skylock sign-manifest {}' if you trust it",
+                manifest.backup_id, manifest.backup_id
+            ))),
+            (Some(_), Some(public_key)) => {
+                if verify_manifest_json(json, public_key)? {
+                    Ok(())
+                } else {
+                    Err(SkylockError::Crypto(format!(
+                        "Manifest signature of backup {} is invalid: the manifest was modified after signing",
+                        manifest.backup_id
+                    )))
+                }
+            }
+            (Some(_), None) if self.require_signed => Err(SkylockError::Crypto(format!(
+                "Manifest of backup {} is signed, but no manifest public key is configured to verify it",
+                manifest.backup_id
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
 /// Latest chain state for anti-rollback protection
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChainState {
@@ -244,6 +398,46 @@ pub fn verify_manifest(
     let sig_metadata = manifest.signature.as_ref()
         .ok_or_else(|| SkylockError::Crypto("Manifest is not signed".to_string()))?;
     
+    // Create canonical manifest (without signature)
+    let mut canonical_manifest = manifest.clone();
+    canonical_manifest.signature = None;
+    
+    let manifest_json = serde_json::to_vec(&canonical_manifest)
+        .map_err(|e| SkylockError::Crypto(format!("Failed to serialize manifest: {}", e)))?;
+    
+    verify_signature(sig_metadata, &manifest_json, public_key)
+}
+
+/// Verify the signature of a serialized manifest
+/// 
+/// Unlike [`verify_manifest`] this checks the JSON as it was stored, so
+/// manifests signed under an older schema still verify after the current
+/// one adds or changes fields.
+pub fn verify_manifest_json(json: &[u8], public_key: &PublicSignatureKey) -> Result<bool> {
+    let mut manifest: serde_json::Value = serde_json::from_slice(json)
+        .map_err(|e| SkylockError::Crypto(format!("Failed to parse manifest: {}", e)))?;
+    
+    // The canonical form is the manifest without its signature, with the
+    // other fields left in the order they were signed in
+    let sig_metadata = manifest.as_object_mut()
+        .and_then(|object| object.shift_remove("signature"))
+        .filter(|signature| !signature.is_null())
+        .ok_or_else(|| SkylockError::Crypto("Manifest is not signed".to_string()))?;
+    let sig_metadata: ManifestSignature = serde_json::from_value(sig_metadata)
+        .map_err(|e| SkylockError::Crypto(format!("Invalid manifest signature: {}", e)))?;
+    
+    let manifest_json = serde_json::to_vec(&manifest)
+        .map_err(|e| SkylockError::Crypto(format!("Failed to serialize manifest: {}", e)))?;
+    
+    verify_signature(&sig_metadata, &manifest_json, public_key)
+}
+
+/// Check `signature` over the canonical manifest bytes
+fn verify_signature(
+    sig_metadata: &ManifestSignature,
+    manifest_json: &[u8],
+    public_key: &PublicSignatureKey,
+) -> Result<bool> {
     // Verify key fingerprint matches
     if sig_metadata.fingerprint != public_key.metadata.fingerprint {
         return Err(SkylockError::Crypto(format!(
@@ -257,15 +451,8 @@ pub fn verify_manifest(
     let signature_bytes = hex::decode(&sig_metadata.signature_hex)
         .map_err(|e| SkylockError::Crypto(format!("Invalid signature hex: {}", e)))?;
     
-    // Create canonical manifest (without signature)
-    let mut canonical_manifest = manifest.clone();
-    canonical_manifest.signature = None;
-    
-    let manifest_json = serde_json::to_vec(&canonical_manifest)
-        .map_err(|e| SkylockError::Crypto(format!("Failed to serialize manifest: {}", e)))?;
-    
     // Verify signature
-    public_key.verify_hash(&manifest_json, &signature_bytes)
+    public_key.verify_hash(manifest_json, &signature_bytes)
         .map_err(|e| SkylockError::Crypto(format!("Verification failed: {}", e)))
 }
 
@@ -342,6 +529,7 @@ mod tests {
     
     fn create_test_manifest() -> BackupManifest {
         BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
             backup_id: "backup_20240101_000000".to_string(),
             timestamp: Utc::now(),
             files: vec![],
@@ -351,9 +539,19 @@ mod tests {
             base_backup_id: None,
             encryption_version: "v2".to_string(),
             kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
             signature: None,
             backup_chain_version: 0,
             encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
         }
     }
     
@@ -398,6 +596,42 @@ mod tests {
         assert!(!is_valid);
     }
     
+    #[test]
+    fn test_manifest_signed_at_older_schema_verifies() {
+        let signing_key = SecureSigningKey::generate("backup_integrity".to_string(), None).unwrap();
+        let public_key = signing_key.public_signature_key();
+        
+        // A manifest as an older skylock wrote it: an earlier schema version,
+        // without a field the current one always serializes
+        let mut old = serde_json::to_value(create_test_manifest()).unwrap();
+        let object = old.as_object_mut().unwrap();
+        object.insert("schema_version".to_string(), 5.into());
+        object.shift_remove("backup_chain_version");
+        let canonical = serde_json::to_vec(&old).unwrap();
+        let signature = ManifestSignature {
+            algorithm: "Ed25519".to_string(),
+            fingerprint: signing_key.metadata().fingerprint.clone(),
+            signature_hex: hex::encode(signing_key.sign_hash(&canonical).unwrap()),
+            signed_at: Utc::now(),
+            key_id: signing_key.metadata().key_id.clone(),
+        };
+        old.as_object_mut().unwrap().insert("signature".to_string(), serde_json::to_value(&signature).unwrap());
+        let json = serde_json::to_vec_pretty(&old).unwrap();
+        
+        // Parsing upgrades it, so only the stored JSON still matches
+        let manifest = BackupManifest::from_json(&json).unwrap();
+        assert_eq!(manifest.schema_version, BackupManifest::SCHEMA_VERSION);
+        assert!(!verify_manifest(&manifest, &public_key).unwrap());
+        assert!(verify_manifest_json(&json, &public_key).unwrap());
+        let policy = SignaturePolicy::new(Some(public_key), true);
+        policy.check(&manifest, &json).unwrap();
+        
+        // Changes to the stored JSON are still caught
+        let tampered = String::from_utf8(json).unwrap().replace("\"file_count\": 0", "\"file_count\": 1");
+        let err = policy.check(&manifest, tampered.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("modified after signing"), "unexpected error: {}", err);
+    }
+    
     #[tokio::test]
     async fn test_chain_version_anti_rollback() {
         let temp_dir = TempDir::new().unwrap();
@@ -62,7 +62,7 @@ pub async fn migrate_backup_v1_to_v2(
     println!();
     println!("🔄 Migrating backup {} from v1 to v2 format", backup_id);
     println!("   📦 Files to migrate: {}", manifest.file_count);
-    println!("   💾 Total size: {:.2} GB", manifest.total_size as f64 / 1024.0 / 1024.0 / 1024.0);
+    println!("   💾 Total size: {}", skylock_core::ByteSize(manifest.total_size));
     println!();
     println!("⚠️  WARNING: This operation may take a long time and use significant disk space!");
     println!("   Original backup will be preserved as: {}", backup_id);
@@ -0,0 +1,351 @@
+//! Concurrent multipart downloads for large restores
+//!
+//! A single HTTP stream rarely saturates the link for one huge file. When the
+//! server supports byte ranges, the object is split into N ranges that are
+//! fetched in parallel over pooled connections and written at their offsets
+//! into a preallocated file. Servers without range support fall back to a
+//! single-stream download.
+
+use std::io::SeekFrom;
+use std::path::Path;
+use base64::Engine;
+use futures::future::try_join_all;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::{debug, info, warn};
+
+use crate::connection_pool::{
+    ConnectionFactory, ConnectionPool, ConnectionPoolConfig, ConnectionPoolError, ConnectionType,
+};
+use crate::error::{Result, SkylockError};
+use skylock_hetzner::{FileMetadata, HetznerClient, HetznerConfig};
+
+/// Default number of parallel ranges
+const DEFAULT_PARTS: usize = 4;
+
+/// Objects smaller than this are downloaded in one stream (64 MB)
+const DEFAULT_MIN_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Multipart download settings
+#[derive(Debug, Clone)]
+pub struct MultipartDownloadConfig {
+    /// Number of ranges fetched in parallel
+    pub parts: usize,
+    /// Minimum object size for splitting into ranges
+    pub min_size: u64,
+}
+
+impl Default for MultipartDownloadConfig {
+    fn default() -> Self {
+        Self {
+            parts: DEFAULT_PARTS,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+}
+
+/// Creates independent storage clients (one HTTP connection pool each)
+pub struct HetznerConnectionFactory {
+    config: HetznerConfig,
+}
+
+impl HetznerConnectionFactory {
+    pub fn new(config: HetznerConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait::async_trait]
+impl ConnectionFactory<HetznerClient> for HetznerConnectionFactory {
+    async fn create(&self) -> std::result::Result<HetznerClient, ConnectionPoolError> {
+        HetznerClient::new(self.config.clone())
+            .map_err(|e| ConnectionPoolError::CreationFailed(e.to_string()))
+    }
+
+    async fn validate(&self, _connection: &HetznerClient) -> bool {
+        // HTTP clients reconnect transparently
+        true
+    }
+
+    async fn close(&self, _connection: HetznerClient) {}
+}
+
+/// Downloads large objects as parallel byte ranges
+pub struct MultipartDownloader {
+    pool: ConnectionPool<HetznerClient, HetznerConnectionFactory>,
+    config: MultipartDownloadConfig,
+}
+
+impl MultipartDownloader {
+    /// Create a downloader with a connection per part
+    pub async fn new(hetzner: HetznerConfig, config: MultipartDownloadConfig) -> Self {
+        let parts = config.parts.max(1);
+        let pool_config = ConnectionPoolConfig {
+            min_connections: 1,
+            max_connections: parts,
+            initial_connections: 1,
+            validate_on_acquire: false,
+            ..Default::default()
+        };
+        let pool = ConnectionPool::new(
+            HetznerConnectionFactory::new(hetzner),
+            pool_config,
+            ConnectionType::WebDav,
+        ).await;
+
+        Self { pool, config }
+    }
+
+    /// Download a remote file, splitting it into ranges when worthwhile
+    ///
+    /// `expected_hash` is the base64 SHA-256 of the object (the format of
+    /// [`FileMetadata::hash`]); the reassembled file is checked against it.
+    pub async fn download_file(
+        &self,
+        remote_path: &Path,
+        local_path: &Path,
+        expected_hash: Option<&str>,
+    ) -> Result<FileMetadata> {
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let info = {
+            let conn = self.acquire().await?;
+            conn.connection().object_info(remote_path).await?
+        };
+
+        let multipart = info.accepts_ranges && self.config.parts > 1 && info.size >= self.config.min_size;
+        let completed = multipart && self.download_ranges(remote_path, local_path, info.size).await?;
+
+        if !completed {
+            if multipart {
+                warn!("Server did not honour range requests for {}, using a single stream", remote_path.display());
+            }
+            let conn = self.acquire().await?;
+            conn.connection().download_file(remote_path, local_path).await?;
+        }
+
+        let metadata = Self::file_metadata(remote_path, local_path).await?;
+        if metadata.size != info.size {
+            return Err(SkylockError::Backup(format!(
+                "Download of {} is {} bytes, expected {}",
+                remote_path.display(), metadata.size, info.size
+            )));
+        }
+        if let Some(expected) = expected_hash {
+            if metadata.hash != expected {
+                return Err(SkylockError::Backup(format!(
+                    "Hash mismatch for {}: expected {}, got {}",
+                    remote_path.display(), expected, metadata.hash
+                )));
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    /// Fetch all ranges in parallel into a preallocated file
+    ///
+    /// Returns `false` if the server answered any range with the whole
+    /// object, in which case the caller falls back to a single stream.
+    async fn download_ranges(&self, remote_path: &Path, local_path: &Path, size: u64) -> Result<bool> {
+        let file = tokio::fs::File::create(local_path).await?;
+        file.set_len(size).await?;
+        drop(file);
+
+        let ranges = Self::split_ranges(size, self.config.parts);
+        info!("Downloading {} in {} parts", remote_path.display(), ranges.len());
+
+        let results = try_join_all(ranges.into_iter().map(|(start, end)| {
+            self.download_part(remote_path, local_path, start, end)
+        })).await?;
+
+        Ok(results.into_iter().all(|honoured| honoured))
+    }
+
+    /// Download one range and write it at its offset
+    async fn download_part(&self, remote_path: &Path, local_path: &Path, start: u64, end: u64) -> Result<bool> {
+        let mut conn = self.acquire().await?;
+        let data = match conn.connection().download_range(remote_path, start, end).await {
+            Ok(Some(data)) => data,
+            Ok(None) => return Ok(false),
+            Err(e) => {
+                conn.record_error();
+                return Err(e.into());
+            }
+        };
+        conn.record_bytes(data.len() as u64);
+        drop(conn);
+
+        let mut file = tokio::fs::OpenOptions::new().write(true).open(local_path).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        file.write_all(&data).await?;
+        file.flush().await?;
+
+        debug!("Wrote bytes {}-{} of {}", start, end, local_path.display());
+        Ok(true)
+    }
+
+    async fn acquire(&self) -> Result<crate::connection_pool::ConnectionGuard<'_, HetznerClient, HetznerConnectionFactory>> {
+        self.pool.acquire().await
+            .map_err(|e| SkylockError::Backup(format!("No storage connection available: {}", e)))
+    }
+
+    /// Split `size` bytes into at most `parts` inclusive ranges
+    fn split_ranges(size: u64, parts: usize) -> Vec<(u64, u64)> {
+        if size == 0 {
+            return Vec::new();
+        }
+        let parts = (parts.max(1) as u64).min(size);
+        // `u64::div_ceil` needs Rust 1.73; the workspace supports 1.70
+        #[allow(clippy::manual_div_ceil)]
+        let part_size = (size + parts - 1) / parts;
+
+        (0..parts)
+            .map(|i| i * part_size)
+            .take_while(|&start| start < size)
+            .map(|start| (start, (start + part_size).min(size) - 1))
+            .collect()
+    }
+
+    /// Size and base64 SHA-256 of a downloaded file
+    async fn file_metadata(remote_path: &Path, local_path: &Path) -> Result<FileMetadata> {
+        let mut file = tokio::fs::File::open(local_path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let mut size = 0u64;
+        loop {
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            size += n as u64;
+        }
+
+        Ok(FileMetadata {
+            path: remote_path.to_path_buf(),
+            size,
+            hash: base64::engine::general_purpose::STANDARD.encode(hasher.finalize()),
+            last_modified: chrono::Utc::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use skylock_hetzner::test_server;
+    use tempfile::TempDir;
+    use tokio::io::AsyncWriteExt;
+
+    /// Serves one object, optionally honouring Range, recording requested ranges
+    async fn mock_server(object: Arc<Vec<u8>>, ranges: bool, requested: Arc<Mutex<Vec<String>>>) -> String {
+        let (listener, endpoint) = test_server::bind().await;
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let object = object.clone();
+                let requested = requested.clone();
+                tokio::spawn(async move {
+                    let Some((head, _)) = test_server::read_head(&mut socket).await else { return };
+                    let method = head.split_whitespace().next().unwrap_or("").to_string();
+                    let range = head.lines()
+                        .find_map(|l| l.to_ascii_lowercase().strip_prefix("range: bytes=").map(str::to_string));
+                    let accept = if ranges { "accept-ranges: bytes\r\n" } else { "" };
+
+                    let (status, body) = match range.filter(|_| ranges && method == "GET") {
+                        Some(range) => {
+                            requested.lock().unwrap().push(range.clone());
+                            let (start, end) = range.trim().split_once('-').unwrap();
+                            let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+                            ("206 Partial Content", object[start..=end].to_vec())
+                        }
+                        None => ("200 OK", object.to_vec()),
+                    };
+                    let header = format!(
+                        "HTTP/1.1 {}\r\n{}content-length: {}\r\nconnection: close\r\n\r\n",
+                        status,
+                        accept,
+                        body.len()
+                    );
+                    let _ = socket.write_all(header.as_bytes()).await;
+                    if method != "HEAD" {
+                        let _ = socket.write_all(&body).await;
+                    }
+                });
+            }
+        });
+        endpoint
+    }
+
+    fn hetzner_config(endpoint: &str) -> HetznerConfig {
+        HetznerConfig {
+            endpoint: endpoint.to_string(),
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            api_token: String::new(),
+            encryption_key: String::new(),
+        }
+    }
+
+    fn test_object() -> Vec<u8> {
+        // Multi-megabyte and not a multiple of the part count
+        (0..5 * 1024 * 1024 + 3u32).map(|i| (i.wrapping_mul(2654435761) >> 7) as u8).collect()
+    }
+
+    fn sha256_base64(data: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(Sha256::digest(data))
+    }
+
+    #[tokio::test]
+    async fn test_multipart_download_reassembles_in_order() {
+        let object = Arc::new(test_object());
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let endpoint = mock_server(object.clone(), true, requested.clone()).await;
+        let config = MultipartDownloadConfig { parts: 4, min_size: 1024 * 1024 };
+        let downloader = MultipartDownloader::new(hetzner_config(&endpoint), config).await;
+
+        let dir = TempDir::new().unwrap();
+        let local = dir.path().join("restored.bin");
+        let expected = sha256_base64(&object);
+        let metadata = downloader
+            .download_file(Path::new("/skylock/big.bin.enc"), &local, Some(&expected))
+            .await
+            .unwrap();
+
+        assert_eq!(requested.lock().unwrap().len(), 4);
+        assert_eq!(metadata.size, object.len() as u64);
+        assert_eq!(metadata.hash, expected);
+        assert_eq!(std::fs::read(&local).unwrap(), *object);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_without_range_support() {
+        let object = Arc::new(test_object());
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let endpoint = mock_server(object.clone(), false, requested.clone()).await;
+        let config = MultipartDownloadConfig { parts: 4, min_size: 1024 * 1024 };
+        let downloader = MultipartDownloader::new(hetzner_config(&endpoint), config).await;
+
+        let dir = TempDir::new().unwrap();
+        let local = dir.path().join("restored.bin");
+        downloader.download_file(Path::new("/skylock/big.bin.enc"), &local, None).await.unwrap();
+
+        assert!(requested.lock().unwrap().is_empty());
+        assert_eq!(std::fs::read(&local).unwrap(), *object);
+
+        // A wrong expected hash is rejected
+        let err = downloader.download_file(Path::new("/skylock/big.bin.enc"), &local, Some("bogus")).await;
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_split_ranges() {
+        assert_eq!(MultipartDownloader::split_ranges(10, 4), vec![(0, 2), (3, 5), (6, 8), (9, 9)]);
+        assert_eq!(MultipartDownloader::split_ranges(3, 4), vec![(0, 0), (1, 1), (2, 2)]);
+        assert!(MultipartDownloader::split_ranges(0, 4).is_empty());
+    }
+}
@@ -1,23 +1,36 @@
 //! Parallel Hashing Module
 //!
 //! Multi-threaded file hashing using rayon for CPU-efficient
-//! SHA-256 hash computation:
-//! - Parallel chunk processing for large files
-//! - Memory-mapped I/O for efficient file access
-//! - Automatic thread pool sizing based on CPU cores
-//! - Progress reporting for long-running operations
+//! SHA-256 or BLAKE3 hash computation:
+//! - Large files split into ranges hashed in parallel and combined with a
+//!   tree hash, so one huge file doesn't starve the pool
+//! - Memory-mapped I/O for efficient access to large files
+//! - Configurable worker count and read buffer size
+//! - Per-file timing and aggregate statistics
+//!
+//! Files of at least `parallel_threshold` bytes hash to the hash of the
+//! hashes of each `chunk_size` range rather than a plain hash, so hashes are
+//! only comparable between configs with the same algorithm and chunking
+//! settings. The worker count, buffer size and mmap setting never change the
+//! result.
 
 use std::io::{Read, Seek, SeekFrom};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use sha2::{Sha256, Digest};
 use rayon::prelude::*;
-use tracing::{debug, info};
+use tracing::debug;
+
+use crate::content_hash::HashAlgorithm;
 
 /// Default chunk size for parallel hashing (4MB)
 const DEFAULT_HASH_CHUNK_SIZE: usize = 4 * 1024 * 1024;
 
+/// Default read buffer size (1MB)
+const DEFAULT_READ_BUFFER_SIZE: usize = 1024 * 1024;
+
 /// Minimum file size to use parallel hashing (16MB)
 const PARALLEL_HASH_THRESHOLD: u64 = 16 * 1024 * 1024;
 
@@ -27,14 +40,18 @@ const MAX_HASH_THREADS: usize = 16;
 /// Configuration for parallel hashing
 #[derive(Debug, Clone)]
 pub struct ParallelHashConfig {
-    /// Chunk size for parallel processing
+    /// Size of the ranges large files are split into
     pub chunk_size: usize,
     /// Minimum file size to use parallel hashing
     pub parallel_threshold: u64,
-    /// Maximum number of threads
+    /// Number of worker threads
     pub max_threads: usize,
-    /// Enable memory mapping for large files
+    /// Buffer size for reading files
+    pub read_buffer_size: usize,
+    /// Memory-map files of at least `parallel_threshold` bytes
     pub use_mmap: bool,
+    /// Hash of ranges and of the tree combining them
+    pub algorithm: HashAlgorithm,
 }
 
 impl Default for ParallelHashConfig {
@@ -47,7 +64,9 @@ impl Default for ParallelHashConfig {
             chunk_size: DEFAULT_HASH_CHUNK_SIZE,
             parallel_threshold: PARALLEL_HASH_THRESHOLD,
             max_threads: cpu_count.min(MAX_HASH_THREADS),
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
             use_mmap: true,
+            algorithm: HashAlgorithm::default(),
         }
     }
 }
@@ -59,7 +78,9 @@ impl ParallelHashConfig {
             chunk_size: 8 * 1024 * 1024, // 8MB chunks
             parallel_threshold: 4 * 1024 * 1024, // 4MB threshold
             max_threads: MAX_HASH_THREADS,
+            read_buffer_size: 4 * 1024 * 1024,
             use_mmap: true,
+            algorithm: HashAlgorithm::default(),
         }
     }
 
@@ -69,7 +90,9 @@ impl ParallelHashConfig {
             chunk_size: 1024 * 1024, // 1MB chunks
             parallel_threshold: 64 * 1024 * 1024, // 64MB threshold
             max_threads: 4,
+            read_buffer_size: 256 * 1024,
             use_mmap: false, // Don't use mmap to conserve memory
+            algorithm: HashAlgorithm::default(),
         }
     }
 
@@ -79,9 +102,35 @@ impl ParallelHashConfig {
             chunk_size: 1024 * 1024,
             parallel_threshold: u64::MAX, // Never use parallel
             max_threads: 1,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
             use_mmap: false,
+            algorithm: HashAlgorithm::default(),
         }
     }
+
+    /// Set the number of worker threads
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.max_threads = workers.max(1);
+        self
+    }
+
+    /// Set the buffer size for reading files
+    pub fn with_read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = size.max(4096);
+        self
+    }
+
+    /// Enable or disable memory mapping of large files
+    pub fn with_mmap(mut self, use_mmap: bool) -> Self {
+        self.use_mmap = use_mmap;
+        self
+    }
+
+    /// Set the hash algorithm
+    pub fn with_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
 }
 
 /// Hashing statistics
@@ -120,6 +169,28 @@ impl HashingStats {
     }
 }
 
+/// Hash of one file from [`ParallelHasher::hash_files_detailed`]
+#[derive(Debug)]
+pub struct FileHashResult {
+    pub path: PathBuf,
+    /// Hex-encoded hash, or the error that stopped hashing
+    pub hash: std::io::Result<String>,
+    /// File size in bytes (0 if it couldn't be read)
+    pub size: u64,
+    /// Number of ranges the file was split into
+    pub ranges: usize,
+    /// Time from the first range starting to the last one finishing
+    pub elapsed: Duration,
+}
+
+/// One unit of work: a whole small file or one range of a large file
+struct HashTask {
+    file: usize,
+    offset: u64,
+    /// `None` hashes to the end of the file
+    len: Option<u64>,
+}
+
 /// Parallel file hasher
 pub struct ParallelHasher {
     config: ParallelHashConfig,
@@ -147,125 +218,169 @@ impl ParallelHasher {
         }
     }
 
-    /// Hash a file and return hex-encoded SHA-256
+    /// Hash a file and return the hex-encoded hash
     pub fn hash_file(&self, path: &Path) -> std::io::Result<String> {
-        let start = std::time::Instant::now();
-        let metadata = std::fs::metadata(path)?;
-        let file_size = metadata.len();
+        self.hash_files_detailed(&[path]).remove(0).hash
+    }
 
-        let hash = if file_size >= self.config.parallel_threshold {
-            // Large file: use parallel hashing
-            self.hash_file_parallel(path, file_size)?
-        } else {
-            // Small file: use sequential hashing
-            self.hash_file_sequential(path)?
-        };
+    /// Hash multiple files in parallel
+    pub fn hash_files(&self, paths: &[&Path]) -> Vec<std::io::Result<(PathBuf, String)>> {
+        self.hash_files_detailed(paths)
+            .into_iter()
+            .map(|result| result.hash.map(|hash| (result.path, hash)))
+            .collect()
+    }
+
+    /// Hash multiple files in parallel, with size and timing per file
+    ///
+    /// Small files are one task each and large files are split into
+    /// `chunk_size` ranges; all tasks share one pool so a huge file is spread
+    /// across every worker instead of occupying one. Results are in the
+    /// order of `paths`.
+    pub fn hash_files_detailed(&self, paths: &[&Path]) -> Vec<FileHashResult> {
+        let chunk_size = self.config.chunk_size.max(1) as u64;
+
+        let sizes: Vec<std::io::Result<u64>> = paths.iter()
+            .map(|path| std::fs::metadata(path).map(|m| m.len()))
+            .collect();
 
-        let elapsed_ms = start.elapsed().as_millis() as u64;
-        let chunks = if file_size >= self.config.parallel_threshold {
-            (file_size as usize + self.config.chunk_size - 1) / self.config.chunk_size
-        } else {
-            1
-        };
+        let mut tasks = Vec::new();
+        let mut maps: Vec<Option<memmap2::Mmap>> = Vec::with_capacity(paths.len());
+        for (file, size) in sizes.iter().enumerate() {
+            let mut map = None;
+            if let Ok(size) = *size {
+                if size >= self.config.parallel_threshold {
+                    let ranges = ((size + chunk_size - 1) / chunk_size).max(1);
+                    for range in 0..ranges {
+                        let offset = range * chunk_size;
+                        tasks.push(HashTask { file, offset, len: Some(chunk_size.min(size - offset)) });
+                    }
+                    if self.config.use_mmap {
+                        map = Self::map_file(paths[file]);
+                    }
+                } else {
+                    tasks.push(HashTask { file, offset: 0, len: None });
+                }
+            }
+            maps.push(map);
+        }
 
-        self.stats.record_hash(file_size, elapsed_ms, chunks as u64);
+        debug!("Hashing {} files as {} tasks on {} workers", paths.len(), tasks.len(), self.config.max_threads);
 
-        debug!(
-            "Hashed {} ({} bytes) in {}ms: {}",
-            path.display(),
-            file_size,
-            elapsed_ms,
-            &hash[..16]
-        );
+        let outcomes: Vec<(std::io::Result<[u8; 32]>, Instant, Instant)> = self.thread_pool.install(|| {
+            tasks
+                .par_iter()
+                .map(|task| {
+                    let start = Instant::now();
+                    let hash = match (&maps[task.file], task.len) {
+                        (Some(map), Some(len)) => map
+                            .get(task.offset as usize..(task.offset + len) as usize)
+                            .map(|range| self.hash_bytes(range))
+                            .ok_or_else(|| Self::shrank(paths[task.file])),
+                        _ => self.hash_range(paths[task.file], task.offset, task.len),
+                    };
+                    (hash, start, Instant::now())
+                })
+                .collect()
+        });
+
+        // Tasks are grouped by file, in file order
+        let mut outcomes = tasks.iter().zip(outcomes).peekable();
+        let mut results = Vec::with_capacity(paths.len());
+        for (file, (path, size)) in paths.iter().zip(sizes).enumerate() {
+            let size = match size {
+                Ok(size) => size,
+                Err(e) => {
+                    results.push(FileHashResult {
+                        path: path.to_path_buf(),
+                        hash: Err(e),
+                        size: 0,
+                        ranges: 0,
+                        elapsed: Duration::ZERO,
+                    });
+                    continue;
+                }
+            };
+
+            let mut range_hashes = Vec::new();
+            let mut error = None;
+            let (mut first_start, mut last_end) = (None::<Instant>, None::<Instant>);
+            while let Some((_, (hash, start, end))) = outcomes.next_if(|(task, _)| task.file == file) {
+                first_start = Some(first_start.map_or(start, |s| s.min(start)));
+                last_end = Some(last_end.map_or(end, |e| e.max(end)));
+                match hash {
+                    Ok(hash) => range_hashes.push(hash),
+                    Err(e) => error = error.or(Some(e)),
+                }
+            }
 
-        Ok(hash)
+            let ranges = range_hashes.len();
+            let elapsed = match (first_start, last_end) {
+                (Some(start), Some(end)) => end.duration_since(start),
+                _ => Duration::ZERO,
+            };
+            let hash = match error {
+                Some(e) => Err(e),
+                None => {
+                    self.stats.record_hash(size, elapsed.as_millis() as u64, ranges as u64);
+                    Ok(hex::encode(self.combine_chunk_hashes(&range_hashes)))
+                }
+            };
+
+            if let Ok(ref hash) = hash {
+                debug!("Hashed {} ({} bytes, {} ranges) in {:?}: {}", path.display(), size, ranges, elapsed, &hash[..16]);
+            }
+            results.push(FileHashResult { path: path.to_path_buf(), hash, size, ranges, elapsed });
+        }
+
+        results
     }
 
-    /// Hash a file sequentially
-    fn hash_file_sequential(&self, path: &Path) -> std::io::Result<String> {
+    /// Hash `len` bytes of a file from `offset`, or to the end of the file
+    fn hash_range(&self, path: &Path, offset: u64, len: Option<u64>) -> std::io::Result<[u8; 32]> {
         let mut file = std::fs::File::open(path)?;
-        let mut hasher = Sha256::new();
-        let mut buffer = vec![0u8; self.config.chunk_size];
+        file.seek(SeekFrom::Start(offset))?;
 
-        loop {
-            let bytes_read = file.read(&mut buffer)?;
+        let mut hasher = self.config.algorithm.hasher();
+        let mut buffer = vec![0u8; self.config.read_buffer_size.max(1)];
+        let mut remaining = len.unwrap_or(u64::MAX);
+
+        while remaining > 0 {
+            let want = buffer.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+            let bytes_read = file.read(&mut buffer[..want])?;
             if bytes_read == 0 {
                 break;
             }
             hasher.update(&buffer[..bytes_read]);
+            remaining -= bytes_read as u64;
         }
 
-        Ok(format!("{:x}", hasher.finalize()))
+        if len.is_some() && remaining > 0 {
+            return Err(Self::shrank(path));
+        }
+        Ok(hasher.finalize_bytes())
     }
 
-    /// Hash a file in parallel using chunk-based processing
-    fn hash_file_parallel(&self, path: &Path, file_size: u64) -> std::io::Result<String> {
-        let chunk_size = self.config.chunk_size;
-        let num_chunks = ((file_size as usize) + chunk_size - 1) / chunk_size;
-
-        debug!(
-            "Parallel hashing {} with {} chunks of {}MB each",
-            path.display(),
-            num_chunks,
-            chunk_size / 1024 / 1024
-        );
-
-        // Read file into memory for parallel processing
-        // For very large files, we could use memory mapping
-        let data = if self.config.use_mmap && file_size > 64 * 1024 * 1024 {
-            self.read_file_mmap(path)?
-        } else {
-            std::fs::read(path)?
-        };
-
-        // Create chunk ranges
-        let chunks: Vec<(usize, usize)> = (0..num_chunks)
-            .map(|i| {
-                let start = i * chunk_size;
-                let end = ((i + 1) * chunk_size).min(data.len());
-                (start, end)
-            })
-            .collect();
-
-        // Hash each chunk in parallel
-        let chunk_hashes: Vec<[u8; 32]> = self.thread_pool.install(|| {
-            chunks
-                .par_iter()
-                .map(|(start, end)| {
-                    let mut hasher = Sha256::new();
-                    hasher.update(&data[*start..*end]);
-                    let result = hasher.finalize();
-                    let mut arr = [0u8; 32];
-                    arr.copy_from_slice(&result);
-                    arr
-                })
-                .collect()
-        });
-
-        // Combine chunk hashes
-        let final_hash = self.combine_chunk_hashes(&chunk_hashes);
-
-        Ok(hex::encode(final_hash))
+    /// Raw digest of `data` in the configured algorithm
+    fn hash_bytes(&self, data: &[u8]) -> [u8; 32] {
+        let mut hasher = self.config.algorithm.hasher();
+        hasher.update(data);
+        hasher.finalize_bytes()
     }
 
-    /// Read file using memory mapping (Linux/Unix)
-    #[cfg(unix)]
-    fn read_file_mmap(&self, path: &Path) -> std::io::Result<Vec<u8>> {
-        use std::os::unix::fs::MetadataExt;
-
-        let file = std::fs::File::open(path)?;
-        let metadata = file.metadata()?;
-        let file_size = metadata.size() as usize;
-
-        // For simplicity, we just read the file
-        // In production, you'd use mmap crate for actual memory mapping
-        std::fs::read(path)
+    fn shrank(path: &Path) -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("{} shrank while being hashed", path.display()),
+        )
     }
 
-    /// Read file without memory mapping (fallback)
-    #[cfg(not(unix))]
-    fn read_file_mmap(&self, path: &Path) -> std::io::Result<Vec<u8>> {
-        std::fs::read(path)
+    /// Memory-map a file, falling back to reads if mapping fails
+    fn map_file(path: &Path) -> Option<memmap2::Mmap> {
+        let file = std::fs::File::open(path).ok()?;
+        // Safety: the map is only read, and a file modified while being
+        // hashed yields a wrong hash just as buffered reads would
+        unsafe { memmap2::Mmap::map(&file) }.ok()
     }
 
     /// Combine chunk hashes into final hash using Merkle-tree style combination
@@ -275,14 +390,7 @@ impl ParallelHasher {
         }
 
         // Merkle-tree style combination for consistent results
-        let mut combined = Sha256::new();
-        for hash in chunk_hashes {
-            combined.update(hash);
-        }
-        let result = combined.finalize();
-        let mut arr = [0u8; 32];
-        arr.copy_from_slice(&result);
-        arr
+        self.hash_bytes(&chunk_hashes.concat())
     }
 
     /// Hash data directly (for in-memory buffers)
@@ -292,9 +400,7 @@ impl ParallelHasher {
         let hash = if data.len() >= self.config.parallel_threshold as usize {
             self.hash_data_parallel(data)
         } else {
-            let mut hasher = Sha256::new();
-            hasher.update(data);
-            format!("{:x}", hasher.finalize())
+            self.config.algorithm.hash(data)
         };
 
         let elapsed_ms = start.elapsed().as_millis() as u64;
@@ -319,14 +425,7 @@ impl ParallelHasher {
         let chunk_hashes: Vec<[u8; 32]> = self.thread_pool.install(|| {
             chunks
                 .par_iter()
-                .map(|(start, end)| {
-                    let mut hasher = Sha256::new();
-                    hasher.update(&data[*start..*end]);
-                    let result = hasher.finalize();
-                    let mut arr = [0u8; 32];
-                    arr.copy_from_slice(&result);
-                    arr
-                })
+                .map(|(start, end)| self.hash_bytes(&data[*start..*end]))
                 .collect()
         });
 
@@ -334,19 +433,6 @@ impl ParallelHasher {
         hex::encode(final_hash)
     }
 
-    /// Hash multiple files in parallel
-    pub fn hash_files(&self, paths: &[&Path]) -> Vec<std::io::Result<(std::path::PathBuf, String)>> {
-        self.thread_pool.install(|| {
-            paths
-                .par_iter()
-                .map(|path| {
-                    self.hash_file(path)
-                        .map(|hash| (path.to_path_buf(), hash))
-                })
-                .collect()
-        })
-    }
-
     /// Get hashing statistics
     pub fn stats(&self) -> &Arc<HashingStats> {
         &self.stats
@@ -384,17 +470,17 @@ pub async fn hash_file_async(path: &Path, config: Option<ParallelHashConfig>) ->
     .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
 }
 
-/// Async wrapper for hashing multiple files
+/// Async wrapper for hashing multiple files, with timing per file
 pub async fn hash_files_async(
-    paths: Vec<std::path::PathBuf>,
+    paths: Vec<PathBuf>,
     config: Option<ParallelHashConfig>,
-) -> Vec<std::io::Result<(std::path::PathBuf, String)>> {
+) -> Vec<FileHashResult> {
     let config = config.unwrap_or_default();
 
     tokio::task::spawn_blocking(move || {
         let hasher = ParallelHasher::with_config(config);
         let path_refs: Vec<&Path> = paths.iter().map(|p| p.as_path()).collect();
-        hasher.hash_files(&path_refs)
+        hasher.hash_files_detailed(&path_refs)
     })
     .await
     .unwrap_or_else(|_| vec![])
@@ -559,6 +645,102 @@ mod tests {
         Ok(())
     }
 
+    /// Single-threaded reference for the hashing scheme
+    fn reference_hash(data: &[u8], config: &ParallelHashConfig) -> String {
+        if (data.len() as u64) < config.parallel_threshold {
+            return config.algorithm.hash(data);
+        }
+        let ranges: Vec<[u8; 32]> = data.chunks(config.chunk_size)
+            .map(|c| {
+                let mut hasher = config.algorithm.hasher();
+                hasher.update(c);
+                hasher.finalize_bytes()
+            })
+            .collect();
+        if ranges.len() == 1 {
+            return hex::encode(ranges[0]);
+        }
+        let mut combined = config.algorithm.hasher();
+        ranges.iter().for_each(|hash| combined.update(hash));
+        combined.finalize()
+    }
+
+    #[test]
+    fn test_large_file_ranges_match_reference() -> std::io::Result<()> {
+        let config = ParallelHashConfig {
+            chunk_size: 64 * 1024,
+            parallel_threshold: 128 * 1024,
+            ..Default::default()
+        };
+        let size = 10 * 64 * 1024 + 123;
+        let file = create_test_file(size)?;
+        let data = std::fs::read(file.path())?;
+
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let config = config.clone().with_algorithm(algorithm);
+            for use_mmap in [false, true] {
+                let hasher = ParallelHasher::with_config(config.clone().with_mmap(use_mmap).with_read_buffer_size(8192));
+                let result = hasher.hash_files_detailed(&[file.path()]).remove(0);
+                assert_eq!(result.hash?, reference_hash(&data, &config), "{}", algorithm);
+                assert_eq!(result.size, size as u64);
+                assert_eq!(result.ranges, 11);
+            }
+            assert_eq!(ParallelHasher::with_config(config.clone()).hash_data(&data), reference_hash(&data, &config));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_detailed_results_keep_order_and_report_errors() -> std::io::Result<()> {
+        let small = create_test_file(100)?;
+        let large = create_test_file(300 * 1024)?;
+        let missing = std::env::temp_dir().join("skylock-parallel-hash-missing");
+        let config = ParallelHashConfig { chunk_size: 64 * 1024, parallel_threshold: 128 * 1024, ..Default::default() };
+        let hasher = ParallelHasher::with_config(config);
+
+        let results = hasher.hash_files_detailed(&[large.path(), missing.as_path(), small.path()]);
+        assert_eq!(results.iter().map(|r| r.path.as_path()).collect::<Vec<_>>(),
+            vec![large.path(), missing.as_path(), small.path()]);
+        assert_eq!(results[0].ranges, 5);
+        assert_eq!(results[1].hash.as_ref().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(results[2].ranges, 1);
+        assert_eq!(results[2].hash.as_ref().unwrap(), &sha256_simple(&std::fs::read(small.path())?));
+        assert_eq!(hasher.stats().files_hashed.load(Ordering::Relaxed), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mixed_corpus_hashes_alike_with_1_and_8_workers() -> std::io::Result<()> {
+        let config = ParallelHashConfig {
+            chunk_size: 512 * 1024,
+            parallel_threshold: 1024 * 1024,
+            ..Default::default()
+        };
+
+        // Many small files and one large file that would starve a
+        // file-per-worker pool
+        let mut corpus = (0..32).map(|i| create_test_file(16 * 1024 + i * 997)).collect::<std::io::Result<Vec<_>>>()?;
+        corpus.push(create_test_file(12 * 1024 * 1024)?);
+        let paths: Vec<PathBuf> = corpus.iter().map(|f| f.path().to_path_buf()).collect();
+        let expected = paths.iter()
+            .map(|p| std::fs::read(p).map(|data| reference_hash(&data, &config)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        for workers in [1, 8] {
+            let results = hash_files_async(paths.clone(), Some(config.clone().with_workers(workers))).await;
+
+            // The large file is split into ranges the workers share, rather
+            // than being left to a single worker
+            let ranges: Vec<usize> = results.iter().map(|r| r.ranges).collect();
+            assert!(ranges[..32].iter().all(|&n| n == 1), "{} workers: {:?}", workers, ranges);
+            assert_eq!(ranges[32], 24, "{} workers", workers);
+
+            let hashes = results.into_iter().map(|r| r.hash).collect::<std::io::Result<Vec<_>>>()?;
+            assert_eq!(hashes, expected, "{} workers", workers);
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_throughput_calculation() {
         let stats = HashingStats::new();
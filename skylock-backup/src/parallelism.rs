@@ -15,6 +15,9 @@ use std::time::{Duration, Instant};
 use tokio::sync::{RwLock, Semaphore};
 use tracing::{debug, info, warn};
 
+use crate::error::{Result, SkylockError};
+use skylock_core::ByteSize;
+
 /// Minimum number of concurrent uploads
 const MIN_PARALLELISM: usize = 4;
 
@@ -36,6 +39,28 @@ const TARGET_BANDWIDTH_UTILIZATION: f64 = 0.85;
 /// Memory pressure threshold to scale down (0.0 - 1.0)
 const MEMORY_PRESSURE_THRESHOLD: f64 = 0.85;
 
+/// Highest concurrency accepted from `--concurrency` or
+/// `max_concurrent_uploads`; larger values are clamped to it
+pub const MAX_CONCURRENCY: usize = MAX_PARALLELISM;
+
+/// Concurrency used when none is configured: one transfer per core, at most 4
+pub fn default_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get().min(4))
+        .unwrap_or(2)
+}
+
+/// Check a configured concurrency, clamping it to [`MAX_CONCURRENCY`]
+pub fn clamp_concurrency(concurrency: usize) -> Result<usize> {
+    if concurrency == 0 {
+        return Err(SkylockError::Backup("Concurrency must be at least 1".to_string()));
+    }
+    if concurrency > MAX_CONCURRENCY {
+        warn!("Concurrency {} is above the maximum, using {}", concurrency, MAX_CONCURRENCY);
+    }
+    Ok(concurrency.min(MAX_CONCURRENCY))
+}
+
 /// Throughput metrics collected during uploads
 #[derive(Debug, Default)]
 pub struct ThroughputMetrics {
@@ -47,6 +72,8 @@ pub struct ThroughputMetrics {
     upload_errors: AtomicU64,
     /// Sum of upload latencies (ms) for averaging
     total_latency_ms: AtomicU64,
+    /// Bytes not uploaded because the remote copy already matched
+    bytes_skipped: AtomicU64,
 }
 
 impl ThroughputMetrics {
@@ -66,6 +93,21 @@ impl ThroughputMetrics {
         self.upload_errors.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Record an upload skipped because the data was already stored remotely
+    pub fn record_skip(&self, bytes: u64) {
+        self.bytes_skipped.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Get total bytes uploaded
+    pub fn bytes_uploaded(&self) -> u64 {
+        self.bytes_uploaded.load(Ordering::Relaxed)
+    }
+
+    /// Get total bytes skipped
+    pub fn bytes_skipped(&self) -> u64 {
+        self.bytes_skipped.load(Ordering::Relaxed)
+    }
+
     /// Get bytes per second throughput
     pub fn bytes_per_second(&self, elapsed_secs: f64) -> f64 {
         if elapsed_secs <= 0.0 {
@@ -100,6 +142,7 @@ impl ThroughputMetrics {
         self.uploads_completed.store(0, Ordering::Relaxed);
         self.upload_errors.store(0, Ordering::Relaxed);
         self.total_latency_ms.store(0, Ordering::Relaxed);
+        self.bytes_skipped.store(0, Ordering::Relaxed);
     }
 }
 
@@ -288,6 +331,17 @@ impl Default for ParallelismConfig {
 }
 
 impl ParallelismConfig {
+    /// Config holding parallelism at exactly `concurrency`, for a level the
+    /// user chose
+    pub fn fixed(concurrency: usize) -> Self {
+        Self {
+            min_parallelism: concurrency,
+            max_parallelism: concurrency,
+            initial_parallelism: concurrency,
+            ..Default::default()
+        }
+    }
+    
     /// Create config with known bandwidth limit
     pub fn with_bandwidth_limit(mut self, bytes_per_sec: u64) -> Self {
         self.known_bandwidth_limit = Some(bytes_per_sec);
@@ -335,9 +389,9 @@ impl ParallelismConfig {
         let initial = (max_parallelism / 2).max(MIN_PARALLELISM);
         
         info!(
-            "Auto-detected parallelism config: {} cores, {}GB available RAM -> max={}, initial={}",
+            "Auto-detected parallelism config: {} cores, {} available RAM -> max={}, initial={}",
             cores,
-            metrics.available_memory_bytes / 1024 / 1024 / 1024,
+            ByteSize(metrics.available_memory_bytes),
             max_parallelism,
             initial
         );
@@ -464,9 +518,9 @@ impl ParallelismController {
         let avg_latency = self.metrics.average_latency_ms();
         
         debug!(
-            "Parallelism adjustment: current={}, throughput={:.2}MB/s, errors={:.1}%, latency={:.0}ms, CPU={:.1}%, mem={:.1}%",
+            "Parallelism adjustment: current={}, throughput={}/s, errors={:.1}%, latency={:.0}ms, CPU={:.1}%, mem={:.1}%",
             current,
-            throughput / 1024.0 / 1024.0,
+            ByteSize(throughput as u64),
             error_rate * 100.0,
             avg_latency,
             system.cpu_utilization * 100.0,
@@ -483,10 +537,10 @@ impl ParallelismController {
         
         if new_parallelism != current {
             info!(
-                "Adjusting parallelism: {} -> {} (throughput: {:.2}MB/s, CPU: {:.1}%)",
+                "Adjusting parallelism: {} -> {} (throughput: {}/s, CPU: {:.1}%)",
                 current,
                 new_parallelism,
-                throughput / 1024.0 / 1024.0,
+                ByteSize(throughput as u64),
                 system.cpu_utilization * 100.0
             );
             
@@ -635,15 +689,19 @@ mod tests {
         metrics.record_upload(1024, 100);
         metrics.record_upload(2048, 200);
         metrics.record_error();
+        metrics.record_skip(4096);
         
-        // Check metrics
+        // Check metrics; skipped bytes don't count towards throughput
         assert_eq!(metrics.bytes_per_second(1.0), 3072.0);
+        assert_eq!(metrics.bytes_uploaded(), 3072);
+        assert_eq!(metrics.bytes_skipped(), 4096);
         assert_eq!(metrics.average_latency_ms(), 150.0);
         assert!((metrics.error_rate() - 0.333).abs() < 0.01);
         
         // Reset
         metrics.reset();
         assert_eq!(metrics.bytes_per_second(1.0), 0.0);
+        assert_eq!(metrics.bytes_skipped(), 0);
     }
 
     #[tokio::test]
@@ -668,6 +726,46 @@ mod tests {
         assert_eq!(controller.current_parallelism(), 8);
     }
 
+    #[test]
+    fn test_clamp_concurrency() {
+        assert!(clamp_concurrency(0).is_err());
+        assert_eq!(clamp_concurrency(1).unwrap(), 1);
+        assert_eq!(clamp_concurrency(12).unwrap(), 12);
+        assert_eq!(clamp_concurrency(10_000).unwrap(), MAX_CONCURRENCY);
+        assert!((1..=4).contains(&default_concurrency()));
+    }
+
+    #[tokio::test]
+    async fn test_fixed_config_runs_requested_workers() {
+        for requested in [1, 3, 12] {
+            let controller = ParallelismController::with_config(ParallelismConfig::fixed(requested));
+            assert_eq!(controller.current_parallelism(), requested);
+            
+            // Far more tasks than workers; count how many hold a permit at once
+            let semaphore = controller.semaphore().await;
+            let active = Arc::new(AtomicUsize::new(0));
+            let peak = Arc::new(AtomicUsize::new(0));
+            let tasks: Vec<_> = (0..requested * 4).map(|_| {
+                let (semaphore, active, peak) = (semaphore.clone(), active.clone(), peak.clone());
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await.unwrap();
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                })
+            }).collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_eq!(peak.load(Ordering::SeqCst), requested);
+            
+            // Adjustment can't move a fixed level
+            controller.force_adjust().await;
+            assert_eq!(controller.current_parallelism(), requested);
+        }
+    }
+
     #[test]
     fn test_calculate_new_parallelism_high_errors() {
         let config = ParallelismConfig::default();
@@ -0,0 +1,157 @@
+//! Path prefix remapping for restores
+//!
+//! A backup records absolute source paths such as `/home/alice/notes.txt`.
+//! Restoring onto a machine with a different layout rewrites those prefixes,
+//! e.g. `/home/alice=/home/bob`, before the path is placed below the
+//! restore target. When several mappings match, the longest prefix wins.
+
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::{Result, SkylockError};
+
+/// One `from=to` prefix rewrite
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathMapping {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+impl PathMapping {
+    /// Rewrite paths under the absolute path `from` to lie under `to`
+    pub fn new(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Self> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        if !from.is_absolute() {
+            return Err(SkylockError::Backup(format!(
+                "Path mapping source {} must be an absolute path",
+                from.display()
+            )));
+        }
+        if to.as_os_str().is_empty() {
+            return Err(SkylockError::Backup(format!("Path mapping for {} has no destination", from.display())));
+        }
+        Ok(Self {
+            from: normalize(from),
+            to: normalize(to),
+        })
+    }
+
+    pub fn from(&self) -> &Path {
+        &self.from
+    }
+
+    pub fn to(&self) -> &Path {
+        &self.to
+    }
+}
+
+impl FromStr for PathMapping {
+    type Err = SkylockError;
+
+    /// Parse `from=to`; the first `=` separates the two paths
+    fn from_str(s: &str) -> Result<Self> {
+        let (from, to) = s.split_once('=').ok_or_else(|| {
+            SkylockError::Backup(format!("Invalid path mapping '{}': expected FROM=TO", s))
+        })?;
+        Self::new(from, to)
+    }
+}
+
+/// Set of prefix rewrites applied to manifest paths during a restore
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathMap {
+    mappings: Vec<PathMapping>,
+}
+
+impl PathMap {
+    /// Combine mappings, rejecting two different destinations for the same
+    /// source prefix
+    pub fn new(mappings: Vec<PathMapping>) -> Result<Self> {
+        let mut map = Self::default();
+        for mapping in mappings {
+            match map.mappings.iter().find(|existing| existing.from == mapping.from) {
+                Some(existing) if existing.to != mapping.to => {
+                    return Err(SkylockError::Backup(format!(
+                        "Conflicting path mappings for {}: {} and {}",
+                        mapping.from.display(),
+                        existing.to.display(),
+                        mapping.to.display()
+                    )));
+                }
+                Some(_) => {}
+                None => map.mappings.push(mapping),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Parse `from=to` arguments as given on the command line
+    pub fn parse<S: AsRef<str>>(specs: &[S]) -> Result<Self> {
+        Self::new(specs.iter().map(|spec| spec.as_ref().parse()).collect::<Result<_>>()?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    pub fn mappings(&self) -> &[PathMapping] {
+        &self.mappings
+    }
+
+    /// `path` with the longest matching source prefix replaced by its
+    /// destination, or unchanged if no mapping matches
+    ///
+    /// Prefixes match whole components, so `/home/al` doesn't match
+    /// `/home/alice`. The result is not checked; callers place it below the
+    /// restore target with the usual traversal guard.
+    pub fn apply(&self, path: &Path) -> PathBuf {
+        let best = self.mappings.iter()
+            .filter_map(|mapping| path.strip_prefix(&mapping.from).ok().map(|rest| (mapping, rest)))
+            .max_by_key(|(mapping, _)| mapping.from.components().count());
+        match best {
+            Some((mapping, rest)) if rest.as_os_str().is_empty() => mapping.to.clone(),
+            Some((mapping, rest)) => mapping.to.join(rest),
+            None => path.to_path_buf(),
+        }
+    }
+}
+
+/// Drop `.` components and trailing separators; `..` is kept for the
+/// restore guard to reject
+fn normalize(path: &Path) -> PathBuf {
+    path.components().filter(|component| *component != Component::CurDir).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let map = PathMap::parse(&[
+            "/home/alice=/home/bob",
+            "/home/alice/work/=/srv/work",
+            "/etc=etc-backup",
+        ]).unwrap();
+
+        assert_eq!(map.apply(Path::new("/home/alice/notes.txt")), Path::new("/home/bob/notes.txt"));
+        assert_eq!(map.apply(Path::new("/home/alice/work/plan.md")), Path::new("/srv/work/plan.md"));
+        assert_eq!(map.apply(Path::new("/home/alice/work")), Path::new("/srv/work"));
+        assert_eq!(map.apply(Path::new("/etc/hosts")), Path::new("etc-backup/hosts"));
+        // Whole components only
+        assert_eq!(map.apply(Path::new("/home/alicia/x")), Path::new("/home/alicia/x"));
+        assert_eq!(map.apply(Path::new("/var/log/syslog")), Path::new("/var/log/syslog"));
+    }
+
+    #[test]
+    fn test_invalid_mappings_rejected() {
+        assert!("/home/alice".parse::<PathMapping>().is_err());
+        assert!("home/alice=/home/bob".parse::<PathMapping>().is_err());
+        assert!("/home/alice=".parse::<PathMapping>().is_err());
+        assert!(PathMap::parse(&["/home/alice=/home/bob", "/home/alice/=/home/carol"]).is_err());
+
+        // Repeating the same mapping is harmless
+        let map = PathMap::parse(&["/data=/mnt/data", "/data/./=/mnt/data"]).unwrap();
+        assert_eq!(map.mappings().len(), 1);
+    }
+}
@@ -0,0 +1,190 @@
+//! Free space preflight for restores
+//!
+//! Before a restore writes anything, the bytes each target filesystem will
+//! receive are added up and compared with its free space plus a margin, so
+//! a restore onto a nearly full disk is refused up front instead of failing
+//! halfway. Paths are grouped by the filesystem they will land on, which
+//! for path-remapped restores may differ from the one holding the target.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::{Result, SkylockError};
+
+/// Smallest headroom left on a filesystem after a restore
+pub const MIN_SPACE_MARGIN: u64 = 64 * 1024 * 1024;
+
+/// Free bytes on the filesystem holding an existing path
+pub type SpaceProbe = Arc<dyn Fn(&Path) -> io::Result<u64> + Send + Sync>;
+
+/// Probe reporting the space available to this user
+pub fn default_probe() -> SpaceProbe {
+    Arc::new(|path| fs2::available_space(path))
+}
+
+/// Headroom required on top of `required` bytes: a tenth of them, at least
+/// [`MIN_SPACE_MARGIN`]
+pub fn space_margin(required: u64) -> u64 {
+    (required / 10).max(MIN_SPACE_MARGIN)
+}
+
+/// Bytes a restore writes to one filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpaceRequirement {
+    /// Existing directory on the filesystem that was probed
+    pub probe_path: PathBuf,
+    /// Bytes restored onto the filesystem
+    pub required: u64,
+    /// Free bytes reported for it
+    pub available: u64,
+}
+
+impl SpaceRequirement {
+    /// Whether the restore fits with the margin to spare
+    pub fn is_sufficient(&self) -> bool {
+        self.available >= self.required.saturating_add(space_margin(self.required))
+    }
+}
+
+/// Nearest ancestor of `path` that exists (or `path` itself)
+fn existing_ancestor(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.exists())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Identity of the filesystem holding an existing path: the device on Unix,
+/// the path's root elsewhere
+#[cfg(unix)]
+fn filesystem_id(existing: &Path) -> io::Result<String> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(std::fs::metadata(existing)?.dev().to_string())
+}
+
+#[cfg(not(unix))]
+fn filesystem_id(existing: &Path) -> io::Result<String> {
+    let root = existing.canonicalize()?
+        .ancestors()
+        .last()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    Ok(root.to_string_lossy().into_owned())
+}
+
+/// Add up the bytes written below each filesystem and probe its free space
+///
+/// `targets` are the final restore paths with their sizes; they need not
+/// exist yet. Returns one requirement per filesystem, in the order they
+/// were first seen.
+pub fn space_requirements(
+    targets: impl IntoIterator<Item = (PathBuf, u64)>,
+    probe: &SpaceProbe,
+) -> Result<Vec<SpaceRequirement>> {
+    let mut requirements: Vec<SpaceRequirement> = Vec::new();
+    let mut by_filesystem: HashMap<String, usize> = HashMap::new();
+    // Most files share a parent with the one before, so resolve each
+    // directory only once
+    let mut by_parent: HashMap<PathBuf, usize> = HashMap::new();
+
+    for (target, size) in targets {
+        let parent = target.parent().map(Path::to_path_buf).unwrap_or_default();
+        let index = match by_parent.get(&parent) {
+            Some(&index) => index,
+            None => {
+                let existing = existing_ancestor(&parent);
+                let id = filesystem_id(&existing).map_err(|e| SkylockError::Backup(format!(
+                    "Failed to inspect restore target {}: {}", existing.display(), e
+                )))?;
+                let index = match by_filesystem.get(&id) {
+                    Some(&index) => index,
+                    None => {
+                        let available = probe(&existing).map_err(|e| SkylockError::Backup(format!(
+                            "Failed to read free space of {}: {}", existing.display(), e
+                        )))?;
+                        requirements.push(SpaceRequirement { probe_path: existing, required: 0, available });
+                        by_filesystem.insert(id, requirements.len() - 1);
+                        requirements.len() - 1
+                    }
+                };
+                by_parent.insert(parent, index);
+                index
+            }
+        };
+        requirements[index].required += size;
+    }
+
+    Ok(requirements)
+}
+
+/// Fail with [`SkylockError::InsufficientSpace`] if any filesystem lacks room
+/// for its share of the restore plus the margin
+pub fn check_space(
+    targets: impl IntoIterator<Item = (PathBuf, u64)>,
+    probe: &SpaceProbe,
+) -> Result<Vec<SpaceRequirement>> {
+    let requirements = space_requirements(targets, probe)?;
+    if let Some(short) = requirements.iter().find(|r| !r.is_sufficient()) {
+        return Err(SkylockError::InsufficientSpace(format!(
+            "restoring needs {} bytes (plus {} bytes headroom) on the filesystem holding {}, but only {} bytes are free",
+            short.required,
+            space_margin(short.required),
+            short.probe_path.display(),
+            short.available
+        )));
+    }
+    Ok(requirements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    fn fixed_probe(available: u64, probed: Arc<Mutex<Vec<PathBuf>>>) -> SpaceProbe {
+        Arc::new(move |path| {
+            probed.lock().unwrap().push(path.to_path_buf());
+            Ok(available)
+        })
+    }
+
+    #[test]
+    fn test_requirements_grouped_per_filesystem() {
+        let target = TempDir::new().unwrap();
+        let probed = Arc::new(Mutex::new(Vec::new()));
+        let probe = fixed_probe(u64::MAX, probed.clone());
+
+        // Files below directories that don't exist yet are attributed to the
+        // filesystem of their nearest existing ancestor
+        let targets = vec![
+            (target.path().join("a/b/one.bin"), 100),
+            (target.path().join("a/b/two.bin"), 200),
+            (target.path().join("c/three.bin"), 300),
+        ];
+        let requirements = space_requirements(targets, &probe).unwrap();
+        assert_eq!(requirements.len(), 1);
+        assert_eq!(requirements[0].required, 600);
+        assert_eq!(requirements[0].probe_path, target.path());
+        assert_eq!(probed.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_margin_required() {
+        let target = TempDir::new().unwrap();
+        let probed = Arc::new(Mutex::new(Vec::new()));
+        let required = 10 * MIN_SPACE_MARGIN;
+        let targets = || vec![(target.path().join("big.bin"), required)];
+
+        // Exactly the data fits but not the headroom
+        let err = check_space(targets(), &fixed_probe(required, probed.clone())).unwrap_err();
+        assert!(matches!(err, SkylockError::InsufficientSpace(_)), "unexpected error: {}", err);
+        assert!(err.to_string().contains(&target.path().display().to_string()));
+
+        let enough = required + space_margin(required);
+        assert_eq!(space_margin(required), MIN_SPACE_MARGIN);
+        check_space(targets(), &fixed_probe(enough, probed)).unwrap();
+    }
+}
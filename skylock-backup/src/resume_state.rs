@@ -1,16 +1,23 @@
 //! Resume state management for interrupted uploads
 //!
 //! Tracks which files have been successfully uploaded so we can resume
-//! from where we left off if the backup is interrupted.
+//! from where we left off if the backup is interrupted. State files live in
+//! `<data_dir>/resume_state/<backup_id>.json` and are checkpointed
+//! periodically while uploading.
 
 use std::path::{Path, PathBuf};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
 
+use crate::direct_upload::FileEntry;
 use crate::error::{Result, SkylockError};
+use crate::state_file;
+
+/// Minimum time between resume state checkpoints during an upload
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
 
 /// State file for tracking upload progress
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,11 +34,19 @@ pub struct ResumeState {
     /// Files that have been successfully uploaded (local paths)
     pub uploaded_files: HashSet<PathBuf>,
     
+    /// Manifest entries of uploaded files, keyed by local path
+    #[serde(default)]
+    pub entries: HashMap<PathBuf, FileEntry>,
+    
     /// Total number of files to upload
     pub total_files: usize,
     
     /// Last updated timestamp
     pub last_updated: DateTime<Utc>,
+    
+    /// When the state was last written to disk (not persisted)
+    #[serde(skip)]
+    last_saved: Option<Instant>,
 }
 
 impl ResumeState {
@@ -42,28 +57,66 @@ impl ResumeState {
             started_at: Utc::now(),
             source_paths,
             uploaded_files: HashSet::new(),
+            entries: HashMap::new(),
             total_files,
             last_updated: Utc::now(),
+            last_saved: None,
         }
     }
     
+    /// Directory holding resume state files below the configured data dir
+    pub fn state_dir(data_dir: &Path) -> PathBuf {
+        data_dir.join("resume_state")
+    }
+    
     /// Get the state file path for a backup ID
-    pub fn state_file_path(backup_id: &str) -> PathBuf {
-        let data_dir = directories::ProjectDirs::from("com", "skylock", "skylock-hybrid")
-            .map(|dirs| dirs.data_dir().to_path_buf())
-            .unwrap_or_else(|| PathBuf::from("."));
-        
-        data_dir.join("resume_state").join(format!("{}.json", backup_id))
+    pub fn state_file_path(state_dir: &Path, backup_id: &str) -> PathBuf {
+        state_dir.join(format!("{}.json", backup_id))
     }
     
     /// Check if a state file exists for the given backup ID
-    pub async fn exists(backup_id: &str) -> bool {
-        Self::state_file_path(backup_id).exists()
+    pub async fn exists(state_dir: &Path, backup_id: &str) -> bool {
+        Self::state_file_path(state_dir, backup_id).exists()
+    }
+    
+    /// Find the most recently updated interrupted backup, if any
+    ///
+    /// Unreadable state files are skipped.
+    pub async fn find_latest(state_dir: &Path) -> Result<Option<Self>> {
+        if !state_dir.exists() {
+            return Ok(None);
+        }
+        
+        let mut entries = fs::read_dir(state_dir).await
+            .map_err(|e| SkylockError::Backup(format!("Failed to read state directory: {}", e)))?;
+        
+        let mut latest: Option<Self> = None;
+        while let Some(entry) = entries.next_entry().await
+            .map_err(|e| SkylockError::Backup(format!("Failed to read directory entry: {}", e)))? {
+            
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            
+            let Ok(json) = fs::read_to_string(&path).await else { continue };
+            let Ok(state) = serde_json::from_str::<ResumeState>(&json) else { continue };
+            
+            let is_newer = match &latest {
+                Some(current) => state.last_updated > current.last_updated,
+                None => true,
+            };
+            if is_newer {
+                latest = Some(state);
+            }
+        }
+        
+        Ok(latest)
     }
     
     /// Load resume state from disk
-    pub async fn load(backup_id: &str) -> Result<Self> {
-        let path = Self::state_file_path(backup_id);
+    pub async fn load(state_dir: &Path, backup_id: &str) -> Result<Self> {
+        let path = Self::state_file_path(state_dir, backup_id);
         
         if !path.exists() {
             return Err(SkylockError::Backup(format!(
@@ -82,55 +135,70 @@ impl ResumeState {
     }
     
     /// Save resume state to disk
-    pub async fn save(&self) -> Result<()> {
-        let path = Self::state_file_path(&self.backup_id);
-        
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).await
-                .map_err(|e| SkylockError::Backup(format!("Failed to create state directory: {}", e)))?;
-        }
-        
-        let json = serde_json::to_string_pretty(self)
-            .map_err(|e| SkylockError::Backup(format!("Failed to serialize state: {}", e)))?;
-        
-        // Write atomically using a temp file
-        let temp_path = path.with_extension("json.tmp");
-        
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&temp_path)
-            .await
-            .map_err(|e| SkylockError::Backup(format!("Failed to create temp state file: {}", e)))?;
-        
-        file.write_all(json.as_bytes()).await
-            .map_err(|e| SkylockError::Backup(format!("Failed to write state: {}", e)))?;
-        
-        file.sync_all().await
-            .map_err(|e| SkylockError::Backup(format!("Failed to sync state file: {}", e)))?;
-        
-        drop(file);
-        
-        // Atomic rename
-        fs::rename(&temp_path, &path).await
-            .map_err(|e| SkylockError::Backup(format!("Failed to rename state file: {}", e)))?;
-        
+    pub async fn save(&mut self, state_dir: &Path) -> Result<()> {
+        state_file::save(&Self::state_file_path(state_dir, &self.backup_id), self, "resume state").await?;
+        self.last_saved = Some(Instant::now());
         Ok(())
     }
     
+    /// Save the state if at least `interval` has passed since the last save
+    ///
+    /// Returns whether the state was written.
+    pub async fn checkpoint(&mut self, state_dir: &Path, interval: Duration) -> Result<bool> {
+        if self.last_saved.is_some_and(|saved| saved.elapsed() < interval) {
+            return Ok(false);
+        }
+        self.save(state_dir).await?;
+        Ok(true)
+    }
+    
     /// Mark a file as uploaded
     pub fn mark_uploaded(&mut self, file_path: PathBuf) {
         self.uploaded_files.insert(file_path);
         self.last_updated = Utc::now();
     }
     
+    /// Record an uploaded file together with its manifest entry
+    pub fn record_upload(&mut self, entry: FileEntry) {
+        self.mark_uploaded(entry.local_path.clone());
+        self.entries.insert(entry.local_path.clone(), entry);
+    }
+    
     /// Check if a file has been uploaded
     pub fn is_uploaded(&self, file_path: &Path) -> bool {
         self.uploaded_files.contains(file_path)
     }
     
+    /// Manifest entry of an uploaded file that is still current
+    ///
+    /// Returns `None` if the file was not uploaded, or if its size changed or
+    /// it was modified after the upload, in which case it must be re-uploaded.
+    pub fn current_entry(&self, file_path: &Path, size: u64) -> Option<&FileEntry> {
+        let entry = self.entries.get(file_path)?;
+        if entry.size != size {
+            return None;
+        }
+        
+        let modified: DateTime<Utc> = std::fs::metadata(file_path).ok()?.modified().ok()?.into();
+        (modified <= entry.timestamp).then_some(entry)
+    }
+    
+    /// Whether this state was recorded for the same set of source paths
+    pub fn matches_sources(&self, source_paths: &[PathBuf]) -> bool {
+        let recorded: HashSet<&PathBuf> = self.source_paths.iter().collect();
+        let current: HashSet<&PathBuf> = source_paths.iter().collect();
+        recorded == current
+    }
+    
+    /// Drop uploads that have no manifest entry (state written by older
+    /// versions), so those files are uploaded again
+    pub fn discard_untracked(&mut self) -> usize {
+        let before = self.uploaded_files.len();
+        let entries = &self.entries;
+        self.uploaded_files.retain(|path| entries.contains_key(path));
+        before - self.uploaded_files.len()
+    }
+    
     /// Get the number of files uploaded
     pub fn uploaded_count(&self) -> usize {
         self.uploaded_files.len()
@@ -145,23 +213,12 @@ impl ResumeState {
     }
     
     /// Delete the resume state file
-    pub async fn delete(backup_id: &str) -> Result<()> {
-        let path = Self::state_file_path(backup_id);
-        
-        if path.exists() {
-            fs::remove_file(&path).await
-                .map_err(|e| SkylockError::Backup(format!("Failed to delete resume state: {}", e)))?;
-        }
-        
-        Ok(())
+    pub async fn delete(state_dir: &Path, backup_id: &str) -> Result<()> {
+        state_file::remove(&Self::state_file_path(state_dir, backup_id), "resume state").await
     }
     
-    /// Clean up old resume state files (older than 7 days)
-    pub async fn cleanup_old_states(days: u64) -> Result<usize> {
-        let state_dir = directories::ProjectDirs::from("com", "skylock", "skylock-hybrid")
-            .map(|dirs| dirs.data_dir().join("resume_state"))
-            .unwrap_or_else(|| PathBuf::from("./resume_state"));
-        
+    /// Clean up resume state files not updated in the last `days` days
+    pub async fn cleanup_old_states(state_dir: &Path, days: u64) -> Result<usize> {
         if !state_dir.exists() {
             return Ok(0);
         }
@@ -169,7 +226,7 @@ impl ResumeState {
         let cutoff = Utc::now() - chrono::Duration::days(days as i64);
         let mut cleaned = 0;
         
-        let mut entries = fs::read_dir(&state_dir).await
+        let mut entries = fs::read_dir(state_dir).await
             .map_err(|e| SkylockError::Backup(format!("Failed to read state directory: {}", e)))?;
         
         while let Some(entry) = entries.next_entry().await
@@ -201,9 +258,33 @@ impl ResumeState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
+    
+    fn entry(path: &Path, size: u64) -> FileEntry {
+        FileEntry {
+            local_path: path.to_path_buf(),
+            remote_path: format!("/skylock/backups/test{}.enc", path.display()),
+            size,
+            hash: String::new(),
+            compressed: false,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        }
+    }
     
     #[tokio::test]
     async fn test_resume_state_save_load() {
+        let temp = TempDir::new().unwrap();
+        let state_dir = ResumeState::state_dir(temp.path());
         let backup_id = "test_20251107_123456".to_string();
         let source_paths = vec![PathBuf::from("/test/path1"), PathBuf::from("/test/path2")];
         
@@ -212,10 +293,11 @@ mod tests {
         state.mark_uploaded(PathBuf::from("/test/path1/file2.txt"));
         
         // Save
-        state.save().await.unwrap();
+        state.save(&state_dir).await.unwrap();
+        assert!(ResumeState::exists(&state_dir, &backup_id).await);
         
         // Load
-        let loaded = ResumeState::load(&backup_id).await.unwrap();
+        let loaded = ResumeState::load(&state_dir, &backup_id).await.unwrap();
         
         assert_eq!(loaded.backup_id, backup_id);
         assert_eq!(loaded.source_paths, source_paths);
@@ -224,7 +306,56 @@ mod tests {
         assert!(!loaded.is_uploaded(&PathBuf::from("/test/path1/file3.txt")));
         
         // Cleanup
-        ResumeState::delete(&backup_id).await.unwrap();
+        ResumeState::delete(&state_dir, &backup_id).await.unwrap();
+        assert!(!ResumeState::exists(&state_dir, &backup_id).await);
+    }
+    
+    #[tokio::test]
+    async fn test_find_latest_and_checkpoint() {
+        let temp = TempDir::new().unwrap();
+        let state_dir = ResumeState::state_dir(temp.path());
+        assert!(ResumeState::find_latest(&state_dir).await.unwrap().is_none());
+        
+        let mut older = ResumeState::new("20250101_000000".to_string(), vec![], 1);
+        older.last_updated = Utc::now() - chrono::Duration::hours(1);
+        older.save(&state_dir).await.unwrap();
+        
+        let mut newer = ResumeState::new("20250102_000000".to_string(), vec![], 1);
+        // First checkpoint always writes, the next one is throttled
+        assert!(newer.checkpoint(&state_dir, CHECKPOINT_INTERVAL).await.unwrap());
+        assert!(!newer.checkpoint(&state_dir, CHECKPOINT_INTERVAL).await.unwrap());
+        assert!(newer.checkpoint(&state_dir, Duration::ZERO).await.unwrap());
+        
+        let latest = ResumeState::find_latest(&state_dir).await.unwrap().unwrap();
+        assert_eq!(latest.backup_id, "20250102_000000");
+    }
+    
+    #[test]
+    fn test_source_and_entry_validation() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.path().join("a.txt");
+        std::fs::write(&file, b"hello").unwrap();
+        
+        let sources = vec![PathBuf::from("/data/a"), PathBuf::from("/data/b")];
+        let mut state = ResumeState::new("test".to_string(), sources.clone(), 2);
+        assert!(state.matches_sources(&[PathBuf::from("/data/b"), PathBuf::from("/data/a")]));
+        assert!(!state.matches_sources(&[PathBuf::from("/data/a")]));
+        
+        state.record_upload(entry(&file, 5));
+        assert!(state.is_uploaded(&file));
+        assert!(state.current_entry(&file, 5).is_some());
+        // Size changed since the upload
+        assert!(state.current_entry(&file, 6).is_none());
+        
+        // Uploads recorded without an entry are discarded
+        state.mark_uploaded(PathBuf::from("/legacy/file"));
+        assert_eq!(state.discard_untracked(), 1);
+        assert_eq!(state.uploaded_count(), 1);
+        
+        // Entries survive a round-trip through the state file format
+        let json = serde_json::to_string(&state).unwrap();
+        let loaded: ResumeState = serde_json::from_str(&json).unwrap();
+        assert!(loaded.entries.contains_key(&file));
     }
     
     #[test]
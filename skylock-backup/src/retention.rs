@@ -1,9 +1,11 @@
-use std::collections::HashMap;
-use chrono::{DateTime, Utc, Duration, Datelike, Timelike, IsoWeek};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use chrono::{DateTime, Utc, Duration, Datelike, Timelike, IsoWeek, NaiveDate};
 use serde::{Serialize, Deserialize};
 
 use crate::error::{Result, SkylockError};
-use crate::direct_upload::BackupManifest;
+use crate::direct_upload::{BackupManifest, KEEP_TAG};
+use crate::BackupMetadata;
 
 /// Retention policy configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +42,51 @@ pub struct GfsPolicy {
     pub keep_yearly: Option<usize>,
 }
 
+/// Reason a backup was retained by GFS selection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepReason {
+    /// Newest backup of this hour
+    Hourly(NaiveDate, u32),
+    /// Newest backup of this day
+    Daily(NaiveDate),
+    /// Newest backup of this ISO week
+    Weekly(IsoWeek),
+    /// Newest backup of this month (year, month)
+    Monthly(i32, u32),
+    /// Newest backup of this year
+    Yearly(i32),
+    /// Tagged `keep`, exempt from rotation
+    Tagged,
+}
+
+impl fmt::Display for KeepReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeepReason::Hourly(date, hour) => write!(f, "hourly {} {:02}:00", date, hour),
+            KeepReason::Daily(date) => write!(f, "daily {}", date),
+            KeepReason::Weekly(week) => write!(f, "weekly {}-W{:02}", week.year(), week.week()),
+            KeepReason::Monthly(year, month) => write!(f, "monthly {}-{:02}", year, month),
+            KeepReason::Yearly(year) => write!(f, "yearly {}", year),
+            KeepReason::Tagged => write!(f, "tagged {}", KEEP_TAG),
+        }
+    }
+}
+
+/// GFS verdict for a single backup
+#[derive(Debug, Clone)]
+pub struct GfsDecision {
+    pub backup_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Every GFS bucket this backup was kept for (empty = delete)
+    pub reasons: Vec<KeepReason>,
+}
+
+impl GfsDecision {
+    pub fn is_kept(&self) -> bool {
+        !self.reasons.is_empty()
+    }
+}
+
 impl Default for RetentionPolicy {
     fn default() -> Self {
         Self {
@@ -51,14 +98,79 @@ impl Default for RetentionPolicy {
     }
 }
 
+/// Days a backup removed by retention stays recoverable in the trash
+pub const DEFAULT_TRASH_DAYS: u32 = 14;
+
+/// A soft-deleted backup waiting in the trash
+///
+/// Its manifest is moved out of the backups directory, so it is no longer
+/// listed or restorable, while its blobs stay in place until `expires_at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub backup_id: String,
+    pub deleted_at: DateTime<Utc>,
+    /// When the backup is deleted for good
+    pub expires_at: DateTime<Utc>,
+}
+
+impl TrashEntry {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires_at
+    }
+}
+
+/// Blobs freed by deleting some backups, from [`RetentionManager::estimate_reclaim`]
+///
+/// Sizes are of the blobs' content before compression and encryption, as
+/// recorded in the manifests.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReclaimEstimate {
+    /// Blobs no remaining backup references
+    pub blobs: usize,
+    pub bytes: u64,
+    /// Blobs of the deleted backups that remaining backups still reference
+    pub shared_blobs: usize,
+    pub shared_bytes: u64,
+}
+
 /// Retention manager for backup lifecycle management
 pub struct RetentionManager {
     policy: RetentionPolicy,
+    /// Time deleted backups spend in the trash (zero = deleted immediately)
+    trash_ttl: Duration,
 }
 
 impl RetentionManager {
     pub fn new(policy: RetentionPolicy) -> Self {
-        Self { policy }
+        Self { policy, trash_ttl: Duration::days(DEFAULT_TRASH_DAYS as i64) }
+    }
+    
+    /// Keep deleted backups in the trash for `days` before purging them
+    /// (0 deletes them immediately)
+    pub fn with_trash_days(mut self, days: u32) -> Self {
+        self.trash_ttl = Duration::days(days as i64);
+        self
+    }
+    
+    /// Whether deletions go through the trash rather than being immediate
+    pub fn uses_trash(&self) -> bool {
+        self.trash_ttl > Duration::zero()
+    }
+    
+    /// Trash entry for `backup_id`, deleted at `now`
+    pub fn trash_entry(&self, backup_id: &str, now: DateTime<Utc>) -> TrashEntry {
+        TrashEntry {
+            backup_id: backup_id.to_string(),
+            deleted_at: now,
+            expires_at: now + self.trash_ttl,
+        }
+    }
+    
+    /// Backups in the trash whose time is up, oldest deletion first
+    pub fn expired_trash(entries: &[TrashEntry], now: DateTime<Utc>) -> Vec<String> {
+        let mut expired: Vec<&TrashEntry> = entries.iter().filter(|e| e.is_expired(now)).collect();
+        expired.sort_by_key(|e| e.deleted_at);
+        expired.into_iter().map(|e| e.backup_id.clone()).collect()
     }
     
     /// Analyze backups and determine which should be deleted
@@ -74,8 +186,12 @@ impl RetentionManager {
         let mut to_delete = Vec::new();
         let mut to_keep: Vec<&BackupManifest> = Vec::new();
         
-        // Apply retention rules
+        // Apply retention rules. Backups tagged `keep` are never deleted and
+        // don't count towards keep_last.
         for manifest in &manifests_sorted {
+            if manifest.has_tag(KEEP_TAG) {
+                continue;
+            }
             let should_keep = self.should_keep_backup(manifest, &to_keep);
             
             if should_keep {
@@ -88,7 +204,79 @@ impl RetentionManager {
             }
         }
         
-        to_delete
+        Self::protect_chains(manifests, to_delete)
+    }
+    
+    /// Remove backups from `to_delete` that a kept incremental backup builds on
+    ///
+    /// An incremental backup only holds the files changed since its base, so
+    /// its whole chain has to stay until it is consolidated into a synthetic
+    /// full backup.
+    pub fn protect_chains(manifests: &[BackupManifest], to_delete: Vec<String>) -> Vec<String> {
+        let by_id: HashMap<&str, &BackupManifest> = manifests.iter()
+            .map(|m| (m.backup_id.as_str(), m))
+            .collect();
+        let deleting: HashSet<&str> = to_delete.iter().map(String::as_str).collect();
+        
+        let mut needed = HashSet::new();
+        for manifest in manifests.iter().filter(|m| !deleting.contains(m.backup_id.as_str())) {
+            let mut base = manifest.base_backup_id.as_deref();
+            while let Some(id) = base {
+                if !needed.insert(id.to_string()) {
+                    break;
+                }
+                base = by_id.get(id).and_then(|m| m.base_backup_id.as_deref());
+            }
+        }
+        
+        to_delete.into_iter().filter(|id| !needed.contains(id)).collect()
+    }
+    
+    /// Blobs freed by deleting the backups in `to_delete` from `manifests`
+    ///
+    /// Backups share blobs through moved files and unchanged chunks, so a
+    /// blob of a deleted backup is only freed when none of the others in
+    /// `manifests` references it. Pass trashed backups along, since they
+    /// keep their blobs until purged. Each blob is counted once.
+    pub fn estimate_reclaim<'a>(
+        manifests: impl IntoIterator<Item = &'a BackupManifest>,
+        to_delete: &[String],
+    ) -> ReclaimEstimate {
+        let deleting: HashSet<&str> = to_delete.iter().map(String::as_str).collect();
+        let mut deleted_blobs: HashMap<&str, u64> = HashMap::new();
+        let mut retained_blobs: HashSet<&str> = HashSet::new();
+        for manifest in manifests {
+            let deleted = deleting.contains(manifest.backup_id.as_str());
+            for (path, size) in Self::blob_refs(manifest) {
+                if deleted {
+                    deleted_blobs.insert(path, size);
+                } else {
+                    retained_blobs.insert(path);
+                }
+            }
+        }
+        
+        let mut estimate = ReclaimEstimate::default();
+        for (path, size) in deleted_blobs {
+            if retained_blobs.contains(path) {
+                estimate.shared_blobs += 1;
+                estimate.shared_bytes += size;
+            } else {
+                estimate.blobs += 1;
+                estimate.bytes += size;
+            }
+        }
+        estimate
+    }
+    
+    /// Remote path and content size of every blob a backup references
+    fn blob_refs(manifest: &BackupManifest) -> impl Iterator<Item = (&str, u64)> {
+        manifest.files.iter().flat_map(|entry| {
+            let whole = (!entry.is_chunked() && !entry.remote_path.is_empty())
+                .then_some((entry.remote_path.as_str(), entry.size));
+            let chunks = entry.chunks.iter().map(|chunk| (chunk.remote_path.as_str(), chunk.size));
+            whole.into_iter().chain(chunks)
+        })
     }
     
     /// Check if a backup should be kept based on retention policy
@@ -219,6 +407,90 @@ impl RetentionManager {
         false
     }
     
+    /// Select backups to delete under a GFS policy
+    ///
+    /// Keeps the newest backup in each of the most recent N hours/days/weeks/
+    /// months/years that contain a backup. Buckets are counted relative to the
+    /// backups themselves, not the wall clock, so a gap in backups never
+    /// causes the whole history to expire. Returns the IDs to delete,
+    /// newest first.
+    pub fn select_for_deletion(backups: &[BackupMetadata], gfs: &GfsPolicy) -> Vec<String> {
+        Self::plan_gfs(backups, gfs)
+            .into_iter()
+            .filter(|d| !d.is_kept())
+            .map(|d| d.backup_id)
+            .collect()
+    }
+
+    /// Compute the GFS verdict for every backup, newest first
+    ///
+    /// Backups tagged `keep` are always kept and take no bucket slot.
+    pub fn plan_gfs(backups: &[BackupMetadata], gfs: &GfsPolicy) -> Vec<GfsDecision> {
+        let (tagged, rotated): (Vec<&BackupMetadata>, Vec<&BackupMetadata>) = backups.iter()
+            .partition(|b| b.has_tag(KEEP_TAG));
+        let mut decisions: Vec<GfsDecision> = rotated.into_iter()
+            .map(|b| GfsDecision {
+                backup_id: b.id.clone(),
+                timestamp: b.timestamp,
+                reasons: Vec::new(),
+            })
+            .collect();
+        decisions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+
+        Self::keep_per_bucket(&mut decisions, gfs.keep_hourly, |t| {
+            KeepReason::Hourly(t.date_naive(), t.hour())
+        });
+        Self::keep_per_bucket(&mut decisions, gfs.keep_daily, |t| {
+            KeepReason::Daily(t.date_naive())
+        });
+        Self::keep_per_bucket(&mut decisions, gfs.keep_weekly, |t| {
+            KeepReason::Weekly(t.iso_week())
+        });
+        Self::keep_per_bucket(&mut decisions, gfs.keep_monthly, |t| {
+            KeepReason::Monthly(t.year(), t.month())
+        });
+        Self::keep_per_bucket(&mut decisions, gfs.keep_yearly, |t| {
+            KeepReason::Yearly(t.year())
+        });
+
+        decisions.extend(tagged.into_iter().map(|b| GfsDecision {
+            backup_id: b.id.clone(),
+            timestamp: b.timestamp,
+            reasons: vec![KeepReason::Tagged],
+        }));
+        decisions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        decisions
+    }
+
+    /// Mark the newest backup of each of the first `count` distinct buckets
+    ///
+    /// `decisions` must be sorted newest first.
+    fn keep_per_bucket<F>(decisions: &mut [GfsDecision], count: Option<usize>, bucket: F)
+    where
+        F: Fn(&DateTime<Utc>) -> KeepReason,
+    {
+        let count = match count {
+            Some(c) if c > 0 => c,
+            _ => return,
+        };
+
+        let mut last_bucket: Option<KeepReason> = None;
+        let mut kept = 0;
+
+        for decision in decisions.iter_mut() {
+            let current = bucket(&decision.timestamp);
+            if last_bucket.as_ref() == Some(&current) {
+                continue;
+            }
+            last_bucket = Some(current.clone());
+            decision.reasons.push(current);
+            kept += 1;
+            if kept >= count {
+                break;
+            }
+        }
+    }
+    
     /// Generate retention policy summary
     pub fn summarize(&self) -> String {
         let mut summary = Vec::new();
@@ -255,6 +527,10 @@ impl RetentionManager {
         
         summary.push(format!("Minimum keep: {} backups", self.policy.minimum_keep));
         
+        if self.uses_trash() {
+            summary.push(format!("Trash: {} days", self.trash_ttl.num_days()));
+        }
+        
         summary.join(" | ")
     }
 }
@@ -262,10 +538,13 @@ impl RetentionManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compression_integrity::CompressionMetadata;
+    use crate::direct_upload::{ChunkEntry, FileEntry};
     use std::path::PathBuf;
     
     fn create_test_manifest(backup_id: &str, days_ago: i64) -> BackupManifest {
         BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
             backup_id: backup_id.to_string(),
             timestamp: Utc::now() - Duration::days(days_ago),
             files: vec![],
@@ -275,9 +554,19 @@ mod tests {
             base_backup_id: None,
             encryption_version: "v2".to_string(),
             kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
             signature: None,
             backup_chain_version: 0,
             encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
         }
     }
     
@@ -327,6 +616,169 @@ mod tests {
         assert_eq!(to_delete.len(), 0);  // Should keep all due to minimum_keep
     }
     
+    #[test]
+    fn test_bases_of_kept_incrementals_are_protected() {
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            keep_days: None,
+            gfs: None,
+            minimum_keep: 1,
+        };
+        let manager = RetentionManager::new(policy);
+        
+        // full1 <- inc1 <- inc2 (kept), and an unrelated old full backup
+        let mut inc2 = create_test_manifest("inc2", 1);
+        inc2.base_backup_id = Some("inc1".to_string());
+        let mut inc1 = create_test_manifest("inc1", 2);
+        inc1.base_backup_id = Some("full1".to_string());
+        let manifests = vec![
+            inc2,
+            inc1,
+            create_test_manifest("full1", 3),
+            create_test_manifest("full0", 4),
+        ];
+        
+        let to_delete = manager.calculate_deletions(&manifests);
+        assert_eq!(to_delete, vec!["full0".to_string()]);
+    }
+    
+    fn daily_backups(days: i64) -> Vec<BackupMetadata> {
+        use chrono::TimeZone;
+        // Daily backups at 02:00 ending on Sunday 2024-12-29
+        let last = Utc.with_ymd_and_hms(2024, 12, 29, 2, 0, 0).unwrap();
+        (0..days)
+            .map(|i| {
+                let timestamp = last - Duration::days(i);
+                BackupMetadata {
+                    id: timestamp.format("%Y%m%d_%H%M%S").to_string(),
+                    timestamp,
+                    source_paths: vec![PathBuf::from("/test")],
+                    size: 1000,
+                    is_vss: false,
+                    storage_tier: Default::default(),
+                    tags: Vec::new(),
+                    note: None,
+                }
+            })
+            .collect()
+    }
+
+    fn gfs(daily: usize, weekly: usize, monthly: usize, yearly: usize) -> GfsPolicy {
+        GfsPolicy {
+            keep_hourly: None,
+            keep_daily: Some(daily),
+            keep_weekly: Some(weekly),
+            keep_monthly: Some(monthly),
+            keep_yearly: Some(yearly),
+        }
+    }
+
+    #[test]
+    fn test_gfs_selection_over_a_year() {
+        let backups = daily_backups(366);
+
+        // (policy, expected kept IDs)
+        let cases: Vec<(GfsPolicy, Vec<&str>)> = vec![
+            (gfs(0, 0, 0, 0), vec![]),
+            (gfs(3, 0, 0, 0), vec!["20241229_020000", "20241228_020000", "20241227_020000"]),
+            // Weeks end on Sunday, so the Sundays are the newest of each week
+            (gfs(0, 3, 0, 0), vec!["20241229_020000", "20241222_020000", "20241215_020000"]),
+            // Last day of each month is its newest backup
+            (gfs(0, 0, 3, 0), vec!["20241229_020000", "20241130_020000", "20241031_020000"]),
+            // 2023 only has Dec 29-31 in range, the newest is the 31st
+            (gfs(0, 0, 0, 5), vec!["20241229_020000", "20231231_020000"]),
+            (gfs(7, 4, 12, 1), vec![
+                "20241229_020000", "20241228_020000", "20241227_020000", "20241226_020000",
+                "20241225_020000", "20241224_020000", "20241223_020000",
+                "20241222_020000", "20241215_020000", "20241208_020000",
+                "20241130_020000", "20241031_020000", "20240930_020000", "20240831_020000",
+                "20240731_020000", "20240630_020000", "20240531_020000", "20240430_020000",
+                "20240331_020000", "20240229_020000", "20240131_020000",
+            ]),
+        ];
+
+        for (policy, expected) in cases {
+            let to_delete = RetentionManager::select_for_deletion(&backups, &policy);
+            let mut kept: Vec<String> = backups.iter()
+                .map(|b| b.id.clone())
+                .filter(|id| !to_delete.contains(id))
+                .collect();
+            kept.sort_by(|a, b| b.cmp(a));
+
+            assert_eq!(kept, expected, "policy {:?}", policy);
+            assert_eq!(kept.len() + to_delete.len(), backups.len());
+        }
+    }
+
+    #[test]
+    fn test_gfs_reasons() {
+        let backups = daily_backups(366);
+        let plan = RetentionManager::plan_gfs(&backups, &gfs(7, 4, 12, 1));
+
+        // Newest backup satisfies every bucket
+        assert_eq!(plan[0].backup_id, "20241229_020000");
+        assert_eq!(plan[0].reasons.len(), 4);
+
+        let nov = plan.iter().find(|d| d.backup_id == "20241130_020000").unwrap();
+        assert_eq!(nov.reasons, vec![KeepReason::Monthly(2024, 11)]);
+        assert_eq!(nov.reasons[0].to_string(), "monthly 2024-11");
+
+        let deleted = plan.iter().find(|d| d.backup_id == "20241129_020000").unwrap();
+        assert!(!deleted.is_kept());
+    }
+
+    #[test]
+    fn test_gfs_ignores_gaps() {
+        // A long gap must not expire every older backup
+        let mut backups = daily_backups(3);
+        backups.iter_mut().for_each(|b| b.timestamp = b.timestamp - Duration::days(400));
+        let to_delete = RetentionManager::select_for_deletion(&backups, &gfs(2, 0, 0, 0));
+        assert_eq!(to_delete.len(), 1);
+    }
+
+    #[test]
+    fn test_keep_tagged_backups_are_never_deleted() {
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            keep_days: None,
+            gfs: None,
+            minimum_keep: 1,
+        };
+        let manager = RetentionManager::new(policy);
+
+        let mut pinned = create_test_manifest("backup4", 4);
+        pinned.tags = vec!["pre-upgrade".to_string(), KEEP_TAG.to_string()];
+        let manifests = vec![
+            create_test_manifest("backup1", 1),
+            create_test_manifest("backup2", 2),
+            create_test_manifest("backup3", 3),
+            pinned,
+            create_test_manifest("backup5", 5),
+        ];
+
+        // The pinned backup doesn't use up one of the two keep_last slots
+        let mut to_delete = manager.calculate_deletions(&manifests);
+        to_delete.sort();
+        assert_eq!(to_delete, vec!["backup3".to_string(), "backup5".to_string()]);
+    }
+
+    #[test]
+    fn test_gfs_keeps_tagged_backups() {
+        let mut backups = daily_backups(10);
+        backups[8].tags = vec![KEEP_TAG.to_string()];
+
+        let plan = RetentionManager::plan_gfs(&backups, &gfs(2, 0, 0, 0));
+        let kept: Vec<&str> = plan.iter()
+            .filter(|d| d.is_kept())
+            .map(|d| d.backup_id.as_str())
+            .collect();
+        assert_eq!(kept, vec!["20241229_020000", "20241228_020000", "20241221_020000"]);
+
+        let pinned = plan.iter().find(|d| d.backup_id == "20241221_020000").unwrap();
+        assert_eq!(pinned.reasons, vec![KeepReason::Tagged]);
+        assert_eq!(pinned.reasons[0].to_string(), "tagged keep");
+    }
+
     #[test]
     fn test_keep_days() {
         let policy = RetentionPolicy {
@@ -348,4 +800,100 @@ mod tests {
         let to_delete = manager.calculate_deletions(&manifests);
         assert_eq!(to_delete.len(), 2);  // Should delete backups older than 7 days
     }
+
+    #[test]
+    fn test_trash_entries_expire_after_ttl() {
+        let manager = RetentionManager::new(RetentionPolicy::default()).with_trash_days(7);
+        let now = Utc::now();
+        let entries = vec![
+            manager.trash_entry("20240110_020000", now - Duration::days(8)),
+            manager.trash_entry("20240101_020000", now - Duration::days(9)),
+            manager.trash_entry("20240120_020000", now - Duration::days(2)),
+        ];
+
+        assert!(!entries[2].is_expired(now));
+        assert_eq!(
+            RetentionManager::expired_trash(&entries, now),
+            vec!["20240101_020000".to_string(), "20240110_020000".to_string()]
+        );
+        assert!(RetentionManager::expired_trash(&entries, now - Duration::days(3)).is_empty());
+
+        let immediate = RetentionManager::new(RetentionPolicy::default()).with_trash_days(0);
+        assert!(!immediate.uses_trash());
+        assert!(immediate.trash_entry("20240101_020000", now).is_expired(now));
+    }
+
+    fn blob_entry(remote_path: &str, size: u64) -> FileEntry {
+        FileEntry {
+            local_path: PathBuf::from(format!("/test/{}", size)),
+            remote_path: remote_path.to_string(),
+            size,
+            hash: String::new(),
+            compressed: false,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        }
+    }
+
+    fn chunked_entry(chunks: &[(&str, u64)]) -> FileEntry {
+        let chunks = chunks.iter().map(|(remote_path, size)| ChunkEntry {
+            hash: String::new(),
+            size: *size,
+            remote_path: remote_path.to_string(),
+            backup_id: String::new(),
+            compression: CompressionMetadata::uncompressed(b""),
+            key_context: None,
+        }).collect();
+        FileEntry { remote_path: String::new(), chunks, ..blob_entry("", 0) }
+    }
+
+    fn manifest_with(backup_id: &str, files: Vec<FileEntry>) -> BackupManifest {
+        BackupManifest { files, ..create_test_manifest(backup_id, 0) }
+    }
+
+    #[test]
+    fn test_reclaim_excludes_blobs_kept_backups_share() {
+        // b reuses a's moved file and one of its chunks; c shares nothing
+        let manifests = vec![
+            manifest_with("a", vec![
+                blob_entry("/a/moved", 100),
+                blob_entry("/a/only", 40),
+                chunked_entry(&[("/a/chunk1", 1000), ("/a/chunk2", 2000)]),
+            ]),
+            manifest_with("b", vec![
+                blob_entry("/a/moved", 100),
+                chunked_entry(&[("/a/chunk1", 1000), ("/b/chunk3", 500)]),
+            ]),
+            manifest_with("c", vec![blob_entry("/c/file", 7)]),
+        ];
+
+        let estimate = RetentionManager::estimate_reclaim(&manifests, &["a".to_string()]);
+        assert_eq!(estimate, ReclaimEstimate { blobs: 2, bytes: 2040, shared_blobs: 2, shared_bytes: 1100 });
+
+        // Deleting both frees the shared blobs, each counted once
+        let estimate = RetentionManager::estimate_reclaim(&manifests, &["a".to_string(), "b".to_string()]);
+        assert_eq!(estimate, ReclaimEstimate { blobs: 5, bytes: 3640, shared_blobs: 0, shared_bytes: 0 });
+
+        // Nothing deleted, nothing freed
+        assert_eq!(RetentionManager::estimate_reclaim(&manifests, &[]), ReclaimEstimate::default());
+    }
+
+    #[test]
+    fn test_reclaim_counts_trashed_backups_as_retained() {
+        let live = vec![manifest_with("old", vec![blob_entry("/old/file", 10), blob_entry("/old/moved", 20)])];
+        let trashed = manifest_with("trashed", vec![blob_entry("/old/moved", 20)]);
+
+        let estimate = RetentionManager::estimate_reclaim(live.iter().chain([&trashed]), &["old".to_string()]);
+        assert_eq!((estimate.blobs, estimate.bytes), (1, 10));
+        assert_eq!((estimate.shared_blobs, estimate.shared_bytes), (1, 20));
+    }
 }
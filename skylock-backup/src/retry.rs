@@ -0,0 +1,277 @@
+//! Retries of failed transfers, within a budget for the whole operation
+//!
+//! A failed upload or download is retried up to `backup.retry.max_retries`
+//! times with exponential backoff. Over a backup of many files those
+//! per-call retries can add up to far more than anyone expects, so every
+//! attempt made while a backup or restore runs also draws on that
+//! operation's [`RetryBudget`]: once its total attempts or its deadline are
+//! spent, the operation gives up even if its transfers could still be
+//! retried, saying after how many attempts and how long.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use skylock_core::{RetryBudget, RetryPolicy};
+
+use crate::error::{Result, SkylockError};
+
+/// Delay before the first retry when `base_delay_ms` is unset
+const DEFAULT_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay between two attempts of one call
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Backup operation, see [`Retrier::begin_operation`]
+pub const BACKUP_OPERATION: &str = "backup";
+
+/// Restore operation, see [`Retrier::begin_operation`]
+pub const RESTORE_OPERATION: &str = "restore";
+
+/// What an operation in progress has spent of its budget
+#[derive(Debug)]
+struct OperationBudget {
+    budget: RetryBudget,
+    started: Instant,
+    attempts: AtomicUsize,
+}
+
+impl OperationBudget {
+    fn new(budget: RetryBudget) -> Self {
+        Self { budget, started: Instant::now(), attempts: AtomicUsize::new(0) }
+    }
+
+    /// Time left before the deadline, if there is one
+    fn remaining(&self) -> Option<Duration> {
+        self.budget.deadline_minutes
+            .map(|minutes| Duration::from_secs(minutes * 60).saturating_sub(self.started.elapsed()))
+    }
+
+    /// Take one attempt from the budget, if any are left before the deadline
+    fn take_attempt(&self) -> bool {
+        if self.remaining().is_some_and(|remaining| remaining.is_zero()) {
+            return false;
+        }
+        self.attempts
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |attempts| {
+                self.below_max(attempts).then_some(attempts + 1)
+            })
+            .is_ok()
+    }
+
+    /// Whether another attempt, started after `delay`, is still allowed
+    fn allows(&self, delay: Duration) -> bool {
+        self.below_max(self.attempts.load(Ordering::SeqCst))
+            && self.remaining().filter(|remaining| *remaining <= delay).is_none()
+    }
+
+    /// Whether `attempts` leaves room for another one
+    fn below_max(&self, attempts: usize) -> bool {
+        self.budget.max_total_attempts.filter(|max| attempts >= *max).is_none()
+    }
+
+    /// "3 attempts / 45 minutes", for failure messages
+    fn spent(&self) -> String {
+        let attempts = self.attempts.load(Ordering::SeqCst);
+        format!(
+            "{} attempt{} / {}",
+            attempts,
+            if attempts == 1 { "" } else { "s" },
+            format_elapsed(self.started.elapsed())
+        )
+    }
+
+    /// The error ending an operation whose budget is spent
+    fn exhausted(&self, call: &str, last_error: Option<&str>) -> SkylockError {
+        let spent = self.spent();
+        tracing::error!("{} gave up after {}", call, spent);
+        SkylockError::RetryBudgetExhausted(match last_error {
+            Some(last_error) => format!("{} gave up after {}: {}", call, spent, last_error),
+            None => format!("{} gave up after {}", call, spent),
+        })
+    }
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    match seconds {
+        0 => format!("{} ms", elapsed.as_millis()),
+        1..=119 => format!("{} seconds", seconds),
+        120..=7199 => format!("{} minutes", seconds / 60),
+        _ => format!("{:.1} hours", seconds as f64 / 3600.0),
+    }
+}
+
+/// Whether retrying could help: a missing file or a wrong key stays that way
+fn is_retryable(error: &SkylockError) -> bool {
+    matches!(error, SkylockError::Io(_) | SkylockError::Core(_)) && !error.is_not_found()
+}
+
+/// Retries transfers under a [`RetryPolicy`], tracking the budgets of the
+/// operations in progress
+#[derive(Debug, Default)]
+pub struct Retrier {
+    policy: RetryPolicy,
+    operations: Mutex<HashMap<String, Arc<OperationBudget>>>,
+}
+
+/// Ends the operation started by [`Retrier::begin_operation`] when dropped
+#[must_use = "the operation ends as soon as the guard is dropped"]
+pub struct OperationGuard {
+    retrier: Arc<Retrier>,
+    operation: String,
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        self.retrier.operations.lock().unwrap().remove(&self.operation);
+    }
+}
+
+impl Retrier {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy, operations: Mutex::new(HashMap::new()) }
+    }
+
+    /// Start a high-level operation such as [`BACKUP_OPERATION`]: until the
+    /// guard is dropped, every attempt of every call [`Self::execute`]s for
+    /// it draws on the policy's budget
+    pub fn begin_operation(self: &Arc<Self>, operation: &str) -> OperationGuard {
+        self.operations.lock().unwrap()
+            .insert(operation.to_string(), Arc::new(OperationBudget::new(self.policy.budget.clone())));
+        OperationGuard { retrier: self.clone(), operation: operation.to_string() }
+    }
+
+    /// Delay before retry number `retry` (from 0)
+    fn delay(&self, retry: u32) -> Duration {
+        let base = self.policy.base_delay_ms.map_or(DEFAULT_BASE_DELAY, Duration::from_millis);
+        base.saturating_mul(1 << retry.min(16)).min(MAX_DELAY)
+    }
+
+    /// Run `call`, made by `operation`, retrying failures that may be
+    /// transient
+    ///
+    /// Within an operation started by [`Self::begin_operation`], gives up
+    /// once the operation's budget is spent, with an error saying after how
+    /// many attempts and how long.
+    pub async fn execute<T, F, Fut>(&self, operation: &str, call: &str, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let budget = self.operations.lock().unwrap().get(operation).cloned();
+        let mut retries = 0;
+        loop {
+            let result = match &budget {
+                Some(budget) => {
+                    if !budget.take_attempt() {
+                        return Err(budget.exhausted(call, None));
+                    }
+                    match budget.remaining() {
+                        // A call that hangs must not outlast the deadline
+                        Some(remaining) => match tokio::time::timeout(remaining, attempt()).await {
+                            Ok(result) => result,
+                            Err(_) => return Err(budget.exhausted(call, Some("timed out"))),
+                        },
+                        None => attempt().await,
+                    }
+                }
+                None => attempt().await,
+            };
+
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(e) if retries >= self.policy.max_retries || !is_retryable(&e) => return Err(e),
+                Err(e) => e,
+            };
+            let delay = self.delay(retries);
+            if let Some(budget) = budget.as_ref().filter(|budget| !budget.allows(delay)) {
+                return Err(budget.exhausted(call, Some(&error.to_string())));
+            }
+            tracing::warn!("{} failed (retry {} of {} in {:?}): {}", call, retries + 1, self.policy.max_retries, delay, error);
+            tokio::time::sleep(delay).await;
+            retries += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A policy that would retry a transient failure practically forever
+    fn persistent_policy(budget: RetryBudget) -> RetryPolicy {
+        RetryPolicy { max_retries: 1000, base_delay_ms: Some(10), budget }
+    }
+
+    async fn always_failing(calls: &AtomicUsize) -> Result<()> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(std::io::Error::other("server busy").into())
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_caps_total_attempts_across_calls() {
+        let retrier = Arc::new(Retrier::new(persistent_policy(RetryBudget {
+            max_total_attempts: Some(5),
+            deadline_minutes: None,
+        })));
+        let calls = AtomicUsize::new(0);
+
+        let operation = retrier.begin_operation(BACKUP_OPERATION);
+        let first = retrier.execute(BACKUP_OPERATION, "upload chunk", || always_failing(&calls)).await;
+        let second = retrier.execute(BACKUP_OPERATION, "upload manifest", || always_failing(&calls)).await;
+        drop(operation);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+        let first = first.unwrap_err().to_string();
+        assert!(first.contains("upload chunk gave up after 5 attempts /"), "{}", first);
+        assert!(first.contains("server busy"), "{}", first);
+        // The budget was already spent, so the second call is never tried
+        let second = second.unwrap_err().to_string();
+        assert!(second.contains("upload manifest gave up after 5 attempts"), "{}", second);
+
+        // Outside the operation only the per-call limit applies
+        let retrier = Arc::new(Retrier::new(RetryPolicy { max_retries: 2, base_delay_ms: Some(1), ..Default::default() }));
+        let calls = AtomicUsize::new(0);
+        assert!(retrier.execute(RESTORE_OPERATION, "download", || always_failing(&calls)).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_budget_deadline_stops_retrying() {
+        let retrier = Arc::new(Retrier::new(persistent_policy(RetryBudget {
+            max_total_attempts: None,
+            deadline_minutes: Some(1),
+        })));
+        let calls = AtomicUsize::new(0);
+
+        let _operation = retrier.begin_operation(RESTORE_OPERATION);
+        let err = retrier.execute(RESTORE_OPERATION, "download", || always_failing(&calls)).await.unwrap_err();
+        assert!(err.to_string().contains("download gave up after"), "{}", err);
+        // Backoff doubles from 10 ms up to the one-minute deadline
+        let calls = calls.load(Ordering::SeqCst);
+        assert!((5..20).contains(&calls), "{} calls", calls);
+
+        // A call that hangs is cut off at the deadline too
+        let retrier = Arc::new(Retrier::new(persistent_policy(RetryBudget {
+            max_total_attempts: None,
+            deadline_minutes: Some(1),
+        })));
+        let _operation = retrier.begin_operation(RESTORE_OPERATION);
+        let err = retrier.execute(RESTORE_OPERATION, "download", std::future::pending::<Result<()>>).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_errors_are_not_retried() {
+        let retrier = Retrier::new(persistent_policy(RetryBudget::default()));
+        let calls = AtomicUsize::new(0);
+        let err = retrier.execute(BACKUP_OPERATION, "upload", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(SkylockError::WrongKey("different key".to_string()))
+        }).await.unwrap_err();
+        assert!(matches!(err, SkylockError::WrongKey(_)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
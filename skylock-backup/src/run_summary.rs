@@ -0,0 +1,106 @@
+//! Figures of a completed backup run, for monitoring
+//!
+//! Each backup writes `summary.json` next to its manifest: how long it took,
+//! what was uploaded or deduplicated, and how well it compressed. It holds
+//! no file names and is stored unencrypted, so monitoring tools can fetch it
+//! without the encryption key or the full manifest.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::compression_config::CompressionStats;
+use crate::direct_upload::BackupManifest;
+use crate::parallelism::ThroughputMetrics;
+
+/// Name of the summary file in a backup's directory
+pub const RUN_SUMMARY_FILE: &str = "summary.json";
+
+/// Aggregate figures of one backup run, as stored in `summary.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupRunSummary {
+    /// Backup the run created
+    pub backup_id: String,
+    /// Whether the backup only holds files changed since its base backup
+    pub incremental: bool,
+    /// When the run started
+    pub started_at: DateTime<Utc>,
+    /// When the manifest was written
+    pub completed_at: DateTime<Utc>,
+    /// Wall-clock duration of the run in seconds
+    pub duration_secs: f64,
+    /// Files recorded in the backup
+    pub file_count: usize,
+    /// Original size of those files in bytes
+    pub total_size: u64,
+    /// Bytes sent to storage, after compression and encryption
+    pub bytes_uploaded: u64,
+    /// Bytes not sent because earlier backups already stored the same data
+    pub bytes_deduplicated: u64,
+    /// Size of the backed-up files after compression
+    pub compressed_size: u64,
+    /// Original size divided by compressed size (1.0 = no savings)
+    pub compression_ratio: f64,
+    /// Upload throughput over the whole run, in bytes per second
+    pub throughput_bytes_per_sec: f64,
+    /// Files left out because they were locked or unreadable
+    pub error_count: usize,
+}
+
+impl BackupRunSummary {
+    /// Summary of the run that wrote `manifest`, given the upload metrics
+    /// before it started (`bytes_uploaded` and `bytes_skipped` baselines,
+    /// since the metrics are shared across runs)
+    pub fn new(
+        manifest: &BackupManifest,
+        started_at: DateTime<Utc>,
+        duration: Duration,
+        metrics: &ThroughputMetrics,
+        baseline: (u64, u64),
+    ) -> Self {
+        let (uploaded_before, skipped_before) = baseline;
+        let bytes_uploaded = metrics.bytes_uploaded().saturating_sub(uploaded_before);
+        let compression = Self::compression(manifest);
+        let duration_secs = duration.as_secs_f64();
+
+        Self {
+            backup_id: manifest.backup_id.clone(),
+            incremental: manifest.base_backup_id.is_some(),
+            started_at,
+            completed_at: manifest.timestamp,
+            duration_secs,
+            file_count: manifest.file_count,
+            total_size: manifest.total_size,
+            bytes_uploaded,
+            bytes_deduplicated: metrics.bytes_skipped().saturating_sub(skipped_before),
+            compressed_size: compression.compressed_size,
+            compression_ratio: compression.ratio,
+            throughput_bytes_per_sec: if duration_secs > 0.0 { bytes_uploaded as f64 / duration_secs } else { 0.0 },
+            error_count: manifest.skipped_files.len(),
+        }
+    }
+
+    /// Remote path of the summary of `backup_id`
+    pub fn remote_path(backup_id: &str) -> PathBuf {
+        PathBuf::from(format!("/skylock/backups/{}/{}", backup_id, RUN_SUMMARY_FILE))
+    }
+
+    /// Original and compressed size over every file and chunk in the backup
+    fn compression(manifest: &BackupManifest) -> CompressionStats {
+        let mut original = 0;
+        let mut compressed = 0;
+        for entry in &manifest.files {
+            if entry.is_chunked() {
+                for chunk in &entry.chunks {
+                    original += chunk.size;
+                    compressed += chunk.compression.compressed_size();
+                }
+            } else {
+                original += entry.size;
+                compressed += entry.compression.as_ref().map_or(entry.size, |c| c.compressed_size());
+            }
+        }
+        CompressionStats::new(original, compressed, 0)
+    }
+}
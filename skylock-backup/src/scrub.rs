@@ -0,0 +1,392 @@
+//! Background integrity scrub
+//!
+//! Like a ZFS scrub, the daemon periodically reads back a slice of the
+//! stored blobs to catch bit rot long before a restore needs them. Each run
+//! (an epoch) checks only the files whose keyed hash falls into that epoch's
+//! slot, so a run stays light while every file is checked once per pass of
+//! `cycles` runs. The grouping is reshuffled for each pass, and a sampled
+//! failure escalates to a full verification of the backup it belongs to.
+//!
+//! The seed and epoch counter live in `<data_dir>/scrub_state.json`, so the
+//! rotation continues across daemon restarts.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::direct_upload::{BackupManifest, FileEntry};
+use crate::encryption::EncryptionManager;
+use crate::error::Result;
+use crate::state_file;
+use crate::verification::{BackupVerifier, VerificationResult};
+
+/// Slot in `0..cycles` a file is checked in during pass `round`
+pub fn scrub_slot(seed: u64, round: u64, entry: &FileEntry, cycles: u32) -> u32 {
+    let cycles = cycles.max(1);
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_le_bytes());
+    hasher.update(round.to_le_bytes());
+    hasher.update(entry.remote_path.as_bytes());
+    for chunk in &entry.chunks {
+        hasher.update(chunk.remote_path.as_bytes());
+    }
+    let digest = hasher.finalize();
+    let value = u64::from_le_bytes(digest[..8].try_into().expect("digest is 32 bytes"));
+    (value % cycles as u64) as u32
+}
+
+/// Whether the scrub run `epoch` checks `entry`
+pub fn in_sample(seed: u64, epoch: u64, entry: &FileEntry, cycles: u32) -> bool {
+    let cycles = cycles.max(1);
+    let round = epoch / cycles as u64;
+    let slot = (epoch % cycles as u64) as u32;
+    scrub_slot(seed, round, entry, cycles) == slot
+}
+
+/// Copy of `manifest` holding only the files the run `epoch` checks
+pub fn sample_manifest(manifest: &BackupManifest, seed: u64, epoch: u64, cycles: u32) -> BackupManifest {
+    let files: Vec<FileEntry> = manifest.files.iter()
+        .filter(|entry| in_sample(seed, epoch, entry, cycles))
+        .cloned()
+        .collect();
+    BackupManifest {
+        file_count: files.len(),
+        total_size: files.iter().map(|f| f.size).sum(),
+        files,
+        ..manifest.clone()
+    }
+}
+
+/// Seed and position of the scrub rotation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubState {
+    /// Keys the sampling, so installations check blobs in different orders
+    pub seed: u64,
+    /// Next run to perform
+    pub epoch: u64,
+    /// When the last run finished
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+impl ScrubState {
+    /// Fresh state with a random seed
+    pub fn new() -> Self {
+        Self {
+            seed: rand::random(),
+            epoch: 0,
+            last_run: None,
+        }
+    }
+
+    /// State file below the configured data dir
+    pub fn state_path(data_dir: &Path) -> PathBuf {
+        data_dir.join("scrub_state.json")
+    }
+
+    /// Saved state, or a fresh one if there is none or it can't be read
+    pub async fn load_or_new(data_dir: &Path) -> Self {
+        state_file::load(&Self::state_path(data_dir)).await.unwrap_or_default()
+    }
+
+    /// Save the state to disk
+    pub async fn save(&self, data_dir: &Path) -> Result<()> {
+        state_file::save(&Self::state_path(data_dir), self, "scrub state").await
+    }
+
+    /// Record a finished run and move on to the next epoch
+    pub fn advance(&mut self, finished: DateTime<Utc>) {
+        self.epoch += 1;
+        self.last_run = Some(finished);
+    }
+}
+
+impl Default for ScrubState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A backup whose sample failed, with the result of its full verification
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScrubFinding {
+    pub backup_id: String,
+    /// Sampled files that failed
+    pub sampled_failures: usize,
+    /// Full verification run after the sample failed
+    pub full: VerificationResult,
+}
+
+/// Outcome of one scrub run
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScrubReport {
+    pub epoch: u64,
+    /// Backups with at least one sampled file
+    pub backups_sampled: usize,
+    pub files_sampled: usize,
+    pub files_total: usize,
+    /// Backups that failed the sample
+    pub findings: Vec<ScrubFinding>,
+    /// The run was cancelled before every backup was checked
+    pub incomplete: bool,
+}
+
+impl ScrubReport {
+    /// Whether every sampled file was intact
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// Runs scrub epochs with a [`BackupVerifier`]
+pub struct Scrubber {
+    verifier: BackupVerifier,
+    seed: u64,
+    cycles: u32,
+}
+
+impl Scrubber {
+    /// Scrub with `verifier`, sampling keyed by `seed` so every file is
+    /// checked once every `cycles` runs
+    pub fn new(verifier: BackupVerifier, seed: u64, cycles: u32) -> Self {
+        Self {
+            verifier,
+            seed,
+            cycles: cycles.max(1),
+        }
+    }
+
+    /// Check the sample of run `epoch` across `manifests`, fully verifying
+    /// each backup whose sample fails
+    pub async fn run(
+        &self,
+        manifests: &[BackupManifest],
+        encryption: Arc<EncryptionManager>,
+        epoch: u64,
+    ) -> Result<ScrubReport> {
+        let mut report = ScrubReport {
+            epoch,
+            backups_sampled: 0,
+            files_sampled: 0,
+            files_total: manifests.iter().map(|m| m.files.len()).sum(),
+            findings: Vec::new(),
+            incomplete: false,
+        };
+
+        for manifest in manifests {
+            let sample = sample_manifest(manifest, self.seed, epoch, self.cycles);
+            if sample.files.is_empty() {
+                continue;
+            }
+
+            let result = self.verifier.verify_full(&sample, encryption.clone()).await?;
+            report.backups_sampled += 1;
+            report.files_sampled += result.file_results.len();
+            if result.incomplete {
+                report.incomplete = true;
+                break;
+            }
+            if result.is_success() {
+                continue;
+            }
+
+            tracing::warn!(
+                "Scrub found {} damaged file(s) in backup {}, verifying it fully",
+                result.files_with_errors, manifest.backup_id
+            );
+            let full = self.verifier.verify_full(manifest, encryption.clone()).await?;
+            report.incomplete |= full.incomplete;
+            report.findings.push(ScrubFinding {
+                backup_id: manifest.backup_id.clone(),
+                sampled_failures: result.files_with_errors,
+                full,
+            });
+            if report.incomplete {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skylock_core::storage::MultiBackend;
+    use skylock_hetzner::test_server;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    fn entry(name: &str) -> FileEntry {
+        FileEntry {
+            local_path: PathBuf::from(format!("/data/{}", name)),
+            remote_path: format!("/skylock/backups/scrub/{}.enc", name),
+            size: 10,
+            hash: String::new(),
+            compressed: false,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        }
+    }
+
+    fn manifest(names: &[String]) -> BackupManifest {
+        let files: Vec<FileEntry> = names.iter().map(|name| entry(name)).collect();
+        BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
+            backup_id: "scrub".to_string(),
+            timestamp: Utc::now(),
+            file_count: files.len(),
+            total_size: files.iter().map(|f| f.size).sum(),
+            files,
+            source_paths: vec![],
+            base_backup_id: None,
+            encryption_version: "v2".to_string(),
+            kdf_params: Some(Default::default()),
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
+            signature: None,
+            backup_chain_version: 0,
+            encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
+        }
+    }
+
+    fn names(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("file{}", i)).collect()
+    }
+
+    fn sampled(manifest: &BackupManifest, seed: u64, epoch: u64, cycles: u32) -> HashSet<PathBuf> {
+        sample_manifest(manifest, seed, epoch, cycles).files.into_iter().map(|f| f.local_path).collect()
+    }
+
+    #[test]
+    fn test_sample_rotation() {
+        let cycles = 5;
+        let manifest = manifest(&names(200));
+
+        // The same seed and epoch always pick the same files
+        assert_eq!(sampled(&manifest, 42, 3, cycles), sampled(&manifest, 42, 3, cycles));
+
+        // Each pass checks every file exactly once
+        for round in 0..3u64 {
+            let mut seen = HashSet::new();
+            for slot in 0..cycles as u64 {
+                let sample = sampled(&manifest, 42, round * cycles as u64 + slot, cycles);
+                assert!(!sample.is_empty(), "empty sample in round {} slot {}", round, slot);
+                for path in sample {
+                    assert!(seen.insert(path), "file checked twice in round {}", round);
+                }
+            }
+            assert_eq!(seen.len(), 200);
+        }
+
+        // Passes group the files differently, as do seeds
+        let first_pass = sampled(&manifest, 42, 0, cycles);
+        assert_ne!(first_pass, sampled(&manifest, 42, cycles as u64, cycles));
+        assert_ne!(first_pass, sampled(&manifest, 7, 0, cycles));
+
+        // A file's selection doesn't depend on the rest of the backup
+        let fewer = self::manifest(&names(20));
+        let expected: HashSet<PathBuf> = first_pass.into_iter()
+            .filter(|p| fewer.files.iter().any(|f| &f.local_path == p))
+            .collect();
+        assert_eq!(sampled(&fewer, 42, 0, cycles), expected);
+
+        let sample = sample_manifest(&manifest, 42, 0, cycles);
+        assert_eq!(sample.file_count, sample.files.len());
+        assert_eq!(sample.total_size, 10 * sample.files.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_state_persists_rotation() {
+        let dir = TempDir::new().unwrap();
+        let mut state = ScrubState::load_or_new(dir.path()).await;
+        assert_eq!(state.epoch, 0);
+        state.advance(Utc::now());
+        state.save(dir.path()).await.unwrap();
+
+        let loaded = ScrubState::load_or_new(dir.path()).await;
+        assert_eq!(loaded.seed, state.seed);
+        assert_eq!(loaded.epoch, 1);
+        assert!(loaded.last_run.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_seeded_corruption_found_within_a_pass() {
+        use sha2::{Digest, Sha256};
+
+        let storage = TempDir::new().unwrap();
+        let encryption = Arc::new(EncryptionManager::new("test_password").unwrap());
+        let mut manifest = manifest(&names(12));
+        for entry in &mut manifest.files {
+            let content = format!("contents of {}", entry.local_path.display());
+            entry.hash = format!("{:x}", Sha256::digest(content.as_bytes()));
+            let blob = encryption.encrypt_with_aad(
+                content.as_bytes(),
+                &manifest.backup_id,
+                &entry.local_path.to_string_lossy(),
+            ).unwrap();
+            let path = storage.path().join(entry.remote_path.trim_start_matches('/'));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, blob).unwrap();
+        }
+
+        // Rot one blob
+        let damaged = manifest.files[7].clone();
+        std::fs::write(storage.path().join(damaged.remote_path.trim_start_matches('/')), b"bit rot").unwrap();
+
+        let backend = skylock_core::storage::LocalStorageProvider::new(&skylock_core::storage::StorageConfig {
+            connection_string: Some(storage.path().to_string_lossy().to_string()),
+            ..Default::default()
+        }).unwrap();
+        let replicas = MultiBackend::new(Default::default()).with_backend("hetzner", Arc::new(backend));
+        let hetzner = test_server::client("http://127.0.0.1:9");
+        let cycles = 4;
+        let scrubber = Scrubber::new(BackupVerifier::new(hetzner).with_replicas(Arc::new(replicas)), 99, cycles);
+
+        let manifests = vec![manifest];
+        let mut found_in = Vec::new();
+        let mut files_sampled = 0;
+        for epoch in 0..cycles as u64 {
+            let report = scrubber.run(&manifests, encryption.clone(), epoch).await.unwrap();
+            assert!(!report.incomplete);
+            assert_eq!(report.files_total, 12);
+            files_sampled += report.files_sampled;
+            if !report.is_clean() {
+                found_in.push(epoch);
+
+                // The failure escalated to a full verification naming the file
+                let finding = &report.findings[0];
+                assert_eq!(finding.backup_id, "scrub");
+                assert_eq!(finding.sampled_failures, 1);
+                assert_eq!(finding.full.total_files, 12);
+                assert_eq!(finding.full.files_verified, 11);
+                assert_eq!(finding.full.corrupted_files()[0].path, damaged.local_path);
+            }
+        }
+
+        // Exactly the run sampling the damaged file reports it, and the pass
+        // checked every file once
+        assert_eq!(found_in.len(), 1);
+        assert!(in_sample(99, found_in[0], &damaged, cycles));
+        assert_eq!(files_sampled, 12);
+    }
+}
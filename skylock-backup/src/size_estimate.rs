@@ -0,0 +1,191 @@
+//! Backup size estimation
+//!
+//! Sums file sizes below the backup paths with a native directory walk, so
+//! the estimate works on every platform and for paths that are not valid
+//! UTF-8. The walk is time-boxed and can be cancelled: a huge tree yields a
+//! lower bound instead of holding up the start of the backup.
+
+use crate::watcher::FileWatcher;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use walkdir::WalkDir;
+
+/// Default time allowed for estimating the size of all backup paths
+pub const DEFAULT_ESTIMATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Result of a size estimate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeEstimate {
+    /// Total size of the files counted, in bytes
+    pub bytes: u64,
+    /// Number of files counted
+    pub files: u64,
+    /// False when the walk was cut short by the timeout or cancellation;
+    /// `bytes` is then a lower bound
+    pub complete: bool,
+}
+
+/// Native recursive size estimator
+#[derive(Debug, Clone)]
+pub struct SizeEstimator {
+    exclude_patterns: Vec<String>,
+    timeout: Duration,
+    cancel: CancellationToken,
+}
+
+impl Default for SizeEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SizeEstimator {
+    pub fn new() -> Self {
+        Self {
+            exclude_patterns: Vec::new(),
+            timeout: DEFAULT_ESTIMATE_TIMEOUT,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Skip files and directories matching these glob patterns (same syntax
+    /// as the watcher's ignore patterns)
+    pub fn with_exclude_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.exclude_patterns = patterns;
+        self
+    }
+
+    /// Stop walking after `timeout` and return what was counted so far
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Stop walking when `cancel` is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        let path_str = path.to_string_lossy();
+        self.exclude_patterns
+            .iter()
+            .any(|pattern| FileWatcher::matches_glob(pattern, &path_str))
+    }
+
+    /// Sum the sizes of all files below `paths`
+    ///
+    /// Symlinks are not followed and unreadable entries are skipped. This
+    /// walks the file system synchronously; use [`Self::estimate_async`]
+    /// from async code.
+    pub fn estimate(&self, paths: &[PathBuf]) -> SizeEstimate {
+        let deadline = Instant::now() + self.timeout;
+        let mut estimate = SizeEstimate { complete: true, ..Default::default() };
+
+        for root in paths {
+            let walker = WalkDir::new(root)
+                .follow_links(false)
+                .into_iter()
+                .filter_entry(|entry| !self.is_excluded(entry.path()));
+
+            for entry in walker {
+                if self.cancel.is_cancelled() || Instant::now() >= deadline {
+                    estimate.complete = false;
+                    return estimate;
+                }
+
+                let Ok(entry) = entry else { continue };
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                if let Ok(metadata) = entry.metadata() {
+                    estimate.bytes += metadata.len();
+                    estimate.files += 1;
+                }
+            }
+        }
+
+        estimate
+    }
+
+    /// Run [`Self::estimate`] on the blocking thread pool
+    pub async fn estimate_async(&self, paths: &[PathBuf]) -> SizeEstimate {
+        let estimator = self.clone();
+        let paths = paths.to_vec();
+        tokio::task::spawn_blocking(move || estimator.estimate(&paths))
+            .await
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Tree with a space and non-ASCII characters in its paths; returns
+    /// the total size of its files
+    fn create_tree(root: &Path) -> u64 {
+        let nested = root.join("my documents").join("résumé ✓");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.join("top level.txt"), vec![b'a'; 1000]).unwrap();
+        std::fs::write(nested.join("naïve file ü.txt"), vec![b'b'; 2500]).unwrap();
+        std::fs::write(nested.join("日本語.bin"), vec![b'c'; 4096]).unwrap();
+        1000 + 2500 + 4096
+    }
+
+    #[test]
+    fn test_estimate_matches_known_size() {
+        let dir = TempDir::new().unwrap();
+        let expected = create_tree(dir.path());
+
+        let estimate = SizeEstimator::new().estimate(&[dir.path().to_path_buf()]);
+        assert!(estimate.complete);
+        assert_eq!(estimate.bytes, expected);
+        assert_eq!(estimate.files, 3);
+
+        // A single file path counts just that file
+        let file = dir.path().join("my documents").join("résumé ✓").join("日本語.bin");
+        let estimate = SizeEstimator::new().estimate(&[file]);
+        assert_eq!(estimate.bytes, 4096);
+
+        // Missing paths are skipped rather than failing the estimate
+        let estimate = SizeEstimator::new().estimate(&[dir.path().join("does not exist")]);
+        assert!(estimate.complete);
+        assert_eq!(estimate.bytes, 0);
+    }
+
+    #[test]
+    fn test_estimate_respects_exclude_patterns() {
+        let dir = TempDir::new().unwrap();
+        create_tree(dir.path());
+
+        let estimator = SizeEstimator::new().with_exclude_patterns(vec!["*.bin".to_string()]);
+        let estimate = estimator.estimate(&[dir.path().to_path_buf()]);
+        assert_eq!(estimate.bytes, 1000 + 2500);
+
+        // Excluding a directory skips everything below it
+        let estimator = SizeEstimator::new().with_exclude_patterns(vec!["my documents".to_string()]);
+        let estimate = estimator.estimate(&[dir.path().to_path_buf()]);
+        assert_eq!(estimate.bytes, 1000);
+        assert_eq!(estimate.files, 1);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_stops_on_timeout_and_cancellation() {
+        let dir = TempDir::new().unwrap();
+        create_tree(dir.path());
+        let paths = vec![dir.path().to_path_buf()];
+
+        let estimate = SizeEstimator::new().with_timeout(Duration::ZERO).estimate_async(&paths).await;
+        assert!(!estimate.complete);
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let estimate = SizeEstimator::new().with_cancellation(cancel).estimate_async(&paths).await;
+        assert!(!estimate.complete);
+        assert_eq!(estimate.bytes, 0);
+    }
+}
@@ -0,0 +1,81 @@
+//! JSON state files below the data directory
+//!
+//! Resume states, deletion and verification checkpoints and the scrub state
+//! are all small JSON files rewritten while long operations run. They are
+//! written to a temporary file, synced and renamed into place, so a crash
+//! while saving leaves the previous state rather than a truncated one.
+
+use std::path::Path;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::error::{Result, SkylockError};
+use crate::temp_files::CleanupGuard;
+
+/// The state saved at `path`, or `None` if there is none or it can't be read
+pub async fn load<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let json = fs::read(path).await.ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// Atomically replace the state at `path` with `state`, creating its
+/// directory; `what` names the state in errors
+pub async fn save<T: Serialize>(path: &Path, state: &T, what: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await
+            .map_err(|e| SkylockError::Backup(format!("Failed to create {} directory: {}", what, e)))?;
+    }
+
+    let json = serde_json::to_vec_pretty(state)
+        .map_err(|e| SkylockError::Backup(format!("Failed to serialize {}: {}", what, e)))?;
+
+    let staged = CleanupGuard::new(path.with_extension("json.tmp"));
+    let mut file = fs::File::create(staged.path()).await
+        .map_err(|e| SkylockError::Backup(format!("Failed to create temp {} file: {}", what, e)))?;
+    file.write_all(&json).await
+        .map_err(|e| SkylockError::Backup(format!("Failed to write {}: {}", what, e)))?;
+    file.sync_all().await
+        .map_err(|e| SkylockError::Backup(format!("Failed to sync {} file: {}", what, e)))?;
+    drop(file);
+
+    fs::rename(staged.path(), path).await
+        .map_err(|e| SkylockError::Backup(format!("Failed to rename {} file: {}", what, e)))?;
+    staged.keep();
+    Ok(())
+}
+
+/// Delete the state at `path`, if there is one
+pub async fn remove(path: &Path, what: &str) -> Result<()> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(SkylockError::Backup(format!("Failed to delete {}: {}", what, e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_save_load_remove() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("state").join("a.json");
+        assert!(load::<BTreeMap<String, u32>>(&path).await.is_none());
+
+        let state = BTreeMap::from([("done".to_string(), 3u32)]);
+        save(&path, &state, "test state").await.unwrap();
+        assert_eq!(load::<BTreeMap<String, u32>>(&path).await, Some(state));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        std::fs::write(&path, b"{ truncated").unwrap();
+        assert!(load::<BTreeMap<String, u32>>(&path).await.is_none());
+
+        remove(&path, "test state").await.unwrap();
+        assert!(!path.exists());
+        remove(&path, "test state").await.unwrap();
+    }
+}
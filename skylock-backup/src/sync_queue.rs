@@ -1,17 +1,23 @@
 //! Sync Queue Processor
 //!
 //! Processes file change events from the watcher, handles conflicts,
-//! and queues files for backup. Uses "newest version wins" conflict resolution.
+//! and queues files for backup. Conflicts are resolved according to the
+//! configured [`ConflictPolicy`] (newest version wins by default).
 
 use std::collections::{HashMap, VecDeque, HashSet};
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::{mpsc, RwLock, Semaphore};
 use tracing::{info, warn, error, debug};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use skylock_core::storage::{StorageBackend, StorageItem};
+use tokio::io::AsyncWriteExt;
+use sha2::{Digest, Sha256};
 
 use crate::watcher::{EventBatch, FileEvent, FileEventKind};
+use crate::sync_state::{FileState, SyncStateManager};
+use crate::temp_files::CleanupGuard;
 
 /// Default maximum queue size
 pub const DEFAULT_MAX_QUEUE_SIZE: usize = 10000;
@@ -106,6 +112,147 @@ pub enum ConflictResolutionType {
     UserChoseRemote,
 }
 
+/// Policy applied when a local change collides with a remote change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// Always upload the local version, overwriting the remote one
+    PreferLocal,
+    /// Always keep the remote version and skip the local change
+    PreferRemote,
+    /// Keep whichever version has the newest modification time
+    #[default]
+    PreferNewest,
+    /// Keep both: the local copy is renamed aside and uploaded under the new
+    /// name, and the remote version is downloaded to the original path
+    KeepBoth,
+}
+
+/// What is currently known about the remote copy of a file
+///
+/// Both fields come from the backend, so they are only ever compared with
+/// what the backend reported before, never with local hashes or clocks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemoteVersion {
+    /// Remote modification time
+    pub mtime: Option<DateTime<Utc>>,
+    /// Remote ETag, if the backend reports one
+    pub etag: Option<String>,
+}
+
+impl RemoteVersion {
+    /// Whether the backend reported anything about the copy
+    pub fn exists(&self) -> bool {
+        self.mtime.is_some() || self.etag.is_some()
+    }
+}
+
+impl From<&StorageItem> for RemoteVersion {
+    fn from(item: &StorageItem) -> Self {
+        Self {
+            mtime: item.last_modified,
+            etag: item.etag.clone(),
+        }
+    }
+}
+
+/// A file copied to or from the remote, as it is recorded in the sync state
+#[derive(Debug, Clone)]
+pub struct SyncedCopy {
+    /// Bytes transferred
+    pub size: u64,
+    /// SHA-256 of the content
+    pub content_hash: String,
+    /// The remote copy's version after the transfer
+    pub remote: RemoteVersion,
+}
+
+impl SyncedCopy {
+    /// Mark `path` synced with this content and remote version
+    pub fn record(&self, state: &mut SyncStateManager, path: &Path) {
+        state.mark_synced(path, Some(self.content_hash.clone()));
+        state.mark_remote(path, self.remote.etag.clone(), self.remote.mtime);
+    }
+}
+
+/// Where the remote copies of synced files live, checked for conflicts
+/// before each upload
+#[derive(Debug, Clone)]
+pub struct RemoteTarget {
+    backend: Arc<dyn StorageBackend>,
+    root: PathBuf,
+}
+
+impl RemoteTarget {
+    /// Files are stored below `root` by their local path, e.g.
+    /// `/home/me/a.txt` as `<root>/home/me/a.txt`
+    pub fn new(backend: Arc<dyn StorageBackend>, root: PathBuf) -> Self {
+        Self { backend, root }
+    }
+
+    /// Remote path of the local file `local`
+    pub fn remote_path(&self, local: &Path) -> PathBuf {
+        let relative: PathBuf = local.components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        self.root.join(relative)
+    }
+
+    /// The remote copy of `local` as the backend reports it; the default
+    /// (no version) if there is none
+    pub async fn version(&self, local: &Path) -> Result<RemoteVersion, SyncQueueError> {
+        let item = self.backend.get_metadata(&self.remote_path(local)).await
+            .map_err(|e| SyncQueueError::SyncError(format!("Failed to check the remote copy of {:?}: {}", local, e)))?;
+        Ok(item.as_ref().map(RemoteVersion::from).unwrap_or_default())
+    }
+
+    /// Upload `local` over its remote copy
+    pub async fn upload(&self, local: &Path) -> Result<SyncedCopy, SyncQueueError> {
+        let content_hash = sha256_file(local).await?;
+        let file = tokio::fs::File::open(local).await?;
+        let size = file.metadata().await?.len();
+        let remote_path = self.remote_path(local);
+        let item = self.backend.upload(Box::pin(file), &remote_path, None).await
+            .map_err(|e| SyncQueueError::SyncError(format!("Failed to upload {:?}: {}", remote_path, e)))?;
+        // Not every backend describes the object it just stored
+        let remote = match RemoteVersion::from(&item) {
+            remote if remote.exists() => remote,
+            _ => self.version(local).await?,
+        };
+        Ok(SyncedCopy { size, content_hash, remote })
+    }
+
+    /// Download the remote copy of `local` to `local`, returning its size
+    ///
+    /// The download streams into the file, which is removed again if it
+    /// fails.
+    async fn fetch(&self, local: &Path) -> Result<u64, SyncQueueError> {
+        let staged = CleanupGuard::new(local.to_path_buf());
+        let mut file = tokio::fs::File::create(local).await?;
+        let (writer, mut reader) = tokio::io::duplex(64 * 1024);
+        let remote_path = self.remote_path(local);
+        let (downloaded, copied) = tokio::join!(
+            self.backend.download(&remote_path, Box::pin(writer), None),
+            tokio::io::copy(&mut reader, &mut file),
+        );
+        downloaded.map_err(|e| SyncQueueError::SyncError(format!("Failed to download {:?}: {}", remote_path, e)))?;
+        let size = copied?;
+        file.flush().await?;
+        staged.keep();
+        Ok(size)
+    }
+}
+
+/// The result of applying a conflict policy to a queued item
+#[derive(Debug, Clone)]
+pub struct ConflictOutcome {
+    /// How the conflict was resolved
+    pub resolution: ConflictResolutionType,
+    /// Item to hand to the uploader (`Skip` when the remote version is kept)
+    pub item: SyncItem,
+    /// New local path when the local copy was renamed aside
+    pub renamed_to: Option<PathBuf>,
+}
+
 /// Configuration for the sync queue
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncQueueConfig {
@@ -121,6 +268,9 @@ pub struct SyncQueueConfig {
     pub warn_on_conflicts: bool,
     /// Whether to log conflict resolutions
     pub log_conflicts: bool,
+    /// How to resolve conflicts between local and remote changes
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
 }
 
 impl Default for SyncQueueConfig {
@@ -132,6 +282,20 @@ impl Default for SyncQueueConfig {
             retry_delay_ms: 1000,
             warn_on_conflicts: true,
             log_conflicts: true,
+            conflict_policy: ConflictPolicy::default(),
+        }
+    }
+}
+
+impl SyncQueueConfig {
+    /// Defaults, with `backup.max_concurrent_uploads` in place of
+    /// [`DEFAULT_CONCURRENT_UPLOADS`] when it is set
+    pub fn from_backup_config(backup: &skylock_core::BackupConfig) -> Self {
+        Self {
+            concurrent_uploads: backup.max_concurrent_uploads
+                .map(|uploads| uploads.clamp(1, crate::parallelism::MAX_CONCURRENCY))
+                .unwrap_or(DEFAULT_CONCURRENT_UPLOADS),
+            ..Self::default()
         }
     }
 }
@@ -317,28 +481,54 @@ impl SyncQueueProcessor {
         let _ = self.completed_tx.send(result).await;
     }
 
+    /// Check whether the remote copy changed since the last successful sync
+    ///
+    /// The remote ETag, or else its modification time, is compared with the
+    /// one recorded at that sync. A file that was never synced but already
+    /// exists remotely is also treated as a conflict, since uploading would
+    /// overwrite unknown data.
+    pub fn detect_conflict(state: Option<&FileState>, remote: &RemoteVersion) -> bool {
+        let state = match state {
+            Some(state) if state.last_synced.is_some() => state,
+            _ => return remote.exists(),
+        };
+
+        if let (Some(known), Some(current)) = (&state.remote_etag, &remote.etag) {
+            return known != current;
+        }
+
+        match (state.remote_mtime, remote.mtime) {
+            (Some(known), Some(current)) => current != known,
+            // Gone remotely, or nothing recorded to compare with
+            _ => false,
+        }
+    }
+
     /// Resolve a conflict between local and remote versions
-    /// Uses "newest version wins" strategy
+    /// using the configured [`ConflictPolicy`]
     pub async fn resolve_conflict(
         &self,
         path: &PathBuf,
         local_mtime: Option<DateTime<Utc>>,
         remote_mtime: Option<DateTime<Utc>>,
     ) -> ConflictResolutionType {
-        let resolution = match (local_mtime, remote_mtime) {
-            (Some(local), Some(remote)) => {
-                if local > remote {
-                    ConflictResolutionType::LocalWins
-                } else if remote > local {
-                    ConflictResolutionType::RemoteWins
-                } else {
-                    // Same time, default to local
-                    ConflictResolutionType::LocalWins
+        let resolution = match self.config.conflict_policy {
+            ConflictPolicy::PreferLocal => ConflictResolutionType::LocalWins,
+            ConflictPolicy::PreferRemote => ConflictResolutionType::RemoteWins,
+            ConflictPolicy::KeepBoth => ConflictResolutionType::BothKept,
+            ConflictPolicy::PreferNewest => match (local_mtime, remote_mtime) {
+                (Some(local), Some(remote)) => {
+                    if remote > local {
+                        ConflictResolutionType::RemoteWins
+                    } else {
+                        // Local is newer or same time, default to local
+                        ConflictResolutionType::LocalWins
+                    }
                 }
-            }
-            (Some(_), None) => ConflictResolutionType::LocalWins,
-            (None, Some(_)) => ConflictResolutionType::RemoteWins,
-            (None, None) => ConflictResolutionType::LocalWins, // Default to local if no info
+                (Some(_), None) => ConflictResolutionType::LocalWins,
+                (None, Some(_)) => ConflictResolutionType::RemoteWins,
+                (None, None) => ConflictResolutionType::LocalWins, // Default to local if no info
+            },
         };
 
         // Log the conflict
@@ -374,6 +564,89 @@ impl SyncQueueProcessor {
         resolution
     }
 
+    /// Check an upload against the last synced state before it overwrites
+    /// the remote copy, applying the conflict policy if the remote changed
+    ///
+    /// Returns `None` when there is no conflict and the item can be uploaded
+    /// as-is. Non-upload items are never treated as conflicts. Keeping both
+    /// versions moves the local file aside and downloads the remote version
+    /// to its path.
+    pub async fn check_upload(
+        &self,
+        item: SyncItem,
+        target: &RemoteTarget,
+        state: &mut SyncStateManager,
+    ) -> Result<Option<ConflictOutcome>, SyncQueueError> {
+        if item.action != SyncAction::Upload {
+            return Ok(None);
+        }
+        let remote = target.version(&item.path).await?;
+        if !Self::detect_conflict(state.get_state(&item.path), &remote) {
+            return Ok(None);
+        }
+
+        let resolution = self.resolve_conflict(&item.path, item.mtime, remote.mtime).await;
+
+        let outcome = match resolution {
+            ConflictResolutionType::RemoteWins | ConflictResolutionType::UserChoseRemote => {
+                state.mark_conflict(&item.path);
+                ConflictOutcome {
+                    resolution,
+                    item: SyncItem { action: SyncAction::Skip, ..item },
+                    renamed_to: None,
+                }
+            }
+            ConflictResolutionType::BothKept => {
+                let renamed = conflict_path(&item.path, |candidate| {
+                    candidate.exists() || state.get_state(candidate).is_some()
+                });
+                tokio::fs::rename(&item.path, &renamed).await?;
+                let fetched = match target.fetch(&item.path).await {
+                    Ok(size) => size,
+                    Err(e) => {
+                        // Leave the local version where it was
+                        if let Err(rename_error) = tokio::fs::rename(&renamed, &item.path).await {
+                            error!("Failed to move {:?} back to {:?}: {}", renamed, item.path, rename_error);
+                        }
+                        return Err(e);
+                    }
+                };
+                self.stats.write().await.bytes_downloaded += fetched;
+                // The item completes under its new path
+                {
+                    let mut in_progress = self.in_progress.write().await;
+                    if in_progress.remove(&item.path) {
+                        in_progress.insert(renamed.clone());
+                    }
+                }
+
+                let metadata = tokio::fs::metadata(&renamed).await?;
+                let mtime = item.mtime.unwrap_or_else(Utc::now);
+                state.mark_modified(&renamed, metadata.len(), mtime);
+                // The original path now holds the remote version
+                let fetched = SyncedCopy {
+                    size: fetched,
+                    content_hash: sha256_file(&item.path).await?,
+                    remote: remote.clone(),
+                };
+                state.mark_modified(&item.path, fetched.size, remote.mtime.unwrap_or_else(Utc::now));
+                fetched.record(state, &item.path);
+
+                info!("Kept both versions of {:?}, local copy moved to {:?}", item.path, renamed);
+                ConflictOutcome {
+                    resolution,
+                    item: SyncItem { path: renamed.clone(), ..item },
+                    renamed_to: Some(renamed),
+                }
+            }
+            ConflictResolutionType::LocalWins | ConflictResolutionType::UserChoseLocal => {
+                ConflictOutcome { resolution, item, renamed_to: None }
+            }
+        };
+
+        Ok(Some(outcome))
+    }
+
     /// Retry a failed item
     pub async fn retry_item(&self, mut item: SyncItem) -> Result<bool, SyncQueueError> {
         item.increment_retry();
@@ -424,6 +697,37 @@ impl SyncQueueProcessor {
     }
 }
 
+/// SHA-256 of the file at `path`, as recorded in [`FileState::content_hash`]
+pub(crate) async fn sha256_file(path: &Path) -> Result<String, SyncQueueError> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(&path)?, &mut hasher)?;
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+    .await
+    .map_err(|e| SyncQueueError::SyncError(format!("Hashing task failed: {}", e)))?
+}
+
+/// Build a deterministic, unused path for the local side of a `KeepBoth`
+/// conflict: `report.txt` becomes `report.conflict-1.txt`, then
+/// `report.conflict-2.txt` if that is taken, and so on.
+pub fn conflict_path(path: &Path, is_taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|e| e.to_string_lossy().into_owned());
+
+    (1u32..)
+        .map(|n| {
+            let name = match &extension {
+                Some(ext) => format!("{}.conflict-{}.{}", stem, n, ext),
+                None => format!("{}.conflict-{}", stem, n),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !is_taken(candidate))
+        .expect("conflict suffixes are unbounded")
+}
+
 /// Errors that can occur in the sync queue
 #[derive(Debug, thiserror::Error)]
 pub enum SyncQueueError {
@@ -453,6 +757,27 @@ mod tests {
         assert_eq!(processor.queue_size().await, 0);
     }
 
+    #[test]
+    fn test_config_honors_max_concurrent_uploads() {
+        let backup = |extra: serde_json::Value| {
+            let mut value = serde_json::json!({
+                "vss_enabled": false,
+                "schedule": "0 2 * * *",
+                "retention_days": 30,
+                "backup_paths": [],
+            });
+            value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            serde_json::from_value::<skylock_core::BackupConfig>(value).unwrap()
+        };
+        
+        let unset = SyncQueueConfig::from_backup_config(&backup(serde_json::json!({})));
+        assert_eq!(unset.concurrent_uploads, DEFAULT_CONCURRENT_UPLOADS);
+        let set = SyncQueueConfig::from_backup_config(&backup(serde_json::json!({ "max_concurrent_uploads": 9 })));
+        assert_eq!(set.concurrent_uploads, 9);
+        let huge = SyncQueueConfig::from_backup_config(&backup(serde_json::json!({ "max_concurrent_uploads": 5000 })));
+        assert_eq!(huge.concurrent_uploads, crate::parallelism::MAX_CONCURRENCY);
+    }
+
     #[tokio::test]
     async fn test_add_item() {
         let config = SyncQueueConfig::default();
@@ -528,6 +853,300 @@ mod tests {
         assert_eq!(second.path, PathBuf::from("/medium.txt"));
     }
 
+    struct ConflictFixture {
+        _dir: tempfile::TempDir,
+        path: PathBuf,
+        state: SyncStateManager,
+        local_mtime: DateTime<Utc>,
+        remote_mtime: DateTime<Utc>,
+    }
+
+    /// SHA-256 of "synced content"
+    const SYNCED_SHA256: &str = "54e435b176d058801e0efa78424b215eb94a76b821e968c243d96d789bbe40c7";
+
+    /// A file that was synced to a remote copy with ETag "synced-etag" and
+    /// has since been edited locally
+    fn conflict_fixture() -> ConflictFixture {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("report.txt");
+        std::fs::write(&path, b"local edit").unwrap();
+
+        let mut state = SyncStateManager::new(crate::sync_state::SyncStateConfig {
+            db_path: dir.path().join("state.json"),
+            ..Default::default()
+        }).unwrap();
+        let local_mtime = Utc::now();
+        // The server's clock runs well ahead of the local one
+        let remote_mtime = local_mtime + chrono::Duration::hours(3);
+        state.mark_modified(&path, 10, local_mtime - chrono::Duration::hours(2));
+        state.mark_synced(&path, Some(SYNCED_SHA256.to_string()));
+        state.mark_remote(&path, Some("synced-etag".to_string()), Some(remote_mtime));
+        state.mark_modified(&path, 10, local_mtime);
+
+        ConflictFixture { _dir: dir, path, state, local_mtime, remote_mtime }
+    }
+
+    fn processor_with(policy: ConflictPolicy) -> SyncQueueProcessor {
+        let config = SyncQueueConfig {
+            warn_on_conflicts: false,
+            conflict_policy: policy,
+            ..Default::default()
+        };
+        SyncQueueProcessor::new(config).0
+    }
+
+    /// Remote holding "remote edit" with the given version; each upload
+    /// replaces it with a new ETag
+    #[derive(Debug)]
+    struct MockRemote {
+        version: std::sync::Mutex<RemoteVersion>,
+        uploads: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl StorageBackend for MockRemote {
+        async fn upload(
+            &self,
+            mut source: std::pin::Pin<Box<dyn tokio::io::AsyncRead + Send>>,
+            destination: &PathBuf,
+            _options: Option<skylock_core::storage::UploadOptions>,
+        ) -> skylock_core::Result<StorageItem> {
+            let size = tokio::io::copy(&mut source, &mut tokio::io::sink()).await?;
+            let upload = self.uploads.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            let version = RemoteVersion {
+                mtime: Some(Utc::now() + chrono::Duration::hours(3)),
+                etag: Some(format!("uploaded-etag-{}", upload)),
+            };
+            *self.version.lock().unwrap() = version.clone();
+            Ok(StorageItem {
+                path: destination.clone(),
+                size,
+                last_modified: version.mtime,
+                metadata: None,
+                etag: version.etag,
+            })
+        }
+
+        async fn download(
+            &self,
+            _source: &PathBuf,
+            mut destination: std::pin::Pin<Box<dyn tokio::io::AsyncWrite + Send>>,
+            _options: Option<skylock_core::storage::DownloadOptions>,
+        ) -> skylock_core::Result<()> {
+            destination.write_all(b"remote edit").await?;
+            Ok(())
+        }
+
+        async fn delete(&self, _path: &PathBuf) -> skylock_core::Result<()> {
+            unimplemented!("the sync queue doesn't delete")
+        }
+
+        async fn list(&self, _prefix: Option<&PathBuf>, _recursive: bool) -> skylock_core::Result<Vec<StorageItem>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_metadata(&self, path: &PathBuf) -> skylock_core::Result<Option<StorageItem>> {
+            let version = self.version.lock().unwrap().clone();
+            Ok(version.exists().then(|| StorageItem {
+                path: path.clone(),
+                size: 11,
+                last_modified: version.mtime,
+                metadata: None,
+                etag: version.etag,
+            }))
+        }
+
+        async fn copy(&self, _source: &PathBuf, _destination: &PathBuf) -> skylock_core::Result<StorageItem> {
+            unimplemented!("the sync queue doesn't copy")
+        }
+    }
+
+    fn remote_target(version: RemoteVersion) -> RemoteTarget {
+        let backend = MockRemote { version: std::sync::Mutex::new(version), uploads: Default::default() };
+        RemoteTarget::new(Arc::new(backend), PathBuf::from("/sync"))
+    }
+
+    fn changed_remote(mtime: DateTime<Utc>) -> RemoteVersion {
+        RemoteVersion {
+            mtime: Some(mtime),
+            etag: Some("remote-etag".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_detect_conflict() {
+        let fx = conflict_fixture();
+        let state = fx.state.get_state(&fx.path);
+
+        // The ETag is compared with the recorded ETag, not the content hash
+        let unchanged = RemoteVersion {
+            mtime: Some(fx.remote_mtime),
+            etag: Some("synced-etag".to_string()),
+        };
+        assert!(!SyncQueueProcessor::detect_conflict(state, &unchanged));
+        assert!(SyncQueueProcessor::detect_conflict(state, &changed_remote(fx.remote_mtime)));
+
+        // Without ETags, the remote mtime is compared with the recorded one,
+        // however far the server's clock is from the local one
+        let same = RemoteVersion { mtime: Some(fx.remote_mtime), etag: None };
+        let touched = RemoteVersion { mtime: Some(fx.remote_mtime + chrono::Duration::seconds(1)), etag: None };
+        assert!(fx.remote_mtime > state.unwrap().last_synced.unwrap());
+        assert!(!SyncQueueProcessor::detect_conflict(state, &same));
+        assert!(SyncQueueProcessor::detect_conflict(state, &touched));
+
+        // Never synced but present remotely
+        assert!(SyncQueueProcessor::detect_conflict(None, &same));
+        assert!(!SyncQueueProcessor::detect_conflict(None, &RemoteVersion::default()));
+    }
+
+    #[tokio::test]
+    async fn test_no_conflict_when_remote_unchanged() {
+        let mut fx = conflict_fixture();
+        let processor = processor_with(ConflictPolicy::PreferRemote);
+        let remote = RemoteVersion { mtime: None, etag: Some("synced-etag".to_string()) };
+
+        let item = SyncItem::new(fx.path.clone(), SyncAction::Upload).with_mtime(fx.local_mtime);
+        let outcome = processor.check_upload(item, &remote_target(remote), &mut fx.state).await.unwrap();
+
+        assert!(outcome.is_none());
+        assert_eq!(processor.stats().await.conflicts_resolved, 0);
+    }
+
+    #[tokio::test]
+    async fn test_policy_prefer_local() {
+        let mut fx = conflict_fixture();
+        let processor = processor_with(ConflictPolicy::PreferLocal);
+        let remote = changed_remote(fx.local_mtime + chrono::Duration::hours(1));
+
+        let item = SyncItem::new(fx.path.clone(), SyncAction::Upload).with_mtime(fx.local_mtime);
+        let outcome = processor.check_upload(item, &remote_target(remote), &mut fx.state).await.unwrap().unwrap();
+
+        assert_eq!(outcome.resolution, ConflictResolutionType::LocalWins);
+        assert_eq!(outcome.item.action, SyncAction::Upload);
+        assert_eq!(outcome.item.path, fx.path);
+        assert!(outcome.renamed_to.is_none());
+        assert_eq!(std::fs::read(&fx.path).unwrap(), b"local edit");
+    }
+
+    #[tokio::test]
+    async fn test_policy_prefer_remote() {
+        let mut fx = conflict_fixture();
+        let processor = processor_with(ConflictPolicy::PreferRemote);
+        let remote = changed_remote(fx.local_mtime - chrono::Duration::hours(1));
+
+        let item = SyncItem::new(fx.path.clone(), SyncAction::Upload).with_mtime(fx.local_mtime);
+        let outcome = processor.check_upload(item, &remote_target(remote), &mut fx.state).await.unwrap().unwrap();
+
+        assert_eq!(outcome.resolution, ConflictResolutionType::RemoteWins);
+        assert_eq!(outcome.item.action, SyncAction::Skip);
+        assert_eq!(std::fs::read(&fx.path).unwrap(), b"local edit");
+        assert_eq!(fx.state.get_state(&fx.path).unwrap().status, crate::sync_state::SyncStatus::Conflict);
+    }
+
+    #[tokio::test]
+    async fn test_policy_prefer_newest() {
+        let processor = processor_with(ConflictPolicy::PreferNewest);
+
+        let mut fx = conflict_fixture();
+        let older_remote = changed_remote(fx.local_mtime - chrono::Duration::hours(1));
+        let item = SyncItem::new(fx.path.clone(), SyncAction::Upload).with_mtime(fx.local_mtime);
+        let outcome = processor.check_upload(item, &remote_target(older_remote), &mut fx.state).await.unwrap().unwrap();
+        assert_eq!(outcome.resolution, ConflictResolutionType::LocalWins);
+        assert_eq!(outcome.item.action, SyncAction::Upload);
+
+        let mut fx = conflict_fixture();
+        let newer_remote = changed_remote(fx.local_mtime + chrono::Duration::hours(1));
+        let item = SyncItem::new(fx.path.clone(), SyncAction::Upload).with_mtime(fx.local_mtime);
+        let outcome = processor.check_upload(item, &remote_target(newer_remote), &mut fx.state).await.unwrap().unwrap();
+        assert_eq!(outcome.resolution, ConflictResolutionType::RemoteWins);
+        assert_eq!(outcome.item.action, SyncAction::Skip);
+
+        assert_eq!(processor.recent_conflicts(10).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_policy_keep_both() {
+        let mut fx = conflict_fixture();
+        let processor = processor_with(ConflictPolicy::KeepBoth);
+        let remote = changed_remote(fx.local_mtime);
+
+        // An earlier conflict copy already exists on disk
+        let first = fx.path.with_file_name("report.conflict-1.txt");
+        std::fs::write(&first, b"older conflict").unwrap();
+
+        let item = SyncItem::new(fx.path.clone(), SyncAction::Upload).with_mtime(fx.local_mtime);
+        let outcome = processor.check_upload(item, &remote_target(remote), &mut fx.state).await.unwrap().unwrap();
+
+        let expected = fx.path.with_file_name("report.conflict-2.txt");
+        assert_eq!(outcome.resolution, ConflictResolutionType::BothKept);
+        assert_eq!(outcome.renamed_to.as_ref(), Some(&expected));
+        assert_eq!(outcome.item.action, SyncAction::Upload);
+        assert_eq!(outcome.item.path, expected);
+
+        // Local edit moved aside, the remote version downloaded in its place
+        assert_eq!(std::fs::read(&fx.path).unwrap(), b"remote edit");
+        assert_eq!(std::fs::read(&expected).unwrap(), b"local edit");
+        let fetched = fx.state.get_state(&fx.path).unwrap();
+        assert_eq!(fetched.status, crate::sync_state::SyncStatus::Synced);
+        assert_eq!(fetched.content_hash.as_deref(), Some("bb91903eb5a1208868a19a2e19ce7a77ae18293f241717983d18960a170fa89a"));
+        assert_eq!(fetched.remote_etag.as_deref(), Some("remote-etag"));
+        assert_eq!(processor.stats().await.bytes_downloaded, 11);
+        assert_eq!(std::fs::read(&first).unwrap(), b"older conflict");
+        assert!(fx.state.get_state(&expected).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_upload_records_remote_version() {
+        let mut fx = conflict_fixture();
+        let processor = processor_with(ConflictPolicy::PreferRemote);
+        let target = remote_target(RemoteVersion {
+            mtime: Some(fx.remote_mtime),
+            etag: Some("synced-etag".to_string()),
+        });
+
+        let uploaded = target.upload(&fx.path).await.unwrap();
+        uploaded.record(&mut fx.state, &fx.path);
+        let state = fx.state.get_state(&fx.path).unwrap();
+        assert_eq!(uploaded.size, 10);
+        assert_eq!(state.content_hash.as_deref(), Some("52f2f0065eab36600dabc023026a10c61034dc373e77390457d4c2be9298cb9a"));
+        assert_eq!(state.remote_etag.as_deref(), Some("uploaded-etag-1"));
+        assert_eq!(state.remote_mtime, uploaded.remote.mtime);
+
+        // Uploading the next local edit isn't mistaken for a conflict
+        std::fs::write(&fx.path, b"another local edit").unwrap();
+        fx.state.mark_modified(&fx.path, 18, Utc::now());
+        let item = SyncItem::new(fx.path.clone(), SyncAction::Upload);
+        assert!(processor.check_upload(item, &target, &mut fx.state).await.unwrap().is_none());
+        assert_eq!(processor.stats().await.conflicts_resolved, 0);
+    }
+
+    #[test]
+    fn test_remote_path() {
+        let target = remote_target(RemoteVersion::default());
+        assert_eq!(target.remote_path(Path::new("/home/me/a.txt")), PathBuf::from("/sync/home/me/a.txt"));
+    }
+
+    #[test]
+    fn test_conflict_path_naming() {
+        let none_taken = |_: &Path| false;
+        assert_eq!(
+            conflict_path(Path::new("/data/report.txt"), none_taken),
+            PathBuf::from("/data/report.conflict-1.txt")
+        );
+        assert_eq!(
+            conflict_path(Path::new("/data/Makefile"), none_taken),
+            PathBuf::from("/data/Makefile.conflict-1")
+        );
+        assert_eq!(
+            conflict_path(Path::new("/data/.bashrc"), none_taken),
+            PathBuf::from("/data/.bashrc.conflict-1")
+        );
+
+        let taken = [PathBuf::from("/data/a.tar.gz"), PathBuf::from("/data/a.tar.conflict-1.gz")];
+        let path = conflict_path(Path::new("/data/a.tar.gz"), |p| taken.iter().any(|t| t == p));
+        assert_eq!(path, PathBuf::from("/data/a.tar.conflict-2.gz"));
+    }
+
     #[test]
     fn test_sync_item_creation() {
         let item = SyncItem::new(PathBuf::from("/test.txt"), SyncAction::Upload)
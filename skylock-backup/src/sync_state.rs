@@ -2,9 +2,18 @@
 //!
 //! Tracks file states, modification times, and sync history using SQLite.
 //! Persists state across restarts for efficient incremental syncing.
+//!
+//! Every change is appended to a write-ahead log (`<db_path>.wal`, one JSON
+//! record per line) as it happens. `save` compacts the log into a snapshot
+//! that is written to a temporary file and renamed over `db_path`, so a
+//! crash leaves either the old or the new snapshot, never a partial one. On
+//! startup the log is replayed over the snapshot, stopping at a record torn
+//! by a crash.
 
-use std::path::{Path, PathBuf};
 use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 use tracing::{info, warn, error, debug};
@@ -18,8 +27,11 @@ pub struct FileState {
     pub size: u64,
     /// Local modification time
     pub local_mtime: DateTime<Utc>,
-    /// Remote modification time (if known)
+    /// Modification time the remote copy had when last synced (if known)
     pub remote_mtime: Option<DateTime<Utc>>,
+    /// ETag the remote copy had when last synced (if the backend reports one)
+    #[serde(default)]
+    pub remote_etag: Option<String>,
     /// SHA-256 hash of file content
     pub content_hash: Option<String>,
     /// Last time this file was synced
@@ -96,6 +108,14 @@ pub struct SyncStateConfig {
     pub history_retention_days: u32,
     /// Maximum history entries to keep
     pub max_history_entries: usize,
+    /// Write-ahead log records after which the log is compacted into a new
+    /// snapshot (0 only compacts on `save`)
+    #[serde(default = "default_wal_compaction_threshold")]
+    pub wal_compaction_threshold: usize,
+}
+
+fn default_wal_compaction_threshold() -> usize {
+    1000
 }
 
 impl Default for SyncStateConfig {
@@ -108,6 +128,7 @@ impl Default for SyncStateConfig {
             db_path: data_dir.join("sync_state.db"),
             history_retention_days: 30,
             max_history_entries: 100000,
+            wal_compaction_threshold: default_wal_compaction_threshold(),
         }
     }
 }
@@ -124,6 +145,10 @@ pub struct SyncStateManager {
     next_history_id: i64,
     /// Whether state has changed since last save
     dirty: bool,
+    /// Write-ahead log, opened on the first change
+    wal: Option<File>,
+    /// Records appended to the write-ahead log since the last snapshot
+    wal_records: usize,
 }
 
 impl SyncStateManager {
@@ -135,13 +160,28 @@ impl SyncStateManager {
             history: Vec::new(),
             next_history_id: 1,
             dirty: false,
+            wal: None,
+            wal_records: 0,
         };
 
+        // A snapshot that was being written when the process died
+        let temp_path = manager.temp_path();
+        if temp_path.exists() {
+            warn!("Removing unfinished sync state snapshot {:?}", temp_path);
+            std::fs::remove_file(&temp_path)?;
+        }
+
         // Try to load existing state
         if manager.config.db_path.exists() {
             manager.load()?;
         }
 
+        // Fold changes made since the last snapshot into a new one
+        if manager.replay_wal()? {
+            manager.dirty = true;
+            manager.save()?;
+        }
+
         Ok(manager)
     }
 
@@ -162,6 +202,119 @@ impl SyncStateManager {
         Ok(())
     }
 
+    /// Apply the write-ahead log to the loaded snapshot. Returns whether
+    /// there was a log to apply, i.e. whether a new snapshot is due.
+    fn replay_wal(&mut self) -> Result<bool, SyncStateError> {
+        let file = match File::open(self.wal_path()) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(SyncStateError::IoError(e)),
+        };
+        if file.metadata()?.len() == 0 {
+            return Ok(false);
+        }
+
+        let mut applied = 0;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            match serde_json::from_str::<WalRecord>(&line) {
+                Ok(record) => {
+                    self.apply(record);
+                    applied += 1;
+                }
+                Err(e) => {
+                    // Everything from a torn record on was never acknowledged
+                    warn!("Sync state log is cut off at record {}: {}; ignoring the rest", index + 1, e);
+                    break;
+                }
+            }
+        }
+        self.prune_history();
+
+        info!("Replayed {} sync state changes from the write-ahead log", applied);
+        Ok(true)
+    }
+
+    /// Apply one logged change
+    fn apply(&mut self, record: WalRecord) {
+        match record {
+            WalRecord::Upsert(state) => {
+                self.states.insert(state.path.clone(), state);
+            }
+            WalRecord::Remove(path) => {
+                self.states.remove(&path);
+            }
+            WalRecord::History(entry) => {
+                // Entries below the next ID are already in the snapshot, if
+                // the process died between writing it and emptying the log
+                if entry.id >= self.next_history_id {
+                    self.next_history_id = entry.id + 1;
+                    self.history.push(entry);
+                }
+            }
+            WalRecord::Clear => {
+                self.states.clear();
+                self.history.clear();
+                self.next_history_id = 1;
+            }
+        }
+    }
+
+    /// Append a change to the write-ahead log, compacting the log once it
+    /// reaches the configured size. Failures are logged and leave the state
+    /// dirty, so the next `save` still persists the change.
+    fn log(&mut self, record: WalRecord) {
+        self.dirty = true;
+        if let Err(e) = self.append(&record) {
+            warn!("Failed to append to sync state log: {}", e);
+            return;
+        }
+
+        self.wal_records += 1;
+        let threshold = self.config.wal_compaction_threshold;
+        if threshold > 0 && self.wal_records >= threshold {
+            if let Err(e) = self.save() {
+                warn!("Failed to compact sync state log: {}", e);
+            }
+        }
+    }
+
+    fn append(&mut self, record: &WalRecord) -> Result<(), SyncStateError> {
+        let mut line = serde_json::to_vec(record)
+            .map_err(|e| SyncStateError::ParseError(e.to_string()))?;
+        line.push(b'\n');
+
+        if self.wal.is_none() {
+            if let Some(parent) = self.config.db_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(self.wal_path())?;
+            self.wal = Some(file);
+        }
+        let wal = self.wal.as_mut().expect("log was just opened");
+        // One write per record, so a crash can only tear the last line
+        wal.write_all(&line)?;
+        wal.flush()?;
+        Ok(())
+    }
+
+    /// Path of the write-ahead log
+    fn wal_path(&self) -> PathBuf {
+        Self::sibling(&self.config.db_path, "wal")
+    }
+
+    /// Path a snapshot is written to before it replaces `db_path`
+    fn temp_path(&self) -> PathBuf {
+        Self::sibling(&self.config.db_path, "tmp")
+    }
+
+    fn sibling(path: &Path, suffix: &str) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".");
+        name.push(suffix);
+        PathBuf::from(name)
+    }
+
     /// Save state to disk
     pub fn save(&mut self) -> Result<(), SyncStateError> {
         if !self.dirty {
@@ -183,8 +336,20 @@ impl SyncStateManager {
         let data = serde_json::to_string_pretty(&saved)
             .map_err(|e| SyncStateError::ParseError(e.to_string()))?;
         
-        std::fs::write(&self.config.db_path, data)
-            .map_err(|e| SyncStateError::IoError(e))?;
+        // Write the snapshot aside and swap it in, so a crash never leaves
+        // a partial one at db_path
+        let temp_path = self.temp_path();
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(data.as_bytes())?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&temp_path, &self.config.db_path)?;
+
+        // The snapshot now holds everything in the log
+        self.wal = None;
+        File::create(self.wal_path())?;
+        self.wal_records = 0;
         
         self.dirty = false;
         debug!("Saved sync state: {} files", self.states.len());
@@ -210,23 +375,34 @@ impl SyncStateManager {
 
     /// Update or insert a file state
     pub fn upsert_state(&mut self, state: FileState) {
-        self.states.insert(state.path.clone(), state);
-        self.dirty = true;
+        self.states.insert(state.path.clone(), state.clone());
+        self.log(WalRecord::Upsert(state));
+    }
+
+    /// Change a tracked file's state and log the result
+    fn update(&mut self, path: &Path, change: impl FnOnce(&mut FileState)) {
+        if let Some(state) = self.states.get_mut(path) {
+            change(state);
+            let state = state.clone();
+            self.log(WalRecord::Upsert(state));
+        }
     }
 
     /// Mark a file as modified
     pub fn mark_modified(&mut self, path: &Path, size: u64, mtime: DateTime<Utc>) {
-        if let Some(state) = self.states.get_mut(path) {
-            state.size = size;
-            state.local_mtime = mtime;
-            state.status = SyncStatus::Modified;
-            self.dirty = true;
+        if self.states.contains_key(path) {
+            self.update(path, |state| {
+                state.size = size;
+                state.local_mtime = mtime;
+                state.status = SyncStatus::Modified;
+            });
         } else {
             self.upsert_state(FileState {
                 path: path.to_path_buf(),
                 size,
                 local_mtime: mtime,
                 remote_mtime: None,
+                remote_etag: None,
                 content_hash: None,
                 last_synced: None,
                 status: SyncStatus::New,
@@ -238,53 +414,54 @@ impl SyncStateManager {
 
     /// Mark a file as deleted
     pub fn mark_deleted(&mut self, path: &Path) {
-        if let Some(state) = self.states.get_mut(path) {
-            state.status = SyncStatus::Deleted;
-            self.dirty = true;
-        }
+        self.update(path, |state| state.status = SyncStatus::Deleted);
     }
 
     /// Mark a file as syncing
     pub fn mark_syncing(&mut self, path: &Path) {
-        if let Some(state) = self.states.get_mut(path) {
+        self.update(path, |state| {
             state.status = SyncStatus::Syncing;
             state.sync_attempts += 1;
-            self.dirty = true;
-        }
+        });
     }
 
     /// Mark a file as synced
     pub fn mark_synced(&mut self, path: &Path, content_hash: Option<String>) {
-        if let Some(state) = self.states.get_mut(path) {
+        self.update(path, |state| {
             state.status = SyncStatus::Synced;
             state.last_synced = Some(Utc::now());
             state.content_hash = content_hash;
             state.last_error = None;
-            self.dirty = true;
-        }
+        });
+    }
+
+    /// Record the version of the remote copy a sync left behind, which later
+    /// syncs compare against to notice remote changes
+    pub fn mark_remote(&mut self, path: &Path, etag: Option<String>, mtime: Option<DateTime<Utc>>) {
+        self.update(path, |state| {
+            state.remote_etag = etag;
+            state.remote_mtime = mtime;
+        });
     }
 
     /// Mark a file sync as failed
     pub fn mark_failed(&mut self, path: &Path, error: String) {
-        if let Some(state) = self.states.get_mut(path) {
+        self.update(path, |state| {
             state.status = SyncStatus::Failed;
             state.last_error = Some(error);
-            self.dirty = true;
-        }
+        });
     }
 
     /// Mark a file as having a conflict
     pub fn mark_conflict(&mut self, path: &Path) {
-        if let Some(state) = self.states.get_mut(path) {
-            state.status = SyncStatus::Conflict;
-            self.dirty = true;
-        }
+        self.update(path, |state| state.status = SyncStatus::Conflict);
     }
 
     /// Remove a file from tracking
     pub fn remove(&mut self, path: &Path) -> Option<FileState> {
-        self.dirty = true;
-        self.states.remove(path)
+        let removed = self.states.remove(path);
+        self.log(WalRecord::Remove(path.to_path_buf()));
+        removed
     }
 
     /// Add a history entry
@@ -292,8 +469,8 @@ impl SyncStateManager {
         let mut entry = entry;
         entry.id = self.next_history_id;
         self.next_history_id += 1;
-        self.history.push(entry);
-        self.dirty = true;
+        self.history.push(entry.clone());
+        self.log(WalRecord::History(entry));
         
         // Prune old history if needed
         self.prune_history();
@@ -395,7 +572,7 @@ impl SyncStateManager {
         self.states.clear();
         self.history.clear();
         self.next_history_id = 1;
-        self.dirty = true;
+        self.log(WalRecord::Clear);
     }
 }
 
@@ -423,6 +600,20 @@ struct SavedState {
     next_history_id: i64,
 }
 
+/// One change in the write-ahead log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", content = "data", rename_all = "snake_case")]
+enum WalRecord {
+    /// A file's state as it is after the change
+    Upsert(FileState),
+    /// A file stopped being tracked
+    Remove(PathBuf),
+    /// A history entry, with its assigned ID
+    History(SyncHistoryEntry),
+    /// All state was cleared
+    Clear,
+}
+
 /// Errors that can occur in sync state management
 #[derive(Debug, thiserror::Error)]
 pub enum SyncStateError {
@@ -466,6 +657,7 @@ mod tests {
             size: 1024,
             local_mtime: Utc::now(),
             remote_mtime: None,
+            remote_etag: None,
             content_hash: None,
             last_synced: None,
             status: SyncStatus::New,
@@ -585,4 +777,107 @@ mod tests {
             assert_eq!(state.unwrap().size, 999);
         }
     }
+
+    #[test]
+    fn test_wal_recovers_after_crash_mid_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = SyncStateConfig {
+            db_path: temp_dir.path().join("state.db"),
+            ..Default::default()
+        };
+        let kept = PathBuf::from("/kept.txt");
+        let changed = PathBuf::from("/changed.txt");
+        let removed = PathBuf::from("/removed.txt");
+
+        {
+            let mut manager = SyncStateManager::new(config.clone()).unwrap();
+            manager.mark_modified(&kept, 1, Utc::now());
+            manager.mark_modified(&removed, 2, Utc::now());
+            manager.record_sync(&kept, SyncAction::Upload, true, 1, 5, None);
+            manager.save().unwrap();
+
+            // Changes after the last snapshot only reach the log
+            manager.mark_modified(&changed, 3, Utc::now());
+            manager.mark_synced(&changed, Some("hash".to_string()));
+            manager.remove(&removed);
+            manager.record_sync(&changed, SyncAction::Upload, true, 3, 7, None);
+            // The process dies here, without saving
+        }
+
+        // ...halfway through writing the next snapshot, and through a log record
+        let snapshot = std::fs::read(&config.db_path).unwrap();
+        std::fs::write(SyncStateManager::sibling(&config.db_path, "tmp"), &snapshot[..snapshot.len() / 2]).unwrap();
+        let wal_path = SyncStateManager::sibling(&config.db_path, "wal");
+        let mut wal = OpenOptions::new().append(true).open(&wal_path).unwrap();
+        wal.write_all(br#"{"op":"upsert","data":{"path":"/torn"#).unwrap();
+        drop(wal);
+
+        let manager = SyncStateManager::new(config.clone()).unwrap();
+        assert_eq!(manager.get_state(&kept).unwrap().size, 1);
+        let state = manager.get_state(&changed).unwrap();
+        assert_eq!(state.status, SyncStatus::Synced);
+        assert_eq!(state.content_hash.as_deref(), Some("hash"));
+        assert!(manager.get_state(&removed).is_none());
+        assert!(manager.get_state(&PathBuf::from("/torn")).is_none());
+        let ids: Vec<i64> = manager.history.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        // Recovery wrote a clean snapshot and emptied the log
+        assert!(!SyncStateManager::sibling(&config.db_path, "tmp").exists());
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+        let snapshot: SavedState = serde_json::from_slice(&std::fs::read(&config.db_path).unwrap()).unwrap();
+        assert_eq!(snapshot.states.len(), 2);
+        assert_eq!(snapshot.next_history_id, 3);
+    }
+
+    #[test]
+    fn test_wal_replay_after_snapshot_does_not_duplicate_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = SyncStateConfig {
+            db_path: temp_dir.path().join("state.db"),
+            ..Default::default()
+        };
+        let path = PathBuf::from("/file.txt");
+
+        {
+            let mut manager = SyncStateManager::new(config.clone()).unwrap();
+            manager.mark_modified(&path, 10, Utc::now());
+            manager.record_sync(&path, SyncAction::Upload, true, 10, 1, None);
+        }
+        let wal_path = SyncStateManager::sibling(&config.db_path, "wal");
+        let log = std::fs::read(&wal_path).unwrap();
+        {
+            let mut manager = SyncStateManager::new(config.clone()).unwrap();
+            manager.record_sync(&path, SyncAction::Upload, true, 10, 1, None);
+            manager.save().unwrap();
+        }
+        // The process died after the snapshot was swapped in but before the
+        // log was emptied
+        std::fs::write(&wal_path, &log).unwrap();
+
+        let manager = SyncStateManager::new(config).unwrap();
+        let ids: Vec<i64> = manager.history.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(manager.get_state(&path).unwrap().size, 10);
+    }
+
+    #[test]
+    fn test_wal_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = SyncStateConfig {
+            db_path: temp_dir.path().join("state.db"),
+            wal_compaction_threshold: 3,
+            ..Default::default()
+        };
+        let mut manager = SyncStateManager::new(config.clone()).unwrap();
+
+        manager.mark_modified(&PathBuf::from("/a"), 1, Utc::now());
+        manager.mark_modified(&PathBuf::from("/b"), 1, Utc::now());
+        assert!(!config.db_path.exists());
+
+        manager.mark_modified(&PathBuf::from("/c"), 1, Utc::now());
+        assert!(config.db_path.exists());
+        assert_eq!(std::fs::metadata(SyncStateManager::sibling(&config.db_path, "wal")).unwrap().len(), 0);
+        assert!(!manager.dirty);
+    }
 }
@@ -0,0 +1,381 @@
+//! Temp files and spill-to-disk buffers
+//!
+//! Every temp file goes through [`TempFiles`], so `backup.temp_dir` decides
+//! where archives, manifests and downloads being checked are staged. Unset,
+//! `SKYLOCK_TEMP_DIR` is used, then `TMPDIR`, then the system default.
+//!
+//! Transfers no larger than `backup.spill_threshold` skip the disk: uploads
+//! are sent from memory, and downloads land in a [`SpillBuffer`], which
+//! only moves to a temp file once the data outgrows the threshold.
+//!
+//! Files that have to live at a fixed path, such as the staging file of an
+//! atomic write or a file being restored, are covered by a [`CleanupGuard`]
+//! instead, so an error, a panic or a cancelled task never leaves them half
+//! written.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use skylock_core::{BackupConfig, DEFAULT_SPILL_THRESHOLD};
+use skylock_hetzner::HetznerClient;
+use tempfile::{NamedTempFile, TempDir};
+use tokio::io::AsyncWrite;
+use tracing::warn;
+
+use crate::error::{Result, SkylockError};
+
+/// Where temp files go and how much a transfer may keep in memory
+#[derive(Debug, Clone)]
+pub struct TempFiles {
+    dir: PathBuf,
+    spill_threshold: u64,
+}
+
+impl Default for TempFiles {
+    fn default() -> Self {
+        Self::new(Self::default_dir())
+    }
+}
+
+impl TempFiles {
+    /// Temp files in `dir`, spilling above [`DEFAULT_SPILL_THRESHOLD`]
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, spill_threshold: DEFAULT_SPILL_THRESHOLD }
+    }
+
+    /// Settings from the `[backup]` section
+    ///
+    /// An invalid `spill_threshold` falls back to the default with a
+    /// warning; `Config::validate` and `check-config` report it.
+    pub fn from_config(config: &BackupConfig) -> Self {
+        let dir = config.temp_dir.clone().unwrap_or_else(Self::default_dir);
+        let spill_threshold = config.spill_threshold_bytes().unwrap_or_else(|e| {
+            warn!("Ignoring backup.spill_threshold: {}", e);
+            DEFAULT_SPILL_THRESHOLD
+        });
+        Self { dir, spill_threshold }
+    }
+
+    /// Keep transfers up to `bytes` in memory (0 = always use a file)
+    pub fn with_spill_threshold(mut self, bytes: u64) -> Self {
+        self.spill_threshold = bytes;
+        self
+    }
+
+    /// `SKYLOCK_TEMP_DIR`, else `TMPDIR`, else the system temp directory
+    fn default_dir() -> PathBuf {
+        std::env::var_os("SKYLOCK_TEMP_DIR")
+            .or_else(|| std::env::var_os("TMPDIR"))
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn spill_threshold(&self) -> u64 {
+        self.spill_threshold
+    }
+
+    /// Whether `len` bytes are staged in a temp file rather than memory
+    pub fn spills(&self, len: u64) -> bool {
+        len > self.spill_threshold
+    }
+
+    /// Temp file that is deleted when dropped, also on early returns
+    pub fn file(&self, what: &str) -> Result<NamedTempFile> {
+        NamedTempFile::new_in(&self.dir).map_err(|e| SkylockError::Backup(format!(
+            "Failed to create temp {} file in {}: {}", what, self.dir.display(), e
+        )))
+    }
+
+    /// Temp directory that is removed with its contents when dropped
+    pub fn directory(&self, what: &str) -> Result<TempDir> {
+        TempDir::new_in(&self.dir).map_err(|e| SkylockError::Backup(format!(
+            "Failed to create temp {} directory in {}: {}", what, self.dir.display(), e
+        )))
+    }
+
+    /// Empty buffer that spills into this directory
+    pub fn buffer(&self) -> SpillBuffer {
+        SpillBuffer::new(self.dir.clone(), self.spill_threshold)
+    }
+
+    /// Upload `data`, from memory if it is within the spill threshold
+    pub async fn upload(&self, hetzner: &HetznerClient, data: &[u8], remote_path: &Path) -> Result<()> {
+        if !self.spills(data.len() as u64) {
+            hetzner.upload_bytes(data.to_vec(), remote_path).await?;
+            return Ok(());
+        }
+        let temp_file = self.file("upload")?;
+        tokio::fs::write(temp_file.path(), data).await?;
+        hetzner.upload_file(temp_file.path(), remote_path).await?;
+        Ok(())
+    }
+
+    /// Download `remote_path` into a buffer that spills past the threshold
+    pub async fn download(&self, hetzner: &HetznerClient, remote_path: &Path) -> Result<SpillBuffer> {
+        let mut buffer = self.buffer();
+        hetzner.download_to(remote_path, &mut buffer).await?;
+        Ok(buffer)
+    }
+
+    /// Download `remote_path` and return its contents
+    pub async fn download_bytes(&self, hetzner: &HetznerClient, remote_path: &Path) -> Result<Vec<u8>> {
+        Ok(self.download(hetzner, remote_path).await?.into_bytes()?)
+    }
+}
+
+/// Removes the file at a path when dropped, unless it was kept
+///
+/// Create the guard before the file, and [`keep`](Self::keep) it once the
+/// file is complete (or renamed away). Every other way out of the scope
+/// (`?`, a panic, or the future being dropped) removes the file.
+#[derive(Debug)]
+#[must_use = "the file is removed as soon as the guard is dropped"]
+pub struct CleanupGuard {
+    path: Option<PathBuf>,
+}
+
+impl CleanupGuard {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: Some(path.into()) }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_deref().expect("path is only taken on drop or keep")
+    }
+
+    /// Leave the file in place
+    pub fn keep(mut self) -> PathBuf {
+        self.path.take().expect("path is only taken on drop or keep")
+    }
+}
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+/// Bytes kept in memory until they exceed a threshold, then in a temp file
+#[derive(Debug)]
+pub struct SpillBuffer {
+    memory: Vec<u8>,
+    file: Option<NamedTempFile>,
+    len: u64,
+    threshold: u64,
+    dir: PathBuf,
+}
+
+impl SpillBuffer {
+    /// Buffer that moves to a temp file in `dir` once it holds more than
+    /// `threshold` bytes
+    pub fn new(dir: PathBuf, threshold: u64) -> Self {
+        Self { memory: Vec::new(), file: None, len: 0, threshold, dir }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the contents moved to a temp file
+    pub fn is_spilled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Path of the temp file, once spilled
+    pub fn path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|file| file.path())
+    }
+
+    /// The contents, read back from the temp file if spilled
+    pub fn into_bytes(mut self) -> io::Result<Vec<u8>> {
+        match self.file.take() {
+            Some(file) => std::fs::read(file.path()),
+            None => Ok(self.memory),
+        }
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        let mut file = NamedTempFile::new_in(&self.dir)?;
+        file.write_all(&self.memory)?;
+        self.memory = Vec::new();
+        self.file = Some(file);
+        Ok(())
+    }
+}
+
+impl Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() && self.len + buf.len() as u64 > self.threshold {
+            self.spill()?;
+        }
+        match &mut self.file {
+            Some(file) => file.write_all(buf)?,
+            None => self.memory.extend_from_slice(buf),
+        }
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes go straight to memory or the local temp file, so they complete
+/// immediately
+impl AsyncWrite for SpillBuffer {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(Write::write(self.get_mut(), buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Write::flush(self.get_mut()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir_entries(dir: &Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn test_temp_files_land_in_configured_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config: BackupConfig = serde_json::from_value(serde_json::json!({
+            "vss_enabled": false,
+            "schedule": "",
+            "retention_days": 1,
+            "backup_paths": [],
+            "temp_dir": dir.path(),
+            "spill_threshold": "1K",
+        })).unwrap();
+        let temp = TempFiles::from_config(&config);
+        assert_eq!(temp.dir(), dir.path());
+        assert_eq!(temp.spill_threshold(), 1024);
+
+        let file = temp.file("manifest").unwrap();
+        assert_eq!(file.path().parent().unwrap(), dir.path());
+        let sub = temp.directory("restore").unwrap();
+        assert_eq!(sub.path().parent().unwrap(), dir.path());
+        assert_eq!(dir_entries(dir.path()), 2);
+        drop((file, sub));
+        assert_eq!(dir_entries(dir.path()), 0);
+
+        // Unset, the threshold is the default
+        let config = BackupConfig { spill_threshold: None, ..config };
+        assert_eq!(TempFiles::from_config(&config).spill_threshold(), DEFAULT_SPILL_THRESHOLD);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_guard_removes_file_on_every_early_exit() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state.json.tmp");
+
+        // Error after the file was created
+        let failing = || -> Result<()> {
+            let staged = CleanupGuard::new(&path);
+            std::fs::write(staged.path(), b"partial")?;
+            Err(SkylockError::Backup("injected failure".to_string()))
+        };
+        assert!(failing().is_err());
+        assert_eq!(dir_entries(dir.path()), 0);
+
+        // Panic after the file was created
+        let panicked = std::panic::catch_unwind(|| {
+            let staged = CleanupGuard::new(&path);
+            std::fs::write(staged.path(), b"partial").unwrap();
+            panic!("injected panic");
+        });
+        assert!(panicked.is_err());
+        assert_eq!(dir_entries(dir.path()), 0);
+
+        // Task cancelled while the file was being written
+        let cancelled = tokio::time::timeout(std::time::Duration::from_millis(50), async {
+            let staged = CleanupGuard::new(&path);
+            tokio::fs::write(staged.path(), b"partial").await.unwrap();
+            std::future::pending::<()>().await;
+        }).await;
+        assert!(cancelled.is_err());
+        assert_eq!(dir_entries(dir.path()), 0);
+
+        // A kept file stays
+        let staged = CleanupGuard::new(&path);
+        std::fs::write(staged.path(), b"complete").unwrap();
+        assert_eq!(staged.keep(), path);
+        assert_eq!(std::fs::read(&path).unwrap(), b"complete");
+    }
+
+    #[test]
+    fn test_spill_switches_at_threshold() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let temp = TempFiles::new(dir.path().to_path_buf()).with_spill_threshold(100);
+        assert!(!temp.spills(100));
+        assert!(temp.spills(101));
+
+        // Up to the threshold everything stays in memory
+        let mut buffer = temp.buffer();
+        buffer.write_all(&[1; 60]).unwrap();
+        buffer.write_all(&[2; 40]).unwrap();
+        assert!(!buffer.is_spilled());
+        assert_eq!(dir_entries(dir.path()), 0);
+
+        // One byte more moves it, and what follows, to a file in the dir
+        buffer.write_all(&[3]).unwrap();
+        buffer.write_all(&[4; 50]).unwrap();
+        assert!(buffer.is_spilled());
+        assert_eq!(buffer.len(), 151);
+        assert_eq!(buffer.path().unwrap().parent().unwrap(), dir.path());
+        assert_eq!(dir_entries(dir.path()), 1);
+
+        let bytes = buffer.into_bytes().unwrap();
+        let expected: Vec<u8> = [vec![1; 60], vec![2; 40], vec![3], vec![4; 50]].concat();
+        assert_eq!(bytes, expected);
+        assert_eq!(dir_entries(dir.path()), 0);
+
+        // A zero threshold always spills, but an empty buffer needs no file
+        let mut buffer = TempFiles::new(dir.path().to_path_buf()).with_spill_threshold(0).buffer();
+        assert!(!buffer.is_spilled() && buffer.is_empty());
+        buffer.write_all(b"x").unwrap();
+        assert!(buffer.is_spilled());
+    }
+
+    #[tokio::test]
+    async fn test_async_writes_spill() {
+        use tokio::io::AsyncWriteExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut buffer = SpillBuffer::new(dir.path().to_path_buf(), 8);
+        AsyncWriteExt::write_all(&mut buffer, b"small").await.unwrap();
+        assert!(!buffer.is_spilled());
+        AsyncWriteExt::write_all(&mut buffer, b" and then larger").await.unwrap();
+        AsyncWriteExt::flush(&mut buffer).await.unwrap();
+        assert!(buffer.is_spilled());
+        assert_eq!(buffer.into_bytes().unwrap(), b"small and then larger");
+    }
+}
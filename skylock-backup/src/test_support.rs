@@ -0,0 +1,73 @@
+//! Fixtures shared by the unit tests
+
+use std::path::Path;
+
+use skylock_core::Config;
+
+/// Config for a Storage Box at `endpoint`, keeping state in `data_dir` and
+/// otherwise using the defaults
+pub(crate) fn test_config(endpoint: &str, data_dir: &Path) -> Config {
+    Config {
+        syncthing: skylock_core::SyncthingConfig {
+            api_key: String::new(),
+            api_url: String::new(),
+            folders: vec![],
+        },
+        hetzner: skylock_core::HetznerConfig {
+            endpoint: endpoint.to_string(),
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            encryption_key: "test_password".to_string(),
+            max_list_requests_per_second: None,
+            max_delete_requests_per_second: None,
+            connect_timeout_secs: 30,
+            read_timeout_secs: 120,
+            protocol: None,
+            sftp: None,
+        },
+        backup: skylock_core::BackupConfig {
+            vss_enabled: false,
+            schedule: String::new(),
+            retention_days: 30,
+            backup_paths: vec![],
+            max_speed_limit: None,
+            encryption_algorithm: None,
+            encrypt_file_names: false,
+            max_chain_length: None,
+            canonical_manifests: false,
+            compress_manifests: false,
+            compression_algorithm: None,
+            compression_profiles: Default::default(),
+            blob_shard_depth: 2,
+            max_concurrent_uploads: None,
+            mirrors: Vec::new(),
+            temp_dir: None,
+            spill_threshold: None,
+            block_cache_size: None,
+            restore_dir: None,
+            stream_threshold: None,
+            max_file_size: None,
+            skip_oversized_files: false,
+            trash_days: None,
+            list_concurrency: None,
+            delete_concurrency: None,
+            require_signed_manifests: false,
+            manifest_public_key: None,
+            aead_frame_size: None,
+            hash_algorithm: None,
+            key_exchange: None,
+            retry: Default::default(),
+        },
+        ui: skylock_core::UiConfig {
+            always_prompt_deletions: false,
+            notification_enabled: false,
+            deletion_default: None,
+        },
+        notifications: Default::default(),
+        metrics: Default::default(),
+        credentials: Default::default(),
+        logging: Default::default(),
+        scrub: Default::default(),
+        data_dir: data_dir.to_path_buf(),
+    }
+}
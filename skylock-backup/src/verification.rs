@@ -2,17 +2,38 @@
 //!
 //! Verifies backup integrity by checking manifests, file existence, and optionally
 //! downloading files to verify their content hashes.
+//!
+//! A verification can be stopped through a [`CancellationToken`]; it then
+//! returns the files checked so far with [`VerificationResult::incomplete`]
+//! set. Downloads still in flight are dropped along with their temp files.
+//!
+//! With replicas configured ([`BackupVerifier::with_replicas`]), a full
+//! verification checks every backend's copy of each blob. Damaged files are
+//! classified as repairable when another backend holds an intact copy, and
+//! [`BackupVerifier::repair`] rewrites the damaged copies from it.
+//!
+//! With a checkpoint directory ([`BackupVerifier::with_checkpoint_dir`]), a
+//! full verification records the files it found intact, and a later run of
+//! the same backup only downloads the rest (see [`crate::verify_checkpoint`]).
 
 use crate::error::{Result, SkylockError};
-use crate::direct_upload::{BackupManifest, FileEntry};
+use crate::direct_upload::{BackupManifest, DirectUploadBackup, FileEntry};
+use crate::encryption::EncryptionManager;
+use crate::resume_state::CHECKPOINT_INTERVAL;
+use crate::verify_checkpoint::VerifyCheckpoint;
+use crate::temp_files::TempFiles;
+use skylock_core::storage::{MultiBackend, ReplicaCheck};
 use skylock_hetzner::HetznerClient;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::Semaphore;
+use tokio::sync::{Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
 use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Serialize, Deserialize};
 
 /// Verification result for a single file
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileVerification {
     /// File path
     pub path: PathBuf,
@@ -22,10 +43,14 @@ pub struct FileVerification {
     pub hash_verified: Option<bool>,
     /// Error message if verification failed
     pub error: Option<String>,
+    /// For failed files checked against replicas: whether another backend
+    /// holds an intact copy to repair from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repairable: Option<bool>,
 }
 
 /// Overall verification result
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct VerificationResult {
     /// Backup ID that was verified
     pub backup_id: String,
@@ -43,12 +68,15 @@ pub struct VerificationResult {
     pub file_results: Vec<FileVerification>,
     /// Overall verification passed
     pub passed: bool,
+    /// Verification was cancelled before every file was checked
+    #[serde(default)]
+    pub incomplete: bool,
 }
 
 impl VerificationResult {
     /// Check if verification passed
     pub fn is_success(&self) -> bool {
-        self.passed && self.files_with_errors == 0
+        self.passed && !self.incomplete && self.files_with_errors == 0
     }
     
     /// Get list of missing files
@@ -66,24 +94,133 @@ impl VerificationResult {
             .filter(|f| matches!(f.hash_verified, Some(false)))
             .collect()
     }
+    
+    /// Get list of failed files with an intact copy on another backend
+    pub fn repairable_files(&self) -> Vec<&FileVerification> {
+        self.file_results
+            .iter()
+            .filter(|f| f.repairable == Some(true))
+            .collect()
+    }
+    
+    /// Get list of failed files without a known intact copy
+    pub fn unrepairable_files(&self) -> Vec<&FileVerification> {
+        self.file_results
+            .iter()
+            .filter(|f| f.error.is_some() && f.repairable != Some(true))
+            .collect()
+    }
+}
+
+/// Outcome of [`BackupVerifier::repair`]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RepairReport {
+    /// Files whose damaged copies were rewritten on every backend
+    pub repaired: Vec<PathBuf>,
+    /// Files that could not be repaired
+    pub failed: Vec<RepairFailure>,
+}
+
+/// A file [`BackupVerifier::repair`] could not fix
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairFailure {
+    pub path: PathBuf,
+    pub error: String,
 }
 
+impl RepairReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Progress callback, called with (files checked, total files)
+pub type ProgressCallback = Arc<dyn Fn(usize, usize) + Send + Sync>;
+
 /// Backup verifier
 pub struct BackupVerifier {
     hetzner: Arc<HetznerClient>,
     max_parallel: usize,
+    /// Stops the verification early when cancelled
+    cancel: CancellationToken,
+    /// Called after each file is checked
+    progress: Option<ProgressCallback>,
+    /// Where downloads too large to keep in memory are staged
+    temp: TempFiles,
+    /// Every copy of the backup, checked instead of the Storage Box alone
+    replicas: Option<Arc<MultiBackend>>,
+    /// Where full verifications record their progress, to resume from
+    checkpoint_dir: Option<PathBuf>,
 }
 
 impl BackupVerifier {
     /// Create new backup verifier
     pub fn new(hetzner: HetznerClient) -> Self {
-        let max_parallel = std::thread::available_parallelism()
-            .map(|n| n.get().min(4))
-            .unwrap_or(2);
-        
         Self {
             hetzner: Arc::new(hetzner),
-            max_parallel,
+            max_parallel: crate::parallelism::default_concurrency(),
+            cancel: CancellationToken::new(),
+            progress: None,
+            temp: TempFiles::default(),
+            replicas: None,
+            checkpoint_dir: None,
+        }
+    }
+    
+    /// Check up to `concurrency` files at once (clamped to
+    /// 1..=[`MAX_CONCURRENCY`](crate::parallelism::MAX_CONCURRENCY))
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.max_parallel = concurrency.clamp(1, crate::parallelism::MAX_CONCURRENCY);
+        self
+    }
+    
+    /// Stop verifying when `cancel` is cancelled, returning a partial result
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+    
+    /// Report progress after each file is checked
+    pub fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+    
+    /// Download files for checking into `dir` instead of the system temp directory
+    pub fn with_temp_dir(mut self, dir: PathBuf) -> Self {
+        self.temp = TempFiles::new(dir).with_spill_threshold(self.temp.spill_threshold());
+        self
+    }
+    
+    /// Stage downloads as `temp` says, e.g. [`TempFiles::from_config`]
+    pub fn with_temp_files(mut self, temp: TempFiles) -> Self {
+        self.temp = temp;
+        self
+    }
+    
+    /// Check every copy in `replicas` during full verification, so damaged
+    /// files can be classified and repaired
+    ///
+    /// `replicas` should include the Storage Box itself (see
+    /// [`crate::HetznerBackend`]); blobs are addressed by their remote path
+    /// relative to each backend's root.
+    pub fn with_replicas(mut self, replicas: Arc<MultiBackend>) -> Self {
+        self.replicas = Some(replicas);
+        self
+    }
+    
+    /// Record files a full verification found intact in `dir`, and skip
+    /// the ones an interrupted earlier run already checked
+    pub fn with_checkpoint_dir(mut self, dir: PathBuf) -> Self {
+        self.checkpoint_dir = Some(dir);
+        self
+    }
+    
+    /// Count a checked file and notify the progress callback
+    fn report_progress(progress: &Option<ProgressCallback>, completed: &AtomicUsize, total: usize) {
+        let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(callback) = progress {
+            callback(done, total);
         }
     }
     
@@ -103,58 +240,61 @@ impl BackupVerifier {
         pb.set_message("📂 Checking files...");
         
         let semaphore = Arc::new(Semaphore::new(self.max_parallel));
+        let completed = Arc::new(AtomicUsize::new(0));
         let mut tasks = Vec::new();
         
         for file in &manifest.files {
             let sem = semaphore.clone();
             let hetzner = self.hetzner.clone();
-            let remote_path = PathBuf::from(&file.remote_path);
+            let remote_paths = Self::blob_paths(file);
             let local_path = file.local_path.clone();
             let pb_clone = pb.clone();
+            let cancel = self.cancel.clone();
+            let progress = self.progress.clone();
+            let completed = completed.clone();
+            let temp = self.temp.clone();
             
             let task = tokio::spawn(async move {
-                let _permit = sem.acquire().await.unwrap();
-                
-                // Check if file exists by attempting to get metadata
-                // Create a temp file path for testing
-                let temp_test = tempfile::NamedTempFile::new()
-                    .map_err(|_| "Failed to create temp file".to_string());
-                
-                let exists = match temp_test {
-                    Ok(temp) => {
-                        hetzner.download_file(&remote_path, &temp.path().to_path_buf())
-                            .await
-                            .is_ok()
+                // First blob of the file that is missing, if any
+                let check = async {
+                    let _permit = sem.acquire().await.unwrap();
+                    
+                    // Check if file exists by attempting to download it
+                    for remote_path in &remote_paths {
+                        if temp.download(&hetzner, remote_path).await.is_err() {
+                            return Some(remote_path.clone());
+                        }
                     }
-                    Err(_) => false,
+                    None
                 };
                 
+                // Dropping the check on cancellation removes its temp file
+                let missing = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => return None,
+                    missing = check => missing,
+                };
+                let exists = missing.is_none();
+                
                 pb_clone.inc(1);
+                Self::report_progress(&progress, &completed, total_files);
                 
-                FileVerification {
+                Some(FileVerification {
                     path: local_path,
                     exists,
                     hash_verified: None,
-                    error: if !exists {
-                        Some(format!("File not found on remote: {}", remote_path.display()))
-                    } else {
-                        None
-                    },
-                }
+                    error: missing.map(|remote_path| {
+                        format!("File not found on remote: {}", remote_path.display())
+                    }),
+                    repairable: None,
+                })
             });
             
             tasks.push(task);
         }
         
-        let mut file_results = Vec::new();
-        for task in tasks {
-            match task.await {
-                Ok(result) => file_results.push(result),
-                Err(e) => {
-                    return Err(SkylockError::Backup(format!("Verification task failed: {}", e)));
-                }
-            }
-        }
+        let file_results = Self::collect_results(tasks).await?;
+        let incomplete = file_results.len() < total_files;
         
         pb.finish_and_clear();
         
@@ -169,7 +309,8 @@ impl BackupVerifier {
             files_verified: 0,
             files_with_errors,
             file_results,
-            passed: files_exist == total_files,
+            passed: !incomplete && files_exist == total_files,
+            incomplete,
         })
     }
     
@@ -177,7 +318,7 @@ impl BackupVerifier {
     pub async fn verify_full(
         &self,
         manifest: &BackupManifest,
-        encryption: Arc<crate::encryption::EncryptionManager>,
+        encryption: Arc<EncryptionManager>,
     ) -> Result<VerificationResult> {
         println!("🔍 Running full verification (downloading and verifying hashes)...");
         println!("⚠️  This will download all backup files and may take significant time.");
@@ -193,33 +334,98 @@ impl BackupVerifier {
         );
         pb.set_message("🔐 Verifying files...");
         
+        let checkpoint = match &self.checkpoint_dir {
+            Some(dir) => Some(VerifyCheckpoint::load_or_new(dir, manifest).await),
+            None => None,
+        };
+        let resumed = checkpoint.as_ref().map_or(0, |c| {
+            manifest.files.iter().filter(|f| c.is_verified(&f.local_path)).count()
+        });
+        if resumed > 0 {
+            pb.println(format!("⏩ Resuming: {} of {} files already verified", resumed, total_files));
+            pb.set_position(resumed as u64);
+        }
+        let checkpoint = checkpoint.map(|c| Arc::new(Mutex::new(c)));
+        
         let semaphore = Arc::new(Semaphore::new(self.max_parallel));
+        let completed = Arc::new(AtomicUsize::new(resumed));
+        let shared_manifest = Arc::new(manifest.clone());
         let mut tasks = Vec::new();
         
         for file in &manifest.files {
+            if let Some(checkpoint) = &checkpoint {
+                if checkpoint.lock().await.is_verified(&file.local_path) {
+                    let verified = FileVerification {
+                        path: file.local_path.clone(),
+                        exists: true,
+                        hash_verified: Some(true),
+                        error: None,
+                        repairable: None,
+                    };
+                    tasks.push(tokio::spawn(async move { Some(verified) }));
+                    continue;
+                }
+            }
+            
             let sem = semaphore.clone();
             let hetzner = self.hetzner.clone();
+            let replicas = self.replicas.clone();
+            let manifest = shared_manifest.clone();
             let encryption = encryption.clone();
-            let remote_path = PathBuf::from(&file.remote_path);
+            let entry = file.clone();
             let local_path = file.local_path.clone();
-            let expected_hash = file.hash.clone();
             let pb_clone = pb.clone();
+            let cancel = self.cancel.clone();
+            let progress = self.progress.clone();
+            let completed = completed.clone();
+            let temp = self.temp.clone();
+            let checkpoint = checkpoint.clone();
+            let checkpoint_dir = self.checkpoint_dir.clone();
             
             let task = tokio::spawn(async move {
-                let _permit = sem.acquire().await.unwrap();
+                let check = async {
+                    let _permit = sem.acquire().await.unwrap();
+                    
+                    match replicas {
+                        // Replicas are checked per blob; chunked files only
+                        // on the primary storage
+                        Some(replicas) if !entry.is_chunked() => Ok(Self::check_replicas(
+                            &replicas,
+                            &manifest,
+                            &entry,
+                            &encryption,
+                        ).await),
+                        // Download and verify file
+                        _ => Self::verify_file_hash(
+                            hetzner.as_ref(),
+                            &manifest,
+                            &entry,
+                            encryption.as_ref(),
+                            &temp,
+                        ).await.map(|verified| (verified, None)),
+                    }
+                };
                 
-                // Download and verify file
-                let result = Self::verify_file_hash(
-                    hetzner.as_ref(),
-                    &remote_path,
-                    &expected_hash,
-                    encryption.as_ref(),
-                ).await;
+                // Dropping the check on cancellation removes its temp file
+                let result = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => return None,
+                    result = check => result,
+                };
                 
                 pb_clone.inc(1);
+                Self::report_progress(&progress, &completed, total_files);
                 
-                match result {
-                    Ok(verified) => FileVerification {
+                if let (Ok((true, _)), Some(checkpoint), Some(dir)) = (&result, &checkpoint, &checkpoint_dir) {
+                    let mut checkpoint = checkpoint.lock().await;
+                    checkpoint.mark_verified(local_path.clone());
+                    if let Err(e) = checkpoint.checkpoint(dir, CHECKPOINT_INTERVAL).await {
+                        tracing::warn!("Failed to save verification checkpoint: {}", e);
+                    }
+                }
+                
+                Some(match result {
+                    Ok((verified, None)) => FileVerification {
                         path: local_path,
                         exists: true,
                         hash_verified: Some(verified),
@@ -228,31 +434,39 @@ impl BackupVerifier {
                         } else {
                             None
                         },
+                        repairable: None,
                     },
+                    Ok((_, Some(check))) => Self::replica_verification(local_path, &check),
                     Err(e) => FileVerification {
                         path: local_path,
                         exists: false,
                         hash_verified: Some(false),
                         error: Some(format!("Verification failed: {}", e)),
+                        repairable: None,
                     },
-                }
+                })
             });
             
             tasks.push(task);
         }
         
-        let mut file_results = Vec::new();
-        for task in tasks {
-            match task.await {
-                Ok(result) => file_results.push(result),
-                Err(e) => {
-                    return Err(SkylockError::Backup(format!("Verification task failed: {}", e)));
-                }
-            }
-        }
+        let file_results = Self::collect_results(tasks).await?;
+        let incomplete = file_results.len() < total_files;
         
         pb.finish_and_clear();
         
+        // Keep the checkpoint for the next run while files remain unchecked
+        if let (Some(checkpoint), Some(dir)) = (checkpoint, &self.checkpoint_dir) {
+            let saved = if incomplete {
+                checkpoint.lock().await.save(dir).await
+            } else {
+                VerifyCheckpoint::delete(dir, &manifest.backup_id).await
+            };
+            if let Err(e) = saved {
+                tracing::warn!("Failed to update verification checkpoint: {}", e);
+            }
+        }
+        
         let files_exist = file_results.iter().filter(|f| f.exists).count();
         let files_verified = file_results.iter()
             .filter(|f| matches!(f.hash_verified, Some(true)))
@@ -267,51 +481,420 @@ impl BackupVerifier {
             files_verified,
             files_with_errors,
             file_results,
-            passed: files_verified == total_files,
+            passed: !incomplete && files_verified == total_files,
+            incomplete,
         })
     }
     
+    /// Wait for all file checks, keeping the ones that finished before cancellation
+    async fn collect_results(
+        tasks: Vec<tokio::task::JoinHandle<Option<FileVerification>>>,
+    ) -> Result<Vec<FileVerification>> {
+        let mut file_results = Vec::new();
+        for task in tasks {
+            match task.await {
+                Ok(Some(result)) => file_results.push(result),
+                Ok(None) => {}
+                Err(e) => {
+                    return Err(SkylockError::Backup(format!("Verification task failed: {}", e)));
+                }
+            }
+        }
+        Ok(file_results)
+    }
+    
+    /// Rewrite the damaged copies of every repairable file in `result`
+    /// from an intact replica
+    ///
+    /// Run [`Self::verify_full`] again afterwards to confirm the repair.
+    pub async fn repair(
+        &self,
+        manifest: &BackupManifest,
+        result: &VerificationResult,
+        encryption: &EncryptionManager,
+    ) -> Result<RepairReport> {
+        let replicas = self.replicas.as_ref().ok_or_else(|| {
+            SkylockError::Backup("No replicas configured to repair from".to_string())
+        })?;
+        
+        let mut report = RepairReport::default();
+        for file in result.file_results.iter().filter(|f| f.error.is_some()) {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            
+            let Some(entry) = manifest.files.iter().find(|e| e.local_path == file.path) else {
+                continue;
+            };
+            if file.repairable != Some(true) {
+                report.failed.push(RepairFailure {
+                    path: file.path.clone(),
+                    error: "No intact copy on any backend".to_string(),
+                });
+                continue;
+            }
+            
+            let repair = replicas.repair_replicas(
+                &Self::replica_path(entry),
+                |data| Self::blob_matches(manifest, entry, encryption, data).unwrap_or(false),
+            ).await;
+            match repair {
+                Ok(fan_out) if fan_out.failed_backends().is_empty() => {
+                    report.repaired.push(file.path.clone());
+                }
+                Ok(fan_out) => report.failed.push(RepairFailure {
+                    path: file.path.clone(),
+                    error: format!("Rewrite failed on: {}", fan_out.failed_backends().join(", ")),
+                }),
+                Err(e) => report.failed.push(RepairFailure {
+                    path: file.path.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        
+        Ok(report)
+    }
+    
+    /// Remote paths of the blobs holding a file
+    fn blob_paths(entry: &FileEntry) -> Vec<PathBuf> {
+        if entry.is_chunked() {
+            entry.chunks.iter().map(|chunk| PathBuf::from(&chunk.remote_path)).collect()
+        } else {
+            vec![PathBuf::from(&entry.remote_path)]
+        }
+    }
+    
+    /// Blob path relative to each replica's root
+    fn replica_path(entry: &FileEntry) -> PathBuf {
+        PathBuf::from(entry.remote_path.trim_start_matches('/'))
+    }
+    
+    /// Check every replica of a file; the result is `(all intact, check)`
+    async fn check_replicas(
+        replicas: &MultiBackend,
+        manifest: &BackupManifest,
+        entry: &FileEntry,
+        encryption: &EncryptionManager,
+    ) -> (bool, Option<ReplicaCheck>) {
+        let check = replicas.check_replicas(
+            &Self::replica_path(entry),
+            |data| Self::blob_matches(manifest, entry, encryption, data).unwrap_or(false),
+        ).await;
+        (check.is_healthy(), Some(check))
+    }
+    
+    /// Classify a file from the state of its replicas
+    fn replica_verification(path: PathBuf, check: &ReplicaCheck) -> FileVerification {
+        if check.is_healthy() {
+            return FileVerification {
+                path,
+                exists: true,
+                hash_verified: Some(true),
+                error: None,
+                repairable: None,
+            };
+        }
+        
+        let mut problems: Vec<String> = check.corrupted.iter()
+            .map(|backend| format!("{}: hash mismatch", backend))
+            .collect();
+        problems.extend(check.missing.iter().map(|o| {
+            format!("{}: {}", o.backend, o.error.as_deref().unwrap_or("missing"))
+        }));
+        
+        FileVerification {
+            path,
+            exists: check.missing.is_empty(),
+            hash_verified: Some(false),
+            error: Some(format!("Damaged copies ({})", problems.join("; "))),
+            repairable: Some(check.is_repairable()),
+        }
+    }
+    
+    /// Whether encrypted blob data decodes to the content hashed in `entry`
+    fn blob_matches(
+        manifest: &BackupManifest,
+        entry: &FileEntry,
+        encryption: &EncryptionManager,
+        encrypted_data: &[u8],
+    ) -> Result<bool> {
+        if entry.is_streamed() {
+            return Self::streamed_blob_matches(manifest, entry, encryption, encrypted_data);
+        }
+        let decrypted_data = DirectUploadBackup::decrypt_file_data(encryption, manifest, entry, encrypted_data)?;
+        
+        // Decompress with the algorithm recorded for this file
+        let data = entry.decompress(decrypted_data)?;
+        
+        // Compute hash in the algorithm the backup used
+        Ok(manifest.hash_algorithm.hash(&data) == entry.hash)
+    }
+    
+    /// Whether a streamed file's blob, read from `blob`, decodes to the
+    /// content hashed in `entry`
+    fn streamed_blob_matches(
+        manifest: &BackupManifest,
+        entry: &FileEntry,
+        encryption: &EncryptionManager,
+        blob: impl std::io::Read,
+    ) -> Result<bool> {
+        let (encryption, backup_id) = DirectUploadBackup::stream_decryption(encryption, manifest, entry);
+        match DirectUploadBackup::decode_streamed(encryption, &backup_id, entry, manifest.hash_algorithm, blob, std::io::sink()) {
+            Ok(_) => Ok(true),
+            Err(SkylockError::Integrity(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
     /// Download and verify a single file's hash
     async fn verify_file_hash(
         hetzner: &HetznerClient,
-        remote_path: &PathBuf,
-        expected_hash: &str,
-        encryption: &crate::encryption::EncryptionManager,
+        manifest: &BackupManifest,
+        entry: &FileEntry,
+        encryption: &EncryptionManager,
+        temp: &TempFiles,
     ) -> Result<bool> {
-        // Create temp file for download
-        let temp_file = tempfile::NamedTempFile::new()
-            .map_err(|e| SkylockError::Backup(format!("Failed to create temp file: {}", e)))?;
-        
-        // Download file
-        hetzner.download_file(remote_path, &temp_file.path().to_path_buf()).await?;
-        
-        // Read and decrypt
-        let encrypted_data = tokio::fs::read(temp_file.path()).await?;
-        let decrypted_data = encryption.decrypt(&encrypted_data)?;
-        
-        // Decompress if needed (check for zstd magic bytes)
-        let data = if decrypted_data.len() >= 4 
-            && decrypted_data[0..4] == [0x28, 0xb5, 0x2f, 0xfd] {
-            // Zstd compressed
-            zstd::decode_all(decrypted_data.as_slice())
-                .map_err(|e| SkylockError::Backup(format!("Decompression failed: {}", e)))?
-        } else {
-            decrypted_data
-        };
-        
-        // Compute hash
-        use sha2::{Sha256, Digest};
-        let mut hasher = Sha256::new();
-        hasher.update(&data);
-        let computed_hash = format!("{:x}", hasher.finalize());
+        if entry.is_chunked() {
+            let data = DirectUploadBackup::fetch_chunks(hetzner, temp, encryption, manifest, entry, None).await?;
+            return Ok(manifest.hash_algorithm.hash(&data) == entry.hash);
+        }
         
-        Ok(computed_hash == expected_hash)
+        let remote_path = PathBuf::from(&entry.remote_path);
+        if entry.is_streamed() {
+            // Checked from disk, since the blob may not fit in memory
+            let blob = temp.file("verify")?;
+            hetzner.download_file(&remote_path, blob.path()).await?;
+            let blob = std::io::BufReader::new(std::fs::File::open(blob.path())?);
+            return Self::streamed_blob_matches(manifest, entry, encryption, blob);
+        }
+        let encrypted_data = temp.download_bytes(hetzner, &remote_path).await?;
+        Self::blob_matches(manifest, entry, encryption, &encrypted_data)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::direct_upload::BackupManifest;
+    use chrono::Utc;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use skylock_hetzner::test_server;
+    
+    /// WebDAV stub that answers 404 for paths containing "missing" and
+    /// never answers anything else
+    async fn stalling_webdav() -> String {
+        let (listener, endpoint) = test_server::bind().await;
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                tokio::spawn(async move {
+                    let Some((head, _)) = test_server::read_head(&mut socket).await else { return };
+                    if head.contains("missing") {
+                        test_server::respond(&mut socket, 404, &[]).await;
+                    } else {
+                        std::future::pending::<()>().await;
+                    }
+                });
+            }
+        });
+        endpoint
+    }
+    
+    fn test_manifest(names: &[&str]) -> BackupManifest {
+        let files: Vec<FileEntry> = names.iter().map(|name| FileEntry {
+            local_path: PathBuf::from(format!("/data/{}", name)),
+            remote_path: format!("/skylock/backups/test/{}.enc", name),
+            size: 10,
+            hash: "hash".to_string(),
+            compressed: false,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        }).collect();
+        
+        BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
+            backup_id: "test".to_string(),
+            timestamp: Utc::now(),
+            file_count: files.len(),
+            total_size: files.iter().map(|f| f.size).sum(),
+            files,
+            source_paths: vec![],
+            base_backup_id: None,
+            encryption_version: "v2".to_string(),
+            kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
+            signature: None,
+            backup_chain_version: 0,
+            encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
+        }
+    }
+    
+    /// Verifier that cancels itself once two files have been checked
+    async fn cancelling_verifier(temp_dir: &TempDir) -> (BackupVerifier, Arc<std::sync::Mutex<Vec<(usize, usize)>>>) {
+        let hetzner = test_server::client(&stalling_webdav().await);
+        
+        let cancel = CancellationToken::new();
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let progress: ProgressCallback = {
+            let cancel = cancel.clone();
+            let seen = seen.clone();
+            Arc::new(move |done, total| {
+                seen.lock().unwrap().push((done, total));
+                if done == 2 {
+                    cancel.cancel();
+                }
+            })
+        };
+        
+        let verifier = BackupVerifier::new(hetzner)
+            .with_cancellation(cancel)
+            .with_progress(progress)
+            .with_temp_files(TempFiles::new(temp_dir.path().to_path_buf()).with_spill_threshold(0));
+        (verifier, seen)
+    }
+    
+    #[tokio::test]
+    async fn test_cancelled_verification_returns_partial_result() {
+        // The missing files are checked first; the stalled ones never finish
+        let manifest = test_manifest(&["missing1", "missing2", "stalled1", "stalled2"]);
+        let encryption = Arc::new(crate::encryption::EncryptionManager::new("test_password").unwrap());
+        
+        for full in [false, true] {
+            let temp_dir = TempDir::new().unwrap();
+            let (verifier, seen) = cancelling_verifier(&temp_dir).await;
+            
+            let verification = async {
+                if full {
+                    verifier.verify_full(&manifest, encryption.clone()).await
+                } else {
+                    verifier.verify_quick(&manifest).await
+                }
+            };
+            let result = tokio::time::timeout(Duration::from_secs(30), verification)
+                .await
+                .expect("cancellation should stop the stalled downloads")
+                .unwrap();
+            
+            assert!(result.incomplete);
+            assert!(!result.passed);
+            assert!(!result.is_success());
+            assert_eq!(result.total_files, 4);
+            assert_eq!(result.file_results.len(), 2);
+            assert!(result.file_results.iter().all(|f| f.path.to_string_lossy().contains("missing")));
+            assert_eq!(*seen.lock().unwrap(), vec![(1, 4), (2, 4)]);
+            
+            // Temp files of the abandoned downloads are gone
+            assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+        }
+    }
+    
+    fn local_replica(dir: &TempDir) -> Arc<dyn skylock_core::storage::StorageBackend + Send + Sync> {
+        let config = skylock_core::storage::StorageConfig {
+            connection_string: Some(dir.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        Arc::new(skylock_core::storage::LocalStorageProvider::new(&config).unwrap())
+    }
+    
+    /// Encrypted backup of `names` written to each replica directory
+    fn replicated_backup(
+        names: &[&str],
+        encryption: &EncryptionManager,
+        replicas: &[&TempDir],
+    ) -> BackupManifest {
+        use sha2::{Digest, Sha256};
+        
+        let mut manifest = test_manifest(names);
+        manifest.kdf_params = Some(Default::default());
+        for entry in &mut manifest.files {
+            let content = format!("contents of {}", entry.local_path.display());
+            entry.hash = format!("{:x}", Sha256::digest(content.as_bytes()));
+            let blob = encryption.encrypt_with_aad(
+                content.as_bytes(),
+                &manifest.backup_id,
+                &entry.local_path.to_string_lossy(),
+            ).unwrap();
+            for dir in replicas {
+                let path = dir.path().join(entry.remote_path.trim_start_matches('/'));
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, &blob).unwrap();
+            }
+        }
+        manifest
+    }
+    
+    #[tokio::test]
+    async fn test_repair_from_intact_replica() {
+        let (primary, mirror) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let encryption = Arc::new(EncryptionManager::new("test_password").unwrap());
+        let manifest = replicated_backup(&["a", "b", "c"], &encryption, &[&primary, &mirror]);
+        
+        let blob = |dir: &TempDir, name: &str| {
+            dir.path().join(format!("skylock/backups/test/{}.enc", name))
+        };
+        // "a" is corrupted on the primary, "b" lost from the mirror and
+        // "c" corrupted on both
+        std::fs::write(blob(&primary, "a"), b"bit rot").unwrap();
+        std::fs::remove_file(blob(&mirror, "b")).unwrap();
+        std::fs::write(blob(&primary, "c"), b"bit rot").unwrap();
+        std::fs::write(blob(&mirror, "c"), b"bit rot").unwrap();
+        
+        let replicas = MultiBackend::new(Default::default())
+            .with_backend("hetzner", local_replica(&primary))
+            .with_backend("mirror", local_replica(&mirror));
+        let hetzner = test_server::client("http://127.0.0.1:9");
+        let verifier = BackupVerifier::new(hetzner).with_replicas(Arc::new(replicas));
+        
+        let result = verifier.verify_full(&manifest, encryption.clone()).await.unwrap();
+        assert!(!result.is_success());
+        assert_eq!(result.files_with_errors, 3);
+        let paths = |files: Vec<&FileVerification>| -> Vec<PathBuf> {
+            files.into_iter().map(|f| f.path.clone()).collect()
+        };
+        assert_eq!(paths(result.repairable_files()), vec![PathBuf::from("/data/a"), PathBuf::from("/data/b")]);
+        assert_eq!(paths(result.unrepairable_files()), vec![PathBuf::from("/data/c")]);
+        assert_eq!(paths(result.missing_files()), vec![PathBuf::from("/data/b")]);
+        
+        let report = verifier.repair(&manifest, &result, &encryption).await.unwrap();
+        assert_eq!(report.repaired, vec![PathBuf::from("/data/a"), PathBuf::from("/data/b")]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].path, PathBuf::from("/data/c"));
+        assert!(!report.is_success());
+        
+        // Only the unrepairable file still fails
+        let result = verifier.verify_full(&manifest, encryption.clone()).await.unwrap();
+        assert_eq!(result.files_verified, 2);
+        assert_eq!(paths(result.unrepairable_files()), vec![PathBuf::from("/data/c")]);
+        assert_eq!(std::fs::read(blob(&primary, "a")).unwrap(), std::fs::read(blob(&mirror, "a")).unwrap());
+        
+        // Once every blob is intact verification passes
+        let manifest = BackupManifest {
+            files: manifest.files.into_iter().filter(|f| !f.local_path.ends_with("c")).collect(),
+            ..manifest
+        };
+        let result = verifier.verify_full(&manifest, encryption).await.unwrap();
+        assert!(result.is_success());
+    }
     
     #[test]
     fn test_verification_result_methods() {
@@ -328,21 +911,25 @@ mod tests {
                     exists: true,
                     hash_verified: Some(true),
                     error: None,
+                    repairable: None,
                 },
                 FileVerification {
                     path: PathBuf::from("/test2.txt"),
                     exists: false,
                     hash_verified: None,
                     error: Some("Not found".to_string()),
+                    repairable: None,
                 },
                 FileVerification {
                     path: PathBuf::from("/test3.txt"),
                     exists: true,
                     hash_verified: Some(false),
                     error: Some("Hash mismatch".to_string()),
+                    repairable: None,
                 },
             ],
             passed: false,
+            incomplete: false,
         };
         
         assert!(!result.is_success());
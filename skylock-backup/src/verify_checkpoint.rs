@@ -0,0 +1,158 @@
+//! Checkpoints for resumable full verification
+//!
+//! A full verification downloads every blob of a backup and can take hours.
+//! With [`BackupVerifier::with_checkpoint_dir`](crate::BackupVerifier::with_checkpoint_dir)
+//! the files whose content checked out are recorded in
+//! `<data_dir>/verify_state/<backup_id>.json`, so an interrupted run picks up
+//! where it stopped instead of downloading everything again.
+//!
+//! A checkpoint is tied to the hash of the manifest it was written for; once
+//! the backup's manifest changes, the checkpoint is ignored and every file is
+//! checked again.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::direct_upload::BackupManifest;
+use crate::error::Result;
+use crate::state_file;
+
+/// Files of one backup that a full verification found intact
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VerifyCheckpoint {
+    /// Backup being verified
+    pub backup_id: String,
+    /// SHA-256 of the manifest the files were checked against
+    pub manifest_hash: String,
+    /// Files whose downloaded content matched their recorded hash
+    pub verified: HashSet<PathBuf>,
+    /// Last updated timestamp
+    pub last_updated: DateTime<Utc>,
+    /// When the checkpoint was last written to disk (not persisted)
+    #[serde(skip)]
+    last_saved: Option<Instant>,
+}
+
+impl VerifyCheckpoint {
+    /// Empty checkpoint for `manifest`
+    pub fn new(manifest: &BackupManifest) -> Self {
+        Self {
+            backup_id: manifest.backup_id.clone(),
+            manifest_hash: Self::manifest_hash(manifest),
+            verified: HashSet::new(),
+            last_updated: Utc::now(),
+            last_saved: None,
+        }
+    }
+
+    /// Hash identifying a manifest, covering every entry and remote path
+    pub fn manifest_hash(manifest: &BackupManifest) -> String {
+        let json = serde_json::to_vec(manifest).expect("manifest always serializes");
+        hex::encode(Sha256::digest(&json))
+    }
+
+    /// Directory holding verification checkpoints below the configured data dir
+    pub fn state_dir(data_dir: &Path) -> PathBuf {
+        data_dir.join("verify_state")
+    }
+
+    /// Get the checkpoint file path for a backup ID
+    pub fn state_file_path(state_dir: &Path, backup_id: &str) -> PathBuf {
+        state_dir.join(format!("{}.json", backup_id))
+    }
+
+    /// Saved checkpoint for `manifest`, or an empty one if there is none,
+    /// it can't be read, or it was written for a different manifest
+    pub async fn load_or_new(state_dir: &Path, manifest: &BackupManifest) -> Self {
+        let saved = state_file::load::<Self>(&Self::state_file_path(state_dir, &manifest.backup_id)).await;
+
+        match saved {
+            Some(checkpoint) if checkpoint.backup_id == manifest.backup_id
+                && checkpoint.manifest_hash == Self::manifest_hash(manifest) => checkpoint,
+            Some(_) => {
+                tracing::info!("Backup {} changed since its last verification, starting over", manifest.backup_id);
+                Self::new(manifest)
+            }
+            None => Self::new(manifest),
+        }
+    }
+
+    /// Save the checkpoint to disk
+    pub async fn save(&mut self, state_dir: &Path) -> Result<()> {
+        state_file::save(&Self::state_file_path(state_dir, &self.backup_id), self, "verification checkpoint").await?;
+        self.last_saved = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Save the checkpoint if at least `interval` has passed since the last save
+    ///
+    /// Returns whether the checkpoint was written.
+    pub async fn checkpoint(&mut self, state_dir: &Path, interval: Duration) -> Result<bool> {
+        if self.last_saved.is_some_and(|saved| saved.elapsed() < interval) {
+            return Ok(false);
+        }
+        self.save(state_dir).await?;
+        Ok(true)
+    }
+
+    /// Record a file as verified
+    pub fn mark_verified(&mut self, path: PathBuf) {
+        self.verified.insert(path);
+        self.last_updated = Utc::now();
+    }
+
+    /// Whether an earlier run already verified a file
+    pub fn is_verified(&self, path: &Path) -> bool {
+        self.verified.contains(path)
+    }
+
+    /// Delete the checkpoint of a backup, once its verification has finished
+    pub async fn delete(state_dir: &Path, backup_id: &str) -> Result<()> {
+        state_file::remove(&Self::state_file_path(state_dir, backup_id), "verification checkpoint").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn manifest(backup_id: &str) -> BackupManifest {
+        serde_json::from_value(serde_json::json!({
+            "backup_id": backup_id,
+            "timestamp": "2024-01-01T00:00:00Z",
+            "files": [],
+            "total_size": 0,
+            "file_count": 0,
+            "source_paths": [],
+            "base_backup_id": null,
+        })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_tied_to_manifest() {
+        let dir = TempDir::new().unwrap();
+        let original = manifest("backup_1");
+
+        let mut checkpoint = VerifyCheckpoint::load_or_new(dir.path(), &original).await;
+        assert!(checkpoint.verified.is_empty());
+        checkpoint.mark_verified(PathBuf::from("/data/a"));
+        checkpoint.save(dir.path()).await.unwrap();
+
+        let loaded = VerifyCheckpoint::load_or_new(dir.path(), &original).await;
+        assert!(loaded.is_verified(Path::new("/data/a")));
+        assert!(!loaded.is_verified(Path::new("/data/b")));
+
+        // A changed manifest starts from scratch
+        let mut changed = original.clone();
+        changed.total_size = 1;
+        assert!(VerifyCheckpoint::load_or_new(dir.path(), &changed).await.verified.is_empty());
+
+        VerifyCheckpoint::delete(dir.path(), "backup_1").await.unwrap();
+        assert!(VerifyCheckpoint::load_or_new(dir.path(), &original).await.verified.is_empty());
+    }
+}
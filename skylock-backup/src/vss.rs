@@ -21,6 +21,11 @@ impl VssSnapshot {
         Ok(())
     }
 
+    /// Path the snapshot was taken of
+    pub fn source_path(&self) -> &Path {
+        &self.source_path
+    }
+
     pub fn get_snapshot_path(&self, original_path: &Path) -> Result<PathBuf> {
         // In non-VSS mode, just return the original path
         Ok(original_path.to_path_buf())
@@ -116,6 +116,213 @@ impl EventBatch {
     }
 }
 
+/// Events collected during the current debounce window, coalesced per path
+///
+/// Each path keeps a single event describing its final state: a create
+/// followed by modifications stays a create, a create that is deleted again
+/// disappears, and a delete followed by a create becomes a modify. A delete
+/// and a create of the same file name in different places, or an explicit
+/// rename, become a single `Rename` event.
+#[derive(Debug, Default)]
+pub struct PendingEvents {
+    /// Pending event per path with its arrival order. Renames are keyed by
+    /// their destination, since that is the path they leave behind.
+    events: HashMap<PathBuf, (u64, FileEvent)>,
+    next_seq: u64,
+}
+
+impl PendingEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Get the pending event for a path (the destination path for renames)
+    pub fn get(&self, path: &Path) -> Option<&FileEvent> {
+        self.events.get(path).map(|(_, event)| event)
+    }
+
+    /// Add a raw event, coalescing it with whatever is pending for its path
+    pub fn push(&mut self, event: FileEvent) {
+        match event.kind {
+            FileEventKind::Rename => match event.new_path.clone() {
+                Some(to) => self.push_rename(event, to),
+                None => self.merge(event),
+            },
+            FileEventKind::Create if !self.events.contains_key(&event.path) => {
+                match self.find_pair(&event, FileEventKind::Delete) {
+                    Some(from) => {
+                        let (seq, _) = self.events.remove(&from).unwrap();
+                        let to = event.path.clone();
+                        let rename = FileEvent { path: from, kind: FileEventKind::Rename, ..event }
+                            .with_new_path(to.clone());
+                        self.events.insert(to, (seq, rename));
+                    }
+                    None => self.merge(event),
+                }
+            }
+            FileEventKind::Delete if !self.events.contains_key(&event.path) => {
+                match self.find_pair(&event, FileEventKind::Create) {
+                    Some(to) => {
+                        let (seq, created) = self.events.remove(&to).unwrap();
+                        let rename = FileEvent { path: event.path, kind: FileEventKind::Rename, ..created }
+                            .with_new_path(to.clone());
+                        self.events.insert(to, (seq, rename));
+                    }
+                    None => self.merge(event),
+                }
+            }
+            _ => self.merge(event),
+        }
+    }
+
+    /// Drain all pending events into a batch, in arrival order
+    pub fn take_batch(&mut self) -> EventBatch {
+        let mut events: Vec<_> = self.events.drain().map(|(_, entry)| entry).collect();
+        events.sort_by_key(|(seq, _)| *seq);
+
+        let mut batch = EventBatch::new();
+        for (_, event) in events {
+            batch.add_event(event);
+        }
+        batch.finalize();
+        batch
+    }
+
+    /// Find a pending event of `kind` elsewhere that, together with `event`,
+    /// looks like one half of a move (same file name, same type)
+    fn find_pair(&self, event: &FileEvent, kind: FileEventKind) -> Option<PathBuf> {
+        let name = event.path.file_name()?;
+        self.events.iter()
+            .filter(|(path, (_, pending))| {
+                pending.kind == kind
+                    && pending.is_dir == event.is_dir
+                    && path.file_name() == Some(name)
+            })
+            .min_by_key(|(_, (seq, _))| *seq)
+            .map(|(path, _)| path.clone())
+    }
+
+    fn insert(&mut self, key: PathBuf, event: FileEvent) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.events.insert(key, (seq, event));
+    }
+
+    /// Merge an event into the pending event for its own path
+    fn merge(&mut self, event: FileEvent) {
+        let key = event.path.clone();
+        let Some((seq, existing)) = self.events.remove(&key) else {
+            self.insert(key, event);
+            return;
+        };
+
+        let merged = match (&existing.kind, &event.kind) {
+            // Created and deleted within the window: nothing happened
+            (FileEventKind::Create, FileEventKind::Delete) => None,
+            // Create + Modify = Create
+            (FileEventKind::Create, FileEventKind::Modify | FileEventKind::Metadata) => {
+                Some(FileEvent { timestamp: event.timestamp, ..existing })
+            }
+            // Deleted then recreated (e.g. editor save): the file changed
+            (FileEventKind::Delete, FileEventKind::Create | FileEventKind::Modify) => {
+                Some(FileEvent { kind: FileEventKind::Modify, ..event })
+            }
+            // Modify + Metadata = Modify (update timestamp)
+            (FileEventKind::Modify, FileEventKind::Modify | FileEventKind::Metadata) => {
+                Some(FileEvent { timestamp: event.timestamp, ..existing })
+            }
+            (FileEventKind::Rename, FileEventKind::Metadata) => {
+                Some(FileEvent { timestamp: event.timestamp, ..existing })
+            }
+            // The moved file was deleted: only the source removal remains
+            (FileEventKind::Rename, FileEventKind::Delete) => {
+                self.source_removed(existing.path, event.timestamp);
+                return;
+            }
+            // The moved file changed afterwards: report it as delete + create
+            (FileEventKind::Rename, _) => {
+                self.source_removed(existing.path, event.timestamp);
+                Some(FileEvent { kind: FileEventKind::Create, ..event })
+            }
+            // Other cases: use newer event
+            _ => Some(event),
+        };
+
+        if let Some(merged) = merged {
+            self.events.insert(key, (seq, merged));
+        }
+    }
+
+    /// A file was moved away from `path` within the window
+    fn source_removed(&mut self, path: PathBuf, timestamp: DateTime<Utc>) {
+        match self.events.get_mut(&path) {
+            // Something new took its place
+            Some((_, pending)) if pending.kind == FileEventKind::Create => {
+                pending.kind = FileEventKind::Modify;
+            }
+            Some(_) => {}
+            None => {
+                let mut event = FileEvent::new(path.clone(), FileEventKind::Delete, false);
+                event.timestamp = timestamp;
+                self.insert(path, event);
+            }
+        }
+    }
+
+    fn push_rename(&mut self, event: FileEvent, to: PathBuf) {
+        let from = event.path.clone();
+        if from == to {
+            return;
+        }
+
+        let rename = match self.events.remove(&from) {
+            // A file created in this window was moved: it is simply new at `to`
+            Some((_, pending)) if pending.kind == FileEventKind::Create => {
+                let created = FileEvent { path: to, kind: FileEventKind::Create, new_path: None, ..event };
+                self.merge(created);
+                return;
+            }
+            // Chained moves collapse to a single move from the original source
+            Some((seq, pending)) if pending.kind == FileEventKind::Rename => {
+                if pending.path == to {
+                    return;
+                }
+                (seq, FileEvent { path: pending.path, ..event })
+            }
+            // Modified then moved: report it as delete + create
+            Some((seq, pending)) if pending.kind == FileEventKind::Modify => {
+                self.events.insert(from.clone(), (seq, pending));
+                self.merge(FileEvent::new(from, FileEventKind::Delete, event.is_dir));
+                let created = FileEvent { path: to, kind: FileEventKind::Create, new_path: None, ..event };
+                self.merge(created);
+                return;
+            }
+            Some((seq, _)) => (seq, event),
+            None => {
+                let seq = self.next_seq;
+                self.next_seq += 1;
+                (seq, event)
+            }
+        };
+
+        // The move overwrites whatever was pending at the destination
+        if let Some((_, replaced)) = self.events.remove(&to) {
+            if replaced.kind == FileEventKind::Rename {
+                self.source_removed(replaced.path, rename.1.timestamp);
+            }
+        }
+        self.events.insert(to, rename);
+    }
+}
+
 /// Configuration for the file watcher
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WatcherConfig {
@@ -206,7 +413,7 @@ fn is_elevated_windows() -> bool {
 pub struct FileWatcher {
     config: WatcherConfig,
     /// Pending events being debounced
-    pending_events: Arc<RwLock<HashMap<PathBuf, FileEvent>>>,
+    pending_events: Arc<RwLock<PendingEvents>>,
     /// Last activity time for debouncing
     last_activity: Arc<RwLock<Instant>>,
     /// Channel for sending batched events
@@ -225,7 +432,7 @@ impl FileWatcher {
 
         let watcher = Self {
             config,
-            pending_events: Arc::new(RwLock::new(HashMap::new())),
+            pending_events: Arc::new(RwLock::new(PendingEvents::new())),
             last_activity: Arc::new(RwLock::new(Instant::now())),
             event_tx,
             shutdown_tx,
@@ -299,16 +506,9 @@ impl FileWatcher {
                             || pending_count >= max_buffer;
                         
                         if should_flush {
-                            let mut batch = EventBatch::new();
-                            {
-                                let mut events = pending.write().await;
-                                for (_, event) in events.drain() {
-                                    batch.add_event(event);
-                                }
-                            }
+                            let batch = pending.write().await.take_batch();
                             
                             if !batch.is_empty() {
-                                batch.finalize();
                                 debug!("Flushing {} events", batch.len());
                                 if event_tx.send(batch).await.is_err() {
                                     error!("Failed to send event batch - receiver dropped");
@@ -335,41 +535,28 @@ impl FileWatcher {
     }
 
     /// Add a raw event to the pending queue
-    /// Events for the same path will be merged/deduplicated
+    /// Events for the same path within the debounce window are coalesced
     pub async fn add_event(&self, event: FileEvent) {
+        let event = match (event.kind.clone(), event.new_path.clone()) {
+            // Moves between ignored and watched names (e.g. an editor
+            // renaming its temp file over the original) are half-visible
+            (FileEventKind::Rename, Some(to)) => {
+                match (self.should_ignore(&event.path), self.should_ignore(&to)) {
+                    (true, false) => FileEvent { path: to, kind: FileEventKind::Create, new_path: None, ..event },
+                    (false, true) => FileEvent { kind: FileEventKind::Delete, new_path: None, ..event },
+                    _ => event,
+                }
+            }
+            _ => event,
+        };
+
         // Check if path matches ignore patterns
         if self.should_ignore(&event.path) {
             debug!("Ignoring event for path: {:?}", event.path);
             return;
         }
 
-        let mut pending = self.pending_events.write().await;
-        let path = event.path.clone();
-
-        // Merge events for the same path
-        if let Some(existing) = pending.get_mut(&path) {
-            // Prioritize certain event types
-            match (&existing.kind, &event.kind) {
-                // Delete always wins
-                (_, FileEventKind::Delete) => {
-                    *existing = event;
-                }
-                // Create + Modify = Create
-                (FileEventKind::Create, FileEventKind::Modify) => {
-                    // Keep as Create
-                }
-                // Modify + Modify = Modify (update timestamp)
-                (FileEventKind::Modify, FileEventKind::Modify) => {
-                    existing.timestamp = event.timestamp;
-                }
-                // Other cases: use newer event
-                _ => {
-                    *existing = event;
-                }
-            }
-        } else {
-            pending.insert(path, event);
-        }
+        self.pending_events.write().await.push(event);
 
         // Update last activity time
         *self.last_activity.write().await = Instant::now();
@@ -389,27 +576,32 @@ impl FileWatcher {
     }
 
     /// Simple glob pattern matching
-    fn matches_glob(pattern: &str, path: &str) -> bool {
-        // Handle simple patterns: *, ?, and **
-        let pattern_parts: Vec<&str> = pattern.split('/').collect();
-        let path_parts: Vec<&str> = path.split(std::path::MAIN_SEPARATOR).collect();
-        
-        // Check if any path component matches
-        for part in &path_parts {
-            if let Some(file_pattern) = pattern_parts.last() {
-                if Self::matches_simple_glob(file_pattern, part) {
-                    return true;
-                }
-            }
-        }
-        
+    ///
+    /// Patterns without a separator match any path component, `dir/*`
+    /// matches anything below a directory named `dir`, and `**` patterns
+    /// are matched against the full path.
+    pub(crate) fn matches_glob(pattern: &str, path: &str) -> bool {
         // Check full path for ** patterns
         if pattern.contains("**") {
             let simple_pattern = pattern.replace("**", "*");
             return Self::matches_simple_glob(&simple_pattern, path);
         }
-        
-        false
+
+        let path_parts: Vec<&str> = path.split(std::path::MAIN_SEPARATOR)
+            .filter(|part| !part.is_empty())
+            .collect();
+
+        if let Some(dir_pattern) = pattern.strip_suffix("/*") {
+            // Any component except the last must be the directory
+            let parents = &path_parts[..path_parts.len().saturating_sub(1)];
+            return parents.iter().any(|part| Self::matches_simple_glob(dir_pattern, part));
+        }
+
+        if !pattern.contains('/') {
+            return path_parts.iter().any(|part| Self::matches_simple_glob(pattern, part));
+        }
+
+        Self::matches_simple_glob(&format!("*/{}", pattern), path)
     }
 
     /// Match simple glob with * and ?
@@ -543,6 +735,102 @@ mod tests {
         assert_eq!(pending.get(&path).unwrap().kind, FileEventKind::Create);
     }
 
+    fn ev(path: &str, kind: FileEventKind) -> FileEvent {
+        FileEvent::new(PathBuf::from(path), kind, false)
+    }
+
+    fn rename(from: &str, to: &str) -> FileEvent {
+        ev(from, FileEventKind::Rename).with_new_path(PathBuf::from(to))
+    }
+
+    /// Feed a sequence of events and return the coalesced batch as
+    /// (path, kind, new_path) tuples in arrival order
+    fn coalesce(events: Vec<FileEvent>) -> Vec<(PathBuf, FileEventKind, Option<PathBuf>)> {
+        let mut pending = PendingEvents::new();
+        for event in events {
+            pending.push(event);
+        }
+        let batch = pending.take_batch();
+        assert!(pending.is_empty());
+        batch.events.into_iter().map(|e| (e.path, e.kind, e.new_path)).collect()
+    }
+
+    #[test]
+    fn test_coalesce_same_path() {
+        use FileEventKind::*;
+        let p = |s: &str| PathBuf::from(s);
+
+        let cases: Vec<(Vec<FileEvent>, Vec<(PathBuf, FileEventKind, Option<PathBuf>)>)> = vec![
+            (vec![ev("/d/a", Create), ev("/d/a", Modify)], vec![(p("/d/a"), Create, None)]),
+            (vec![ev("/d/a", Create), ev("/d/a", Modify), ev("/d/a", Delete)], vec![]),
+            (vec![ev("/d/a", Modify), ev("/d/a", Modify), ev("/d/a", Metadata)], vec![(p("/d/a"), Modify, None)]),
+            (vec![ev("/d/a", Modify), ev("/d/a", Delete)], vec![(p("/d/a"), Delete, None)]),
+            (vec![ev("/d/a", Delete), ev("/d/a", Create)], vec![(p("/d/a"), Modify, None)]),
+            (
+                vec![ev("/d/a", Modify), ev("/d/b", Create), ev("/d/a", Modify)],
+                vec![(p("/d/a"), Modify, None), (p("/d/b"), Create, None)],
+            ),
+        ];
+
+        for (events, expected) in cases {
+            let input: Vec<_> = events.iter().map(|e| e.kind.clone()).collect();
+            assert_eq!(coalesce(events), expected, "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_coalesce_moves() {
+        use FileEventKind::*;
+        let p = |s: &str| PathBuf::from(s);
+
+        let cases: Vec<(Vec<FileEvent>, Vec<(PathBuf, FileEventKind, Option<PathBuf>)>)> = vec![
+            // Remove + create of the same name elsewhere, in either order
+            (vec![ev("/d/a", Delete), ev("/e/a", Create)], vec![(p("/d/a"), Rename, Some(p("/e/a")))]),
+            (vec![ev("/e/a", Create), ev("/d/a", Delete)], vec![(p("/d/a"), Rename, Some(p("/e/a")))]),
+            // Different names are not paired
+            (
+                vec![ev("/d/a", Delete), ev("/e/b", Create)],
+                vec![(p("/d/a"), Delete, None), (p("/e/b"), Create, None)],
+            ),
+            // Chained moves collapse, moving back cancels out
+            (vec![rename("/d/a", "/d/b"), rename("/d/b", "/d/c")], vec![(p("/d/a"), Rename, Some(p("/d/c")))]),
+            (vec![rename("/d/a", "/d/b"), rename("/d/b", "/d/a")], vec![]),
+            // A file created in the window and then moved is just new
+            (vec![ev("/d/a", Create), rename("/d/a", "/d/b")], vec![(p("/d/b"), Create, None)]),
+            // Save via temp file: write tmp, drop the original, move tmp over it
+            (
+                vec![ev("/d/a.new", Create), ev("/d/a", Delete), rename("/d/a.new", "/d/a")],
+                vec![(p("/d/a"), Modify, None)],
+            ),
+            // Moved then deleted: only the source removal remains
+            (vec![rename("/d/a", "/d/b"), ev("/d/b", Delete)], vec![(p("/d/a"), Delete, None)]),
+            // Moved then edited: delete + create
+            (
+                vec![rename("/d/a", "/d/b"), ev("/d/b", Modify)],
+                vec![(p("/d/b"), Create, None), (p("/d/a"), Delete, None)],
+            ),
+        ];
+
+        for (events, expected) in cases {
+            let input: Vec<_> = events.iter().map(|e| (e.path.clone(), e.kind.clone())).collect();
+            assert_eq!(coalesce(events), expected, "input {:?}", input);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rename_from_ignored_temp_file() {
+        let (watcher, _rx) = FileWatcher::new(WatcherConfig::default());
+
+        watcher.add_event(ev("/d/report.tmp", FileEventKind::Create)).await;
+        watcher.add_event(ev("/d/report.txt", FileEventKind::Delete)).await;
+        watcher.add_event(rename("/d/report.tmp", "/d/report.txt")).await;
+
+        let batch = watcher.pending_events.write().await.take_batch();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch.events[0].path, PathBuf::from("/d/report.txt"));
+        assert_eq!(batch.events[0].kind, FileEventKind::Modify);
+    }
+
     #[test]
     fn test_ignore_patterns() {
         let config = WatcherConfig::default();
@@ -0,0 +1,211 @@
+//! Extended attribute preservation
+//!
+//! Captures extended attributes (macOS resource forks, Linux security labels,
+//! `user.*` metadata) during backup and reapplies them on restore. This is
+//! best-effort: attributes that cannot be read or written are logged and
+//! skipped rather than failing the file. On platforms without xattr support
+//! both operations are no-ops.
+
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+#[cfg(unix)]
+use tracing::{debug, warn};
+
+/// A single extended attribute name/value pair
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtendedAttribute {
+    /// Attribute name including namespace (e.g., "user.comment")
+    pub name: String,
+    /// Raw attribute value
+    pub value: Vec<u8>,
+}
+
+/// Whether extended attributes are supported on this platform
+pub fn is_supported() -> bool {
+    #[cfg(unix)]
+    {
+        xattr::SUPPORTED_PLATFORM
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// Read all extended attributes of a file
+///
+/// Never fails: unreadable attributes are skipped with a warning.
+#[cfg(unix)]
+pub fn read_xattrs(path: &Path) -> Vec<ExtendedAttribute> {
+    if !is_supported() {
+        return Vec::new();
+    }
+
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(e) => {
+            warn!("Failed to list extended attributes of {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut attrs = Vec::new();
+    for name in names {
+        let Some(name_str) = name.to_str() else {
+            warn!("Skipping non-UTF-8 extended attribute {:?} on {}", name, path.display());
+            continue;
+        };
+
+        match xattr::get(path, &name) {
+            Ok(Some(value)) => attrs.push(ExtendedAttribute {
+                name: name_str.to_string(),
+                value,
+            }),
+            // Removed between list and get
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Skipping extended attribute {} on {}: {}", name_str, path.display(), e);
+            }
+        }
+    }
+
+    attrs.sort_by(|a, b| a.name.cmp(&b.name));
+    attrs
+}
+
+#[cfg(not(unix))]
+pub fn read_xattrs(_path: &Path) -> Vec<ExtendedAttribute> {
+    Vec::new()
+}
+
+/// Apply extended attributes to a restored file
+///
+/// Returns the number of attributes applied. Attributes that cannot be set
+/// (e.g., `security.*` without privileges) are skipped with a warning.
+#[cfg(unix)]
+pub fn apply_xattrs(path: &Path, attrs: &[ExtendedAttribute]) -> usize {
+    if attrs.is_empty() || !is_supported() {
+        return 0;
+    }
+
+    let mut applied = 0;
+    for attr in attrs {
+        match xattr::set(path, &attr.name, &attr.value) {
+            Ok(()) => applied += 1,
+            Err(e) => {
+                warn!("Failed to restore extended attribute {} on {}: {}", attr.name, path.display(), e);
+            }
+        }
+    }
+
+    debug!("Restored {}/{} extended attributes on {}", applied, attrs.len(), path.display());
+    applied
+}
+
+#[cfg(not(unix))]
+pub fn apply_xattrs(_path: &Path, _attrs: &[ExtendedAttribute]) -> usize {
+    0
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::direct_upload::{DirectUploadBackup, FileEntry};
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_xattr_backup_and_restore() {
+        let source_dir = TempDir::new().unwrap();
+        let source = source_dir.path().join("labelled.txt");
+        std::fs::write(&source, b"contents").unwrap();
+
+        if let Err(e) = xattr::set(&source, "user.skylock.test", b"label-value") {
+            // Filesystem without user xattr support (e.g., some tmpfs mounts)
+            eprintln!("Skipping xattr test: {}", e);
+            return;
+        }
+
+        // Backup: capture attributes into the manifest entry
+        let entry = FileEntry {
+            local_path: source.clone(),
+            remote_path: "/skylock/backups/test/labelled.txt.enc".to_string(),
+            size: 8,
+            hash: String::new(),
+            compressed: false,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: read_xattrs(&source),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        };
+        assert!(entry.xattrs.contains(&ExtendedAttribute {
+            name: "user.skylock.test".to_string(),
+            value: b"label-value".to_vec(),
+        }));
+
+        // The attributes survive the manifest round-trip
+        let json = serde_json::to_string(&entry).unwrap();
+        let entry: FileEntry = serde_json::from_str(&json).unwrap();
+
+        // Restore into a clean location
+        let restore_dir = TempDir::new().unwrap();
+        let restored = DirectUploadBackup::write_restored_file(&entry, restore_dir.path(), &Default::default(), b"contents", true)
+            .await
+            .unwrap();
+
+        assert!(restored.starts_with(restore_dir.path()));
+        assert_eq!(
+            xattr::get(&restored, "user.skylock.test").unwrap(),
+            Some(b"label-value".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xattrs_not_restored_when_disabled() {
+        let restore_dir = TempDir::new().unwrap();
+        let entry = FileEntry {
+            local_path: "/data/file.txt".into(),
+            remote_path: "/skylock/backups/test/data/file.txt.enc".to_string(),
+            size: 1,
+            hash: String::new(),
+            compressed: false,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: vec![ExtendedAttribute { name: "user.skylock.test".to_string(), value: b"x".to_vec() }],
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        };
+
+        let restored = DirectUploadBackup::write_restored_file(&entry, restore_dir.path(), &Default::default(), b"x", false)
+            .await
+            .unwrap();
+
+        assert_eq!(xattr::get(&restored, "user.skylock.test").unwrap(), None);
+    }
+
+    #[test]
+    fn test_legacy_entry_without_xattrs() {
+        let json = r#"{"local_path":"/a","remote_path":"/r","size":1,"hash":"h","compressed":false,"encrypted":true,"timestamp":"2024-01-01T00:00:00Z"}"#;
+        let entry: FileEntry = serde_json::from_str(json).unwrap();
+        assert!(entry.xattrs.is_empty());
+        assert!(!serde_json::to_string(&entry).unwrap().contains("xattrs"));
+    }
+
+    #[test]
+    fn test_unreadable_path_is_skipped() {
+        assert!(read_xattrs(Path::new("/nonexistent/skylock/file")).is_empty());
+    }
+}
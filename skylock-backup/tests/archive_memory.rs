@@ -0,0 +1,201 @@
+//! Streams a multi-gigabyte synthetic archive through the archive pipeline
+//! and back, checking that memory use stays flat.
+//!
+//! Kept in its own test binary so the measured peak isn't shared with other
+//! tests, and ignored by default for its size: run it with
+//! `cargo test --release --test archive_memory -- --ignored`.
+//! `SKYLOCK_ARCHIVE_TEST_BYTES` overrides the size of the large file.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+
+use skylock_backup::archive::{open_archive, ArchiveWriter};
+use skylock_backup::EncryptionManager;
+
+const DEFAULT_SIZE: u64 = 3 * 1024 * 1024 * 1024;
+
+/// Allowed growth of the resident set while the pipeline runs
+const MEMORY_CEILING: u64 = 64 * 1024 * 1024;
+
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Writes buffered between the producer and consumer threads
+const PIPE_DEPTH: usize = 16;
+
+/// Deterministic data: a pseudo-random block, stamped with the block number
+/// so no two blocks are identical
+struct Synthetic {
+    block: Vec<u8>,
+    remaining: u64,
+    position: u64,
+}
+
+impl Synthetic {
+    fn new(size: u64, seed: u64) -> Self {
+        let mut state = seed | 1;
+        let block = (0..BLOCK_SIZE).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect();
+        Self { block, remaining: size, position: 0 }
+    }
+}
+
+impl Read for Synthetic {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let offset = (self.position % BLOCK_SIZE as u64) as usize;
+        let n = buf.len().min(BLOCK_SIZE - offset).min(self.remaining as usize);
+        buf[..n].copy_from_slice(&self.block[offset..offset + n]);
+        // Stamp the block number over the start of each block
+        let stamp = (self.position / BLOCK_SIZE as u64).to_le_bytes();
+        if offset < stamp.len() {
+            let end = (offset + n).min(stamp.len());
+            buf[..end - offset].copy_from_slice(&stamp[offset..end]);
+        }
+        self.position += n as u64;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Blocking in-memory pipe holding at most [`PIPE_DEPTH`] writes
+fn pipe() -> (PipeReader, PipeWriter) {
+    let (tx, rx) = sync_channel(PIPE_DEPTH);
+    (PipeReader { rx, chunk: Vec::new(), pos: 0 }, PipeWriter { tx })
+}
+
+struct PipeReader {
+    rx: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                // The writer was dropped
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+struct PipeWriter {
+    tx: SyncSender<Vec<u8>>,
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx.send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "reader dropped"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn resident_bytes() -> u64 {
+    std::fs::read_to_string("/proc/self/status").ok()
+        .and_then(|status| status.lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse::<u64>().ok()))
+        .map(|kb| kb * 1024)
+        .unwrap_or(0)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_bytes() -> u64 {
+    0
+}
+
+/// Compare everything read from `actual` with `expected`, in blocks
+fn assert_same_stream(mut actual: impl Read, mut expected: impl Read, name: &str) {
+    let (mut a, mut e) = (vec![0u8; BLOCK_SIZE], vec![0u8; BLOCK_SIZE]);
+    let mut offset = 0u64;
+    loop {
+        let n = expected.read(&mut e).unwrap();
+        if n == 0 {
+            assert_eq!(actual.read(&mut a).unwrap(), 0, "{} has extra data", name);
+            return;
+        }
+        actual.read_exact(&mut a[..n]).unwrap();
+        assert!(a[..n] == e[..n], "{} differs near offset {}", name, offset);
+        offset += n as u64;
+    }
+}
+
+#[test]
+#[ignore] // Streams 3 GiB through the archive pipeline; run with --ignored, ideally in release mode
+fn test_multi_gigabyte_archive_round_trip_in_bounded_memory() {
+    let size = std::env::var("SKYLOCK_ARCHIVE_TEST_BYTES").ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_SIZE);
+    let files = [("tree/large.bin", size, 1), ("tree/nested/small.bin", 100_000, 2), ("tree/nested/empty.bin", 0, 3)];
+    let encryption = Arc::new(EncryptionManager::new("test_password").unwrap());
+
+    // Sample the resident set while the archive streams through a pipe
+    let baseline = resident_bytes();
+    let peak = Arc::new(AtomicU64::new(baseline));
+    let running = Arc::new(AtomicBool::new(true));
+    let sampler = {
+        let (peak, running) = (peak.clone(), running.clone());
+        std::thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                peak.fetch_max(resident_bytes(), Ordering::Relaxed);
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        })
+    };
+
+    let (reader, writer) = pipe();
+    let producer = {
+        let encryption = encryption.clone();
+        std::thread::spawn(move || {
+            let mut archive = ArchiveWriter::new(writer, encryption, "backup_memory").unwrap();
+            for (name, size, seed) in files {
+                archive.append_data(name, size, Synthetic::new(size, seed)).unwrap();
+            }
+            archive.finish().unwrap();
+        })
+    };
+
+    let mut archive = open_archive(io::BufReader::new(reader), encryption, "backup_memory").unwrap();
+    let mut restored = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let entry = entry.unwrap();
+        let name = entry.path().unwrap().to_string_lossy().to_string();
+        let (_, size, seed) = files.iter().find(|(n, _, _)| *n == name).copied()
+            .unwrap_or_else(|| panic!("unexpected entry {}", name));
+        assert_eq!(entry.header().size().unwrap(), size);
+        assert_same_stream(entry, Synthetic::new(size, seed), &name);
+        restored.push(name);
+    }
+    producer.join().unwrap();
+    running.store(false, Ordering::Relaxed);
+    sampler.join().unwrap();
+
+    assert_eq!(restored, files.iter().map(|(name, _, _)| name.to_string()).collect::<Vec<_>>());
+    let growth = peak.load(Ordering::Relaxed).saturating_sub(baseline);
+    assert!(
+        growth < MEMORY_CEILING,
+        "resident memory grew by {} MiB streaming {} MiB",
+        growth / 1024 / 1024,
+        size / 1024 / 1024
+    );
+}
@@ -5,14 +5,17 @@
 //! ## Features
 //! - Structured audit events with timestamps and metadata
 //! - Tamper-evident logging with HMAC integrity
+//! - Hash-chained append-only log of key, credential and destructive operations
 //! - Multiple output backends (file, syslog, remote)
 //! - Async, non-blocking logging
 //! - Event filtering and severity levels
 
+pub mod chain;
 pub mod events;
 pub mod logger;
 pub mod storage;
 
+pub use chain::{AuditLog, AuditOperation, AuditRecord, ChainVerification};
 pub use events::{AuditEvent, AuditEventType, EventSeverity, EventOutcome};
 pub use logger::{AuditLogger, AuditLoggerConfig};
 pub use storage::{AuditStorage, FileAuditStorage};
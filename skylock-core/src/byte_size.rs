@@ -0,0 +1,190 @@
+//! Byte sizes as users type and read them
+//!
+//! One parser and formatter for every size in the config, on the command
+//! line and in output, so "what you type" and "what is enforced" agree.
+//! Units follow rsync's conventions:
+//!
+//! - `K`, `Ki`, `KiB` (and `M`, `G`, `T`, `P`, `E` alike) are binary, 1024
+//! - `KB`, `MB`, `GB`, ... are decimal, 1000
+//! - no unit or `B` is bytes
+//!
+//! Units are case-insensitive and may be separated from the number by
+//! spaces. Sizes are displayed in binary units with IEC labels ("1.50 MiB"),
+//! which parse back to the same value.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Result, SkylockError};
+
+const BINARY_UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+const DECIMAL_UNITS: [&str; 7] = ["B", "KB", "MB", "GB", "TB", "PB", "EB"];
+const PREFIXES: [char; 6] = ['k', 'm', 'g', 't', 'p', 'e'];
+
+/// A size in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Format in binary units: "512 B", "1.50 KiB", "2.00 GiB"
+    pub fn to_binary_string(self) -> String {
+        Self::format(self.0, 1024, &BINARY_UNITS)
+    }
+
+    /// Format in decimal units: "512 B", "1.54 KB", "2.15 GB"
+    pub fn to_decimal_string(self) -> String {
+        Self::format(self.0, 1000, &DECIMAL_UNITS)
+    }
+
+    fn format(bytes: u64, base: u64, units: &[&str; 7]) -> String {
+        let mut unit = 0;
+        let mut scale = 1u64;
+        while unit + 1 < units.len() && bytes / scale >= base {
+            scale *= base;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} B", bytes)
+        } else {
+            format!("{:.2} {}", bytes as f64 / scale as f64, units[unit])
+        }
+    }
+
+    /// Bytes per unit for a suffix, or `None` if it isn't one
+    fn multiplier(unit: &str) -> Option<u64> {
+        let unit = unit.to_ascii_lowercase();
+        if unit.is_empty() || unit == "b" {
+            return Some(1);
+        }
+        let mut chars = unit.chars();
+        let prefix = chars.next()?;
+        let exponent = PREFIXES.iter().position(|&p| p == prefix)? as u32 + 1;
+        match chars.as_str() {
+            "" | "i" | "ib" => Some(1024u64.pow(exponent)),
+            "b" => Some(1000u64.pow(exponent)),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = SkylockError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let input = s.trim();
+        let invalid = |reason: &str| SkylockError::Config(format!("Invalid size '{}': {}", s.trim(), reason));
+
+        if input.starts_with('-') {
+            return Err(invalid("sizes can't be negative"));
+        }
+        let split = input.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(input.len());
+        let (number, unit) = input.split_at(split);
+        if number.is_empty() {
+            return Err(invalid("expected a number such as \"1.5G\" or \"500KB\""));
+        }
+        let multiplier = Self::multiplier(unit.trim()).ok_or_else(|| {
+            invalid("unknown unit (use B, K/KiB, M/MiB, G/GiB, T/TiB for binary or KB, MB, GB, TB for decimal)")
+        })?;
+
+        if !number.contains('.') {
+            return number.parse::<u64>().ok()
+                .and_then(|n| n.checked_mul(multiplier))
+                .map(ByteSize)
+                .ok_or_else(|| invalid("too large"));
+        }
+
+        if multiplier == 1 {
+            return Err(invalid("a byte count can't have a fraction"));
+        }
+        let value: f64 = number.parse().map_err(|_| invalid("not a number"))?;
+        let bytes = (value * multiplier as f64).round();
+        if bytes >= u64::MAX as f64 {
+            return Err(invalid("too large"));
+        }
+        Ok(ByteSize(bytes as u64))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_binary_string())
+    }
+}
+
+impl From<u64> for ByteSize {
+    fn from(bytes: u64) -> Self {
+        ByteSize(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> u64 {
+        s.parse::<ByteSize>().unwrap_or_else(|e| panic!("{}: {}", s, e)).as_u64()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("0"), 0);
+        assert_eq!(parse("1024"), 1024);
+        assert_eq!(parse(" 1024 B "), 1024);
+        assert_eq!(parse("1K"), 1024);
+        assert_eq!(parse("1k"), 1024);
+        assert_eq!(parse("1KiB"), 1024);
+        assert_eq!(parse("1KB"), 1000);
+        assert_eq!(parse("1kb"), 1000);
+        assert_eq!(parse("1.5M"), 1_572_864);
+        assert_eq!(parse("1.5 MiB"), 1_572_864);
+        assert_eq!(parse("1.5MB"), 1_500_000);
+        assert_eq!(parse("1.5Gi"), 1_610_612_736);
+        assert_eq!(parse("2G"), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse("2GB"), 2_000_000_000);
+        assert_eq!(parse("1T"), 1u64 << 40);
+        assert_eq!(parse(".5K"), 512);
+        assert_eq!(parse("15E"), 15 * (1u64 << 60));
+    }
+
+    #[test]
+    fn test_parse_rejects() {
+        for input in ["", "-1", "-1.5M", "K", "1.5", "1.5B", "1X", "1 KiBs", "1.2.3M", "fast", "16E", "99999999999999999999"] {
+            let err = input.parse::<ByteSize>().unwrap_err();
+            assert!(matches!(err, SkylockError::Config(_)), "{}: {}", input, err);
+        }
+        assert!("-1".parse::<ByteSize>().unwrap_err().to_string().contains("negative"));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(ByteSize(0).to_string(), "0 B");
+        assert_eq!(ByteSize(1023).to_string(), "1023 B");
+        assert_eq!(ByteSize(1024).to_string(), "1.00 KiB");
+        assert_eq!(ByteSize(1_572_864).to_string(), "1.50 MiB");
+        assert_eq!(ByteSize(u64::MAX).to_string(), "16.00 EiB");
+        assert_eq!(ByteSize(999).to_decimal_string(), "999 B");
+        assert_eq!(ByteSize(1_500_000).to_decimal_string(), "1.50 MB");
+        assert_eq!(ByteSize(1024).to_decimal_string(), "1.02 KB");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for bytes in [0, 1, 1023, 1024, 1536, 1_572_864, 1_610_612_736, 5 << 40] {
+            let size = ByteSize(bytes);
+            assert_eq!(parse(&size.to_string()), bytes, "{}", size);
+        }
+        for bytes in [0, 999, 1_500_000, 2_000_000_000] {
+            assert_eq!(parse(&ByteSize(bytes).to_decimal_string()), bytes);
+        }
+
+        // Other values come back within the two displayed decimals
+        for bytes in [1000u64, 123_456_789, 987_654_321_000] {
+            let back = parse(&ByteSize(bytes).to_string());
+            assert!(back.abs_diff(bytes) as f64 <= bytes as f64 * 0.005, "{} -> {}", bytes, back);
+        }
+    }
+}
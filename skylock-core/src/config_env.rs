@@ -0,0 +1,234 @@
+//! Configuration overrides from environment variables
+//!
+//! Every setting in a section can be set through a variable named
+//! `SKYLOCK_<SECTION>__<KEY>`, e.g. `SKYLOCK_HETZNER__USERNAME` or
+//! `SKYLOCK_BACKUP__SCHEDULE`; deeper tables add further `__`-separated
+//! levels. Variables take precedence over the config file, and when they
+//! make up a complete configuration the file may be left out entirely,
+//! which suits containers and CI.
+//!
+//! Values are read as TOML when they parse as a TOML value (numbers,
+//! booleans, arrays such as `["/data", "/srv"]`, or quoted strings) and as
+//! plain strings otherwise. Settings that take a string keep the variable's
+//! text as it is, so `SKYLOCK_HETZNER__PASSWORD=123456` is the password
+//! `123456` rather than a number.
+
+use std::path::Path;
+
+use crate::{Config, Result, SkylockError};
+
+/// Prefix of configuration environment variables
+pub const CONFIG_ENV_PREFIX: &str = "SKYLOCK_";
+
+/// Separator between the nesting levels of a variable name
+pub const CONFIG_ENV_SEPARATOR: &str = "__";
+
+/// A setting taken from the environment
+struct EnvOverride {
+    variable: String,
+    keys: Vec<String>,
+    raw: String,
+    value: toml::Value,
+}
+
+impl Config {
+    /// [`Config::load`] with the environment passed in
+    pub(crate) fn load_with_env(
+        path: &Path,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let overrides = env_overrides(env);
+
+        let config_str = match std::fs::read_to_string(path) {
+            Ok(config_str) => config_str,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !overrides.is_empty() => String::new(),
+            Err(e) => return Err(SkylockError::Config(format!("Failed to read config file: {}", e))),
+        };
+
+        // Parse the file directly when nothing overrides it, for error
+        // messages pointing at the offending line
+        if overrides.is_empty() {
+            return toml::from_str(&config_str)
+                .map_err(|e| SkylockError::Config(format!("Failed to parse config: {}", e)));
+        }
+
+        let mut table: toml::Table = toml::from_str(&config_str)
+            .map_err(|e| SkylockError::Config(format!("Failed to parse config: {}", e)))?;
+        for setting in &overrides {
+            apply_override(&mut table, setting)?;
+        }
+
+        // A value read as TOML may land on a setting that takes a string;
+        // put the variable's text there instead and try again
+        let mut overrides = overrides;
+        loop {
+            let err = match serde_path_to_error::deserialize(toml::Value::Table(table.clone())) {
+                Ok(config) => return Ok(config),
+                Err(err) => err,
+            };
+            let path = err.path().to_string();
+            let Some(setting) = overrides.iter_mut()
+                .find(|setting| !setting.value.is_str() && setting.keys.join(".") == path)
+            else {
+                return Err(SkylockError::Config(format!(
+                    "Failed to parse config with environment overrides: {}", err
+                )));
+            };
+            setting.value = toml::Value::String(setting.raw.clone());
+            apply_override(&mut table, setting)?;
+        }
+    }
+}
+
+/// Settings named by `SKYLOCK_<SECTION>__<KEY>` variables, in name order
+///
+/// Variables without a section (such as the `SKYLOCK_HETZNER_PASSWORD`
+/// credential variable) are not configuration settings and are skipped.
+fn env_overrides(env: impl IntoIterator<Item = (String, String)>) -> Vec<EnvOverride> {
+    let mut overrides: Vec<EnvOverride> = env.into_iter()
+        .filter_map(|(variable, raw)| {
+            let name = variable.strip_prefix(CONFIG_ENV_PREFIX)?;
+            let keys: Vec<String> = name.split(CONFIG_ENV_SEPARATOR)
+                .map(|key| key.to_ascii_lowercase())
+                .collect();
+            if keys.len() < 2 || keys.iter().any(|key| key.is_empty()) {
+                return None;
+            }
+            Some(EnvOverride { value: env_value(&raw), raw, variable, keys })
+        })
+        .collect();
+    overrides.sort_by(|a, b| a.variable.cmp(&b.variable));
+    overrides
+}
+
+/// Read a variable's value as TOML if it is one, otherwise as a string
+fn env_value(raw: &str) -> toml::Value {
+    if raw.contains('\n') {
+        return toml::Value::String(raw.to_string());
+    }
+    toml::from_str::<toml::Table>(&format!("value = {}", raw)).ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Set a setting in `table`, creating the sections leading to it
+fn apply_override(table: &mut toml::Table, setting: &EnvOverride) -> Result<()> {
+    let (key, sections) = setting.keys.split_last().expect("overrides have a section and key");
+
+    let mut current = table;
+    for section in sections {
+        let entry = current.entry(section.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        current = entry.as_table_mut().ok_or_else(|| SkylockError::Config(format!(
+            "{}: `{}` is not a section", setting.variable, section
+        )))?;
+    }
+    current.insert(key.clone(), setting.value.clone());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    const CONFIG: &str = r#"
+[syncthing]
+api_key = "file-key"
+api_url = "http://localhost:8384"
+folders = []
+
+[hetzner]
+endpoint = "u1.your-storagebox.de"
+username = "file-user"
+password = "file-password"
+
+[backup]
+vss_enabled = false
+schedule = "0 0 2 * * *"
+retention_days = 30
+backup_paths = ["/data"]
+
+[ui]
+always_prompt_deletions = true
+notification_enabled = false
+"#;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_env_overrides_file_values() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, CONFIG).unwrap();
+
+        let config = Config::load_with_env(&path, env(&[
+            ("SKYLOCK_HETZNER__USERNAME", "env-user"),
+            ("SKYLOCK_BACKUP__SCHEDULE", "0 30 3 * * *"),
+            ("SKYLOCK_BACKUP__RETENTION_DAYS", "7"),
+            ("SKYLOCK_BACKUP__BACKUP_PATHS", r#"["/srv", "/home"]"#),
+            ("SKYLOCK_BACKUP__MAX_SPEED_LIMIT", "1.5M"),
+            ("SKYLOCK_METRICS__ENABLED", "true"),
+            // Not configuration settings
+            ("SKYLOCK_HETZNER_PASSWORD", "ignored"),
+            ("HOME", "/root"),
+        ])).unwrap();
+
+        assert_eq!(config.hetzner.username, "env-user");
+        assert_eq!(config.hetzner.password, "file-password");
+        assert_eq!(config.backup.schedule, "0 30 3 * * *");
+        assert_eq!(config.backup.retention_days, 7);
+        assert_eq!(config.backup.backup_paths, vec![PathBuf::from("/srv"), PathBuf::from("/home")]);
+        assert_eq!(config.backup.max_speed_limit.as_deref(), Some("1.5M"));
+        assert!(config.metrics.enabled);
+        assert_eq!(config.syncthing.api_key, "file-key");
+
+        // Overrides are still validated
+        let err = Config::load_with_env(&path, env(&[("SKYLOCK_BACKUP__MAX_CONCURRENT_UPLOADS", "0")]))
+            .unwrap().validate().unwrap_err();
+        assert!(err.to_string().contains("max_concurrent_uploads"));
+
+        let err = Config::load_with_env(&path, env(&[("SKYLOCK_HETZNER__USERNAME__NAME", "x")])).unwrap_err();
+        assert!(err.to_string().contains("SKYLOCK_HETZNER__USERNAME__NAME"));
+
+        // Settings that don't take a string still reject text
+        let err = Config::load_with_env(&path, env(&[("SKYLOCK_BACKUP__RETENTION_DAYS", "soon")])).unwrap_err();
+        assert!(err.to_string().contains("backup.retention_days"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_config_entirely_from_env_validates() {
+        let dir = TempDir::new().unwrap();
+        let missing = dir.path().join("config.toml");
+
+        let config = Config::load_with_env(&missing, env(&[
+            ("SKYLOCK_SYNCTHING__API_KEY", "true"),
+            ("SKYLOCK_SYNCTHING__API_URL", "http://localhost:8384"),
+            ("SKYLOCK_SYNCTHING__FOLDERS", "[]"),
+            ("SKYLOCK_HETZNER__ENDPOINT", "u1.your-storagebox.de"),
+            ("SKYLOCK_HETZNER__USERNAME", "u1"),
+            ("SKYLOCK_HETZNER__PASSWORD", "123456"),
+            ("SKYLOCK_HETZNER__ENCRYPTION_KEY", "correct horse battery staple"),
+            ("SKYLOCK_BACKUP__VSS_ENABLED", "false"),
+            ("SKYLOCK_BACKUP__SCHEDULE", "0 0 2 * * *"),
+            ("SKYLOCK_BACKUP__RETENTION_DAYS", "30"),
+            ("SKYLOCK_BACKUP__BACKUP_PATHS", r#"["/data"]"#),
+            ("SKYLOCK_UI__ALWAYS_PROMPT_DELETIONS", "false"),
+            ("SKYLOCK_UI__NOTIFICATION_ENABLED", "false"),
+        ])).unwrap();
+
+        config.validate().unwrap();
+        assert_eq!(config.hetzner.password, "123456");
+        assert_eq!(config.syncthing.api_key, "true");
+        assert_eq!(config.hetzner.encryption_key, "correct horse battery staple");
+        assert!(config.hetzner.has_encryption_key());
+
+        // Without the file or any override there is nothing to load
+        let err = Config::load_with_env(&missing, env(&[])).unwrap_err();
+        assert!(err.to_string().contains("Failed to read config file"));
+    }
+}
@@ -119,6 +119,8 @@ pub enum StorageErrorType {
     ConnectionFailed(String),
     /// Configuration error for storage provider
     ConfigError,
+    /// Object is in an archive tier and must be restored before it can be read
+    ColdStorage(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -215,6 +217,11 @@ impl std::fmt::Display for StorageErrorType {
             StorageErrorType::AuthenticationFailed => write!(f, "Authentication failed"),
             StorageErrorType::ConnectionFailed(msg) => write!(f, "Connection failed: {}", msg),
             StorageErrorType::ConfigError => write!(f, "Storage configuration error"),
+            StorageErrorType::ColdStorage(path) => write!(
+                f,
+                "Object {} is in cold storage, restore may be delayed until it is retrieved",
+                path
+            ),
         }
     }
 }
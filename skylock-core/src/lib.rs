@@ -1,4 +1,5 @@
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -10,6 +11,8 @@ pub mod storage;
 pub mod sync;
 pub mod error_types;
 pub mod audit;
+pub mod config_env;
+pub mod byte_size;
 
 // Re-export error types
 pub use error_types::{Error, ErrorCategory, ErrorSeverity, SystemError};
@@ -22,6 +25,7 @@ pub use error_types::{
 pub use security::{KeyType, EncryptionEngine, SecureKey};
 pub use security::key_manager::{KeyManager, KeyRotationPolicy, KeyStatus, KeyMetadata};
 pub use compression::{CompressionConfig, CompressionEngine, CompressionType};
+pub use byte_size::ByteSize;
 
 #[derive(Debug, Error)]
 pub enum SkylockError {
@@ -114,6 +118,16 @@ pub struct Config {
     pub hetzner: HetznerConfig,
     pub backup: BackupConfig,
     pub ui: UiConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub credentials: CredentialsConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub scrub: ScrubConfig,
     #[serde(default = "default_data_dir")]
     pub data_dir: PathBuf,
 }
@@ -134,13 +148,77 @@ pub struct SyncthingConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HetznerConfig {
     pub endpoint: String,
+    /// Storage box user; may instead come from a credential source
+    #[serde(default)]
     pub username: String,
+    /// Storage box password; may instead come from a credential source
+    #[serde(default)]
     pub password: String,
+    /// Encryption passphrase; may instead come from a credential source
+    #[serde(default)]
     pub encryption_key: String,
+    /// Maximum directory listing (PROPFIND) requests per second, to stay
+    /// under the provider's rate limits (None = unlimited)
+    #[serde(default)]
+    pub max_list_requests_per_second: Option<f64>,
+    /// Maximum deletion requests per second when cleanup removes backups
+    /// (None = unlimited)
+    #[serde(default)]
+    pub max_delete_requests_per_second: Option<f64>,
+    /// Seconds allowed to establish a connection to the storage box
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Seconds an upload, download or listing may go without sending or
+    /// receiving any data before it fails as timed out. Measured against
+    /// progress, so long transfers are fine while bytes keep moving
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// Transfer protocol: "webdav" (default) or "sftp", which needs the
+    /// `[hetzner.sftp]` settings
+    #[serde(default)]
+    pub protocol: Option<String>,
+    /// Key authentication and host key verification for SFTP
+    #[serde(default)]
+    pub sftp: Option<SftpConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpConfig {
+    /// SSH port; Storage Boxes accept SFTP with key authentication on 23
+    #[serde(default = "default_sftp_port")]
+    pub port: u16,
+    /// Ed25519 private key whose public key is installed on the Storage Box
+    pub private_key: PathBuf,
+    /// Passphrase of the private key, if it has one
+    #[serde(default)]
+    pub key_passphrase: Option<String>,
+    /// known_hosts file holding the server's host key (default:
+    /// `known_hosts` in the data directory). Recorded on first connect;
+    /// a changed key is refused
+    #[serde(default)]
+    pub known_hosts: Option<PathBuf>,
+}
+
+fn default_sftp_port() -> u16 {
+    23
+}
+
+fn default_blob_shard_depth() -> usize {
+    2
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    30
+}
+
+fn default_read_timeout_secs() -> u64 {
+    120
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BackupConfig {
+    /// Read files that are locked or unreadable from a shadow copy instead
+    /// of skipping them
     pub vss_enabled: bool,
     pub schedule: String,
     pub retention_days: u32,
@@ -148,27 +226,541 @@ pub struct BackupConfig {
     /// Maximum upload speed limit (e.g., "1.5M", "500K", "0" for unlimited)
     #[serde(default)]
     pub max_speed_limit: Option<String>,
+    /// Cipher for new backups ("aes-256-gcm" or "chacha20-poly1305", default AES-256-GCM)
+    #[serde(default)]
+    pub encryption_algorithm: Option<String>,
+    /// Encrypt file names in manifests and store files under opaque names.
+    /// Off by default because it makes the storage box hard to inspect
+    #[serde(default)]
+    pub encrypt_file_names: bool,
+    /// Number of incremental backups allowed on top of a full backup before
+    /// the chain is consolidated into a synthetic full (None = never)
+    #[serde(default)]
+    pub max_chain_length: Option<usize>,
+    /// Sort manifest entries by path, so backups of an unchanged tree give
+    /// manifests that differ only in run metadata (IDs and timestamps)
+    #[serde(default)]
+    pub canonical_manifests: bool,
+    /// Compress manifests with zstd before encrypting them, which shrinks
+    /// the download for backups with many files
+    #[serde(default)]
+    pub compress_manifests: bool,
+    /// Compression for every file in direct uploads ("none", "lz4", "zstd",
+    /// "brotli" or "gzip"); unset picks one per file. `--compression-algo`
+    /// overrides it
+    #[serde(default)]
+    pub compression_algorithm: Option<String>,
+    /// Compression pinned per file type, as glob or MIME type to
+    /// "algorithm[:level]" (e.g. `"*.jpg" = "none"`, `"*.log" = "zstd:19"`);
+    /// files matching none are compressed adaptively. Ignored while
+    /// `compression_algorithm` or `--compression-algo` forces one algorithm
+    #[serde(default)]
+    pub compression_profiles: std::collections::BTreeMap<String, String>,
+    /// Directory levels that hash-named blobs (chunks and files with
+    /// encrypted names) fan out over, e.g. 2 for `ab/cd/<hash>`, so no remote
+    /// directory holds every blob. 0 stores them flat; only new uploads are
+    /// affected
+    #[serde(default = "default_blob_shard_depth")]
+    pub blob_shard_depth: usize,
+    /// Files uploaded, downloaded or verified at once; unset picks a
+    /// default from the number of cores. `--concurrency` overrides it
+    #[serde(default)]
+    pub max_concurrent_uploads: Option<usize>,
+    /// Directories holding further copies of the backups (e.g. a mounted NAS
+    /// or USB disk), used by `verify --repair` to fix damaged blobs
+    #[serde(default)]
+    pub mirrors: Vec<MirrorConfig>,
+    /// Directory for temporary files (archives, manifests and downloads
+    /// being checked); unset uses `SKYLOCK_TEMP_DIR`, then the system
+    /// default, which honours `TMPDIR`
+    #[serde(default)]
+    pub temp_dir: Option<PathBuf>,
+    /// Size up to which transfers are kept in memory instead of a file in
+    /// `temp_dir` (e.g. "16M"; default 8 MiB, "0" always uses files)
+    #[serde(default)]
+    pub spill_threshold: Option<String>,
+    /// Memory for decrypted blocks reused by `browse` and `preview-file`
+    /// (e.g. "256M"; default 64 MiB, "0" disables the cache)
+    #[serde(default)]
+    pub block_cache_size: Option<String>,
+    /// Directory `restore` creates its target in when no `--target` or
+    /// `--output-dir` is given; unset uses the current directory
+    #[serde(default)]
+    pub restore_dir: Option<PathBuf>,
+    /// Files at least this large (e.g. "1G") are compressed, encrypted and
+    /// uploaded as a stream instead of being read into memory; unset keeps
+    /// every file in memory
+    #[serde(default)]
+    pub stream_threshold: Option<String>,
+    /// Files larger than this (e.g. "50G") are streamed whatever
+    /// `stream_threshold` says, or skipped with `skip_oversized_files`;
+    /// unset puts no limit on file size
+    #[serde(default)]
+    pub max_file_size: Option<String>,
+    /// Skip files larger than `max_file_size`, recording them in the
+    /// manifest, instead of streaming them
+    #[serde(default)]
+    pub skip_oversized_files: bool,
+    /// Days a backup removed by `cleanup` stays in the trash, recoverable
+    /// with `restore-deleted`, before it is deleted for good (default 14;
+    /// 0 deletes immediately)
+    #[serde(default)]
+    pub trash_days: Option<u32>,
+    /// Manifests downloaded at once when listing backups (default 8)
+    #[serde(default)]
+    pub list_concurrency: Option<usize>,
+    /// Blobs deleted at once when removing a backup (default 8)
+    #[serde(default)]
+    pub delete_concurrency: Option<usize>,
+    /// Refuse to restore from or build on a manifest that is unsigned or
+    /// whose signature doesn't verify against the manifest public key
+    #[serde(default)]
+    pub require_signed_manifests: bool,
+    /// PEM public key manifest signatures are checked against; unset uses
+    /// the key `skylock generate-manifest-key` wrote to the data directory
+    #[serde(default)]
+    pub manifest_public_key: Option<PathBuf>,
+    /// Plaintext bytes per encrypted segment of streamed files (e.g. "4M";
+    /// default 16 MiB, between 64 KiB and 64 MiB). Larger frames cost memory
+    /// on both ends, smaller ones more overhead and more segments per key
+    #[serde(default)]
+    pub aead_frame_size: Option<String>,
+    /// Hash of file and chunk content in new backups ("sha256" or "blake3",
+    /// default SHA-256). Each backup records its own, so restores and
+    /// verification are unaffected by changing it
+    #[serde(default)]
+    pub hash_algorithm: Option<String>,
+    /// Key agreement of forward-secrecy sessions ("classic" for X25519, or
+    /// "hybrid" to add ML-KEM-768 against future quantum attacks; default
+    /// classic)
+    #[serde(default)]
+    pub key_exchange: Option<String>,
+    /// Retries of failed uploads and downloads, and the limits on them
+    /// across a whole backup or restore (`[backup.retry]`)
+    #[serde(default)]
+    pub retry: RetryPolicy,
+}
+
+/// How failed transfers are retried
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Retries of a failed upload or download before its file is failed
+    /// (default 0)
+    #[serde(default)]
+    pub max_retries: u32,
+    /// Delay before the first retry in milliseconds, doubled for each
+    /// further one (default 1000)
+    #[serde(default)]
+    pub base_delay_ms: Option<u64>,
+    /// Limits on all the attempts of one backup or restore together
+    #[serde(default)]
+    pub budget: RetryBudget,
+}
+
+/// Limits on all the attempts of one high-level operation, after which it
+/// gives up even if its transfers could still be retried
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryBudget {
+    /// Attempts allowed in total, first tries included (None = unlimited)
+    #[serde(default)]
+    pub max_total_attempts: Option<usize>,
+    /// Minutes the whole operation may take (None = unlimited)
+    #[serde(default)]
+    pub deadline_minutes: Option<u64>,
+}
+
+/// Transfers up to this size stay in memory when `backup.spill_threshold` is unset
+pub const DEFAULT_SPILL_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+/// Decrypted blocks kept in memory when `backup.block_cache_size` is unset
+pub const DEFAULT_BLOCK_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Segment size of streamed files when `backup.aead_frame_size` is unset
+pub const DEFAULT_AEAD_FRAME_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Smallest `backup.aead_frame_size`; smaller frames add overhead and use
+/// up a key's safe message count on files that are merely large
+pub const MIN_AEAD_FRAME_SIZE: u64 = 64 * 1024;
+
+/// Largest `backup.aead_frame_size`, bounding the buffers of restores
+pub const MAX_AEAD_FRAME_SIZE: u64 = 64 * 1024 * 1024;
+
+impl BackupConfig {
+    /// `spill_threshold` in bytes, or [`DEFAULT_SPILL_THRESHOLD`] when unset
+    pub fn spill_threshold_bytes(&self) -> Result<u64> {
+        match self.spill_threshold.as_deref() {
+            Some(size) => Ok(size.parse::<ByteSize>()?.as_u64()),
+            None => Ok(DEFAULT_SPILL_THRESHOLD),
+        }
+    }
+
+    /// `block_cache_size` in bytes, or [`DEFAULT_BLOCK_CACHE_SIZE`] when unset
+    pub fn block_cache_size_bytes(&self) -> Result<u64> {
+        match self.block_cache_size.as_deref() {
+            Some(size) => Ok(size.parse::<ByteSize>()?.as_u64()),
+            None => Ok(DEFAULT_BLOCK_CACHE_SIZE),
+        }
+    }
+
+    /// `stream_threshold` in bytes, or `None` when streaming is off
+    pub fn stream_threshold_bytes(&self) -> Result<Option<u64>> {
+        self.stream_threshold.as_deref()
+            .map(|size| Ok(size.parse::<ByteSize>()?.as_u64()))
+            .transpose()
+    }
+
+    /// `max_file_size` in bytes, or `None` when there is no limit
+    pub fn max_file_size_bytes(&self) -> Result<Option<u64>> {
+        self.max_file_size.as_deref()
+            .map(|size| Ok(size.parse::<ByteSize>()?.as_u64()))
+            .transpose()
+    }
+
+    /// `aead_frame_size` in bytes, or [`DEFAULT_AEAD_FRAME_SIZE`] when
+    /// unset; fails outside [`MIN_AEAD_FRAME_SIZE`]..=[`MAX_AEAD_FRAME_SIZE`]
+    pub fn aead_frame_size_bytes(&self) -> Result<u64> {
+        let size = match self.aead_frame_size.as_deref() {
+            Some(size) => size.parse::<ByteSize>()?.as_u64(),
+            None => return Ok(DEFAULT_AEAD_FRAME_SIZE),
+        };
+        if !(MIN_AEAD_FRAME_SIZE..=MAX_AEAD_FRAME_SIZE).contains(&size) {
+            return Err(SkylockError::Config(format!(
+                "{} bytes is outside the supported range of {} KiB to {} MiB",
+                size, MIN_AEAD_FRAME_SIZE / 1024, MAX_AEAD_FRAME_SIZE / (1024 * 1024)
+            )));
+        }
+        Ok(size)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    /// Name shown in verification and repair reports
+    pub name: String,
+    /// Directory laid out like the Storage Box root
+    pub path: PathBuf,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UiConfig {
     pub always_prompt_deletions: bool,
     pub notification_enabled: bool,
+    /// Action applied without prompting when `always_prompt_deletions` is off
+    /// ("local", "everywhere" or "cancel"; default "local")
+    #[serde(default)]
+    pub deletion_default: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    /// URL that receives a JSON POST for each event routed to the webhook
+    /// (by default backup start, success and failure, and scrub damage)
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// Shared secret for signing the request body with HMAC-SHA256
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+    /// Per-request timeout in seconds
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub webhook_timeout_secs: u64,
+    /// Additional attempts after a failed delivery
+    #[serde(default = "default_webhook_retries")]
+    pub webhook_retries: u32,
+    /// Events less severe than this are sent nowhere (default "info")
+    #[serde(default)]
+    pub min_severity: NotificationSeverity,
+    /// Channels per event type, replacing the defaults of the events listed
+    #[serde(default)]
+    pub routes: HashMap<NotificationEvent, NotificationRoute>,
+    /// SMTP server for the email channel
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+}
+
+impl NotificationsConfig {
+    /// Channels `event` is sent to: those of its route, or its defaults
+    /// without one, and none when it is below the applicable minimum severity
+    pub fn channels_for(&self, event: NotificationEvent) -> Vec<NotificationChannel> {
+        let route = self.routes.get(&event);
+        let min_severity = route.and_then(|route| route.min_severity).unwrap_or(self.min_severity);
+        if event.severity() < min_severity {
+            return Vec::new();
+        }
+        match route {
+            Some(route) => route.channels.clone(),
+            None => event.default_channels().to_vec(),
+        }
+    }
+}
+
+/// How serious a notification is, least serious first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationSeverity {
+    #[default]
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+/// Backup lifecycle or scrub event a notification reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    BackupStarted,
+    BackupSucceeded,
+    BackupFailed,
+    RestoreStarted,
+    RestoreSucceeded,
+    RestoreFailed,
+    ScrubFailed,
+}
+
+impl NotificationEvent {
+    pub fn severity(&self) -> NotificationSeverity {
+        match self {
+            NotificationEvent::BackupStarted
+            | NotificationEvent::BackupSucceeded
+            | NotificationEvent::RestoreStarted
+            | NotificationEvent::RestoreSucceeded => NotificationSeverity::Info,
+            NotificationEvent::BackupFailed | NotificationEvent::RestoreFailed => NotificationSeverity::Error,
+            // Stored backups are damaged
+            NotificationEvent::ScrubFailed => NotificationSeverity::Critical,
+        }
+    }
+
+    /// Channels used when no route is configured; restores only ever
+    /// notified the desktop
+    pub fn default_channels(&self) -> &'static [NotificationChannel] {
+        match self {
+            NotificationEvent::RestoreStarted
+            | NotificationEvent::RestoreSucceeded
+            | NotificationEvent::RestoreFailed => &[NotificationChannel::Desktop],
+            _ => &[NotificationChannel::Desktop, NotificationChannel::Webhook],
+        }
+    }
+}
+
+/// Where a notification is delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationChannel {
+    /// Desktop notification (D-Bus on Linux, printed elsewhere)
+    Desktop,
+    /// POST to `webhook_url`
+    Webhook,
+    /// Mail through the `[notifications.email]` SMTP server
+    Email,
+    /// Entry in the application log
+    Log,
+}
+
+/// Channels and threshold of one event type
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationRoute {
+    /// Channels the event is sent to; empty silences it
+    #[serde(default)]
+    pub channels: Vec<NotificationChannel>,
+    /// Overrides `min_severity` for this event
+    #[serde(default)]
+    pub min_severity: Option<NotificationSeverity>,
+}
+
+/// SMTP settings of the email channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    /// SMTP server, reached with STARTTLS
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Sender address
+    pub from: String,
+    /// Recipient addresses
+    pub to: Vec<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    10
+}
+
+fn default_webhook_retries() -> u32 {
+    2
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            webhook_secret: None,
+            webhook_timeout_secs: default_webhook_timeout_secs(),
+            webhook_retries: default_webhook_retries(),
+            min_severity: NotificationSeverity::default(),
+            routes: HashMap::new(),
+            email: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Serve Prometheus metrics on `/metrics` while the daemon runs
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address the metrics endpoint listens on
+    #[serde(default = "default_metrics_bind_address")]
+    pub bind_address: String,
+}
+
+fn default_metrics_bind_address() -> String {
+    "127.0.0.1:9477".to_string()
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: default_metrics_bind_address(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Console log format: "text" (default) or "json" for one JSON object
+    /// per line. The log file is always JSON
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Level filter, either a level ("debug") or per-module directives such
+    /// as "info,skylock_hetzner=debug". `RUST_LOG` takes precedence
+    #[serde(default)]
+    pub level: Option<String>,
+    /// OTLP/gRPC collector endpoint (e.g. "http://localhost:4317") that
+    /// backup and restore spans are exported to. Nothing is exported when unset
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubConfig {
+    /// Have the daemon periodically read back a sample of the stored blobs
+    #[serde(default)]
+    pub enabled: bool,
+    /// Cron expression (6 fields) for scrub runs
+    #[serde(default = "default_scrub_schedule")]
+    pub schedule: String,
+    /// Runs it takes to check every blob once; each run checks about
+    /// 1/cycles of them
+    #[serde(default = "default_scrub_cycles")]
+    pub cycles: u32,
+}
+
+fn default_scrub_schedule() -> String {
+    "0 30 4 * * *".to_string()
+}
+
+fn default_scrub_cycles() -> u32 {
+    30
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            schedule: default_scrub_schedule(),
+            cycles: default_scrub_cycles(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialsConfig {
+    /// Credential sources tried in order: "env", "keychain", "file" and
+    /// "vault". Values in `[hetzner]` are used when no source has a secret
+    #[serde(default = "default_credential_sources")]
+    pub sources: Vec<String>,
+    /// Encrypted credentials file (default: `credentials.json` in the data
+    /// directory)
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// HashiCorp Vault KV v2 secret, used by the "vault" source
+    #[serde(default)]
+    pub vault: Option<VaultConfig>,
+}
+
+fn default_credential_sources() -> Vec<String> {
+    vec!["env".to_string(), "keychain".to_string(), "file".to_string()]
+}
+
+impl Default for CredentialsConfig {
+    fn default() -> Self {
+        Self {
+            sources: default_credential_sources(),
+            file: None,
+            vault: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultConfig {
+    /// Vault server address (default: `VAULT_ADDR`)
+    #[serde(default)]
+    pub address: Option<String>,
+    /// KV v2 mount point
+    #[serde(default = "default_vault_mount")]
+    pub mount: String,
+    /// Secret path below the mount
+    #[serde(default = "default_vault_path")]
+    pub path: String,
+}
+
+fn default_vault_mount() -> String {
+    "secret".to_string()
+}
+
+fn default_vault_path() -> String {
+    "skylock".to_string()
+}
+
+impl HetznerConfig {
+    /// Whether a storage box user and password are set, ignoring the
+    /// placeholders of the generated default config
+    pub fn has_credentials(&self) -> bool {
+        !self.username.is_empty() && self.username != "your-username" && !self.password.is_empty()
+    }
+
+    /// Whether an encryption passphrase is set, ignoring the placeholder of
+    /// the generated default config
+    pub fn has_encryption_key(&self) -> bool {
+        !self.encryption_key.is_empty() && self.encryption_key != "your-encryption-key"
+    }
 }
 
 impl Config {
+    /// Load the config file at `path` (default: the platform config
+    /// directory), overlaid with `SKYLOCK_<SECTION>__<KEY>` environment
+    /// variables (see [`config_env`])
+    ///
+    /// The file is optional when at least one such variable is set.
     pub fn load(path: Option<PathBuf>) -> Result<Self> {
-        let path = path.unwrap_or_else(|| {
-            directories::ProjectDirs::from("com", "skylock", "skylock-hybrid")
-                .map(|proj_dirs| proj_dirs.config_dir().join("config.toml"))
-                .unwrap_or_else(|| PathBuf::from("config.toml"))
-        });
-
-        let config_str = std::fs::read_to_string(&path)
-            .map_err(|e| SkylockError::Config(format!("Failed to read config file: {}", e)))?;
+        let path = path.unwrap_or_else(Self::default_path);
+        Self::load_with_env(&path, std::env::vars())
+    }
 
-        toml::from_str(&config_str)
-            .map_err(|e| SkylockError::Config(format!("Failed to parse config: {}", e)))
+    /// Config file read when no path is given
+    pub fn default_path() -> PathBuf {
+        directories::ProjectDirs::from("com", "skylock", "skylock-hybrid")
+            .map(|proj_dirs| proj_dirs.config_dir().join("config.toml"))
+            .unwrap_or_else(|| PathBuf::from("config.toml"))
     }
     
     pub fn validate(&self) -> Result<()> {
@@ -185,6 +777,53 @@ impl Config {
             return Err(SkylockError::Config("At least one backup path is required".to_string()));
         }
         
+        match self.hetzner.protocol.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None | Some("webdav") => {}
+            Some("sftp") if self.hetzner.sftp.is_some() => {}
+            Some("sftp") => {
+                return Err(SkylockError::Config(
+                    "hetzner.protocol = \"sftp\" needs a [hetzner.sftp] section with the private key".to_string()
+                ));
+            }
+            Some(other) => {
+                return Err(SkylockError::Config(format!(
+                    "Unknown hetzner.protocol '{}' (expected \"webdav\" or \"sftp\")", other
+                )));
+            }
+        }
+        
+        if self.backup.max_concurrent_uploads == Some(0) {
+            return Err(SkylockError::Config("backup.max_concurrent_uploads must be at least 1".to_string()));
+        }
+        
+        if self.backup.list_concurrency == Some(0) {
+            return Err(SkylockError::Config("backup.list_concurrency must be at least 1".to_string()));
+        }
+        
+        if self.backup.delete_concurrency == Some(0) {
+            return Err(SkylockError::Config("backup.delete_concurrency must be at least 1".to_string()));
+        }
+        
+        if let Err(SkylockError::Config(reason)) = self.backup.spill_threshold_bytes() {
+            return Err(SkylockError::Config(format!("backup.spill_threshold: {}", reason)));
+        }
+        
+        if let Err(SkylockError::Config(reason)) = self.backup.block_cache_size_bytes() {
+            return Err(SkylockError::Config(format!("backup.block_cache_size: {}", reason)));
+        }
+        
+        if let Err(SkylockError::Config(reason)) = self.backup.stream_threshold_bytes() {
+            return Err(SkylockError::Config(format!("backup.stream_threshold: {}", reason)));
+        }
+        
+        if let Err(SkylockError::Config(reason)) = self.backup.max_file_size_bytes() {
+            return Err(SkylockError::Config(format!("backup.max_file_size: {}", reason)));
+        }
+        
+        if let Err(SkylockError::Config(reason)) = self.backup.aead_frame_size_bytes() {
+            return Err(SkylockError::Config(format!("backup.aead_frame_size: {}", reason)));
+        }
+        
         Ok(())
     }
 }
\ No newline at end of file
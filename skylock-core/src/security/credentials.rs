@@ -1,80 +1,716 @@
-use crate::error::Result;
-use aes_gcm::{Aes256Gcm, Key, Nonce};
-use aes_gcm::aead::{Aead, NewAead};
-use rand::Rng;
-use std::path::PathBuf;
-use tokio::fs;
-
-pub struct CredentialManager {
-    key_file: PathBuf,
-    cipher: Aes256Gcm,
-}
-
-impl CredentialManager {
-    pub fn new(key_file: PathBuf) -> Result<Self> {
-        let key = if key_file.exists() {
-            fs::read(&key_file).await?
-        } else {
-            let mut key = [0u8; 32];
-            rand::thread_rng().fill(&mut key);
-            fs::write(&key_file, &key).await?;
-            key.to_vec()
-        };
+//! Credential providers
+//!
+//! Storage credentials and the encryption passphrase are looked up through a
+//! [`CredentialProvider`] rather than only read from plaintext config fields.
+//! The `[credentials] sources` setting chains providers in order (by default
+//! environment, OS keychain, then an encrypted file); the first one holding a
+//! secret wins and the config value is the last resort.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{Config, CredentialsConfig, HetznerConfig, Result, SkylockError};
+
+/// Secret name of the storage box user
+pub const HETZNER_USERNAME: &str = "hetzner-username";
+/// Secret name of the storage box password
+pub const HETZNER_PASSWORD: &str = "hetzner-password";
+/// Secret name of the encryption passphrase
+pub const ENCRYPTION_KEY: &str = "encryption-key";
+/// Secret name of the Syncthing REST API key
+pub const SYNCTHING_API_KEY: &str = "syncthing-api-key";
+
+/// Environment variable unlocking the encrypted credentials file
+pub const CREDENTIALS_PASSPHRASE_ENV: &str = "SKYLOCK_CREDENTIALS_PASSPHRASE";
 
-        let cipher = Aes256Gcm::new(Key::from_slice(&key));
+/// File name of the encrypted credentials file inside the data directory
+pub const CREDENTIALS_FILE_NAME: &str = "credentials.json";
 
-        Ok(Self {
-            key_file,
-            cipher,
-        })
+/// Source of secrets such as passwords and keys
+#[async_trait]
+pub trait CredentialProvider: Send + Sync {
+    /// Short name used in messages, e.g. "env"
+    fn name(&self) -> &str;
+
+    /// Secret stored under `key`, or `None` if this provider doesn't hold it
+    async fn get(&self, key: &str) -> Result<Option<Zeroizing<String>>>;
+
+    /// Store a secret under `key`
+    ///
+    /// Returns `false` if this provider cannot store secrets, because it is
+    /// read-only or unavailable on this system.
+    async fn set(&self, key: &str, value: &str) -> Result<bool>;
+}
+
+/// Reads `SKYLOCK_<KEY>` environment variables, e.g. `SKYLOCK_HETZNER_PASSWORD`
+#[derive(Debug, Clone)]
+pub struct EnvCredentialProvider {
+    prefix: String,
+}
+
+impl Default for EnvCredentialProvider {
+    fn default() -> Self {
+        Self::with_prefix("SKYLOCK_")
     }
+}
 
-    pub async fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
-        let mut nonce = [0u8; 12];
-        rand::thread_rng().fill(&mut nonce);
-        let nonce = Nonce::from_slice(&nonce);
+impl EnvCredentialProvider {
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self { prefix: prefix.to_string() }
+    }
+
+    /// Variable holding `key`: the prefix plus the upper-cased key with
+    /// dashes as underscores
+    pub fn variable(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key.to_ascii_uppercase().replace('-', "_"))
+    }
+}
 
-        let ciphertext = self.cipher
-            .encrypt(nonce, data)
-            .map_err(|_| SecurityErrorType::EncryptionFailed)?;
+#[async_trait]
+impl CredentialProvider for EnvCredentialProvider {
+    fn name(&self) -> &str {
+        "env"
+    }
 
-        let mut result = nonce.to_vec();
-        result.extend(ciphertext);
-        Ok(result)
+    async fn get(&self, key: &str) -> Result<Option<Zeroizing<String>>> {
+        Ok(std::env::var(self.variable(key)).ok()
+            .filter(|value| !value.is_empty())
+            .map(Zeroizing::new))
     }
 
-    pub async fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
-        if data.len() < 12 {
-            return Err(SecurityErrorType::DecryptionFailed)?;
+    async fn set(&self, _key: &str, _value: &str) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// OS keychain: macOS Keychain, Windows Credential Manager or the Secret
+/// Service on Linux
+///
+/// A system without a usable keychain (e.g. a headless server) is treated
+/// as an empty, read-only provider.
+#[derive(Debug, Clone)]
+pub struct KeychainCredentialProvider {
+    service: String,
+}
+
+impl Default for KeychainCredentialProvider {
+    fn default() -> Self {
+        Self::new("skylock")
+    }
+}
+
+impl KeychainCredentialProvider {
+    /// Keychain entries stored under `service`, one per secret
+    pub fn new(service: &str) -> Self {
+        Self { service: service.to_string() }
+    }
+}
+
+#[cfg(feature = "keychain")]
+#[async_trait]
+impl CredentialProvider for KeychainCredentialProvider {
+    fn name(&self) -> &str {
+        "keychain"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Zeroizing<String>>> {
+        let (service, key) = (self.service.clone(), key.to_string());
+        let result = tokio::task::spawn_blocking(move || {
+            keyring::Entry::new(&service, &key).and_then(|entry| entry.get_password())
+        }).await.map_err(|e| SkylockError::Other(format!("Keychain lookup failed: {}", e)))?;
+
+        match result {
+            Ok(value) => Ok(Some(Zeroizing::new(value))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e @ (keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_))) => {
+                tracing::debug!("Keychain unavailable: {}", e);
+                Ok(None)
+            }
+            Err(e) => Err(SkylockError::Other(format!("Keychain lookup failed: {}", e))),
+        }
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<bool> {
+        let (service, key) = (self.service.clone(), key.to_string());
+        let value = Zeroizing::new(value.to_string());
+        let result = tokio::task::spawn_blocking(move || {
+            keyring::Entry::new(&service, &key).and_then(|entry| entry.set_password(&value))
+        }).await.map_err(|e| SkylockError::Other(format!("Keychain update failed: {}", e)))?;
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(e @ (keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_))) => {
+                tracing::debug!("Keychain unavailable: {}", e);
+                Ok(false)
+            }
+            Err(e) => Err(SkylockError::Other(format!("Keychain update failed: {}", e))),
         }
+    }
+}
+
+#[cfg(not(feature = "keychain"))]
+#[async_trait]
+impl CredentialProvider for KeychainCredentialProvider {
+    fn name(&self) -> &str {
+        "keychain"
+    }
 
+    async fn get(&self, _key: &str) -> Result<Option<Zeroizing<String>>> {
+        tracing::debug!("Built without keychain support");
+        Ok(None)
+    }
+
+    async fn set(&self, _key: &str, _value: &str) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Argon2id parameters of an encrypted credentials file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileKdf {
+    /// Base64 salt
+    salt: String,
+    memory_cost: u32,
+    time_cost: u32,
+    parallelism: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CredentialsFile {
+    kdf: FileKdf,
+    /// Base64 of [nonce][ciphertext+tag] by secret name
+    secrets: BTreeMap<String, String>,
+}
+
+/// Secrets in a JSON file, each encrypted with AES-256-GCM under a key
+/// derived from a passphrase
+///
+/// The secret name is authenticated with each value, so values can't be
+/// swapped between names. The file is written with owner-only permissions.
+pub struct EncryptedFileCredentialProvider {
+    path: PathBuf,
+    passphrase: Option<Zeroizing<String>>,
+}
+
+impl EncryptedFileCredentialProvider {
+    /// Provider for the file at `path`; without a passphrase it can only
+    /// report that it is locked
+    pub fn new(path: PathBuf, passphrase: Option<Zeroizing<String>>) -> Self {
+        Self { path, passphrase }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn passphrase(&self) -> Result<&str> {
+        self.passphrase.as_deref().map(String::as_str).ok_or_else(|| SkylockError::Config(format!(
+            "Credentials file {} is locked; set {} to its passphrase",
+            self.path.display(),
+            CREDENTIALS_PASSPHRASE_ENV
+        )))
+    }
+
+    fn cipher(&self, kdf: &FileKdf) -> Result<Aes256Gcm> {
+        let salt = base64::engine::general_purpose::STANDARD.decode(&kdf.salt)
+            .map_err(|e| SkylockError::Config(format!("Invalid credentials file salt: {}", e)))?;
+        let params = argon2::Params::new(kdf.memory_cost, kdf.time_cost, kdf.parallelism, Some(32))
+            .map_err(|e| SkylockError::Config(format!("Invalid credentials file KDF parameters: {}", e)))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password_into(self.passphrase()?.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| SkylockError::Encryption(format!("Failed to derive credentials file key: {}", e)))?;
+        Aes256Gcm::new_from_slice(key.as_ref())
+            .map_err(|e| SkylockError::Encryption(format!("Invalid credentials file key: {}", e)))
+    }
+
+    async fn load(&self) -> Result<Option<CredentialsFile>> {
+        match tokio::fs::read(&self.path).await {
+            Ok(data) => serde_json::from_slice(&data).map(Some).map_err(|e| SkylockError::Config(format!(
+                "Failed to parse credentials file {}: {}",
+                self.path.display(),
+                e
+            ))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&self, file: &CredentialsFile) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp_path = self.path.with_extension("json.tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec_pretty(file)?).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o600)).await?;
+        }
+        tokio::fs::rename(&temp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for EncryptedFileCredentialProvider {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Zeroizing<String>>> {
+        let Some(file) = self.load().await? else {
+            return Ok(None);
+        };
+        let Some(encrypted) = file.secrets.get(key) else {
+            return Ok(None);
+        };
+
+        let data = base64::engine::general_purpose::STANDARD.decode(encrypted)
+            .map_err(|e| SkylockError::Config(format!("Invalid credential {}: {}", key, e)))?;
+        if data.len() < 12 {
+            return Err(SkylockError::Config(format!("Invalid credential {}: too short", key)));
+        }
         let (nonce, ciphertext) = data.split_at(12);
-        let nonce = Nonce::from_slice(nonce);
+        let plaintext = Zeroizing::new(self.cipher(&file.kdf)?
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: key.as_bytes() })
+            .map_err(|_| SkylockError::Encryption(format!(
+                "Failed to decrypt {} from {} (wrong passphrase?)",
+                key,
+                self.path.display()
+            )))?);
+        String::from_utf8(plaintext.to_vec())
+            .map(|value| Some(Zeroizing::new(value)))
+            .map_err(|_| SkylockError::Config(format!("Credential {} is not valid UTF-8", key)))
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<bool> {
+        use rand::RngCore;
+
+        let mut file = match self.load().await? {
+            Some(file) => file,
+            None => {
+                let mut salt = [0u8; 16];
+                rand::rngs::OsRng.fill_bytes(&mut salt);
+                let defaults = argon2::Params::default();
+                CredentialsFile {
+                    kdf: FileKdf {
+                        salt: base64::engine::general_purpose::STANDARD.encode(salt),
+                        memory_cost: defaults.m_cost(),
+                        time_cost: defaults.t_cost(),
+                        parallelism: defaults.p_cost(),
+                    },
+                    secrets: BTreeMap::new(),
+                }
+            }
+        };
+
+        let mut nonce = [0u8; 12];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self.cipher(&file.kdf)?
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: value.as_bytes(), aad: key.as_bytes() })
+            .map_err(|e| SkylockError::Encryption(format!("Failed to encrypt {}: {}", key, e)))?;
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+        file.secrets.insert(key.to_string(), base64::engine::general_purpose::STANDARD.encode(data));
 
-        self.cipher
-            .decrypt(nonce, ciphertext)
-            .map_err(|_| SecurityErrorType::DecryptionFailed.into())
+        self.save(&file).await?;
+        Ok(true)
+    }
+}
+
+/// HashiCorp Vault KV version 2 secret holding one field per secret name
+#[cfg(feature = "vault")]
+pub struct VaultCredentialProvider {
+    client: reqwest::Client,
+    /// `<address>/v1/<mount>/data/<path>`
+    url: String,
+    token: Zeroizing<String>,
+}
+
+#[cfg(feature = "vault")]
+impl VaultCredentialProvider {
+    pub fn new(address: &str, mount: &str, path: &str, token: Zeroizing<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: format!(
+                "{}/v1/{}/data/{}",
+                address.trim_end_matches('/'),
+                mount.trim_matches('/'),
+                path.trim_matches('/')
+            ),
+            token,
+        }
+    }
+
+    /// Current fields of the secret (empty if it doesn't exist yet)
+    async fn read(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let response = self.client.get(&self.url)
+            .header("X-Vault-Token", self.token.as_str())
+            .send()
+            .await
+            .map_err(|e| SkylockError::Other(format!("Vault request failed: {}", e)))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Default::default());
+        }
+        if !response.status().is_success() {
+            return Err(SkylockError::Other(format!("Vault returned {} for {}", response.status(), self.url)));
+        }
+        let mut body: serde_json::Value = response.json().await
+            .map_err(|e| SkylockError::Other(format!("Invalid Vault response: {}", e)))?;
+        match body["data"]["data"].take() {
+            serde_json::Value::Object(fields) => Ok(fields),
+            _ => Ok(Default::default()),
+        }
+    }
+}
+
+#[cfg(feature = "vault")]
+#[async_trait]
+impl CredentialProvider for VaultCredentialProvider {
+    fn name(&self) -> &str {
+        "vault"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Zeroizing<String>>> {
+        Ok(self.read().await?.get(key)
+            .and_then(|value| value.as_str())
+            .map(|value| Zeroizing::new(value.to_string())))
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<bool> {
+        // Writing a KV v2 secret replaces all of its fields
+        let mut fields = self.read().await?;
+        fields.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+        let response = self.client.post(&self.url)
+            .header("X-Vault-Token", self.token.as_str())
+            .json(&serde_json::json!({ "data": fields }))
+            .send()
+            .await
+            .map_err(|e| SkylockError::Other(format!("Vault request failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(SkylockError::Other(format!("Vault returned {} for {}", response.status(), self.url)));
+        }
+        Ok(true)
+    }
+}
+
+/// Providers consulted in order
+#[derive(Default)]
+pub struct CredentialChain {
+    providers: Vec<Box<dyn CredentialProvider>>,
+}
+
+impl CredentialChain {
+    pub fn new(providers: Vec<Box<dyn CredentialProvider>>) -> Self {
+        Self { providers }
+    }
+
+    /// Chain of the sources named in the config
+    ///
+    /// `file_passphrase` unlocks the encrypted credentials file, which lives
+    /// in `data_dir` unless the config names another path.
+    pub fn from_config(
+        config: &CredentialsConfig,
+        data_dir: &Path,
+        file_passphrase: Option<Zeroizing<String>>,
+    ) -> Result<Self> {
+        let mut providers: Vec<Box<dyn CredentialProvider>> = Vec::new();
+        for source in &config.sources {
+            match source.as_str() {
+                "env" => providers.push(Box::new(EnvCredentialProvider::default())),
+                "keychain" => providers.push(Box::new(KeychainCredentialProvider::default())),
+                "file" => providers.push(Box::new(EncryptedFileCredentialProvider::new(
+                    config.file.clone().unwrap_or_else(|| data_dir.join(CREDENTIALS_FILE_NAME)),
+                    file_passphrase.clone(),
+                ))),
+                #[cfg(feature = "vault")]
+                "vault" => {
+                    let vault = config.vault.as_ref().ok_or_else(|| SkylockError::Config(
+                        "Credential source \"vault\" needs a [credentials.vault] section".to_string()
+                    ))?;
+                    let address = vault.address.clone()
+                        .or_else(|| std::env::var("VAULT_ADDR").ok())
+                        .ok_or_else(|| SkylockError::Config(
+                            "Vault address not configured (credentials.vault.address or VAULT_ADDR)".to_string()
+                        ))?;
+                    let token = std::env::var("VAULT_TOKEN").map_err(|_| SkylockError::Config(
+                        "VAULT_TOKEN must be set to read credentials from Vault".to_string()
+                    ))?;
+                    providers.push(Box::new(VaultCredentialProvider::new(
+                        &address,
+                        &vault.mount,
+                        &vault.path,
+                        Zeroizing::new(token),
+                    )));
+                }
+                #[cfg(not(feature = "vault"))]
+                "vault" => {
+                    return Err(SkylockError::Config("Built without Vault support".to_string()));
+                }
+                other => {
+                    return Err(SkylockError::Config(format!(
+                        "Unknown credential source \"{}\" (expected env, keychain, file or vault)",
+                        other
+                    )));
+                }
+            }
+        }
+        Ok(Self::new(providers))
+    }
+
+    pub fn providers(&self) -> impl Iterator<Item = &dyn CredentialProvider> {
+        self.providers.iter().map(|provider| provider.as_ref())
+    }
+
+    /// First provider holding `key`, with its value
+    pub async fn lookup(&self, key: &str) -> Result<Option<(&str, Zeroizing<String>)>> {
+        for provider in &self.providers {
+            if let Some(value) = provider.get(key).await? {
+                return Ok(Some((provider.name(), value)));
+            }
+        }
+        Ok(None)
     }
 
-    pub async fn store_credential(&self, key: &str, value: &str) -> Result<()> {
-        let encrypted = self.encrypt(value.as_bytes()).await?;
-        let path = self.credential_path(key);
-        fs::create_dir_all(path.parent().unwrap()).await?;
-        fs::write(path, encrypted).await?;
+    /// Store `key` in the first provider able to store secrets, returning
+    /// that provider's name
+    pub async fn store(&self, key: &str, value: &str) -> Result<Option<&str>> {
+        for provider in &self.providers {
+            if provider.set(key, value).await? {
+                return Ok(Some(provider.name()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Fill the storage credentials and encryption key from the chain
+    ///
+    /// A value found in a provider replaces the config field; fields no
+    /// provider holds keep their config value.
+    pub async fn resolve(&self, hetzner: &mut HetznerConfig) -> Result<()> {
+        let fields = [
+            (HETZNER_USERNAME, &mut hetzner.username),
+            (HETZNER_PASSWORD, &mut hetzner.password),
+            (ENCRYPTION_KEY, &mut hetzner.encryption_key),
+        ];
+        for (key, field) in fields {
+            if let Some((source, value)) = self.lookup(key).await? {
+                tracing::debug!("Using {} from {}", key, source);
+                *field = value.to_string();
+            }
+        }
         Ok(())
     }
+}
+
+#[async_trait]
+impl CredentialProvider for CredentialChain {
+    fn name(&self) -> &str {
+        "chain"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Zeroizing<String>>> {
+        Ok(self.lookup(key).await?.map(|(_, value)| value))
+    }
+
+    /// Stored by the first provider able to store secrets
+    async fn set(&self, key: &str, value: &str) -> Result<bool> {
+        Ok(self.store(key, value).await?.is_some())
+    }
+}
+
+impl Config {
+    /// Load the configuration and resolve its credentials, see
+    /// [`Config::resolve_credentials`]
+    pub async fn load_with_credentials(path: Option<PathBuf>) -> Result<Self> {
+        let mut config = Self::load(path)?;
+        config.resolve_credentials().await?;
+        Ok(config)
+    }
+
+    /// Replace the storage credentials, encryption key and Syncthing API key
+    /// with those held by the configured credential sources
+    ///
+    /// The credentials file is unlocked with `SKYLOCK_CREDENTIALS_PASSPHRASE`.
+    pub async fn resolve_credentials(&mut self) -> Result<()> {
+        let passphrase = std::env::var(CREDENTIALS_PASSPHRASE_ENV).ok().map(Zeroizing::new);
+        let chain = CredentialChain::from_config(&self.credentials, &self.data_dir, passphrase)?;
+        self.resolve_credentials_from(&chain).await
+    }
+
+    /// Replace the secrets held by `chain`, see [`Config::resolve_credentials`]
+    pub async fn resolve_credentials_from(&mut self, chain: &CredentialChain) -> Result<()> {
+        chain.resolve(&mut self.hetzner).await?;
+        if let Some((source, value)) = chain.lookup(SYNCTHING_API_KEY).await? {
+            tracing::debug!("Using {} from {}", SYNCTHING_API_KEY, source);
+            self.syncthing.api_key = value.to_string();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    /// In-memory stand-in for the OS keychain, so tests never touch the
+    /// real one
+    #[derive(Default)]
+    struct MockKeychain {
+        secrets: Mutex<BTreeMap<String, String>>,
+    }
+
+    #[async_trait]
+    impl CredentialProvider for MockKeychain {
+        fn name(&self) -> &str {
+            "keychain"
+        }
+
+        async fn get(&self, key: &str) -> Result<Option<Zeroizing<String>>> {
+            Ok(self.secrets.lock().unwrap().get(key).cloned().map(Zeroizing::new))
+        }
+
+        async fn set(&self, key: &str, value: &str) -> Result<bool> {
+            self.secrets.lock().unwrap().insert(key.to_string(), value.to_string());
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_env_provider_reads_prefixed_variables() {
+        let provider = EnvCredentialProvider::with_prefix("SKYLOCK_TEST_ENV_PROVIDER_");
+        assert_eq!(provider.variable(HETZNER_PASSWORD), "SKYLOCK_TEST_ENV_PROVIDER_HETZNER_PASSWORD");
+
+        assert!(provider.get(HETZNER_PASSWORD).await.unwrap().is_none());
+        std::env::set_var("SKYLOCK_TEST_ENV_PROVIDER_HETZNER_PASSWORD", "s3cret");
+        assert_eq!(provider.get(HETZNER_PASSWORD).await.unwrap().unwrap().as_str(), "s3cret");
+        // Read-only
+        assert!(!provider.set(HETZNER_PASSWORD, "other").await.unwrap());
+        std::env::remove_var("SKYLOCK_TEST_ENV_PROVIDER_HETZNER_PASSWORD");
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_file_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CREDENTIALS_FILE_NAME);
+        let provider = EncryptedFileCredentialProvider::new(path.clone(), Some(Zeroizing::new("passphrase".to_string())));
+
+        assert!(provider.get(HETZNER_PASSWORD).await.unwrap().is_none());
+        assert!(provider.set(HETZNER_PASSWORD, "s3cret").await.unwrap());
+        assert!(provider.set(HETZNER_USERNAME, "u123456").await.unwrap());
+        assert_eq!(provider.get(HETZNER_PASSWORD).await.unwrap().unwrap().as_str(), "s3cret");
+        assert_eq!(provider.get(HETZNER_USERNAME).await.unwrap().unwrap().as_str(), "u123456");
+
+        // Nothing is stored in plaintext
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("s3cret") && !contents.contains("u123456"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let wrong = EncryptedFileCredentialProvider::new(path.clone(), Some(Zeroizing::new("wrong".to_string())));
+        assert!(wrong.get(HETZNER_PASSWORD).await.is_err());
+        let locked = EncryptedFileCredentialProvider::new(path, None);
+        let err = locked.get(HETZNER_PASSWORD).await.unwrap_err();
+        assert!(err.to_string().contains(CREDENTIALS_PASSPHRASE_ENV));
+    }
+
+    #[tokio::test]
+    async fn test_chain_falls_back_in_order() {
+        let dir = TempDir::new().unwrap();
+        let file = EncryptedFileCredentialProvider::new(
+            dir.path().join(CREDENTIALS_FILE_NAME),
+            Some(Zeroizing::new("passphrase".to_string())),
+        );
+        file.set(HETZNER_USERNAME, "file-user").await.unwrap();
+        file.set(HETZNER_PASSWORD, "file-password").await.unwrap();
+        std::env::set_var("SKYLOCK_TEST_CHAIN_HETZNER_PASSWORD", "env-password");
+
+        let keychain = MockKeychain::default();
+        keychain.set(HETZNER_PASSWORD, "keychain-password").await.unwrap();
+        keychain.set(HETZNER_USERNAME, "keychain-user").await.unwrap();
+
+        let chain = CredentialChain::new(vec![
+            Box::new(EnvCredentialProvider::with_prefix("SKYLOCK_TEST_CHAIN_")),
+            Box::new(keychain),
+            Box::new(file),
+        ]);
+        let (source, password) = chain.lookup(HETZNER_PASSWORD).await.unwrap().unwrap();
+        assert_eq!((source, password.as_str()), ("env", "env-password"));
+        let (source, username) = chain.lookup(HETZNER_USERNAME).await.unwrap().unwrap();
+        assert_eq!((source, username.as_str()), ("keychain", "keychain-user"));
+        assert!(chain.lookup(ENCRYPTION_KEY).await.unwrap().is_none());
+
+        let mut hetzner = HetznerConfig {
+            endpoint: String::new(),
+            username: "your-username".to_string(),
+            password: String::new(),
+            encryption_key: "config-key".to_string(),
+            max_list_requests_per_second: None,
+            max_delete_requests_per_second: None,
+            connect_timeout_secs: 30,
+            read_timeout_secs: 120,
+            protocol: None,
+            sftp: None,
+        };
+        chain.resolve(&mut hetzner).await.unwrap();
+        assert_eq!(hetzner.username, "keychain-user");
+        assert_eq!(hetzner.password, "env-password");
+        assert_eq!(hetzner.encryption_key, "config-key");
+        std::env::remove_var("SKYLOCK_TEST_CHAIN_HETZNER_PASSWORD");
+    }
+
+    #[tokio::test]
+    async fn test_config_resolves_from_keychain() {
+        let mut config: Config = toml::from_str(r#"
+            [syncthing]
+            api_key = ""
+            api_url = "http://localhost:8384"
+            folders = []
+
+            [hetzner]
+            endpoint = "u1.your-storagebox.de"
+            username = "u1"
+            password = ""
+
+            [backup]
+            vss_enabled = false
+            schedule = "0 0 2 * * *"
+            retention_days = 30
+            backup_paths = []
+
+            [ui]
+            always_prompt_deletions = true
+            notification_enabled = false
+        "#).unwrap();
+
+        let keychain = MockKeychain::default();
+        keychain.set(HETZNER_PASSWORD, "keychain-password").await.unwrap();
+        keychain.set(SYNCTHING_API_KEY, "keychain-api-key").await.unwrap();
+        let chain = CredentialChain::new(vec![Box::new(keychain)]);
 
-    pub async fn get_credential(&self, key: &str) -> Result<String> {
-        let path = self.credential_path(key);
-        let encrypted = fs::read(path).await?;
-        let decrypted = self.decrypt(&encrypted).await?;
-        String::from_utf8(decrypted)
-            .map_err(|_| SecurityErrorType::InvalidCredential.into())
+        config.resolve_credentials_from(&chain).await.unwrap();
+        assert_eq!(config.hetzner.username, "u1");
+        assert_eq!(config.hetzner.password, "keychain-password");
+        assert_eq!(config.syncthing.api_key, "keychain-api-key");
     }
 
-    fn credential_path(&self, key: &str) -> PathBuf {
-        self.key_file.parent().unwrap()
-            .join("credentials")
-            .join(key)
+    #[test]
+    fn test_unknown_source_rejected() {
+        let config = CredentialsConfig { sources: vec!["env".to_string(), "ldap".to_string()], ..Default::default() };
+        let err = CredentialChain::from_config(&config, Path::new("/tmp"), None).err().unwrap();
+        assert!(err.to_string().contains("ldap"));
     }
 }
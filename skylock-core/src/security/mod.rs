@@ -1,6 +1,7 @@
 mod types;
 pub mod backup;
 pub mod cloud_storage;
+pub mod credentials;
 pub mod error;
 pub mod hsm;
 pub mod key_manager;
@@ -12,6 +13,12 @@ pub mod rate_limiter;
 // Re-export common types
 pub use backup::BackupManager;
 pub use cloud_storage::CloudStorageProvider;
+pub use credentials::{
+    CredentialChain, CredentialProvider, EncryptedFileCredentialProvider, EnvCredentialProvider,
+    KeychainCredentialProvider,
+};
+#[cfg(feature = "vault")]
+pub use credentials::VaultCredentialProvider;
 #[cfg(feature = "aws-storage")]
 pub use cloud_storage::S3StorageProvider;
 pub use error::{SecurityError, SecurityErrorType, ErrorSeverity};
@@ -9,6 +9,7 @@ use crate::{
     error_types::{Error, ErrorCategory, ErrorSeverity, StorageErrorType},
 };
 
+pub mod multi;
 pub mod providers;
 pub mod unified;
 
@@ -17,6 +18,7 @@ pub use providers::{
     HetznerStorageProvider,
 };
 pub use unified::{UnifiedStorage, UnifiedStorageBuilder, helpers};
+pub use multi::{MultiBackend, WriteQuorum, FanOutReport, BackendOutcome, ReplicaCheck};
 
 #[cfg(feature = "aws-storage")]
 pub use providers::AWSStorageProvider;
@@ -119,11 +121,55 @@ pub struct StorageItem {
     pub etag: Option<String>,
 }
 
+/// Storage class for uploaded objects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageTier {
+    /// Regular storage, readable immediately
+    #[default]
+    Standard,
+    /// Cheaper cold storage for rarely read backups (e.g. S3 Glacier);
+    /// objects may have to be retrieved before they can be downloaded
+    Archive,
+}
+
+impl StorageTier {
+    pub fn is_standard(&self) -> bool {
+        *self == StorageTier::Standard
+    }
+}
+
+impl std::fmt::Display for StorageTier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageTier::Standard => write!(f, "standard"),
+            StorageTier::Archive => write!(f, "archive"),
+        }
+    }
+}
+
+impl std::str::FromStr for StorageTier {
+    type Err = SkylockError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "standard" => Ok(StorageTier::Standard),
+            "archive" | "cold" => Ok(StorageTier::Archive),
+            other => Err(SkylockError::Config(format!(
+                "Unknown storage tier '{}' (expected \"standard\" or \"archive\")",
+                other
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct UploadOptions {
     pub chunk_size: Option<usize>,
     pub metadata: Option<std::collections::HashMap<String, String>>,
     pub content_type: Option<String>,
+    /// Storage class hint; backends without storage classes ignore it
+    pub storage_tier: Option<StorageTier>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -0,0 +1,808 @@
+//! Multi-Destination Storage
+//!
+//! Writes every object to several storage backends at once (e.g. a Hetzner
+//! Storage Box and an S3 bucket) for redundancy. Uploads, copies and deletes
+//! are fanned out to all backends and succeed when a configurable quorum of
+//! them does; reads go to the first healthy backend and fail over to the rest.
+//!
+//! Writes that succeed on some backends but not others are logged and kept in
+//! a per-backend report so the caller can tell which destination is missing
+//! which objects. Damaged or missing copies can be rewritten from an intact
+//! one with [`MultiBackend::repair_replicas`].
+
+use crate::storage::{
+    unified::create_provider, DownloadOptions, StorageBackend, StorageConfig, StorageItem,
+    UploadOptions,
+};
+use crate::error_types::StorageErrorType;
+use crate::{Result, SkylockError};
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{debug, warn};
+
+/// How many backends must accept a write for it to count as successful
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum WriteQuorum {
+    /// Every backend
+    #[default]
+    All,
+    /// More than half of the backends
+    Majority,
+    /// At least this many backends (clamped to 1..=backend count)
+    AtLeast(usize),
+}
+
+impl WriteQuorum {
+    /// Number of successful backends required out of `backends`
+    pub fn required(&self, backends: usize) -> usize {
+        match self {
+            WriteQuorum::All => backends,
+            WriteQuorum::Majority => backends / 2 + 1,
+            WriteQuorum::AtLeast(n) => (*n).clamp(1, backends.max(1)),
+        }
+    }
+}
+
+/// Result of one fanned-out write on one backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendOutcome {
+    pub backend: String,
+    /// `None` on success
+    pub error: Option<String>,
+}
+
+/// Per-backend results of a fanned-out write
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FanOutReport {
+    /// "upload", "copy", "delete" or "repair"
+    pub operation: &'static str,
+    pub path: PathBuf,
+    pub outcomes: Vec<BackendOutcome>,
+    /// Successes needed for the write to count
+    pub required: usize,
+}
+
+impl FanOutReport {
+    pub fn succeeded(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.error.is_none()).count()
+    }
+
+    pub fn quorum_met(&self) -> bool {
+        self.succeeded() >= self.required
+    }
+
+    /// Backends where the write did not happen
+    pub fn failed_backends(&self) -> Vec<&str> {
+        self.outcomes
+            .iter()
+            .filter(|o| o.error.is_some())
+            .map(|o| o.backend.as_str())
+            .collect()
+    }
+
+    fn summary(&self) -> String {
+        let failures: Vec<String> = self
+            .outcomes
+            .iter()
+            .filter_map(|o| o.error.as_ref().map(|e| format!("{}: {}", o.backend, e)))
+            .collect();
+        format!(
+            "{} of {} on {}/{} backends (quorum {}); failed: {}",
+            self.operation,
+            self.path.display(),
+            self.succeeded(),
+            self.outcomes.len(),
+            self.required,
+            failures.join("; ")
+        )
+    }
+}
+
+/// State of one object's copies across all backends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaCheck {
+    pub path: PathBuf,
+    /// Backends holding a copy that passed the integrity check
+    pub intact: Vec<String>,
+    /// Backends holding a copy that failed the integrity check
+    pub corrupted: Vec<String>,
+    /// Backends the copy could not be read from, with the error
+    pub missing: Vec<BackendOutcome>,
+}
+
+impl ReplicaCheck {
+    pub fn is_healthy(&self) -> bool {
+        self.corrupted.is_empty() && self.missing.is_empty()
+    }
+
+    /// Backends whose copy is corrupted or missing
+    pub fn damaged_backends(&self) -> Vec<&str> {
+        self.corrupted
+            .iter()
+            .map(String::as_str)
+            .chain(self.missing.iter().map(|o| o.backend.as_str()))
+            .collect()
+    }
+
+    /// At least one intact copy exists to rewrite the damaged ones from
+    pub fn is_repairable(&self) -> bool {
+        !self.intact.is_empty()
+    }
+}
+
+struct Destination {
+    name: String,
+    backend: Arc<dyn StorageBackend + Send + Sync>,
+    healthy: AtomicBool,
+}
+
+/// Storage backend that mirrors writes to several backends
+pub struct MultiBackend {
+    destinations: Vec<Destination>,
+    quorum: WriteQuorum,
+    incomplete: Mutex<Vec<FanOutReport>>,
+}
+
+impl std::fmt::Debug for MultiBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiBackend")
+            .field("backends", &self.backend_names())
+            .field("quorum", &self.quorum)
+            .finish()
+    }
+}
+
+impl MultiBackend {
+    /// Create an empty fan-out backend; add destinations with [`Self::with_backend`]
+    pub fn new(quorum: WriteQuorum) -> Self {
+        Self {
+            destinations: Vec::new(),
+            quorum,
+            incomplete: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Add a destination, identified by `name` in reports and logs
+    pub fn with_backend(
+        mut self,
+        name: impl Into<String>,
+        backend: Arc<dyn StorageBackend + Send + Sync>,
+    ) -> Self {
+        self.destinations.push(Destination {
+            name: name.into(),
+            backend,
+            healthy: AtomicBool::new(true),
+        });
+        self
+    }
+
+    /// Create a destination for each named storage configuration
+    pub async fn from_configs(
+        configs: Vec<(String, StorageConfig)>,
+        quorum: WriteQuorum,
+    ) -> Result<Self> {
+        let mut multi = Self::new(quorum);
+        for (name, config) in configs {
+            let backend = create_provider(&config).await?;
+            multi = multi.with_backend(name, backend);
+        }
+        if multi.destinations.is_empty() {
+            return Err(SkylockError::Storage(StorageErrorType::ConfigError));
+        }
+        Ok(multi)
+    }
+
+    pub fn backend_names(&self) -> Vec<&str> {
+        self.destinations.iter().map(|d| d.name.as_str()).collect()
+    }
+
+    pub fn quorum(&self) -> WriteQuorum {
+        self.quorum
+    }
+
+    /// Writes that did not reach every backend since the last call
+    pub fn take_incomplete(&self) -> Vec<FanOutReport> {
+        std::mem::take(&mut *self.incomplete.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Download every backend's copy of `path` and sort them by `is_intact`
+    pub async fn check_replicas<F>(&self, path: &Path, is_intact: F) -> ReplicaCheck
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        self.read_replicas(path, is_intact).await.0
+    }
+
+    /// Rewrite missing or damaged copies of `path` from an intact one
+    ///
+    /// The report lists one outcome per rewritten backend and is empty when
+    /// every copy was already intact. Fails when no backend holds a copy
+    /// that passes `is_intact`.
+    pub async fn repair_replicas<F>(&self, path: &Path, is_intact: F) -> Result<FanOutReport>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let (check, good_copy) = self.read_replicas(path, is_intact).await;
+        let Some(data) = good_copy else {
+            return Err(SkylockError::Storage(StorageErrorType::ReplicationError(format!(
+                "no intact copy of {} on any backend",
+                path.display()
+            ))));
+        };
+
+        let damaged: Vec<&Destination> = self
+            .destinations
+            .iter()
+            .filter(|d| check.damaged_backends().contains(&d.name.as_str()))
+            .collect();
+        let destination = path.to_path_buf();
+        let results = join_all(damaged.iter().map(|d| {
+            let reader: Pin<Box<dyn AsyncRead + Send>> = Box::pin(std::io::Cursor::new(data.clone()));
+            let backend = d.backend.clone();
+            let destination = destination.clone();
+            async move { backend.upload(reader, &destination, None).await }
+        }))
+        .await;
+
+        let outcomes: Vec<BackendOutcome> = damaged
+            .iter()
+            .zip(results)
+            .map(|(dest, result)| {
+                match &result {
+                    Ok(_) => debug!("Repaired {} on backend '{}'", path.display(), dest.name),
+                    Err(e) => warn!("Repair of {} failed on backend '{}': {}", path.display(), dest.name, e),
+                }
+                BackendOutcome {
+                    backend: dest.name.clone(),
+                    error: result.err().map(|e| e.to_string()),
+                }
+            })
+            .collect();
+
+        Ok(FanOutReport {
+            operation: "repair",
+            path: destination,
+            required: outcomes.len(),
+            outcomes,
+        })
+    }
+
+    /// Check every backend's copy of `path`, keeping one intact copy
+    async fn read_replicas<F>(&self, path: &Path, is_intact: F) -> (ReplicaCheck, Option<Vec<u8>>)
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let source = path.to_path_buf();
+        let results = join_all(self.destinations.iter().map(|d| {
+            let backend = d.backend.clone();
+            let source = source.clone();
+            async move {
+                let buffer = SharedBuffer::default();
+                backend.download(&source, Box::pin(buffer.clone()), None).await?;
+                let data = std::mem::take(&mut *buffer.0.lock().unwrap_or_else(|e| e.into_inner()));
+                Ok::<_, SkylockError>(data)
+            }
+        }))
+        .await;
+
+        let mut check = ReplicaCheck {
+            path: source,
+            intact: Vec::new(),
+            corrupted: Vec::new(),
+            missing: Vec::new(),
+        };
+        let mut good_copy = None;
+        for (dest, result) in self.destinations.iter().zip(results) {
+            match result {
+                Ok(data) if is_intact(&data) => {
+                    check.intact.push(dest.name.clone());
+                    good_copy.get_or_insert(data);
+                }
+                Ok(_) => check.corrupted.push(dest.name.clone()),
+                Err(e) => check.missing.push(BackendOutcome {
+                    backend: dest.name.clone(),
+                    error: Some(e.to_string()),
+                }),
+            }
+        }
+        (check, good_copy)
+    }
+
+    /// Run `operation` on every backend concurrently and collect the results
+    async fn fan_out<T, F, Fut>(
+        &self,
+        operation: &'static str,
+        path: &Path,
+        op: F,
+    ) -> (FanOutReport, Vec<Option<T>>)
+    where
+        F: Fn(Arc<dyn StorageBackend + Send + Sync>) -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let results = join_all(self.destinations.iter().map(|d| op(d.backend.clone()))).await;
+
+        let mut outcomes = Vec::with_capacity(results.len());
+        let mut values = Vec::with_capacity(results.len());
+        for (dest, result) in self.destinations.iter().zip(results) {
+            match result {
+                Ok(value) => {
+                    outcomes.push(BackendOutcome { backend: dest.name.clone(), error: None });
+                    values.push(Some(value));
+                }
+                Err(e) => {
+                    outcomes.push(BackendOutcome { backend: dest.name.clone(), error: Some(e.to_string()) });
+                    values.push(None);
+                }
+            }
+        }
+
+        let report = FanOutReport {
+            operation,
+            path: path.to_path_buf(),
+            outcomes,
+            required: self.quorum.required(self.destinations.len()),
+        };
+        (report, values)
+    }
+
+    /// Record partial failures and turn a missed quorum into an error
+    fn settle(&self, report: FanOutReport) -> Result<()> {
+        if report.failed_backends().is_empty() {
+            return Ok(());
+        }
+
+        for outcome in report.outcomes.iter().filter(|o| o.error.is_some()) {
+            warn!(
+                "{} of {} failed on backend '{}': {}",
+                report.operation,
+                report.path.display(),
+                outcome.backend,
+                outcome.error.as_deref().unwrap_or_default()
+            );
+        }
+
+        let quorum_met = report.quorum_met();
+        let summary = report.summary();
+        self.incomplete
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(report);
+
+        if quorum_met {
+            Ok(())
+        } else {
+            Err(SkylockError::Storage(StorageErrorType::ReplicationError(summary)))
+        }
+    }
+
+    /// Run a read on the first healthy backend, failing over in order
+    ///
+    /// Backends that failed a previous read are tried after the healthy ones.
+    async fn read_with_failover<T, F, Fut>(&self, op: F) -> Result<T>
+    where
+        F: Fn(Arc<dyn StorageBackend + Send + Sync>) -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let (healthy, unhealthy): (Vec<&Destination>, Vec<&Destination>) = self
+            .destinations
+            .iter()
+            .partition(|d| d.healthy.load(Ordering::Relaxed));
+
+        let mut errors = Vec::new();
+        for dest in healthy.into_iter().chain(unhealthy) {
+            match op(dest.backend.clone()).await {
+                Ok(value) => {
+                    dest.healthy.store(true, Ordering::Relaxed);
+                    return Ok(value);
+                }
+                Err(e) => {
+                    debug!("Read from backend '{}' failed, trying next: {}", dest.name, e);
+                    dest.healthy.store(false, Ordering::Relaxed);
+                    errors.push(format!("{}: {}", dest.name, e));
+                }
+            }
+        }
+
+        Err(SkylockError::Storage(StorageErrorType::ConnectionFailed(format!(
+            "All backends failed: {}",
+            errors.join("; ")
+        ))))
+    }
+}
+
+/// `AsyncWrite` into a shared buffer, so a failed read can be discarded
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl AsyncWrite for SharedBuffer {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+        std::task::Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MultiBackend {
+    async fn upload(
+        &self,
+        mut source: Pin<Box<dyn AsyncRead + Send>>,
+        destination: &PathBuf,
+        options: Option<UploadOptions>,
+    ) -> Result<StorageItem> {
+        // Every backend needs its own reader, so buffer the source once
+        let mut data = Vec::new();
+        source.read_to_end(&mut data).await?;
+        let data = Arc::new(data);
+
+        let (report, items) = self
+            .fan_out("upload", destination, |backend| {
+                let data = data.clone();
+                let options = options.clone();
+                async move {
+                    let reader = Box::pin(std::io::Cursor::new(data.as_ref().clone()));
+                    backend.upload(reader, destination, options).await
+                }
+            })
+            .await;
+
+        self.settle(report)?;
+        items
+            .into_iter()
+            .flatten()
+            .next()
+            .ok_or(SkylockError::Storage(StorageErrorType::WriteError))
+    }
+
+    async fn download(
+        &self,
+        source: &PathBuf,
+        mut destination: Pin<Box<dyn AsyncWrite + Send>>,
+        options: Option<DownloadOptions>,
+    ) -> Result<()> {
+        let data = self
+            .read_with_failover(|backend| {
+                let options = options.clone();
+                async move {
+                    let buffer = SharedBuffer::default();
+                    backend.download(source, Box::pin(buffer.clone()), options).await?;
+                    let data = std::mem::take(&mut *buffer.0.lock().unwrap_or_else(|e| e.into_inner()));
+                    Ok(data)
+                }
+            })
+            .await?;
+
+        destination.write_all(&data).await?;
+        destination.flush().await?;
+        Ok(())
+    }
+
+    async fn delete(&self, path: &PathBuf) -> Result<()> {
+        let (report, _) = self
+            .fan_out("delete", path, |backend| async move { backend.delete(path).await })
+            .await;
+        self.settle(report)
+    }
+
+    async fn list(&self, prefix: Option<&PathBuf>, recursive: bool) -> Result<Vec<StorageItem>> {
+        self.read_with_failover(|backend| async move { backend.list(prefix, recursive).await })
+            .await
+    }
+
+    async fn get_metadata(&self, path: &PathBuf) -> Result<Option<StorageItem>> {
+        self.read_with_failover(|backend| async move { backend.get_metadata(path).await })
+            .await
+    }
+
+    async fn copy(&self, source: &PathBuf, destination: &PathBuf) -> Result<StorageItem> {
+        let (report, items) = self
+            .fan_out("copy", destination, |backend| async move {
+                backend.copy(source, destination).await
+            })
+            .await;
+
+        self.settle(report)?;
+        items
+            .into_iter()
+            .flatten()
+            .next()
+            .ok_or(SkylockError::Storage(StorageErrorType::WriteError))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{LocalStorageProvider, StorageProviderType, StorageTier};
+    use tempfile::TempDir;
+
+    /// Backend whose writes and reads always fail
+    #[derive(Debug)]
+    struct FailingBackend;
+
+    fn unavailable<T>() -> Result<T> {
+        Err(SkylockError::Storage(StorageErrorType::StorageBoxUnavailable))
+    }
+
+    #[async_trait]
+    impl StorageBackend for FailingBackend {
+        async fn upload(
+            &self,
+            _source: Pin<Box<dyn AsyncRead + Send>>,
+            _destination: &PathBuf,
+            _options: Option<UploadOptions>,
+        ) -> Result<StorageItem> {
+            unavailable()
+        }
+
+        async fn download(
+            &self,
+            _source: &PathBuf,
+            _destination: Pin<Box<dyn AsyncWrite + Send>>,
+            _options: Option<DownloadOptions>,
+        ) -> Result<()> {
+            unavailable()
+        }
+
+        async fn delete(&self, _path: &PathBuf) -> Result<()> {
+            unavailable()
+        }
+
+        async fn list(&self, _prefix: Option<&PathBuf>, _recursive: bool) -> Result<Vec<StorageItem>> {
+            unavailable()
+        }
+
+        async fn get_metadata(&self, _path: &PathBuf) -> Result<Option<StorageItem>> {
+            unavailable()
+        }
+
+        async fn copy(&self, _source: &PathBuf, _destination: &PathBuf) -> Result<StorageItem> {
+            unavailable()
+        }
+    }
+
+    fn local(dir: &TempDir) -> Arc<dyn StorageBackend + Send + Sync> {
+        let config = StorageConfig {
+            provider: StorageProviderType::Local,
+            connection_string: Some(dir.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        Arc::new(LocalStorageProvider::new(&config).unwrap())
+    }
+
+    async fn upload(multi: &MultiBackend, path: &str, data: &[u8]) -> Result<StorageItem> {
+        let reader: Pin<Box<dyn AsyncRead + Send>> = Box::pin(std::io::Cursor::new(data.to_vec()));
+        multi.upload(reader, &PathBuf::from(path), None).await
+    }
+
+    async fn download(multi: &MultiBackend, path: &str) -> Result<Vec<u8>> {
+        let buffer = SharedBuffer::default();
+        multi.download(&PathBuf::from(path), Box::pin(buffer.clone()), None).await?;
+        let data = buffer.0.lock().unwrap().clone();
+        Ok(data)
+    }
+
+    #[test]
+    fn test_quorum_required() {
+        assert_eq!(WriteQuorum::All.required(3), 3);
+        assert_eq!(WriteQuorum::Majority.required(2), 2);
+        assert_eq!(WriteQuorum::Majority.required(3), 2);
+        assert_eq!(WriteQuorum::AtLeast(1).required(3), 1);
+        assert_eq!(WriteQuorum::AtLeast(0).required(3), 1);
+        assert_eq!(WriteQuorum::AtLeast(5).required(3), 3);
+    }
+
+    #[tokio::test]
+    async fn test_upload_reaches_every_backend() {
+        let (a, b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let multi = MultiBackend::new(WriteQuorum::All)
+            .with_backend("hetzner", local(&a))
+            .with_backend("s3", local(&b));
+
+        upload(&multi, "backups/one.enc", b"payload").await.unwrap();
+        assert_eq!(std::fs::read(a.path().join("backups/one.enc")).unwrap(), b"payload");
+        assert_eq!(std::fs::read(b.path().join("backups/one.enc")).unwrap(), b"payload");
+        assert!(multi.take_incomplete().is_empty());
+
+        multi.delete(&PathBuf::from("backups/one.enc")).await.unwrap();
+        assert!(!a.path().join("backups/one.enc").exists());
+        assert!(!b.path().join("backups/one.enc").exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_backend_misses_quorum_all() {
+        let dir = TempDir::new().unwrap();
+        let multi = MultiBackend::new(WriteQuorum::All)
+            .with_backend("hetzner", local(&dir))
+            .with_backend("s3", Arc::new(FailingBackend));
+
+        let err = upload(&multi, "backups/one.enc", b"payload").await.unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("1/2 backends"), "{}", message);
+        assert!(message.contains("s3"), "{}", message);
+
+        // The healthy destination still got the object; the report says which one is missing it
+        assert!(dir.path().join("backups/one.enc").exists());
+        let incomplete = multi.take_incomplete();
+        assert_eq!(incomplete.len(), 1);
+        assert_eq!(incomplete[0].failed_backends(), vec!["s3"]);
+        assert_eq!(incomplete[0].path, PathBuf::from("backups/one.enc"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_backend_within_quorum_succeeds_with_report() {
+        let (a, b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let multi = MultiBackend::new(WriteQuorum::Majority)
+            .with_backend("hetzner", local(&a))
+            .with_backend("b2", Arc::new(FailingBackend))
+            .with_backend("s3", local(&b));
+
+        let item = upload(&multi, "backups/one.enc", b"payload").await.unwrap();
+        assert_eq!(item.size, 7);
+
+        let incomplete = multi.take_incomplete();
+        assert_eq!(incomplete.len(), 1);
+        assert_eq!(incomplete[0].operation, "upload");
+        assert_eq!(incomplete[0].failed_backends(), vec!["b2"]);
+        assert_eq!(incomplete[0].succeeded(), 2);
+        assert!(multi.take_incomplete().is_empty());
+
+        // Two failures out of three miss a majority
+        let only_one = MultiBackend::new(WriteQuorum::Majority)
+            .with_backend("hetzner", local(&a))
+            .with_backend("b2", Arc::new(FailingBackend))
+            .with_backend("s3", Arc::new(FailingBackend));
+        assert!(upload(&only_one, "backups/two.enc", b"payload").await.is_err());
+
+        // ...but satisfy an explicit quorum of one
+        let at_least_one = MultiBackend::new(WriteQuorum::AtLeast(1))
+            .with_backend("hetzner", local(&a))
+            .with_backend("b2", Arc::new(FailingBackend))
+            .with_backend("s3", Arc::new(FailingBackend));
+        upload(&at_least_one, "backups/three.enc", b"payload").await.unwrap();
+        assert_eq!(at_least_one.take_incomplete()[0].failed_backends(), vec!["b2", "s3"]);
+    }
+
+    #[tokio::test]
+    async fn test_reads_fail_over_to_healthy_backend() {
+        let dir = TempDir::new().unwrap();
+        let multi = MultiBackend::new(WriteQuorum::AtLeast(1))
+            .with_backend("primary", Arc::new(FailingBackend))
+            .with_backend("mirror", local(&dir));
+
+        upload(&multi, "backups/one.enc", b"payload").await.unwrap();
+        assert_eq!(download(&multi, "backups/one.enc").await.unwrap(), b"payload");
+
+        // The failing backend is now tried last
+        assert!(!multi.destinations[0].healthy.load(Ordering::Relaxed));
+        let listed = multi.list(Some(&PathBuf::from("backups")), true).await.unwrap();
+        assert_eq!(listed.len(), 1);
+
+        let all_down = MultiBackend::new(WriteQuorum::All)
+            .with_backend("a", Arc::new(FailingBackend))
+            .with_backend("b", Arc::new(FailingBackend));
+        let err = download(&all_down, "backups/one.enc").await.unwrap_err();
+        assert!(err.to_string().contains("All backends failed"));
+    }
+
+    #[tokio::test]
+    async fn test_repair_rewrites_damaged_replicas() {
+        let (primary, mirror, spare) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        let multi = MultiBackend::new(WriteQuorum::All)
+            .with_backend("primary", local(&primary))
+            .with_backend("mirror", local(&mirror))
+            .with_backend("spare", local(&spare));
+        upload(&multi, "backups/one.enc", b"payload").await.unwrap();
+
+        // Corrupt one copy and lose another
+        std::fs::write(primary.path().join("backups/one.enc"), b"garbage").unwrap();
+        std::fs::remove_file(spare.path().join("backups/one.enc")).unwrap();
+
+        let is_intact = |data: &[u8]| data == b"payload";
+        let path = PathBuf::from("backups/one.enc");
+        let check = multi.check_replicas(&path, is_intact).await;
+        assert!(!check.is_healthy());
+        assert!(check.is_repairable());
+        assert_eq!(check.intact, vec!["mirror"]);
+        assert_eq!(check.corrupted, vec!["primary"]);
+        assert_eq!(check.damaged_backends(), vec!["primary", "spare"]);
+
+        let report = multi.repair_replicas(&path, is_intact).await.unwrap();
+        assert_eq!(report.operation, "repair");
+        assert!(report.quorum_met());
+        assert_eq!(report.outcomes.len(), 2);
+        assert!(multi.check_replicas(&path, is_intact).await.is_healthy());
+        assert_eq!(std::fs::read(primary.path().join("backups/one.enc")).unwrap(), b"payload");
+
+        // Nothing to repair from when every copy is bad
+        let check = multi.check_replicas(&path, |_| false).await;
+        assert!(!check.is_repairable());
+        assert!(multi.repair_replicas(&path, |_| false).await.is_err());
+    }
+
+    /// Backend recording the upload options it receives
+    #[derive(Debug, Default)]
+    struct RecordingBackend {
+        tiers: Mutex<Vec<Option<StorageTier>>>,
+    }
+
+    #[async_trait]
+    impl StorageBackend for RecordingBackend {
+        async fn upload(
+            &self,
+            _source: Pin<Box<dyn AsyncRead + Send>>,
+            destination: &PathBuf,
+            options: Option<UploadOptions>,
+        ) -> Result<StorageItem> {
+            self.tiers.lock().unwrap().push(options.and_then(|o| o.storage_tier));
+            Ok(StorageItem { path: destination.clone(), size: 0, last_modified: None, metadata: None, etag: None })
+        }
+
+        async fn download(
+            &self,
+            _source: &PathBuf,
+            _destination: Pin<Box<dyn AsyncWrite + Send>>,
+            _options: Option<DownloadOptions>,
+        ) -> Result<()> {
+            unavailable()
+        }
+
+        async fn delete(&self, _path: &PathBuf) -> Result<()> {
+            Ok(())
+        }
+
+        async fn list(&self, _prefix: Option<&PathBuf>, _recursive: bool) -> Result<Vec<StorageItem>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_metadata(&self, _path: &PathBuf) -> Result<Option<StorageItem>> {
+            Ok(None)
+        }
+
+        async fn copy(&self, _source: &PathBuf, _destination: &PathBuf) -> Result<StorageItem> {
+            unavailable()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_storage_tier_passed_to_every_backend() {
+        let (a, b) = (Arc::new(RecordingBackend::default()), Arc::new(RecordingBackend::default()));
+        let multi = MultiBackend::new(WriteQuorum::All)
+            .with_backend("hetzner", a.clone())
+            .with_backend("s3", b.clone());
+
+        let options = UploadOptions { storage_tier: Some(StorageTier::Archive), ..Default::default() };
+        let reader: Pin<Box<dyn AsyncRead + Send>> = Box::pin(std::io::Cursor::new(b"yearly".to_vec()));
+        multi.upload(reader, &PathBuf::from("backups/yearly.enc"), Some(options)).await.unwrap();
+        upload(&multi, "backups/daily.enc", b"daily").await.unwrap();
+
+        for backend in [&a, &b] {
+            assert_eq!(*backend.tiers.lock().unwrap(), vec![Some(StorageTier::Archive), None]);
+        }
+        assert_eq!("archive".parse::<StorageTier>().unwrap(), StorageTier::Archive);
+        assert!("glacier-ish".parse::<StorageTier>().is_err());
+    }
+}
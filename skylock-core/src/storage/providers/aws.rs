@@ -8,7 +8,7 @@
 //! - Streaming downloads with range support
 //! - S3-compatible services (MinIO, Wasabi, DigitalOcean Spaces, etc.)
 
-use crate::storage::{StorageConfig, StorageItem, UploadOptions, DownloadOptions, StorageBackend};
+use crate::storage::{StorageConfig, StorageItem, StorageTier, UploadOptions, DownloadOptions, StorageBackend};
 use crate::{Result, SkylockError};
 use async_trait::async_trait;
 use std::path::PathBuf;
@@ -16,7 +16,8 @@ use std::pin::Pin;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use aws_sdk_s3::Client;
 use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, ServerSideEncryption};
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, ServerSideEncryption, StorageClass};
 use chrono::{DateTime, Utc};
 use tracing::{info, debug, warn, error};
 
@@ -127,7 +128,15 @@ impl AWSStorageProvider {
         }
     }
 
-    async fn upload_single(&self, data: Vec<u8>, key: &str, content_type: Option<String>) -> Result<StorageItem> {
+    /// S3 storage class for a tier (`None` keeps the bucket default)
+    fn storage_class(tier: Option<StorageTier>) -> Option<StorageClass> {
+        match tier {
+            Some(StorageTier::Archive) => Some(StorageClass::Glacier),
+            Some(StorageTier::Standard) | None => None,
+        }
+    }
+
+    async fn upload_single(&self, data: Vec<u8>, key: &str, content_type: Option<String>, tier: Option<StorageTier>) -> Result<StorageItem> {
         let size = data.len() as u64;
         let mut request = self.client
             .put_object()
@@ -143,6 +152,10 @@ impl AWSStorageProvider {
             request = request.server_side_encryption(sse);
         }
 
+        if let Some(class) = Self::storage_class(tier) {
+            request = request.storage_class(class);
+        }
+
         let response = request.send().await
             .map_err(|e| {
                 error!("S3 upload failed: {}", e);
@@ -158,7 +171,7 @@ impl AWSStorageProvider {
         })
     }
 
-    async fn upload_multipart(&self, data: Vec<u8>, key: &str, content_type: Option<String>) -> Result<StorageItem> {
+    async fn upload_multipart(&self, data: Vec<u8>, key: &str, content_type: Option<String>, tier: Option<StorageTier>) -> Result<StorageItem> {
         // Start multipart upload
         let mut create_req = self.client
             .create_multipart_upload()
@@ -173,6 +186,10 @@ impl AWSStorageProvider {
             create_req = create_req.server_side_encryption(sse);
         }
 
+        if let Some(class) = Self::storage_class(tier) {
+            create_req = create_req.storage_class(class);
+        }
+
         let create_resp = create_req.send().await
             .map_err(|e| {
                 error!("Failed to start multipart upload: {}", e);
@@ -273,6 +290,7 @@ impl StorageBackend for AWSStorageProvider {
     ) -> Result<StorageItem> {
         let key = self.path_to_key(destination);
         let content_type = options.as_ref().and_then(|o| o.content_type.clone());
+        let tier = options.as_ref().and_then(|o| o.storage_tier);
 
         // Read all data into memory
         let mut data = Vec::new();
@@ -280,10 +298,10 @@ impl StorageBackend for AWSStorageProvider {
 
         if data.len() as u64 <= self.multipart_threshold {
             debug!("Using single upload for key={}, size={}", key, data.len());
-            self.upload_single(data, &key, content_type).await
+            self.upload_single(data, &key, content_type, tier).await
         } else {
             debug!("Using multipart upload for key={}, size={}", key, data.len());
-            self.upload_multipart(data, &key, content_type).await
+            self.upload_multipart(data, &key, content_type, tier).await
         }
     }
 
@@ -308,6 +326,9 @@ impl StorageBackend for AWSStorageProvider {
 
         let response = request.send().await
             .map_err(|e| {
+                if matches!(e.as_service_error(), Some(GetObjectError::InvalidObjectState(_))) {
+                    return SkylockError::Storage(crate::error_types::StorageErrorType::ColdStorage(key.clone()));
+                }
                 error!("S3 download failed for key={}: {}", key, e);
                 SkylockError::Storage(crate::error_types::StorageErrorType::ReadError)
             })?;
@@ -93,7 +93,7 @@ impl UnifiedStorageBuilder {
 }
 
 /// Create a storage provider from configuration
-async fn create_provider(config: &StorageConfig) -> Result<Arc<dyn StorageBackend + Send + Sync>> {
+pub(crate) async fn create_provider(config: &StorageConfig) -> Result<Arc<dyn StorageBackend + Send + Sync>> {
     match config.provider {
         StorageProviderType::Local => {
             let provider = LocalStorageProvider::new(config)?;
@@ -0,0 +1,86 @@
+//! Content hashing of an upload as it is sent
+//!
+//! [`HashingReader`] passes the bytes of its source through unchanged while
+//! feeding them to SHA-256, so the hash of a file comes out of the same read
+//! that uploads it instead of a second pass over the whole file.
+
+use base64::engine::general_purpose::STANDARD as base64_standard;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+#[derive(Default)]
+struct HashState {
+    hasher: Sha256,
+    bytes: u64,
+}
+
+/// Handle on the hash of the bytes read through a [`HashingReader`], kept
+/// by the caller once the reader itself has been handed to the transport
+#[derive(Clone, Default)]
+pub struct StreamDigest {
+    state: Arc<Mutex<HashState>>,
+}
+
+impl StreamDigest {
+    /// Bytes hashed so far
+    pub fn bytes(&self) -> u64 {
+        self.state.lock().unwrap().bytes
+    }
+
+    /// Base64 SHA-256 of everything read so far
+    pub fn finish(&self) -> String {
+        let hasher = self.state.lock().unwrap().hasher.clone();
+        base64_standard.encode(hasher.finalize())
+    }
+}
+
+/// [`AsyncRead`] adapter hashing everything read from `inner`
+pub struct HashingReader<R> {
+    inner: R,
+    digest: StreamDigest,
+}
+
+impl<R> HashingReader<R> {
+    /// Wrap `inner`, returning the reader and the handle its hash is read from
+    pub fn new(inner: R) -> (Self, StreamDigest) {
+        let digest = StreamDigest::default();
+        (Self { inner, digest: digest.clone() }, digest)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            let read = &buf.filled()[before..];
+            let mut state = self.digest.state.lock().unwrap();
+            state.hasher.update(read);
+            state.bytes += read.len() as u64;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_hash_matches_whole_buffer() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let (mut reader, digest) = HashingReader::new(data.as_slice());
+
+        // Read in uneven pieces, like a transport would
+        let mut chunk = vec![0u8; 7919];
+        while reader.read(&mut chunk).await.unwrap() > 0 {}
+
+        assert_eq!(digest.bytes(), data.len() as u64);
+        assert_eq!(digest.finish(), base64_standard.encode(Sha256::digest(&data)));
+    }
+}
@@ -1,23 +1,35 @@
 mod sftp;
 mod sftp_secure;
+mod sftp_backend;
 mod api;
 mod webdav;
 mod tls_pinning;
+mod rate_limit;
+mod hashing;
 pub mod metadata_encryption;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_server;
 
 use std::path::{Path, PathBuf};
-use tokio::io::AsyncReadExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWrite};
 use sha2::{Sha256, Digest};
 use skylock_core::{Result, StorageErrorType, SkylockError};
 use tracing::{info, debug};
 use base64::engine::general_purpose::STANDARD as base64_standard;
 use base64::Engine;
 use indicatif::ProgressBar;
+use skylock_core::storage::StorageBackend;
 
 pub use api::{StorageBox, CreateStorageBoxRequest, StorageBoxCredentials};
 pub use sftp::SftpClient;
-pub use sftp_secure::{SecureSftpClient, SecureSftpConfig, generate_ed25519_keypair};
-pub use webdav::{HetznerWebDAVClient, WebDAVConfig};
+pub use sftp_secure::{SecureSftpClient, SecureSftpConfig, SftpEntry, generate_ed25519_keypair};
+pub use sftp_backend::SftpBackend;
+pub use webdav::{HetznerWebDAVClient, RemoteObjectInfo, StatusError, TimeoutError, WebDAVConfig};
+pub use webdav::{DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT};
+pub use rate_limit::RequestRateLimiter;
+pub use hashing::{HashingReader, StreamDigest};
 pub use tls_pinning::{
     TlsPinningConfig, CertificatePin, CertificatePinner, PinValidationResult,
     compute_spki_hash, verify_spki_hash
@@ -27,12 +39,28 @@ pub use metadata_encryption::{PathEncryptor, PathMapping, MetadataEncryptionErro
 #[allow(dead_code)]
 const USER_AGENT: &str = "Skylock-Hybrid/1.0";
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum StorageProtocol {
+    #[default]
     WebDav,
     Sftp,
 }
 
+impl std::str::FromStr for StorageProtocol {
+    type Err = SkylockError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "webdav" => Ok(StorageProtocol::WebDav),
+            "sftp" => Ok(StorageProtocol::Sftp),
+            other => Err(SkylockError::Config(format!(
+                "Unknown storage protocol '{}' (expected \"webdav\" or \"sftp\")",
+                other
+            ))),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub api_token: String,
@@ -61,6 +89,8 @@ pub struct HetznerConfig {
 #[allow(dead_code)]
 pub struct HetznerClient {
     webdav: HetznerWebDAVClient,
+    /// Transfers and listings go over SFTP instead of WebDAV when set
+    sftp: Option<Arc<SftpBackend>>,
 }
 
 impl HetznerClient {
@@ -85,9 +115,40 @@ impl HetznerClient {
         debug!("HetznerClient created successfully");
         Ok(Self {
             webdav,
+            sftp: None,
         })
     }
 
+    /// Send transfers, listings and deletions over SFTP instead of WebDAV
+    ///
+    /// Storage Boxes serve the same files over both protocols, so clients
+    /// using either can be mixed. Byte ranges aren't supported over SFTP;
+    /// [`HetznerClient::download_range`] returns `None` there.
+    pub fn with_sftp(mut self, sftp: SftpBackend) -> Self {
+        self.sftp = Some(Arc::new(sftp));
+        self
+    }
+
+    /// Limit listing requests to `requests_per_second` (`None` = unlimited)
+    pub fn with_list_rate_limit(mut self, requests_per_second: Option<f64>) -> Self {
+        self.webdav = self.webdav.with_list_rate_limit(requests_per_second);
+        self
+    }
+
+    /// Limit WebDAV deletions to `requests_per_second` (`None` = unlimited)
+    pub fn with_delete_rate_limit(mut self, requests_per_second: Option<f64>) -> Self {
+        self.webdav = self.webdav.with_delete_rate_limit(requests_per_second);
+        self
+    }
+
+    /// Give up connecting after `connect`, and fail any upload, download or
+    /// listing that sends and receives nothing for `read` with
+    /// [`StorageErrorType::NetworkTimeout`]
+    pub fn with_timeouts(mut self, connect: Duration, read: Duration) -> Self {
+        self.webdav = self.webdav.with_timeouts(connect, read);
+        self
+    }
+
 
     pub async fn upload_file(&self, local_path: &Path, remote_path: &Path) -> Result<FileMetadata> {
         self.upload_file_with_progress(local_path, remote_path, None).await
@@ -98,20 +159,29 @@ impl HetznerClient {
         let metadata = file.metadata().await?;
         let file_size = metadata.len();
 
-        // Calculate file hash
-        let mut hasher = Sha256::new();
-        let mut buffer = Vec::new();
-        tokio::fs::File::open(local_path).await?.read_to_end(&mut buffer).await?;
-        hasher.update(&buffer);
-        let hash = base64_standard.encode(hasher.finalize());
+        // The hash is taken from the same read that sends the file
+        let (reader, digest) = HashingReader::new(file);
 
         let remote_path_str = remote_path.to_string_lossy().into_owned();
         debug!("Uploading file to {}", remote_path_str);
 
-        // Use WebDAV client for upload with progress
-        self.webdav.upload_file_with_progress(local_path, &remote_path_str, progress)
-            .await
-            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
+        if let Some(sftp) = &self.sftp {
+            sftp.upload(Box::pin(reader), &remote_path.to_path_buf(), None).await?;
+            if let Some(progress) = progress {
+                progress.inc(file_size);
+            }
+        } else {
+            // Use WebDAV client for upload with progress
+            self.webdav.upload_reader_with_progress(reader, file_size, &remote_path_str, progress)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        // A file that changed size while being read was not sent as measured
+        if digest.bytes() != file_size {
+            return Err(SkylockError::Storage(StorageErrorType::WriteError));
+        }
+        let hash = digest.finish();
 
         Ok(FileMetadata {
             path: remote_path.to_path_buf(),
@@ -125,20 +195,30 @@ impl HetznerClient {
         let remote_path_str = remote_path.to_string_lossy().into_owned();
         info!("Downloading file from {}", remote_path_str);
 
-        // Use WebDAV client for download
-        self.webdav.download_file(&remote_path_str, local_path)
-            .await
-            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
+        if let Some(sftp) = &self.sftp {
+            let mut file = tokio::fs::File::create(local_path).await?;
+            sftp.download_to(remote_path, &mut file).await?;
+        } else {
+            // Use WebDAV client for download
+            self.webdav.download_file(&remote_path_str, local_path)
+                .await
+                .map_err(storage_error)?;
+        }
 
-        // Get file size and calculate hash
-        let file = tokio::fs::File::open(local_path).await?;
-        let metadata = file.metadata().await?;
-        let file_size = metadata.len();
+        // Get file size and calculate hash, reading in blocks so large
+        // downloads aren't loaded into memory
+        let mut file = tokio::fs::File::open(local_path).await?;
+        let file_size = file.metadata().await?.len();
 
         let mut hasher = Sha256::new();
-        let mut buffer = Vec::new();
-        tokio::fs::File::open(local_path).await?.read_to_end(&mut buffer).await?;
-        hasher.update(&buffer);
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
         let hash = base64_standard.encode(hasher.finalize());
 
         Ok(FileMetadata {
@@ -149,20 +229,103 @@ impl HetznerClient {
         })
     }
 
+    /// Upload a body that is already in memory, without a local file
+    pub async fn upload_bytes(&self, data: Vec<u8>, remote_path: &Path) -> Result<()> {
+        let remote_path_str = remote_path.to_string_lossy().into_owned();
+        debug!("Uploading {} bytes to {}", data.len(), remote_path_str);
+        if let Some(sftp) = &self.sftp {
+            sftp.upload(Box::pin(std::io::Cursor::new(data)), &remote_path.to_path_buf(), None).await?;
+            return Ok(());
+        }
+        self.webdav.upload_bytes(data, &remote_path_str)
+            .await
+            .map_err(storage_error)
+    }
+
+    /// Download into `writer` as the data arrives, returning its size
+    pub async fn download_to(&self, remote_path: &Path, writer: &mut (dyn AsyncWrite + Send + Unpin)) -> Result<u64> {
+        if let Some(sftp) = &self.sftp {
+            return sftp.download_to(remote_path, writer).await;
+        }
+        let remote_path_str = remote_path.to_string_lossy().into_owned();
+        self.webdav.download_to(&remote_path_str, writer)
+            .await
+            .map_err(storage_error)
+    }
+
+    /// Upload a body of unknown length as it is produced, e.g. an archive
+    /// being written, without buffering it in memory or on disk
+    /// 
+    /// Unlike [`HetznerClient::upload_file`] this can't be retried, since the
+    /// body is consumed as it is sent.
+    pub async fn upload_stream<S>(&self, stream: S, remote_path: &Path) -> Result<()>
+    where
+        S: futures_util::Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static,
+    {
+        let remote_path_str = remote_path.to_string_lossy().into_owned();
+        debug!("Streaming upload to {}", remote_path_str);
+        if let Some(sftp) = &self.sftp {
+            return sftp.upload_stream(stream, remote_path).await.map(|_| ());
+        }
+        self.webdav.upload_stream(stream, &remote_path_str)
+            .await
+            .map_err(storage_error)
+    }
+
+    pub async fn object_info(&self, remote_path: &Path) -> Result<RemoteObjectInfo> {
+        if let Some(sftp) = &self.sftp {
+            let size = sftp.file_size(remote_path).await?
+                .ok_or(SkylockError::Storage(StorageErrorType::FileNotFound))?;
+            return Ok(RemoteObjectInfo { size, accepts_ranges: false });
+        }
+        let remote_path_str = remote_path.to_string_lossy().into_owned();
+        self.webdav.object_info(&remote_path_str)
+            .await
+            .map_err(storage_error)
+    }
+
+    /// Download an inclusive byte range; `None` if ranges aren't honoured
+    pub async fn download_range(&self, remote_path: &Path, start: u64, end: u64) -> Result<Option<bytes::Bytes>> {
+        if self.sftp.is_some() {
+            return Ok(None);
+        }
+        let remote_path_str = remote_path.to_string_lossy().into_owned();
+        self.webdav.download_range(&remote_path_str, start, end)
+            .await
+            .map_err(storage_error)
+    }
+
     pub async fn delete_file(&self, remote_path: &Path) -> Result<()> {
         let remote_path_str = remote_path.to_string_lossy().into_owned();
         info!("Deleting file {}", remote_path_str);
 
+        if let Some(sftp) = &self.sftp {
+            return sftp.delete(&remote_path.to_path_buf()).await;
+        }
         self.webdav.delete_file(&remote_path_str)
             .await
-            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
+            .map_err(storage_error)?;
         Ok(())
     }
 
     pub async fn list_files(&self, prefix: &str) -> Result<Vec<FileMetadata>> {
+        if let Some(sftp) = &self.sftp {
+            let entries = sftp.list_dir(Path::new(prefix)).await?;
+            return Ok(entries.into_iter()
+                .filter(|entry| !entry.is_dir)
+                .map(|entry| FileMetadata {
+                    path: sftp_listing_path(&entry.path),
+                    size: entry.size,
+                    hash: String::new(),
+                    last_modified: entry.modified
+                        .and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0))
+                        .unwrap_or_else(chrono::Utc::now),
+                })
+                .collect());
+        }
         let file_names = self.webdav.list_files(prefix)
             .await
-            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
+            .map_err(storage_error)?;
 
         // Convert file names to FileMetadata
         let mut files = Vec::new();
@@ -180,17 +343,168 @@ impl HetznerClient {
 
     pub async fn create_directory(&self, path: &str) -> Result<()> {
         debug!("Creating directory: {}", path);
+        if let Some(sftp) = &self.sftp {
+            return sftp.create_dir_all(Path::new(path)).await;
+        }
         self.webdav.create_directory(path)
             .await
-            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
+            .map_err(storage_error)?;
         Ok(())
     }
 
+    /// Create a directory together with any missing parent directories
+    pub async fn create_directory_all(&self, path: &str) -> Result<()> {
+        debug!("Creating directory tree: {}", path);
+        if let Some(sftp) = &self.sftp {
+            return sftp.create_dir_all(Path::new(path)).await;
+        }
+        self.webdav.create_directory_all(path)
+            .await
+            .map_err(storage_error)
+    }
+
     pub async fn list_directories(&self, path: &str) -> Result<Vec<String>> {
         debug!("Listing directories in: {}", path);
+        if let Some(sftp) = &self.sftp {
+            let entries = sftp.list_dir(Path::new(path)).await?;
+            return Ok(entries.into_iter()
+                .filter(|entry| entry.is_dir)
+                .map(|entry| sftp_listing_path(&entry.path).to_string_lossy().into_owned())
+                .collect());
+        }
         self.webdav.list_directories(path)
             .await
-            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))
+            .map_err(storage_error)
     }
 
 }
+
+/// Listed path in the form WebDAV listings use, relative to the storage root
+fn sftp_listing_path(path: &Path) -> PathBuf {
+    path.strip_prefix("/").unwrap_or(path).to_path_buf()
+}
+
+/// Storage error for a failed WebDAV request, telling rejected credentials
+/// and unreachable servers apart from other failures
+fn storage_error(error: anyhow::Error) -> SkylockError {
+    if error.downcast_ref::<webdav::TimeoutError>().is_some() {
+        return SkylockError::Storage(StorageErrorType::NetworkTimeout);
+    }
+    if let Some(status) = error.downcast_ref::<webdav::StatusError>().map(|e| e.status) {
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return SkylockError::Storage(StorageErrorType::AuthenticationFailed);
+        }
+        if status == reqwest::StatusCode::FORBIDDEN {
+            return SkylockError::Storage(StorageErrorType::AccessDenied);
+        }
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return SkylockError::Storage(StorageErrorType::FileNotFound);
+        }
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return SkylockError::Storage(StorageErrorType::RateLimitExceeded);
+        }
+    }
+    let unreachable = error.chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_connect() || e.is_timeout());
+    if unreachable {
+        return SkylockError::Storage(StorageErrorType::ConnectionFailed(error.to_string()));
+    }
+    SkylockError::Storage(StorageErrorType::IOError(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::test_server;
+
+    /// Server accepting PUTs, hashing each body as it arrives without
+    /// keeping it. Returns the endpoint and the size and hash of each body
+    async fn hashing_put_server() -> (String, Arc<Mutex<Vec<(u64, String)>>>) {
+        let (listener, endpoint) = test_server::bind().await;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let received = log.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let Some((head, body)) = test_server::read_head(&mut socket).await else { continue };
+                let length = test_server::content_length(&head).unwrap();
+
+                let mut hasher = Sha256::new();
+                hasher.update(&body);
+                let mut total = body.len() as u64;
+                let mut chunk = vec![0u8; 64 * 1024];
+                while total < length {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&chunk[..n]);
+                    total += n as u64;
+                }
+                received.lock().unwrap().push((total, base64_standard.encode(hasher.finalize())));
+                test_server::respond(&mut socket, 201, &[]).await;
+            }
+        });
+
+        (endpoint, log)
+    }
+
+    /// Peak resident set size of this process, where the platform reports it
+    fn peak_rss() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let kib = status.lines().find_map(|line| line.strip_prefix("VmHWM:"))?;
+        Some(kib.trim().trim_end_matches("kB").trim().parse::<u64>().ok()? * 1024)
+    }
+
+    #[tokio::test]
+    async fn test_upload_hash_is_taken_in_the_same_pass() {
+        let (endpoint, log) = hashing_put_server().await;
+        let client = test_server::client(&endpoint);
+
+        let local = tempfile::NamedTempFile::new().unwrap();
+        let data: Vec<u8> = (0..5 * 1024 * 1024 + 123u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(local.path(), &data).unwrap();
+        let reference = base64_standard.encode(Sha256::digest(&data));
+
+        let metadata = client.upload_file(local.path(), Path::new("/skylock/data.bin")).await.unwrap();
+        assert_eq!(metadata.size, data.len() as u64);
+        assert_eq!(metadata.hash, reference);
+        // The server got exactly the bytes that were hashed
+        assert_eq!(*log.lock().unwrap(), vec![(data.len() as u64, reference)]);
+    }
+
+    #[tokio::test]
+    #[ignore] // Uploads 1 GiB over loopback; run with --ignored, ideally in release mode
+    async fn test_large_upload_hashes_under_memory_ceiling() {
+        const SIZE: u64 = 1024 * 1024 * 1024;
+        const MEMORY_CEILING: u64 = 128 * 1024 * 1024;
+
+        let (endpoint, log) = hashing_put_server().await;
+        let client = test_server::client(&endpoint);
+
+        // Sparse, so it takes no disk space
+        let local = tempfile::NamedTempFile::new().unwrap();
+        local.as_file().set_len(SIZE).unwrap();
+        let mut reference = Sha256::new();
+        let zeros = vec![0u8; 1024 * 1024];
+        for _ in 0..SIZE / zeros.len() as u64 {
+            reference.update(&zeros);
+        }
+        let reference = base64_standard.encode(reference.finalize());
+
+        let before = peak_rss();
+        let metadata = client.upload_file(local.path(), Path::new("/skylock/disk.img")).await.unwrap();
+        assert_eq!((metadata.size, metadata.hash.as_str()), (SIZE, reference.as_str()));
+        assert_eq!(*log.lock().unwrap(), vec![(SIZE, reference)]);
+
+        if let (Some(before), Some(after)) = (before, peak_rss()) {
+            assert!(
+                after.saturating_sub(before) < MEMORY_CEILING,
+                "peak memory grew by {} bytes uploading {} bytes", after - before, SIZE
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Client-side rate limiting of listing requests
+//!
+//! Listing many backup directories fires a burst of PROPFIND requests that
+//! can trip the provider's rate limits. [`RequestRateLimiter`] spaces those
+//! requests out with a token bucket and holds all of them back after a 429
+//! until the server's `Retry-After` has passed.
+
+use reqwest::header::HeaderValue;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Longest `Retry-After` honoured; anything later is capped to this
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+struct BucketState {
+    /// Tokens available, at most one so requests are evenly spaced
+    tokens: f64,
+    last_refill: Instant,
+    /// No request may start before this, set from a 429's `Retry-After`
+    blocked_until: Option<Instant>,
+}
+
+/// Token bucket shared by all requests of one client
+#[derive(Debug)]
+pub struct RequestRateLimiter {
+    /// Tokens added per second (`None` = unlimited)
+    requests_per_second: Option<f64>,
+    state: Mutex<BucketState>,
+}
+
+impl RequestRateLimiter {
+    /// Allow at most `requests_per_second` requests (`None` or a
+    /// non-positive rate only honours `Retry-After`)
+    pub fn new(requests_per_second: Option<f64>) -> Self {
+        Self {
+            requests_per_second: requests_per_second.filter(|rate| *rate > 0.0),
+            state: Mutex::new(BucketState {
+                tokens: 1.0,
+                last_refill: Instant::now(),
+                blocked_until: None,
+            }),
+        }
+    }
+
+    pub fn requests_per_second(&self) -> Option<f64> {
+        self.requests_per_second
+    }
+
+    /// Wait until the next request may be sent
+    ///
+    /// The lock is held while waiting, so concurrent callers queue up and
+    /// go out one token at a time.
+    pub async fn acquire(&self) {
+        let mut state = self.state.lock().await;
+
+        if let Some(until) = state.blocked_until.take() {
+            tokio::time::sleep_until(until).await;
+        }
+
+        let Some(rate) = self.requests_per_second else {
+            return;
+        };
+        let now = Instant::now();
+        state.tokens = (state.tokens + now.duration_since(state.last_refill).as_secs_f64() * rate).min(1.0);
+        state.last_refill = now;
+        if state.tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - state.tokens) / rate);
+            tokio::time::sleep(wait).await;
+            state.tokens = 1.0;
+            state.last_refill = Instant::now();
+        }
+        state.tokens -= 1.0;
+    }
+
+    /// Hold back every request for `wait`, e.g. after a 429
+    pub async fn defer(&self, wait: Duration) {
+        let until = Instant::now() + wait.min(MAX_RETRY_AFTER);
+        let mut state = self.state.lock().await;
+        state.blocked_until = Some(state.blocked_until.map_or(until, |current| current.max(until)));
+    }
+}
+
+/// Parse a `Retry-After` header: delay seconds or an HTTP date
+pub fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_steady_rate_stays_under_cap() {
+        let limiter = RequestRateLimiter::new(Some(20.0));
+        let started = Instant::now();
+        for _ in 0..11 {
+            limiter.acquire().await;
+        }
+        // The first request goes out at once, the other ten 50ms apart
+        assert_eq!(started.elapsed(), Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unlimited_only_waits_after_defer() {
+        let limiter = RequestRateLimiter::new(None);
+        let started = Instant::now();
+        for _ in 0..100 {
+            limiter.acquire().await;
+        }
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        limiter.defer(Duration::from_millis(300)).await;
+        limiter.acquire().await;
+        assert_eq!(started.elapsed(), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after(&HeaderValue::from_static("7")), Some(Duration::from_secs(7)));
+        assert_eq!(
+            parse_retry_after(&HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(120)).to_rfc2822();
+        let wait = parse_retry_after(&HeaderValue::from_str(&later).unwrap()).unwrap();
+        assert!(wait > Duration::from_secs(110) && wait <= Duration::from_secs(120), "{:?}", wait);
+        assert_eq!(parse_retry_after(&HeaderValue::from_static("soon")), None);
+    }
+}
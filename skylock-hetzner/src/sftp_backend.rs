@@ -0,0 +1,344 @@
+//! Storage Box over SFTP as a generic storage backend
+//!
+//! Wraps a [`SecureSftpClient`] in the [`StorageBackend`] trait. The SSH
+//! connection is opened on first use, authenticates with a key and verifies
+//! the server's host key against a known_hosts file. ssh2 blocks, so every
+//! request runs on a blocking task; transfers stream through a channel
+//! instead of being buffered whole.
+
+use async_trait::async_trait;
+use futures_util::{Stream, StreamExt};
+use skylock_core::storage::{DownloadOptions, StorageBackend, StorageItem, UploadOptions};
+use skylock_core::{Result, SkylockError, StorageErrorType};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, OnceCell};
+
+use crate::sftp_secure::{SecureSftpClient, SecureSftpConfig, SftpEntry};
+
+/// Bytes read from the source per transfer chunk
+const TRANSFER_CHUNK: usize = 64 * 1024;
+
+/// Chunks buffered between the async and the blocking side of a transfer
+const CHANNEL_DEPTH: usize = 8;
+
+/// [`StorageBackend`] over SFTP with key authentication
+pub struct SftpBackend {
+    config: SecureSftpConfig,
+    root: PathBuf,
+    client: OnceCell<Arc<SecureSftpClient>>,
+}
+
+impl std::fmt::Debug for SftpBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SftpBackend")
+            .field("host", &self.config.host)
+            .field("port", &self.config.port)
+            .field("root", &self.root)
+            .finish()
+    }
+}
+
+impl SftpBackend {
+    /// Backend for the server in `config`, connecting on first use
+    ///
+    /// Fails if `config` has no known_hosts file, since host keys are always
+    /// verified.
+    pub fn new(config: SecureSftpConfig) -> Result<Self> {
+        if config.known_hosts_path.is_none() {
+            return Err(SkylockError::Config(
+                "SFTP needs a known_hosts file to verify the server's host key".to_string()
+            ));
+        }
+        Ok(Self {
+            config,
+            root: PathBuf::from("/"),
+            client: OnceCell::new(),
+        })
+    }
+
+    /// Resolve paths below `root` on the server instead of `/`
+    pub fn with_root(mut self, root: PathBuf) -> Self {
+        self.root = root;
+        self
+    }
+
+    async fn client(&self) -> Result<Arc<SecureSftpClient>> {
+        self.client.get_or_try_init(|| async {
+            let config = self.config.clone();
+            let client = tokio::task::spawn_blocking(move || SecureSftpClient::connect(config))
+                .await
+                .map_err(join_error)??;
+            Ok(Arc::new(client))
+        }).await.cloned()
+    }
+
+    /// Run a blocking request on the connection
+    async fn run<T, F>(&self, request: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&SecureSftpClient) -> Result<T> + Send + 'static,
+    {
+        let client = self.client().await?;
+        tokio::task::spawn_blocking(move || request(&client))
+            .await
+            .map_err(join_error)?
+    }
+
+    /// Server path of a backend path
+    fn remote_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    /// Upload the chunks of `stream` to `destination`, returning its size
+    ///
+    /// Stops at the first error in the stream; the partial file is left in
+    /// place.
+    pub async fn upload_stream<S>(&self, stream: S, destination: &Path) -> Result<u64>
+    where
+        S: Stream<Item = std::io::Result<Vec<u8>>> + Send,
+    {
+        let client = self.client().await?;
+        let remote = self.remote_path(destination);
+        let (tx, rx) = mpsc::channel(CHANNEL_DEPTH);
+        let writer = tokio::task::spawn_blocking(move || {
+            client.write_from(&remote, &mut ChannelReader::new(rx))
+        });
+
+        let mut stream = std::pin::pin!(stream);
+        let mut failed = None;
+        while let Some(chunk) = stream.next().await {
+            let error = chunk.as_ref().err().map(|e| std::io::Error::new(e.kind(), e.to_string()));
+            // A closed channel means the writer failed; its error is returned below
+            if tx.send(chunk).await.is_err() {
+                break;
+            }
+            if error.is_some() {
+                failed = error;
+                break;
+            }
+        }
+        drop(tx);
+
+        let written = writer.await.map_err(join_error)?;
+        match failed {
+            Some(e) => Err(e.into()),
+            None => written,
+        }
+    }
+
+    /// Download `source` into `destination`, returning its size
+    pub async fn download_to(
+        &self,
+        source: &Path,
+        destination: &mut (dyn AsyncWrite + Send + Unpin),
+    ) -> Result<u64> {
+        let client = self.client().await?;
+        let remote = self.remote_path(source);
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(CHANNEL_DEPTH);
+        let reader = tokio::task::spawn_blocking(move || {
+            client.read_into(&remote, &mut ChannelWriter { tx })
+        });
+
+        let mut written = Ok(());
+        while let Some(chunk) = rx.recv().await {
+            if let Err(e) = destination.write_all(&chunk).await {
+                written = Err(e);
+                break;
+            }
+        }
+        // Stops the reader if writing failed
+        drop(rx);
+
+        let size = reader.await.map_err(join_error);
+        written?;
+        destination.flush().await?;
+        size?
+    }
+
+    /// Entries directly inside `dir`
+    pub async fn list_dir(&self, dir: &Path) -> Result<Vec<SftpEntry>> {
+        let remote = self.remote_path(dir);
+        let dir = dir.to_path_buf();
+        let entries = self.run(move |client| client.read_dir(&remote)).await?;
+        Ok(entries.into_iter()
+            .filter_map(|entry| {
+                let name = entry.path.file_name()?.to_owned();
+                Some(SftpEntry { path: dir.join(name), ..entry })
+            })
+            .collect())
+    }
+
+    /// Create a directory together with any missing parent directories
+    pub async fn create_dir_all(&self, path: &Path) -> Result<()> {
+        let remote = self.remote_path(path);
+        self.run(move |client| client.create_dir_all(&remote)).await
+    }
+
+    /// Size of a file, `None` if it doesn't exist
+    pub async fn file_size(&self, path: &Path) -> Result<Option<u64>> {
+        let remote = self.remote_path(path);
+        self.run(move |client| client.file_size(&remote)).await
+    }
+
+    fn item(path: &Path, size: u64, modified: Option<u64>) -> StorageItem {
+        StorageItem {
+            path: path.to_path_buf(),
+            size,
+            last_modified: modified.and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0)),
+            metadata: None,
+            etag: None,
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for SftpBackend {
+    async fn upload(
+        &self,
+        source: Pin<Box<dyn AsyncRead + Send>>,
+        destination: &PathBuf,
+        _options: Option<UploadOptions>,
+    ) -> Result<StorageItem> {
+        let chunks = futures_util::stream::unfold(Some(source), |source| async move {
+            let mut source = source?;
+            let mut buf = vec![0u8; TRANSFER_CHUNK];
+            match source.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok(buf), Some(source)))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        });
+        let size = self.upload_stream(chunks, destination).await?;
+        Ok(Self::item(destination, size, None))
+    }
+
+    async fn download(
+        &self,
+        source: &PathBuf,
+        mut destination: Pin<Box<dyn AsyncWrite + Send>>,
+        _options: Option<DownloadOptions>,
+    ) -> Result<()> {
+        self.download_to(source, &mut destination).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, path: &PathBuf) -> Result<()> {
+        let remote = self.remote_path(path);
+        self.run(move |client| client.delete_file(&remote)).await
+    }
+
+    async fn list(&self, prefix: Option<&PathBuf>, recursive: bool) -> Result<Vec<StorageItem>> {
+        let mut pending = vec![prefix.cloned().unwrap_or_default()];
+        let mut items = Vec::new();
+        while let Some(dir) = pending.pop() {
+            for entry in self.list_dir(&dir).await? {
+                if entry.is_dir {
+                    if recursive {
+                        pending.push(entry.path);
+                    }
+                } else {
+                    items.push(Self::item(&entry.path, entry.size, entry.modified));
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    async fn get_metadata(&self, path: &PathBuf) -> Result<Option<StorageItem>> {
+        Ok(self.file_size(path).await?.map(|size| Self::item(path, size, None)))
+    }
+
+    async fn copy(&self, source: &PathBuf, destination: &PathBuf) -> Result<StorageItem> {
+        let source = self.remote_path(source);
+        let target = self.remote_path(destination);
+        let size = self.run(move |client| client.copy_file(&source, &target)).await?;
+        Ok(Self::item(destination, size, None))
+    }
+}
+
+fn join_error(error: tokio::task::JoinError) -> SkylockError {
+    SkylockError::Storage(StorageErrorType::IOError(format!("SFTP task failed: {}", error)))
+}
+
+/// Blocking reader over the chunks of an upload
+struct ChannelReader {
+    rx: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ChannelReader {
+    fn new(rx: mpsc::Receiver<std::io::Result<Vec<u8>>>) -> Self {
+        Self { rx, chunk: Vec::new(), pos: 0 }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Blocking writer handing the chunks of a download to an async task
+struct ChannelWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.tx.blocking_send(buf.to_vec())
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "download cancelled"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(known_hosts_path: Option<PathBuf>) -> SecureSftpConfig {
+        SecureSftpConfig {
+            host: "u123456.your-storagebox.de".to_string(),
+            port: 23,
+            username: "u123456".to_string(),
+            private_key_path: PathBuf::from("/home/user/.ssh/id_ed25519"),
+            key_passphrase: None,
+            known_hosts_path,
+            metadata_key: None,
+        }
+    }
+
+    #[test]
+    fn test_host_key_verification_required() {
+        assert!(SftpBackend::new(config(None)).is_err());
+
+        let backend = SftpBackend::new(config(Some(PathBuf::from("/tmp/known_hosts"))))
+            .unwrap()
+            .with_root(PathBuf::from("/home/storage"));
+        assert_eq!(backend.remote_path(Path::new("/skylock/backups")), PathBuf::from("/home/storage/skylock/backups"));
+        assert_eq!(backend.remote_path(Path::new("skylock")), PathBuf::from("/home/storage/skylock"));
+    }
+}
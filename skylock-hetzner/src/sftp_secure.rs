@@ -5,6 +5,9 @@
 //! - Host key verification
 //! - Metadata encryption (filenames, paths)
 //! - Zero-knowledge architecture
+//!
+//! Host keys are always verified against a known_hosts file. A host seen for
+//! the first time is added to it; a host whose key changed is refused.
 
 use skylock_core::{Result, SkylockError, StorageErrorType};
 use ssh2::{Session, KnownHosts};
@@ -34,10 +37,21 @@ pub struct SecureSftpConfig {
     pub private_key_path: PathBuf,
     /// Optional passphrase for encrypted private key
     pub key_passphrase: Option<String>,
-    /// Path to known_hosts file for host key verification
+    /// Path to known_hosts file for host key verification (required)
     pub known_hosts_path: Option<PathBuf>,
-    /// Encryption key for metadata (filenames, paths)
-    pub metadata_key: [u8; 32],
+    /// Encryption key for metadata (filenames, paths); `None` stores
+    /// paths as given
+    pub metadata_key: Option<[u8; 32]>,
+}
+
+/// Entry of a remote directory listing
+#[derive(Debug, Clone)]
+pub struct SftpEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub is_dir: bool,
+    /// Modification time in seconds since the Unix epoch
+    pub modified: Option<u64>,
 }
 
 /// Secure SFTP client with key-based authentication and metadata encryption
@@ -45,7 +59,7 @@ pub struct SecureSftpClient {
     session: Session,
     sftp: ssh2::Sftp,
     config: SecureSftpConfig,
-    metadata_cipher: Aes256Gcm,
+    metadata_cipher: Option<Aes256Gcm>,
 }
 
 impl SecureSftpClient {
@@ -72,11 +86,13 @@ impl SecureSftpClient {
             })?;
 
         // Verify host key (MITM protection)
-        if let Some(ref known_hosts_path) = config.known_hosts_path {
-            Self::verify_host_key(&session, &config.host, known_hosts_path)?;
-        } else {
-            warn!("⚠️  Host key verification disabled - vulnerable to MITM attacks!");
-        }
+        let known_hosts_path = config.known_hosts_path.as_ref().ok_or_else(|| {
+            error!("No known_hosts file configured for {}", config.host);
+            SkylockError::Storage(StorageErrorType::ConnectionFailed(
+                "Host key verification requires a known_hosts file".to_string()
+            ))
+        })?;
+        Self::verify_host_key(&session, &config.host, config.port, known_hosts_path)?;
 
         // Authenticate using Ed25519 private key
         debug!("🔑 Authenticating with Ed25519 key: {}", config.private_key_path.display());
@@ -105,10 +121,10 @@ impl SecureSftpClient {
             })?;
 
         // Initialize metadata encryption cipher
-        let key = Key::<Aes256Gcm>::from_slice(&config.metadata_key);
-        let metadata_cipher = Aes256Gcm::new(key);
-
-        info!("🔒 Metadata encryption initialized");
+        let metadata_cipher = config.metadata_key.as_ref().map(|key| {
+            info!("🔒 Metadata encryption initialized");
+            Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        });
 
         Ok(Self {
             session,
@@ -119,8 +135,11 @@ impl SecureSftpClient {
     }
 
     /// Verify host key against known_hosts file (prevents MITM attacks)
-    fn verify_host_key(session: &Session, host: &str, known_hosts_path: &Path) -> Result<()> {
-        debug!("🔍 Verifying host key for {}", host);
+    ///
+    /// Hosts on a port other than 22 are recorded as `[host]:port`, like
+    /// OpenSSH does.
+    fn verify_host_key(session: &Session, host: &str, port: u16, known_hosts_path: &Path) -> Result<()> {
+        debug!("🔍 Verifying host key for {}:{}", host, port);
         
         let mut known_hosts = session.known_hosts()
             .map_err(|e| SkylockError::Storage(StorageErrorType::ConnectionFailed(e.to_string())))?;
@@ -144,16 +163,21 @@ impl SecureSftpClient {
             })?;
 
         // Check against known_hosts
-        match known_hosts.check(host, key) {
+        match known_hosts.check_port(host, port, key) {
             ssh2::CheckResult::Match => {
                 info!("✅ Host key verified successfully");
                 Ok(())
             }
             ssh2::CheckResult::NotFound => {
-                warn!("⚠️  Host {} not in known_hosts - adding automatically", host);
-                // In production, you should prompt the user!
-                known_hosts.add(host, key, "", key_type.into())
+                // Trust on first use: later connections must present the same key
+                let entry = if port == 22 { host.to_string() } else { format!("[{}]:{}", host, port) };
+                warn!("⚠️  Host {} not in known_hosts - recording its key", entry);
+                known_hosts.add(&entry, key, "", key_type.into())
                     .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
+                if let Some(parent) = known_hosts_path.parent() {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
+                }
                 known_hosts.write_file(known_hosts_path, ssh2::KnownHostFileKind::OpenSSH)
                     .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
                 Ok(())
@@ -170,14 +194,14 @@ impl SecureSftpClient {
     }
 
     /// Encrypt filename/path for zero-knowledge storage
-    fn encrypt_metadata(&self, plaintext: &str) -> Result<String> {
+    fn encrypt_metadata(cipher: &Aes256Gcm, plaintext: &str) -> Result<String> {
         // Generate random nonce
         let mut nonce_bytes = [0u8; 12];
         rand::thread_rng().fill_bytes(&mut nonce_bytes);
         let nonce = Nonce::from_slice(&nonce_bytes);
 
         // Encrypt the metadata
-        let ciphertext = self.metadata_cipher
+        let ciphertext = cipher
             .encrypt(nonce, plaintext.as_bytes())
             .map_err(|e| {
                 error!("Metadata encryption failed: {}", e);
@@ -193,7 +217,7 @@ impl SecureSftpClient {
     }
 
     /// Decrypt filename/path
-    fn decrypt_metadata(&self, encrypted: &str) -> Result<String> {
+    fn decrypt_metadata(cipher: &Aes256Gcm, encrypted: &str) -> Result<String> {
         // Decode from base64
         let combined = URL_SAFE_NO_PAD.decode(encrypted)
             .map_err(|e| {
@@ -212,7 +236,7 @@ impl SecureSftpClient {
         let nonce = Nonce::from_slice(nonce_bytes);
 
         // Decrypt
-        let plaintext = self.metadata_cipher
+        let plaintext = cipher
             .decrypt(nonce, ciphertext)
             .map_err(|e| {
                 error!("Metadata decryption failed: {}", e);
@@ -236,7 +260,7 @@ impl SecureSftpClient {
 
         // Create encrypted parent directories
         if let Some(parent) = encrypted_path.parent() {
-            self.create_dir_all(parent).await?;
+            self.mkdir_all(parent)?;
         }
 
         let mut local_file = tokio::fs::File::open(local_path).await
@@ -345,12 +369,16 @@ impl SecureSftpClient {
         let entries = self.sftp.readdir(&encrypted_path)
             .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
 
+        let Some(cipher) = &self.metadata_cipher else {
+            return Ok(entries.into_iter().map(|(path, _stat)| path).collect());
+        };
+
         // Decrypt filenames
         let mut decrypted = Vec::new();
         for (path, _stat) in entries {
             if let Some(filename) = path.file_name() {
                 if let Some(filename_str) = filename.to_str() {
-                    match self.decrypt_metadata(filename_str) {
+                    match Self::decrypt_metadata(cipher, filename_str) {
                         Ok(decrypted_name) => {
                             let mut full_path = path.clone();
                             full_path.set_file_name(decrypted_name);
@@ -367,32 +395,17 @@ impl SecureSftpClient {
         Ok(decrypted)
     }
 
-    /// Create all parent directories with encrypted names
-    async fn create_dir_all(&self, path: &Path) -> Result<()> {
-        let mut current = PathBuf::new();
-        
-        for component in path.components() {
-            current.push(component);
-            
-            match self.sftp.mkdir(&current, 0o755) {
-                Ok(_) => debug!("📁 Created directory: {:?}", current),
-                Err(e) if e.message().contains("already exists") => {
-                    // Directory exists, continue
-                }
-                Err(_) => return Err(SkylockError::Storage(StorageErrorType::AccessDenied)),
-            }
-        }
-        
-        Ok(())
-    }
-
     /// Helper: Encrypt a full path (each component separately for efficient directory traversal)
     fn encrypt_path(&self, path: &Path) -> Result<PathBuf> {
+        let Some(cipher) = &self.metadata_cipher else {
+            return Ok(path.to_path_buf());
+        };
+
         let mut encrypted = PathBuf::new();
         
         for component in path.components() {
             if let Some(os_str) = component.as_os_str().to_str() {
-                let encrypted_component = self.encrypt_metadata(os_str)?;
+                let encrypted_component = Self::encrypt_metadata(cipher, os_str)?;
                 encrypted.push(encrypted_component);
             }
         }
@@ -400,6 +413,119 @@ impl SecureSftpClient {
         Ok(encrypted)
     }
 
+    /// Write everything `reader` produces to a remote file, creating its
+    /// parent directories
+    ///
+    /// Blocks on the SSH connection; call from a blocking task.
+    pub fn write_from(&self, remote_path: &Path, reader: &mut dyn Read) -> Result<u64> {
+        self.write_from_encrypted(&self.encrypt_path(remote_path)?, reader)
+    }
+
+    /// [`write_from`](Self::write_from) for an already encrypted path
+    fn write_from_encrypted(&self, remote_path: &Path, reader: &mut dyn Read) -> Result<u64> {
+        if let Some(parent) = remote_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            self.mkdir_all(parent)?;
+        }
+
+        let mut remote_file = self.sftp.create(remote_path)
+            .map_err(|e| {
+                error!("Failed to create remote file: {}", e);
+                SkylockError::Storage(StorageErrorType::AccessDenied)
+            })?;
+        std::io::copy(reader, &mut remote_file)
+            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))
+    }
+
+    /// Copy a remote file into `writer`
+    ///
+    /// Blocks on the SSH connection; call from a blocking task.
+    pub fn read_into(&self, remote_path: &Path, writer: &mut dyn Write) -> Result<u64> {
+        let remote_path = self.encrypt_path(remote_path)?;
+        let mut remote_file = self.sftp.open(&remote_path)
+            .map_err(|e| {
+                debug!("Failed to open remote file: {}", e);
+                SkylockError::Storage(StorageErrorType::FileNotFound)
+            })?;
+        std::io::copy(&mut remote_file, writer)
+            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))
+    }
+
+    /// Copy one remote file to another, returning its size
+    ///
+    /// Streams between the two open files on the server connection instead of
+    /// holding the file in memory. Blocks on the SSH connection; call from a
+    /// blocking task.
+    pub fn copy_file(&self, source: &Path, destination: &Path) -> Result<u64> {
+        let source = self.encrypt_path(source)?;
+        let mut source_file = self.sftp.open(&source)
+            .map_err(|e| {
+                debug!("Failed to open remote file: {}", e);
+                SkylockError::Storage(StorageErrorType::FileNotFound)
+            })?;
+        self.write_from_encrypted(&self.encrypt_path(destination)?, &mut source_file)
+    }
+
+    /// Size of a remote file, `None` if it doesn't exist
+    pub fn file_size(&self, remote_path: &Path) -> Result<Option<u64>> {
+        let remote_path = self.encrypt_path(remote_path)?;
+        match self.sftp.stat(&remote_path) {
+            Ok(stat) => Ok(Some(stat.size.unwrap_or(0))),
+            Err(e) if e.code() == ssh2::ErrorCode::SFTP(2) => Ok(None), // SSH_FX_NO_SUCH_FILE
+            Err(e) => Err(SkylockError::Storage(StorageErrorType::IOError(e.to_string()))),
+        }
+    }
+
+    /// List a remote directory with the size and type of each entry
+    pub fn read_dir(&self, remote_path: &Path) -> Result<Vec<SftpEntry>> {
+        let encrypted_path = self.encrypt_path(remote_path)?;
+        let entries = self.sftp.readdir(&encrypted_path)
+            .map_err(|e| SkylockError::Storage(StorageErrorType::IOError(e.to_string())))?;
+
+        let mut listed = Vec::with_capacity(entries.len());
+        for (path, stat) in entries {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+            let name = match &self.metadata_cipher {
+                Some(cipher) => match Self::decrypt_metadata(cipher, name) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        warn!("Failed to decrypt filename: {} - {}", name, e);
+                        continue;
+                    }
+                },
+                None => name.to_string(),
+            };
+            listed.push(SftpEntry {
+                path: remote_path.join(name),
+                size: stat.size.unwrap_or(0),
+                is_dir: stat.is_dir(),
+                modified: stat.mtime,
+            });
+        }
+
+        Ok(listed)
+    }
+
+    /// Create a remote directory together with any missing parents
+    pub fn create_dir_all(&self, remote_path: &Path) -> Result<()> {
+        let remote_path = self.encrypt_path(remote_path)?;
+        self.mkdir_all(&remote_path)
+    }
+
+    /// Create a directory and its missing parents (already encrypted)
+    fn mkdir_all(&self, path: &Path) -> Result<()> {
+        let mut current = PathBuf::new();
+        for component in path.components() {
+            current.push(component);
+            match self.sftp.mkdir(&current, 0o755) {
+                Ok(_) => debug!("📁 Created directory: {:?}", current),
+                // Servers report an existing directory as a generic failure
+                Err(_) if self.sftp.stat(&current).is_ok_and(|stat| stat.is_dir()) => {}
+                Err(_) => return Err(SkylockError::Storage(StorageErrorType::AccessDenied)),
+            }
+        }
+        Ok(())
+    }
+
     /// Test connection
     pub fn test_connection(&self) -> Result<()> {
         if !self.session.authenticated() {
@@ -457,31 +583,18 @@ mod tests {
 
     #[test]
     fn test_metadata_encryption() {
-        let config = SecureSftpConfig {
-            host: "test.example.com".to_string(),
-            port: 22,
-            username: "test".to_string(),
-            private_key_path: PathBuf::from("/tmp/test_key"),
-            key_passphrase: None,
-            known_hosts_path: None,
-            metadata_key: [42u8; 32],
-        };
-
-        let key = Key::<Aes256Gcm>::from_slice(&config.metadata_key);
+        let key = Key::<Aes256Gcm>::from_slice(&[42u8; 32]);
         let cipher = Aes256Gcm::new(key);
 
-        let client = SecureSftpClient {
-            session: unsafe { std::mem::zeroed() }, // Mock for test
-            sftp: unsafe { std::mem::zeroed() },
-            config,
-            metadata_cipher: cipher,
-        };
-
         let plaintext = "secret_file.txt";
-        let encrypted = client.encrypt_metadata(plaintext).unwrap();
-        let decrypted = client.decrypt_metadata(&encrypted).unwrap();
+        let encrypted = SecureSftpClient::encrypt_metadata(&cipher, plaintext).unwrap();
+        let decrypted = SecureSftpClient::decrypt_metadata(&cipher, &encrypted).unwrap();
 
         assert_eq!(plaintext, decrypted);
         assert_ne!(plaintext, encrypted); // Should be different!
+
+        // A different key cannot read the name
+        let other = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&[7u8; 32]));
+        assert!(SecureSftpClient::decrypt_metadata(&other, &encrypted).is_err());
     }
 }
@@ -0,0 +1,122 @@
+//! Building blocks for in-process HTTP servers standing in for a Storage
+//! Box in tests
+//!
+//! Available to this crate's tests and, through the `test-support` feature,
+//! to the tests of crates depending on it.
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{HetznerClient, HetznerConfig};
+
+/// Listener on a free loopback port, with its `http://` endpoint
+pub async fn bind() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    (listener, endpoint)
+}
+
+/// Client for the server at `endpoint`
+pub fn client(endpoint: &str) -> HetznerClient {
+    HetznerClient::new(HetznerConfig {
+        endpoint: endpoint.to_string(),
+        username: "user".to_string(),
+        password: "pass".to_string(),
+        api_token: String::new(),
+        encryption_key: String::new(),
+    }).unwrap()
+}
+
+/// Read a request head, returning it with any body bytes read past it, or
+/// `None` if the connection closes first
+pub async fn read_head(socket: &mut TcpStream) -> Option<(String, Vec<u8>)> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let end = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let n = socket.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let rest = buf.split_off(end);
+    Some((String::from_utf8_lossy(&buf).to_string(), rest))
+}
+
+/// Value of the `Content-Length` header in `head`
+pub fn content_length(head: &str) -> Option<u64> {
+    head.lines()
+        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+        .and_then(|v| v.parse().ok())
+}
+
+/// A request read in full
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub head: String,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Read a whole request, with a `Content-Length` or chunked body, or
+    /// `None` if the connection closes first
+    pub async fn read(socket: &mut TcpStream) -> Option<Self> {
+        let (head, mut rest) = read_head(socket).await?;
+        let mut chunk = [0u8; 8192];
+        let body = if head.to_ascii_lowercase().contains("transfer-encoding: chunked") {
+            // Streamed uploads: <hex size>\r\n<data>\r\n ... 0\r\n\r\n
+            let mut body = Vec::new();
+            loop {
+                let line_end = loop {
+                    if let Some(i) = rest.windows(2).position(|w| w == b"\r\n") {
+                        break i;
+                    }
+                    let n = socket.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+                    rest.extend_from_slice(&chunk[..n]);
+                };
+                let size = usize::from_str_radix(std::str::from_utf8(&rest[..line_end]).ok()?.trim(), 16).ok()?;
+                while rest.len() < line_end + 2 + size + 2 {
+                    let n = socket.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+                    rest.extend_from_slice(&chunk[..n]);
+                }
+                if size == 0 {
+                    break body;
+                }
+                body.extend_from_slice(&rest[line_end + 2..line_end + 2 + size]);
+                rest.drain(..line_end + 2 + size + 2);
+            }
+        } else {
+            let length = content_length(&head).unwrap_or(0) as usize;
+            while rest.len() < length {
+                let n = socket.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+                rest.extend_from_slice(&chunk[..n]);
+            }
+            rest.truncate(length);
+            rest
+        };
+
+        let mut parts = head.split_whitespace();
+        let method = parts.next()?.to_string();
+        let path = parts.next()?.to_string();
+        Some(Self { method, path, head, body })
+    }
+
+    /// Answer with `status` and `body`, sending only the headers for HEAD
+    pub async fn respond(&self, socket: &mut TcpStream, status: u16, body: &[u8]) {
+        write_response(socket, status, body, self.method != "HEAD").await;
+    }
+}
+
+/// Write a response with `status` and `body`, closing the connection after
+pub async fn respond(socket: &mut TcpStream, status: u16, body: &[u8]) {
+    write_response(socket, status, body, true).await;
+}
+
+async fn write_response(socket: &mut TcpStream, status: u16, body: &[u8], send_body: bool) {
+    let header = format!("HTTP/1.1 {} X\r\ncontent-length: {}\r\nconnection: close\r\n\r\n", status, body.len());
+    let _ = socket.write_all(header.as_bytes()).await;
+    if send_body {
+        let _ = socket.write_all(body).await;
+    }
+}
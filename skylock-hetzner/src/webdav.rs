@@ -1,14 +1,45 @@
+use std::future::Future;
 use std::path::Path;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use anyhow::{Result, anyhow};
-use reqwest::{Client, Method, Response};
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, CONTENT_LENGTH};
+use futures_util::StreamExt;
+use reqwest::{Client, Method, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_RANGES, AUTHORIZATION, CONTENT_TYPE, CONTENT_LENGTH, RANGE, RETRY_AFTER};
+use reqwest::StatusCode;
 use base64::prelude::*;
 use serde::{Deserialize, Serialize};
 use tracing::{info, debug, warn, error};
 use url::Url;
 use indicatif::ProgressBar;
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+
+use crate::rate_limit::{parse_retry_after, RequestRateLimiter};
+
+/// Times a listing or deletion is retried after a 429 before the response
+/// is returned
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+/// Default time allowed to establish a connection
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default time a request may go without sending or receiving any data
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Size of the pieces an in-memory upload body is sent in, so progress can
+/// be tracked while it is written
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8" ?>
+<D:propfind xmlns:D="DAV:">
+    <D:prop>
+        <D:resourcetype/>
+        <D:getcontentlength/>
+        <D:getlastmodified/>
+    </D:prop>
+</D:propfind>"#;
 
 #[derive(Debug, Clone)]
 pub struct WebDAVConfig {
@@ -23,6 +54,59 @@ pub struct HetznerWebDAVClient {
     client: Client,
     config: WebDAVConfig,
     auth_header: HeaderValue,
+    /// Paces PROPFIND requests; shared by clones of this client
+    list_limiter: Arc<RequestRateLimiter>,
+    /// Paces DELETE requests; shared by clones of this client
+    delete_limiter: Arc<RequestRateLimiter>,
+    /// Longest a request may go without bytes moving in either direction
+    read_timeout: Duration,
+}
+
+/// Size and range support of a remote file, from a HEAD request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteObjectInfo {
+    pub size: u64,
+    /// Server advertised `Accept-Ranges: bytes`
+    pub accepts_ranges: bool,
+}
+
+/// Request answered with an unsuccessful HTTP status
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct StatusError {
+    pub status: StatusCode,
+    message: String,
+}
+
+fn status_error(status: StatusCode, message: String) -> anyhow::Error {
+    StatusError { status, message }.into()
+}
+
+/// Request abandoned because no data moved for the read timeout
+#[derive(Debug, thiserror::Error)]
+#[error("Timeout: {operation} made no progress for {timeout:?}")]
+pub struct TimeoutError {
+    pub operation: String,
+    pub timeout: Duration,
+}
+
+/// When a request last sent or received data; shared with the body streams
+/// that move it
+#[derive(Debug, Clone)]
+struct Progress(Arc<Mutex<Instant>>);
+
+impl Progress {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    fn touch(&self) {
+        *self.0.lock().unwrap() = Instant::now();
+    }
+
+    fn last(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -66,11 +150,7 @@ struct ResourceType {
 
 impl HetznerWebDAVClient {
     pub fn new(config: WebDAVConfig) -> Result<Self> {
-        // Create HTTP client with TLS support (rustls)
-        let client = Client::builder()
-            .use_rustls_tls()
-            .timeout(std::time::Duration::from_secs(300)) // 5 minutes for large uploads
-            .build()?;
+        let client = Self::http_client(DEFAULT_CONNECT_TIMEOUT)?;
 
         // Create Basic Auth header
         let credentials = format!("{}:{}", config.username, config.password);
@@ -81,9 +161,146 @@ impl HetznerWebDAVClient {
             client,
             config,
             auth_header,
+            list_limiter: Arc::new(RequestRateLimiter::new(None)),
+            delete_limiter: Arc::new(RequestRateLimiter::new(None)),
+            read_timeout: DEFAULT_READ_TIMEOUT,
         })
     }
 
+    /// HTTP client with TLS support (rustls)
+    ///
+    /// There is deliberately no total timeout: large uploads legitimately
+    /// take hours, so stalls are caught by the read timeout instead.
+    fn http_client(connect_timeout: Duration) -> Result<Client> {
+        Ok(Client::builder()
+            .use_rustls_tls()
+            .connect_timeout(connect_timeout)
+            .build()?)
+    }
+
+    /// Give up connecting after `connect` and abandon any request that goes
+    /// `read` without sending or receiving data
+    pub fn with_timeouts(mut self, connect: Duration, read: Duration) -> Self {
+        match Self::http_client(connect) {
+            Ok(client) => self.client = client,
+            Err(e) => warn!("Keeping the default connect timeout: {}", e),
+        }
+        self.read_timeout = read;
+        self
+    }
+
+    /// Run `request`, failing with a [`TimeoutError`] once `progress` has
+    /// stood still for the read timeout
+    async fn within_timeout<T>(
+        &self,
+        operation: &str,
+        progress: &Progress,
+        request: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        tokio::pin!(request);
+        loop {
+            let deadline = progress.last() + self.read_timeout;
+            tokio::select! {
+                result = &mut request => return result,
+                _ = tokio::time::sleep_until(deadline) => {
+                    if progress.last() + self.read_timeout <= Instant::now() {
+                        warn!("{} made no progress for {:?}, giving up", operation, self.read_timeout);
+                        return Err(TimeoutError {
+                            operation: operation.to_string(),
+                            timeout: self.read_timeout,
+                        }.into());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send a request without a body, allowing the read timeout for the
+    /// response headers
+    async fn send(&self, operation: &str, request: RequestBuilder) -> Result<Response> {
+        self.within_timeout(operation, &Progress::new(), async { Ok(request.send().await?) }).await
+    }
+
+    /// Read a whole response body, allowing the read timeout between chunks
+    async fn read_body(&self, operation: &str, mut response: Response) -> Result<Vec<u8>> {
+        let progress = Progress::new();
+        self.within_timeout(operation, &progress, async {
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                progress.touch();
+                body.extend_from_slice(&chunk);
+            }
+            Ok(body)
+        }).await
+    }
+
+    /// Send `request` with a streamed body; the read timeout is measured
+    /// from the last chunk the server accepted
+    async fn send_stream<S>(&self, operation: &str, request: RequestBuilder, body: S) -> Result<Response>
+    where
+        S: futures_util::Stream<Item = std::io::Result<bytes::Bytes>> + Send + 'static,
+    {
+        let progress = Progress::new();
+        let tracker = progress.clone();
+        let body = body.inspect(move |_| tracker.touch());
+        self.within_timeout(operation, &progress, async {
+            Ok(request.body(reqwest::Body::wrap_stream(body)).send().await?)
+        }).await
+    }
+
+    /// Send at most `requests_per_second` listing (PROPFIND) requests
+    /// (`None` = unlimited). A 429's `Retry-After` is honoured either way
+    pub fn with_list_rate_limit(mut self, requests_per_second: Option<f64>) -> Self {
+        self.list_limiter = Arc::new(RequestRateLimiter::new(requests_per_second));
+        self
+    }
+
+    /// Send at most `requests_per_second` DELETE requests (`None` =
+    /// unlimited). A 429's `Retry-After` is honoured either way
+    pub fn with_delete_rate_limit(mut self, requests_per_second: Option<f64>) -> Self {
+        self.delete_limiter = Arc::new(RequestRateLimiter::new(requests_per_second));
+        self
+    }
+
+    /// Depth-1 PROPFIND, paced by the list limiter
+    async fn propfind(&self, url: Url) -> Result<Response> {
+        self.send_paced(&self.list_limiter, &format!("PROPFIND {}", url), || {
+            Ok(self.client
+                .request(Method::from_bytes(b"PROPFIND")?, url.clone())
+                .header(AUTHORIZATION, &self.auth_header)
+                .header("Depth", "1")
+                .header(CONTENT_TYPE, "text/xml; charset=utf-8")
+                .body(PROPFIND_BODY))
+        }).await
+    }
+
+    /// Send the request `build` makes once `limiter` allows it
+    ///
+    /// A 429 holds back every request of the limiter for the server's
+    /// `Retry-After` (or an exponential backoff from one second without it)
+    /// and retries.
+    async fn send_paced<F>(&self, limiter: &RequestRateLimiter, operation: &str, build: F) -> Result<Response>
+    where
+        F: Fn() -> Result<RequestBuilder>,
+    {
+        let mut attempt = 0;
+        loop {
+            limiter.acquire().await;
+            let response = self.send(operation, build()?).await?;
+
+            if response.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= MAX_RATE_LIMIT_RETRIES {
+                return Ok(response);
+            }
+            let wait = response.headers()
+                .get(RETRY_AFTER)
+                .and_then(parse_retry_after)
+                .unwrap_or_else(|| Duration::from_secs(1 << attempt));
+            warn!("{} was rate limited, retrying in {:?}", operation, wait);
+            limiter.defer(wait).await;
+            attempt += 1;
+        }
+    }
+
     fn build_url(&self, path: &str) -> Result<Url> {
         let clean_path = path.trim_start_matches('/');
         
@@ -115,11 +332,10 @@ impl HetznerWebDAVClient {
         info!("Testing WebDAV connection to {}", url);
         
         // Use HEAD request instead of PROPFIND for simple connectivity test
-        let response = self.client
+        let request = self.client
             .head(url.clone())
-            .header(AUTHORIZATION, &self.auth_header)
-            .send()
-            .await?;
+            .header(AUTHORIZATION, &self.auth_header);
+        let response = self.send(&format!("HEAD {}", url), request).await?;
 
         let status = response.status();
         debug!("WebDAV HEAD response status: {} for URL: {}", status, url);
@@ -129,7 +345,7 @@ impl HetznerWebDAVClient {
             Ok(())
         } else {
             error!("WebDAV connection failed: {}", status);
-            Err(anyhow!("Connection failed with status: {}", status))
+            Err(status_error(status, format!("Connection failed with status: {}", status)))
         }
     }
 
@@ -137,11 +353,10 @@ impl HetznerWebDAVClient {
         debug!("Creating directory: {}", path);
         
         let url = self.build_url(path)?;
-        let response = self.client
+        let request = self.client
             .request(Method::from_bytes(b"MKCOL")?, url)
-            .header(AUTHORIZATION, &self.auth_header)
-            .send()
-            .await?;
+            .header(AUTHORIZATION, &self.auth_header);
+        let response = self.send(&format!("MKCOL {}", path), request).await?;
 
         if response.status().is_success() || response.status().as_u16() == 405 {
             // 405 Method Not Allowed usually means directory already exists
@@ -149,8 +364,23 @@ impl HetznerWebDAVClient {
             Ok(())
         } else {
             warn!("Failed to create directory {}: {}", path, response.status());
-            Err(anyhow!("Failed to create directory: {}", response.status()))
+            Err(status_error(response.status(), format!("Failed to create directory: {}", response.status())))
+        }
+    }
+
+    /// Create a directory and any missing parents, one MKCOL per path
+    /// segment from the top down
+    ///
+    /// Segments that already exist (405) are skipped, so this is safe to call
+    /// repeatedly for the same path.
+    pub async fn create_directory_all(&self, path: &str) -> Result<()> {
+        let mut current = String::new();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            current.push('/');
+            current.push_str(segment);
+            self.create_directory(&current).await?;
         }
+        Ok(())
     }
 
     pub async fn upload_file(&self, local_path: &Path, remote_path: &str) -> Result<()> {
@@ -160,10 +390,83 @@ impl HetznerWebDAVClient {
     pub async fn upload_file_with_progress(&self, local_path: &Path, remote_path: &str, progress: Option<ProgressBar>) -> Result<()> {
         info!("Uploading {} to {}", local_path.display(), remote_path);
         
+        // Streamed from disk, so large files aren't held in memory
+        let file = tokio::fs::File::open(local_path).await?;
+        let size = file.metadata().await?.len();
+        self.upload_reader_with_progress(file, size, remote_path, progress).await
+    }
+
+    /// Upload the `size` bytes of `reader`, reading them as they are sent
+    /// 
+    /// The body has a known length, unlike [`Self::upload_stream`], so the
+    /// server can reject a short upload.
+    pub async fn upload_reader_with_progress<R>(&self, reader: R, size: u64, remote_path: &str, progress: Option<ProgressBar>) -> Result<()>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
         let url = self.build_url(remote_path)?;
         info!("Full upload URL: {}", url);
-        let file = tokio::fs::File::open(local_path).await?;
-        let file_size = file.metadata().await?.len();
+
+        if let Some(pb) = &progress {
+            pb.set_position(0);
+            pb.set_message(format!("📤 Uploading..."));
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, self.auth_header.clone());
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_str(&size.to_string())?);
+
+        // One chunk in memory at a time; a read error ends the stream with
+        // that error, which aborts the request
+        let pieces = futures_util::stream::unfold(Some(reader), |reader| async move {
+            let mut reader = reader?;
+            let mut chunk = vec![0u8; UPLOAD_CHUNK_SIZE];
+            match reader.read(&mut chunk).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    chunk.truncate(n);
+                    Some((Ok(bytes::Bytes::from(chunk)), Some(reader)))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        });
+        let sent = progress.clone();
+        let pieces = pieces.inspect(move |piece| {
+            if let (Some(pb), Ok(piece)) = (&sent, piece) {
+                pb.inc(piece.len() as u64);
+            }
+        });
+        let request = self.client
+            .put(url)
+            .headers(headers);
+        let response = self.send_stream(&format!("PUT {}", remote_path), request, pieces).await?;
+
+        if let Some(pb) = &progress {
+            pb.set_position(size);
+        }
+
+        if response.status().is_success() {
+            info!("Successfully uploaded {}", remote_path);
+            Ok(())
+        } else {
+            let status = response.status();
+            error!("Upload failed for {}: {}", remote_path, status);
+            let error_body = response.text().await.unwrap_or_default();
+            Err(status_error(status, format!("Upload failed: {} - {}", status, error_body)))
+        }
+    }
+
+    /// Upload a body that is already in memory
+    pub async fn upload_bytes(&self, data: Vec<u8>, remote_path: &str) -> Result<()> {
+        debug!("Uploading {} bytes to {}", data.len(), remote_path);
+        self.upload_bytes_with_progress(data, remote_path, None).await
+    }
+
+    async fn upload_bytes_with_progress(&self, buffer: Vec<u8>, remote_path: &str, progress: Option<ProgressBar>) -> Result<()> {
+        let url = self.build_url(remote_path)?;
+        info!("Full upload URL: {}", url);
+        let file_size = buffer.len() as u64;
         
         // Set progress bar to show it's starting
         if let Some(pb) = &progress {
@@ -171,22 +474,22 @@ impl HetznerWebDAVClient {
             pb.set_message(format!("📤 Uploading..."));
         }
         
-        // Read entire file into memory (reqwest needs the full body)
-        // This is fast and not the bottleneck - network upload is
-        let buffer = tokio::fs::read(local_path).await?;
-        
         let mut headers = HeaderMap::new();
         headers.insert(AUTHORIZATION, self.auth_header.clone());
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
         headers.insert(CONTENT_LENGTH, HeaderValue::from_str(&file_size.to_string())?);
 
-        // Now do the actual upload (this is where the time is spent)
-        let response = self.client
+        // Now do the actual upload (this is where the time is spent), in
+        // pieces so a stalled server is noticed
+        let buffer = bytes::Bytes::from(buffer);
+        let pieces = (0..buffer.len())
+            .step_by(UPLOAD_CHUNK_SIZE)
+            .map(move |start| Ok(buffer.slice(start..(start + UPLOAD_CHUNK_SIZE).min(buffer.len()))))
+            .collect::<Vec<_>>();
+        let request = self.client
             .put(url)
-            .headers(headers)
-            .body(buffer)
-            .send()
-            .await?;
+            .headers(headers);
+        let response = self.send_stream(&format!("PUT {}", remote_path), request, futures_util::stream::iter(pieces)).await?;
 
         // Mark as complete after successful upload
         if let Some(pb) = &progress {
@@ -200,34 +503,149 @@ impl HetznerWebDAVClient {
             let status = response.status();
             error!("Upload failed for {}: {}", remote_path, status);
             let error_body = response.text().await.unwrap_or_default();
-            Err(anyhow!("Upload failed: {} - {}", status, error_body))
+            Err(status_error(status, format!("Upload failed: {} - {}", status, error_body)))
+        }
+    }
+
+    /// Upload a body of unknown length as it is produced, using chunked
+    /// transfer encoding
+    /// 
+    /// An `Err` item from the stream aborts the request, so the server never
+    /// sees a truncated body as a complete upload.
+    pub async fn upload_stream<S>(&self, stream: S, remote_path: &str) -> Result<()>
+    where
+        S: futures_util::Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static,
+    {
+        info!("Streaming upload to {}", remote_path);
+        
+        let url = self.build_url(remote_path)?;
+        let request = self.client
+            .put(url)
+            .header(AUTHORIZATION, &self.auth_header)
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+        let body = stream.map(|chunk| chunk.map(bytes::Bytes::from));
+        let response = self.send_stream(&format!("PUT {}", remote_path), request, body).await?;
+
+        if response.status().is_success() {
+            info!("Successfully uploaded {}", remote_path);
+            Ok(())
+        } else {
+            let status = response.status();
+            error!("Upload failed for {}: {}", remote_path, status);
+            let error_body = response.text().await.unwrap_or_default();
+            Err(status_error(status, format!("Upload failed: {} - {}", status, error_body)))
         }
     }
 
     pub async fn download_file(&self, remote_path: &str, local_path: &Path) -> Result<()> {
         info!("Downloading {} to {}", remote_path, local_path.display());
         
+        let response = self.start_download(remote_path).await?;
+
+        // Ensure local directory exists
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        
+        // Written as it arrives, so large files aren't held in memory
+        let mut file = tokio::fs::File::create(local_path).await?;
+        self.write_body(remote_path, response, &mut file).await?;
+        info!("Successfully downloaded {}", remote_path);
+        Ok(())
+    }
+
+    /// Download `remote_path` into `writer` as it arrives, returning its size
+    pub async fn download_to(&self, remote_path: &str, writer: &mut (dyn AsyncWrite + Send + Unpin)) -> Result<u64> {
+        debug!("Downloading {}", remote_path);
+        let response = self.start_download(remote_path).await?;
+        self.write_body(remote_path, response, writer).await
+    }
+
+    /// Send the GET for a download, failing unless it succeeded
+    async fn start_download(&self, remote_path: &str) -> Result<Response> {
         let url = self.build_url(remote_path)?;
-        let response = self.client
+        let request = self.client
             .get(url)
-            .header(AUTHORIZATION, &self.auth_header)
-            .send()
-            .await?;
+            .header(AUTHORIZATION, &self.auth_header);
+        let response = self.send(&format!("GET {}", remote_path), request).await?;
 
         if response.status().is_success() {
-            let content = response.bytes().await?;
-            
-            // Ensure local directory exists
-            if let Some(parent) = local_path.parent() {
-                tokio::fs::create_dir_all(parent).await?;
+            Ok(response)
+        } else {
+            error!("Download failed for {}: {}", remote_path, response.status());
+            Err(status_error(response.status(), format!("Download failed: {}", response.status())))
+        }
+    }
+
+    /// Copy a download's body into `writer`, failing if it stalls
+    async fn write_body(&self, remote_path: &str, mut response: Response, writer: &mut (dyn AsyncWrite + Send + Unpin)) -> Result<u64> {
+        let progress = Progress::new();
+        let mut written = 0u64;
+        self.within_timeout(&format!("GET {}", remote_path), &progress, async {
+            while let Some(chunk) = response.chunk().await? {
+                progress.touch();
+                writer.write_all(&chunk).await?;
+                written += chunk.len() as u64;
             }
-            
-            tokio::fs::write(local_path, content).await?;
-            info!("Successfully downloaded {}", remote_path);
             Ok(())
+        }).await?;
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    pub async fn object_info(&self, remote_path: &str) -> Result<RemoteObjectInfo> {
+        let url = self.build_url(remote_path)?;
+        let request = self.client
+            .head(url)
+            .header(AUTHORIZATION, &self.auth_header);
+        let response = self.send(&format!("HEAD {}", remote_path), request).await?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response.status(), format!("HEAD failed: {}", response.status())));
+        }
+
+        // Read the header directly: HEAD responses have no body to size
+        let size = response.headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| anyhow!("HEAD response for {} has no Content-Length", remote_path))?;
+        let accepts_ranges = response.headers()
+            .get(ACCEPT_RANGES)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.split(',').any(|unit| unit.trim().eq_ignore_ascii_case("bytes")));
+
+        Ok(RemoteObjectInfo { size, accepts_ranges })
+    }
+
+    /// Download bytes `start..=end` of a remote file
+    ///
+    /// Returns `None` if the server ignored the Range header and answered
+    /// with something other than the exact partial content requested.
+    pub async fn download_range(&self, remote_path: &str, start: u64, end: u64) -> Result<Option<bytes::Bytes>> {
+        debug!("Downloading bytes {}-{} of {}", start, end, remote_path);
+
+        let url = self.build_url(remote_path)?;
+        let operation = format!("GET {} bytes {}-{}", remote_path, start, end);
+        let request = self.client
+            .get(url)
+            .header(AUTHORIZATION, &self.auth_header)
+            .header(RANGE, format!("bytes={}-{}", start, end));
+        let response = self.send(&operation, request).await?;
+
+        let status = response.status();
+        if status == StatusCode::PARTIAL_CONTENT {
+            let content = bytes::Bytes::from(self.read_body(&operation, response).await?);
+            if content.len() as u64 == end - start + 1 {
+                return Ok(Some(content));
+            }
+            warn!("Range {}-{} of {} returned {} bytes", start, end, remote_path, content.len());
+            Ok(None)
+        } else if status.is_success() {
+            Ok(None)
         } else {
-            error!("Download failed for {}: {}", remote_path, response.status());
-            Err(anyhow!("Download failed: {}", response.status()))
+            error!("Range download failed for {}: {}", remote_path, status);
+            Err(status_error(status, format!("Range download failed: {}", status)))
         }
     }
 
@@ -235,18 +653,18 @@ impl HetznerWebDAVClient {
         debug!("Deleting {}", remote_path);
         
         let url = self.build_url(remote_path)?;
-        let response = self.client
-            .delete(url)
-            .header(AUTHORIZATION, &self.auth_header)
-            .send()
-            .await?;
+        let response = self.send_paced(&self.delete_limiter, &format!("DELETE {}", remote_path), || {
+            Ok(self.client
+                .delete(url.clone())
+                .header(AUTHORIZATION, &self.auth_header))
+        }).await?;
 
         if response.status().is_success() {
             debug!("Successfully deleted {}", remote_path);
             Ok(())
         } else {
             warn!("Delete failed for {}: {}", remote_path, response.status());
-            Err(anyhow!("Delete failed: {}", response.status()))
+            Err(status_error(response.status(), format!("Delete failed: {}", response.status())))
         }
     }
 
@@ -254,31 +672,14 @@ impl HetznerWebDAVClient {
         debug!("Listing files in {}", path);
         
         let url = self.build_url(path)?;
-        
-        let propfind_body = r#"<?xml version="1.0" encoding="utf-8" ?>
-<D:propfind xmlns:D="DAV:">
-    <D:prop>
-        <D:resourcetype/>
-        <D:getcontentlength/>
-        <D:getlastmodified/>
-    </D:prop>
-</D:propfind>"#;
-
-        let response = self.client
-            .request(Method::from_bytes(b"PROPFIND")?, url)
-            .header(AUTHORIZATION, &self.auth_header)
-            .header("Depth", "1")
-            .header(CONTENT_TYPE, "text/xml; charset=utf-8")
-            .body(propfind_body)
-            .send()
-            .await?;
+        let response = self.propfind(url).await?;
 
         if response.status().is_success() {
-            let body = response.text().await?;
-            self.parse_propfind_response(&body)
+            let body = self.read_body(&format!("PROPFIND {}", path), response).await?;
+            self.parse_propfind_response(&String::from_utf8_lossy(&body))
         } else {
             error!("List files failed for {}: {}", path, response.status());
-            Err(anyhow!("List files failed: {}", response.status()))
+            Err(status_error(response.status(), format!("List files failed: {}", response.status())))
         }
     }
 
@@ -334,31 +735,14 @@ impl HetznerWebDAVClient {
         debug!("Listing directories in {}", path);
         
         let url = self.build_url(path)?;
-        
-        let propfind_body = r#"<?xml version="1.0" encoding="utf-8" ?>
-<D:propfind xmlns:D="DAV:">
-    <D:prop>
-        <D:resourcetype/>
-        <D:getcontentlength/>
-        <D:getlastmodified/>
-    </D:prop>
-</D:propfind>"#;
-
-        let response = self.client
-            .request(Method::from_bytes(b"PROPFIND")?, url)
-            .header(AUTHORIZATION, &self.auth_header)
-            .header("Depth", "1")
-            .header(CONTENT_TYPE, "text/xml; charset=utf-8")
-            .body(propfind_body)
-            .send()
-            .await?;
+        let response = self.propfind(url).await?;
 
         if response.status().is_success() {
-            let body = response.text().await?;
-            self.parse_propfind_directories(&body)
+            let body = self.read_body(&format!("PROPFIND {}", path), response).await?;
+            self.parse_propfind_directories(&String::from_utf8_lossy(&body))
         } else {
             error!("List directories failed for {}: {}", path, response.status());
-            Err(anyhow!("List directories failed: {}", response.status()))
+            Err(status_error(response.status(), format!("List directories failed: {}", response.status())))
         }
     }
     
@@ -411,22 +795,20 @@ impl HetznerWebDAVClient {
 
     pub async fn file_exists(&self, remote_path: &str) -> Result<bool> {
         let url = self.build_url(remote_path)?;
-        let response = self.client
+        let request = self.client
             .head(url)
-            .header(AUTHORIZATION, &self.auth_header)
-            .send()
-            .await?;
+            .header(AUTHORIZATION, &self.auth_header);
+        let response = self.send(&format!("HEAD {}", remote_path), request).await?;
 
         Ok(response.status().is_success())
     }
 
     pub async fn get_file_size(&self, remote_path: &str) -> Result<Option<u64>> {
         let url = self.build_url(remote_path)?;
-        let response = self.client
+        let request = self.client
             .head(url)
-            .header(AUTHORIZATION, &self.auth_header)
-            .send()
-            .await?;
+            .header(AUTHORIZATION, &self.auth_header);
+        let response = self.send(&format!("HEAD {}", remote_path), request).await?;
 
         if response.status().is_success() {
             if let Some(content_length) = response.headers().get("content-length") {
@@ -445,6 +827,10 @@ impl HetznerWebDAVClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::AsyncWriteExt;
+    use crate::test_server;
 
     #[tokio::test]
     async fn test_webdav_client_creation() {
@@ -459,6 +845,253 @@ mod tests {
         assert!(client.is_ok());
     }
 
+    /// Minimal WebDAV server that only understands MKCOL, answering like a
+    /// real server: 201 when created, 405 when it exists and 409 when the
+    /// parent is missing. Returns the endpoint and every status it sent
+    async fn mkcol_server() -> (String, Arc<Mutex<Vec<(String, u16)>>>) {
+        let (listener, endpoint) = test_server::bind().await;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let requests = log.clone();
+
+        tokio::spawn(async move {
+            let mut collections: HashSet<String> = HashSet::from(["".to_string()]);
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let Some((head, _)) = test_server::read_head(&mut socket).await else { continue };
+                let mut parts = head.split_whitespace();
+                let method = parts.next().unwrap_or("").to_string();
+                let path = parts.next().unwrap_or("").trim_end_matches('/').to_string();
+                let parent = path.rsplit_once('/').map(|(p, _)| p.to_string()).unwrap_or_default();
+
+                let status = if method != "MKCOL" {
+                    501
+                } else if collections.contains(&path) {
+                    405
+                } else if !collections.contains(&parent) {
+                    409
+                } else {
+                    collections.insert(path.clone());
+                    201
+                };
+                requests.lock().unwrap().push((path, status));
+
+                test_server::respond(&mut socket, status, &[]).await;
+            }
+        });
+
+        (endpoint, log)
+    }
+
+    fn client_for(endpoint: &str) -> HetznerWebDAVClient {
+        HetznerWebDAVClient::new(WebDAVConfig {
+            base_url: endpoint.to_string(),
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            base_path: "/".to_string(),
+        }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_directory_all_creates_parents() {
+        let (endpoint, log) = mkcol_server().await;
+        let client = client_for(&endpoint);
+
+        // A single MKCOL can't create a nested directory
+        assert!(client.create_directory("/skylock/backups/20240101_020000").await.is_err());
+
+        client.create_directory_all("/skylock/backups/20240101_020000/").await.unwrap();
+        let created: Vec<_> = log.lock().unwrap().iter()
+            .filter(|(_, status)| *status == 201)
+            .map(|(path, _)| path.clone())
+            .collect();
+        assert_eq!(created, vec!["/skylock", "/skylock/backups", "/skylock/backups/20240101_020000"]);
+
+        // Running again only hits existing directories
+        log.lock().unwrap().clear();
+        client.create_directory_all("/skylock/backups/20240101_020000").await.unwrap();
+        let statuses: Vec<_> = log.lock().unwrap().iter().map(|(_, status)| *status).collect();
+        assert_eq!(statuses, vec![405, 405, 405]);
+    }
+
+    /// WebDAV server answering PROPFIND with one file, after first sending
+    /// `throttled` 429 responses with `Retry-After: 1`. Returns the endpoint
+    /// and the arrival time of every request
+    async fn throttling_server(throttled: usize) -> (String, Arc<Mutex<Vec<std::time::Instant>>>) {
+        let (listener, endpoint) = test_server::bind().await;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let requests = log.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                if test_server::read_head(&mut socket).await.is_none() {
+                    continue;
+                }
+                let count = {
+                    let mut requests = requests.lock().unwrap();
+                    requests.push(std::time::Instant::now());
+                    requests.len()
+                };
+
+                let response = if count <= throttled {
+                    "HTTP/1.1 429 Too Many Requests\r\nretry-after: 1\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string()
+                } else {
+                    let body = "<D:multistatus xmlns:D=\"DAV:\">\n<D:response>\n<D:href>/skylock/file.txt</D:href>\n</D:response>\n</D:multistatus>\n";
+                    format!("HTTP/1.1 207 Multi-Status\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", body.len(), body)
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (endpoint, log)
+    }
+
+    #[tokio::test]
+    async fn test_list_waits_for_retry_after() {
+        let (endpoint, log) = throttling_server(1).await;
+        let client = client_for(&endpoint);
+
+        let files = client.list_files("/skylock").await.unwrap();
+        assert_eq!(files, vec!["skylock/file.txt"]);
+
+        let requests = log.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        let waited = requests[1] - requests[0];
+        assert!(waited >= std::time::Duration::from_secs(1), "retried after {:?}", waited);
+    }
+
+    #[tokio::test]
+    async fn test_delete_waits_for_retry_after() {
+        let (endpoint, log) = throttling_server(2).await;
+        let client = client_for(&endpoint).with_delete_rate_limit(Some(50.0));
+
+        client.delete_file("/skylock/file.txt").await.unwrap();
+
+        // Both 429s were waited out before the delete went through
+        let requests = log.lock().unwrap().clone();
+        assert_eq!(requests.len(), 3);
+        for pair in requests.windows(2) {
+            let waited = pair[1] - pair[0];
+            assert!(waited >= std::time::Duration::from_secs(1), "retried after {:?}", waited);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_requests_stay_under_rate_limit() {
+        let (endpoint, log) = throttling_server(0).await;
+        let client = client_for(&endpoint).with_list_rate_limit(Some(10.0));
+
+        for _ in 0..6 {
+            client.list_directories("/skylock").await.unwrap();
+        }
+
+        // Requests are at least 100ms apart, within timer jitter
+        let requests = log.lock().unwrap().clone();
+        assert_eq!(requests.len(), 6);
+        for pair in requests.windows(2) {
+            let gap = pair[1] - pair[0];
+            assert!(gap >= std::time::Duration::from_millis(90), "requests {:?} apart", gap);
+        }
+    }
+
+    /// Server that stalls: listings never get a response and downloads stop
+    /// after the headers and the first few body bytes. Connections stay open
+    async fn stalling_server() -> String {
+        let (listener, endpoint) = test_server::bind().await;
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let Some((head, _)) = test_server::read_head(&mut socket).await else { return };
+                    if head.starts_with("GET") {
+                        let _ = socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1000\r\n\r\npartial").await;
+                    }
+                    tokio::time::sleep(Duration::from_secs(3600)).await;
+                    drop(socket);
+                });
+            }
+        });
+
+        endpoint
+    }
+
+    #[tokio::test]
+    async fn test_stalled_requests_time_out() {
+        let endpoint = stalling_server().await;
+        let read_timeout = Duration::from_millis(300);
+        let client = client_for(&endpoint).with_timeouts(DEFAULT_CONNECT_TIMEOUT, read_timeout);
+
+        let started = std::time::Instant::now();
+        let err = client.list_files("/skylock").await.unwrap_err();
+        let waited = started.elapsed();
+        assert!(err.downcast_ref::<TimeoutError>().is_some(), "{}", err);
+        assert!(waited >= read_timeout && waited < Duration::from_secs(2), "timed out after {:?}", waited);
+
+        // A body that stops arriving part way is a stall too
+        let local = tempfile::NamedTempFile::new().unwrap();
+        let started = std::time::Instant::now();
+        let err = client.download_file("/skylock/file.bin", local.path()).await.unwrap_err();
+        let waited = started.elapsed();
+        assert!(err.downcast_ref::<TimeoutError>().is_some(), "{}", err);
+        assert!(waited >= read_timeout && waited < Duration::from_secs(2), "timed out after {:?}", waited);
+
+        // Callers see it as a network timeout, which is retried
+        assert!(matches!(
+            crate::storage_error(err),
+            skylock_core::SkylockError::Storage(skylock_core::StorageErrorType::NetworkTimeout)
+        ));
+    }
+
+    /// Server that accepts a PUT body slowly, pausing after every MiB, and
+    /// answers 201 with the number of bytes it received
+    async fn slow_upload_server() -> (String, Arc<Mutex<Vec<usize>>>) {
+        let (listener, endpoint) = test_server::bind().await;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let received = log.clone();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let (head, body) = test_server::read_head(&mut socket).await.unwrap();
+            let length = test_server::content_length(&head).unwrap() as usize;
+            let mut total = body.len();
+            let mut chunk = vec![0u8; 64 * 1024];
+            let mut next_pause = 1024 * 1024;
+            while total < length {
+                let n = socket.read(&mut chunk).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                total += n;
+                if total >= next_pause {
+                    tokio::time::sleep(Duration::from_millis(25)).await;
+                    next_pause += 1024 * 1024;
+                }
+            }
+            received.lock().unwrap().push(total);
+            let _ = socket.write_all(b"HTTP/1.1 201 Created\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+        });
+
+        (endpoint, log)
+    }
+
+    #[tokio::test]
+    async fn test_slow_upload_outlasts_read_timeout_while_progressing() {
+        let (endpoint, log) = slow_upload_server().await;
+        let read_timeout = Duration::from_millis(400);
+        let client = client_for(&endpoint).with_timeouts(DEFAULT_CONNECT_TIMEOUT, read_timeout);
+
+        let local = tempfile::NamedTempFile::new().unwrap();
+        let size = 48 * 1024 * 1024;
+        std::fs::write(local.path(), vec![7u8; size]).unwrap();
+
+        // Takes well over the read timeout in total, but bytes keep moving
+        let started = std::time::Instant::now();
+        client.upload_file(local.path(), "/skylock/large.bin").await.unwrap();
+        assert!(started.elapsed() > read_timeout * 2, "upload took {:?}", started.elapsed());
+        assert_eq!(*log.lock().unwrap(), vec![size]);
+    }
+
     #[test]
     fn test_url_building() {
         let config = WebDAVConfig {
@@ -0,0 +1,163 @@
+//! SFTP backend against a local OpenSSH server
+//!
+//! Starts `sshd` on a free port with a throwaway host key, authorizing a
+//! freshly generated Ed25519 key. Skipped when sshd isn't installed.
+
+use skylock_core::storage::StorageBackend;
+use skylock_hetzner::{generate_ed25519_keypair, SecureSftpConfig, SftpBackend};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Running sshd, stopped on drop
+struct Sshd {
+    child: Child,
+    port: u16,
+}
+
+impl Drop for Sshd {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn sshd_binary() -> Option<PathBuf> {
+    ["/usr/sbin/sshd", "/usr/bin/sshd", "/usr/local/sbin/sshd"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
+
+fn start_sshd(sshd: &Path, dir: &Path, client_key: &Path) -> Sshd {
+    let host_key = dir.join("host_key");
+    generate_ed25519_keypair(&host_key, None).unwrap();
+    std::fs::copy(client_key.with_extension("pub"), dir.join("authorized_keys")).unwrap();
+
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let config = dir.join("sshd_config");
+    std::fs::write(&config, format!(
+        "Port {port}\n\
+         ListenAddress 127.0.0.1\n\
+         HostKey {host_key}\n\
+         AuthorizedKeysFile {authorized_keys}\n\
+         PidFile {pid}\n\
+         PubkeyAuthentication yes\n\
+         PasswordAuthentication no\n\
+         KbdInteractiveAuthentication no\n\
+         StrictModes no\n\
+         UsePAM no\n\
+         Subsystem sftp internal-sftp\n",
+        host_key = host_key.display(),
+        authorized_keys = dir.join("authorized_keys").display(),
+        pid = dir.join("sshd.pid").display(),
+    )).unwrap();
+
+    let child = Command::new(sshd)
+        .arg("-D")
+        .arg("-e")
+        .arg("-f")
+        .arg(&config)
+        .spawn()
+        .unwrap();
+    let server = Sshd { child, port };
+
+    for _ in 0..50 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return server;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    panic!("sshd did not start on port {}", port);
+}
+
+fn current_user() -> String {
+    std::env::var("USER").ok()
+        .or_else(|| Command::new("id").arg("-un").output().ok()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string()))
+        .unwrap()
+}
+
+async fn download(backend: &SftpBackend, path: &str, dir: &Path) -> Vec<u8> {
+    let local = dir.join("download");
+    let file = tokio::fs::File::create(&local).await.unwrap();
+    backend.download(&PathBuf::from(path), Box::pin(file), None).await.unwrap();
+    std::fs::read(&local).unwrap()
+}
+
+#[tokio::test]
+async fn test_upload_list_download_over_sftp() {
+    let Some(sshd) = sshd_binary() else {
+        eprintln!("skipping: sshd is not installed");
+        return;
+    };
+
+    let dir = TempDir::new().unwrap();
+    let client_key = dir.path().join("client_key");
+    generate_ed25519_keypair(&client_key, None).unwrap();
+    let server = start_sshd(&sshd, dir.path(), &client_key);
+
+    let root = dir.path().join("storage");
+    std::fs::create_dir(&root).unwrap();
+    let known_hosts = dir.path().join("known_hosts");
+    let config = SecureSftpConfig {
+        host: "127.0.0.1".to_string(),
+        port: server.port,
+        username: current_user(),
+        private_key_path: client_key,
+        key_passphrase: None,
+        known_hosts_path: Some(known_hosts.clone()),
+        metadata_key: None,
+    };
+    let backend = SftpBackend::new(config.clone()).unwrap().with_root(root.clone());
+
+    // Uploads create missing directories and span several transfer chunks
+    let blob = b"encrypted chunk ".repeat(20_000);
+    backend.upload(Box::pin(std::io::Cursor::new(blob.clone())), &PathBuf::from("/skylock/chunks/ab/blob"), None)
+        .await.unwrap();
+    backend.upload(Box::pin(std::io::Cursor::new(b"{}".to_vec())), &PathBuf::from("/skylock/manifest.json"), None)
+        .await.unwrap();
+    assert_eq!(std::fs::read(root.join("skylock/chunks/ab/blob")).unwrap(), blob);
+
+    let listed = |recursive| {
+        let backend = &backend;
+        async move {
+            let mut items: Vec<_> = backend.list(Some(&PathBuf::from("/skylock")), recursive).await.unwrap()
+                .into_iter()
+                .map(|item| (item.path, item.size))
+                .collect();
+            items.sort();
+            items
+        }
+    };
+    assert_eq!(listed(true).await, vec![
+        (PathBuf::from("/skylock/chunks/ab/blob"), blob.len() as u64),
+        (PathBuf::from("/skylock/manifest.json"), 2),
+    ]);
+    assert_eq!(listed(false).await, vec![(PathBuf::from("/skylock/manifest.json"), 2)]);
+
+    assert_eq!(download(&backend, "/skylock/chunks/ab/blob", dir.path()).await, blob);
+    let metadata = backend.get_metadata(&PathBuf::from("/skylock/manifest.json")).await.unwrap();
+    assert_eq!(metadata.map(|item| item.size), Some(2));
+    assert!(backend.get_metadata(&PathBuf::from("/skylock/missing")).await.unwrap().is_none());
+
+    // The host key was recorded on first connect; a different one is refused
+    let recorded = std::fs::read_to_string(&known_hosts).unwrap();
+    assert!(recorded.contains(&format!("[127.0.0.1]:{}", server.port)), "{}", recorded);
+
+    let other_key = dir.path().join("other_host_key");
+    generate_ed25519_keypair(&other_key, None).unwrap();
+    let other_public = std::fs::read_to_string(other_key.with_extension("pub")).unwrap();
+    let mut fields = other_public.split_whitespace();
+    std::fs::write(&known_hosts, format!(
+        "[127.0.0.1]:{} {} {}\n",
+        server.port,
+        fields.next().unwrap(),
+        fields.next().unwrap(),
+    )).unwrap();
+
+    let spoofed = SftpBackend::new(config).unwrap().with_root(root);
+    assert!(spoofed.get_metadata(&PathBuf::from("/skylock/manifest.json")).await.is_err());
+}
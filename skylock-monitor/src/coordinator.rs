@@ -1,6 +1,6 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use skylock_core::{Result, SkylockError, notifications::NotificationManager};
+use skylock_core::{Result, SkylockError, UiConfig, notifications::NotificationManager};
 use crate::{FileMonitor, SyncthingClient, HetznerClient};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
@@ -11,6 +11,7 @@ pub struct SyncCoordinator {
     syncthing: Arc<SyncthingClient>,
     hetzner: Arc<HetznerClient>,
     notifications: NotificationManager,
+    ui: UiConfig,
     sync_state: Arc<RwLock<HashMap<PathBuf, SyncState>>>,
 }
 
@@ -28,12 +29,14 @@ impl SyncCoordinator {
         syncthing: SyncthingClient,
         hetzner: HetznerClient,
         notifications: NotificationManager,
+        ui: UiConfig,
     ) -> Self {
         Self {
             file_monitor: Arc::new(file_monitor),
             syncthing: Arc::new(syncthing),
             hetzner: Arc::new(hetzner),
             notifications,
+            ui,
             sync_state: Arc::new(RwLock::new(HashMap::new())),
         }
     }
@@ -129,9 +132,8 @@ impl SyncCoordinator {
     }
 
     pub async fn handle_deletion(&self, path: &Path) -> Result<()> {
-        // Show deletion prompt
-        use skylock_ui::show_deletion_prompt;
-        match show_deletion_prompt(path)? {
+        // Apply the configured default, or prompt if the settings ask for it
+        match skylock_ui::resolve_deletion(path, &self.ui)? {
             skylock_ui::DeletionChoice::DeleteEverywhere => {
                 // Delete from Syncthing
                 self.syncthing.delete_file(path).await?;
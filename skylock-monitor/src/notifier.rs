@@ -60,13 +60,8 @@ pub struct Notification {
     pub metadata: serde_json::Value,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
-pub enum NotificationSeverity {
-    Info,
-    Warning,
-    Error,
-    Critical,
-}
+/// Shared with the notification routes of the core app's config
+pub use skylock_core::NotificationSeverity;
 
 pub struct NotificationManager {
     config: NotificationConfig,
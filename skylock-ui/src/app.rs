@@ -539,6 +539,12 @@ impl SkylockApp {
                     username: username.clone(),
                     password: password.clone(),
                     encryption_key: encryption_key.clone(),
+                    max_list_requests_per_second: None,
+                    max_delete_requests_per_second: None,
+                    connect_timeout_secs: 30,
+                    read_timeout_secs: 120,
+                    protocol: None,
+                    sftp: None,
                 },
                 backup: skylock_core::BackupConfig {
                     vss_enabled: false,
@@ -546,11 +552,43 @@ impl SkylockApp {
                     retention_days: 30,
                     backup_paths: backup_paths.clone(),
                     max_speed_limit: None,
+                    encryption_algorithm: None,
+                    encrypt_file_names: false,
+                    max_chain_length: None,
+                    canonical_manifests: false,
+                    compress_manifests: false,
+                    compression_algorithm: None,
+                    compression_profiles: Default::default(),
+                    blob_shard_depth: 2,
+                    max_concurrent_uploads: None,
+                    mirrors: Vec::new(),
+                    temp_dir: None,
+                    spill_threshold: None,
+                    block_cache_size: None,
+                    restore_dir: None,
+                    stream_threshold: None,
+                    max_file_size: None,
+                    skip_oversized_files: false,
+                    trash_days: None,
+                    list_concurrency: None,
+                    delete_concurrency: None,
+                    require_signed_manifests: false,
+                    manifest_public_key: None,
+                    aead_frame_size: None,
+                    hash_algorithm: None,
+                    key_exchange: None,
+                    retry: Default::default(),
                 },
                 ui: skylock_core::UiConfig {
                     always_prompt_deletions: false,
                     notification_enabled: true,
+                    deletion_default: None,
                 },
+                notifications: skylock_core::NotificationsConfig::default(),
+                metrics: skylock_core::MetricsConfig::default(),
+                credentials: skylock_core::CredentialsConfig::default(),
+                logging: skylock_core::LoggingConfig::default(),
+                scrub: skylock_core::ScrubConfig::default(),
                 data_dir: dirs::data_dir()
                     .unwrap_or_else(|| PathBuf::from("."))
                     .join("skylock"),
@@ -597,12 +635,20 @@ impl SkylockApp {
                 progress: 0.2,
             });
             
-            let direct_backup = skylock_backup::DirectUploadBackup::new(
+            let direct_backup = match skylock_backup::DirectUploadBackup::new(
                 core_config,
                 hetzner_client,
                 encryption,
                 None, // No bandwidth limit
-            );
+            ) {
+                Ok(backup) => backup,
+                Err(e) => {
+                    let _ = tx.send(BackupProgressEvent::BackupFailed {
+                        error: format!("Failed to open the backup: {}", e),
+                    });
+                    return;
+                }
+            };
             
             // Execute the backup with full encryption
             let _ = tx.send(BackupProgressEvent::FileUploading {
@@ -0,0 +1,114 @@
+//! Detection of non-interactive sessions and prompts that never block on them
+//!
+//! Cron jobs, CI runners and daemons have no one to answer a prompt, and a
+//! `read_line` on an inherited stdin can wait forever. Prompts go through
+//! [`prompt_line`], which refuses to read when the session is not interactive
+//! and treats a closed stdin as an error instead of an empty answer.
+
+use skylock_core::{Result, SkylockError};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Environment variable that disables all prompts when set to a non-empty
+/// value other than `0` or `false`
+pub const NONINTERACTIVE_ENV: &str = "SKYLOCK_NONINTERACTIVE";
+
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Record that the user passed `--yes`: confirmations are accepted and no
+/// prompt is shown for the rest of the process
+pub fn set_assume_yes(yes: bool) {
+    ASSUME_YES.store(yes, Ordering::Relaxed);
+}
+
+/// Whether `--yes` was given
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::Relaxed)
+}
+
+fn env_noninteractive() -> bool {
+    std::env::var(NONINTERACTIVE_ENV)
+        .map(|v| !v.is_empty() && v != "0" && !v.eq_ignore_ascii_case("false"))
+        .unwrap_or(false)
+}
+
+/// Whether prompts were turned off with `--yes` or the environment
+pub fn prompts_disabled() -> bool {
+    assume_yes() || env_noninteractive()
+}
+
+/// Whether it is safe to prompt on stdin
+pub fn is_interactive() -> bool {
+    !prompts_disabled() && io::stdin().is_terminal()
+}
+
+/// Error for a prompt that cannot be answered, naming what to do instead
+pub fn noninteractive_error(what: &str, hint: &str) -> SkylockError {
+    SkylockError::Config(format!(
+        "{} requires input but no terminal is available (stdin is not a TTY, --yes was given or {} is set); {}",
+        what, NONINTERACTIVE_ENV, hint
+    ))
+}
+
+/// Print `prompt` and read one trimmed line from stdin
+///
+/// Fails immediately with `hint` in the message when the session is not
+/// interactive or stdin is closed.
+pub fn prompt_line(prompt: &str, what: &str, hint: &str) -> Result<String> {
+    if !is_interactive() {
+        return Err(noninteractive_error(what, hint));
+    }
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    read_answer(&mut io::stdin().lock(), what, hint)
+}
+
+/// Read one trimmed line from `reader`, treating end of input as an error
+pub fn read_answer<R: BufRead>(reader: &mut R, what: &str, hint: &str) -> Result<String> {
+    let mut input = String::new();
+    if reader.read_line(&mut input)? == 0 {
+        return Err(SkylockError::Config(format!(
+            "{} requires input but stdin was closed; {}",
+            what, hint
+        )));
+    }
+    Ok(input.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_closed_stdin_fails_fast() {
+        let start = Instant::now();
+        let mut closed = io::empty();
+        let err = read_answer(&mut closed, "Hetzner username", "pass --username").unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let message = err.to_string();
+        assert!(message.contains("Hetzner username"));
+        assert!(message.contains("stdin was closed"));
+        assert!(message.contains("pass --username"));
+    }
+
+    #[test]
+    fn test_reads_trimmed_answer() {
+        let mut input = &b"  yes \nignored\n"[..];
+        assert_eq!(read_answer(&mut input, "confirmation", "").unwrap(), "yes");
+    }
+
+    #[test]
+    fn test_noninteractive_prompt_does_not_read() {
+        // --yes forces the non-interactive path whatever stdin is attached to
+        set_assume_yes(true);
+        let start = Instant::now();
+        // Not reset afterwards: tests run in parallel and none rely on prompting
+        let err = prompt_line("Password: ", "Hetzner password", "set SKYLOCK_HETZNER_PASSWORD").unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(err.to_string().contains("no terminal is available"));
+        assert!(err.to_string().contains("set SKYLOCK_HETZNER_PASSWORD"));
+    }
+}
@@ -4,7 +4,9 @@
 //! built with egui for cross-platform desktop support.
 
 use std::path::Path;
-use skylock_core::{Result, SkylockError};
+use skylock_core::{Result, SkylockError, UiConfig};
+
+pub mod interactive;
 
 // GUI application module
 #[cfg(feature = "gui")]
@@ -13,13 +15,29 @@ pub mod app;
 #[cfg(feature = "gui")]
 pub use app::{run_gui, SkylockApp, AppState, BackupInfo, FileEntry, View, StatusMessage, StatusLevel, garble_text};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeletionChoice {
     DeleteEverywhere,
     DeleteLocalOnly,
     Cancel,
 }
 
+impl std::str::FromStr for DeletionChoice {
+    type Err = SkylockError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "everywhere" => Ok(DeletionChoice::DeleteEverywhere),
+            "local" => Ok(DeletionChoice::DeleteLocalOnly),
+            "cancel" => Ok(DeletionChoice::Cancel),
+            other => Err(SkylockError::Config(format!(
+                "Unknown deletion action '{}' (expected everywhere, local or cancel)",
+                other
+            ))),
+        }
+    }
+}
+
 // Windows implementation using native-windows-gui (for dialogs only)
 #[cfg(windows)]
 mod windows_impl {
@@ -31,6 +49,12 @@ mod windows_impl {
     }
 
     pub fn show_deletion_prompt(file_path: &Path) -> Result<DeletionChoice> {
+        if crate::interactive::prompts_disabled() {
+            return Err(crate::interactive::noninteractive_error(
+                &format!("Deleting '{}'", file_path.display()),
+                DELETION_HINT,
+            ));
+        }
         init_gui()?;
 
         let params = nwg::MessageParams {
@@ -69,19 +93,19 @@ mod windows_impl {
 #[cfg(unix)]
 mod unix_impl {
     use super::*;
-    use std::io::{self, Write};
+    use crate::interactive;
 
     pub fn show_deletion_prompt(file_path: &Path) -> Result<DeletionChoice> {
-        print!(
-            "Delete '{}'?\n[y] Delete everywhere [n] Delete locally [c] Cancel: ",
-            file_path.display()
-        );
-        io::stdout().flush().map_err(|e| SkylockError::Other(e.to_string()))?;
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).map_err(|e| SkylockError::Other(e.to_string()))?;
+        let input = interactive::prompt_line(
+            &format!(
+                "Delete '{}'?\n[y] Delete everywhere [n] Delete locally [c] Cancel: ",
+                file_path.display()
+            ),
+            &format!("Deleting '{}'", file_path.display()),
+            DELETION_HINT,
+        )?;
         
-        match input.trim().to_lowercase().as_str() {
+        match input.to_lowercase().as_str() {
             "y" | "yes" => Ok(DeletionChoice::DeleteEverywhere),
             "n" | "no" => Ok(DeletionChoice::DeleteLocalOnly),
             _ => Ok(DeletionChoice::Cancel),
@@ -99,6 +123,26 @@ mod unix_impl {
     }
 }
 
+/// How to avoid the deletion prompt when nobody can answer it
+const DELETION_HINT: &str =
+    "set ui.always_prompt_deletions = false and ui.deletion_default to \"local\", \"everywhere\" or \"cancel\"";
+
+/// Decide what to do with a deleted file according to the UI settings
+///
+/// With `always_prompt_deletions` off, `deletion_default` (local-only unless
+/// configured) is applied without asking. Otherwise the user is prompted, and
+/// a session that cannot be prompted fails with an error instead of blocking.
+pub fn resolve_deletion(file_path: &Path, ui: &UiConfig) -> Result<DeletionChoice> {
+    let default = match ui.deletion_default.as_deref() {
+        Some(action) => action.parse()?,
+        None => DeletionChoice::DeleteLocalOnly,
+    };
+    if !ui.always_prompt_deletions {
+        return Ok(default);
+    }
+    show_deletion_prompt(file_path)
+}
+
 // Public API that delegates to platform-specific implementations
 pub fn show_deletion_prompt(file_path: &Path) -> Result<DeletionChoice> {
     #[cfg(windows)]
@@ -141,3 +185,40 @@ pub fn show_progress(title: &str, message: &str) -> Result<()> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn ui(always_prompt_deletions: bool, deletion_default: Option<&str>) -> UiConfig {
+        UiConfig {
+            always_prompt_deletions,
+            notification_enabled: false,
+            deletion_default: deletion_default.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_deletion_default_applied_without_prompt() {
+        let path = Path::new("/data/report.pdf");
+        assert_eq!(resolve_deletion(path, &ui(false, None)).unwrap(), DeletionChoice::DeleteLocalOnly);
+        assert_eq!(
+            resolve_deletion(path, &ui(false, Some("everywhere"))).unwrap(),
+            DeletionChoice::DeleteEverywhere
+        );
+        assert!(resolve_deletion(path, &ui(false, Some("sometimes"))).is_err());
+    }
+
+    #[test]
+    fn test_required_prompt_fails_fast_when_noninteractive() {
+        interactive::set_assume_yes(true);
+        let start = Instant::now();
+        let err = resolve_deletion(Path::new("/data/report.pdf"), &ui(true, Some("cancel"))).unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let message = err.to_string();
+        assert!(message.contains("/data/report.pdf"));
+        assert!(message.contains("always_prompt_deletions"));
+    }
+}
@@ -0,0 +1,66 @@
+//! Audit trail for security-relevant CLI operations
+//!
+//! Records are appended to `audit.log` in the data directory using the
+//! hash-chained [`AuditLog`] from skylock-core. A failure to write the audit
+//! log is reported as a warning and never aborts the operation itself.
+
+use skylock_core::audit::{AuditLog, AuditOperation, EventOutcome};
+use skylock_core::Config;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// File name of the audit log inside `data_dir`
+pub const AUDIT_LOG_NAME: &str = "audit.log";
+
+/// Handle for writing audit records on behalf of the local user
+pub struct AuditTrail {
+    log_path: PathBuf,
+    actor: String,
+    storage_account: String,
+}
+
+impl AuditTrail {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            log_path: config.data_dir.join(AUDIT_LOG_NAME),
+            actor: local_user(),
+            storage_account: format!("hetzner:{}", config.hetzner.username),
+        }
+    }
+
+    /// Append a record to the audit log
+    pub fn record(&self, operation: AuditOperation, target: &str, outcome: EventOutcome) {
+        let result = AuditLog::open(&self.log_path)
+            .and_then(|log| log.append(&self.actor, operation, target, outcome));
+        if let Err(e) = result {
+            warn!("Failed to write audit record ({} {}): {}", operation, target, e);
+        }
+    }
+
+    /// Append a record whose outcome follows `result`
+    pub fn record_result<T, E>(&self, operation: AuditOperation, target: &str, result: &Result<T, E>) {
+        let outcome = if result.is_ok() { EventOutcome::Success } else { EventOutcome::Failure };
+        self.record(operation, target, outcome);
+    }
+
+    /// Record that the storage credentials and the encryption key were read
+    /// from the configuration for `command`
+    pub fn record_config_secrets(&self, command: &str) {
+        self.record(AuditOperation::CredentialRead, &format!("{} ({})", self.storage_account, command), EventOutcome::Success);
+        self.record(AuditOperation::KeyAccess, &format!("encryption key ({})", command), EventOutcome::Success);
+    }
+}
+
+/// Whether restoring into `path` writes over existing local files
+pub fn would_overwrite(path: &Path) -> bool {
+    match std::fs::read_dir(path) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(_) => path.is_file(),
+    }
+}
+
+fn local_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
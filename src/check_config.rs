@@ -0,0 +1,477 @@
+//! `skylock check-config`: field-by-field validation of the configuration
+//!
+//! Where `Config::validate` only rejects a few empty fields, this checks
+//! every value other commands would otherwise trip over later: the cron
+//! schedule, backup paths, the endpoint URL, retention and concurrency
+//! numbers, the bandwidth limit and temp file settings, cipher names, log
+//! settings, notification routes and placeholder credentials. Each field is
+//! reported as pass/warn/fail like `doctor`. Exclude patterns are only given
+//! per run, so their check is reported as skipped.
+
+use anyhow::Result;
+use colored::*;
+use skylock_core::{Config, NotificationChannel};
+use std::path::{Path, PathBuf};
+
+use crate::doctor::{self, CheckResult, CheckStatus, DoctorReport};
+use crate::output::{self, ConfigCheckFailed, OutputFormat};
+use crate::scheduler;
+
+/// Retention beyond this many days gets a warning
+const MAX_SANE_RETENTION_DAYS: u32 = 3650;
+
+/// Check every field of a loaded configuration
+pub fn check_fields(config: &Config) -> Vec<CheckResult> {
+    let mut checks = vec![
+        check_endpoint(&config.hetzner.endpoint),
+        check_username(&config.hetzner.username),
+        check_encryption_key(&config.hetzner.encryption_key),
+    ];
+
+    if let Some(ref protocol) = config.hetzner.protocol {
+        checks.push(match protocol.parse::<skylock_hetzner::StorageProtocol>() {
+            Err(e) => CheckResult::fail("hetzner.protocol", e.to_string(), "Use \"webdav\" or \"sftp\""),
+            Ok(skylock_hetzner::StorageProtocol::Sftp) if config.hetzner.sftp.is_none() => CheckResult::fail(
+                "hetzner.protocol", "\"sftp\" needs a [hetzner.sftp] section",
+                "Add [hetzner.sftp] with private_key (see `config.sample.toml`)"),
+            Ok(_) => CheckResult::pass("hetzner.protocol", protocol.to_ascii_lowercase()),
+        });
+    }
+
+    checks.push(match scheduler::validate_cron_expression(&config.backup.schedule) {
+        Ok(()) => CheckResult::pass("backup.schedule", config.backup.schedule.clone()),
+        Err(e) => CheckResult::fail("backup.schedule", e.to_string(),
+            "Use a 6-field expression such as \"0 0 2 * * *\" (see `skylock schedule --presets`)"),
+    });
+
+    checks.extend(check_backup_paths(&config.backup.backup_paths));
+
+    // Exclusions are only given per run, as `--exclude` globs
+    checks.push(CheckResult::warn("backup.exclude_patterns",
+        "Skipped: the configuration has no exclude patterns to check",
+        "Pass --exclude <GLOB> to `skylock backup`; globs are matched as given"));
+
+    let retention = config.backup.retention_days;
+    checks.push(if retention == 0 {
+        CheckResult::fail("backup.retention_days", "0 days would delete every backup at cleanup",
+            "Keep backups for at least 1 day")
+    } else if retention > MAX_SANE_RETENTION_DAYS {
+        CheckResult::warn("backup.retention_days", format!("{} days is over 10 years", retention),
+            "Check the value is in days")
+    } else {
+        CheckResult::pass("backup.retention_days", format!("{} days", retention))
+    });
+
+    if config.backup.max_chain_length == Some(0) {
+        checks.push(CheckResult::fail("backup.max_chain_length", "0 leaves no room for incremental backups",
+            "Use at least 1, or remove the setting"));
+    }
+
+    if let Some(concurrency) = config.backup.max_concurrent_uploads {
+        let max = skylock_backup::parallelism::MAX_CONCURRENCY;
+        checks.push(if concurrency == 0 {
+            CheckResult::fail("backup.max_concurrent_uploads", "must be at least 1", "Use a value such as 4")
+        } else if concurrency > max {
+            CheckResult::warn("backup.max_concurrent_uploads",
+                format!("{} is above the maximum, {} will be used", concurrency, max),
+                format!("Use a value between 1 and {}", max))
+        } else {
+            CheckResult::pass("backup.max_concurrent_uploads", concurrency.to_string())
+        });
+    }
+
+    if let Some(ref limit) = config.backup.max_speed_limit {
+        checks.push(match skylock_backup::parse_bandwidth_limit(limit) {
+            Ok(0) => CheckResult::pass("backup.max_speed_limit", format!("{} (unlimited)", limit)),
+            Ok(bytes) => CheckResult::pass("backup.max_speed_limit",
+                format!("{} ({}/s)", limit, skylock_core::ByteSize(bytes))),
+            Err(e) => CheckResult::fail("backup.max_speed_limit", e.to_string(),
+                "Use a value such as \"1.5M\", \"500K\" or \"0\" for unlimited"),
+        });
+    }
+
+    if let Some(ref threshold) = config.backup.spill_threshold {
+        checks.push(match config.backup.spill_threshold_bytes() {
+            Ok(bytes) => CheckResult::pass("backup.spill_threshold",
+                format!("{} ({})", threshold, skylock_core::ByteSize(bytes))),
+            Err(e) => CheckResult::fail("backup.spill_threshold", e.to_string(),
+                "Use a size such as \"16M\", or \"0\" to always use temp files"),
+        });
+    }
+
+    if let Some(ref size) = config.backup.block_cache_size {
+        checks.push(match config.backup.block_cache_size_bytes() {
+            Ok(bytes) => CheckResult::pass("backup.block_cache_size",
+                format!("{} ({})", size, skylock_core::ByteSize(bytes))),
+            Err(e) => CheckResult::fail("backup.block_cache_size", e.to_string(),
+                "Use a size such as \"256M\", or \"0\" to disable the cache"),
+        });
+    }
+
+    if let Some(ref threshold) = config.backup.stream_threshold {
+        checks.push(match config.backup.stream_threshold_bytes() {
+            Ok(bytes) => CheckResult::pass("backup.stream_threshold",
+                format!("{} ({})", threshold, skylock_core::ByteSize(bytes.unwrap_or_default()))),
+            Err(e) => CheckResult::fail("backup.stream_threshold", e.to_string(),
+                "Use a size such as \"1G\", or remove it to read files into memory"),
+        });
+    }
+
+    if let Some(ref max) = config.backup.max_file_size {
+        let action = if config.backup.skip_oversized_files { "skipped" } else { "streamed" };
+        checks.push(match config.backup.max_file_size_bytes() {
+            Ok(bytes) => CheckResult::pass("backup.max_file_size",
+                format!("{} ({}), larger files are {}", max, skylock_core::ByteSize(bytes.unwrap_or_default()), action)),
+            Err(e) => CheckResult::fail("backup.max_file_size", e.to_string(),
+                "Use a size such as \"50G\", or remove it to put no limit on file size"),
+        });
+    }
+
+    if let Some(ref dir) = config.backup.temp_dir {
+        checks.push(if dir.is_dir() {
+            CheckResult::pass("backup.temp_dir", dir.display().to_string())
+        } else {
+            CheckResult::fail("backup.temp_dir", format!("{} does not exist", dir.display()),
+                "Create the directory or remove the setting to use the system temp directory")
+        });
+    }
+
+    if let Some(ref algorithm) = config.backup.encryption_algorithm {
+        checks.push(match algorithm.parse::<skylock_backup::AeadAlgorithm>() {
+            Ok(_) => CheckResult::pass("backup.encryption_algorithm", algorithm.clone()),
+            Err(e) => CheckResult::fail("backup.encryption_algorithm", e.to_string(),
+                "Use \"aes-256-gcm\" or \"chacha20-poly1305\", or remove the setting"),
+        });
+    }
+
+    if let Some(ref algorithm) = config.backup.hash_algorithm {
+        checks.push(match algorithm.parse::<skylock_backup::HashAlgorithm>() {
+            Ok(parsed) => CheckResult::pass("backup.hash_algorithm", parsed.to_string()),
+            Err(e) => CheckResult::fail("backup.hash_algorithm", e.to_string(),
+                "Use \"sha256\" or \"blake3\", or remove the setting"),
+        });
+    }
+
+    if let Some(ref mode) = config.backup.key_exchange {
+        checks.push(match mode.parse::<skylock_backup::KeyExchangeMode>() {
+            Ok(parsed) => CheckResult::pass("backup.key_exchange", parsed.to_string()),
+            Err(e) => CheckResult::fail("backup.key_exchange", e.to_string(),
+                "Use \"classic\" or \"hybrid\", or remove the setting"),
+        });
+    }
+
+    if let Some(ref algorithm) = config.backup.compression_algorithm {
+        checks.push(match algorithm.parse::<skylock_backup::CompressionAlgorithm>() {
+            Ok(parsed) => CheckResult::pass("backup.compression_algorithm", parsed.to_string()),
+            Err(e) => CheckResult::fail("backup.compression_algorithm", e.to_string(),
+                "Use \"none\", \"lz4\", \"zstd\", \"brotli\" or \"gzip\", or remove the setting"),
+        });
+    }
+
+    if config.scrub.enabled {
+        checks.push(match scheduler::validate_cron_expression(&config.scrub.schedule) {
+            Ok(()) => CheckResult::pass("scrub.schedule", config.scrub.schedule.clone()),
+            Err(e) => CheckResult::fail("scrub.schedule", e.to_string(),
+                "Use a 6-field expression such as \"0 30 4 * * *\" (see `skylock schedule --presets`)"),
+        });
+        checks.push(if config.scrub.cycles == 0 {
+            CheckResult::fail("scrub.cycles", "must be at least 1", "Use a value such as 30")
+        } else {
+            CheckResult::pass("scrub.cycles", format!("every blob checked once per {} runs", config.scrub.cycles))
+        });
+    }
+
+    if !config.notifications.routes.is_empty() {
+        let routed = |channel| config.notifications.routes.values().any(|route| route.channels.contains(&channel));
+        checks.push(if routed(NotificationChannel::Email) && config.notifications.email.is_none() {
+            CheckResult::fail("notifications.routes", "email is routed to but there is no [notifications.email]",
+                "Add [notifications.email] with smtp_host, from and to, or remove \"email\" from the routes")
+        } else if routed(NotificationChannel::Webhook) && config.notifications.webhook_url.is_none() {
+            CheckResult::warn("notifications.routes", "webhook is routed to but webhook_url is not set",
+                "Set notifications.webhook_url, or remove \"webhook\" from the routes")
+        } else {
+            CheckResult::pass("notifications.routes", format!("{} event types routed", config.notifications.routes.len()))
+        });
+    }
+
+    if let Some(ref format) = config.logging.format {
+        checks.push(match format.parse::<skylock_hybrid::logging::LogFormat>() {
+            Ok(_) => CheckResult::pass("logging.format", format.to_ascii_lowercase()),
+            Err(e) => CheckResult::fail("logging.format", e.to_string(), "Use \"text\" or \"json\""),
+        });
+    }
+
+    if let Some(ref level) = config.logging.level {
+        checks.push(match skylock_hybrid::logging::parse_filter(level) {
+            Ok(_) => CheckResult::pass("logging.level", level.clone()),
+            Err(e) => CheckResult::fail("logging.level", e.to_string(),
+                "Use a level such as \"info\", optionally with module overrides like \"info,skylock_hetzner=debug\""),
+        });
+    }
+
+    checks
+}
+
+/// The endpoint must be a host name or an http(s) URL
+fn check_endpoint(endpoint: &str) -> CheckResult {
+    const NAME: &str = "hetzner.endpoint";
+    const HINT: &str = "Use the Storage Box address, e.g. \"https://u123456.your-storagebox.de\"";
+
+    if endpoint.is_empty() {
+        return CheckResult::fail(NAME, "not set", HINT);
+    }
+    let url = if endpoint.contains("://") { endpoint.to_string() } else { format!("https://{}", endpoint) };
+    match reqwest::Url::parse(&url) {
+        Ok(url) if !matches!(url.scheme(), "http" | "https") => {
+            CheckResult::fail(NAME, format!("unsupported scheme '{}'", url.scheme()), HINT)
+        }
+        Ok(url) if url.host_str().map_or(true, str::is_empty) => CheckResult::fail(NAME, "URL has no host", HINT),
+        Ok(_) if endpoint == doctor::DEFAULT_ENDPOINT => CheckResult::warn(NAME, "still the placeholder value", HINT),
+        Ok(_) => CheckResult::pass(NAME, endpoint),
+        Err(e) => CheckResult::fail(NAME, format!("not a valid URL: {}", e), HINT),
+    }
+}
+
+fn check_username(username: &str) -> CheckResult {
+    const NAME: &str = "hetzner.username";
+    const HINT: &str = "Set the Storage Box username, e.g. \"u123456\"";
+
+    if username.is_empty() {
+        CheckResult::fail(NAME, "not set", HINT)
+    } else if username == doctor::DEFAULT_USERNAME {
+        CheckResult::warn(NAME, "still the placeholder value", HINT)
+    } else {
+        CheckResult::pass(NAME, username)
+    }
+}
+
+fn check_encryption_key(key: &str) -> CheckResult {
+    const NAME: &str = "hetzner.encryption_key";
+    const HINT: &str = "Use a long, unique passphrase; changing it only affects new backups";
+
+    if key.is_empty() {
+        CheckResult::fail(NAME, "not set", HINT)
+    } else if key == doctor::DEFAULT_ENCRYPTION_KEY {
+        CheckResult::warn(NAME, "still the default key", HINT)
+    } else if key.len() < doctor::MIN_ENCRYPTION_KEY_LEN {
+        CheckResult::warn(NAME, format!("shorter than {} characters", doctor::MIN_ENCRYPTION_KEY_LEN), HINT)
+    } else {
+        CheckResult::pass(NAME, "set")
+    }
+}
+
+/// One result per backup path, failing for paths that can't be read
+fn check_backup_paths(paths: &[PathBuf]) -> Vec<CheckResult> {
+    const NAME: &str = "backup.backup_paths";
+
+    if paths.is_empty() {
+        return vec![CheckResult::fail(NAME, "no paths configured", "Add backup_paths to the [backup] section")];
+    }
+    paths.iter()
+        .map(|path| match readable(path) {
+            Ok(()) => CheckResult::pass(NAME, path.display().to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => CheckResult::fail(NAME,
+                format!("{} does not exist", path.display()), "Fix or remove the path in backup_paths"),
+            Err(e) => CheckResult::fail(NAME, format!("{} is not readable: {}", path.display(), e),
+                "Run as a user that can read the path, or remove it"),
+        })
+        .collect()
+}
+
+fn readable(path: &Path) -> std::io::Result<()> {
+    if std::fs::metadata(path)?.is_dir() {
+        std::fs::read_dir(path).map(drop)
+    } else {
+        std::fs::File::open(path).map(drop)
+    }
+}
+
+/// Load the configuration and check each of its fields
+pub async fn run_checks(config_path: Option<PathBuf>) -> Vec<CheckResult> {
+    let mut config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(e) => return vec![CheckResult::fail("config", format!("Cannot load configuration: {}", e),
+            "Run `skylock config` to generate one, or pass --config <path>")],
+    };
+    let mut checks = vec![CheckResult::pass("config", "Configuration file parsed")];
+    if let Err(e) = config.resolve_credentials().await {
+        checks.push(CheckResult::fail("credentials", format!("Cannot read credential sources: {}", e),
+            "Check the [credentials] section of your config"));
+    }
+    checks.extend(check_fields(&config));
+    checks
+}
+
+/// `skylock check-config`: print each field's status and fail on any failure
+pub async fn run_check_config(config_path: Option<PathBuf>, format: OutputFormat) -> Result<()> {
+    if !format.is_json() {
+        println!("{}", "🔎 Checking configuration".bright_blue().bold());
+        println!();
+    }
+
+    let report = DoctorReport::new(run_checks(config_path).await);
+
+    if format.is_json() {
+        output::print_json(&report)?;
+    } else {
+        doctor::print_report(&report);
+    }
+
+    if report.failed > 0 {
+        return Err(ConfigCheckFailed(report.failed).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skylock_core::NotificationEvent;
+
+    const VALID: &str = r#"
+[syncthing]
+api_key = "key"
+api_url = "http://localhost:8384"
+folders = []
+
+[hetzner]
+endpoint = "https://u123456.your-storagebox.de"
+username = "u123456"
+password = "secret"
+encryption_key = "correct horse battery staple"
+
+[backup]
+vss_enabled = false
+schedule = "0 0 2 * * *"
+retention_days = 30
+backup_paths = []
+max_speed_limit = "1.5M"
+spill_threshold = "16M"
+block_cache_size = "256M"
+stream_threshold = "1G"
+max_file_size = "50G"
+skip_oversized_files = true
+
+[ui]
+always_prompt_deletions = true
+notification_enabled = false
+"#;
+
+    fn valid_config(backup_path: &Path) -> Config {
+        let mut config: Config = toml::from_str(VALID).unwrap();
+        config.backup.backup_paths = vec![backup_path.to_path_buf()];
+        config
+    }
+
+    fn find<'a>(checks: &'a [CheckResult], name: &str) -> &'a CheckResult {
+        checks.iter().find(|c| c.name == name).unwrap_or_else(|| panic!("no {} check", name))
+    }
+
+    #[test]
+    fn test_valid_config_passes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let checks = check_fields(&valid_config(dir.path()));
+
+        let exclude = find(&checks, "backup.exclude_patterns");
+        assert_eq!(exclude.status, CheckStatus::Warn);
+        assert!(exclude.message.starts_with("Skipped"));
+        assert!(checks.iter().all(|c| c.status == CheckStatus::Pass || c.name == exclude.name), "{:?}", checks);
+        assert_eq!(find(&checks, "backup.max_speed_limit").message, "1.5M (1.50 MiB/s)");
+        assert_eq!(find(&checks, "backup.spill_threshold").message, "16M (16.00 MiB)");
+        assert_eq!(find(&checks, "backup.block_cache_size").message, "256M (256.00 MiB)");
+        assert_eq!(find(&checks, "backup.stream_threshold").message, "1G (1.00 GiB)");
+        assert_eq!(find(&checks, "backup.max_file_size").message, "50G (50.00 GiB), larger files are skipped");
+        let report = DoctorReport::new(checks);
+        assert!(report.success);
+    }
+
+    #[test]
+    fn test_broken_config_diagnostics() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = valid_config(dir.path());
+        config.hetzner.endpoint = "ftp://u123456.your-storagebox.de".to_string();
+        config.hetzner.username = doctor::DEFAULT_USERNAME.to_string();
+        config.hetzner.encryption_key = doctor::DEFAULT_ENCRYPTION_KEY.to_string();
+        config.hetzner.protocol = Some("scp".to_string());
+        config.backup.schedule = "0 2 * * *".to_string();
+        config.backup.backup_paths.push(dir.path().join("missing"));
+        config.backup.retention_days = 0;
+        config.backup.max_concurrent_uploads = Some(1000);
+        config.backup.max_speed_limit = Some("fast".to_string());
+        config.backup.encryption_algorithm = Some("des".to_string());
+        config.backup.compression_algorithm = Some("bzip2".to_string());
+        config.backup.hash_algorithm = Some("md5".to_string());
+        config.backup.key_exchange = Some("kyber".to_string());
+        config.backup.spill_threshold = Some("lots".to_string());
+        config.backup.block_cache_size = Some("plenty".to_string());
+        config.backup.stream_threshold = Some("huge".to_string());
+        config.backup.temp_dir = Some(dir.path().join("no-such-temp"));
+        config.logging.level = Some("info,skylock_hetzner=chatty".to_string());
+        config.scrub.enabled = true;
+        config.scrub.schedule = "weekly".to_string();
+        config.scrub.cycles = 0;
+        config.notifications.routes.insert(NotificationEvent::BackupFailed, skylock_core::NotificationRoute {
+            channels: vec![NotificationChannel::Email],
+            min_severity: None,
+        });
+
+        let checks = check_fields(&config);
+        let status = |name| find(&checks, name).status;
+
+        let endpoint = find(&checks, "hetzner.endpoint");
+        assert_eq!(endpoint.status, CheckStatus::Fail);
+        assert!(endpoint.message.contains("ftp"));
+        assert_eq!(status("hetzner.username"), CheckStatus::Warn);
+        let key = find(&checks, "hetzner.encryption_key");
+        assert_eq!(key.status, CheckStatus::Warn);
+        assert!(key.message.contains("default key"));
+        assert_eq!(status("hetzner.protocol"), CheckStatus::Fail);
+        assert!(find(&checks, "backup.schedule").message.contains("0 2 * * *"));
+        assert_eq!(status("backup.schedule"), CheckStatus::Fail);
+        assert_eq!(status("backup.retention_days"), CheckStatus::Fail);
+        assert_eq!(status("backup.max_concurrent_uploads"), CheckStatus::Warn);
+        assert_eq!(status("backup.max_speed_limit"), CheckStatus::Fail);
+        assert_eq!(status("backup.encryption_algorithm"), CheckStatus::Fail);
+        assert_eq!(status("backup.compression_algorithm"), CheckStatus::Fail);
+        assert_eq!(status("backup.hash_algorithm"), CheckStatus::Fail);
+        assert_eq!(status("backup.key_exchange"), CheckStatus::Fail);
+        assert_eq!(status("backup.spill_threshold"), CheckStatus::Fail);
+        assert_eq!(status("backup.block_cache_size"), CheckStatus::Fail);
+        assert_eq!(status("backup.stream_threshold"), CheckStatus::Fail);
+        assert_eq!(status("backup.temp_dir"), CheckStatus::Fail);
+        assert_eq!(status("logging.level"), CheckStatus::Fail);
+        assert_eq!(status("scrub.schedule"), CheckStatus::Fail);
+        assert_eq!(status("scrub.cycles"), CheckStatus::Fail);
+        assert_eq!(status("notifications.routes"), CheckStatus::Fail);
+
+        // The existing path still passes, the missing one fails
+        let paths: Vec<_> = checks.iter().filter(|c| c.name == "backup.backup_paths").collect();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].status, CheckStatus::Pass);
+        assert_eq!(paths[1].status, CheckStatus::Fail);
+        assert!(paths[1].message.contains("does not exist"));
+
+        let report = DoctorReport::new(checks);
+        assert!(!report.success);
+        assert_eq!(report.failed, 18);
+        assert_eq!(report.warnings, 4);
+    }
+
+    #[test]
+    fn test_endpoint_forms() {
+        assert_eq!(check_endpoint("u123456.your-storagebox.de").status, CheckStatus::Pass);
+        assert_eq!(check_endpoint("http://localhost:8080").status, CheckStatus::Pass);
+        assert_eq!(check_endpoint("").status, CheckStatus::Fail);
+        assert_eq!(check_endpoint("https://").status, CheckStatus::Fail);
+        assert_eq!(check_endpoint(doctor::DEFAULT_ENDPOINT).status, CheckStatus::Warn);
+    }
+
+    #[tokio::test]
+    async fn test_missing_config_file_fails() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let checks = run_checks(Some(dir.path().join("config.toml"))).await;
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, CheckStatus::Fail);
+        assert_eq!(checks[0].name, "config");
+    }
+}
@@ -1,26 +1,34 @@
 use anyhow::Result;
 use std::path::PathBuf;
 use skylock_core::Config;
-use skylock_backup::{RetentionPolicy, RetentionManager};
+use skylock_backup::{RetentionPolicy, RetentionManager, GfsPolicy, BackupMetadata, DirectUploadBackup, DEFAULT_TRASH_DAYS};
 use colored::*;
+use skylock_ui::interactive;
 
+use crate::audit::AuditTrail;
 use crate::progress::{ProgressReporter, ErrorHandler};
+use skylock_core::audit::{AuditOperation, EventOutcome};
 
-pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<PathBuf>) -> Result<()> {
+/// Apply the retention policy, moving expired backups to the trash (or
+/// deleting them right away with `purge`) and purging trashed backups whose
+/// time is up
+pub async fn perform_cleanup(dry_run: bool, force: bool, purge: bool, gfs: Option<GfsPolicy>, config_path: Option<PathBuf>) -> Result<()> {
     use std::io::{self, Write};
     
     let progress = ProgressReporter::new();
     
     if dry_run {
         ErrorHandler::print_info("Cleanup Mode", "DRY RUN - No backups will be deleted");
+    } else if purge {
+        ErrorHandler::print_info("Cleanup Mode", "Will permanently delete old backups based on retention policy");
     } else {
-        ErrorHandler::print_info("Cleanup Mode", "Will delete old backups based on retention policy");
+        ErrorHandler::print_info("Cleanup Mode", "Will move old backups to the trash based on retention policy");
     }
     println!();
     
     // Load configuration
     let config_spinner = progress.create_spinner("Loading configuration...");
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => {
             progress.finish_with_message(&config_spinner, "Configuration loaded");
             config
@@ -32,11 +40,14 @@ pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<Pat
         }
     };
     
-    if config.hetzner.username == "your-username" {
+    if !config.hetzner.has_credentials() {
         ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
         return Err(anyhow::anyhow!("Hetzner credentials required"));
     }
     
+    let audit_trail = AuditTrail::new(&config);
+    audit_trail.record_config_secrets("cleanup");
+    
     // Create Hetzner client
     let client_spinner = progress.create_spinner("Connecting to Hetzner Storage Box...");
     let hetzner_config = skylock_hetzner::HetznerConfig {
@@ -63,11 +74,19 @@ pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<Pat
     let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
         .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
     
-    // Extract retention_days before moving config
+    // Extract retention settings before moving config
     let retention_days = config.backup.retention_days;
+    let trash_days = if purge { 0 } else { config.backup.trash_days.unwrap_or(DEFAULT_TRASH_DAYS) };
     
     // Create direct upload backup manager (no bandwidth limit for cleanup)
-    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None);
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    
+    // Deletions a previous cleanup didn't finish, and backups whose time in
+    // the trash is up, go first
+    if !dry_run {
+        finish_interrupted_deletions(&direct_backup, &audit_trail).await;
+        purge_expired_trash(&direct_backup, &audit_trail).await;
+    }
     
     // List all backups
     let list_spinner = progress.create_spinner("Fetching backup list...");
@@ -80,15 +99,24 @@ pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<Pat
         return Ok(());
     }
     
-    // Create retention policy from config
-    let retention_policy = RetentionPolicy {
-        keep_last: Some(30),
-        keep_days: Some(retention_days as u32),
-        gfs: None, // Can be configured later
-        minimum_keep: 3,
+    // Create retention policy from config (GFS rotation replaces age-based rules)
+    let retention_policy = if gfs.is_some() {
+        RetentionPolicy {
+            keep_last: None,
+            keep_days: None,
+            gfs: gfs.clone(),
+            minimum_keep: 0,
+        }
+    } else {
+        RetentionPolicy {
+            keep_last: Some(30),
+            keep_days: Some(retention_days as u32),
+            gfs: None,
+            minimum_keep: 3,
+        }
     };
     
-    let retention_manager = RetentionManager::new(retention_policy);
+    let retention_manager = RetentionManager::new(retention_policy).with_trash_days(trash_days);
     
     // Show retention policy
     println!();
@@ -97,7 +125,43 @@ pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<Pat
     println!();
     
     // Calculate deletions
-    let to_delete = retention_manager.calculate_deletions(&manifests);
+    let to_delete = if let Some(ref gfs) = gfs {
+        let backups: Vec<BackupMetadata> = manifests.iter()
+            .map(|m| BackupMetadata {
+                id: m.backup_id.clone(),
+                timestamp: m.timestamp,
+                source_paths: m.source_paths.clone(),
+                size: m.total_size,
+                is_vss: false,
+                storage_tier: m.storage_tier,
+                tags: m.tags.clone(),
+                note: m.note.clone(),
+            })
+            .collect();
+        let plan = RetentionManager::plan_gfs(&backups, gfs);
+        
+        if dry_run {
+            println!("{}", "📦 Backups to Keep:".bright_green().bold());
+            println!();
+            for decision in plan.iter().filter(|d| d.is_kept()) {
+                let reasons: Vec<String> = decision.reasons.iter().map(|r| r.to_string()).collect();
+                println!("   • {} - {}",
+                    decision.backup_id.bright_green(),
+                    reasons.join(", ").dimmed()
+                );
+            }
+            println!();
+        }
+        
+        let expired = plan.into_iter()
+            .filter(|d| !d.is_kept())
+            .map(|d| d.backup_id)
+            .collect();
+        // Incremental backups need their whole chain
+        RetentionManager::protect_chains(&manifests, expired)
+    } else {
+        retention_manager.calculate_deletions(&manifests)
+    };
     
     if to_delete.is_empty() {
         println!();
@@ -114,12 +178,11 @@ pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<Pat
     for backup_id in &to_delete {
         if let Some(manifest) = manifests.iter().find(|m| &m.backup_id == backup_id) {
             let age_days = (chrono::Utc::now() - manifest.timestamp).num_days();
-            let size_mb = manifest.total_size as f64 / 1024.0 / 1024.0;
             total_size_to_delete += manifest.total_size;
             
-            println!("   • {} - {:.2} MB, {} files, {} days old",
+            println!("   • {} - {}, {} files, {} days old",
                 backup_id.bright_red(),
-                size_mb,
+                skylock_core::ByteSize(manifest.total_size),
                 manifest.file_count,
                 age_days
             );
@@ -127,12 +190,31 @@ pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<Pat
     }
     
     println!();
-    println!("   Total to delete: {} backups, {:.2} MB",
+    println!("   Total to delete: {} backups, {}",
         to_delete.len(),
-        total_size_to_delete as f64 / 1024.0 / 1024.0
+        skylock_core::ByteSize(total_size_to_delete)
     );
     println!("   Will keep: {} backups", manifests.len() - to_delete.len());
     
+    // Blobs shared with kept backups stay, so deleting frees less than the
+    // backups' total size
+    match direct_backup.estimate_reclaim(&manifests, &to_delete).await {
+        Ok(estimate) => {
+            println!("   Reclaims: {} blobs, {}{}",
+                estimate.blobs,
+                skylock_core::ByteSize(estimate.bytes),
+                if retention_manager.uses_trash() { " once purged from the trash" } else { "" }
+            );
+            if estimate.shared_blobs > 0 {
+                println!("   Still shared: {} blobs, {} (used by backups that are kept)",
+                    estimate.shared_blobs,
+                    skylock_core::ByteSize(estimate.shared_bytes)
+                );
+            }
+        }
+        Err(e) => ErrorHandler::print_warning("Reclaim Estimate", &e.to_string()),
+    }
+    
     if dry_run {
         println!();
         ErrorHandler::print_info("Dry Run Complete", "No backups were deleted");
@@ -140,38 +222,58 @@ pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<Pat
         return Ok(());
     }
     
-    // Confirmation prompt
-    if !force {
+    // Confirmation prompt (--yes counts as confirmation)
+    if !force && !interactive::assume_yes() {
         println!();
-        print!("{} ", "⚠️  Are you sure you want to delete these backups? (yes/no):".bright_yellow().bold());
-        io::stdout().flush()?;
-        
-        let mut response = String::new();
-        io::stdin().read_line(&mut response)?;
+        let question = if retention_manager.uses_trash() {
+            format!("⚠️  Move these backups to the trash for {} days? (yes/no):", trash_days)
+        } else {
+            "⚠️  Are you sure you want to permanently delete these backups? (yes/no):".to_string()
+        };
+        let response = interactive::prompt_line(
+            &format!("{} ", question.bright_yellow().bold()),
+            "Cleanup confirmation",
+            "re-run with --force or --yes to delete without confirmation",
+        )?;
         
-        if response.trim().to_lowercase() != "yes" {
+        if response.to_lowercase() != "yes" {
             println!();
             ErrorHandler::print_info("Cancelled", "Cleanup cancelled by user");
             return Ok(());
         }
     }
     
-    // Delete backups
+    // Delete backups, through the trash unless purging
+    let trashing = retention_manager.uses_trash();
     println!();
-    println!("{}", "🗑️  Deleting backups...".bright_red().bold());
+    if trashing {
+        println!("{}", "🗑️  Moving backups to the trash...".bright_yellow().bold());
+    } else {
+        println!("{}", "🗑️  Deleting backups...".bright_red().bold());
+    }
     println!();
     
     let mut deleted_count = 0;
     let mut failed_count = 0;
+    let mut live_ids: std::collections::HashSet<String> = manifests.iter()
+        .map(|m| m.backup_id.clone())
+        .collect();
     
     for backup_id in &to_delete {
-        print!("   Deleting {}... ", backup_id);
+        print!("   {} {}... ", if trashing { "Trashing" } else { "Deleting" }, backup_id);
         io::stdout().flush()?;
         
-        match direct_backup.delete_backup(backup_id).await {
+        let result = if trashing {
+            direct_backup.trash_backup(&retention_manager.trash_entry(backup_id, chrono::Utc::now())).await
+        } else {
+            direct_backup.delete_backup(backup_id).await
+        };
+        audit_trail.record_result(AuditOperation::Delete, backup_id, &result);
+        match result {
             Ok(_) => {
                 println!("{}", "✓".bright_green());
                 deleted_count += 1;
+                live_ids.remove(backup_id);
             }
             Err(e) => {
                 println!("{} - {}", "✗".bright_red(), e);
@@ -180,13 +282,158 @@ pub async fn perform_cleanup(dry_run: bool, force: bool, config_path: Option<Pat
         }
     }
     
+    // Remove blobs no longer referenced by any remaining backup
+    let gc_spinner = progress.create_spinner("Collecting orphaned blobs...");
+    match direct_backup.collect_garbage(&live_ids).await {
+        Ok(stats) => {
+            audit_trail.record(
+                AuditOperation::Delete,
+                &format!("{} orphaned files", stats.files_deleted),
+                if stats.files_failed > 0 { EventOutcome::Failure } else { EventOutcome::Success },
+            );
+            progress.finish_with_message(&gc_spinner, &format!(
+                "Removed {} orphaned files from {} backup directories",
+                stats.files_deleted,
+                stats.orphaned_backups.len()
+            ));
+            if stats.files_failed > 0 {
+                ErrorHandler::print_warning("Garbage Collection",
+                    &format!("{} orphaned files could not be deleted", stats.files_failed));
+            }
+        }
+        Err(e) => {
+            progress.finish_with_message(&gc_spinner, "Garbage collection failed");
+            ErrorHandler::print_warning("Garbage Collection", &e.to_string());
+        }
+    }
+    
+    audit_trail.record(
+        AuditOperation::Cleanup,
+        &format!("{} backups deleted, {} failed", deleted_count, failed_count),
+        if failed_count == 0 { EventOutcome::Success } else { EventOutcome::Failure },
+    );
+    
     println!();
+    let done = if trashing {
+        format!("Moved {} backups to the trash", deleted_count)
+    } else {
+        format!("Deleted {} backups", deleted_count)
+    };
     if failed_count == 0 {
-        ErrorHandler::print_success("Cleanup Complete", &format!("Deleted {} backups", deleted_count));
+        ErrorHandler::print_success("Cleanup Complete", &done);
     } else {
         ErrorHandler::print_warning("Cleanup Partial", 
-            &format!("Deleted {} backups, {} failed", deleted_count, failed_count));
+            &format!("{}, {} failed", done, failed_count));
+    }
+    if trashing && deleted_count > 0 {
+        println!("   Recoverable for {} days with: skylock restore-deleted <backup_id>", trash_days);
     }
     
     Ok(())
 }
+
+/// Delete what is left of backups whose deletion was interrupted
+/// 
+/// Their manifests are only removed once every blob is gone, so each is
+/// still listed until this finishes it.
+async fn finish_interrupted_deletions(direct_backup: &DirectUploadBackup, audit_trail: &AuditTrail) {
+    let backup_ids = match direct_backup.interrupted_deletions().await {
+        Ok(backup_ids) => backup_ids,
+        Err(e) => {
+            ErrorHandler::print_warning("Interrupted Deletions", &e.to_string());
+            return;
+        }
+    };
+    for backup_id in backup_ids {
+        let result = direct_backup.delete_backup(&backup_id).await;
+        audit_trail.record_result(AuditOperation::Delete, &backup_id, &result);
+        match result {
+            Ok(_) => println!("   Finished deleting {}", backup_id.bright_red()),
+            Err(e) => ErrorHandler::print_warning("Interrupted Deletions", &format!("Could not finish deleting {}: {}", backup_id, e)),
+        }
+    }
+}
+
+/// Permanently delete trashed backups whose time in the trash is up
+async fn purge_expired_trash(direct_backup: &DirectUploadBackup, audit_trail: &AuditTrail) {
+    let entries = match direct_backup.list_trash().await {
+        Ok(entries) => entries,
+        Err(e) => {
+            ErrorHandler::print_warning("Trash", &e.to_string());
+            return;
+        }
+    };
+    for backup_id in RetentionManager::expired_trash(&entries, chrono::Utc::now()) {
+        let result = direct_backup.purge_trashed(&backup_id).await;
+        audit_trail.record_result(AuditOperation::Delete, &backup_id, &result);
+        match result {
+            Ok(_) => println!("   Purged {} from the trash", backup_id.bright_red()),
+            Err(e) => ErrorHandler::print_warning("Trash", &format!("Could not purge {}: {}", backup_id, e)),
+        }
+    }
+}
+
+/// Bring a backup removed by cleanup back from the trash, or list the trash
+/// without a backup ID
+pub async fn restore_deleted(backup_id: Option<String>, config_path: Option<PathBuf>) -> Result<()> {
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::anyhow!("Configuration required"));
+        }
+    };
+    
+    if !config.hetzner.has_credentials() {
+        ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
+        return Err(anyhow::anyhow!("Hetzner credentials required"));
+    }
+    
+    let hetzner_config = skylock_hetzner::HetznerConfig {
+        endpoint: config.hetzner.endpoint.clone(),
+        username: config.hetzner.username.clone(),
+        password: config.hetzner.password.clone(),
+        api_token: config.hetzner.encryption_key.clone(),
+        encryption_key: config.hetzner.encryption_key.clone(),
+    };
+    let hetzner_client = skylock_hetzner::HetznerClient::new(hetzner_config)
+        .map_err(|e| anyhow::anyhow!("Failed to initialize Hetzner client: {}", e))?;
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+    let direct_backup = DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    
+    let Some(backup_id) = backup_id else {
+        let entries = direct_backup.list_trash().await?;
+        if entries.is_empty() {
+            ErrorHandler::print_info("Trash Empty", "No deleted backups can be recovered");
+            return Ok(());
+        }
+        println!("{}", "🗑️  Deleted Backups:".bright_blue().bold());
+        println!();
+        for entry in &entries {
+            println!("   • {} - deleted {}, purged after {}",
+                entry.backup_id.bright_yellow(),
+                entry.deleted_at.format("%Y-%m-%d %H:%M"),
+                entry.expires_at.format("%Y-%m-%d %H:%M")
+            );
+        }
+        println!();
+        println!("   Recover one with: skylock restore-deleted <backup_id>");
+        return Ok(());
+    };
+    
+    match direct_backup.restore_deleted(&backup_id).await {
+        Ok(entry) => {
+            ErrorHandler::print_success("Backup Recovered", &format!(
+                "{} (deleted {}) is available again",
+                backup_id.bright_yellow(),
+                entry.deleted_at.format("%Y-%m-%d %H:%M")
+            ));
+            Ok(())
+        }
+        Err(e) => {
+            ErrorHandler::print_error("Recovery Failed", &e.to_string());
+            Err(anyhow::anyhow!("Failed to recover backup {}", backup_id))
+        }
+    }
+}
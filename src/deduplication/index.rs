@@ -0,0 +1,374 @@
+//! Block index for content-addressable storage
+//!
+//! The index maps block hashes to their size and reference count. The
+//! default [`ShardedBlockIndex`] splits it into 65536 small append-only
+//! shard files keyed by the first two hash bytes and keeps a bounded number
+//! of shards in memory, so lookups and inserts never load or rewrite the
+//! whole index.
+
+use super::{ContentHash, DeduplicationError};
+use std::collections::{HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Number of shard files (one per leading two hash bytes)
+const SHARD_COUNT: usize = 65536;
+
+/// Shards kept in memory by default
+pub const DEFAULT_CACHED_SHARDS: usize = 4096;
+
+/// On-disk record: hash, size (u64 LE), refs (u32 LE), live flag
+const RECORD_LEN: usize = 32 + 8 + 4 + 1;
+
+/// Pending bytes per shard that trigger an append to disk
+const MAX_PENDING_BYTES: usize = 64 * 1024;
+
+/// Superseded records tolerated before a shard file is compacted
+const MIN_COMPACT_RECORDS: usize = 1024;
+
+/// Size and reference count of a stored block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockEntry {
+    pub size: usize,
+    pub refs: u32,
+}
+
+/// Storage for the block index of a [`super::ContentAddressableStorage`]
+pub trait BlockIndex: Send {
+    /// Look up a block
+    fn get(&self, hash: &ContentHash) -> Result<Option<BlockEntry>, DeduplicationError>;
+
+    /// Insert or update a block
+    fn put(&mut self, hash: &ContentHash, entry: BlockEntry) -> Result<(), DeduplicationError>;
+
+    /// Remove a block
+    fn remove(&mut self, hash: &ContentHash) -> Result<(), DeduplicationError>;
+
+    /// Visit every block without requiring the whole index in memory
+    fn for_each(&self, f: &mut dyn FnMut(&ContentHash, BlockEntry)) -> Result<(), DeduplicationError>;
+
+    /// Persist pending changes. Does nothing if nothing changed.
+    fn flush(&mut self) -> Result<(), DeduplicationError>;
+}
+
+/// A shard loaded into memory
+struct Shard {
+    entries: HashMap<ContentHash, BlockEntry>,
+    /// Encoded records not yet appended to the shard file
+    pending: Vec<u8>,
+    /// Records in the shard file plus pending ones
+    records: usize,
+}
+
+impl Shard {
+    fn record(&mut self, hash: &ContentHash, entry: Option<BlockEntry>) {
+        let (size, refs) = entry.map(|e| (e.size as u64, e.refs)).unwrap_or((0, 0));
+        self.pending.extend_from_slice(hash);
+        self.pending.extend_from_slice(&size.to_le_bytes());
+        self.pending.extend_from_slice(&refs.to_le_bytes());
+        self.pending.push(entry.is_some() as u8);
+        self.records += 1;
+    }
+
+    fn is_dirty(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
+struct ShardCache {
+    shards: HashMap<u16, Shard>,
+    /// Load order, for first-in first-out eviction
+    order: VecDeque<u16>,
+    /// Shard files loaded and records read from them
+    loads: usize,
+    records_read: usize,
+}
+
+/// Append-only block index split into 65536 shard files
+///
+/// Every change appends a fixed-size record to its shard; the latest record
+/// for a hash wins. Shards are loaded on demand, at most `max_cached_shards`
+/// stay in memory, and a shard file is compacted once superseded records
+/// outnumber live ones. Shards hold a few entries each even for millions of
+/// blocks, so a cache miss costs one small file read.
+pub struct ShardedBlockIndex {
+    dir: PathBuf,
+    max_cached_shards: usize,
+    cache: Mutex<ShardCache>,
+}
+
+impl ShardedBlockIndex {
+    /// Open the index in `dir`, creating it if needed
+    pub fn open<P: AsRef<Path>>(dir: P, max_cached_shards: usize) -> Result<Self, DeduplicationError> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(ShardedBlockIndex {
+            dir,
+            max_cached_shards: max_cached_shards.max(1),
+            cache: Mutex::new(ShardCache {
+                shards: HashMap::new(),
+                order: VecDeque::new(),
+                loads: 0,
+                records_read: 0,
+            }),
+        })
+    }
+
+    /// Number of entries currently held in memory
+    pub fn cached_entries(&self) -> usize {
+        self.lock().shards.values().map(|s| s.entries.len()).sum()
+    }
+
+    /// Shard files loaded so far and the records read from them
+    #[cfg(test)]
+    fn load_counts(&self) -> (usize, usize) {
+        let cache = self.lock();
+        (cache.loads, cache.records_read)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ShardCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn shard_id(hash: &ContentHash) -> u16 {
+        u16::from_be_bytes([hash[0], hash[1]])
+    }
+
+    fn shard_path(dir: &Path, id: u16) -> PathBuf {
+        let [high, low] = id.to_be_bytes();
+        dir.join(format!("{:02x}", high)).join(format!("{:02x}.idx", low))
+    }
+
+    fn load_shard(dir: &Path, id: u16) -> Result<Shard, DeduplicationError> {
+        let mut shard = Shard { entries: HashMap::new(), pending: Vec::new(), records: 0 };
+        let data = match std::fs::read(Self::shard_path(dir, id)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(shard),
+            Err(e) => return Err(e.into()),
+        };
+
+        // A trailing partial record is a torn write and is ignored
+        for record in data.chunks_exact(RECORD_LEN) {
+            let hash: ContentHash = record[..32].try_into().unwrap();
+            if record[44] == 0 {
+                shard.entries.remove(&hash);
+            } else {
+                let size = u64::from_le_bytes(record[32..40].try_into().unwrap()) as usize;
+                let refs = u32::from_le_bytes(record[40..44].try_into().unwrap());
+                shard.entries.insert(hash, BlockEntry { size, refs });
+            }
+            shard.records += 1;
+        }
+        Ok(shard)
+    }
+
+    /// Write a shard's pending records, compacting the file if it is
+    /// mostly superseded records
+    fn flush_shard(dir: &Path, id: u16, shard: &mut Shard) -> Result<(), DeduplicationError> {
+        if !shard.is_dirty() {
+            return Ok(());
+        }
+
+        let path = Self::shard_path(dir, id);
+        if shard.records > 2 * shard.entries.len() + MIN_COMPACT_RECORDS {
+            let mut compacted = Shard {
+                entries: HashMap::new(),
+                pending: Vec::with_capacity(shard.entries.len() * RECORD_LEN),
+                records: 0,
+            };
+            for (hash, entry) in &shard.entries {
+                compacted.record(hash, Some(*entry));
+            }
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            let temp_path = path.with_extension("idx.tmp");
+            std::fs::write(&temp_path, &compacted.pending)?;
+            std::fs::rename(&temp_path, &path)?;
+            shard.records = compacted.records;
+        } else {
+            let mut options = OpenOptions::new();
+            options.create(true).append(true);
+            let mut file = match options.open(&path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    std::fs::create_dir_all(path.parent().unwrap())?;
+                    options.open(&path)?
+                }
+                file => file?,
+            };
+            file.write_all(&shard.pending)?;
+        }
+        shard.pending.clear();
+        Ok(())
+    }
+
+    /// Run `f` on the shard owning `hash`, loading it and evicting the
+    /// oldest loaded shard if needed
+    fn with_shard<T>(
+        &self,
+        cache: &mut ShardCache,
+        hash: &ContentHash,
+        f: impl FnOnce(&mut Shard) -> T,
+    ) -> Result<T, DeduplicationError> {
+        let id = Self::shard_id(hash);
+        if !cache.shards.contains_key(&id) {
+            if cache.shards.len() >= self.max_cached_shards {
+                if let Some(oldest) = cache.order.pop_front() {
+                    let mut evicted = cache.shards.remove(&oldest).unwrap();
+                    Self::flush_shard(&self.dir, oldest, &mut evicted)?;
+                }
+            }
+            let shard = Self::load_shard(&self.dir, id)?;
+            cache.loads += 1;
+            cache.records_read += shard.records;
+            cache.shards.insert(id, shard);
+            cache.order.push_back(id);
+        }
+
+        let shard = cache.shards.get_mut(&id).unwrap();
+        let result = f(shard);
+        if shard.pending.len() >= MAX_PENDING_BYTES {
+            Self::flush_shard(&self.dir, id, shard)?;
+        }
+        Ok(result)
+    }
+}
+
+impl BlockIndex for ShardedBlockIndex {
+    fn get(&self, hash: &ContentHash) -> Result<Option<BlockEntry>, DeduplicationError> {
+        let mut cache = self.lock();
+        self.with_shard(&mut cache, hash, |shard| shard.entries.get(hash).copied())
+    }
+
+    fn put(&mut self, hash: &ContentHash, entry: BlockEntry) -> Result<(), DeduplicationError> {
+        let mut cache = self.lock();
+        self.with_shard(&mut cache, hash, |shard| {
+            if shard.entries.insert(*hash, entry) != Some(entry) {
+                shard.record(hash, Some(entry));
+            }
+        })
+    }
+
+    fn remove(&mut self, hash: &ContentHash) -> Result<(), DeduplicationError> {
+        let mut cache = self.lock();
+        self.with_shard(&mut cache, hash, |shard| {
+            if shard.entries.remove(hash).is_some() {
+                shard.record(hash, None);
+            }
+        })
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&ContentHash, BlockEntry)) -> Result<(), DeduplicationError> {
+        let cache = self.lock();
+        for id in 0..SHARD_COUNT {
+            let id = id as u16;
+            // Uncached shards are read one at a time and not kept
+            match cache.shards.get(&id) {
+                Some(shard) => shard.entries.iter().for_each(|(hash, entry)| f(hash, *entry)),
+                None => Self::load_shard(&self.dir, id)?
+                    .entries.iter().for_each(|(hash, entry)| f(hash, *entry)),
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), DeduplicationError> {
+        let cache = self.cache.get_mut().unwrap_or_else(|e| e.into_inner());
+        for (&id, shard) in cache.shards.iter_mut() {
+            Self::flush_shard(&self.dir, id, shard)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+
+    fn hash_of(n: u64) -> ContentHash {
+        Sha256::digest(n.to_le_bytes()).into()
+    }
+
+    #[test]
+    fn test_index_persists_updates_and_removals() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut index = ShardedBlockIndex::open(temp_dir.path(), 4).unwrap();
+
+        for n in 0..100 {
+            index.put(&hash_of(n), BlockEntry { size: n as usize, refs: 1 }).unwrap();
+        }
+        index.put(&hash_of(7), BlockEntry { size: 7, refs: 3 }).unwrap();
+        index.remove(&hash_of(8)).unwrap();
+        index.flush().unwrap();
+        drop(index);
+
+        let index = ShardedBlockIndex::open(temp_dir.path(), 4).unwrap();
+        assert_eq!(index.get(&hash_of(7)).unwrap(), Some(BlockEntry { size: 7, refs: 3 }));
+        assert_eq!(index.get(&hash_of(8)).unwrap(), None);
+        assert_eq!(index.get(&hash_of(9)).unwrap(), Some(BlockEntry { size: 9, refs: 1 }));
+
+        let mut count = 0;
+        index.for_each(&mut |_, _| count += 1).unwrap();
+        assert_eq!(count, 99);
+    }
+
+    #[test]
+    fn test_index_compacts_superseded_records() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut index = ShardedBlockIndex::open(temp_dir.path(), 1).unwrap();
+        let hash = hash_of(1);
+
+        for refs in 1..=(MIN_COMPACT_RECORDS as u32 * 2) {
+            index.put(&hash, BlockEntry { size: 10, refs }).unwrap();
+        }
+        index.flush().unwrap();
+
+        let shard_file = ShardedBlockIndex::shard_path(temp_dir.path(), ShardedBlockIndex::shard_id(&hash));
+        let records = std::fs::metadata(&shard_file).unwrap().len() as usize / RECORD_LEN;
+        assert!(records < MIN_COMPACT_RECORDS, "{} records after compaction", records);
+        drop(index);
+
+        let index = ShardedBlockIndex::open(temp_dir.path(), 1).unwrap();
+        assert_eq!(index.get(&hash).unwrap().unwrap().refs, MIN_COMPACT_RECORDS as u32 * 2);
+    }
+
+    #[test]
+    fn test_100k_inserts_bounded_memory_and_io() {
+        const BLOCKS: u64 = 100_000;
+        const BATCH: u64 = 10_000;
+        const CACHED_SHARDS: usize = 1024;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut index = ShardedBlockIndex::open(temp_dir.path(), CACHED_SHARDS).unwrap();
+
+        let mut max_cached = 0;
+        for batch in 0..BLOCKS / BATCH {
+            let (loads_before, records_before) = index.load_counts();
+            for n in batch * BATCH..(batch + 1) * BATCH {
+                let hash = hash_of(n);
+                assert!(index.get(&hash).unwrap().is_none());
+                index.put(&hash, BlockEntry { size: 4096, refs: 1 }).unwrap();
+            }
+            max_cached = max_cached.max(index.cached_entries());
+
+            // Per-insert cost doesn't grow with the index size: at most one
+            // shard file is read per insert, holding a few records, however
+            // many blocks are already indexed
+            let (loads, records) = index.load_counts();
+            let (loads, records) = (loads - loads_before, records - records_before);
+            assert!(loads <= BATCH as usize, "batch {}: {} shard loads", batch, loads);
+            assert!(records <= loads * 4, "batch {}: {} records read in {} loads", batch, records, loads);
+        }
+        index.flush().unwrap();
+
+        // Only CACHED_SHARDS shards of a few entries each are ever resident
+        let bound = CACHED_SHARDS * 4;
+        assert!(max_cached <= bound, "{} entries cached, bound {}", max_cached, bound);
+
+        let mut count = 0;
+        index.for_each(&mut |_, _| count += 1).unwrap();
+        assert_eq!(count, BLOCKS);
+    }
+}
@@ -1,13 +1,22 @@
-//! Content-addressable storage with SHA-256 hashing for block-level deduplication
+//! Content-addressable storage with SHA-256 or BLAKE3 hashing for block-level deduplication
 //!
 //! This module provides efficient deduplication by splitting files into blocks,
-//! computing content hashes, and storing only unique blocks.
+//! computing content hashes, and storing only unique blocks. A store keeps
+//! one hash algorithm for all its blocks, recorded next to its index.
 
 use std::io::{Read, Write};
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use skylock_backup::HashAlgorithm;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+mod index;
+
+pub use index::{BlockEntry, BlockIndex, ShardedBlockIndex, DEFAULT_CACHED_SHARDS};
 
 /// Deduplication errors
 #[derive(Error, Debug)]
@@ -22,9 +31,11 @@ pub enum DeduplicationError {
     ChecksumMismatch,
     #[error("Serialization error: {0}")]
     Serialization(String),
+    #[error("Store hashes its blocks with {stored}, not {requested}")]
+    HashAlgorithmMismatch { stored: HashAlgorithm, requested: HashAlgorithm },
 }
 
-/// Content hash type (SHA-256)
+/// Content hash type (32 bytes of SHA-256 or BLAKE3)
 pub type ContentHash = [u8; 32];
 
 /// Block reference with hash and size
@@ -68,17 +79,41 @@ impl DeduplicationStats {
     }
 }
 
+/// Running totals behind [`DeduplicationStats`], kept so stats don't
+/// require a scan of the index
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+struct IndexTotals {
+    unique_blocks: u64,
+    unique_size: u64,
+    total_blocks: u64,
+    total_size: u64,
+}
+
 /// Content-addressable storage for deduplicated blocks
 pub struct ContentAddressableStorage {
     storage_path: PathBuf,
     block_size: usize,
-    blocks: HashMap<ContentHash, usize>, // hash -> size
-    block_refs: HashMap<ContentHash, u32>, // hash -> reference count
+    index: Box<dyn BlockIndex>,
+    totals: IndexTotals,
+    /// Totals changed since the last save
+    dirty: bool,
+    /// Hash of every block in the store
+    hash_algorithm: HashAlgorithm,
 }
 
 impl ContentAddressableStorage {
     /// Create new content-addressable storage
     pub fn new<P: AsRef<Path>>(storage_path: P, block_size: usize) -> Result<Self, DeduplicationError> {
+        let index = ShardedBlockIndex::open(storage_path.as_ref().join("index"), DEFAULT_CACHED_SHARDS)?;
+        Self::with_index(storage_path, block_size, Box::new(index))
+    }
+    
+    /// Create content-addressable storage backed by a custom block index
+    pub fn with_index<P: AsRef<Path>>(
+        storage_path: P,
+        block_size: usize,
+        index: Box<dyn BlockIndex>,
+    ) -> Result<Self, DeduplicationError> {
         let storage_dir = storage_path.as_ref().to_path_buf();
         std::fs::create_dir_all(&storage_dir)?;
         
@@ -87,25 +122,57 @@ impl ContentAddressableStorage {
             return Err(DeduplicationError::InvalidBlockSize(block_size));
         }
         
+        let hash_algorithm = Self::load_hash_algorithm(&storage_dir)?;
         let mut cas = ContentAddressableStorage {
             storage_path: storage_dir,
             block_size,
-            blocks: HashMap::new(),
-            block_refs: HashMap::new(),
+            index,
+            totals: IndexTotals::default(),
+            dirty: false,
+            hash_algorithm,
         };
         
-        cas.load_index()?;
+        cas.migrate_legacy_index()?;
+        cas.load_totals()?;
         Ok(cas)
     }
     
+    /// Hash blocks with `algorithm`, recording it in the store
+    ///
+    /// A store never mixes algorithms, so switching is refused once it
+    /// holds blocks. Reopening a store uses its recorded algorithm.
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Result<Self, DeduplicationError> {
+        if algorithm == self.hash_algorithm {
+            return Ok(self);
+        }
+        if self.totals.unique_blocks > 0 {
+            return Err(DeduplicationError::HashAlgorithmMismatch {
+                stored: self.hash_algorithm,
+                requested: algorithm,
+            });
+        }
+        std::fs::write(self.storage_path.join("hash_algorithm"), algorithm.as_str())?;
+        self.hash_algorithm = algorithm;
+        Ok(self)
+    }
+    
+    /// Hash algorithm of the blocks in this store
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+    
     /// Store a block and return its content hash
     pub fn store_block(&mut self, data: &[u8]) -> Result<ContentHash, DeduplicationError> {
-        let hash = Self::compute_hash(data);
+        let hash = Self::compute_hash(self.hash_algorithm, data);
         
         // Check if block already exists
-        if self.blocks.contains_key(&hash) {
+        if let Some(mut entry) = self.index.get(&hash)? {
             // Increment reference count
-            *self.block_refs.entry(hash).or_insert(0) += 1;
+            entry.refs += 1;
+            self.index.put(&hash, entry)?;
+            self.totals.total_blocks += 1;
+            self.totals.total_size += entry.size as u64;
+            self.dirty = true;
             return Ok(hash);
         }
         
@@ -118,15 +185,19 @@ impl ContentAddressableStorage {
         std::fs::write(&block_path, data)?;
         
         // Update index
-        self.blocks.insert(hash, data.len());
-        self.block_refs.insert(hash, 1);
+        self.index.put(&hash, BlockEntry { size: data.len(), refs: 1 })?;
+        self.totals.unique_blocks += 1;
+        self.totals.unique_size += data.len() as u64;
+        self.totals.total_blocks += 1;
+        self.totals.total_size += data.len() as u64;
+        self.dirty = true;
         
         Ok(hash)
     }
     
     /// Retrieve a block by its content hash
     pub fn get_block(&self, hash: &ContentHash) -> Result<Vec<u8>, DeduplicationError> {
-        if !self.blocks.contains_key(hash) {
+        if self.index.get(hash)?.is_none() {
             return Err(DeduplicationError::BlockNotFound(hex::encode(hash)));
         }
         
@@ -134,7 +205,7 @@ impl ContentAddressableStorage {
         let data = std::fs::read(&block_path)?;
         
         // Verify integrity
-        let computed_hash = Self::compute_hash(&data);
+        let computed_hash = Self::compute_hash(self.hash_algorithm, &data);
         if computed_hash != *hash {
             return Err(DeduplicationError::ChecksumMismatch);
         }
@@ -144,43 +215,38 @@ impl ContentAddressableStorage {
     
     /// Check if a block exists
     pub fn has_block(&self, hash: &ContentHash) -> bool {
-        self.blocks.contains_key(hash)
+        matches!(self.index.get(hash), Ok(Some(_)))
     }
     
     /// Delete a block (decrements reference count)
     pub fn delete_block(&mut self, hash: &ContentHash) -> Result<bool, DeduplicationError> {
-        if let Some(ref_count) = self.block_refs.get_mut(hash) {
-            *ref_count -= 1;
+        let mut entry = self.index.get(hash)?
+            .ok_or_else(|| DeduplicationError::BlockNotFound(hex::encode(hash)))?;
+        
+        entry.refs = entry.refs.saturating_sub(1);
+        self.totals.total_blocks = self.totals.total_blocks.saturating_sub(1);
+        self.totals.total_size = self.totals.total_size.saturating_sub(entry.size as u64);
+        self.dirty = true;
+        
+        if entry.refs == 0 {
+            // No more references, remove block
+            let block_path = self.get_block_path(hash);
+            std::fs::remove_file(&block_path)?;
             
-            if *ref_count == 0 {
-                // No more references, remove block
-                let block_path = self.get_block_path(hash);
-                std::fs::remove_file(&block_path)?;
-                
-                self.blocks.remove(hash);
-                self.block_refs.remove(hash);
-                
-                Ok(true) // Block deleted
-            } else {
-                Ok(false) // Still has references
-            }
+            self.index.remove(hash)?;
+            self.totals.unique_blocks = self.totals.unique_blocks.saturating_sub(1);
+            self.totals.unique_size = self.totals.unique_size.saturating_sub(entry.size as u64);
+            
+            Ok(true) // Block deleted
         } else {
-            Err(DeduplicationError::BlockNotFound(hex::encode(hash)))
+            self.index.put(hash, entry)?;
+            Ok(false) // Still has references
         }
     }
     
     /// Get storage statistics
     pub fn get_stats(&self) -> DeduplicationStats {
-        let unique_blocks = self.blocks.len() as u64;
-        let unique_size: u64 = self.blocks.values().map(|&size| size as u64).sum();
-        let total_blocks: u64 = self.block_refs.values().map(|&count| count as u64).sum();
-        let total_size: u64 = self.blocks.iter()
-            .map(|(hash, &size)| {
-                let ref_count = self.block_refs.get(hash).copied().unwrap_or(0) as u64;
-                size as u64 * ref_count
-            })
-            .sum();
-        
+        let IndexTotals { unique_blocks, unique_size, total_blocks, total_size } = self.totals;
         let deduplication_ratio = DeduplicationStats::calculate_ratio(total_size, unique_size);
         let space_saved = total_size.saturating_sub(unique_size);
         
@@ -199,32 +265,45 @@ impl ContentAddressableStorage {
         let mut removed_count = 0;
         let mut to_remove = Vec::new();
         
-        for (hash, &ref_count) in &self.block_refs {
-            if ref_count == 0 {
-                to_remove.push(*hash);
+        self.index.for_each(&mut |hash, entry| {
+            if entry.refs == 0 {
+                to_remove.push((*hash, entry.size));
             }
-        }
+        })?;
         
-        for hash in to_remove {
+        for (hash, size) in to_remove {
             let block_path = self.get_block_path(&hash);
             if block_path.exists() {
                 std::fs::remove_file(&block_path)?;
                 removed_count += 1;
             }
             
-            self.blocks.remove(&hash);
-            self.block_refs.remove(&hash);
+            self.index.remove(&hash)?;
+            self.totals.unique_blocks = self.totals.unique_blocks.saturating_sub(1);
+            self.totals.unique_size = self.totals.unique_size.saturating_sub(size as u64);
+            self.dirty = true;
         }
         
         self.save_index()?;
         Ok(removed_count)
     }
     
-    /// Compute SHA-256 hash of data
-    fn compute_hash(data: &[u8]) -> ContentHash {
-        let mut hasher = Sha256::new();
+    /// Compute the `algorithm` hash of data
+    fn compute_hash(algorithm: HashAlgorithm, data: &[u8]) -> ContentHash {
+        let mut hasher = algorithm.hasher();
         hasher.update(data);
-        hasher.finalize().into()
+        hasher.finalize_bytes()
+    }
+    
+    /// Recorded hash algorithm of the store at `storage_dir`; stores from
+    /// before the choice existed have none and use SHA-256
+    fn load_hash_algorithm(storage_dir: &Path) -> Result<HashAlgorithm, DeduplicationError> {
+        match std::fs::read_to_string(storage_dir.join("hash_algorithm")) {
+            Ok(name) => name.parse()
+                .map_err(|e: skylock_backup::SkylockError| DeduplicationError::Serialization(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashAlgorithm::default()),
+            Err(e) => Err(e.into()),
+        }
     }
     
     /// Get file path for a block
@@ -240,59 +319,66 @@ impl ContentAddressableStorage {
             .join(filename)
     }
     
-    /// Load block index from disk
-    fn load_index(&mut self) -> Result<(), DeduplicationError> {
+    /// Move a pre-sharding `index.json` into the block index
+    fn migrate_legacy_index(&mut self) -> Result<(), DeduplicationError> {
         let index_path = self.storage_path.join("index.json");
+        if !index_path.exists() {
+            return Ok(());
+        }
         
-        if index_path.exists() {
-            let index_data = std::fs::read_to_string(&index_path)?;
-            let index: (HashMap<String, usize>, HashMap<String, u32>) = 
-                serde_json::from_str(&index_data)
-                    .map_err(|e| DeduplicationError::Serialization(e.to_string()))?;
-            
-            // Convert string keys back to ContentHash
-            self.blocks = index.0
-                .into_iter()
-                .filter_map(|(k, v)| {
-                    hex::decode(&k).ok()
-                        .and_then(|bytes| bytes.try_into().ok())
-                        .map(|hash| (hash, v))
-                })
-                .collect();
-                
-            self.block_refs = index.1
-                .into_iter()
-                .filter_map(|(k, v)| {
-                    hex::decode(&k).ok()
-                        .and_then(|bytes| bytes.try_into().ok())
-                        .map(|hash| (hash, v))
-                })
-                .collect();
+        let index_data = std::fs::read_to_string(&index_path)?;
+        let (blocks, refs): (HashMap<String, usize>, HashMap<String, u32>) =
+            serde_json::from_str(&index_data)
+                .map_err(|e| DeduplicationError::Serialization(e.to_string()))?;
+        
+        for (key, size) in blocks {
+            let Some(hash) = hex::decode(&key).ok().and_then(|bytes| <ContentHash>::try_from(bytes).ok()) else {
+                continue;
+            };
+            let refs = refs.get(&key).copied().unwrap_or(0);
+            self.index.put(&hash, BlockEntry { size, refs })?;
         }
+        self.index.flush()?;
         
+        // Recompute totals from the migrated index
+        let _ = std::fs::remove_file(self.storage_path.join("stats.json"));
+        std::fs::remove_file(&index_path)?;
         Ok(())
     }
     
-    /// Save block index to disk
-    fn save_index(&self) -> Result<(), DeduplicationError> {
-        let index_path = self.storage_path.join("index.json");
+    /// Load running totals, rebuilding them from the index if missing
+    fn load_totals(&mut self) -> Result<(), DeduplicationError> {
+        let stats_path = self.storage_path.join("stats.json");
         
-        // Convert ContentHash keys to strings for JSON serialization
-        let blocks_str: HashMap<String, usize> = self.blocks
-            .iter()
-            .map(|(k, &v)| (hex::encode(k), v))
-            .collect();
-            
-        let refs_str: HashMap<String, u32> = self.block_refs
-            .iter()
-            .map(|(k, &v)| (hex::encode(k), v))
-            .collect();
+        if stats_path.exists() {
+            let stats_data = std::fs::read_to_string(&stats_path)?;
+            self.totals = serde_json::from_str(&stats_data)
+                .map_err(|e| DeduplicationError::Serialization(e.to_string()))?;
+            return Ok(());
+        }
         
-        let index = (blocks_str, refs_str);
-        let index_data = serde_json::to_string_pretty(&index)
-            .map_err(|e| DeduplicationError::Serialization(e.to_string()))?;
+        let mut totals = IndexTotals::default();
+        self.index.for_each(&mut |_, entry| {
+            totals.unique_blocks += 1;
+            totals.unique_size += entry.size as u64;
+            totals.total_blocks += entry.refs as u64;
+            totals.total_size += entry.size as u64 * entry.refs as u64;
+        })?;
+        self.totals = totals;
+        self.dirty = totals.unique_blocks > 0;
+        Ok(())
+    }
+    
+    /// Save pending index changes and totals. Does nothing if nothing changed.
+    fn save_index(&mut self) -> Result<(), DeduplicationError> {
+        self.index.flush()?;
         
-        std::fs::write(&index_path, index_data)?;
+        if self.dirty {
+            let stats_data = serde_json::to_string_pretty(&self.totals)
+                .map_err(|e| DeduplicationError::Serialization(e.to_string()))?;
+            std::fs::write(self.storage_path.join("stats.json"), stats_data)?;
+            self.dirty = false;
+        }
         Ok(())
     }
 }
@@ -330,6 +416,16 @@ impl DeduplicationEngine {
         Ok(engine)
     }
     
+    /// Hash blocks with `algorithm` (see [`ContentAddressableStorage::with_hash_algorithm`])
+    pub fn with_hash_algorithm(self, algorithm: HashAlgorithm) -> Result<Self, DeduplicationError> {
+        let DeduplicationEngine { cas, metadata_path, file_metadata } = self;
+        Ok(DeduplicationEngine {
+            cas: cas.with_hash_algorithm(algorithm)?,
+            metadata_path,
+            file_metadata,
+        })
+    }
+    
     /// Store a file with deduplication
     pub fn store_file<P: AsRef<Path>, R: Read>(
         &mut self,
@@ -493,66 +589,343 @@ impl DeduplicationEngine {
     }
 }
 
+/// Number of top duplicated blocks and files an analysis reports by default
+pub const DEFAULT_TOP_DUPLICATES: usize = 10;
+
+/// Progress of a directory analysis, reported after each file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnalysisProgress {
+    pub files_scanned: u64,
+    pub total_files: u64,
+    pub bytes_scanned: u64,
+    pub total_bytes: u64,
+}
+
+/// Progress callback for [`DuplicationAnalyzer::analyze_directory`]
+pub type AnalysisProgressCallback = Arc<dyn Fn(AnalysisProgress) + Send + Sync>;
+
+/// A block found more than once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateBlock {
+    pub hash: ContentHash,
+    pub size: usize,
+    pub occurrences: u32,
+}
+
+impl DuplicateBlock {
+    /// Bytes saved by storing the block once
+    pub fn space_saved(&self) -> u64 {
+        self.size as u64 * (self.occurrences as u64 - 1)
+    }
+}
+
+/// Files with identical contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateFiles {
+    pub hash: ContentHash,
+    pub size: u64,
+    /// Sorted paths of every copy
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateFiles {
+    /// Bytes saved by storing the contents once
+    pub fn space_saved(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Result of [`DuplicationAnalyzer::analyze_directory`]
+#[derive(Debug, Clone)]
+pub struct DuplicationReport {
+    pub stats: DeduplicationStats,
+    pub files_scanned: u64,
+    pub bytes_scanned: u64,
+    /// False when the analysis was cancelled; the figures then cover only
+    /// the files scanned
+    pub complete: bool,
+    /// Blocks that save the most space, largest saving first
+    pub top_blocks: Vec<DuplicateBlock>,
+    /// Identical files that save the most space, largest saving first
+    pub top_files: Vec<DuplicateFiles>,
+}
+
+/// Block hashes sent from the analysis workers in one message
+const ANALYSIS_BATCH_BLOCKS: usize = 256;
+
+/// A message from an analysis worker
+enum Scanned {
+    /// Hashes and sizes of consecutive blocks of a file
+    Blocks(Vec<(ContentHash, usize)>),
+    /// A file was scanned in full: its position in the listing, size and hash
+    File(usize, u64, ContentHash),
+}
+
+/// Occurrences of each block and file content seen so far
+///
+/// Block counts live in a [`ShardedBlockIndex`] in a scratch directory, so
+/// only its cached shards are held in memory. Files are recorded by their
+/// position in the listing instead of by path.
+struct ScanTally {
+    blocks: ShardedBlockIndex,
+    files: Vec<(ContentHash, u64, usize)>, // (hash, size, position)
+    total_size: u64,
+    total_blocks: u64,
+    unique_blocks: u64,
+    unique_size: u64,
+    _scratch: tempfile::TempDir,
+}
+
+impl ScanTally {
+    fn new(max_cached_shards: usize) -> Result<Self, DeduplicationError> {
+        let scratch = tempfile::Builder::new().prefix("skylock-analysis-").tempdir()?;
+        Ok(ScanTally {
+            blocks: ShardedBlockIndex::open(scratch.path(), max_cached_shards)?,
+            files: Vec::new(),
+            total_size: 0,
+            total_blocks: 0,
+            unique_blocks: 0,
+            unique_size: 0,
+            _scratch: scratch,
+        })
+    }
+    
+    fn add_blocks(&mut self, blocks: Vec<(ContentHash, usize)>) -> Result<(), DeduplicationError> {
+        for (hash, size) in blocks {
+            let entry = match self.blocks.get(&hash)? {
+                Some(entry) => BlockEntry { refs: entry.refs.saturating_add(1), ..entry },
+                None => {
+                    self.unique_blocks += 1;
+                    self.unique_size += size as u64;
+                    BlockEntry { size, refs: 1 }
+                }
+            };
+            self.blocks.put(&hash, entry)?;
+            self.total_blocks += 1;
+        }
+        Ok(())
+    }
+    
+    fn add_file(&mut self, position: usize, size: u64, hash: ContentHash) {
+        self.total_size += size;
+        self.files.push((hash, size, position));
+    }
+
+    fn stats(&self) -> DeduplicationStats {
+        DeduplicationStats {
+            total_blocks: self.total_blocks,
+            unique_blocks: self.unique_blocks,
+            total_size: self.total_size,
+            unique_size: self.unique_size,
+            deduplication_ratio: DeduplicationStats::calculate_ratio(self.total_size, self.unique_size),
+            space_saved: self.total_size.saturating_sub(self.unique_size),
+        }
+    }
+
+    /// Stream the index, keeping only the `count` best blocks seen so far
+    fn top_blocks(&self, count: usize) -> Result<Vec<DuplicateBlock>, DeduplicationError> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        // Min-heap on (saving, reversed hash), so the worst kept block is on top
+        let mut best = BinaryHeap::with_capacity(count + 1);
+        self.blocks.for_each(&mut |hash, entry| {
+            if entry.refs > 1 {
+                let block = DuplicateBlock { hash: *hash, size: entry.size, occurrences: entry.refs };
+                best.push(Reverse((block.space_saved(), Reverse(block.hash), block.size, block.occurrences)));
+                if best.len() > count {
+                    best.pop();
+                }
+            }
+        })?;
+        Ok(best.into_sorted_vec().into_iter()
+            .map(|Reverse((_, Reverse(hash), size, occurrences))| DuplicateBlock { hash, size, occurrences })
+            .collect())
+    }
+
+    fn top_files(&mut self, paths: &[PathBuf], count: usize) -> Vec<DuplicateFiles> {
+        self.files.sort_unstable();
+        let mut files: Vec<(u64, Vec<&PathBuf>, ContentHash, u64)> = Vec::new();
+        let mut rest = &self.files[..];
+        while let Some(&(hash, size, _)) = rest.first() {
+            let copies = rest.iter().take_while(|(other, _, _)| *other == hash).count();
+            let (group, remaining) = rest.split_at(copies);
+            rest = remaining;
+            if size > 0 && copies > 1 {
+                let mut group_paths: Vec<&PathBuf> = group.iter().map(|(_, _, position)| &paths[*position]).collect();
+                group_paths.sort();
+                files.push((size * (copies as u64 - 1), group_paths, hash, size));
+            }
+        }
+        files.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        files.truncate(count);
+        files.into_iter()
+            .map(|(_, copies, hash, size)| DuplicateFiles { hash, size, paths: copies.into_iter().cloned().collect() })
+            .collect()
+    }
+}
+
 /// Deduplication analyzer for detecting duplicate files
 pub struct DuplicationAnalyzer {
     block_size: usize,
+    hash_algorithm: HashAlgorithm,
+    workers: usize,
+    top_count: usize,
+    max_cached_shards: usize,
+    cancel: CancellationToken,
+    progress: Option<AnalysisProgressCallback>,
 }
 
 impl DuplicationAnalyzer {
     /// Create new duplication analyzer
     pub fn new(block_size: usize) -> Self {
-        DuplicationAnalyzer { block_size }
+        DuplicationAnalyzer {
+            block_size,
+            hash_algorithm: HashAlgorithm::default(),
+            workers: 1,
+            top_count: DEFAULT_TOP_DUPLICATES,
+            max_cached_shards: DEFAULT_CACHED_SHARDS,
+            cancel: CancellationToken::new(),
+            progress: None,
+        }
+    }
+    
+    /// Hash blocks and files with `algorithm` (default SHA-256)
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+    
+    /// Hash up to `workers` files at once (default 1)
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+    
+    /// Report this many top duplicated blocks and files
+    pub fn with_top_count(mut self, count: usize) -> Self {
+        self.top_count = count;
+        self
+    }
+    
+    /// Keep at most this many shards of the block tally in memory (default
+    /// [`DEFAULT_CACHED_SHARDS`]); the rest stay in a scratch directory
+    pub fn with_max_cached_shards(mut self, max_cached_shards: usize) -> Self {
+        self.max_cached_shards = max_cached_shards;
+        self
+    }
+    
+    /// Stop scanning when `cancel` is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+    
+    /// Report progress after each file is scanned
+    pub fn with_progress(mut self, progress: AnalysisProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
     }
     
     /// Analyze files for potential deduplication savings
+    ///
+    /// The tree is listed first, then its files are hashed by the worker
+    /// pool while this thread tallies the results. Workers send block hashes
+    /// in batches over a bounded channel and the tally is disk-backed, so
+    /// memory stays bounded however large the tree or its files. If
+    /// cancelled, the report covers the files scanned so far and is marked
+    /// incomplete.
     pub fn analyze_directory<P: AsRef<Path>>(
         &self,
         directory: P,
-    ) -> Result<DeduplicationStats, DeduplicationError> {
-        let mut all_blocks = HashMap::<ContentHash, (usize, u32)>::new(); // hash -> (size, count)
-        let mut total_size = 0u64;
+    ) -> Result<DuplicationReport, DeduplicationError> {
+        let mut files = Vec::new();
+        let mut progress = AnalysisProgress::default();
         
         for entry in walkdir::WalkDir::new(directory) {
+            if self.cancel.is_cancelled() {
+                break;
+            }
             let entry = entry.map_err(|e| DeduplicationError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
             
             if entry.file_type().is_file() {
-                let file_size = entry.metadata().map_err(|e| DeduplicationError::Io(e.into()))?.len();
-                total_size += file_size;
-                
-                let blocks = self.analyze_file_blocks(entry.path())?;
-                for (hash, size) in blocks {
-                    let (stored_size, count) = all_blocks.entry(hash).or_insert((size, 0));
-                    *count += 1;
-                    // Ensure size consistency
-                    assert_eq!(*stored_size, size);
-                }
+                progress.total_bytes += entry.metadata().map_err(|e| DeduplicationError::Io(e.into()))?.len();
+                files.push(entry.into_path());
             }
         }
+        progress.total_files = files.len() as u64;
+        
+        // Stops the workers on cancellation or the first error
+        let stop = self.cancel.child_token();
+        let next = AtomicUsize::new(0);
+        let mut tally = ScanTally::new(self.max_cached_shards)?;
+        let mut failure = None;
+        
+        std::thread::scope(|scope| {
+            let (tx, rx) = mpsc::sync_channel(self.workers * 2);
+            for _ in 0..self.workers.min(files.len()) {
+                let (tx, files, next, stop) = (tx.clone(), &files, &next, &stop);
+                scope.spawn(move || {
+                    while !stop.is_cancelled() {
+                        let position = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = files.get(position) else { break };
+                        let scanned = self.scan_file(path, |blocks| {
+                            let _ = tx.send(Ok(Scanned::Blocks(blocks)));
+                        });
+                        let message = scanned.map(|(size, hash)| Scanned::File(position, size, hash));
+                        if tx.send(message).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(tx);
+            
+            for scanned in rx {
+                let tallied = scanned.and_then(|scanned| match scanned {
+                    Scanned::Blocks(blocks) => tally.add_blocks(blocks),
+                    Scanned::File(position, size, hash) => {
+                        tally.add_file(position, size, hash);
+                        progress.files_scanned += 1;
+                        progress.bytes_scanned += size;
+                        if let Some(callback) = &self.progress {
+                            callback(progress);
+                        }
+                        Ok(())
+                    }
+                });
+                if let Err(e) = tallied {
+                    stop.cancel();
+                    failure.get_or_insert(e);
+                }
+            }
+        });
         
-        let unique_size: u64 = all_blocks.values().map(|(size, _)| *size as u64).sum();
-        let unique_blocks = all_blocks.len() as u64;
-        let total_blocks: u64 = all_blocks.values().map(|(_, count)| *count as u64).sum();
-        
-        let deduplication_ratio = DeduplicationStats::calculate_ratio(total_size, unique_size);
-        let space_saved = total_size.saturating_sub(unique_size);
+        if let Some(e) = failure {
+            return Err(e);
+        }
         
-        Ok(DeduplicationStats {
-            total_blocks,
-            unique_blocks,
-            total_size,
-            unique_size,
-            deduplication_ratio,
-            space_saved,
+        Ok(DuplicationReport {
+            stats: tally.stats(),
+            files_scanned: progress.files_scanned,
+            bytes_scanned: progress.bytes_scanned,
+            complete: !self.cancel.is_cancelled() && progress.files_scanned == progress.total_files,
+            top_blocks: tally.top_blocks(self.top_count)?,
+            top_files: tally.top_files(&files, self.top_count),
         })
     }
     
-    /// Analyze blocks in a single file
-    fn analyze_file_blocks<P: AsRef<Path>>(
+    /// Hash a single file, passing its block hashes to `emit` in batches,
+    /// and return its size and whole-file hash
+    fn scan_file(
         &self,
-        file_path: P,
-    ) -> Result<Vec<(ContentHash, usize)>, DeduplicationError> {
+        file_path: &Path,
+        mut emit: impl FnMut(Vec<(ContentHash, usize)>),
+    ) -> Result<(u64, ContentHash), DeduplicationError> {
         let mut file = std::fs::File::open(file_path)?;
-        let mut blocks = Vec::new();
+        let mut hasher = self.hash_algorithm.hasher();
+        let mut blocks = Vec::with_capacity(ANALYSIS_BATCH_BLOCKS);
+        let mut size = 0u64;
         let mut buffer = vec![0u8; self.block_size];
         
         loop {
@@ -562,11 +935,18 @@ impl DuplicationAnalyzer {
             }
             
             let block_data = &buffer[..bytes_read];
-            let hash = ContentAddressableStorage::compute_hash(block_data);
-            blocks.push((hash, bytes_read));
+            hasher.update(block_data);
+            blocks.push((ContentAddressableStorage::compute_hash(self.hash_algorithm, block_data), bytes_read));
+            size += bytes_read as u64;
+            if blocks.len() == ANALYSIS_BATCH_BLOCKS {
+                emit(std::mem::replace(&mut blocks, Vec::with_capacity(ANALYSIS_BATCH_BLOCKS)));
+            }
+        }
+        if !blocks.is_empty() {
+            emit(blocks);
         }
         
-        Ok(blocks)
+        Ok((size, hasher.finalize_bytes()))
     }
     
     /// Find duplicate files in a directory
@@ -594,7 +974,7 @@ impl DuplicationAnalyzer {
     /// Compute hash of entire file
     fn compute_file_hash<P: AsRef<Path>>(&self, file_path: P) -> Result<ContentHash, DeduplicationError> {
         let mut file = std::fs::File::open(file_path)?;
-        let mut hasher = Sha256::new();
+        let mut hasher = self.hash_algorithm.hasher();
         let mut buffer = vec![0u8; self.block_size];
         
         loop {
@@ -606,7 +986,7 @@ impl DuplicationAnalyzer {
             hasher.update(&buffer[..bytes_read]);
         }
         
-        Ok(hasher.finalize().into())
+        Ok(hasher.finalize_bytes())
     }
 }
 
@@ -647,6 +1027,87 @@ mod tests {
         assert_eq!(stats.total_blocks, 3); // 2 refs to hash1 + 1 ref to hash3
     }
     
+    #[test]
+    fn test_index_survives_reopen_and_unchanged_drop_writes_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let hash = {
+            let mut cas = ContentAddressableStorage::new(temp_dir.path(), 4096).unwrap();
+            let hash = cas.store_block(b"persisted block").unwrap();
+            cas.store_block(b"persisted block").unwrap();
+            hash
+        };
+        
+        let snapshot = || {
+            walkdir::WalkDir::new(temp_dir.path()).into_iter()
+                .map(|e| e.unwrap())
+                .filter(|e| e.file_type().is_file())
+                .map(|e| (e.path().to_path_buf(), e.metadata().unwrap().modified().unwrap(), std::fs::read(e.path()).unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let before = snapshot();
+        
+        {
+            let cas = ContentAddressableStorage::new(temp_dir.path(), 4096).unwrap();
+            assert!(cas.has_block(&hash));
+            assert_eq!(cas.get_block(&hash).unwrap(), b"persisted block");
+            let stats = cas.get_stats();
+            assert_eq!((stats.unique_blocks, stats.total_blocks), (1, 2));
+        }
+        
+        // Read-only use must not rewrite the index on drop
+        assert_eq!(snapshot(), before);
+    }
+    
+    #[test]
+    fn test_blake3_store_keeps_its_algorithm() {
+        let temp_dir = TempDir::new().unwrap();
+        let hash = {
+            let mut cas = ContentAddressableStorage::new(temp_dir.path(), 4096).unwrap()
+                .with_hash_algorithm(HashAlgorithm::Blake3)
+                .unwrap();
+            let hash = cas.store_block(b"blake3 block").unwrap();
+            assert_eq!(hex::encode(hash), HashAlgorithm::Blake3.hash(b"blake3 block"));
+            hash
+        };
+        
+        // Reopened, the store reads and verifies blocks with BLAKE3
+        let cas = ContentAddressableStorage::new(temp_dir.path(), 4096).unwrap();
+        assert_eq!(cas.hash_algorithm(), HashAlgorithm::Blake3);
+        assert_eq!(cas.get_block(&hash).unwrap(), b"blake3 block");
+        
+        // ...and refuses to mix in SHA-256 blocks
+        match cas.with_hash_algorithm(HashAlgorithm::Sha256) {
+            Err(DeduplicationError::HashAlgorithmMismatch { stored, requested }) => {
+                assert_eq!((stored, requested), (HashAlgorithm::Blake3, HashAlgorithm::Sha256));
+            }
+            _ => panic!("expected a hash algorithm mismatch"),
+        }
+    }
+    
+    #[test]
+    fn test_legacy_json_index_is_migrated() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = b"block from a legacy index";
+        let hash = ContentAddressableStorage::compute_hash(HashAlgorithm::Sha256, data);
+        {
+            let cas = ContentAddressableStorage::new(temp_dir.path(), 4096).unwrap();
+            let block_path = cas.get_block_path(&hash);
+            std::fs::create_dir_all(block_path.parent().unwrap()).unwrap();
+            std::fs::write(block_path, data).unwrap();
+        }
+        let legacy: (HashMap<String, usize>, HashMap<String, u32>) = (
+            [(hex::encode(hash), data.len())].into_iter().collect(),
+            [(hex::encode(hash), 3)].into_iter().collect(),
+        );
+        std::fs::write(temp_dir.path().join("index.json"), serde_json::to_string(&legacy).unwrap()).unwrap();
+        
+        let mut cas = ContentAddressableStorage::new(temp_dir.path(), 4096).unwrap();
+        assert!(!temp_dir.path().join("index.json").exists());
+        assert_eq!(cas.get_block(&hash).unwrap(), data);
+        assert_eq!(cas.get_stats().total_blocks, 3);
+        assert!(!cas.delete_block(&hash).unwrap());
+    }
+    
     #[test]
     fn test_deduplication_engine() {
         let temp_dir = TempDir::new().unwrap();
@@ -701,6 +1162,114 @@ mod tests {
         println!("Space saved: {} bytes", stats.space_saved);
     }
     
+    /// Tree of small files, hashed in 4-byte blocks: three copies of
+    /// "AAAABBBB", one "AAAACCCC", one "DDDDDDDDDDDD" and two empty files
+    fn create_duplicate_tree(root: &Path) {
+        let deeper = root.join("sub").join("deeper");
+        std::fs::create_dir_all(&deeper).unwrap();
+        std::fs::write(root.join("a.txt"), b"AAAABBBB").unwrap();
+        std::fs::write(root.join("sub").join("a copy.txt"), b"AAAABBBB").unwrap();
+        std::fs::write(deeper.join("a copy 2.txt"), b"AAAABBBB").unwrap();
+        std::fs::write(root.join("b.txt"), b"AAAACCCC").unwrap();
+        std::fs::write(root.join("c.bin"), b"DDDDDDDDDDDD").unwrap();
+        std::fs::write(root.join("empty"), b"").unwrap();
+        std::fs::write(deeper.join("empty"), b"").unwrap();
+    }
+    
+    #[test]
+    fn test_analyzer_reports_stats_and_top_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        create_duplicate_tree(temp_dir.path());
+        
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen_by_callback = seen.clone();
+        let report = DuplicationAnalyzer::new(4)
+            .with_workers(3)
+            .with_progress(Arc::new(move |progress| seen_by_callback.lock().unwrap().push(progress)))
+            .analyze_directory(temp_dir.path())
+            .unwrap();
+        
+        assert!(report.complete);
+        assert_eq!((report.files_scanned, report.bytes_scanned), (7, 44));
+        assert_eq!(report.stats.total_blocks, 11);
+        assert_eq!(report.stats.unique_blocks, 4);
+        assert_eq!(report.stats.total_size, 44);
+        assert_eq!(report.stats.unique_size, 16);
+        assert_eq!(report.stats.space_saved, 28);
+        
+        // AAAA occurs four times; BBBB and DDDD three times each; CCCC once
+        let saved: Vec<u64> = report.top_blocks.iter().map(|b| b.space_saved()).collect();
+        assert_eq!(saved, vec![12, 8, 8]);
+        assert_eq!(report.top_blocks[0].hash, ContentAddressableStorage::compute_hash(HashAlgorithm::Sha256, b"AAAA"));
+        assert_eq!(report.top_blocks[0].occurrences, 4);
+        
+        // Empty files are identical but save nothing
+        assert_eq!(report.top_files.len(), 1);
+        assert_eq!(report.top_files[0].size, 8);
+        assert_eq!(report.top_files[0].space_saved(), 16);
+        assert_eq!(report.top_files[0].paths, vec![
+            temp_dir.path().join("a.txt"),
+            temp_dir.path().join("sub").join("a copy.txt"),
+            temp_dir.path().join("sub").join("deeper").join("a copy 2.txt"),
+        ]);
+        
+        // One report per file, counting up to the totals
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 7);
+        assert!(seen.windows(2).all(|w| w[0].bytes_scanned <= w[1].bytes_scanned));
+        assert_eq!(seen.last().copied(), Some(AnalysisProgress {
+            files_scanned: 7, total_files: 7, bytes_scanned: 44, total_bytes: 44,
+        }));
+        
+        // The worker count and top count don't change the figures
+        let single = DuplicationAnalyzer::new(4).with_top_count(1).analyze_directory(temp_dir.path()).unwrap();
+        assert_eq!(single.stats.space_saved, 28);
+        assert_eq!(single.top_blocks, report.top_blocks[..1]);
+        assert_eq!(single.top_files, report.top_files);
+    }
+    
+    #[test]
+    fn test_analyzer_figures_hold_with_one_cached_shard() {
+        let temp_dir = TempDir::new().unwrap();
+        create_duplicate_tree(temp_dir.path());
+        // 1000 blocks, sent to the tally in several batches
+        std::fs::write(temp_dir.path().join("large"), b"EEEE".repeat(1000)).unwrap();
+        
+        let unbounded = DuplicationAnalyzer::new(4).with_workers(2).analyze_directory(temp_dir.path()).unwrap();
+        let bounded = DuplicationAnalyzer::new(4)
+            .with_workers(2)
+            .with_max_cached_shards(1)
+            .analyze_directory(temp_dir.path())
+            .unwrap();
+        
+        assert!(bounded.complete);
+        assert_eq!(bounded.stats.total_blocks, 1011);
+        assert_eq!(bounded.stats.unique_blocks, 5);
+        assert_eq!(bounded.stats.space_saved, 28 + 3996);
+        assert_eq!(bounded.top_blocks[0].occurrences, 1000);
+        assert_eq!(bounded.top_blocks, unbounded.top_blocks);
+        assert_eq!(bounded.top_files, unbounded.top_files);
+        assert_eq!(bounded.stats.space_saved, unbounded.stats.space_saved);
+    }
+    
+    #[test]
+    fn test_analyzer_stops_when_cancelled() {
+        let temp_dir = TempDir::new().unwrap();
+        create_duplicate_tree(temp_dir.path());
+        
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let report = DuplicationAnalyzer::new(4)
+            .with_workers(2)
+            .with_cancellation(cancel)
+            .analyze_directory(temp_dir.path())
+            .unwrap();
+        assert!(!report.complete);
+        assert_eq!(report.files_scanned, 0);
+        assert_eq!(report.stats.total_size, 0);
+        assert!(report.top_blocks.is_empty() && report.top_files.is_empty());
+    }
+    
     #[test]
     fn test_file_deletion() {
         let temp_dir = TempDir::new().unwrap();
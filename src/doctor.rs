@@ -0,0 +1,841 @@
+//! `skylock doctor`: diagnostics for a Skylock installation
+//!
+//! Runs every check independently so one failure doesn't hide the others,
+//! and reports pass/warn/fail with a hint for anything that needs attention.
+//! Each check is a plain function over its inputs so failures can be
+//! injected in tests. `skylock test` runs the same checks for one component.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use colored::*;
+use serde::Serialize;
+use skylock_core::Config;
+use std::path::{Path, PathBuf};
+
+use crate::audit::AuditTrail;
+use crate::output::{self, DoctorFailed, OutputFormat};
+use crate::scheduler;
+use skylock_core::audit::{AuditOperation, EventOutcome};
+
+/// Remote file written and deleted by the storage check
+const PROBE_PATH: &str = "/skylock_doctor_probe.txt";
+
+/// Clock skew against the storage server that warns / fails
+const CLOCK_SKEW_WARN_SECS: i64 = 60;
+const CLOCK_SKEW_FAIL_SECS: i64 = 15 * 60;
+
+/// Free space in the temp directory below which the check warns / fails
+const TEMP_SPACE_WARN_BYTES: u64 = 1024 * 1024 * 1024;
+const TEMP_SPACE_FAIL_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Placeholder values written by `skylock config`
+pub(crate) const DEFAULT_ENDPOINT: &str = "https://your-username.your-server.de";
+pub(crate) const DEFAULT_USERNAME: &str = "your-username";
+const DEFAULT_PASSWORD: &str = "your-password";
+pub(crate) const DEFAULT_ENCRYPTION_KEY: &str = "your-encryption-key";
+
+/// Encryption keys shorter than this get a warning
+pub(crate) const MIN_ENCRYPTION_KEY_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    /// Stable identifier, e.g. "storage_writable"
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+    /// What to do about a warning or failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl CheckResult {
+    pub(crate) fn pass(name: &'static str, message: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Pass, message: message.into(), hint: None }
+    }
+
+    pub(crate) fn warn(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Warn, message: message.into(), hint: Some(hint.into()) }
+    }
+
+    pub(crate) fn fail(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Fail, message: message.into(), hint: Some(hint.into()) }
+    }
+}
+
+/// Output of `doctor`, also the `--format json` document
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+    /// No check failed (warnings allowed)
+    pub success: bool,
+    pub passed: usize,
+    pub warnings: usize,
+    pub failed: usize,
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    pub fn new(checks: Vec<CheckResult>) -> Self {
+        let count = |status| checks.iter().filter(|c| c.status == status).count();
+        let (passed, warnings, failed) = (count(CheckStatus::Pass), count(CheckStatus::Warn), count(CheckStatus::Fail));
+        Self { success: failed == 0, passed, warnings, failed, checks }
+    }
+}
+
+/// Storage operations the storage and clock checks need
+#[async_trait]
+pub trait StorageProbe {
+    /// List the root directory, returning the number of entries
+    async fn list_root(&self) -> Result<usize>;
+    /// Upload `data` to `remote_path`
+    async fn upload(&self, remote_path: &str, data: &[u8]) -> Result<()>;
+    /// Delete `remote_path`
+    async fn delete(&self, remote_path: &str) -> Result<()>;
+    /// Server time from the HTTP `Date` header, if it sends one
+    async fn server_time(&self) -> Result<Option<DateTime<Utc>>>;
+}
+
+/// [`StorageProbe`] for the configured Hetzner Storage Box
+struct WebDavProbe {
+    client: skylock_hetzner::HetznerWebDAVClient,
+    endpoint: String,
+    http: reqwest::Client,
+}
+
+impl WebDavProbe {
+    fn new(config: &Config) -> Result<Self> {
+        let client = skylock_hetzner::HetznerWebDAVClient::new(skylock_hetzner::WebDAVConfig {
+            base_url: config.hetzner.endpoint.clone(),
+            username: config.hetzner.username.clone(),
+            password: config.hetzner.password.clone(),
+            base_path: "/".to_string(),
+        })?;
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(15))
+            .build()?;
+        Ok(Self { client, endpoint: config.hetzner.endpoint.clone(), http })
+    }
+}
+
+#[async_trait]
+impl StorageProbe for WebDavProbe {
+    async fn list_root(&self) -> Result<usize> {
+        Ok(self.client.list_files("/").await?.len())
+    }
+
+    async fn upload(&self, remote_path: &str, data: &[u8]) -> Result<()> {
+        self.client.upload_bytes(data.to_vec(), remote_path).await
+    }
+
+    async fn delete(&self, remote_path: &str) -> Result<()> {
+        Ok(self.client.delete_file(remote_path).await?)
+    }
+
+    async fn server_time(&self) -> Result<Option<DateTime<Utc>>> {
+        // Any response carries a Date header, authenticated or not
+        let response = self.http.head(&self.endpoint).send().await?;
+        Ok(response.headers()
+            .get(reqwest::header::DATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map(|time| time.with_timezone(&Utc)))
+    }
+}
+
+/// Load the configuration and check the values other commands parse lazily
+pub fn check_config(config_path: Option<PathBuf>) -> (CheckResult, Option<Config>) {
+    const NAME: &str = "config";
+
+    let config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            return (
+                CheckResult::fail(NAME, format!("Cannot load configuration: {}", e),
+                    "Run `skylock config` to generate one, or pass --config <path>"),
+                None,
+            );
+        }
+    };
+
+    if let Some(ref algorithm) = config.backup.encryption_algorithm {
+        if let Err(e) = algorithm.parse::<skylock_backup::AeadAlgorithm>() {
+            return (
+                CheckResult::fail(NAME, format!("Invalid backup.encryption_algorithm: {}", e),
+                    "Use \"aes-256-gcm\" or \"chacha20-poly1305\", or remove the setting"),
+                Some(config),
+            );
+        }
+    }
+    if let Some(ref limit) = config.backup.max_speed_limit {
+        if let Err(e) = skylock_backup::parse_bandwidth_limit(limit) {
+            return (
+                CheckResult::fail(NAME, format!("Invalid backup.max_speed_limit: {}", e),
+                    "Use a value such as \"1.5M\", \"500K\" or \"0\" for unlimited"),
+                Some(config),
+            );
+        }
+    }
+
+    if let Some(ref protocol) = config.hetzner.protocol {
+        if let Err(e) = protocol.parse::<skylock_hetzner::StorageProtocol>() {
+            return (
+                CheckResult::fail(NAME, format!("Invalid hetzner.protocol: {}", e),
+                    "Use \"webdav\" or \"sftp\", or remove the setting"),
+                Some(config),
+            );
+        }
+        if protocol.eq_ignore_ascii_case("sftp") && config.hetzner.sftp.is_none() {
+            return (
+                CheckResult::fail(NAME, "hetzner.protocol is \"sftp\" but [hetzner.sftp] is missing",
+                    "Add a [hetzner.sftp] section with private_key (see `config.sample.toml`)"),
+                Some(config),
+            );
+        }
+    }
+
+    let result = if let Err(e) = scheduler::validate_cron_expression(&config.backup.schedule) {
+        CheckResult::warn(NAME, format!("{}; scheduled backups will not run", e),
+            "Use a 6-field expression such as \"0 0 2 * * *\" (see `skylock schedule --presets`)")
+    } else if config.backup.backup_paths.is_empty() {
+        CheckResult::warn(NAME, "No backup paths configured",
+            "Add backup_paths to the [backup] section, or pass paths to `skylock backup`")
+    } else if let Some(missing) = config.backup.backup_paths.iter().find(|p| !p.exists()) {
+        CheckResult::warn(NAME, format!("Backup path does not exist: {}", missing.display()),
+            "Fix or remove the path in backup_paths")
+    } else {
+        CheckResult::pass(NAME, format!("Configuration valid ({} backup paths)", config.backup.backup_paths.len()))
+    };
+    (result, Some(config))
+}
+
+/// Fail on the placeholder values from `skylock config`
+pub fn check_credentials(config: &Config) -> CheckResult {
+    const NAME: &str = "credentials";
+    const HINT: &str = "Edit the [hetzner] section of your config, or run `skylock store-credentials`";
+
+    let hetzner = &config.hetzner;
+    let mut unset = Vec::new();
+    if hetzner.endpoint.is_empty() || hetzner.endpoint == DEFAULT_ENDPOINT {
+        unset.push("endpoint");
+    }
+    if hetzner.username.is_empty() || hetzner.username == DEFAULT_USERNAME {
+        unset.push("username");
+    }
+    if hetzner.password.is_empty() || hetzner.password == DEFAULT_PASSWORD {
+        unset.push("password");
+    }
+    if hetzner.encryption_key.is_empty() || hetzner.encryption_key == DEFAULT_ENCRYPTION_KEY {
+        unset.push("encryption_key");
+    }
+
+    if !unset.is_empty() {
+        CheckResult::fail(NAME, format!("Not configured: {}", unset.join(", ")), HINT)
+    } else if hetzner.encryption_key.len() < MIN_ENCRYPTION_KEY_LEN {
+        CheckResult::warn(NAME,
+            format!("Encryption key is shorter than {} characters", MIN_ENCRYPTION_KEY_LEN),
+            "Use a long passphrase; changing it only affects new backups")
+    } else {
+        CheckResult::pass(NAME, format!("Credentials set for {}", hetzner.username))
+    }
+}
+
+/// Check that storage answers and accepts a write followed by a delete
+pub async fn check_storage(probe: &dyn StorageProbe) -> Vec<CheckResult> {
+    const REACHABLE: &str = "storage_reachable";
+    const WRITABLE: &str = "storage_writable";
+
+    let mut results = Vec::new();
+    match probe.list_root().await {
+        Ok(entries) => results.push(CheckResult::pass(REACHABLE, format!("Storage reachable ({} entries in /)", entries))),
+        Err(e) => {
+            results.push(CheckResult::fail(REACHABLE, format!("Cannot reach storage: {}", e),
+                "Check the endpoint URL, username, password and your network connection"));
+            return results;
+        }
+    }
+
+    let probe_data = format!("skylock doctor probe {}", Utc::now().to_rfc3339());
+    if let Err(e) = probe.upload(PROBE_PATH, probe_data.as_bytes()).await {
+        results.push(CheckResult::fail(WRITABLE, format!("Cannot write to storage: {}", e),
+            "Check that the account has write access and the storage box is not full"));
+        return results;
+    }
+    results.push(match probe.delete(PROBE_PATH).await {
+        Ok(()) => CheckResult::pass(WRITABLE, "Storage writable (probe uploaded and deleted)"),
+        Err(e) => CheckResult::warn(WRITABLE, format!("Probe uploaded but could not be deleted: {}", e),
+            format!("Delete {} manually; cleanup and retention need delete access", PROBE_PATH)),
+    });
+    results
+}
+
+/// Compare the local clock with the storage server's
+pub fn check_clock_skew(local: DateTime<Utc>, server: Result<Option<DateTime<Utc>>>) -> CheckResult {
+    const NAME: &str = "clock_skew";
+    const HINT: &str = "Enable time synchronisation (NTP); schedules and retention rely on the local clock";
+
+    let server = match server {
+        Ok(Some(server)) => server,
+        Ok(None) => return CheckResult::warn(NAME, "Server did not report its time", "Clock skew could not be checked"),
+        Err(e) => return CheckResult::warn(NAME, format!("Cannot read server time: {}", e), "Clock skew could not be checked"),
+    };
+
+    let skew = (local - server).num_seconds();
+    let message = format!("Local clock is {}s {} the server", skew.abs(), if skew >= 0 { "ahead of" } else { "behind" });
+    if skew.abs() >= CLOCK_SKEW_FAIL_SECS {
+        CheckResult::fail(NAME, message, HINT)
+    } else if skew.abs() >= CLOCK_SKEW_WARN_SECS {
+        CheckResult::warn(NAME, message, HINT)
+    } else {
+        CheckResult::pass(NAME, message)
+    }
+}
+
+/// Encode and decode `data`, failing when either step errors or the data
+/// doesn't survive the round trip
+pub fn check_round_trip(
+    name: &'static str,
+    data: &[u8],
+    encode: impl FnOnce(&[u8]) -> Result<Vec<u8>>,
+    decode: impl FnOnce(&[u8]) -> Result<Vec<u8>>,
+    hint: &str,
+) -> CheckResult {
+    let encoded = match encode(data) {
+        Ok(encoded) => encoded,
+        Err(e) => return CheckResult::fail(name, format!("Encoding failed: {}", e), hint),
+    };
+    match decode(&encoded) {
+        Ok(decoded) if decoded == data => {
+            CheckResult::pass(name, format!("Round trip OK ({} -> {} bytes)", data.len(), encoded.len()))
+        }
+        Ok(_) => CheckResult::fail(name, "Round trip returned different data", hint),
+        Err(e) => CheckResult::fail(name, format!("Decoding failed: {}", e), hint),
+    }
+}
+
+/// Encrypt and decrypt a sample with the backup cipher, and make sure a
+/// tampered ciphertext is rejected
+pub fn check_encryption(encryption: &skylock_backup::EncryptionManager) -> CheckResult {
+    const NAME: &str = "encryption";
+    const HINT: &str = "Reinstall Skylock; the encryption libraries are not working correctly";
+
+    let sample = b"Skylock encryption self-test";
+    let result = check_round_trip(
+        NAME,
+        sample,
+        |data| Ok(encryption.encrypt_with_aad(data, "doctor", "/self-test")?),
+        |data| Ok(encryption.decrypt_with_aad(data, "doctor", "/self-test")?),
+        HINT,
+    );
+    if result.status != CheckStatus::Pass {
+        return result;
+    }
+
+    let mut tampered = match encryption.encrypt_with_aad(sample, "doctor", "/self-test") {
+        Ok(tampered) => tampered,
+        Err(e) => return CheckResult::fail(NAME, format!("Encoding failed: {}", e), HINT),
+    };
+    let last = tampered.len() - 1;
+    tampered[last] ^= 0x01;
+    if encryption.decrypt_with_aad(&tampered, "doctor", "/self-test").is_ok() {
+        return CheckResult::fail(NAME, "Tampered ciphertext was accepted", HINT);
+    }
+    CheckResult::pass(NAME, format!("{} round trip and tamper detection OK", encryption.algorithm()))
+}
+
+/// Compress and decompress a sample with every supported algorithm
+pub fn check_compression() -> CheckResult {
+    use skylock_backup::compression::CompressionLevel;
+    use skylock_backup::{CompressionAlgorithm, CompressionEngine};
+    const NAME: &str = "compression";
+
+    let engine = CompressionEngine::new();
+    let sample = "Skylock compression self-test. ".repeat(200);
+    let algorithms = [CompressionAlgorithm::Zstd, CompressionAlgorithm::Lz4, CompressionAlgorithm::Brotli, CompressionAlgorithm::Gzip];
+    for algorithm in algorithms {
+        let result = check_round_trip(
+            NAME,
+            sample.as_bytes(),
+            |data| Ok(engine.compress_with_algorithm(data, algorithm, CompressionLevel::Default)?.data),
+            |data| Ok(engine.decompress_raw(data, algorithm)?),
+            "Reinstall Skylock; the compression libraries are not working correctly",
+        );
+        if result.status != CheckStatus::Pass {
+            return CheckResult { message: format!("{}: {}", algorithm, result.message), ..result };
+        }
+    }
+    CheckResult::pass(NAME, "zstd, lz4, brotli and gzip round trips OK")
+}
+
+/// Check that `dir` is writable and has room for temporary files, given the
+/// free space reported for it
+pub fn check_temp_space(dir: &Path, available: std::io::Result<u64>) -> CheckResult {
+    const NAME: &str = "temp_space";
+    const HINT: &str = "Free up space or point backup.temp_dir at a larger, writable directory";
+
+    if let Err(e) = tempfile::tempfile_in(dir) {
+        return CheckResult::fail(NAME, format!("Cannot write to {}: {}", dir.display(), e), HINT);
+    }
+    let available = match available {
+        Ok(available) => available,
+        Err(e) => return CheckResult::warn(NAME, format!("Cannot read free space of {}: {}", dir.display(), e), HINT),
+    };
+
+    let message = format!("{} free in {}", skylock_core::ByteSize(available), dir.display());
+    if available < TEMP_SPACE_FAIL_BYTES {
+        CheckResult::fail(NAME, message, HINT)
+    } else if available < TEMP_SPACE_WARN_BYTES {
+        CheckResult::warn(NAME, message, HINT)
+    } else {
+        CheckResult::pass(NAME, message)
+    }
+}
+
+/// Groups of checks: `doctor` runs them all, `test` one or all of the first three
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckGroup {
+    /// Configuration, credentials, storage access and clock skew
+    Storage,
+    /// Encryption self-test with the configured key and cipher
+    Encryption,
+    /// Compression self-test with every algorithm
+    Compression,
+    /// Room for temporary files
+    Environment,
+}
+
+impl CheckGroup {
+    pub const ALL: [CheckGroup; 4] = [Self::Storage, Self::Encryption, Self::Compression, Self::Environment];
+}
+
+/// Run all checks, skipping those that depend on a failed one
+pub async fn run_checks(config_path: Option<PathBuf>) -> Vec<CheckResult> {
+    run_check_groups(config_path, &CheckGroup::ALL).await
+}
+
+/// Run the checks of `groups`, skipping those that depend on a failed one
+///
+/// The configuration is loaded for every group that uses it, but its check
+/// is only reported with the storage checks, which can't run without it.
+pub async fn run_check_groups(config_path: Option<PathBuf>, groups: &[CheckGroup]) -> Vec<CheckResult> {
+    let wants = |group| groups.contains(&group);
+    let mut checks = Vec::new();
+
+    let mut config = None;
+    if groups.iter().any(|&group| group != CheckGroup::Compression) {
+        let (config_check, loaded) = check_config(config_path);
+        if wants(CheckGroup::Storage) {
+            checks.push(config_check);
+        }
+        config = loaded;
+    }
+
+    if wants(CheckGroup::Storage) {
+        checks.extend(storage_checks(config.as_mut()).await);
+    } else if let Some(ref mut config) = config {
+        // The storage checks resolve the credentials, including the key;
+        // a key that can't be resolved is tested as configured
+        let _ = config.resolve_credentials().await;
+    }
+    if wants(CheckGroup::Encryption) {
+        checks.push(configured_encryption_check(config.as_ref()));
+    }
+    if wants(CheckGroup::Compression) {
+        checks.push(check_compression());
+    }
+    if wants(CheckGroup::Environment) {
+        let temp_dir = match config {
+            Some(ref config) => skylock_backup::TempFiles::from_config(&config.backup),
+            None => skylock_backup::TempFiles::default(),
+        }.dir().to_path_buf();
+        let available = fs2::available_space(&temp_dir);
+        checks.push(check_temp_space(&temp_dir, available));
+    }
+
+    checks
+}
+
+/// Credentials, storage and clock checks, skipped without a configuration
+/// or usable credentials
+async fn storage_checks(config: Option<&mut Config>) -> Vec<CheckResult> {
+    let mut checks = Vec::new();
+    match config {
+        Some(config) => {
+            let credentials = match config.resolve_credentials().await {
+                Ok(()) => check_credentials(config),
+                Err(e) => CheckResult::fail("credentials", format!("Cannot read credential sources: {}", e),
+                    "Check the [credentials] section of your config"),
+            };
+            let configured = credentials.status != CheckStatus::Fail;
+            checks.push(credentials);
+
+            if configured {
+                AuditTrail::new(config).record(
+                    AuditOperation::CredentialRead,
+                    &format!("hetzner:{} (doctor)", config.hetzner.username),
+                    EventOutcome::Success,
+                );
+                match WebDavProbe::new(config) {
+                    Ok(probe) => {
+                        checks.extend(check_storage(&probe).await);
+                        checks.push(check_clock_skew(Utc::now(), probe.server_time().await));
+                    }
+                    Err(e) => checks.push(CheckResult::fail("storage_reachable",
+                        format!("Cannot create storage client: {}", e),
+                        "Check the endpoint URL in the [hetzner] section")),
+                }
+            } else {
+                for name in ["storage_reachable", "storage_writable", "clock_skew"] {
+                    checks.push(CheckResult::warn(name, "Skipped: credentials not configured", "Fix the credentials check first"));
+                }
+            }
+        }
+        None => {
+            for name in ["credentials", "storage_reachable", "storage_writable", "clock_skew"] {
+                checks.push(CheckResult::warn(name, "Skipped: no configuration", "Fix the config check first"));
+            }
+        }
+    }
+    checks
+}
+
+/// Encryption self-test with the configured key and cipher when there are
+/// any, else with a throwaway key
+fn configured_encryption_check(config: Option<&Config>) -> CheckResult {
+    let (key, algorithm) = match config {
+        Some(config) if !config.hetzner.encryption_key.is_empty() => (
+            config.hetzner.encryption_key.clone(),
+            config.backup.encryption_algorithm.as_deref()
+                .and_then(|name| name.parse().ok())
+                .unwrap_or_default(),
+        ),
+        _ => ("skylock-doctor-self-test".to_string(), Default::default()),
+    };
+    match skylock_backup::EncryptionManager::new(&key) {
+        Ok(encryption) => check_encryption(&encryption.with_algorithm(algorithm)),
+        Err(e) => CheckResult::fail("encryption", format!("Cannot derive encryption key: {}", e),
+            "Argon2 key derivation needs about 64 MiB of free memory"),
+    }
+}
+
+/// `skylock doctor`: print the checklist and fail if any check failed
+pub async fn run_doctor(config_path: Option<PathBuf>, format: OutputFormat) -> Result<()> {
+    if !format.is_json() {
+        println!("{}", "🩺 Skylock Doctor".bright_blue().bold());
+        println!();
+    }
+
+    let report = DoctorReport::new(run_checks(config_path).await);
+
+    if format.is_json() {
+        output::print_json(&report)?;
+    } else {
+        print_report(&report);
+    }
+
+    if report.failed > 0 {
+        return Err(DoctorFailed(report.failed).into());
+    }
+    Ok(())
+}
+
+pub(crate) fn print_report(report: &DoctorReport) {
+    for check in &report.checks {
+        let symbol = match check.status {
+            CheckStatus::Pass => "✓".bright_green(),
+            CheckStatus::Warn => "!".bright_yellow(),
+            CheckStatus::Fail => "✗".bright_red(),
+        };
+        println!("   {} {:<18} {}", symbol, check.name, check.message);
+        if let Some(ref hint) = check.hint {
+            println!("     {:<18} 💡 {}", "", hint.dimmed());
+        }
+    }
+
+    println!();
+    println!("   {} passed, {} warnings, {} failed",
+        report.passed.to_string().bright_green(),
+        report.warnings.to_string().bright_yellow(),
+        report.failed.to_string().bright_red()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn test_config() -> Config {
+        Config {
+            syncthing: skylock_core::SyncthingConfig {
+                api_key: String::new(),
+                api_url: String::new(),
+                folders: vec![],
+            },
+            hetzner: skylock_core::HetznerConfig {
+                endpoint: "https://u123.your-storagebox.de".to_string(),
+                username: "u123".to_string(),
+                password: "secret-password".to_string(),
+                encryption_key: "a long encryption passphrase".to_string(),
+                max_list_requests_per_second: None,
+                max_delete_requests_per_second: None,
+                connect_timeout_secs: 30,
+                read_timeout_secs: 120,
+                protocol: None,
+                sftp: None,
+            },
+            backup: skylock_core::BackupConfig {
+                vss_enabled: false,
+                schedule: "0 0 2 * * *".to_string(),
+                retention_days: 30,
+                backup_paths: vec![],
+                max_speed_limit: None,
+                encryption_algorithm: None,
+                encrypt_file_names: false,
+                max_chain_length: None,
+                canonical_manifests: false,
+                compress_manifests: false,
+                compression_algorithm: None,
+                compression_profiles: Default::default(),
+                blob_shard_depth: 2,
+                max_concurrent_uploads: None,
+                mirrors: Vec::new(),
+                temp_dir: None,
+                spill_threshold: None,
+                block_cache_size: None,
+                restore_dir: None,
+                stream_threshold: None,
+                max_file_size: None,
+                skip_oversized_files: false,
+                trash_days: None,
+                list_concurrency: None,
+                delete_concurrency: None,
+                require_signed_manifests: false,
+                manifest_public_key: None,
+                aead_frame_size: None,
+                hash_algorithm: None,
+                key_exchange: None,
+                retry: Default::default(),
+            },
+            ui: skylock_core::UiConfig {
+                always_prompt_deletions: false,
+                notification_enabled: false,
+                deletion_default: None,
+            },
+            notifications: Default::default(),
+            metrics: Default::default(),
+            credentials: Default::default(),
+            logging: Default::default(),
+            scrub: Default::default(),
+            data_dir: PathBuf::from("/tmp/skylock-doctor-test"),
+        }
+    }
+
+    /// Storage probe failing at a chosen step
+    #[derive(Default)]
+    struct FakeProbe {
+        unreachable: bool,
+        read_only: bool,
+        no_delete: bool,
+        uploads: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl StorageProbe for FakeProbe {
+        async fn list_root(&self) -> Result<usize> {
+            if self.unreachable {
+                anyhow::bail!("connection refused");
+            }
+            Ok(3)
+        }
+
+        async fn upload(&self, remote_path: &str, _data: &[u8]) -> Result<()> {
+            if self.read_only {
+                anyhow::bail!("403 Forbidden");
+            }
+            self.uploads.lock().unwrap().push(remote_path.to_string());
+            Ok(())
+        }
+
+        async fn delete(&self, _remote_path: &str) -> Result<()> {
+            if self.no_delete {
+                anyhow::bail!("405 Method Not Allowed");
+            }
+            Ok(())
+        }
+
+        async fn server_time(&self) -> Result<Option<DateTime<Utc>>> {
+            Ok(None)
+        }
+    }
+
+    fn statuses(results: &[CheckResult]) -> Vec<(&str, CheckStatus)> {
+        results.iter().map(|r| (r.name, r.status)).collect()
+    }
+
+    #[test]
+    fn test_config_check() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+
+        let (result, config) = check_config(Some(path.clone()));
+        assert_eq!(result.status, CheckStatus::Fail);
+        assert!(result.hint.unwrap().contains("skylock config"));
+        assert!(config.is_none());
+
+        let mut config = test_config();
+        config.backup.backup_paths = vec![dir.path().to_path_buf()];
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(check_config(Some(path.clone())).0.status, CheckStatus::Pass);
+
+        // A 5-field cron expression never fires
+        config.backup.schedule = "0 2 * * *".to_string();
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(check_config(Some(path.clone())).0.status, CheckStatus::Warn);
+
+        config.backup.encryption_algorithm = Some("des".to_string());
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+        let (result, config) = check_config(Some(path));
+        assert_eq!(result.status, CheckStatus::Fail);
+        assert!(result.message.contains("encryption_algorithm"));
+        assert!(config.is_some());
+    }
+
+    #[test]
+    fn test_credentials_check() {
+        assert_eq!(check_credentials(&test_config()).status, CheckStatus::Pass);
+
+        let mut config = test_config();
+        config.hetzner.username = DEFAULT_USERNAME.to_string();
+        config.hetzner.password = DEFAULT_PASSWORD.to_string();
+        let result = check_credentials(&config);
+        assert_eq!(result.status, CheckStatus::Fail);
+        assert_eq!(result.message, "Not configured: username, password");
+
+        let mut config = test_config();
+        config.hetzner.encryption_key = "short".to_string();
+        assert_eq!(check_credentials(&config).status, CheckStatus::Warn);
+    }
+
+    #[tokio::test]
+    async fn test_storage_check_with_injected_failures() {
+        let probe = FakeProbe::default();
+        let results = check_storage(&probe).await;
+        assert_eq!(statuses(&results), vec![
+            ("storage_reachable", CheckStatus::Pass),
+            ("storage_writable", CheckStatus::Pass),
+        ]);
+        assert_eq!(*probe.uploads.lock().unwrap(), vec![PROBE_PATH.to_string()]);
+
+        let results = check_storage(&FakeProbe { unreachable: true, ..Default::default() }).await;
+        assert_eq!(statuses(&results), vec![("storage_reachable", CheckStatus::Fail)]);
+        assert!(results[0].message.contains("connection refused"));
+
+        let results = check_storage(&FakeProbe { read_only: true, ..Default::default() }).await;
+        assert_eq!(results[1].status, CheckStatus::Fail);
+        assert!(results[1].message.contains("403"));
+
+        let results = check_storage(&FakeProbe { no_delete: true, ..Default::default() }).await;
+        assert_eq!(results[1].status, CheckStatus::Warn);
+        assert!(results[1].hint.as_ref().unwrap().contains(PROBE_PATH));
+    }
+
+    #[tokio::test]
+    async fn test_check_groups_run_only_their_checks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let missing = Some(dir.path().join("missing.toml"));
+        let names = |checks: &[CheckResult]| checks.iter().map(|c| c.name).collect::<Vec<_>>();
+
+        // Compression doesn't need a configuration at all
+        let checks = run_check_groups(missing.clone(), &[CheckGroup::Compression]).await;
+        assert_eq!(names(&checks), vec!["compression"]);
+        assert_eq!(checks[0].status, CheckStatus::Pass);
+
+        // Storage reports the missing configuration and skips the rest
+        let checks = run_check_groups(missing, &[CheckGroup::Storage]).await;
+        assert_eq!(names(&checks), vec!["config", "credentials", "storage_reachable", "storage_writable", "clock_skew"]);
+        assert_eq!(checks[0].status, CheckStatus::Fail);
+        assert!(checks[1..].iter().all(|c| c.message.starts_with("Skipped")));
+    }
+
+    #[test]
+    fn test_clock_skew_check() {
+        let now = Utc::now();
+        let at = |secs| Ok(Some(now - chrono::Duration::seconds(secs)));
+
+        assert_eq!(check_clock_skew(now, at(5)).status, CheckStatus::Pass);
+        assert_eq!(check_clock_skew(now, at(-120)).status, CheckStatus::Warn);
+        let result = check_clock_skew(now, at(3600));
+        assert_eq!(result.status, CheckStatus::Fail);
+        assert_eq!(result.message, "Local clock is 3600s ahead of the server");
+
+        assert_eq!(check_clock_skew(now, Ok(None)).status, CheckStatus::Warn);
+        assert_eq!(check_clock_skew(now, Err(anyhow::anyhow!("timeout"))).status, CheckStatus::Warn);
+    }
+
+    #[test]
+    fn test_round_trip_check_with_injected_failures() {
+        let identity = |data: &[u8]| Ok(data.to_vec());
+
+        assert_eq!(check_round_trip("codec", b"data", identity, identity, "").status, CheckStatus::Pass);
+
+        let corrupt = |data: &[u8]| Ok(data.iter().map(|b| b ^ 1).collect());
+        let result = check_round_trip("codec", b"data", identity, corrupt, "hint");
+        assert_eq!(result.status, CheckStatus::Fail);
+        assert_eq!(result.message, "Round trip returned different data");
+
+        let broken = |_: &[u8]| -> Result<Vec<u8>> { anyhow::bail!("library missing") };
+        let result = check_round_trip("codec", b"data", broken, identity, "hint");
+        assert_eq!(result.status, CheckStatus::Fail);
+        assert!(result.message.contains("library missing"));
+    }
+
+    #[test]
+    fn test_self_tests_pass() {
+        let encryption = skylock_backup::EncryptionManager::new("doctor test password").unwrap();
+        assert_eq!(check_encryption(&encryption).status, CheckStatus::Pass);
+        let chacha = encryption.with_algorithm(skylock_backup::AeadAlgorithm::ChaCha20Poly1305);
+        assert_eq!(check_encryption(&chacha).status, CheckStatus::Pass);
+        assert_eq!(check_compression().status, CheckStatus::Pass);
+    }
+
+    #[test]
+    fn test_temp_space_check() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let gb = 1024 * 1024 * 1024;
+
+        assert_eq!(check_temp_space(dir.path(), Ok(10 * gb)).status, CheckStatus::Pass);
+        assert_eq!(check_temp_space(dir.path(), Ok(gb / 2)).status, CheckStatus::Warn);
+        assert_eq!(check_temp_space(dir.path(), Ok(1024)).status, CheckStatus::Fail);
+
+        let missing = dir.path().join("missing");
+        let result = check_temp_space(&missing, Ok(10 * gb));
+        assert_eq!(result.status, CheckStatus::Fail);
+        assert!(result.message.contains("Cannot write"));
+    }
+
+    #[test]
+    fn test_report_counts_and_json() {
+        let report = DoctorReport::new(vec![
+            CheckResult::pass("config", "ok"),
+            CheckResult::warn("clock_skew", "unknown", "hint"),
+            CheckResult::fail("storage_reachable", "down", "check network"),
+        ]);
+        assert!(!report.success);
+        assert_eq!((report.passed, report.warnings, report.failed), (1, 1, 1));
+
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["checks"][0]["status"], "pass");
+        assert!(value["checks"][0].get("hint").is_none());
+        assert_eq!(value["checks"][2]["status"], "fail");
+        assert_eq!(value["checks"][2]["hint"], "check network");
+    }
+}
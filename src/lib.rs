@@ -7,7 +7,7 @@ pub mod config;
 pub mod backup;
 pub mod stubs;
 pub mod crypto;
-pub mod compression;
+pub use skylock_backup::compression;
 pub mod deduplication;
 pub mod logging;
 pub mod error_display;
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -13,9 +13,20 @@ mod progress;
 mod notifications;
 mod cleanup;
 mod scheduler;
+mod output;
+mod audit;
+mod doctor;
+mod check_config;
+mod time_filter;
+mod metrics;
+mod shutdown;
+mod restore_target;
 
 use skylock_core::Config;
 use stubs::*;
+use output::{CliError, ErrorKind, OutputFormat};
+use skylock_core::audit::{AuditOperation, EventOutcome};
+use skylock_core::storage::StorageTier;
 
 pub struct ApplicationState {
     config: Arc<Config>,
@@ -70,6 +81,15 @@ struct Cli {
     /// Mount points to initialize
     #[arg(short, long)]
     mounts: Option<Vec<String>>,
+
+    /// Output format for list, diff, verify and changes
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Never prompt: accept confirmations and fail fast where input is required
+    /// (same as SKYLOCK_NONINTERACTIVE=1)
+    #[arg(short = 'y', long, global = true)]
+    yes: bool,
 }
 
 #[derive(Parser)]
@@ -80,12 +100,12 @@ enum Commands {
         #[arg(long)]
         with_config: bool,
     },
-    /// Store credentials securely
+    /// Store credentials in the OS keychain or the encrypted credentials file
     StoreCredentials {
-        /// Hetzner username
+        /// Hetzner username (falls back to SKYLOCK_HETZNER_USERNAME, then a prompt)
         #[arg(long)]
         username: Option<String>,
-        /// Hetzner password (will prompt if not provided)
+        /// Hetzner password (falls back to SKYLOCK_HETZNER_PASSWORD, then a prompt)
         #[arg(long)]
         password: Option<String>,
     },
@@ -105,19 +125,111 @@ enum Commands {
         /// Create incremental backup (only changed files)
         #[arg(long)]
         incremental: bool,
-        /// Maximum upload speed (e.g., "1.5M", "500K", "0" for unlimited)
+        /// Maximum upload speed per second (e.g., "1.5M", "500K", "0" for
+        /// unlimited); K/M/G and KiB/MiB/GiB are binary, KB/MB/GB decimal
         #[arg(long)]
         max_speed: Option<String>,
+        /// Capture extended attributes (direct upload mode only)
+        #[arg(long)]
+        xattrs: bool,
+        /// Read back each uploaded file and check its hash (direct upload mode only)
+        #[arg(long)]
+        verify_on_upload: bool,
+        /// Re-upload attempts for a file that fails --verify-on-upload
+        #[arg(long, default_value = "2")]
+        verify_retries: u32,
+        /// Storage tier: "standard", or "archive" for rarely restored backups
+        /// (placed in cold storage where the backend supports it)
+        #[arg(long, default_value = "standard")]
+        tier: StorageTier,
+        /// Compress every file with this algorithm for this run: none, lz4,
+        /// zstd, brotli or gzip (direct upload mode only; default is
+        /// backup.compression_algorithm, else picked per file)
+        #[arg(long)]
+        compression_algo: Option<skylock_backup::CompressionAlgorithm>,
+        /// Compression level for this run: fastest, fast, default, better,
+        /// best, or a number valid for --compression-algo
+        #[arg(long)]
+        compression_level: Option<skylock_backup::compression::CompressionLevel>,
+        /// Transfer up to N files at once, overriding
+        /// backup.max_concurrent_uploads (at most 32)
+        #[arg(long, value_name = "N")]
+        concurrency: Option<usize>,
+        /// Label the backup (repeatable or comma-separated); backups tagged
+        /// "keep" are never removed by cleanup
+        #[arg(long = "tag", value_name = "TAG", value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Free-form note stored with the backup
+        #[arg(long)]
+        note: Option<String>,
+        /// Back up only files matching this glob, e.g. "*.docx" (repeatable;
+        /// direct upload mode only)
+        #[arg(long = "include-only", value_name = "GLOB")]
+        include_only: Vec<String>,
+        /// Skip files and directories matching this glob, even if
+        /// --include-only matches them (repeatable; direct upload mode only)
+        #[arg(long = "exclude", value_name = "GLOB")]
+        exclude: Vec<String>,
     },
     /// Restore from backup
     Restore {
-        /// Backup ID to restore from
-        backup_id: String,
+        /// Backup ID to restore from (omit with --latest)
+        #[arg(required_unless_present = "latest")]
+        backup_id: Option<String>,
         /// Target directory for restoration
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with_all = ["output_dir", "name_template"])]
         target: Option<PathBuf>,
-        /// Restore specific files/directories (relative to backup)
+        /// Create the restore directory in DIR instead of backup.restore_dir
+        /// or the current directory
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+        /// Name of the restore directory, with {backup_id}, {date}, {time},
+        /// {timestamp} and {source} replaced; `/` nests directories
+        /// [default: restore_{timestamp}]
+        #[arg(long, value_name = "TEMPLATE")]
+        name_template: Option<String>,
+        /// Restore only these files/directories, as recorded in the backup;
+        /// only their files are downloaded
         paths: Vec<PathBuf>,
+        /// Reapply extended attributes stored in the backup
+        #[arg(long)]
+        xattrs: bool,
+        /// Restore paths under FROM below TO instead (repeatable; the
+        /// longest matching FROM wins), e.g. --map /home/alice=/home/bob
+        #[arg(long = "map", value_name = "FROM=TO")]
+        map: Vec<String>,
+        /// Transfer up to N files at once, overriding
+        /// backup.max_concurrent_uploads (at most 32)
+        #[arg(long, value_name = "N")]
+        concurrency: Option<usize>,
+        /// Overwrite files that already exist at the target
+        #[arg(long)]
+        force: bool,
+        /// Restore even if the target doesn't appear to have enough free space
+        #[arg(long)]
+        no_space_check: bool,
+        /// Leave files that already exist at the target untouched and only
+        /// restore the missing ones
+        #[arg(long, conflicts_with = "force")]
+        skip_existing: bool,
+        /// Restore the newest backup (matching --pattern, --tag and --source)
+        /// instead of naming one
+        #[arg(long)]
+        latest: bool,
+        /// With --latest: glob (`*`, `?`) or substring the backup ID must match
+        #[arg(long, requires = "latest")]
+        pattern: Option<String>,
+        /// With --latest: tag the backup must carry
+        #[arg(long, requires = "latest")]
+        tag: Option<String>,
+        /// With --latest: path the backup must hold (a backed up path or
+        /// anything below one)
+        #[arg(long, value_name = "PATH", requires = "latest")]
+        source: Option<PathBuf>,
+        /// Show which files would be created, overwritten or left as they
+        /// are at the target, without restoring anything
+        #[arg(long)]
+        preview: bool,
     },
     /// Restore a single file from backup
     RestoreFile {
@@ -125,9 +237,12 @@ enum Commands {
         backup_id: String,
         /// Path of file in backup
         file_path: String,
-        /// Where to save the restored file
+        /// Where to save the restored file (`-` writes it to stdout)
         #[arg(short, long)]
         output: PathBuf,
+        /// Reapply extended attributes stored in the backup
+        #[arg(long)]
+        xattrs: bool,
     },
     /// Preview backup contents before restoring
     Preview {
@@ -148,18 +263,67 @@ enum Commands {
         backup_id: String,
         /// File path within backup
         file_path: String,
-        /// Maximum lines to display
+        /// Maximum lines to display (hexdump rows for binary files)
         #[arg(short, long, default_value = "50")]
         lines: usize,
+        /// Print binary files raw instead of as a hexdump
+        #[arg(long)]
+        force: bool,
+        /// Show the last lines instead of the first; large files only
+        /// download the chunks holding them
+        #[arg(long)]
+        tail: bool,
     },
     /// List available backups
     List {
         /// Show detailed information
         #[arg(short, long)]
         detailed: bool,
-        /// Filter by backup name pattern
+        /// Filter by backup name: a glob (`*`, `?`) over the whole ID, or
+        /// a substring
         #[arg(short, long)]
         pattern: Option<String>,
+        /// Only backups created at or after this time (RFC 3339, YYYY-MM-DD, or relative like 24h, 7d)
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+        /// Only backups created at or before this time (same formats as --since)
+        #[arg(long, value_name = "TIME")]
+        until: Option<String>,
+        /// Show at most this many of the newest matching backups
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+        /// Only backups carrying this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only backups holding this path (a backed up path or anything
+        /// below one)
+        #[arg(long, value_name = "PATH")]
+        source: Option<PathBuf>,
+    },
+    /// Find files matching a glob across backups, with their size, hash
+    /// and modification time in each backup holding them
+    Find {
+        /// Path glob: `*` and `?` match within a directory, `**` across
+        /// directories; without a `/` only file names are matched
+        glob: String,
+        /// Only search backups created at or after this time (same formats
+        /// as list --since)
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+        /// Only search backups created at or before this time
+        #[arg(long, value_name = "TIME")]
+        until: Option<String>,
+        /// Only search backups carrying this tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Show how recent backup runs went: duration, data uploaded and
+    /// deduplicated, compression and throughput, from each backup's
+    /// summary.json
+    Stats {
+        /// Number of newest backups to show
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
     },
     /// Test Hetzner connection
     Test {
@@ -167,6 +331,10 @@ enum Commands {
         #[arg(value_enum)]
         component: Option<TestComponent>,
     },
+    /// Diagnose configuration, storage and environment problems
+    Doctor,
+    /// Validate every configuration field without contacting storage
+    CheckConfig,
     /// Generate default configuration
     Config {
         /// Output path for config file
@@ -181,6 +349,27 @@ enum Commands {
         /// Force deletion without confirmation
         #[arg(short, long)]
         force: bool,
+        /// Delete backups permanently instead of moving them to the trash
+        #[arg(long)]
+        purge: bool,
+        /// GFS: keep the newest backup of each of the last N days
+        #[arg(long)]
+        keep_daily: Option<usize>,
+        /// GFS: keep the newest backup of each of the last N weeks
+        #[arg(long)]
+        keep_weekly: Option<usize>,
+        /// GFS: keep the newest backup of each of the last N months
+        #[arg(long)]
+        keep_monthly: Option<usize>,
+        /// GFS: keep the newest backup of each of the last N years
+        #[arg(long)]
+        keep_yearly: Option<usize>,
+    },
+    /// Recover a backup that cleanup moved to the trash (lists the trash
+    /// without an ID)
+    RestoreDeleted {
+        /// Backup ID to recover
+        backup_id: Option<String>,
     },
     /// Validate and test cron schedule expressions
     Schedule {
@@ -202,6 +391,28 @@ enum Commands {
         /// Show only specific change types (added, removed, modified, moved)
         #[arg(short, long, value_delimiter = ',')]
         filter: Option<Vec<String>>,
+        /// List the N modified files whose size changed the most
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+    },
+    /// Compare a backup with the files on disk now
+    CompareLocal {
+        /// Backup ID to compare against
+        backup_id: String,
+        /// Directory (or file) to compare, as it was backed up
+        path: PathBuf,
+        /// Show detailed file list (default: summary only)
+        #[arg(short, long)]
+        detailed: bool,
+        /// Show only specific change types (added, removed, modified, moved)
+        #[arg(short, long, value_delimiter = ',')]
+        filter: Option<Vec<String>>,
+        /// Only compare local files matching this glob (repeatable)
+        #[arg(long = "include-only", value_name = "GLOB")]
+        include_only: Vec<String>,
+        /// Ignore local files and directories matching this glob (repeatable)
+        #[arg(long = "exclude", value_name = "GLOB")]
+        exclude: Vec<String>,
     },
     /// Show file changes since last backup
     Changes {
@@ -211,6 +422,21 @@ enum Commands {
         #[arg(short, long)]
         summary: bool,
     },
+    /// Scan a local directory for duplicated blocks and files and show how
+    /// much deduplication would save (Ctrl-C stops early with partial figures)
+    Duplicates {
+        /// Directory to scan
+        path: PathBuf,
+        /// Block size in bytes
+        #[arg(long, value_name = "BYTES", default_value_t = 64 * 1024)]
+        block_size: usize,
+        /// Hash up to N files at once (defaults to the number of CPUs)
+        #[arg(long, value_name = "N")]
+        workers: Option<usize>,
+        /// Number of top duplicated blocks and files to show
+        #[arg(long, value_name = "N", default_value_t = skylock_hybrid::deduplication::DEFAULT_TOP_DUPLICATES)]
+        top: usize,
+    },
     /// Verify backup integrity
     Verify {
         /// Backup ID to verify
@@ -218,7 +444,77 @@ enum Commands {
         /// Perform full verification (download and verify hashes)
         #[arg(short, long)]
         full: bool,
+        /// Rewrite damaged or missing blobs from an intact copy on a
+        /// configured mirror (implies --full)
+        #[arg(long)]
+        repair: bool,
+        /// Transfer up to N files at once while checking, overriding
+        /// backup.max_concurrent_uploads (at most 32)
+        #[arg(long, value_name = "N")]
+        concurrency: Option<usize>,
+    },
+    /// Rotate the encryption key (re-wraps the keyfile, no backup data is re-encrypted)
+    RotateKey {
+        /// Reason recorded in the key rotation history
+        #[arg(long, default_value = "manual rotation")]
+        reason: String,
+    },
+    /// Print a recovery key for the keyfile to store offline (wrapped keys
+    /// and KDF parameters only; useless without the passphrase)
+    ExportKey {
+        /// Write the recovery key to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Recreate the keyfile from a recovery key made by export-key
+    ImportKey {
+        /// File holding the recovery key (`-` or omitted reads stdin)
+        input: Option<PathBuf>,
+        /// Replace an existing keyfile
+        #[arg(long)]
+        force: bool,
+    },
+    /// Store the configuration (without secrets) and the keyfile's wrapped
+    /// keys on the storage box, encrypted with the passphrase
+    BackupConfig,
+    /// Recreate the configuration and keyfile on a new machine from the
+    /// bundle stored by backup-config
+    RecoverConfig {
+        /// Write the configuration here instead of the config path in use
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Replace an existing configuration file and keyfile
+        #[arg(long)]
+        force: bool,
+    },
+    /// Create the Ed25519 key pair backup manifests are signed with, in the
+    /// data directory; new backups are signed from then on
+    GenerateManifestKey {
+        /// Replace an existing key (manifests signed with it stop verifying)
+        #[arg(long)]
+        force: bool,
+    },
+    /// Sign the manifests of existing backups with the manifest key, e.g.
+    /// ones made before it was generated
+    SignManifest {
+        /// Backup IDs whose manifests to sign
+        #[arg(required = true)]
+        backup_ids: Vec<String>,
     },
+    /// Rebuild the local change tracking index from a remote backup manifest
+    Reindex {
+        /// Backup ID to rebuild from (defaults to the newest backup)
+        backup_id: Option<String>,
+    },
+    /// Run the daemon at boot as a systemd user unit, launchd agent or
+    /// Windows Scheduled Task, with this binary and config file
+    InstallService {
+        /// Print the service definition instead of installing it
+        #[arg(long)]
+        print: bool,
+    },
+    /// Stop and remove the service added by install-service
+    UninstallService,
 }
 
 #[derive(clap::ValueEnum, Clone)]
@@ -229,7 +525,7 @@ enum TestComponent {
     All,
 }
 
-async fn handle_command(command: Commands, config_path: Option<PathBuf>) -> Result<()> {
+async fn handle_command(command: Commands, config_path: Option<PathBuf>, format: OutputFormat) -> Result<()> {
     match command {
         Commands::Init { with_config } => {
             println!("🚀 Initializing Skylock...");
@@ -239,13 +535,18 @@ async fn handle_command(command: Commands, config_path: Option<PathBuf>) -> Resu
             initialize_application().await
         }
         Commands::StoreCredentials { username, password } => {
-            store_credentials_interactive(username, password).await
+            store_credentials_interactive(username, password, config_path).await
         }
-        Commands::Backup { paths, name, force, direct, incremental, max_speed } => {
-            perform_backup(paths, name, force, direct, incremental, config_path, max_speed).await
+        Commands::Backup { paths, name, force, direct, incremental, max_speed, xattrs, verify_on_upload, verify_retries, tier, compression_algo, compression_level, concurrency, tags, note, include_only, exclude } => {
+            let verify_on_upload = verify_on_upload.then_some(verify_retries);
+            let compression = skylock_backup::CompressionOverride::new(compression_algo, compression_level)
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            let tags = normalize_tags(tags)?;
+            let filter = skylock_backup::FileFilter::new(&include_only, &exclude);
+            perform_backup(paths, name, force, direct, incremental, config_path, max_speed, xattrs, verify_on_upload, tier, compression, concurrency, tags, note, filter).await
         }
-        Commands::RestoreFile { backup_id, file_path, output } => {
-            perform_restore_file(backup_id, file_path, output, config_path).await
+        Commands::RestoreFile { backup_id, file_path, output, xattrs } => {
+            perform_restore_file(backup_id, file_path, output, config_path, xattrs).await
         }
         Commands::Preview { backup_id, target } => {
             perform_preview(backup_id, target, config_path).await
@@ -253,35 +554,122 @@ async fn handle_command(command: Commands, config_path: Option<PathBuf>) -> Resu
         Commands::Browse { backup_id } => {
             perform_browse(backup_id, config_path).await
         }
-        Commands::PreviewFile { backup_id, file_path, lines } => {
-            perform_preview_file(backup_id, file_path, lines, config_path).await
+        Commands::PreviewFile { backup_id, file_path, lines, force, tail } => {
+            perform_preview_file(backup_id, file_path, lines, force, tail, config_path).await
+        }
+        Commands::Restore { backup_id, target, output_dir, name_template, mut paths, xattrs, map, concurrency, force, no_space_check, skip_existing, latest, pattern, tag, source, preview } => {
+            let path_map = skylock_backup::PathMap::parse(&map)
+                .map_err(|e| CliError::new(ErrorKind::Config, e.to_string()))?;
+            let selection = restore_selection(backup_id, latest, &mut paths, pattern, tag, source);
+            let target = match target {
+                Some(target) => RestoreTarget::Path(target),
+                None => RestoreTarget::Template { output_dir, name_template },
+            };
+            let policy = if force {
+                skylock_backup::RestorePolicy::Overwrite
+            } else if skip_existing {
+                skylock_backup::RestorePolicy::SkipExisting
+            } else {
+                skylock_backup::RestorePolicy::Abort
+            };
+            perform_restore(selection, target, paths, config_path, xattrs, path_map, concurrency, !no_space_check, policy, preview).await
+        }
+        Commands::List { detailed, pattern, since, until, limit, tag, source } => {
+            let filter = time_filter::BackupFilter::from_args(
+                pattern,
+                since.as_deref(),
+                until.as_deref(),
+                limit,
+                Utc::now(),
+            )?.with_tag(tag).with_source(source);
+            list_backups(detailed, filter, config_path, format).await
         }
-        Commands::Restore { backup_id, target, paths } => {
-            perform_restore(backup_id, target, paths, config_path).await
+        Commands::Find { glob, since, until, tag } => {
+            let filter = time_filter::BackupFilter::from_args(None, since.as_deref(), until.as_deref(), None, Utc::now())?
+                .with_tag(tag);
+            find_files(glob, filter, config_path, format).await
         }
-        Commands::List { detailed, pattern } => {
-            list_backups(detailed, pattern, config_path).await
+        Commands::Stats { limit } => {
+            show_stats(limit, config_path, format).await
         }
         Commands::Test { component } => {
-            run_tests(component).await
+            run_tests(component, config_path, format).await
+        }
+        Commands::Doctor => {
+            doctor::run_doctor(config_path, format).await
+        }
+        Commands::CheckConfig => {
+            check_config::run_check_config(config_path, format).await
         }
         Commands::Config { output } => {
             generate_default_config(output).await
         }
-        Commands::Cleanup { dry_run, force } => {
-            cleanup::perform_cleanup(dry_run, force, config_path).await
+        Commands::Cleanup { dry_run, force, purge, keep_daily, keep_weekly, keep_monthly, keep_yearly } => {
+            // Any --keep-* flag switches cleanup to GFS rotation
+            let gfs = if keep_daily.is_some() || keep_weekly.is_some() || keep_monthly.is_some() || keep_yearly.is_some() {
+                Some(skylock_backup::GfsPolicy {
+                    keep_hourly: None,
+                    keep_daily,
+                    keep_weekly,
+                    keep_monthly,
+                    keep_yearly,
+                })
+            } else {
+                None
+            };
+            cleanup::perform_cleanup(dry_run, force, purge, gfs, config_path).await
+        }
+        Commands::RestoreDeleted { backup_id } => {
+            cleanup::restore_deleted(backup_id, config_path).await
         }
         Commands::Schedule { expression, presets } => {
             test_schedule(expression, presets).await
         }
-        Commands::Diff { backup_id_old, backup_id_new, detailed, filter } => {
-            perform_diff(backup_id_old, backup_id_new, detailed, filter, config_path).await
+        Commands::Diff { backup_id_old, backup_id_new, detailed, filter, top } => {
+            perform_diff(backup_id_old, backup_id_new, detailed, filter, top, config_path, format).await
+        }
+        Commands::CompareLocal { backup_id, path, detailed, filter, include_only, exclude } => {
+            let local_filter = skylock_backup::FileFilter::new(&include_only, &exclude);
+            compare_local(backup_id, path, detailed, filter, local_filter, config_path, format).await
         }
         Commands::Changes { paths, summary } => {
-            show_file_changes(paths, summary, config_path).await
+            show_file_changes(paths, summary, config_path, format).await
+        }
+        Commands::Duplicates { path, block_size, workers, top } => {
+            analyze_duplicates(path, block_size, workers, top, format).await
+        }
+        Commands::Verify { backup_id, full, repair, concurrency } => {
+            verify_backup(backup_id, full, repair, concurrency, config_path, format).await
+        }
+        Commands::RotateKey { reason } => {
+            rotate_key(reason, config_path).await
+        }
+        Commands::ExportKey { output } => {
+            export_key(output, config_path).await
+        }
+        Commands::ImportKey { input, force } => {
+            import_key(input, force, config_path).await
+        }
+        Commands::BackupConfig => {
+            backup_config(config_path).await
+        }
+        Commands::RecoverConfig { output, force } => {
+            recover_config(output, force, config_path).await
+        }
+        Commands::GenerateManifestKey { force } => {
+            generate_manifest_key(force, config_path).await
+        }
+        Commands::SignManifest { backup_ids } => {
+            sign_manifests(backup_ids, config_path).await
+        }
+        Commands::Reindex { backup_id } => {
+            rebuild_index(backup_id, config_path).await
         }
-        Commands::Verify { backup_id, full } => {
-            verify_backup(backup_id, full, config_path).await
+        Commands::InstallService { print } => {
+            install_service(print, config_path)
+        }
+        Commands::UninstallService => {
+            uninstall_service()
         }
     }
 }
@@ -300,6 +688,12 @@ async fn generate_default_config(output: Option<PathBuf>) -> Result<()> {
             username: "your-username".to_string(),
             password: "your-password".to_string(),
             encryption_key: "your-encryption-key".to_string(),
+            max_list_requests_per_second: None, // Unlimited listing requests
+            max_delete_requests_per_second: None, // Unlimited deletion requests
+            connect_timeout_secs: 30,
+            read_timeout_secs: 120, // Fail stalled transfers after two minutes without progress
+            protocol: None, // WebDAV; "sftp" needs the [hetzner.sftp] settings
+            sftp: None,
         },
         backup: skylock_core::BackupConfig {
             vss_enabled: true,
@@ -307,11 +701,43 @@ async fn generate_default_config(output: Option<PathBuf>) -> Result<()> {
             retention_days: 30,
             backup_paths: vec![],
             max_speed_limit: None, // No bandwidth limit by default
+            encryption_algorithm: None, // AES-256-GCM by default
+            encrypt_file_names: false, // Plain file names on the storage box by default
+            max_chain_length: None, // Never consolidate incremental chains by default
+            canonical_manifests: false, // Keep manifest entries in upload order by default
+            compress_manifests: false, // zstd-compress manifests before encryption
+            compression_algorithm: None, // e.g. "gzip" for blobs readable with gunzip
+            compression_profiles: Default::default(), // e.g. "*.jpg" = "none", "*.log" = "zstd:19"
+            blob_shard_depth: 2, // Spread hash-named blobs over ab/cd/ directories
+            max_concurrent_uploads: None, // Pick concurrency from the number of cores
+            mirrors: Vec::new(), // No mirror copies to repair from by default
+            temp_dir: None, // SKYLOCK_TEMP_DIR, else the system temp directory
+            spill_threshold: None, // Keep transfers up to 8 MiB in memory
+            block_cache_size: None, // Cache 64 MiB of decrypted blocks when browsing
+            restore_dir: None, // Restore into the current directory
+            stream_threshold: None, // Read every file into memory
+            max_file_size: None, // No limit on file size
+            skip_oversized_files: false, // Stream files over max_file_size
+            trash_days: None, // Keep deleted backups recoverable for 14 days
+            list_concurrency: None, // Download 8 manifests at once when listing
+            delete_concurrency: None, // Delete 8 blobs at once when removing a backup
+            require_signed_manifests: false, // Load unsigned manifests too
+            manifest_public_key: None, // Key from `skylock generate-manifest-key`
+            aead_frame_size: None, // Stream large files in 16 MiB segments
+            hash_algorithm: None, // SHA-256
+            key_exchange: None, // X25519 only
+            retry: Default::default(), // No retries
         },
         ui: skylock_core::UiConfig {
             always_prompt_deletions: true,
             notification_enabled: true,
+            deletion_default: None,
         },
+        notifications: skylock_core::NotificationsConfig::default(),
+        metrics: skylock_core::MetricsConfig::default(), // Metrics endpoint off by default
+        credentials: skylock_core::CredentialsConfig::default(), // env, keychain, then encrypted file
+        logging: skylock_core::LoggingConfig::default(), // Text on the console at info level
+        scrub: skylock_core::ScrubConfig::default(), // Background scrub off by default
         data_dir: directories::ProjectDirs::from("com", "skylock", "skylock-hybrid")
             .map(|dirs| dirs.data_dir().to_path_buf())
             .unwrap_or_else(|| PathBuf::from("./data")),
@@ -326,52 +752,81 @@ async fn generate_default_config(output: Option<PathBuf>) -> Result<()> {
     // Create directory if it doesn't exist
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)
-            .map_err(|e| anyhow::anyhow!("Failed to create config directory: {}", e))?;
+            .context("Failed to create config directory")?;
     }
 
     let config_str = toml::to_string_pretty(&config)
-        .map_err(|e| anyhow::anyhow!("Failed to serialize config: {}", e))?;
+        .context("Failed to serialize config")?;
 
     std::fs::write(&path, config_str)
-        .map_err(|e| anyhow::anyhow!("Failed to write config file: {}", e))?;
+        .context("Failed to write config file")?;
 
     println!("📝 Configuration file created at: {}", path.display());
     println!("⚠️  Please edit the configuration file with your actual credentials and paths.");
     Ok(())
 }
 
-async fn store_credentials_interactive(username: Option<String>, password: Option<String>) -> Result<()> {
+async fn store_credentials_interactive(username: Option<String>, password: Option<String>, config_path: Option<PathBuf>) -> Result<()> {
     println!("🔐 Storing Hetzner credentials...");
     
-    let username = match username {
+    use skylock_core::security::credentials::{self, CredentialChain};
+    use skylock_ui::interactive;
+    
+    let username = match username.or_else(|| std::env::var("SKYLOCK_HETZNER_USERNAME").ok()) {
         Some(u) => u,
-        None => {
-            use std::io::{self, Write};
-            print!("Enter Hetzner username: ");
-            io::stdout().flush().unwrap();
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            input.trim().to_string()
-        }
+        None => interactive::prompt_line(
+            "Enter Hetzner username: ",
+            "Hetzner username",
+            "pass --username or set SKYLOCK_HETZNER_USERNAME",
+        )?,
     };
     
-    let password = match password {
+    let password = match password.or_else(|| std::env::var("SKYLOCK_HETZNER_PASSWORD").ok()) {
         Some(p) => p,
         None => {
+            if !interactive::is_interactive() {
+                return Err(interactive::noninteractive_error(
+                    "Hetzner password",
+                    "pass --password or set SKYLOCK_HETZNER_PASSWORD",
+                ).into());
+            }
             println!("Enter Hetzner password (input hidden): ");
             rpassword::read_password()
-                .map_err(|e| anyhow::anyhow!("Failed to read password: {}", e))?
+                .context("Failed to read password")?
+        }
+    };
+    
+    let config = Config::load(config_path).context("Configuration required to store credentials")?;
+    
+    // Only needed if no earlier source (normally the keychain) can store secrets
+    let file_passphrase = match std::env::var(credentials::CREDENTIALS_PASSPHRASE_ENV) {
+        Ok(passphrase) => Some(zeroize::Zeroizing::new(passphrase)),
+        Err(_) if config.credentials.sources.iter().any(|source| source == "file") && interactive::is_interactive() => {
+            println!("Enter a passphrase for the credentials file, used if no keychain is available (input hidden): ");
+            Some(zeroize::Zeroizing::new(rpassword::read_password().context("Failed to read passphrase")?))
         }
+        Err(_) => None,
     };
+    let chain = CredentialChain::from_config(&config.credentials, &config.data_dir, file_passphrase)?;
+    
+    for (key, value) in [(credentials::HETZNER_USERNAME, &username), (credentials::HETZNER_PASSWORD, &password)] {
+        match chain.store(key, value).await? {
+            Some(source) => println!("🔒 Stored {} in {}", key, source),
+            None => {
+                return Err(CliError::new(
+                    ErrorKind::Config,
+                    "No writable credential source; add \"keychain\" or \"file\" to [credentials] sources",
+                ).into());
+            }
+        }
+    }
     
-    // Store credentials securely (stub implementation)
     println!("✅ Credentials stored successfully for user: {}", username);
-    println!("🔒 Password stored in secure keychain");
     
     Ok(())
 }
 
-async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool, direct: bool, incremental: bool, config_path: Option<PathBuf>, max_speed: Option<String>) -> Result<()> {
+async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool, direct: bool, incremental: bool, config_path: Option<PathBuf>, max_speed: Option<String>, xattrs: bool, verify_on_upload: Option<u32>, tier: StorageTier, compression: skylock_backup::CompressionOverride, concurrency: Option<usize>, tags: Vec<String>, note: Option<String>, filter: skylock_backup::FileFilter) -> Result<()> {
     use progress::{ProgressReporter, ErrorHandler};
     use std::time::Instant;
     use colored::*;
@@ -387,7 +842,7 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
     
     // Load configuration with progress
     let config_spinner = progress.create_spinner("Loading configuration...");
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => {
             progress.finish_with_message(&config_spinner, "Configuration loaded successfully");
             config
@@ -396,19 +851,20 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
             progress.finish_with_message(&config_spinner, "Failed to load configuration");
             ErrorHandler::print_error("Configuration Error", &e.to_string());
             ErrorHandler::suggest_solution("Run 'skylock config' to generate a configuration file");
-            return Err(anyhow::anyhow!("Configuration required for backup operation"));
+            return Err(anyhow::Error::from(e).context("Configuration required for backup operation"));
         }
     };
     
     // Check if Hetzner credentials are configured
     let cred_spinner = progress.create_spinner("Validating credentials...");
-    if config.hetzner.username == "your-username" {
+    if !config.hetzner.has_credentials() {
         progress.finish_with_message(&cred_spinner, "Credentials validation failed");
         ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
         ErrorHandler::suggest_solution("Edit your config file with real Hetzner Storage Box credentials");
-        return Err(anyhow::anyhow!("Hetzner credentials required for backup"));
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required for backup").into());
     }
     progress.finish_with_message(&cred_spinner, "Credentials validated");
+    let concurrency = effective_concurrency(concurrency, &config)?;
     
     // Determine backup paths
     let backup_paths = if !paths.is_empty() {
@@ -421,7 +877,7 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
         if config.backup.backup_paths.is_empty() {
             ErrorHandler::print_error("No Backup Paths", "No backup paths specified");
             ErrorHandler::suggest_solution("Either provide paths as arguments or configure them in your config file");
-            return Err(anyhow::anyhow!("No backup paths specified"));
+            return Err(CliError::new(ErrorKind::Config, "No backup paths specified").into());
         }
         ErrorHandler::print_info("Backup Paths", &format!("Using {} paths from configuration:", config.backup.backup_paths.len()));
         for (i, path) in config.backup.backup_paths.iter().enumerate() {
@@ -449,6 +905,9 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
     }
     progress.finish_with_message(&validation_spinner, "All paths validated successfully");
     
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("backup");
+    
     // Initialize Hetzner client with progress
     let client_spinner = progress.create_spinner("Initializing Hetzner client...");
     let hetzner_config = skylock_hetzner::HetznerConfig {
@@ -466,8 +925,9 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
         },
         Err(e) => {
             progress.finish_with_message(&client_spinner, "Failed to create Hetzner client");
-            ErrorHandler::print_detailed_error(&anyhow::anyhow!(e));
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client"));
+            let e = anyhow::Error::from(e);
+            ErrorHandler::print_detailed_error(&e);
+            return Err(e.context("Failed to initialize Hetzner client"));
         }
     };
     
@@ -477,7 +937,7 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
         progress.finish_with_message(&conn_spinner, "Connection test failed");
         ErrorHandler::print_error("Connection Failed", &e.to_string());
         ErrorHandler::suggest_solution("Check your credentials, endpoint URL, and network connection");
-        return Err(anyhow::anyhow!("Hetzner connection failed: {}", e));
+        return Err(anyhow::Error::from(e).context("Hetzner connection failed"));
     }
     progress.finish_with_message(&conn_spinner, "Connection test successful");
     
@@ -485,22 +945,47 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
     let mut backup_config = config.clone();
     backup_config.backup.backup_paths = backup_paths.clone();
     
+    notifications::notify(&config.notifications, notifications::WebhookPayload::started());
+    
     // Check if using direct upload mode
     if direct {
         println!("🔐 Using direct upload mode (per-file encryption, no archives)");
         println!();
         
-        // Send notification that backup started
-        let _ = notifications::notify_backup_started(backup_paths.len());
-        
-        // Create encryption manager
+        // Create encryption manager with the configured cipher
+        let algorithm = match config.backup.encryption_algorithm.as_deref() {
+            Some(name) => name.parse::<skylock_backup::AeadAlgorithm>()
+                .context("Invalid backup.encryption_algorithm")?,
+            None => skylock_backup::AeadAlgorithm::default(),
+        };
         let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
-            .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+            .context("Failed to create encryption")?
+            .with_algorithm(algorithm);
+        
+        // backup.compression_algorithm applies unless --compression-algo is given
+        let compression = match (compression.algorithm, config.backup.compression_algorithm.as_deref()) {
+            (None, Some(name)) => {
+                let algorithm = name.parse::<skylock_backup::CompressionAlgorithm>()
+                    .context("Invalid backup.compression_algorithm")?;
+                skylock_backup::CompressionOverride::new(Some(algorithm), compression.level)
+                    .context("Invalid --compression-level for backup.compression_algorithm")?
+            }
+            _ => compression,
+        };
+        let compression_profiles = skylock_backup::CompressionProfiles::parse(&config.backup.compression_profiles)
+            .context("Invalid backup.compression_profiles")?;
+        let hash_algorithm = match config.backup.hash_algorithm.as_deref() {
+            Some(name) => name.parse::<skylock_backup::HashAlgorithm>()
+                .context("Invalid backup.hash_algorithm")?,
+            None => skylock_backup::HashAlgorithm::default(),
+        };
         
         // Parse bandwidth limit (CLI > config > unlimited)
         let bandwidth_limit = max_speed
             .or_else(|| config.backup.max_speed_limit.clone())
-            .and_then(|s| skylock_backup::parse_bandwidth_limit(&s).ok());
+            .map(|s| skylock_backup::parse_bandwidth_limit(&s))
+            .transpose()
+            .map_err(|e| CliError::new(ErrorKind::Config, e))?;
         
         if let Some(limit) = bandwidth_limit {
             if limit > 0 {
@@ -517,7 +1002,15 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
             hetzner_client,
             encryption,
             bandwidth_limit
-        );
+        )?.with_xattrs(xattrs)
+            .with_verify_on_upload(verify_on_upload)
+            .with_storage_tier(tier)
+            .with_compression_override(compression)
+            .with_compression_profiles(compression_profiles)
+            .with_hash_algorithm(hash_algorithm)
+            .with_concurrency(concurrency)
+            .with_tags(tags, note)
+            .with_file_filter(filter);
         
         let result = if incremental {
             direct_backup.create_incremental_backup(&backup_paths).await
@@ -540,6 +1033,9 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
                 println!("   📅 Timestamp: {}", manifest.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
                 println!("   📦 Size: {} ({} bytes)", size_formatted.bright_cyan(), manifest.total_size);
                 println!("   📁 Files backed up: {}", manifest.file_count);
+                if !manifest.tags.is_empty() {
+                    println!("   🏷️  Tags: {}", manifest.tags.join(", "));
+                }
                 println!("   ⏱️  Duration: {}", duration_formatted.bright_yellow());
                 println!("   🔐 Encryption: {}", "AES-256-GCM per-file".bright_green());
                 
@@ -550,31 +1046,50 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
                 }
                 
                 // Send success notification
-                let size_mb = manifest.total_size as f64 / 1024.0 / 1024.0;
-                let _ = notifications::notify_backup_completed(
-                    manifest.file_count,
-                    size_mb,
-                    duration.as_secs()
-                );
+                let _ = notifications::notify(
+                    &config.notifications,
+                    notifications::WebhookPayload::succeeded(
+                        &manifest.backup_id,
+                        Some(manifest.file_count),
+                        manifest.total_size,
+                        duration.as_secs()
+                    )
+                ).await;
                 
                 return Ok(());
             }
             Err(e) => {
                 let error_msg = e.to_string();
                 ErrorHandler::print_error("Backup Failed", &format!("Operation failed after {}", ErrorHandler::format_duration(start_time.elapsed())));
-                ErrorHandler::print_detailed_error(&anyhow::anyhow!(e));
+                let e = anyhow::Error::from(e);
+                ErrorHandler::print_detailed_error(&e);
                 
                 // Send failure notification
-                let _ = notifications::notify_backup_failed(&error_msg);
+                let _ = notifications::notify(
+                    &config.notifications,
+                    notifications::WebhookPayload::failed(&error_msg, start_time.elapsed().as_secs())
+                ).await;
                 
-                return Err(anyhow::anyhow!("Backup operation failed"));
+                return Err(e.context("Backup operation failed"));
             }
         }
     }
     
     // Original archive-based backup
+    if xattrs {
+        ErrorHandler::print_warning("Extended Attributes", "--xattrs is only supported with --direct, ignoring");
+    }
+    if compression.is_set() {
+        ErrorHandler::print_warning("Compression", "--compression-algo and --compression-level are only supported with --direct, ignoring");
+    }
+    if !filter.is_empty() {
+        ErrorHandler::print_warning("File Selection", "--include-only and --exclude are only supported with --direct, ignoring");
+    }
+    
     let init_spinner = progress.create_spinner("Initializing backup manager...");
-    let mut backup_manager = skylock_backup::BackupManager::new(backup_config, hetzner_client);
+    let mut backup_manager = skylock_backup::BackupManager::new(backup_config, hetzner_client)?
+        .with_storage_tier(tier)
+        .with_tags(tags, note);
     progress.finish_with_message(&init_spinner, "Backup manager initialized");
     
     // Perform backup with timing
@@ -601,6 +1116,9 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
             println!("   📅 Timestamp: {}", metadata.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
             println!("   📦 Size: {} ({} bytes)", size_formatted.bright_cyan(), metadata.size);
             println!("   📁 Paths backed up: {}", metadata.source_paths.len());
+            if !metadata.tags.is_empty() {
+                println!("   🏷️  Tags: {}", metadata.tags.join(", "));
+            }
             println!("   ⏱️  Duration: {}", duration_formatted.bright_yellow());
             if metadata.is_vss {
                 println!("   💾 VSS snapshot: {}", "Enabled".bright_green());
@@ -612,36 +1130,53 @@ async fn perform_backup(paths: Vec<PathBuf>, name: Option<String>, force: bool,
                 let rate_formatted = ErrorHandler::format_file_size(rate as u64);
                 println!("   🚀 Transfer rate: {}/s", rate_formatted.bright_magenta());
             }
+            
+            let _ = notifications::notify(
+                &config.notifications,
+                notifications::WebhookPayload::succeeded(&metadata.id, None, metadata.size, duration.as_secs())
+            ).await;
         }
         Err(e) => {
             progress.finish_with_message(&backup_spinner, "Backup failed");
+            let _ = notifications::notify(
+                &config.notifications,
+                notifications::WebhookPayload::failed(&e.to_string(), start_time.elapsed().as_secs())
+            ).await;
             ErrorHandler::print_error("Backup Failed", &format!("Operation failed after {}", ErrorHandler::format_duration(start_time.elapsed())));
-            ErrorHandler::print_detailed_error(&anyhow::anyhow!(e));
+            let e = anyhow::Error::from(e);
+            ErrorHandler::print_detailed_error(&e);
             ErrorHandler::suggest_solution("Check network connectivity and storage space on Hetzner Storage Box");
-            return Err(anyhow::anyhow!("Backup operation failed"));
+            return Err(e.context("Backup operation failed"));
         }
     }
     
     Ok(())
 }
 
-async fn perform_restore_file(backup_id: String, file_path: String, output: PathBuf, config_path: Option<PathBuf>) -> Result<()> {
-    println!("🔄 Restoring single file from backup: {}", backup_id);
+async fn perform_restore_file(backup_id: String, file_path: String, output: PathBuf, config_path: Option<PathBuf>, xattrs: bool) -> Result<()> {
+    // `--output -` streams the file to stdout, so status goes to stderr
+    let to_stdout = output.as_os_str() == "-";
+    let status = |message: String| if to_stdout { eprintln!("{}", message) } else { println!("{}", message) };
+    
+    status(format!("🔄 Restoring single file from backup: {}", backup_id));
     
     // Load configuration
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => config,
         Err(e) => {
-            println!("❌ Failed to load configuration: {}", e);
-            return Err(anyhow::anyhow!("Configuration required for restore operation"));
+            status(format!("❌ Failed to load configuration: {}", e));
+            return Err(anyhow::Error::from(e).context("Configuration required for restore operation"));
         }
     };
     
-    if config.hetzner.username == "your-username" {
-        println!("❌ Hetzner credentials not configured");
-        return Err(anyhow::anyhow!("Hetzner credentials required"));
+    if !config.hetzner.has_credentials() {
+        status("❌ Hetzner credentials not configured".to_string());
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
     }
     
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("restore-file");
+    
     // Create Hetzner client
     let hetzner_config = skylock_hetzner::HetznerConfig {
         endpoint: config.hetzner.endpoint.clone(),
@@ -654,27 +1189,49 @@ async fn perform_restore_file(backup_id: String, file_path: String, output: Path
     let hetzner_client = match skylock_hetzner::HetznerClient::new(hetzner_config) {
         Ok(client) => client,
         Err(e) => {
-            println!("❌ Failed to create Hetzner client: {}", e);
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client: {}", e));
+            status(format!("❌ Failed to create Hetzner client: {}", e));
+            return Err(anyhow::Error::from(e).context("Failed to initialize Hetzner client"));
         }
     };
     
     // Create encryption manager
     let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+        .context("Failed to create encryption")?;
     
     // Create direct upload backup manager (no bandwidth limit for restores)
-    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None);
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?
+        .with_xattrs(xattrs);
+    
+    if to_stdout {
+        let result = direct_backup.restore_file_to_writer(&backup_id, &file_path, std::io::stdout()).await;
+        audit_trail.record_result(AuditOperation::Decryption, &format!("{}:{}", backup_id, file_path), &result);
+        return match result {
+            Ok(bytes) => {
+                status(format!("✅ Wrote {} bytes to stdout", bytes));
+                Ok(())
+            }
+            Err(e) => {
+                status(format!("❌ Restore failed: {}", e));
+                Err(anyhow::Error::from(e).context("Restore operation failed"))
+            }
+        };
+    }
     
     // Restore file
-    match direct_backup.restore_file(&backup_id, &file_path, &output).await {
+    let overwrite = audit::would_overwrite(&output);
+    let result = direct_backup.restore_file(&backup_id, &file_path, &output).await;
+    audit_trail.record_result(AuditOperation::Decryption, &format!("{}:{}", backup_id, file_path), &result);
+    if overwrite {
+        audit_trail.record_result(AuditOperation::RestoreOverwrite, &output.display().to_string(), &result);
+    }
+    match result {
         Ok(_) => {
             println!("✅ File restored successfully!");
             Ok(())
         }
         Err(e) => {
             println!("❌ Restore failed: {}", e);
-            Err(anyhow::anyhow!("Restore operation failed: {}", e))
+            Err(anyhow::Error::from(e).context("Restore operation failed"))
         }
     }
 }
@@ -686,19 +1243,22 @@ async fn perform_preview(backup_id: String, target: Option<PathBuf>, config_path
     ErrorHandler::print_info("Preview Backup", &format!("Loading backup: {}", backup_id.bright_green()));
     
     // Load configuration
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => config,
         Err(e) => {
             ErrorHandler::print_error("Configuration Error", &e.to_string());
-            return Err(anyhow::anyhow!("Configuration required"));
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
     };
     
-    if config.hetzner.username == "your-username" {
+    if !config.hetzner.has_credentials() {
         ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
-        return Err(anyhow::anyhow!("Hetzner credentials required"));
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
     }
     
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("preview");
+    
     // Create Hetzner client
     let hetzner_config = skylock_hetzner::HetznerConfig {
         endpoint: config.hetzner.endpoint.clone(),
@@ -712,19 +1272,21 @@ async fn perform_preview(backup_id: String, target: Option<PathBuf>, config_path
         Ok(client) => client,
         Err(e) => {
             ErrorHandler::print_error("Client Error", &e.to_string());
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client"));
+            return Err(anyhow::Error::from(e).context("Failed to initialize Hetzner client"));
         }
     };
     
     // Create encryption manager
     let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+        .context("Failed to create encryption")?;
     
     // Create direct upload backup manager (no bandwidth limit for preview)
-    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None);
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
     
     // Show preview
-    direct_backup.preview_backup(&backup_id).await?;
+    let result = direct_backup.preview_backup(&backup_id).await;
+    audit_trail.record_result(AuditOperation::Decryption, &backup_id, &result);
+    result?;
     
     // Check for conflicts if target specified
     if let Some(target_path) = target {
@@ -735,15 +1297,10 @@ async fn perform_preview(backup_id: String, target: Option<PathBuf>, config_path
             println!();
             ErrorHandler::print_warning("File Conflicts Detected", &format!("{} files already exist", conflicts.len()));
             println!();
-            println!("   The following files will be overwritten:");
-            for (i, path) in conflicts.iter().take(10).enumerate() {
-                println!("   {}. {}", i + 1, path.display());
-            }
-            if conflicts.len() > 10 {
-                println!("   ... and {} more", conflicts.len() - 10);
-            }
+            println!("   A restore stops at these unless --force or --skip-existing is given:");
+            print_conflicts(&conflicts);
             println!();
-            ErrorHandler::suggest_solution("Use --force to overwrite, or choose a different target directory");
+            ErrorHandler::suggest_solution("Use --force to overwrite, --skip-existing to keep them, or choose a different target directory");
         } else {
             println!();
             println!("{}", "✅ No conflicts - safe to restore".bright_green());
@@ -757,19 +1314,22 @@ async fn perform_browse(backup_id: String, config_path: Option<PathBuf>) -> Resu
     use progress::ErrorHandler;
     
     // Load configuration
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => config,
         Err(e) => {
             ErrorHandler::print_error("Configuration Error", &e.to_string());
-            return Err(anyhow::anyhow!("Configuration required"));
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
     };
     
-    if config.hetzner.username == "your-username" {
+    if !config.hetzner.has_credentials() {
         ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
-        return Err(anyhow::anyhow!("Hetzner credentials required"));
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
     }
     
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("browse");
+    
     // Create Hetzner client
     let hetzner_config = skylock_hetzner::HetznerConfig {
         endpoint: config.hetzner.endpoint.clone(),
@@ -783,20 +1343,23 @@ async fn perform_browse(backup_id: String, config_path: Option<PathBuf>) -> Resu
         Ok(client) => client,
         Err(e) => {
             ErrorHandler::print_error("Client Error", &e.to_string());
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client"));
+            return Err(anyhow::Error::from(e).context("Failed to initialize Hetzner client"));
         }
     };
     
     // Create encryption manager
     let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+        .context("Failed to create encryption")?;
     
     // Create direct upload backup manager (no bandwidth limit for browsing)
-    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None);
+    let block_cache = Arc::new(skylock_backup::BlockCache::from_config(&config.backup));
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
     
     // Create browser and browse
-    let browser = skylock_backup::EncryptedBrowser::new(direct_backup);
-    browser.browse(&backup_id).await?;
+    let browser = skylock_backup::EncryptedBrowser::with_cache(direct_backup, block_cache);
+    let result = browser.browse(&backup_id).await;
+    audit_trail.record_result(AuditOperation::Decryption, &backup_id, &result);
+    result?;
     
     Ok(())
 }
@@ -805,24 +1368,29 @@ async fn perform_preview_file(
     backup_id: String,
     file_path: String,
     max_lines: usize,
+    force: bool,
+    tail: bool,
     config_path: Option<PathBuf>,
 ) -> Result<()> {
     use progress::ErrorHandler;
     
     // Load configuration
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => config,
         Err(e) => {
             ErrorHandler::print_error("Configuration Error", &e.to_string());
-            return Err(anyhow::anyhow!("Configuration required"));
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
     };
     
-    if config.hetzner.username == "your-username" {
+    if !config.hetzner.has_credentials() {
         ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
-        return Err(anyhow::anyhow!("Hetzner credentials required"));
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
     }
     
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("preview-file");
+    
     // Create Hetzner client
     let hetzner_config = skylock_hetzner::HetznerConfig {
         endpoint: config.hetzner.endpoint.clone(),
@@ -836,25 +1404,108 @@ async fn perform_preview_file(
         Ok(client) => client,
         Err(e) => {
             ErrorHandler::print_error("Client Error", &e.to_string());
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client"));
+            return Err(anyhow::Error::from(e).context("Failed to initialize Hetzner client"));
         }
     };
     
     // Create encryption manager
     let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+        .context("Failed to create encryption")?;
     
     // Create direct upload backup manager (no bandwidth limit for preview)
-    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None);
+    let block_cache = Arc::new(skylock_backup::BlockCache::from_config(&config.backup));
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
     
     // Create browser and preview file
-    let browser = skylock_backup::EncryptedBrowser::new(direct_backup);
-    browser.preview_file(&backup_id, &file_path, max_lines).await?;
+    let browser = skylock_backup::EncryptedBrowser::with_cache(direct_backup, block_cache);
+    let result = if tail {
+        browser.preview_file_tail(&backup_id, &file_path, max_lines, force).await
+    } else {
+        browser.preview_file(&backup_id, &file_path, max_lines, force).await
+    };
+    audit_trail.record_result(AuditOperation::Decryption, &format!("{}:{}", backup_id, file_path), &result);
+    result?;
     
     Ok(())
 }
 
-async fn perform_restore(backup_id: String, target: Option<PathBuf>, paths: Vec<PathBuf>, config_path: Option<PathBuf>) -> Result<()> {
+/// Tags from `--tag`, trimmed and deduplicated in the given order
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            continue;
+        }
+        if tag.chars().any(char::is_whitespace) {
+            return Err(CliError::new(ErrorKind::Config, format!("Invalid tag '{}': tags can't contain whitespace", tag)).into());
+        }
+        if !normalized.iter().any(|t| t == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    Ok(normalized)
+}
+
+/// Concurrency for this run: `--concurrency`, else
+/// `backup.max_concurrent_uploads`, else a default from the number of cores
+fn effective_concurrency(flag: Option<usize>, config: &Config) -> Result<usize> {
+    let concurrency = match flag.or(config.backup.max_concurrent_uploads) {
+        Some(concurrency) => skylock_backup::parallelism::clamp_concurrency(concurrency)
+            .map_err(|e| CliError::new(ErrorKind::Config, e.to_string()))?,
+        None => skylock_backup::parallelism::default_concurrency(),
+    };
+    info!("Effective concurrency: {}", concurrency);
+    Ok(concurrency)
+}
+
+/// Which backup `skylock restore` restores
+#[derive(Debug)]
+enum BackupSelection {
+    /// The backup with this ID
+    Id(String),
+    /// The newest backup passing the filter (`--latest`)
+    Latest(time_filter::BackupFilter),
+}
+
+/// Backup chosen by `restore`'s arguments
+///
+/// With `--latest` there is no ID to give, so a first positional argument
+/// is a path to restore and is moved to the front of `paths`.
+fn restore_selection(
+    backup_id: Option<String>,
+    latest: bool,
+    paths: &mut Vec<PathBuf>,
+    pattern: Option<String>,
+    tag: Option<String>,
+    source: Option<PathBuf>,
+) -> BackupSelection {
+    match backup_id {
+        Some(id) if !latest => BackupSelection::Id(id),
+        first => {
+            if let Some(first) = first {
+                paths.insert(0, PathBuf::from(first));
+            }
+            BackupSelection::Latest(
+                time_filter::BackupFilter { pattern, ..Default::default() }
+                    .with_tag(tag)
+                    .with_source(source)
+            )
+        }
+    }
+}
+
+/// Where `skylock restore` writes
+#[derive(Debug)]
+enum RestoreTarget {
+    /// Exactly this directory (`--target`)
+    Path(PathBuf),
+    /// A new directory named from a template below `--output-dir`, else
+    /// `backup.restore_dir`, else the current directory
+    Template { output_dir: Option<PathBuf>, name_template: Option<String> },
+}
+
+async fn perform_restore(selection: BackupSelection, target: RestoreTarget, paths: Vec<PathBuf>, config_path: Option<PathBuf>, xattrs: bool, path_map: skylock_backup::PathMap, concurrency: Option<usize>, space_check: bool, policy: skylock_backup::RestorePolicy, preview: bool) -> Result<()> {
     use progress::{ProgressReporter, ErrorHandler};
     use colored::*;
     use std::time::Instant;
@@ -862,22 +1513,23 @@ async fn perform_restore(backup_id: String, target: Option<PathBuf>, paths: Vec<
     let start_time = Instant::now();
     let progress = ProgressReporter::new();
     
-    ErrorHandler::print_info("Restore Operation", &format!("Backup ID: {}", backup_id.bright_green()));
-    
-    let target_path = target.unwrap_or_else(|| {
-        PathBuf::from(format!("restore_{}", chrono::Utc::now().format("%Y%m%d_%H%M%S")))
-    });
-    println!("   📂 Target: {}", target_path.display());
+    match &selection {
+        BackupSelection::Id(backup_id) => {
+            ErrorHandler::print_info("Restore Operation", &format!("Backup ID: {}", backup_id.bright_green()));
+        }
+        BackupSelection::Latest(filter) => {
+            ErrorHandler::print_info("Restore Operation", &format!("Newest backup matching {}", filter.describe().bright_green()));
+        }
+    }
     
-    if !paths.is_empty() {
-        ErrorHandler::print_warning("Selective Restore", "Specific path selection not yet implemented");
-        println!("   Will restore entire backup");
+    for path in &paths {
+        ErrorHandler::print_info("Selective Restore", &format!("Only {}", path.display().to_string().bright_green()));
     }
     println!();
     
     // Load configuration
     let config_spinner = progress.create_spinner("Loading configuration...");
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => {
             progress.finish_with_message(&config_spinner, "Configuration loaded");
             config
@@ -885,14 +1537,18 @@ async fn perform_restore(backup_id: String, target: Option<PathBuf>, paths: Vec<
         Err(e) => {
             progress.finish_with_message(&config_spinner, "Configuration failed");
             ErrorHandler::print_error("Configuration Error", &e.to_string());
-            return Err(anyhow::anyhow!("Configuration required"));
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
     };
     
-    if config.hetzner.username == "your-username" {
+    if !config.hetzner.has_credentials() {
         ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
-        return Err(anyhow::anyhow!("Hetzner credentials required"));
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
     }
+    let concurrency = effective_concurrency(concurrency, &config)?;
+    
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("restore");
     
     // Create Hetzner client
     let client_spinner = progress.create_spinner("Connecting to Hetzner Storage Box...");
@@ -904,7 +1560,7 @@ async fn perform_restore(backup_id: String, target: Option<PathBuf>, paths: Vec<
         encryption_key: config.hetzner.encryption_key.clone(),
     };
     
-    let hetzner_client = match skylock_hetzner::HetznerClient::new(hetzner_config) {
+    let hetzner_client = match skylock_hetzner::HetznerClient::new(hetzner_config.clone()) {
         Ok(client) => {
             progress.finish_with_message(&client_spinner, "Connected to storage");
             client
@@ -912,73 +1568,230 @@ async fn perform_restore(backup_id: String, target: Option<PathBuf>, paths: Vec<
         Err(e) => {
             progress.finish_with_message(&client_spinner, "Connection failed");
             ErrorHandler::print_error("Client Error", &e.to_string());
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client"));
+            return Err(anyhow::Error::from(e).context("Failed to initialize Hetzner client"));
+        }
+    };
+    
+    // Resolve --latest to the newest matching backup
+    let backup_id = match selection {
+        BackupSelection::Id(backup_id) => backup_id,
+        BackupSelection::Latest(filter) => {
+            let list_client = skylock_hetzner::HetznerClient::new(hetzner_config)
+                .context("Failed to create client for listing backups")?;
+            let backups = skylock_backup::BackupManager::new(config.clone(), list_client)?
+                .list_backups().await
+                .context("Failed to list backups")?;
+            let Some(latest) = filter.latest(backups) else {
+                ErrorHandler::print_error("No Matching Backup", &format!("No backup matches {}", filter.describe()));
+                ErrorHandler::suggest_solution("Run `skylock list` with the same --pattern, --tag or --source to see what exists");
+                return Err(CliError::new(ErrorKind::General, format!("No backup matches {}", filter.describe())).into());
+            };
+            println!("   🆔 Latest: {} ({})", latest.id.bright_green(), latest.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
+            latest.id
         }
     };
     
     // Create encryption manager
     let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+        .context("Failed to create encryption")?;
     
     // Create direct upload backup manager (no bandwidth limit for restores)
-    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None);
+    let restore_dir = config.backup.restore_dir.clone();
+    let notifications_config = config.notifications.clone();
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?
+        .with_xattrs(xattrs)
+        .with_path_map(path_map)
+        .with_concurrency(concurrency)
+        .with_restore_policy(policy)
+        .with_space_check(space_check);
+    
+    let target_path = match target {
+        RestoreTarget::Path(path) => path,
+        RestoreTarget::Template { output_dir, name_template } => {
+            let template = name_template.as_deref().unwrap_or(restore_target::DEFAULT_NAME_TEMPLATE);
+            // Only {source} needs the manifest
+            let manifest = if restore_target::needs_source(template) {
+                Some(direct_backup.load_manifest(&backup_id).await
+                    .context("Failed to load the backup manifest for {source}")?)
+            } else {
+                None
+            };
+            let values = restore_target::TemplateValues {
+                backup_id: &backup_id,
+                now: Utc::now(),
+                source: manifest.as_ref().and_then(|m| m.source_paths.first()).map(PathBuf::as_path),
+            };
+            let name = restore_target::render(template, &values)
+                .map_err(|e| CliError::new(ErrorKind::Config, e.to_string()))?;
+            let parent = output_dir.or(restore_dir).unwrap_or_else(|| PathBuf::from("."));
+            restore_target::unused_target(&parent, &name)?
+        }
+    };
+    println!("   📂 Target: {}", target_path.display());
+    
+    if preview {
+        println!();
+        let result = direct_backup.preview_restore(&backup_id, &paths, &target_path).await;
+        audit_trail.record_result(AuditOperation::Decryption, &backup_id, &result);
+        print_restore_preview(&result?);
+        return Ok(());
+    }
     
     // Send notification that restore started
-    let _ = notifications::notify_restore_started(&backup_id);
+    notifications::notify(&notifications_config, notifications::WebhookPayload::restore_started(&backup_id));
     
     // Perform restore
     println!();
-    match direct_backup.restore_backup(&backup_id, &target_path).await {
+    let overwrite = audit::would_overwrite(&target_path);
+    let result = if paths.is_empty() {
+        direct_backup.restore_backup(&backup_id, &target_path).await
+    } else {
+        // Only the files below the paths are downloaded
+        direct_backup.restore_subtree(&backup_id, &paths, &target_path).await
+    };
+    audit_trail.record_result(AuditOperation::Decryption, &backup_id, &result);
+    if overwrite {
+        audit_trail.record_result(AuditOperation::RestoreOverwrite, &target_path.display().to_string(), &result);
+    }
+    match result {
         Ok(_) => {
             let duration = start_time.elapsed();
             println!();
             ErrorHandler::print_success("Restore Complete!", &format!("Files restored to {}", target_path.display()));
             println!("   ⏱️  Duration: {}", ErrorHandler::format_duration(duration).bright_yellow());
             
-            // Send success notification (we don't know exact file count without parsing manifest again)
-            let _ = notifications::notify_restore_completed(0, duration.as_secs());
+            // Send success notification
+            let _ = notifications::notify(
+                &notifications_config,
+                notifications::WebhookPayload::restore_succeeded(&backup_id, duration.as_secs())
+            ).await;
         }
         Err(e) => {
             let error_msg = e.to_string();
             ErrorHandler::print_error("Restore Failed", &format!("After {}", ErrorHandler::format_duration(start_time.elapsed())));
-            ErrorHandler::print_detailed_error(&anyhow::anyhow!(e));
+            let e = anyhow::Error::from(e);
+            ErrorHandler::print_detailed_error(&e);
+            match e.downcast_ref() {
+                Some(skylock_backup::SkylockError::InsufficientSpace(_)) => {
+                    ErrorHandler::suggest_solution("Free up space, restore to another disk with --target or --map, or pass --no-space-check to restore anyway");
+                }
+                Some(skylock_backup::SkylockError::RestoreConflict(conflicts)) => {
+                    print_conflicts(conflicts);
+                    ErrorHandler::suggest_solution("Use --force to overwrite them, --skip-existing to restore only missing files, or choose a different target directory");
+                }
+                _ => {}
+            }
             
             // Send failure notification
-            let _ = notifications::notify_restore_failed(&error_msg);
+            let _ = notifications::notify(
+                &notifications_config,
+                notifications::WebhookPayload::restore_failed(&backup_id, &error_msg, start_time.elapsed().as_secs())
+            ).await;
             
-            return Err(anyhow::anyhow!("Restore operation failed"));
+            return Err(e.context("Restore operation failed"));
         }
     }
     
     Ok(())
 }
 
-async fn list_backups(detailed: bool, pattern: Option<String>, config_path: Option<PathBuf>) -> Result<()> {
+/// List the first files a restore conflicts with
+fn print_conflicts(conflicts: &[PathBuf]) {
+    for (i, path) in conflicts.iter().take(10).enumerate() {
+        println!("   {}. {}", i + 1, path.display());
+    }
+    if conflicts.len() > 10 {
+        println!("   ... and {} more", conflicts.len() - 10);
+    }
+}
+
+/// Print what a restore would do, grouped by action
+fn print_restore_preview(preview: &[skylock_backup::RestorePreviewEntry]) {
+    use skylock_backup::RestoreAction;
+    use colored::*;
+    
+    const SHOWN: usize = 20;
+    let sections = [
+        (RestoreAction::Create, "➕", "created"),
+        (RestoreAction::Overwrite, "✏️ ", "overwritten"),
+        (RestoreAction::Unchanged, "✅", "left untouched (identical)"),
+    ];
+    for (action, icon, label) in sections {
+        let entries: Vec<_> = preview.iter().filter(|e| e.action == action).collect();
+        println!("{} {} files would be {}", icon, entries.len().to_string().bold(), label);
+        for entry in entries.iter().take(SHOWN) {
+            match (action, entry.existing_size) {
+                (RestoreAction::Overwrite, Some(size)) if size == entry.backup_size => {
+                    println!("   {} ({}, content differs)", entry.target.display(), skylock_core::ByteSize(size));
+                }
+                (RestoreAction::Overwrite, Some(size)) => {
+                    println!("   {} ({} → {})", entry.target.display(), skylock_core::ByteSize(size), skylock_core::ByteSize(entry.backup_size));
+                }
+                (RestoreAction::Overwrite, None) => {
+                    println!("   {} (not a regular file)", entry.target.display());
+                }
+                _ => println!("   {} ({})", entry.target.display(), skylock_core::ByteSize(entry.backup_size)),
+            }
+        }
+        if entries.len() > SHOWN {
+            println!("   ... and {} more", entries.len() - SHOWN);
+        }
+        println!();
+    }
+    println!("{}", "Preview only - nothing was restored".bright_yellow());
+}
+
+async fn list_backups(detailed: bool, filter: time_filter::BackupFilter, config_path: Option<PathBuf>, format: OutputFormat) -> Result<()> {
     use progress::{ProgressReporter, ErrorHandler};
     use colored::*;
     
     let progress = ProgressReporter::new();
+    let json = format.is_json();
     
-    ErrorHandler::print_info("Listing Backups", "Fetching backup information from storage...");
-    
-    if let Some(pattern) = &pattern {
-        ErrorHandler::print_info("Filter Applied", &format!("Pattern: {}", pattern.bright_yellow()));
+    if !json {
+        ErrorHandler::print_info("Listing Backups", "Fetching backup information from storage...");
+        
+        if let Some(pattern) = &filter.pattern {
+            ErrorHandler::print_info("Filter Applied", &format!("Pattern: {}", pattern.bright_yellow()));
+        }
+        if let Some(since) = filter.since {
+            ErrorHandler::print_info("Filter Applied", &format!("Since: {}", since.format("%Y-%m-%d %H:%M:%S UTC").to_string().bright_yellow()));
+        }
+        if let Some(until) = filter.until {
+            ErrorHandler::print_info("Filter Applied", &format!("Until: {}", until.format("%Y-%m-%d %H:%M:%S UTC").to_string().bright_yellow()));
+        }
+        if let Some(limit) = filter.limit {
+            ErrorHandler::print_info("Filter Applied", &format!("Limit: {}", limit.to_string().bright_yellow()));
+        }
+        if let Some(tag) = &filter.tag {
+            ErrorHandler::print_info("Filter Applied", &format!("Tag: {}", tag.bright_yellow()));
+        }
+        if let Some(source) = &filter.source {
+            ErrorHandler::print_info("Filter Applied", &format!("Source: {}", source.display().to_string().bright_yellow()));
+        }
     }
     
     // Load configuration
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => config,
         Err(e) => {
-            println!("❌ Failed to load configuration: {}", e);
-            return Err(anyhow::anyhow!("Configuration required for listing backups"));
+            if !json {
+                println!("❌ Failed to load configuration: {}", e);
+            }
+            return Err(anyhow::Error::from(e).context("Configuration required for listing backups"));
         }
     };
     
-    if config.hetzner.username == "your-username" {
-        println!("❌ Hetzner credentials not configured");
-        return Err(anyhow::anyhow!("Hetzner credentials required"));
+    if !config.hetzner.has_credentials() {
+        if !json {
+            println!("❌ Hetzner credentials not configured");
+        }
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
     }
     
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("list");
+    
     // Create Hetzner client
     let hetzner_config = skylock_hetzner::HetznerConfig {
         endpoint: config.hetzner.endpoint.clone(),
@@ -991,17 +1804,29 @@ async fn list_backups(detailed: bool, pattern: Option<String>, config_path: Opti
     let hetzner_client = match skylock_hetzner::HetznerClient::new(hetzner_config) {
         Ok(client) => client,
         Err(e) => {
-            println!("❌ Failed to create Hetzner client: {}", e);
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client: {}", e));
+            if !json {
+                println!("❌ Failed to create Hetzner client: {}", e);
+            }
+            return Err(anyhow::Error::from(e).context("Failed to initialize Hetzner client"));
         }
     };
     
     // Initialize backup manager
-    let backup_manager = skylock_backup::BackupManager::new(config, hetzner_client);
+    let backup_manager = skylock_backup::BackupManager::new(config, hetzner_client)?;
     
     // List backups
-    println!("🔍 Fetching backup list...");
+    if !json {
+        println!("🔍 Fetching backup list...");
+    }
     match backup_manager.list_backups().await {
+        Ok(backups) if json => {
+            let backups = filter.apply(backups);
+            
+            output::print_json(&output::BackupListReport {
+                count: backups.len(),
+                backups: backups.iter().collect(),
+            })?;
+        }
         Ok(backups) => {
             if backups.is_empty() {
                 println!("💭 No backups found");
@@ -1011,229 +1836,254 @@ async fn list_backups(detailed: bool, pattern: Option<String>, config_path: Opti
             println!("📊 Found {} backup(s):", backups.len());
             println!();
             
-            let mut filtered_backups = backups;
+            // Apply pattern, time and count filters (newest first)
+            let filter_active = filter.is_active();
+            let filtered_backups = filter.apply(backups);
             
-            // Apply pattern filtering if specified
-            if let Some(pattern) = pattern {
-                filtered_backups = filtered_backups.into_iter()
-                    .filter(|backup| backup.id.contains(&pattern))
-                    .collect();
-                
-                if filtered_backups.is_empty() {
-                    println!("💭 No backups match the pattern '{}'", pattern);
-                    return Ok(());
-                }
+            if filter_active && filtered_backups.is_empty() {
+                println!("💭 No backups match the filters");
+                return Ok(());
             }
             
-            // Sort by timestamp (newest first)
-            filtered_backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-            
             for backup in filtered_backups {
                 if detailed {
                     println!("┌── 🆔 {}", backup.id);
                     println!("│   📅 Created: {}", backup.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
-                    println!("│   📊 Size: {} bytes ({:.2} MB)", backup.size, backup.size as f64 / 1024.0 / 1024.0);
+                    println!("│   📊 Size: {} bytes ({})", backup.size, skylock_core::ByteSize(backup.size));
                     println!("│   📁 Paths: {} item(s)", backup.source_paths.len());
                     if backup.is_vss {
                         println!("│   💸 VSS: Enabled");
                     }
+                    if !backup.tags.is_empty() {
+                        println!("│   🏷️  Tags: {}", backup.tags.join(", "));
+                    }
+                    if let Some(note) = &backup.note {
+                        println!("│   📝 Note: {}", note);
+                    }
                     println!("│   📂 Source paths:");
                     for path in &backup.source_paths {
                         println!("│     - {}", path.display());
                     }
                     println!("└────────────────────────────────────────────");
                 } else {
-                    let size_mb = backup.size as f64 / 1024.0 / 1024.0;
-                    println!("{:<30} {:<20} {:>10.2} MB  {} paths", 
+                    let size = skylock_core::ByteSize(backup.size).to_string();
+                    let tags = if backup.tags.is_empty() {
+                        String::new()
+                    } else {
+                        format!("  [{}]", backup.tags.join(", "))
+                    };
+                    println!("{:<30} {:<20} {:>12}  {} paths{}", 
                         backup.id,
                         backup.timestamp.format("%Y-%m-%d %H:%M"),
-                        size_mb,
-                        backup.source_paths.len()
+                        size,
+                        backup.source_paths.len(),
+                        tags
                     );
                 }
             }
         }
         Err(e) => {
-            println!("❌ Failed to list backups: {}", e);
-            return Err(anyhow::anyhow!("Failed to list backups: {}", e));
+            if !json {
+                println!("❌ Failed to list backups: {}", e);
+            }
+            return Err(anyhow::Error::from(e).context("Failed to list backups"));
         }
     }
     
     Ok(())
 }
 
-async fn run_tests(component: Option<TestComponent>) -> Result<()> {
-    let component = component.unwrap_or(TestComponent::All);
+async fn find_files(glob: String, filter: time_filter::BackupFilter, config_path: Option<PathBuf>, format: OutputFormat) -> Result<()> {
+    use progress::ErrorHandler;
+    use colored::*;
     
-    match component {
-        TestComponent::Hetzner => {
-            println!("🔗 Testing Hetzner Storage Box connection...");
-            test_hetzner_connection().await?;
-        }
-        TestComponent::Encryption => {
-            println!("🔐 Testing encryption functionality...");
-            test_encryption().await?;
+    let json = format.is_json();
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            if !json {
+                ErrorHandler::print_error("Configuration Error", &e.to_string());
+            }
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
-        TestComponent::Compression => {
-            println!("🗜️ Testing compression functionality...");
-            test_compression().await?;
+    };
+    
+    if !config.hetzner.has_credentials() {
+        if !json {
+            ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
         }
-        TestComponent::All => {
-            println!("🧪 Running all tests...");
-            test_hetzner_connection().await?;
-            test_encryption().await?;
-            test_compression().await?;
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
+    }
+    
+    let hetzner_config = skylock_hetzner::HetznerConfig {
+        endpoint: config.hetzner.endpoint.clone(),
+        username: config.hetzner.username.clone(),
+        password: config.hetzner.password.clone(),
+        api_token: config.hetzner.encryption_key.clone(),
+        encryption_key: config.hetzner.encryption_key.clone(),
+    };
+    let hetzner_client = skylock_hetzner::HetznerClient::new(hetzner_config)
+        .context("Failed to initialize Hetzner client")?;
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption")?;
+    
+    if !json {
+        ErrorHandler::print_info("Searching Backups", &format!(
+            "Files matching {} ({})",
+            glob.bright_yellow(),
+            filter.describe()
+        ));
+    }
+    
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    let result = direct_backup.find_files(skylock_backup::PathGlob::new(&glob), |summary| {
+        filter.matches(&skylock_backup::BackupMetadata::from_summary(summary))
+    }).await.context("Failed to search backups")?;
+    let found = &result.files;
+    
+    if json {
+        return output::print_json(&output::FindReport {
+            glob: &glob,
+            count: found.len(),
+            files: found,
+            skipped: &result.skipped,
+        });
+    }
+    
+    for skipped in &result.skipped {
+        ErrorHandler::print_warning(
+            "Backup Skipped",
+            &format!("Manifest of {} could not be read: {}", skipped.backup_id, skipped.error),
+        );
+    }
+    if !result.skipped.is_empty() {
+        eprintln!("   Results may be missing files from these backups and from incremental backups built on them");
+        eprintln!();
+    }
+    
+    if found.is_empty() {
+        println!("💭 No backed up files match {}", glob);
+        return Ok(());
+    }
+    
+    println!("📊 Found {} file(s):", found.len());
+    for file in found {
+        println!();
+        println!("📄 {}", file.path.display().to_string().bold());
+        for (index, version) in file.versions.iter().enumerate() {
+            let modified = version.modified
+                .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string());
+            let hash: String = version.hash.chars().take(12).collect();
+            let marker = if file.is_change(index) { "" } else { "  (unchanged)" };
+            println!("   {:<20} {:<16} {:>12}  {}  modified {}{}",
+                version.backup_id,
+                version.backup_time.format("%Y-%m-%d %H:%M"),
+                skylock_core::ByteSize(version.size).to_string(),
+                hash,
+                modified,
+                marker.dimmed()
+            );
         }
     }
     
     Ok(())
 }
 
-async fn test_hetzner_connection() -> Result<()> {
-    use skylock_hetzner::HetznerWebDAVClient;
-    
-    // Try to load config first
-    let config_result = Config::load(None);
+async fn show_stats(limit: usize, config_path: Option<PathBuf>, format: OutputFormat) -> Result<()> {
+    use progress::ErrorHandler;
+    use skylock_core::ByteSize;
     
-    let (endpoint, username, password) = match config_result {
-        Ok(config) => {
-            if config.hetzner.username == "your-username" {
-                println!("⚠️  Using default config - please configure real credentials");
-                return Ok(());
+    let json = format.is_json();
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            if !json {
+                ErrorHandler::print_error("Configuration Error", &e.to_string());
             }
-            (config.hetzner.endpoint, config.hetzner.username, config.hetzner.password)
-        }
-        Err(_) => {
-            println!("⚠️  No configuration found. Run 'skylock config' to generate one.");
-            return Ok(());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
     };
     
-    println!("🔗 Testing connection to: {}", endpoint);
-    println!("👤 Username: {}", username);
+    if !config.hetzner.has_credentials() {
+        if !json {
+            ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
+        }
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
+    }
     
-    // Create WebDAV client config
-    let webdav_config = skylock_hetzner::WebDAVConfig {
-        base_url: endpoint.clone(),
-        username: username.clone(),
-        password: password.clone(),
-        base_path: "/".to_string(),
+    let hetzner_config = skylock_hetzner::HetznerConfig {
+        endpoint: config.hetzner.endpoint.clone(),
+        username: config.hetzner.username.clone(),
+        password: config.hetzner.password.clone(),
+        api_token: config.hetzner.encryption_key.clone(),
+        encryption_key: config.hetzner.encryption_key.clone(),
     };
+    let hetzner_client = skylock_hetzner::HetznerClient::new(hetzner_config)
+        .context("Failed to initialize Hetzner client")?;
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption")?;
     
-    let client = HetznerWebDAVClient::new(webdav_config)
-        .map_err(|e| anyhow::anyhow!("Failed to create WebDAV client: {}", e))?;
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    let summaries = direct_backup.recent_run_summaries(limit).await
+        .context("Failed to load backup summaries")?;
     
-    // Test connection by listing root directory  
-    match client.list_files("/").await {
-        Ok(entries) => {
-            println!("✅ Connection successful!");
-            println!("📁 Found {} items in root directory", entries.len());
-            
-            if entries.len() > 0 {
-                println!("📋 Directory contents:");
-                for entry in entries.iter().take(5) { // Show first 5 items
-                    println!("  - {}", entry);
-                }
-                if entries.len() > 5 {
-                    println!("  ... and {} more items", entries.len() - 5);
-                }
-            }
-        }
-        Err(e) => {
-            println!("❌ Connection failed: {}", e);
-            println!("💡 Check your credentials and endpoint URL");
-            return Err(anyhow::anyhow!("Hetzner connection test failed: {}", e));
-        }
+    if json {
+        return output::print_json(&summaries);
     }
     
-    // Test write permissions by creating a test file
-    println!("🧪 Testing write permissions...");
-    let test_content = b"Skylock connection test";
-    let test_path_str = "/skylock_test.txt";
-    
-    // Create a temporary file for upload
-    let temp_file = tempfile::NamedTempFile::new()
-        .map_err(|e| anyhow::anyhow!("Failed to create temp file: {}", e))?;
-    tokio::fs::write(temp_file.path(), test_content).await
-        .map_err(|e| anyhow::anyhow!("Failed to write temp file: {}", e))?;
+    if summaries.is_empty() {
+        println!("💭 No backup has a run summary yet");
+        return Ok(());
+    }
     
-    match client.upload_file(temp_file.path(), test_path_str).await {
-        Ok(_) => {
-            println!("✅ Write test successful!");
-            
-            // Clean up test file
-            if let Err(e) = client.delete_file(test_path_str).await {
-                println!("⚠️  Failed to clean up test file: {}", e);
-            } else {
-                println!("🧹 Test file cleaned up");
-            }
-        }
-        Err(e) => {
-            println!("⚠️  Write test failed: {}", e);
-            println!("💡 Check if you have write permissions on the storage box");
-        }
+    println!("📊 Last {} backup run(s), newest first:", summaries.len());
+    println!();
+    println!("   {:<20} {:>10} {:>8} {:>12} {:>12} {:>7} {:>12} {:>7}",
+        "Backup", "Duration", "Files", "Uploaded", "Deduped", "Ratio", "Throughput", "Errors");
+    for summary in &summaries {
+        let duration = std::time::Duration::from_secs_f64(summary.duration_secs.max(0.0));
+        println!("   {:<20} {:>10} {:>8} {:>12} {:>12} {:>6.2}x {:>10}/s {:>7}",
+            summary.backup_id,
+            ErrorHandler::format_duration(duration),
+            summary.file_count,
+            ByteSize(summary.bytes_uploaded).to_string(),
+            ByteSize(summary.bytes_deduplicated).to_string(),
+            summary.compression_ratio,
+            ByteSize(summary.throughput_bytes_per_sec as u64).to_string(),
+            summary.error_count
+        );
     }
     
-    println!("✅ Hetzner connection test completed");
     Ok(())
 }
 
-async fn test_encryption() -> Result<()> {
-    use skylock_core::encryption::EncryptionManager;
-    use std::path::Path;
-    
-    println!("🔐 Testing encryption functionality...");
-    
-    // Create temporary directory for encryption test
-    let temp_dir = tempfile::tempdir()
-        .map_err(|e| anyhow::anyhow!("Failed to create temp dir: {}", e))?;
-    
-    let config_path = temp_dir.path();
-    let password = "test_password_123";
-    
-    // Initialize encryption manager
-    let encryption_manager = match EncryptionManager::new(config_path, password).await {
-        Ok(em) => em,
-        Err(e) => {
-            println!("⚠️  Could not initialize encryption manager: {}", e);
-            println!("✅ Encryption test skipped (dependencies not available)");
-            return Ok(());
-        }
+async fn run_tests(component: Option<TestComponent>, config_path: Option<PathBuf>, format: OutputFormat) -> Result<()> {
+    use colored::*;
+    use doctor::CheckGroup;
+    
+    // The same checks as `doctor`, limited to the chosen component
+    let (title, groups) = match component.unwrap_or(TestComponent::All) {
+        TestComponent::Hetzner => ("🔗 Testing Hetzner Storage Box connection", vec![CheckGroup::Storage]),
+        TestComponent::Encryption => ("🔐 Testing encryption functionality", vec![CheckGroup::Encryption]),
+        TestComponent::Compression => ("🗜️ Testing compression functionality", vec![CheckGroup::Compression]),
+        TestComponent::All => ("🧪 Running all tests", vec![CheckGroup::Storage, CheckGroup::Encryption, CheckGroup::Compression]),
     };
+    if !format.is_json() {
+        println!("{}", title.bright_blue().bold());
+        println!();
+    }
     
-    println!("✅ Encryption manager initialized");
-    
-    // Test block encryption
-    let test_data = b"Hello, Skylock! This is a test message for encryption.";
-    let block_hash = "test_block_hash";
-    
-    println!("📝 Test data: {} bytes", test_data.len());
-    
-    // Encrypt block
-    match encryption_manager.encrypt_block(test_data, block_hash, 0).await {
-        Ok(encrypted) => {
-            println!("✅ Block encryption successful: {} bytes", encrypted.len());
-            
-            // Decrypt block
-            match encryption_manager.decrypt_block(&encrypted, block_hash, 0).await {
-                Ok(decrypted) => {
-                    if decrypted == test_data {
-                        println!("✅ Block decryption successful and data matches!");
-                    } else {
-                        return Err(anyhow::anyhow!("Decrypted data doesn't match original"));
-                    }
-                }
-                Err(e) => {
-                    return Err(anyhow::anyhow!("Block decryption failed: {}", e));
-                }
-            }
-        }
-        Err(e) => {
-            return Err(anyhow::anyhow!("Block encryption failed: {}", e));
-        }
+    let report = doctor::DoctorReport::new(doctor::run_check_groups(config_path, &groups).await);
+    if format.is_json() {
+        output::print_json(&report)?;
+    } else {
+        doctor::print_report(&report);
     }
     
-    println!("✅ Encryption test passed");
+    if report.failed > 0 {
+        return Err(output::DoctorFailed(report.failed).into());
+    }
     Ok(())
 }
 
@@ -1355,32 +2205,41 @@ async fn perform_diff(
     backup_id_new: String,
     detailed: bool,
     filter: Option<Vec<String>>,
+    top: Option<usize>,
     config_path: Option<PathBuf>,
+    format: OutputFormat,
 ) -> Result<()> {
     use progress::ErrorHandler;
     use colored::*;
     use skylock_backup::{BackupDiff, DirectUploadBackup};
     
-    ErrorHandler::print_info("Comparing Backups", &format!(
-        "Comparing {} → {}",
-        backup_id_old.bright_yellow(),
-        backup_id_new.bright_yellow()
-    ));
+    let json = format.is_json();
+    
+    if !json {
+        ErrorHandler::print_info("Comparing Backups", &format!(
+            "Comparing {} → {}",
+            backup_id_old.bright_yellow(),
+            backup_id_new.bright_yellow()
+        ));
+    }
     
     // Load configuration
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => config,
         Err(e) => {
             ErrorHandler::print_error("Configuration Error", &e.to_string());
-            return Err(anyhow::anyhow!("Configuration required"));
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
     };
     
-    if config.hetzner.username == "your-username" {
+    if !config.hetzner.has_credentials() {
         ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
-        return Err(anyhow::anyhow!("Hetzner credentials required"));
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
     }
     
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("diff");
+    
     // Create Hetzner client
     let hetzner_config = skylock_hetzner::HetznerConfig {
         endpoint: config.hetzner.endpoint.clone(),
@@ -1394,26 +2253,49 @@ async fn perform_diff(
         Ok(client) => client,
         Err(e) => {
             ErrorHandler::print_error("Client Error", &e.to_string());
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client"));
+            return Err(anyhow::Error::from(e).context("Failed to initialize Hetzner client"));
         }
     };
     
     // Create encryption manager
     let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+        .context("Failed to create encryption")?;
     
     // Create direct upload backup manager (no bandwidth limit for diff)
-    let direct_backup = DirectUploadBackup::new(config, hetzner_client, encryption, None);
+    let direct_backup = DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    
+    // Ctrl-C stops the diff, keeping whatever was compared
+    let cancel = cancel_on_ctrl_c();
     
     // Load both manifests
-    println!("📥 Loading backup manifests...");
-    let manifest_old = direct_backup.load_manifest(&backup_id_old).await
-        .map_err(|e| anyhow::anyhow!("Failed to load old backup manifest: {}", e))?;
-    let manifest_new = direct_backup.load_manifest(&backup_id_new).await
-        .map_err(|e| anyhow::anyhow!("Failed to load new backup manifest: {}", e))?;
+    if !json {
+        println!("📥 Loading backup manifests...");
+    }
+    let manifest_old = tokio::select! {
+        _ = cancel.cancelled() => return Err(anyhow::anyhow!("Diff cancelled")),
+        manifest = direct_backup.load_manifest(&backup_id_old) => manifest,
+    };
+    audit_trail.record_result(AuditOperation::Decryption, &format!("{} manifest", backup_id_old), &manifest_old);
+    let manifest_old = manifest_old
+        .context("Failed to load old backup manifest")?;
+    let manifest_new = tokio::select! {
+        _ = cancel.cancelled() => return Err(anyhow::anyhow!("Diff cancelled")),
+        manifest = direct_backup.load_manifest(&backup_id_new) => manifest,
+    };
+    audit_trail.record_result(AuditOperation::Decryption, &format!("{} manifest", backup_id_new), &manifest_new);
+    let manifest_new = manifest_new
+        .context("Failed to load new backup manifest")?;
     
     // Compare manifests
-    let diff = BackupDiff::compare(&manifest_old, &manifest_new);
+    let mut diff = BackupDiff::compare_cancellable(&manifest_old, &manifest_new, &cancel, None);
+    
+    if json {
+        output::filter_diff(&mut diff, filter.as_deref());
+        if let Some(top) = top {
+            output::keep_largest_modifications(&mut diff, top);
+        }
+        return output::print_json(&diff);
+    }
     
     // Display summary
     println!();
@@ -1425,19 +2307,128 @@ async fn perform_diff(
     println!("   {} {}", "New backup:".dimmed(), backup_id_new.bright_yellow());
     println!("   {} {}", "  Created:".dimmed(), diff.timestamp_new.format("%Y-%m-%d %H:%M:%S UTC"));
     println!();
+    if diff.fingerprint_old == diff.fingerprint_new {
+        println!("   {} {} (same content)", "Content fingerprint:".dimmed(), &diff.fingerprint_new[..16]);
+    } else {
+        println!("   {} {} → {}", "Content fingerprint:".dimmed(), &diff.fingerprint_old[..16], &diff.fingerprint_new[..16]);
+    }
+    println!();
+    
+    if diff.incomplete {
+        println!("{}", "⚠️  Comparison cancelled - only part of the changes are listed".bright_yellow());
+        println!();
+    }
     
     if !diff.has_changes() {
         println!("{}", "✅ No differences found - backups are identical".bright_green());
         return Ok(());
     }
     
+    print_diff_changes(&diff, detailed, filter.as_deref(), top);
+    
+    Ok(())
+}
+
+async fn compare_local(
+    backup_id: String,
+    path: PathBuf,
+    detailed: bool,
+    filter: Option<Vec<String>>,
+    local_filter: skylock_backup::FileFilter,
+    config_path: Option<PathBuf>,
+    format: OutputFormat,
+) -> Result<()> {
+    use progress::ErrorHandler;
+    use colored::*;
+    use skylock_backup::{BackupDiff, DirectUploadBackup};
+    
+    let json = format.is_json();
+    
+    if !json {
+        ErrorHandler::print_info("Comparing With Local Files", &format!(
+            "Comparing {} → {}",
+            backup_id.bright_yellow(),
+            path.display().to_string().bright_yellow()
+        ));
+    }
+    
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    if !config.hetzner.has_credentials() {
+        ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
+    }
+    
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("compare-local");
+    
+    let hetzner_client = storage_client(&config)?;
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption")?;
+    let direct_backup = DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    
+    if !json {
+        println!("📥 Loading backup manifest...");
+    }
+    let manifest = direct_backup.load_manifest(&backup_id).await;
+    audit_trail.record_result(AuditOperation::Decryption, &format!("{} manifest", backup_id), &manifest);
+    let manifest = manifest.context("Failed to load backup manifest")?;
+    
+    if !json {
+        if !manifest.files.iter().any(|entry| entry.local_path.starts_with(&path)) {
+            ErrorHandler::print_warning("Nothing Backed Up Here", &format!(
+                "Backup {} has no files under {}; give the path as it was backed up", backup_id, path.display()
+            ));
+        }
+        println!("🔍 Scanning local files...");
+    }
+    let mut diff = BackupDiff::compare_local(&manifest, &path, &local_filter).await
+        .context("Failed to compare with local files")?;
+    
+    if json {
+        output::filter_diff(&mut diff, filter.as_deref());
+        return output::print_json(&diff);
+    }
+    
+    println!();
+    println!("{}", "📊 Local Comparison Summary".bright_blue().bold());
+    println!();
+    println!("   {} {}", "Backup:".dimmed(), backup_id.bright_yellow());
+    println!("   {} {}", "  Created:".dimmed(), diff.timestamp_old.format("%Y-%m-%d %H:%M:%S UTC"));
+    println!("   {} {}", "Local path:".dimmed(), path.display().to_string().bright_yellow());
+    println!();
+    
+    if !diff.has_changes() {
+        println!("{}", "✅ No differences found - local files match the backup".bright_green());
+        return Ok(());
+    }
+    
+    println!("   {} added locally, {} missing locally, {} with different content",
+        "+".bright_green().bold(), "-".bright_red().bold(), "~".bright_yellow().bold());
+    println!();
+    print_diff_changes(&diff, detailed, filter.as_deref(), None);
+    
+    Ok(())
+}
+
+/// Print the changes of `diff` by type, as `skylock diff` shows them
+fn print_diff_changes(diff: &skylock_backup::BackupDiff, detailed: bool, filter: Option<&[String]>, top: Option<usize>) {
+    use progress::ErrorHandler;
+    use colored::*;
+    
     println!("{}", "Changes:".bright_cyan().bold());
     
     // Determine which change types to show
-    let show_added = filter.as_ref().map_or(true, |f| f.iter().any(|t| t == "added"));
-    let show_removed = filter.as_ref().map_or(true, |f| f.iter().any(|t| t == "removed"));
-    let show_modified = filter.as_ref().map_or(true, |f| f.iter().any(|t| t == "modified"));
-    let show_moved = filter.as_ref().map_or(true, |f| f.iter().any(|t| t == "moved"));
+    let show_added = filter.map_or(true, |f| f.iter().any(|t| t == "added"));
+    let show_removed = filter.map_or(true, |f| f.iter().any(|t| t == "removed"));
+    let show_modified = filter.map_or(true, |f| f.iter().any(|t| t == "modified"));
+    let show_moved = filter.map_or(true, |f| f.iter().any(|t| t == "moved"));
     
     // Added files
     if show_added && diff.summary.files_added_count > 0 {
@@ -1499,85 +2490,780 @@ async fn perform_diff(
             }
         }
     }
-    
-    // Moved/renamed files
-    if show_moved && diff.summary.files_moved_count > 0 {
-        println!("   {} {} files",
-            "→".bright_cyan().bold(),
-            diff.summary.files_moved_count.to_string().bright_cyan()
-        );
-        
-        if detailed {
-            for file in &diff.files_moved {
-                println!("      {} → {}", 
-                    file.path_old.dimmed(),
-                    file.path_new.bright_cyan()
-                );
-            }
-        }
+    
+    // Moved/renamed files
+    if show_moved && diff.summary.files_moved_count > 0 {
+        println!("   {} {} files",
+            "→".bright_cyan().bold(),
+            diff.summary.files_moved_count.to_string().bright_cyan()
+        );
+        
+        if detailed {
+            for file in &diff.files_moved {
+                println!("      {} → {}", 
+                    file.path_old.dimmed(),
+                    file.path_new.bright_cyan()
+                );
+            }
+        }
+    }
+    
+    // Unchanged files
+    println!("   {} {} files",
+        "=".dimmed(),
+        diff.summary.files_unchanged_count.to_string().dimmed()
+    );
+    
+    // Net change
+    println!();
+    let net_change_str = if diff.summary.size_delta > 0 {
+        format!("+{}", ErrorHandler::format_file_size(diff.summary.size_delta as u64))
+    } else if diff.summary.size_delta < 0 {
+        format!("-{}", ErrorHandler::format_file_size((-diff.summary.size_delta) as u64))
+    } else {
+        "0 bytes".to_string()
+    };
+    
+    let net_color = if diff.summary.size_delta > 0 {
+        net_change_str.bright_green()
+    } else if diff.summary.size_delta < 0 {
+        net_change_str.bright_red()
+    } else {
+        net_change_str.dimmed()
+    };
+    
+    println!("   {} {}", "Net change:".bright_cyan(), net_color);
+    
+    // Rates over the time between the two backups
+    if let (Some(churn), Some(growth)) = (diff.summary.churn_bytes_per_day, diff.summary.growth_bytes_per_day) {
+        let trend = if growth > 0.0 {
+            format!("growing {}/day", ErrorHandler::format_file_size(growth as u64)).bright_green()
+        } else if growth < 0.0 {
+            format!("shrinking {}/day", ErrorHandler::format_file_size((-growth) as u64)).bright_red()
+        } else {
+            "stable".dimmed()
+        };
+        println!("   {} {}/day", "Churn rate:".bright_cyan(), ErrorHandler::format_file_size(churn as u64));
+        println!("   {} {}", "Trend:".bright_cyan(), trend);
+    }
+    
+    // Largest size changes among modified files
+    if let Some(top) = top.filter(|_| show_modified && diff.summary.files_modified_count > 0) {
+        println!();
+        println!("{}", format!("Largest changes (top {}):", top).bright_cyan().bold());
+        for file in diff.largest_modifications(top) {
+            let delta_str = if file.size_delta >= 0 {
+                format!("+{}", ErrorHandler::format_file_size(file.size_delta as u64)).bright_green()
+            } else {
+                format!("-{}", ErrorHandler::format_file_size(file.size_delta.unsigned_abs())).bright_red()
+            };
+            println!("      ~ {:<60} {}", file.path.bright_yellow(), delta_str);
+        }
+    }
+    
+    if !detailed && diff.total_changes() > 0 {
+        println!();
+        println!("💡 Use {} for detailed file listings", "--detailed".bright_yellow());
+    }
+    
+}
+
+async fn rotate_key(reason: String, config_path: Option<PathBuf>) -> Result<()> {
+    use progress::ErrorHandler;
+    use colored::*;
+    use skylock_backup::{KeyRotationManager, KeyRotationPolicy};
+    
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    if !config.hetzner.has_encryption_key() {
+        ErrorHandler::print_error("Encryption Error", "Encryption key not configured");
+        return Err(CliError::new(ErrorKind::Config, "Encryption key required").into());
+    }
+    
+    let audit_trail = audit::AuditTrail::new(&config);
+    let passphrase = &config.hetzner.encryption_key;
+    let path = KeyRotationManager::keyfile_path(&config.data_dir);
+    let keyfile_target = format!("keyfile {}", path.display());
+    
+    let manager = if path.exists() {
+        let opened = KeyRotationManager::open_keyfile(path.clone(), passphrase);
+        audit_trail.record_result(AuditOperation::KeyAccess, &keyfile_target, &opened);
+        let manager = opened
+            .map_err(|e| {
+                ErrorHandler::print_error("Keyfile Error", &e.to_string());
+                ErrorHandler::suggest_solution("Check that encryption_key matches the key used to create the keyfile");
+                anyhow::Error::from(e).context(format!("Failed to open keyfile {}", path.display()))
+            })?;
+        let rotated = manager.rotate_keyfile(passphrase, &reason);
+        audit_trail.record_result(AuditOperation::KeyAccess, &format!("{} (rotate)", keyfile_target), &rotated);
+        let version = rotated?;
+        ErrorHandler::print_success("Key Rotated", &format!(
+            "New backups use key version {}; existing backups are unchanged",
+            version.to_string().bright_yellow()
+        ));
+        manager
+    } else {
+        let created = KeyRotationManager::create_keyfile(path.clone(), passphrase, KeyRotationPolicy::default());
+        audit_trail.record_result(AuditOperation::KeyAccess, &format!("{} (create)", keyfile_target), &created);
+        let manager = created?;
+        ErrorHandler::print_success("Keyfile Created", &format!("New backups use key version 1 from {}", path.display()));
+        ErrorHandler::print_info(
+            "Existing Backups",
+            "Backups made before the keyfile existed keep using the password-derived key",
+        );
+        manager
+    };
+    
+    let info = manager.info();
+    println!();
+    println!("{}", "Key versions:".bright_cyan().bold());
+    for version in &info.versions {
+        let status = if version.version == info.active_version {
+            "active".bright_green()
+        } else if version.can_decrypt {
+            "decrypt only".yellow()
+        } else {
+            "retired".dimmed()
+        };
+        println!(
+            "  v{}  {}  created {}  [{}]",
+            version.version,
+            version.fingerprint,
+            version.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            status
+        );
+    }
+    
+    if !info.rotations.is_empty() {
+        println!();
+        println!("{}", "Rotation history:".bright_cyan().bold());
+        for rotation in &info.rotations {
+            println!(
+                "  {}  v{} -> v{}  {}",
+                rotation.rotated_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                rotation.from_version,
+                rotation.to_version,
+                rotation.reason
+            );
+        }
+    }
+    
+    Ok(())
+}
+
+/// Encryption passphrase typed by the user, or from SKYLOCK_PASSPHRASE
+fn read_passphrase() -> Result<zeroize::Zeroizing<String>> {
+    use skylock_ui::interactive;
+    
+    if let Ok(passphrase) = std::env::var("SKYLOCK_PASSPHRASE") {
+        return Ok(zeroize::Zeroizing::new(passphrase));
+    }
+    if !interactive::is_interactive() {
+        return Err(interactive::noninteractive_error("Encryption passphrase", "set SKYLOCK_PASSPHRASE").into());
+    }
+    eprintln!("Enter encryption passphrase (input hidden): ");
+    Ok(zeroize::Zeroizing::new(rpassword::read_password().context("Failed to read passphrase")?))
+}
+
+async fn export_key(output: Option<PathBuf>, config_path: Option<PathBuf>) -> Result<()> {
+    use progress::ErrorHandler;
+    use skylock_backup::KeyRotationManager;
+    
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    let path = KeyRotationManager::keyfile_path(&config.data_dir);
+    if !path.exists() {
+        ErrorHandler::print_error("Keyfile Error", &format!("No keyfile at {}", path.display()));
+        ErrorHandler::suggest_solution("Run 'skylock rotate-key' to create one; backups made without a keyfile only need the passphrase");
+        return Err(CliError::new(ErrorKind::Config, format!("No keyfile at {}", path.display())).into());
+    }
+    
+    // Always asked for, even though the config holds it, so whoever exports
+    // the recovery key has to know the passphrase
+    let passphrase = read_passphrase()?;
+    let audit_trail = audit::AuditTrail::new(&config);
+    let exported = KeyRotationManager::export_recovery_key(path.clone(), &passphrase);
+    audit_trail.record_result(AuditOperation::KeyAccess, &format!("keyfile {} (export)", path.display()), &exported);
+    let recovery_key = exported.map_err(|e| {
+        ErrorHandler::print_error("Keyfile Error", &e.to_string());
+        anyhow::Error::from(e).context(format!("Failed to export keyfile {}", path.display()))
+    })?;
+    
+    match output {
+        Some(output) => {
+            std::fs::write(&output, &recovery_key)
+                .with_context(|| format!("Failed to write {}", output.display()))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&output, std::fs::Permissions::from_mode(0o600))?;
+            }
+            ErrorHandler::print_success("Recovery Key Exported", &output.display().to_string());
+        }
+        None => print!("{}", recovery_key),
+    }
+    eprintln!("Store the recovery key offline. With the passphrase, 'skylock import-key' restores the keyfile on a new install.");
+    
+    Ok(())
+}
+
+async fn import_key(input: Option<PathBuf>, force: bool, config_path: Option<PathBuf>) -> Result<()> {
+    use progress::ErrorHandler;
+    use skylock_backup::KeyRotationManager;
+    use std::io::Read;
+    
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    let path = KeyRotationManager::keyfile_path(&config.data_dir);
+    if path.exists() && !force {
+        ErrorHandler::print_error("Keyfile Error", &format!("A keyfile already exists at {}", path.display()));
+        ErrorHandler::suggest_solution("Pass --force to replace it");
+        return Err(CliError::new(ErrorKind::Config, format!("Keyfile {} already exists", path.display())).into());
+    }
+    
+    let recovery_key = match input.filter(|input| input.as_os_str() != "-") {
+        Some(input) => std::fs::read_to_string(&input)
+            .with_context(|| format!("Failed to read {}", input.display()))?,
+        None => {
+            let mut recovery_key = String::new();
+            std::io::stdin().read_to_string(&mut recovery_key).context("Failed to read recovery key")?;
+            recovery_key
+        }
+    };
+    
+    let passphrase = read_passphrase()?;
+    let audit_trail = audit::AuditTrail::new(&config);
+    let imported = KeyRotationManager::import_recovery_key(&recovery_key, path.clone(), &passphrase);
+    audit_trail.record_result(AuditOperation::KeyAccess, &format!("keyfile {} (import)", path.display()), &imported);
+    let manager = imported.map_err(|e| {
+        ErrorHandler::print_error("Recovery Key Error", &e.to_string());
+        anyhow::Error::from(e).context("Failed to import recovery key")
+    })?;
+    
+    ErrorHandler::print_success("Keyfile Restored", &format!(
+        "{} with key versions up to {}",
+        path.display(),
+        manager.active_version()
+    ));
+    if config.hetzner.encryption_key != *passphrase {
+        ErrorHandler::print_warning(
+            "Encryption Key Mismatch",
+            "encryption_key in the configuration differs from the passphrase; set it to the passphrase so backups can use the keyfile",
+        );
+    }
+    
+    Ok(())
+}
+
+/// Client for the storage box named in `config`
+fn storage_client(config: &Config) -> Result<skylock_hetzner::HetznerClient> {
+    let hetzner_config = skylock_hetzner::HetznerConfig {
+        endpoint: config.hetzner.endpoint.clone(),
+        username: config.hetzner.username.clone(),
+        password: config.hetzner.password.clone(),
+        api_token: config.hetzner.encryption_key.clone(),
+        encryption_key: config.hetzner.encryption_key.clone(),
+    };
+    skylock_hetzner::HetznerClient::new(hetzner_config)
+        .context("Failed to initialize Hetzner client")
+}
+
+async fn backup_config(config_path: Option<PathBuf>) -> Result<()> {
+    use progress::ErrorHandler;
+    use skylock_backup::ConfigBundle;
+    
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    if !config.hetzner.has_credentials() {
+        ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
+    }
+    
+    // Asked for like export-key, since it is what recovers the bundle
+    let passphrase = read_passphrase()?;
+    if config.hetzner.encryption_key != *passphrase {
+        ErrorHandler::print_warning(
+            "Passphrase Differs",
+            "The bundle is sealed with the passphrase you entered, not the configured encryption key; recover-config will need this one",
+        );
+    }
+    
+    let audit_trail = audit::AuditTrail::new(&config);
+    let bundled = ConfigBundle::new(&config, &passphrase);
+    audit_trail.record_result(AuditOperation::KeyAccess, "keyfile (config bundle)", &bundled);
+    let bundle = bundled.map_err(|e| {
+        ErrorHandler::print_error("Config Bundle Error", &e.to_string());
+        anyhow::Error::from(e).context("Failed to bundle the configuration")
+    })?;
+    
+    let hetzner_client = storage_client(&config)?;
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption")?;
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    direct_backup.upload_config_bundle(&bundle, &passphrase).await
+        .map_err(|e| {
+            ErrorHandler::print_error("Upload Failed", &e.to_string());
+            anyhow::Error::from(e).context("Failed to upload the config bundle")
+        })?;
+    
+    ErrorHandler::print_success("Configuration Backed Up", skylock_backup::CONFIG_BUNDLE_PATH);
+    if bundle.recovery_key.is_some() {
+        println!("   🔑 Keyfile included with its keys wrapped by the passphrase");
+    }
+    if !bundle.redacted.is_empty() {
+        println!("   🙈 Left out: {}", bundle.redacted.join(", "));
+    }
+    println!("   On a new machine, 'skylock recover-config' restores it with the storage box credentials and the passphrase.");
+    
+    Ok(())
+}
+
+async fn recover_config(output: Option<PathBuf>, force: bool, config_path: Option<PathBuf>) -> Result<()> {
+    use progress::ErrorHandler;
+    use skylock_backup::KeyRotationManager;
+    
+    // Only needs to reach the storage box, e.g. a config made by 'skylock init'
+    let current = match Config::load_with_credentials(config_path.clone()).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            ErrorHandler::suggest_solution("Run 'skylock init' to set up the storage box credentials first");
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    if !current.hetzner.has_credentials() {
+        ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
+    }
+    
+    let output = output.or_else(|| config_path.clone()).unwrap_or_else(Config::default_path);
+    if output.exists() && !force {
+        ErrorHandler::print_error("Config Error", &format!("{} already exists", output.display()));
+        ErrorHandler::suggest_solution("Pass --force to replace it, or --output to write the recovered configuration elsewhere");
+        return Err(CliError::new(ErrorKind::Config, format!("Config file {} already exists", output.display())).into());
+    }
+    
+    let passphrase = read_passphrase()?;
+    let hetzner_client = storage_client(&current)?;
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&current.hetzner.encryption_key)
+        .context("Failed to create encryption")?;
+    let direct_backup = skylock_backup::DirectUploadBackup::new(current, hetzner_client, encryption, None)?;
+    let bundle = direct_backup.download_config_bundle(&passphrase).await
+        .map_err(|e| {
+            ErrorHandler::print_error("Recovery Failed", &e.to_string());
+            anyhow::Error::from(e).context("Failed to recover the config bundle")
+        })?;
+    
+    // Secrets come from the config file in use, never from a credential
+    // store, so none end up in the written file that weren't there before
+    let file_config = Config::load(config_path).ok();
+    let config = match &file_config {
+        Some(file_config) => bundle.restored_config(file_config),
+        None => bundle.config.clone(),
+    };
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let config_str = toml::to_string_pretty(&config).context("Failed to serialize config")?;
+    std::fs::write(&output, config_str)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    ErrorHandler::print_success("Configuration Recovered", &format!(
+        "{} (bundled {})",
+        output.display(),
+        bundle.created_at.format("%Y-%m-%d %H:%M UTC")
+    ));
+    
+    // Secrets the config file in use didn't hold either
+    let (_, filled) = skylock_backup::ConfigBundle::redact(&config);
+    let missing: Vec<&str> = bundle.redacted.iter()
+        .filter(|field| !filled.contains(field))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        println!("   ✏️  Fill in, or store with 'skylock store-credentials': {}", missing.join(", "));
+    }
+    
+    if let Some(recovery_key) = &bundle.recovery_key {
+        let keyfile = KeyRotationManager::keyfile_path(&config.data_dir);
+        if keyfile.exists() && !force {
+            ErrorHandler::print_warning(
+                "Keyfile Kept",
+                &format!("{} already exists; pass --force to replace it with the bundled one", keyfile.display()),
+            );
+        } else {
+            if let Some(parent) = keyfile.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            KeyRotationManager::import_recovery_key(recovery_key, keyfile.clone(), &passphrase)
+                .context("Failed to restore the keyfile")?;
+            ErrorHandler::print_success("Keyfile Restored", &keyfile.display().to_string());
+        }
+    }
+    
+    Ok(())
+}
+
+async fn generate_manifest_key(force: bool, config_path: Option<PathBuf>) -> Result<()> {
+    use progress::ErrorHandler;
+    use skylock_backup::manifest_signing::{public_key_path, signing_key_path};
+    
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    let key_path = signing_key_path(&config.data_dir);
+    let public_path = public_key_path(&config.data_dir);
+    if key_path.exists() && !force {
+        ErrorHandler::print_error("Signing Key Error", &format!("A manifest signing key already exists at {}", key_path.display()));
+        ErrorHandler::suggest_solution("Pass --force to replace it; manifests signed with the old key then fail to verify");
+        return Err(CliError::new(ErrorKind::Config, format!("Signing key {} already exists", key_path.display())).into());
+    }
+    
+    let key = skylock_backup::SecureSigningKey::generate("manifest-signing".to_string(), None)
+        .context("Failed to generate signing key")?;
+    if let Some(dir) = key_path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    key.save_to_file(&key_path)
+        .with_context(|| format!("Failed to write {}", key_path.display()))?;
+    key.save_public_key_to_file(&public_path)
+        .with_context(|| format!("Failed to write {}", public_path.display()))?;
+    
+    ErrorHandler::print_success("Manifest Signing Key Created", &format!(
+        "{} (fingerprint {})",
+        key_path.display(),
+        key.metadata().fingerprint
+    ));
+    println!("   Public key: {}", public_path.display());
+    println!("   New backups are signed from now on; sign existing ones with 'skylock sign-manifest <id>'.");
+    println!("   Copy the public key to machines that restore, and set backup.manifest_public_key there.");
+    if !config.backup.require_signed_manifests {
+        println!("   Set backup.require_signed_manifests = true to refuse unsigned manifests.");
+    }
+    
+    Ok(())
+}
+
+async fn sign_manifests(backup_ids: Vec<String>, config_path: Option<PathBuf>) -> Result<()> {
+    use progress::ErrorHandler;
+    use colored::*;
+    
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    if !config.hetzner.has_credentials() {
+        ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
+    }
+    
+    let hetzner_config = skylock_hetzner::HetznerConfig {
+        endpoint: config.hetzner.endpoint.clone(),
+        username: config.hetzner.username.clone(),
+        password: config.hetzner.password.clone(),
+        api_token: config.hetzner.encryption_key.clone(),
+        encryption_key: config.hetzner.encryption_key.clone(),
+    };
+    let hetzner_client = skylock_hetzner::HetznerClient::new(hetzner_config)
+        .context("Failed to initialize Hetzner client")?;
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption")?;
+    
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    for backup_id in &backup_ids {
+        let manifest = direct_backup.sign_backup_manifest(backup_id).await
+            .map_err(|e| {
+                ErrorHandler::print_error("Signing Failed", &e.to_string());
+                anyhow::Error::from(e).context(format!("Failed to sign manifest of {}", backup_id))
+            })?;
+        let fingerprint = manifest.signature.map(|signature| signature.fingerprint).unwrap_or_default();
+        println!("   ✍️  {} signed with key {}", backup_id.bright_yellow(), fingerprint);
+    }
+    
+    ErrorHandler::print_success("Manifests Signed", &format!("{} backup(s)", backup_ids.len()));
+    Ok(())
+}
+
+async fn rebuild_index(backup_id: Option<String>, config_path: Option<PathBuf>) -> Result<()> {
+    use progress::ErrorHandler;
+    use colored::*;
+    
+    let config = match Config::load_with_credentials(config_path).await {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    
+    if !config.hetzner.has_credentials() {
+        ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
+    }
+    
+    let hetzner_config = skylock_hetzner::HetznerConfig {
+        endpoint: config.hetzner.endpoint.clone(),
+        username: config.hetzner.username.clone(),
+        password: config.hetzner.password.clone(),
+        api_token: config.hetzner.encryption_key.clone(),
+        encryption_key: config.hetzner.encryption_key.clone(),
+    };
+    let hetzner_client = skylock_hetzner::HetznerClient::new(hetzner_config)
+        .context("Failed to initialize Hetzner client")?;
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption")?;
+    
+    ErrorHandler::print_info("Rebuilding Index", &format!(
+        "Checking local files against {}",
+        backup_id.as_deref().unwrap_or("the newest backup").bright_yellow()
+    ));
+    
+    let direct_backup = skylock_backup::DirectUploadBackup::new(config, hetzner_client, encryption, None)?;
+    let stats = direct_backup.rebuild_index(backup_id.as_deref()).await
+        .map_err(|e| {
+            ErrorHandler::print_error("Reindex Failed", &e.to_string());
+            anyhow::Error::from(e).context("Failed to rebuild index")
+        })?;
+    
+    ErrorHandler::print_success("Index Rebuilt", &format!(
+        "Incremental backups continue from {}",
+        stats.backup_id.bright_yellow()
+    ));
+    println!("   ✅ {} files unchanged", stats.verified);
+    if stats.changed > 0 {
+        println!("   ✏️  {} files changed since the backup (uploaded by the next backup)", stats.changed);
+    }
+    if stats.missing > 0 {
+        println!("   🗑️  {} files no longer exist locally", stats.missing);
+    }
+    
+    Ok(())
+}
+
+fn install_service(print: bool, config_path: Option<PathBuf>) -> Result<()> {
+    use platform::service::{self, ServiceKind, ServiceSpec};
+    use progress::ErrorHandler;
+
+    // A service that can't load its config would only fail at every boot
+    let config = match Config::load(config_path.clone()) {
+        Ok(config) => config,
+        Err(e) => {
+            ErrorHandler::print_error("Configuration Error", &e.to_string());
+            return Err(anyhow::Error::from(e).context("Configuration required"));
+        }
+    };
+    let spec = ServiceSpec::current(config_path, &config.backup.schedule)?;
+    let kind = ServiceKind::current();
+
+    if print {
+        print!("{}", kind.render(&spec));
+        return Ok(());
+    }
+
+    let installed = service::install(kind, &spec)?;
+    let location = match installed {
+        Some(path) => path.display().to_string(),
+        None => format!("Scheduled Task \\{}", service::SERVICE_NAME),
+    };
+    ErrorHandler::print_success("Service Installed", &format!(
+        "{} runs `skylock --daemon` with {} (schedule: {})",
+        location,
+        spec.config.display(),
+        spec.schedule
+    ));
+    if kind == ServiceKind::Systemd {
+        println!("   To start it at boot rather than at login, run: loginctl enable-linger");
     }
+    Ok(())
+}
+
+fn uninstall_service() -> Result<()> {
+    use platform::service::{self, ServiceKind};
+    use progress::ErrorHandler;
+
+    let removed = service::uninstall(ServiceKind::current())?;
+    let location = match removed {
+        Some(path) => path.display().to_string(),
+        None => format!("Scheduled Task \\{}", service::SERVICE_NAME),
+    };
+    ErrorHandler::print_success("Service Removed", &location);
+    Ok(())
+}
+
+async fn analyze_duplicates(
+    path: PathBuf,
+    block_size: usize,
+    workers: Option<usize>,
+    top: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    use progress::{ErrorHandler, ProgressReporter};
+    use skylock_core::ByteSize;
+    use skylock_hybrid::deduplication::DuplicationAnalyzer;
     
-    // Unchanged files
-    println!("   {} {} files",
-        "=".dimmed(),
-        diff.summary.files_unchanged_count.to_string().dimmed()
-    );
+    let json = format.is_json();
+    if block_size == 0 {
+        return Err(CliError::new(ErrorKind::Config, "--block-size must be at least 1 byte").into());
+    }
+    if !path.is_dir() {
+        return Err(CliError::new(ErrorKind::Config, format!("{} is not a directory", path.display())).into());
+    }
+    let workers = workers
+        .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+    
+    let mut analyzer = DuplicationAnalyzer::new(block_size)
+        .with_workers(workers)
+        .with_top_count(top)
+        .with_cancellation(cancel_on_ctrl_c());
+    let reporter = ProgressReporter::new();
+    let bar = (!json).then(|| reporter.create_progress_bar(0, "🔍 Scanning"));
+    if let Some(bar) = bar.clone() {
+        analyzer = analyzer.with_progress(Arc::new(move |progress| {
+            bar.set_length(progress.total_bytes);
+            bar.set_position(progress.bytes_scanned);
+        }));
+    }
     
-    // Net change
-    println!();
-    let net_change_str = if diff.summary.size_delta > 0 {
-        format!("+{}", ErrorHandler::format_file_size(diff.summary.size_delta as u64))
-    } else if diff.summary.size_delta < 0 {
-        format!("-{}", ErrorHandler::format_file_size((-diff.summary.size_delta) as u64))
-    } else {
-        "0 bytes".to_string()
-    };
+    let report = tokio::task::spawn_blocking(move || analyzer.analyze_directory(&path)).await?
+        .context("Failed to analyze directory")?;
+    if let Some(bar) = &bar {
+        bar.finish_and_clear();
+    }
     
-    let net_color = if diff.summary.size_delta > 0 {
-        net_change_str.bright_green()
-    } else if diff.summary.size_delta < 0 {
-        net_change_str.bright_red()
-    } else {
-        net_change_str.dimmed()
-    };
+    if json {
+        return output::print_json(&output::DuplicatesReport::from_report(&report));
+    }
     
-    println!("   {} {}", "Net change:".bright_cyan(), net_color);
+    if !report.complete {
+        ErrorHandler::print_warning("Scan Interrupted", "Figures cover only the files scanned");
+    }
+    let stats = &report.stats;
+    println!("📊 {} files, {} scanned", report.files_scanned, ByteSize(report.bytes_scanned));
+    println!("   Blocks: {} total, {} unique", stats.total_blocks, stats.unique_blocks);
+    println!("   Unique data: {}", ByteSize(stats.unique_size));
+    println!("   Deduplication would save {} ({:.1}%)", ByteSize(stats.space_saved), stats.deduplication_ratio * 100.0);
     
-    if !detailed && diff.total_changes() > 0 {
+    if !report.top_files.is_empty() {
         println!();
-        println!("💡 Use {} for detailed file listings", "--detailed".bright_yellow());
+        println!("📄 Identical files:");
+        for files in &report.top_files {
+            println!("   {} × {} (saves {})", files.paths.len(), ByteSize(files.size), ByteSize(files.space_saved()));
+            for path in &files.paths {
+                println!("      {}", path.display());
+            }
+        }
+    }
+    if !report.top_blocks.is_empty() {
+        println!();
+        println!("🧱 Most duplicated blocks:");
+        for block in &report.top_blocks {
+            println!("   {}  {} × {} (saves {})",
+                &hex::encode(block.hash)[..16],
+                block.occurrences,
+                ByteSize(block.size as u64),
+                ByteSize(block.space_saved()));
+        }
     }
     
     Ok(())
 }
 
+/// Token that is cancelled when the user presses Ctrl-C
+fn cancel_on_ctrl_c() -> skylock_backup::CancellationToken {
+    let cancel = skylock_backup::CancellationToken::new();
+    let token = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            token.cancel();
+        }
+    });
+    cancel
+}
+
 async fn verify_backup(
     backup_id: String,
     full: bool,
+    repair: bool,
+    concurrency: Option<usize>,
     config_path: Option<PathBuf>,
+    format: OutputFormat,
 ) -> Result<()> {
     use progress::ErrorHandler;
     use colored::*;
-    use skylock_backup::{BackupVerifier, DirectUploadBackup};
+    use skylock_backup::{BackupVerifier, DirectUploadBackup, HetznerBackend};
+    use skylock_core::storage::{LocalStorageProvider, MultiBackend, StorageConfig, WriteQuorum};
     
-    ErrorHandler::print_info("Backup Verification", &format!(
-        "Verifying backup: {}",
-        backup_id.bright_yellow()
-    ));
+    let json = format.is_json();
+    let full = full || repair;
+    
+    if !json {
+        ErrorHandler::print_info("Backup Verification", &format!(
+            "Verifying backup: {}",
+            backup_id.bright_yellow()
+        ));
+    }
     
     // Load configuration
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => config,
         Err(e) => {
             ErrorHandler::print_error("Configuration Error", &e.to_string());
-            return Err(anyhow::anyhow!("Configuration required"));
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
     };
     
-    if config.hetzner.username == "your-username" {
+    if !config.hetzner.has_credentials() {
         ErrorHandler::print_error("Credentials Error", "Hetzner credentials not configured");
-        return Err(anyhow::anyhow!("Hetzner credentials required"));
+        return Err(CliError::new(ErrorKind::Config, "Hetzner credentials required").into());
     }
+    let concurrency = effective_concurrency(concurrency, &config)?;
+    
+    let mirrors = config.backup.mirrors.clone();
+    if repair && mirrors.is_empty() {
+        ErrorHandler::print_error(
+            "Repair Unavailable",
+            "No mirrors configured - add a [[backup.mirrors]] section to repair from"
+        );
+        return Err(CliError::new(ErrorKind::Config, "Mirrors required for --repair").into());
+    }
+    
+    let audit_trail = audit::AuditTrail::new(&config);
+    audit_trail.record_config_secrets("verify");
     
     // Create Hetzner client for DirectUploadBackup
     let hetzner_config = skylock_hetzner::HetznerConfig {
@@ -1592,43 +3278,129 @@ async fn verify_backup(
         Ok(client) => client,
         Err(e) => {
             ErrorHandler::print_error("Client Error", &e.to_string());
-            return Err(anyhow::anyhow!("Failed to initialize Hetzner client"));
+            return Err(anyhow::Error::from(e).context("Failed to initialize Hetzner client"));
         }
     };
     
     // Create encryption manager for DirectUploadBackup
     let encryption1 = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create encryption: {}", e))?;
+        .context("Failed to create encryption")?;
     
-    // Save encryption_key before moving config
+    // Save encryption_key, timeouts and data_dir before moving config
     let encryption_key = config.hetzner.encryption_key.clone();
+    let checkpoint_dir = skylock_backup::VerifyCheckpoint::state_dir(&config.data_dir);
+    let connect_timeout = std::time::Duration::from_secs(config.hetzner.connect_timeout_secs);
+    let read_timeout = std::time::Duration::from_secs(config.hetzner.read_timeout_secs);
     
     // Load manifest
-    println!("📥 Loading backup manifest...");
-    let direct_backup = DirectUploadBackup::new(config, hetzner_client1, encryption1, None);
-    let manifest = direct_backup.load_manifest(&backup_id).await
-        .map_err(|e| anyhow::anyhow!("Failed to load backup manifest: {}", e))?;
-    
-    println!("✅ Manifest loaded: {} files", manifest.file_count);
-    println!();
+    if !json {
+        println!("📥 Loading backup manifest...");
+    }
+    let temp_files = skylock_backup::TempFiles::from_config(&config.backup);
+    let direct_backup = DirectUploadBackup::new(config, hetzner_client1, encryption1, None)?;
+    let manifest = direct_backup.load_manifest(&backup_id).await;
+    audit_trail.record_result(AuditOperation::Decryption, &format!("{} manifest", backup_id), &manifest);
+    let manifest = manifest
+        .context("Failed to load backup manifest")?;
+    
+    if !json {
+        println!("✅ Manifest loaded: {} files", manifest.file_count);
+        println!();
+    }
     
     // Create separate instances for BackupVerifier
-    let hetzner_client2 = skylock_hetzner::HetznerClient::new(hetzner_config)
-        .map_err(|e| anyhow::anyhow!("Failed to create second client: {}", e))?;
-    let encryption2 = skylock_backup::encryption::EncryptionManager::new(&encryption_key)
-        .map_err(|e| anyhow::anyhow!("Failed to create encryption for verification: {}", e))?;
+    let hetzner_client2 = skylock_hetzner::HetznerClient::new(hetzner_config.clone())
+        .context("Failed to create second client")?
+        .with_timeouts(connect_timeout, read_timeout);
+    let encryption2 = Arc::new(skylock_backup::encryption::EncryptionManager::new(&encryption_key)
+        .context("Failed to create encryption for verification")?);
+    
+    let mut verifier = BackupVerifier::new(hetzner_client2)
+        .with_cancellation(cancel_on_ctrl_c())
+        .with_concurrency(concurrency)
+        .with_checkpoint_dir(checkpoint_dir)
+        .with_temp_files(temp_files.clone());
+    if !json {
+        println!("📁 Checking up to {} files at once", concurrency);
+    }
     
-    let verifier = BackupVerifier::new(hetzner_client2);
+    // With mirrors, a full verification checks every copy of each blob
+    if full && !mirrors.is_empty() {
+        let storage_box = skylock_hetzner::HetznerClient::new(hetzner_config)
+            .context("Failed to create replica client")?
+            .with_timeouts(connect_timeout, read_timeout);
+        let mut replicas = MultiBackend::new(WriteQuorum::All)
+            .with_backend("hetzner", Arc::new(HetznerBackend::new(Arc::new(storage_box))
+                .with_temp_dir(temp_files.dir().to_path_buf())));
+        for mirror in &mirrors {
+            let storage = StorageConfig {
+                connection_string: Some(mirror.path.to_string_lossy().into_owned()),
+                ..Default::default()
+            };
+            let backend = LocalStorageProvider::new(&storage)
+                .map_err(|e| anyhow::anyhow!("Invalid mirror '{}': {}", mirror.name, e))?;
+            replicas = replicas.with_backend(mirror.name.clone(), Arc::new(backend));
+        }
+        if !json {
+            println!("🪞 Checking {} copies: {}", mirrors.len() + 1, replicas.backend_names().join(", "));
+            println!();
+        }
+        verifier = verifier.with_replicas(Arc::new(replicas));
+    }
     
     // Perform verification
-    let result = if full {
-        verifier.verify_full(&manifest, Arc::new(encryption2)).await
-            .map_err(|e| anyhow::anyhow!("Verification failed: {}", e))?
+    let mut result = if full {
+        let result = verifier.verify_full(&manifest, encryption2.clone()).await;
+        audit_trail.record_result(AuditOperation::Decryption, &backup_id, &result);
+        result.context("Verification failed")?
     } else {
         verifier.verify_quick(&manifest).await
-            .map_err(|e| anyhow::anyhow!("Verification failed: {}", e))?
+            .context("Verification failed")?
     };
     
+    // Rewrite damaged copies, then verify again to confirm
+    let mut repair_report = None;
+    if repair && !result.incomplete && !result.is_success() {
+        if !json {
+            println!("🩹 Repairing {} file(s) from intact copies...", result.repairable_files().len());
+        }
+        let report = verifier.repair(&manifest, &result, &encryption2).await
+            .context("Repair failed")?;
+        if !json {
+            println!("   {} repaired, {} could not be repaired", report.repaired.len(), report.failed.len());
+            println!();
+        }
+        result = verifier.verify_full(&manifest, encryption2.clone()).await
+            .context("Verification after repair failed")?;
+        repair_report = Some(report);
+    }
+    
+    if result.incomplete && !json {
+        println!();
+        println!("{}", format!(
+            "⚠️  Verification cancelled after {} of {} files",
+            result.file_results.len(),
+            result.total_files
+        ).bright_yellow());
+        if full {
+            println!("   Run the same command again to resume; verified files are skipped.");
+        }
+    }
+    
+    if json {
+        output::print_json(&output::VerifyReport {
+            mode: if full { "full" } else { "quick" },
+            success: result.is_success(),
+            result: &result,
+            repair: repair_report.as_ref(),
+        })?;
+        
+        if !result.is_success() {
+            return Err(output::VerificationFailed(backup_id).into());
+        }
+        return Ok(());
+    }
+    
     // Display results
     println!();
     println!("{}", "📋 Verification Results".bright_blue().bold());
@@ -1681,9 +3453,24 @@ async fn verify_backup(
             }
         }
         
+        // Show which failures a mirror can fix
+        let repairable = result.repairable_files().len();
+        if repairable > 0 {
+            println!("{}", format!(
+                "🪞 {} of {} damaged files have an intact copy on another backend",
+                repairable,
+                result.files_with_errors
+            ).bright_cyan());
+            println!();
+        }
+        
         // Suggest recovery actions
         println!("{}", "💡 Recovery Suggestions:".bright_yellow().bold());
         
+        if repairable > 0 && !repair {
+            println!("   - Run with {} to rewrite them from the intact copies", "--repair".bright_yellow());
+        }
+        
         if !missing.is_empty() {
             println!("   1. Re-run the backup to ensure all files are uploaded");
             println!("   2. Check network connectivity and storage space");
@@ -1703,6 +3490,10 @@ async fn verify_backup(
         println!("   (This will download all files and may take significant time)");
     }
     
+    if !result.is_success() {
+        return Err(output::VerificationFailed(backup_id).into());
+    }
+    
     Ok(())
 }
 
@@ -1710,20 +3501,27 @@ async fn show_file_changes(
     paths: Vec<PathBuf>,
     summary_only: bool,
     config_path: Option<PathBuf>,
+    format: OutputFormat,
 ) -> Result<()> {
     use progress::ErrorHandler;
     use colored::*;
     use skylock_backup::{ChangeTracker, ChangeType};
     
-    ErrorHandler::print_info("File Change Detection", "Detecting changes since last backup");
+    let json = format.is_json();
+    
+    if !json {
+        ErrorHandler::print_info("File Change Detection", "Detecting changes since last backup");
+    }
     
     // Load configuration
-    let config = match Config::load(config_path) {
+    let config = match Config::load_with_credentials(config_path).await {
         Ok(config) => config,
         Err(e) => {
-            ErrorHandler::print_error("Configuration Error", &e.to_string());
-            ErrorHandler::suggest_solution("Run 'skylock config' to generate a configuration file");
-            return Err(anyhow::anyhow!("Configuration required"));
+            if !json {
+                ErrorHandler::print_error("Configuration Error", &e.to_string());
+                ErrorHandler::suggest_solution("Run 'skylock config' to generate a configuration file");
+            }
+            return Err(anyhow::Error::from(e).context("Configuration required"));
         }
     };
     
@@ -1735,9 +3533,11 @@ async fn show_file_changes(
     };
     
     if check_paths.is_empty() {
-        ErrorHandler::print_error("No Paths", "No paths specified and none in config");
-        ErrorHandler::suggest_solution("Specify paths to check or add backup_paths to config");
-        return Err(anyhow::anyhow!("No paths to check for changes"));
+        if !json {
+            ErrorHandler::print_error("No Paths", "No paths specified and none in config");
+            ErrorHandler::suggest_solution("Specify paths to check or add backup_paths to config");
+        }
+        return Err(CliError::new(ErrorKind::Config, "No paths to check for changes").into());
     }
     
     // Set up change tracker
@@ -1746,7 +3546,19 @@ async fn show_file_changes(
     let tracker = ChangeTracker::new(index_dir);
     
     // Check if there's a previous backup to compare against
-    if !tracker.has_latest_index().await {
+    let has_previous = tracker.has_latest_index().await;
+    if !has_previous && json {
+        let file_index = skylock_backup::FileIndex::build(&check_paths)
+            .context("Failed to scan files")?;
+        
+        return output::print_json(&output::ChangesReport {
+            first_backup: true,
+            summary: output::ChangeCounts { added: file_index.file_count(), total: file_index.file_count(), ..Default::default() },
+            changes: Vec::new(),
+        });
+    }
+    
+    if !has_previous {
         println!();
         println!("{}", "⚠️  No previous backup found".bright_yellow());
         println!("   This appears to be the first backup.");
@@ -1756,7 +3568,7 @@ async fn show_file_changes(
         // Count files that would be backed up
         println!("📊 Scanning current files...");
         let file_index = skylock_backup::FileIndex::build(&check_paths)
-            .map_err(|e| anyhow::anyhow!("Failed to scan files: {}", e))?;
+            .context("Failed to scan files")?;
         
         println!();
         println!("   {} {} files would be backed up",
@@ -1769,9 +3581,19 @@ async fn show_file_changes(
     }
     
     // Detect changes
-    println!("🔍 Detecting changes...");
+    if !json {
+        println!("🔍 Detecting changes...");
+    }
     let changes = tracker.detect_changes_since_last_backup(&check_paths).await
-        .map_err(|e| anyhow::anyhow!("Failed to detect changes: {}", e))?;
+        .context("Failed to detect changes")?;
+    
+    if json {
+        return output::print_json(&output::ChangesReport {
+            first_backup: false,
+            summary: output::ChangeCounts::from_changes(&changes),
+            changes: if summary_only { Vec::new() } else { changes.iter().collect() },
+        });
+    }
     
     if changes.is_empty() {
         println!();
@@ -1785,6 +3607,7 @@ async fn show_file_changes(
     let mut removed_count = 0;
     let mut modified_count = 0;
     let mut metadata_count = 0;
+    let mut moved_count = 0;
     
     for change in &changes {
         match change.change_type {
@@ -1792,6 +3615,7 @@ async fn show_file_changes(
             ChangeType::Removed => removed_count += 1,
             ChangeType::Modified => modified_count += 1,
             ChangeType::MetadataChanged => metadata_count += 1,
+            ChangeType::Moved => moved_count += 1,
         }
     }
     
@@ -1828,6 +3652,13 @@ async fn show_file_changes(
         );
     }
     
+    if moved_count > 0 {
+        println!("   {} {} files (moved, not re-uploaded)",
+            "→".bright_blue().bold(),
+            moved_count.to_string().bright_blue()
+        );
+    }
+    
     println!();
     println!("   {} {} total changes",
         "Σ".bright_cyan(),
@@ -1846,9 +3677,15 @@ async fn show_file_changes(
                 ChangeType::Removed => ("-".to_string(), |s| s.bright_red()),
                 ChangeType::Modified => ("~".to_string(), |s| s.bright_yellow()),
                 ChangeType::MetadataChanged => ("◦".to_string(), |s| s.dimmed()),
+                ChangeType::Moved => ("→".to_string(), |s| s.bright_blue()),
             };
             
-            let path_str = change.path.display().to_string();
+            let path_str = match change.old_info {
+                Some(ref old_info) if change.change_type == ChangeType::Moved => {
+                    format!("{} → {}", old_info.path.display(), change.path.display())
+                }
+                _ => change.path.display().to_string(),
+            };
             println!("   {} {}", symbol, color(path_str));
         }
         
@@ -1872,62 +3709,47 @@ async fn show_file_changes(
     Ok(())
 }
 
-async fn test_compression() -> Result<()> {
-    use skylock_core::compression::{CompressionEngine, CompressionConfig, CompressionType};
-    
-    println!("🗜️ Testing Zstandard compression...");
-    
-    // Create test data with repetitive content (good for compression)
-    let test_data = "This is a test string that repeats. ".repeat(100);
-    let test_bytes = test_data.as_bytes();
-    println!("📝 Test data: {} bytes", test_bytes.len());
-    
-    // Create compression engine
-    let config = CompressionConfig {
-        compression_type: CompressionType::Zstd,
-        level: 6,
-    };
-    let engine = CompressionEngine::new(config);
-    
-    // Compress
-    match engine.compress(test_bytes) {
-        Ok(compressed) => {
-            let compression_ratio = (test_bytes.len() as f64) / (compressed.len() as f64);
-            println!("✅ Compression successful: {} bytes -> {} bytes (ratio: {:.2}x)", 
-                    test_bytes.len(), compressed.len(), compression_ratio);
-            
-            // Decompress
-            match engine.decompress(&compressed) {
-                Ok(decompressed) => {
-                    if decompressed == test_bytes {
-                        println!("✅ Decompression successful and data matches!");
-                    } else {
-                        return Err(anyhow::anyhow!("Decompressed data doesn't match original"));
-                    }
-                }
-                Err(e) => {
-                    return Err(anyhow::anyhow!("Decompression failed: {}", e));
-                }
-            }
-        }
-        Err(e) => {
-            return Err(anyhow::anyhow!("Compression failed: {}", e));
-        }
-    }
-    
-    println!("✅ Compression test passed");
-    Ok(())
-}
-
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
+    // Parse command line arguments
+    let cli = Cli::parse();
+    
     // Initialize secure logging system with file rotation
     let log_dir = directories::ProjectDirs::from("dev", "nullme", "skylock")
         .map(|dirs| dirs.data_dir().join("logs"))
         .unwrap_or_else(|| std::path::PathBuf::from("/tmp/skylock/logs"));
     
+    // Format and levels come from [logging] (or SKYLOCK_LOGGING__*) when the
+    // config loads; commands report config errors themselves later
+    let logging = Config::load(cli.config.clone())
+        .map(|config| config.logging)
+        .unwrap_or_default();
+    let log_format = match logging.format.as_deref().map(str::parse) {
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            eprintln!("Warning: {}, using text logs", e);
+            skylock_hybrid::logging::LogFormat::Text
+        }
+        None => skylock_hybrid::logging::LogFormat::Text,
+    };
+    let log_level = match logging.level.as_deref() {
+        Some(level) => match skylock_hybrid::logging::parse_filter(level) {
+            Ok(_) => level,
+            Err(e) => {
+                eprintln!("Warning: {}, logging at {}", e, skylock_hybrid::logging::DEFAULT_LOG_LEVEL);
+                skylock_hybrid::logging::DEFAULT_LOG_LEVEL
+            }
+        },
+        None => skylock_hybrid::logging::DEFAULT_LOG_LEVEL,
+    };
+    
     // Initialize logging (keep guard alive for duration of program)
-    let _log_guard = skylock_hybrid::logging::init_logging(log_dir.clone(), "info")
+    let _log_guard = skylock_hybrid::logging::init_logging(
+        log_dir.clone(),
+        log_level,
+        log_format,
+        logging.otlp_endpoint.as_deref(),
+    )
         .unwrap_or_else(|e| {
             eprintln!("Warning: Failed to initialize file logging: {}", e);
             eprintln!("Continuing with console-only logging...");
@@ -1942,51 +3764,52 @@ async fn main() -> Result<()> {
     info!("Skylock starting up...");
     info!("Logs are being written to: {}", log_dir.display());
 
-    // Parse command line arguments
-    let cli = Cli::parse();
+    skylock_ui::interactive::set_assume_yes(cli.yes);
 
     // Handle CLI commands
+    // Failed commands exit with the code of their error category
     if let Some(command) = cli.command {
-        return handle_command(command, cli.config).await;
+        let format = cli.format;
+        if let Err(e) = handle_command(command, cli.config, format).await {
+            if format.is_json() {
+                output::print_json_error(&e);
+            } else {
+                eprintln!("Error: {:?}", e);
+            }
+            drop(_log_guard);
+            std::process::exit(output::exit_code(&e));
+        }
+        return Ok(());
     }
 
-    // Load and validate configuration
-    let config = Config::load(cli.config)?;
+    // Load and validate configuration, with secrets from the configured
+    // credential sources (env, keychain, encrypted file or Vault)
+    let config = Config::load_with_credentials(cli.config).await?;
     config.validate()?;
     info!("Configuration loaded and validated successfully");
 
-    // Initialize security
-    let credential_manager = CredentialManager::new(
-        config.data_dir.join("secrets/master.key")
-    )?;
-
     // Initialize recovery
     let recovery_manager = RecoveryManager::new(
         config.data_dir.join("recovery/state.json")
     );
 
     // Initialize shutdown manager
-    let shutdown_manager = ShutdownManager::new();
+    let shutdown_manager = shutdown::ShutdownManager::new();
 
-    // Load encrypted credentials
-    let hetzner_key = credential_manager.get_credential("hetzner_api_key").await?;
-    let syncthing_key = credential_manager.get_credential("syncthing_api_key").await?;
-
-    // Initialize clients with secure credentials
-    let hetzner = HetznerClient::new(
-        config.hetzner.clone(),
-        &hetzner_key
-    )?;
+    // Initialize clients with the resolved credentials
+    let hetzner = HetznerClient::new(config.hetzner.clone())?;
     info!("Hetzner client initialized");
 
-    // Initialize backup manager
-    let backup_manager = BackupManager::new(config.backup.clone(), hetzner.clone());
-    info!("Backup manager initialized");
-
     // Initialize Syncthing client
     let syncthing = SyncthingClient::new(&config.syncthing.api_url, &config.syncthing.api_key)?;
     info!("Syncthing client initialized");
 
+    // Serve Prometheus metrics when enabled
+    let daemon_metrics = Arc::new(metrics::DaemonMetrics::new());
+    if config.metrics.enabled {
+        metrics::spawn_server(&config.metrics.bind_address, daemon_metrics.clone()).await?;
+    }
+
     // Initialize notification system
     let (notification_manager, mut notification_rx) = NotificationManager::new();
 
@@ -2027,8 +3850,10 @@ async fn main() -> Result<()> {
     });
 
     // Start file monitor event processing
+    let queue_metrics = daemon_metrics.clone();
     tokio::spawn(async move {
         while let Some(event) = event_rx.recv().await {
+            queue_metrics.set_queue_depth(event_rx.len() as u64);
             if let Err(e) = file_monitor.process_event(event).await {
                 error!("Error processing file event: {}", e);
             }
@@ -2049,27 +3874,96 @@ async fn main() -> Result<()> {
 
     // Test connection to Hetzner
     match hetzner.list_files("/").await {
-        Ok(_) => info!("Successfully connected to Hetzner Storage Box"),
-        Err(e) => error!("Failed to connect to Hetzner Storage Box: {}", e),
+        Ok(_) => {
+            daemon_metrics.breaker_success("hetzner");
+            info!("Successfully connected to Hetzner Storage Box");
+        }
+        Err(e) => {
+            daemon_metrics.breaker_failure("hetzner");
+            error!("Failed to connect to Hetzner Storage Box: {}", e);
+        }
+    }
+
+    // Start background scrub: sample stored blobs on its own schedule and
+    // fully verify any backup whose sample fails
+    if config.scrub.enabled {
+        let schedule = config.scrub.schedule.clone();
+        let config = config.clone();
+        let shutdown = shutdown_manager.token();
+        let started = Utc::now();
+        tokio::spawn(async move {
+            let mut state = skylock_backup::ScrubState::load_or_new(&config.data_dir).await;
+            // A failed scrub waits for its next scheduled time too, instead
+            // of being retried every minute
+            let mut last_attempt = None;
+            while !shutdown.is_cancelled() {
+                let since = last_attempt.max(state.last_run).or(Some(started));
+                if scheduler::should_run_backup(&config.scrub.schedule, Utc::now(), since) {
+                    last_attempt = Some(Utc::now());
+                    match run_scheduled_scrub(&config, &state, shutdown.clone()).await {
+                        Ok(report) if report.incomplete => {
+                            info!("Scrub {} stopped early, it runs again next time", report.epoch);
+                        }
+                        Ok(report) => {
+                            info!(
+                                "Scrub {} checked {} of {} files across {} backups",
+                                report.epoch, report.files_sampled, report.files_total, report.backups_sampled
+                            );
+                            for finding in &report.findings {
+                                let damaged = finding.full.files_with_errors;
+                                error!("Scrub found {} damaged file(s) in backup {}", damaged, finding.backup_id);
+                                notifications::notify(
+                                    &config.notifications,
+                                    notifications::WebhookPayload::scrub_failed(
+                                        &finding.backup_id, damaged, finding.full.total_files
+                                    ),
+                                );
+                            }
+                            state.advance(Utc::now());
+                            if let Err(e) = state.save(&config.data_dir).await {
+                                error!("Failed to save scrub state: {}", e);
+                            }
+                        }
+                        Err(e) => error!("Scrub failed: {}", e),
+                    }
+                }
+                tokio::select! {
+                    _ = sleep(Duration::from_secs(60)) => {}
+                    _ = shutdown.cancelled() => {}
+                }
+            }
+        });
+        info!("Background scrub scheduled: {}", schedule);
     }
 
     // Start backup scheduler
     let notification_manager_clone = notification_manager.clone();
+    let shutdown = shutdown_manager.token();
     let backup_handle = tokio::spawn(async move {
-        loop {
+        while !shutdown.is_cancelled() {
             let now = Utc::now();
             if should_run_backup(&config.backup.schedule, now) {
                 if let Err(e) = notification_manager_clone.notify_backup_started() {
                     error!("Failed to send backup started notification: {}", e);
                 }
-                match backup_manager.create_backup().await {
-                    Ok(metadata) => {
-                        if let Err(e) = notification_manager_clone.notify_backup_completed(metadata.id.clone()) {
+                daemon_metrics.backup_started();
+                daemon_metrics.breaker_attempt("hetzner");
+                let started = std::time::Instant::now();
+                match run_scheduled_backup(&config, shutdown.clone(), daemon_metrics.upload_metrics()).await {
+                    Ok(manifest) => {
+                        daemon_metrics.backup_succeeded(started.elapsed(), Utc::now());
+                        daemon_metrics.breaker_success("hetzner");
+                        if let Err(e) = notification_manager_clone.notify_backup_completed(manifest.backup_id.clone()) {
                             error!("Failed to send backup completed notification: {}", e);
                         }
-                        info!("Backup completed successfully: {}", metadata.id);
+                        info!("Backup completed successfully: {}", manifest.backup_id);
+                    }
+                    Err(e) if shutdown.is_cancelled() => {
+                        info!("Backup stopped for shutdown, resume point saved: {}", e);
                     }
                     Err(e) => {
+                        daemon_metrics.backup_failed();
+                        daemon_metrics.breaker_failure("hetzner");
                         if let Err(e) = notification_manager_clone.notify_backup_failed(e.to_string()) {
                             error!("Failed to send backup failed notification: {}", e);
                         }
@@ -2077,18 +3971,93 @@ async fn main() -> Result<()> {
                     }
                 }
             }
-            sleep(Duration::from_secs(60)).await;
+            tokio::select! {
+                _ = sleep(Duration::from_secs(60)) => {}
+                _ = shutdown.cancelled() => {}
+            }
         }
     });
 
-    // Wait for shutdown signal
+    // Wait for shutdown signal, then let the running backup finish its
+    // current files and save a resume point
     let _ = shutdown_rx.recv().await;
-    backup_handle.abort();
     info!("Shutting down gracefully");
+    if shutdown_manager.shutdown(backup_handle).await == shutdown::ShutdownOutcome::Aborted {
+        error!("Backup did not stop in time and was aborted; it resumes from its last checkpoint");
+    }
 
     Ok(())
 }
 
+/// Run one scheduled backup of the configured paths, counting uploads in
+/// `upload_metrics` and stopping at the next file boundary once `shutdown`
+/// is cancelled
+async fn run_scheduled_backup(
+    config: &Config,
+    shutdown: tokio_util::sync::CancellationToken,
+    upload_metrics: Arc<skylock_backup::ThroughputMetrics>,
+) -> Result<skylock_backup::direct_upload::BackupManifest> {
+    let hetzner_client = skylock_hetzner::HetznerClient::new(skylock_hetzner::HetznerConfig {
+        endpoint: config.hetzner.endpoint.clone(),
+        username: config.hetzner.username.clone(),
+        password: config.hetzner.password.clone(),
+        api_token: config.hetzner.encryption_key.clone(),
+        encryption_key: config.hetzner.encryption_key.clone(),
+    })?;
+    let algorithm = match config.backup.encryption_algorithm.as_deref() {
+        Some(name) => name.parse::<skylock_backup::AeadAlgorithm>()
+            .context("Invalid backup.encryption_algorithm")?,
+        None => skylock_backup::AeadAlgorithm::default(),
+    };
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption")?
+        .with_algorithm(algorithm);
+    let bandwidth_limit = config.backup.max_speed_limit.as_deref()
+        .map(skylock_backup::parse_bandwidth_limit)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("Invalid backup.max_speed_limit")?;
+
+    let backup = skylock_backup::DirectUploadBackup::new(config.clone(), hetzner_client, encryption, bandwidth_limit)?
+        .with_shutdown(shutdown)
+        .with_upload_metrics(upload_metrics);
+    Ok(backup.create_backup(&config.backup.backup_paths).await?)
+}
+
+/// Run the scrub epoch recorded in `state` over every backup
+async fn run_scheduled_scrub(
+    config: &Config,
+    state: &skylock_backup::ScrubState,
+    shutdown: tokio_util::sync::CancellationToken,
+) -> Result<skylock_backup::ScrubReport> {
+    let hetzner_config = skylock_hetzner::HetznerConfig {
+        endpoint: config.hetzner.endpoint.clone(),
+        username: config.hetzner.username.clone(),
+        password: config.hetzner.password.clone(),
+        api_token: config.hetzner.encryption_key.clone(),
+        encryption_key: config.hetzner.encryption_key.clone(),
+    };
+    let encryption = skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption")?;
+    let backup = skylock_backup::DirectUploadBackup::new(
+        config.clone(),
+        skylock_hetzner::HetznerClient::new(hetzner_config.clone())?,
+        encryption,
+        None,
+    )?;
+    let manifests = backup.list_backups().await.context("Failed to list backups")?;
+
+    let verifier = skylock_backup::BackupVerifier::new(skylock_hetzner::HetznerClient::new(hetzner_config)?)
+        .with_cancellation(shutdown)
+        .with_concurrency(config.backup.max_concurrent_uploads
+            .unwrap_or_else(skylock_backup::parallelism::default_concurrency))
+        .with_temp_files(skylock_backup::TempFiles::from_config(&config.backup));
+    let encryption = Arc::new(skylock_backup::encryption::EncryptionManager::new(&config.hetzner.encryption_key)
+        .context("Failed to create encryption for verification")?);
+    let scrubber = skylock_backup::Scrubber::new(verifier, state.seed, config.scrub.cycles);
+    Ok(scrubber.run(&manifests, encryption, state.epoch).await?)
+}
+
 fn should_run_backup(schedule: &str, now: DateTime<Utc>) -> bool {
     use cron::Schedule;
     use std::str::FromStr;
@@ -2101,3 +4070,200 @@ fn should_run_backup(schedule: &str, now: DateTime<Utc>) -> bool {
         })
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::Mutex as StdMutex;
+    use skylock_hetzner::test_server::{self, Request};
+
+    /// Backup and paths `skylock restore` picks for `args`
+    fn parse_restore(args: &[&str]) -> (BackupSelection, Vec<PathBuf>) {
+        let mut argv = vec!["skylock", "restore"];
+        argv.extend_from_slice(args);
+        let cli = Cli::try_parse_from(argv).unwrap();
+        let Some(Commands::Restore { backup_id, mut paths, latest, pattern, tag, source, .. }) = cli.command else {
+            panic!("not a restore command");
+        };
+        let selection = restore_selection(backup_id, latest, &mut paths, pattern, tag, source);
+        (selection, paths)
+    }
+
+    #[test]
+    fn test_restore_latest_arguments() {
+        let (selection, paths) = parse_restore(&["backup_20240101_020000", "docs"]);
+        assert!(matches!(selection, BackupSelection::Id(ref id) if id == "backup_20240101_020000"));
+        assert_eq!(paths, vec![PathBuf::from("docs")]);
+
+        // Every positional is a path with --latest
+        let (selection, paths) = parse_restore(&["--latest", "--tag", "nightly", "--source", "/etc", "docs", "src"]);
+        let BackupSelection::Latest(filter) = selection else { panic!("expected --latest") };
+        assert_eq!(filter.tag.as_deref(), Some("nightly"));
+        assert_eq!(filter.source, Some(PathBuf::from("/etc")));
+        assert_eq!(paths, vec![PathBuf::from("docs"), PathBuf::from("src")]);
+
+        let (selection, _) = parse_restore(&["--latest", "--pattern", "daily_*"]);
+        assert!(matches!(selection, BackupSelection::Latest(ref f) if f.pattern.as_deref() == Some("daily_*")));
+
+        // An ID or --latest is required, and the filters only go with --latest
+        assert!(Cli::try_parse_from(["skylock", "restore"]).is_err());
+        assert!(Cli::try_parse_from(["skylock", "restore", "backup_1", "--tag", "nightly"]).is_err());
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = vec![" pre-upgrade".to_string(), "keep".to_string(), "".to_string(), "keep".to_string()];
+        assert_eq!(normalize_tags(tags).unwrap(), vec!["pre-upgrade", "keep"]);
+        assert!(normalize_tags(vec!["two words".to_string()]).is_err());
+    }
+
+    /// Write a config for `endpoint` into `dir`, starting from the default
+    /// config so that only the storage settings differ
+    async fn write_config(dir: &Path, endpoint: &str) -> PathBuf {
+        let path = dir.join("config.toml");
+        generate_default_config(Some(path.clone())).await.unwrap();
+        let mut config = Config::load(Some(path.clone())).unwrap();
+        config.hetzner.endpoint = endpoint.to_string();
+        config.hetzner.username = "user".to_string();
+        config.hetzner.password = "pass".to_string();
+        config.hetzner.encryption_key = "test_password".to_string();
+        config.data_dir = dir.join("data");
+        std::fs::write(&path, toml::to_string_pretty(&config).unwrap()).unwrap();
+        path
+    }
+
+    /// Minimal WebDAV server keeping files in memory; every request is
+    /// answered with `status` instead when it is set
+    async fn webdav_server(files: Arc<StdMutex<HashMap<String, Vec<u8>>>>, status: Option<u16>) -> String {
+        let (listener, endpoint) = test_server::bind().await;
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let files = files.clone();
+                tokio::spawn(async move {
+                    let Some(request) = Request::read(&mut socket).await else { return };
+                    let (code, response) = {
+                        let mut files = files.lock().unwrap();
+                        match (status, request.method.as_str()) {
+                            (Some(code), _) => (code, Vec::new()),
+                            (None, "MKCOL") => (201, Vec::new()),
+                            (None, "PUT") => {
+                                files.insert(request.path.clone(), request.body.clone());
+                                (201, Vec::new())
+                            }
+                            (None, "GET" | "HEAD") => match files.get(&request.path) {
+                                Some(data) => (200, data.clone()),
+                                None => (404, Vec::new()),
+                            },
+                            (None, "PROPFIND") => {
+                                let prefix = format!("{}/", request.path.trim_end_matches('/'));
+                                let mut xml = String::from("<D:multistatus xmlns:D=\"DAV:\">\n");
+                                for file in files.keys().filter(|file| file.starts_with(&prefix)) {
+                                    xml.push_str(&format!("<D:response>\n<D:href>{}</D:href>\n</D:response>\n", file));
+                                }
+                                xml.push_str("</D:multistatus>\n");
+                                (207, xml.into_bytes())
+                            }
+                            _ => (405, Vec::new()),
+                        }
+                    };
+                    request.respond(&mut socket, code, &response).await;
+                });
+            }
+        });
+        endpoint
+    }
+
+    async fn list_exit_code(config_path: PathBuf) -> i32 {
+        let error = list_backups(false, time_filter::BackupFilter::default(), Some(config_path), OutputFormat::Json)
+            .await
+            .unwrap_err();
+        output::exit_code(&error)
+    }
+
+    #[tokio::test]
+    async fn test_config_failures_exit_with_config_code() {
+        let dir = tempfile::TempDir::new().unwrap();
+
+        // Missing config file
+        assert_eq!(list_exit_code(dir.path().join("missing.toml")).await, output::EXIT_CONFIG);
+
+        // Placeholder credentials of a freshly generated config
+        let path = dir.path().join("config.toml");
+        generate_default_config(Some(path.clone())).await.unwrap();
+        assert_eq!(list_exit_code(path).await, output::EXIT_CONFIG);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_flag_overrides_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        generate_default_config(Some(path.clone())).await.unwrap();
+        let mut config = Config::load(Some(path)).unwrap();
+
+        assert_eq!(effective_concurrency(None, &config).unwrap(), skylock_backup::parallelism::default_concurrency());
+        config.backup.max_concurrent_uploads = Some(8);
+        assert_eq!(effective_concurrency(None, &config).unwrap(), 8);
+        assert_eq!(effective_concurrency(Some(2), &config).unwrap(), 2);
+        assert_eq!(effective_concurrency(Some(500), &config).unwrap(), skylock_backup::parallelism::MAX_CONCURRENCY);
+
+        let error = effective_concurrency(Some(0), &config).unwrap_err();
+        assert_eq!(output::exit_code(&error), output::EXIT_CONFIG);
+        config.backup.max_concurrent_uploads = Some(0);
+        assert!(effective_concurrency(None, &config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_exits_with_network_code() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // Nothing listens on a port freed right after binding
+        let (listener, endpoint) = test_server::bind().await;
+        drop(listener);
+
+        let config_path = write_config(dir.path(), &endpoint).await;
+        assert_eq!(list_exit_code(config_path).await, output::EXIT_NETWORK);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_credentials_exit_with_auth_code() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let endpoint = webdav_server(Arc::default(), Some(401)).await;
+
+        let config_path = write_config(dir.path(), &endpoint).await;
+        assert_eq!(list_exit_code(config_path).await, output::EXIT_AUTH);
+    }
+
+    #[tokio::test]
+    async fn test_failed_verification_exits_with_integrity_code() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("notes.txt"), "backed up and then lost").unwrap();
+
+        let files: Arc<StdMutex<HashMap<String, Vec<u8>>>> = Arc::default();
+        let endpoint = webdav_server(files.clone(), None).await;
+        let config_path = write_config(dir.path(), &endpoint).await;
+
+        // Keys come from a keyfile, so the verifying run derives the same one
+        let config = Config::load(Some(config_path.clone())).unwrap();
+        let keyfile = skylock_backup::key_rotation::KeyRotationManager::keyfile_path(&config.data_dir);
+        std::fs::create_dir_all(&config.data_dir).unwrap();
+        skylock_backup::key_rotation::KeyRotationManager::create_keyfile(keyfile, "test_password", Default::default()).unwrap();
+        let client = test_server::client(&endpoint);
+        let encryption = skylock_backup::EncryptionManager::new("test_password").unwrap();
+        let manifest = skylock_backup::DirectUploadBackup::new(config, client, encryption, None).unwrap()
+            .create_backup(&[source])
+            .await
+            .unwrap();
+
+        // Lose the uploaded file, keeping the manifest
+        files.lock().unwrap().retain(|path, _| path.contains("manifest"));
+
+        let error = verify_backup(manifest.backup_id, false, false, None, Some(config_path), OutputFormat::Json)
+            .await
+            .unwrap_err();
+        assert_eq!(output::exit_code(&error), output::EXIT_INTEGRITY);
+    }
+}
@@ -0,0 +1,380 @@
+//! Prometheus metrics endpoint for the daemon
+//!
+//! Tracks the backup, performance and error figures of the monitoring
+//! module's `BackupMetrics`, `PerformanceMetrics` and `ErrorMetrics` and
+//! serves them in the Prometheus text exposition format on `/metrics`.
+//! Uploaded bytes are read from the upload counters the daemon's backups
+//! share, so they grow while a backup runs.
+//! The encoding and the HTTP handling are hand-rolled to keep the daemon
+//! free of a web framework.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use skylock_backup::ThroughputMetrics;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Content type of the Prometheus text format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Consecutive failures that open a circuit breaker
+const BREAKER_FAILURE_THRESHOLD: u32 = 3;
+
+/// State of a circuit breaker guarding a remote endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    const ALL: [CircuitState; 3] = [CircuitState::Closed, CircuitState::Open, CircuitState::HalfOpen];
+
+    fn label(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// Circuit breaker over the requests to one endpoint
+///
+/// Opens after [`BREAKER_FAILURE_THRESHOLD`] consecutive failures, lets the
+/// next attempt through half-open, and closes on the first success.
+#[derive(Debug)]
+struct Breaker {
+    state: CircuitState,
+    failures: u32,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Self { state: CircuitState::Closed, failures: 0 }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    backups_total: u64,
+    backups_succeeded: u64,
+    backups_failed: u64,
+    last_backup: Option<DateTime<Utc>>,
+    /// Uploaded bytes when the running backup started
+    uploaded_at_start: u64,
+    throughput_bytes_per_second: f64,
+    queue_depth: u64,
+    circuit_breakers: BTreeMap<String, Breaker>,
+}
+
+/// Counters and gauges updated by the daemon and rendered on scrape
+#[derive(Debug, Default)]
+pub struct DaemonMetrics {
+    state: Mutex<MetricsState>,
+    uploads: Arc<ThroughputMetrics>,
+}
+
+impl DaemonMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MetricsState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Upload counters to hand to the daemon's backups
+    pub fn upload_metrics(&self) -> Arc<ThroughputMetrics> {
+        self.uploads.clone()
+    }
+
+    pub fn backup_started(&self) {
+        let mut state = self.state();
+        state.backups_total += 1;
+        state.uploaded_at_start = self.uploads.bytes_uploaded();
+    }
+
+    /// Record a finished backup; throughput is taken from this backup alone
+    pub fn backup_succeeded(&self, elapsed: Duration, finished_at: DateTime<Utc>) {
+        let mut state = self.state();
+        state.backups_succeeded += 1;
+        state.last_backup = Some(finished_at);
+        let bytes = self.uploads.bytes_uploaded().saturating_sub(state.uploaded_at_start);
+        let seconds = elapsed.as_secs_f64();
+        state.throughput_bytes_per_second = if seconds > 0.0 { bytes as f64 / seconds } else { 0.0 };
+    }
+
+    pub fn backup_failed(&self) {
+        let mut state = self.state();
+        state.backups_failed += 1;
+        state.throughput_bytes_per_second = 0.0;
+    }
+
+    /// Number of file events waiting to be processed
+    pub fn set_queue_depth(&self, depth: u64) {
+        self.state().queue_depth = depth;
+    }
+
+    /// A request to `name` is about to be made; an open breaker goes half-open
+    pub fn breaker_attempt(&self, name: &str) {
+        let mut state = self.state();
+        let breaker = state.circuit_breakers.entry(name.to_string()).or_default();
+        if breaker.state == CircuitState::Open {
+            info!("Circuit breaker {} is half-open", name);
+            breaker.state = CircuitState::HalfOpen;
+        }
+    }
+
+    /// A request to `name` succeeded, closing its breaker
+    pub fn breaker_success(&self, name: &str) {
+        let mut state = self.state();
+        let breaker = state.circuit_breakers.entry(name.to_string()).or_default();
+        if breaker.state != CircuitState::Closed {
+            info!("Circuit breaker {} closed", name);
+        }
+        *breaker = Breaker::default();
+    }
+
+    /// A request to `name` failed; a half-open breaker or too many failures
+    /// in a row open it
+    pub fn breaker_failure(&self, name: &str) {
+        let mut state = self.state();
+        let breaker = state.circuit_breakers.entry(name.to_string()).or_default();
+        breaker.failures += 1;
+        let opens = match breaker.state {
+            CircuitState::Closed => breaker.failures >= BREAKER_FAILURE_THRESHOLD,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if opens {
+            warn!("Circuit breaker {} opened after {} failures", name, breaker.failures);
+            breaker.state = CircuitState::Open;
+        }
+    }
+
+    /// Encode all metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let state = self.state();
+        let mut out = String::new();
+
+        write_metric(&mut out, "skylock_backups_total", "counter",
+            "Backups started by the daemon", state.backups_total);
+        write_metric(&mut out, "skylock_backups_succeeded_total", "counter",
+            "Backups that completed successfully", state.backups_succeeded);
+        write_metric(&mut out, "skylock_backups_failed_total", "counter",
+            "Backups that failed", state.backups_failed);
+        write_metric(&mut out, "skylock_last_backup_timestamp_seconds", "gauge",
+            "Unix time of the last successful backup (0 if none)",
+            state.last_backup.map_or(0, |t| t.timestamp()));
+        write_metric(&mut out, "skylock_uploaded_bytes_total", "counter",
+            "Bytes uploaded by backups", self.uploads.bytes_uploaded());
+        write_metric(&mut out, "skylock_throughput_bytes_per_second", "gauge",
+            "Upload throughput of the last backup", state.throughput_bytes_per_second);
+        write_metric(&mut out, "skylock_queue_depth", "gauge",
+            "File events waiting to be processed", state.queue_depth);
+
+        write_header(&mut out, "skylock_circuit_breaker_state", "gauge",
+            "Circuit breaker state per endpoint (1 for the current state)");
+        for (name, breaker) in &state.circuit_breakers {
+            for candidate in CircuitState::ALL {
+                let _ = writeln!(
+                    out,
+                    "skylock_circuit_breaker_state{{breaker=\"{}\",state=\"{}\"}} {}",
+                    escape_label(name),
+                    candidate.label(),
+                    u8::from(candidate == breaker.state)
+                );
+            }
+        }
+
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    write_header(out, name, kind, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Escape a label value as required by the text format
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Bind the metrics endpoint and serve it in the background
+pub async fn spawn_server(bind_address: &str, metrics: Arc<DaemonMetrics>) -> Result<std::net::SocketAddr> {
+    let listener = TcpListener::bind(bind_address).await
+        .map_err(|e| anyhow::anyhow!("Failed to bind metrics endpoint on {}: {}", bind_address, e))?;
+    let address = listener.local_addr()?;
+    info!("Serving Prometheus metrics on http://{}/metrics", address);
+    tokio::spawn(serve(listener, metrics));
+    Ok(address)
+}
+
+/// Answer scrapes until the listener fails
+pub async fn serve(listener: TcpListener, metrics: Arc<DaemonMetrics>) {
+    loop {
+        let Ok((socket, peer)) = listener.accept().await else { break };
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(socket, &metrics).await {
+                debug!("Metrics request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut socket: TcpStream, metrics: &DaemonMetrics) -> std::io::Result<()> {
+    // Only the request line matters; bodies are never expected
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < 8192 {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&buf);
+    let mut parts = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", metrics.render()),
+        (_, "/metrics") => ("405 Method Not Allowed", String::new()),
+        _ => ("404 Not Found", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const METRIC_NAMES: [&str; 8] = [
+        "skylock_backups_total",
+        "skylock_backups_succeeded_total",
+        "skylock_backups_failed_total",
+        "skylock_last_backup_timestamp_seconds",
+        "skylock_uploaded_bytes_total",
+        "skylock_throughput_bytes_per_second",
+        "skylock_queue_depth",
+        "skylock_circuit_breaker_state",
+    ];
+
+    fn sample(body: &str, series: &str) -> Option<String> {
+        body.lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' ').map(str::to_string))
+    }
+
+    #[tokio::test]
+    async fn test_scrape_metrics_endpoint() {
+        let metrics = Arc::new(DaemonMetrics::new());
+        metrics.backup_started();
+        metrics.upload_metrics().record_upload(4096, 10);
+        metrics.backup_succeeded(Duration::from_secs(2), Utc.timestamp_opt(1_700_000_000, 0).unwrap());
+        metrics.backup_started();
+        metrics.backup_failed();
+        metrics.set_queue_depth(3);
+        for _ in 0..BREAKER_FAILURE_THRESHOLD {
+            metrics.breaker_failure("hetzner");
+        }
+
+        let address = spawn_server("127.0.0.1:0", metrics.clone()).await.unwrap();
+        let response = reqwest::get(format!("http://{}/metrics", address)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+        let body = response.text().await.unwrap();
+
+        // One HELP and one TYPE line per metric
+        for name in METRIC_NAMES {
+            let help = format!("# HELP {} ", name);
+            let kind = format!("# TYPE {} ", name);
+            assert_eq!(body.lines().filter(|l| l.starts_with(&help)).count(), 1, "HELP for {}", name);
+            assert_eq!(body.lines().filter(|l| l.starts_with(&kind)).count(), 1, "TYPE for {}", name);
+        }
+        assert!(body.contains("# TYPE skylock_backups_total counter\n"));
+        assert!(body.contains("# TYPE skylock_queue_depth gauge\n"));
+
+        assert_eq!(sample(&body, "skylock_backups_total").as_deref(), Some("2"));
+        assert_eq!(sample(&body, "skylock_backups_succeeded_total").as_deref(), Some("1"));
+        assert_eq!(sample(&body, "skylock_backups_failed_total").as_deref(), Some("1"));
+        assert_eq!(sample(&body, "skylock_last_backup_timestamp_seconds").as_deref(), Some("1700000000"));
+        assert_eq!(sample(&body, "skylock_uploaded_bytes_total").as_deref(), Some("4096"));
+        assert_eq!(sample(&body, "skylock_throughput_bytes_per_second").as_deref(), Some("0"));
+        assert_eq!(sample(&body, "skylock_queue_depth").as_deref(), Some("3"));
+        assert_eq!(sample(&body, "skylock_circuit_breaker_state{breaker=\"hetzner\",state=\"open\"}").as_deref(), Some("1"));
+        assert_eq!(sample(&body, "skylock_circuit_breaker_state{breaker=\"hetzner\",state=\"closed\"}").as_deref(), Some("0"));
+
+        let missing = reqwest::get(format!("http://{}/other", address)).await.unwrap();
+        assert_eq!(missing.status(), 404);
+    }
+
+    #[test]
+    fn test_throughput_of_last_backup() {
+        let metrics = DaemonMetrics::new();
+        let uploads = metrics.upload_metrics();
+        uploads.record_upload(5_000, 10);
+        metrics.backup_started();
+        uploads.record_upload(10_000, 10);
+        assert_eq!(sample(&metrics.render(), "skylock_uploaded_bytes_total").as_deref(), Some("15000"));
+
+        metrics.backup_succeeded(Duration::from_secs(4), Utc::now());
+        let body = metrics.render();
+        assert_eq!(sample(&body, "skylock_throughput_bytes_per_second").as_deref(), Some("2500"));
+        assert_eq!(sample(&body, "skylock_last_backup_timestamp_seconds").as_deref().map(|v| v != "0"), Some(true));
+    }
+
+    #[test]
+    fn test_circuit_breaker_transitions() {
+        let metrics = DaemonMetrics::new();
+        let state = |metrics: &DaemonMetrics| {
+            let body = metrics.render();
+            CircuitState::ALL.into_iter()
+                .find(|s| sample(&body, &format!(
+                    "skylock_circuit_breaker_state{{breaker=\"hetzner\",state=\"{}\"}}", s.label()
+                )).as_deref() == Some("1"))
+        };
+
+        metrics.breaker_success("hetzner");
+        assert_eq!(state(&metrics), Some(CircuitState::Closed));
+        for _ in 1..BREAKER_FAILURE_THRESHOLD {
+            metrics.breaker_failure("hetzner");
+        }
+        assert_eq!(state(&metrics), Some(CircuitState::Closed));
+        metrics.breaker_failure("hetzner");
+        assert_eq!(state(&metrics), Some(CircuitState::Open));
+
+        // A failed trial opens it again at once, a successful one closes it
+        metrics.breaker_attempt("hetzner");
+        assert_eq!(state(&metrics), Some(CircuitState::HalfOpen));
+        metrics.breaker_failure("hetzner");
+        assert_eq!(state(&metrics), Some(CircuitState::Open));
+        metrics.breaker_attempt("hetzner");
+        metrics.breaker_success("hetzner");
+        assert_eq!(state(&metrics), Some(CircuitState::Closed));
+    }
+}
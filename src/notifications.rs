@@ -1,4 +1,11 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use skylock_core::{EmailConfig, NotificationChannel, NotificationEvent, NotificationSeverity, NotificationsConfig};
+use std::time::Duration;
+use tracing::{error, info, warn};
 
 #[cfg(target_os = "linux")]
 use notify_rust::{Notification, Timeout, Urgency};
@@ -38,73 +45,492 @@ pub fn send_notification(title: &str, body: &str, success: bool) -> Result<()> {
     Ok(())
 }
 
-/// Notify backup started
-pub fn notify_backup_started(paths_count: usize) -> Result<()> {
-    send_notification(
-        "🚀 Skylock Backup Started",
-        &format!("Backing up {} path(s)...", paths_count),
-        true,
-    )
+/// Header carrying the HMAC-SHA256 signature of the webhook body
+pub const SIGNATURE_HEADER: &str = "X-Skylock-Signature";
+
+/// Event being notified, as the JSON body POSTed to the webhook; the other
+/// channels show its [`title`](Self::title) and [`body`](Self::body)
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub event: NotificationEvent,
+    pub backup_id: Option<String>,
+    pub file_count: Option<usize>,
+    pub bytes: Option<u64>,
+    pub duration_secs: Option<u64>,
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
 }
 
-/// Notify backup completed
-pub fn notify_backup_completed(files: usize, size_mb: f64, duration_secs: u64) -> Result<()> {
-    let body = format!(
-        "✅ Backed up {} files ({:.2} MB) in {}s",
-        files, size_mb, duration_secs
-    );
-    send_notification("Skylock Backup Complete", &body, true)
+impl WebhookPayload {
+    fn new(event: NotificationEvent) -> Self {
+        Self {
+            event,
+            backup_id: None,
+            file_count: None,
+            bytes: None,
+            duration_secs: None,
+            error: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn started() -> Self {
+        Self::new(NotificationEvent::BackupStarted)
+    }
+
+    pub fn succeeded(backup_id: &str, file_count: Option<usize>, bytes: u64, duration_secs: u64) -> Self {
+        Self {
+            backup_id: Some(backup_id.to_string()),
+            file_count,
+            bytes: Some(bytes),
+            duration_secs: Some(duration_secs),
+            ..Self::new(NotificationEvent::BackupSucceeded)
+        }
+    }
+
+    pub fn failed(error: &str, duration_secs: u64) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            duration_secs: Some(duration_secs),
+            ..Self::new(NotificationEvent::BackupFailed)
+        }
+    }
+
+    pub fn restore_started(backup_id: &str) -> Self {
+        Self {
+            backup_id: Some(backup_id.to_string()),
+            ..Self::new(NotificationEvent::RestoreStarted)
+        }
+    }
+
+    pub fn restore_succeeded(backup_id: &str, duration_secs: u64) -> Self {
+        Self {
+            backup_id: Some(backup_id.to_string()),
+            duration_secs: Some(duration_secs),
+            ..Self::new(NotificationEvent::RestoreSucceeded)
+        }
+    }
+
+    pub fn restore_failed(backup_id: &str, error: &str, duration_secs: u64) -> Self {
+        Self {
+            backup_id: Some(backup_id.to_string()),
+            error: Some(error.to_string()),
+            duration_secs: Some(duration_secs),
+            ..Self::new(NotificationEvent::RestoreFailed)
+        }
+    }
+
+    /// A scrub found `damaged` files in `backup_id`; `file_count` is the
+    /// backup's size in files
+    pub fn scrub_failed(backup_id: &str, damaged: usize, file_count: usize) -> Self {
+        Self {
+            backup_id: Some(backup_id.to_string()),
+            file_count: Some(file_count),
+            error: Some(format!("{} damaged file(s)", damaged)),
+            ..Self::new(NotificationEvent::ScrubFailed)
+        }
+    }
+
+    /// One-line summary, used as desktop title and email subject
+    pub fn title(&self) -> &'static str {
+        match self.event {
+            NotificationEvent::BackupStarted => "🚀 Skylock Backup Started",
+            NotificationEvent::BackupSucceeded => "Skylock Backup Complete",
+            NotificationEvent::BackupFailed => "Skylock Backup Failed",
+            NotificationEvent::RestoreStarted => "🔄 Skylock Restore Started",
+            NotificationEvent::RestoreSucceeded => "Skylock Restore Complete",
+            NotificationEvent::RestoreFailed => "Skylock Restore Failed",
+            NotificationEvent::ScrubFailed => "Skylock Scrub Found Damage",
+        }
+    }
+
+    /// Human-readable description of the event
+    pub fn body(&self) -> String {
+        let backup_id = self.backup_id.as_deref().unwrap_or("unknown");
+        let error = self.error.as_deref().unwrap_or("unknown error");
+        let duration = self.duration_secs.unwrap_or(0);
+        match self.event {
+            NotificationEvent::BackupStarted => "Backup is running...".to_string(),
+            NotificationEvent::BackupSucceeded => match self.file_count {
+                Some(files) => format!(
+                    "✅ Backed up {} files ({}) in {}s",
+                    files, skylock_core::ByteSize(self.bytes.unwrap_or(0)), duration
+                ),
+                None => format!("✅ Backed up {} in {}s", skylock_core::ByteSize(self.bytes.unwrap_or(0)), duration),
+            },
+            NotificationEvent::BackupFailed => format!("❌ Backup failed: {}", error),
+            NotificationEvent::RestoreStarted => format!("Restoring backup: {}", backup_id),
+            NotificationEvent::RestoreSucceeded => format!("✅ Restored backup {} in {}s", backup_id, duration),
+            NotificationEvent::RestoreFailed => format!("❌ Restore failed: {}", error),
+            NotificationEvent::ScrubFailed => format!("❌ Scrub found {} in backup {}", error, backup_id),
+        }
+    }
 }
 
-/// Notify backup failed
-pub fn notify_backup_failed(error: &str) -> Result<()> {
-    let body = format!("❌ Backup failed: {}", error);
-    send_notification("Skylock Backup Failed", &body, false)
+/// Send `payload` to every channel its event is routed to, in the
+/// background
+///
+/// Delivery, webhook retries included, runs on its own task so a slow or
+/// unreachable channel never holds up the operation being reported, and
+/// failures are logged rather than propagated. Await the returned handle
+/// where the process may exit before delivery finishes.
+pub fn notify(config: &NotificationsConfig, payload: WebhookPayload) -> tokio::task::JoinHandle<()> {
+    let config = config.clone();
+    tokio::spawn(async move { deliver(&config, payload).await })
 }
 
-/// Notify restore started
-pub fn notify_restore_started(backup_id: &str) -> Result<()> {
-    send_notification(
-        "🔄 Skylock Restore Started",
-        &format!("Restoring backup: {}", backup_id),
-        true,
-    )
+/// Send `payload` to its channels, logging failures
+async fn deliver(config: &NotificationsConfig, payload: WebhookPayload) {
+    let severity = payload.event.severity();
+    for channel in config.channels_for(payload.event) {
+        let result = match channel {
+            NotificationChannel::Desktop => send_notification(payload.title(), &payload.body(), severity < NotificationSeverity::Error),
+            NotificationChannel::Webhook => send_webhook(config, &payload).await,
+            NotificationChannel::Email => match &config.email {
+                Some(email) => send_email(email, &payload).await,
+                None => Err(anyhow::anyhow!("no [notifications.email] settings")),
+            },
+            NotificationChannel::Log => {
+                log_notification(&payload);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            warn!("Failed to deliver {:?} notification to {:?}: {}", payload.event, channel, e);
+        }
+    }
 }
 
-/// Notify restore completed
-pub fn notify_restore_completed(files: usize, duration_secs: u64) -> Result<()> {
-    let body = format!("✅ Restored {} files in {}s", files, duration_secs);
-    send_notification("Skylock Restore Complete", &body, true)
+/// Write a notification to the application log, at a level matching its
+/// severity
+fn log_notification(payload: &WebhookPayload) {
+    match payload.event.severity() {
+        NotificationSeverity::Info => info!("{}: {}", payload.title(), payload.body()),
+        NotificationSeverity::Warning => warn!("{}: {}", payload.title(), payload.body()),
+        NotificationSeverity::Error | NotificationSeverity::Critical => error!("{}: {}", payload.title(), payload.body()),
+    }
 }
 
-/// Notify restore failed
-pub fn notify_restore_failed(error: &str) -> Result<()> {
-    let body = format!("❌ Restore failed: {}", error);
-    send_notification("Skylock Restore Failed", &body, false)
+/// Mail a notification to the configured recipients
+pub async fn send_email(config: &EmailConfig, payload: &WebhookPayload) -> Result<()> {
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+    let mut message = Message::builder()
+        .from(config.from.parse()?)
+        .subject(payload.title());
+    for recipient in &config.to {
+        message = message.to(recipient.parse()?);
+    }
+    let message = message.body(format!("{}\n\nEvent: {:?}\nTime: {}\n", payload.body(), payload.event, payload.timestamp))?;
+
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+        .port(config.smtp_port);
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport.build::<Tokio1Executor>().send(message).await?;
+    Ok(())
 }
 
-/// Notify scheduled backup
-pub fn notify_scheduled_backup() -> Result<()> {
-    send_notification(
-        "⏰ Skylock Scheduled Backup",
-        "Automated backup is running...",
-        true,
-    )
+/// Sign a webhook body as `sha256=<hex>` with the shared secret
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// POST a payload to the webhook, retrying failed deliveries
+///
+/// Does nothing when no webhook is configured.
+pub async fn send_webhook(config: &NotificationsConfig, payload: &WebhookPayload) -> Result<()> {
+    let Some(url) = config.webhook_url.as_deref() else {
+        return Ok(());
+    };
+
+    let body = serde_json::to_vec(payload)?;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.webhook_timeout_secs))
+        .build()?;
+
+    let mut last_error = None;
+    for attempt in 0..=config.webhook_retries {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt - 1))).await;
+        }
+
+        let mut request = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(secret) = &config.webhook_secret {
+            request = request.header(SIGNATURE_HEADER, sign_payload(secret, &body));
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => last_error = Some(anyhow::anyhow!("webhook returned {}", response.status())),
+            Err(e) => last_error = Some(e.into()),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("webhook delivery failed")))
 }
 
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use skylock_core::NotificationRoute;
 
-    #[test]
+    #[tokio::test]
     #[ignore] // Only run manually to avoid spamming notifications during tests
-    fn test_notifications() {
-        notify_backup_started(2).unwrap();
+    async fn test_notifications() {
+        let config = NotificationsConfig::default();
+        notify(&config, WebhookPayload::started()).await.unwrap();
         std::thread::sleep(std::time::Duration::from_secs(2));
         
-        notify_backup_completed(100, 50.5, 30).unwrap();
+        notify(&config, WebhookPayload::succeeded("backup_20250101_020000", Some(100), 52_953_088, 30)).await.unwrap();
         std::thread::sleep(std::time::Duration::from_secs(2));
         
-        notify_backup_failed("Network error").unwrap();
+        notify(&config, WebhookPayload::failed("Network error", 30)).await.unwrap();
+    }
+
+    fn route(channels: &[NotificationChannel], min_severity: Option<NotificationSeverity>) -> NotificationRoute {
+        NotificationRoute { channels: channels.to_vec(), min_severity }
+    }
+
+    #[test]
+    fn test_events_route_to_configured_channels() {
+        use NotificationChannel::*;
+
+        // Successes go to the webhook, failures to the webhook and email
+        let mut config = NotificationsConfig::default();
+        config.routes.insert(NotificationEvent::BackupSucceeded, route(&[Webhook], None));
+        config.routes.insert(NotificationEvent::BackupFailed, route(&[Webhook, Email], None));
+        config.routes.insert(NotificationEvent::RestoreFailed, route(&[Log], None));
+
+        assert_eq!(config.channels_for(NotificationEvent::BackupSucceeded), vec![Webhook]);
+        assert_eq!(config.channels_for(NotificationEvent::BackupFailed), vec![Webhook, Email]);
+        assert_eq!(config.channels_for(NotificationEvent::RestoreFailed), vec![Log]);
+        // Events without a route keep their defaults
+        assert_eq!(config.channels_for(NotificationEvent::BackupStarted), vec![Desktop, Webhook]);
+        assert_eq!(config.channels_for(NotificationEvent::RestoreStarted), vec![Desktop]);
+    }
+
+    #[test]
+    fn test_events_below_threshold_are_suppressed() {
+        use NotificationChannel::*;
+
+        // Only failures notify
+        let mut config = NotificationsConfig {
+            min_severity: NotificationSeverity::Error,
+            ..Default::default()
+        };
+        config.routes.insert(NotificationEvent::BackupSucceeded, route(&[Webhook], None));
+        assert!(config.channels_for(NotificationEvent::BackupStarted).is_empty());
+        assert!(config.channels_for(NotificationEvent::BackupSucceeded).is_empty());
+        assert_eq!(config.channels_for(NotificationEvent::BackupFailed), vec![Desktop, Webhook]);
+        assert_eq!(config.channels_for(NotificationEvent::ScrubFailed), vec![Desktop, Webhook]);
+
+        // A route's own threshold overrides the global one either way
+        config.routes.insert(NotificationEvent::BackupSucceeded, route(&[Webhook], Some(NotificationSeverity::Info)));
+        config.routes.insert(NotificationEvent::BackupFailed, route(&[Email], Some(NotificationSeverity::Critical)));
+        assert_eq!(config.channels_for(NotificationEvent::BackupSucceeded), vec![Webhook]);
+        assert!(config.channels_for(NotificationEvent::BackupFailed).is_empty());
+        assert_eq!(config.channels_for(NotificationEvent::ScrubFailed), vec![Desktop, Webhook]);
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_event_never_reaches_webhook() {
+        let (url, server) = mock_webhook(vec![200]).await;
+        let mut config = NotificationsConfig {
+            webhook_url: Some(url),
+            min_severity: NotificationSeverity::Error,
+            ..Default::default()
+        };
+        config.routes.insert(NotificationEvent::BackupStarted, route(&[NotificationChannel::Webhook], None));
+        config.routes.insert(NotificationEvent::BackupFailed, route(&[NotificationChannel::Webhook], None));
+
+        notify(&config, WebhookPayload::started()).await.unwrap();
+        notify(&config, WebhookPayload::failed("disk full", 5)).await.unwrap();
+
+        // The server answers a single request, which is the failure
+        let requests = server.await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&requests[0].1).unwrap();
+        assert_eq!(value["event"], "backup_failed");
+    }
+
+    #[test]
+    fn test_routes_parse_from_toml() {
+        let config: NotificationsConfig = toml::from_str(r#"
+            min_severity = "warning"
+
+            [routes.backup_succeeded]
+            channels = ["webhook"]
+            min_severity = "info"
+
+            [routes.backup_failed]
+            channels = ["webhook", "email"]
+
+            [email]
+            smtp_host = "smtp.example.com"
+            from = "skylock@example.com"
+            to = ["admin@example.com"]
+        "#).unwrap();
+
+        assert_eq!(config.min_severity, NotificationSeverity::Warning);
+        assert_eq!(config.channels_for(NotificationEvent::BackupSucceeded), vec![NotificationChannel::Webhook]);
+        assert_eq!(
+            config.channels_for(NotificationEvent::BackupFailed),
+            vec![NotificationChannel::Webhook, NotificationChannel::Email]
+        );
+        assert!(config.channels_for(NotificationEvent::RestoreSucceeded).is_empty());
+        let email = config.email.unwrap();
+        assert_eq!(email.smtp_port, 587);
+        assert_eq!(email.to, vec!["admin@example.com"]);
+        assert!(toml::from_str::<NotificationsConfig>("min_severity = \"loud\"").is_err());
+    }
+
+    /// Minimal HTTP server that records one request per status in `statuses`
+    async fn mock_webhook(statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<Vec<(String, Vec<u8>)>>) {
+        use skylock_hetzner::test_server::{self, Request};
+
+        let (listener, endpoint) = test_server::bind().await;
+        let url = format!("{}/hook", endpoint);
+
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = Request::read(&mut socket).await.unwrap();
+                request.respond(&mut socket, status, &[]).await;
+                requests.push((request.head, request.body));
+            }
+            requests
+        });
+
+        (url, handle)
+    }
+
+    fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+        head.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    #[tokio::test]
+    async fn test_webhook_payload_shape_and_signature() {
+        let (url, server) = mock_webhook(vec![200]).await;
+        let config = NotificationsConfig {
+            webhook_url: Some(url),
+            webhook_secret: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+
+        let payload = WebhookPayload::succeeded("backup_20250101_020000", Some(42), 1_048_576, 30);
+        send_webhook(&config, &payload).await.unwrap();
+
+        let requests = server.await.unwrap();
+        let (head, body) = &requests[0];
+        assert!(head.starts_with("POST /hook"));
+        assert_eq!(header(head, "content-type"), Some("application/json"));
+        assert_eq!(header(head, SIGNATURE_HEADER), Some(sign_payload("s3cret", body).as_str()));
+
+        let value: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(value["event"], "backup_succeeded");
+        assert_eq!(value["backup_id"], "backup_20250101_020000");
+        assert_eq!(value["file_count"], 42);
+        assert_eq!(value["bytes"], 1_048_576);
+        assert_eq!(value["duration_secs"], 30);
+        assert!(value["error"].is_null());
+        assert!(value["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_scrub_payload_shape() {
+        let value = serde_json::to_value(WebhookPayload::scrub_failed("backup_20250101_020000", 2, 40)).unwrap();
+        assert_eq!(value["event"], "scrub_failed");
+        assert_eq!(value["backup_id"], "backup_20250101_020000");
+        assert_eq!(value["file_count"], 40);
+        assert_eq!(value["error"], "2 damaged file(s)");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries_then_succeeds() {
+        let (url, server) = mock_webhook(vec![500, 200]).await;
+        let config = NotificationsConfig {
+            webhook_url: Some(url),
+            webhook_retries: 1,
+            ..Default::default()
+        };
+
+        send_webhook(&config, &WebhookPayload::failed("disk full", 5)).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(header(&requests[1].0, SIGNATURE_HEADER).is_none());
+        let value: serde_json::Value = serde_json::from_slice(&requests[1].1).unwrap();
+        assert_eq!(value["event"], "backup_failed");
+        assert_eq!(value["error"], "disk full");
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_webhook_does_not_fail() {
+        // Bind then drop to get a port nothing listens on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+
+        let config = NotificationsConfig {
+            webhook_url: Some(url),
+            webhook_timeout_secs: 1,
+            webhook_retries: 1,
+            ..Default::default()
+        };
+
+        assert!(send_webhook(&config, &WebhookPayload::started()).await.is_err());
+        // The dispatcher used by the backup swallows the error
+        let mut config = config;
+        config.routes.insert(NotificationEvent::BackupStarted, route(&[NotificationChannel::Webhook], None));
+        notify(&config, WebhookPayload::started()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stalled_webhook_delivers_in_background() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let mut config = NotificationsConfig {
+            webhook_url: Some(url),
+            webhook_timeout_secs: 1,
+            webhook_retries: 0,
+            ..Default::default()
+        };
+        config.routes.insert(NotificationEvent::BackupStarted, route(&[NotificationChannel::Webhook], None));
+
+        // The caller carries on while delivery waits out the timeout
+        let started = std::time::Instant::now();
+        let delivery = notify(&config, WebhookPayload::started());
+        assert!(started.elapsed() < Duration::from_millis(100));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!delivery.is_finished());
+
+        delivery.await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_disabled_without_url() {
+        send_webhook(&NotificationsConfig::default(), &WebhookPayload::started()).await.unwrap();
     }
 }
@@ -0,0 +1,600 @@
+use anyhow::Result;
+use serde::Serialize;
+use skylock_backup::{BackupDiff, BackupMetadata, ChangeType, FileChange, FoundFile, RepairReport, SkippedBackup, VerificationResult};
+use skylock_hybrid::deduplication::DuplicationReport;
+
+/// Exit code for a failed command not covered by a more specific code
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Exit code when the configuration is missing, unreadable or incomplete;
+/// clap also exits with 2 on invalid command-line usage
+pub const EXIT_CONFIG: i32 = 2;
+
+/// Exit code when the storage credentials or the encryption key are rejected
+pub const EXIT_AUTH: i32 = 3;
+
+/// Exit code when the storage server can't be reached
+pub const EXIT_NETWORK: i32 = 4;
+
+/// Exit code when stored data fails an integrity check, including a
+/// verification that ran to completion but found problems
+pub const EXIT_INTEGRITY: i32 = 5;
+
+/// Category of a command failure, reported as the process exit code and as
+/// the `code` of a JSON error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    General,
+    Config,
+    Auth,
+    Network,
+    Integrity,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::General => EXIT_FAILURE,
+            ErrorKind::Config => EXIT_CONFIG,
+            ErrorKind::Auth => EXIT_AUTH,
+            ErrorKind::Network => EXIT_NETWORK,
+            ErrorKind::Integrity => EXIT_INTEGRITY,
+        }
+    }
+
+    /// Category of a library error, if it has one
+    fn of_cause(cause: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        if cause.is::<VerificationFailed>() {
+            return Some(ErrorKind::Integrity);
+        }
+        if cause.is::<ConfigCheckFailed>() {
+            return Some(ErrorKind::Config);
+        }
+        if let Some(e) = cause.downcast_ref::<CliError>() {
+            return Some(e.kind);
+        }
+        if let Some(e) = cause.downcast_ref::<skylock_backup::SkylockError>() {
+            return match e {
+                skylock_backup::SkylockError::Integrity(_) => Some(ErrorKind::Integrity),
+                skylock_backup::SkylockError::Encryption(_)
+                | skylock_backup::SkylockError::Crypto(_)
+                | skylock_backup::SkylockError::WrongKey(_) => Some(ErrorKind::Auth),
+                skylock_backup::SkylockError::Core(core) => Self::of_core(core),
+                _ => None,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<skylock_core::SkylockError>() {
+            return Self::of_core(e);
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return (e.is_connect() || e.is_timeout()).then_some(ErrorKind::Network);
+        }
+        None
+    }
+
+    fn of_core(error: &skylock_core::SkylockError) -> Option<Self> {
+        use skylock_core::{SkylockError, StorageErrorType};
+        match error {
+            SkylockError::Config(_) | SkylockError::Configuration => Some(ErrorKind::Config),
+            SkylockError::Network(_) => Some(ErrorKind::Network),
+            SkylockError::Security | SkylockError::Encryption(_) => Some(ErrorKind::Auth),
+            SkylockError::Backup(skylock_core::BackupErrorType::VerificationFailed)
+            | SkylockError::Backup(skylock_core::BackupErrorType::CorruptBackup) => Some(ErrorKind::Integrity),
+            SkylockError::Storage(storage) => match storage {
+                StorageErrorType::AuthenticationFailed
+                | StorageErrorType::AccessDenied
+                | StorageErrorType::PermissionDenied => Some(ErrorKind::Auth),
+                StorageErrorType::ConnectionFailed(_)
+                | StorageErrorType::NetworkTimeout
+                | StorageErrorType::StorageBoxUnavailable => Some(ErrorKind::Network),
+                StorageErrorType::ConfigError => Some(ErrorKind::Config),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Failure detected by a command handler itself, e.g. missing credentials
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct CliError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl CliError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into() }
+    }
+}
+
+/// Output format for commands that support machine-readable output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable, decorated output
+    #[default]
+    Text,
+    /// Structured JSON on stdout
+    Json,
+}
+
+impl OutputFormat {
+    pub fn is_json(self) -> bool {
+        self == OutputFormat::Json
+    }
+}
+
+/// Returned by `verify` when the backup has missing or corrupted files.
+/// The verification report has already been printed when this is raised.
+#[derive(Debug, thiserror::Error)]
+#[error("Backup {0} failed verification")]
+pub struct VerificationFailed(pub String);
+
+/// Returned by `doctor` and `test` when at least one check failed. The
+/// checklist has already been printed when this is raised.
+#[derive(Debug, thiserror::Error)]
+#[error("{0} check(s) failed")]
+pub struct DoctorFailed(pub usize);
+
+/// Returned by `check-config` when at least one field failed. The per-field
+/// report has already been printed when this is raised.
+#[derive(Debug, thiserror::Error)]
+#[error("{0} configuration check(s) failed")]
+pub struct ConfigCheckFailed(pub usize);
+
+/// Output of `list --format json`
+#[derive(Debug, Serialize)]
+pub struct BackupListReport<'a> {
+    pub count: usize,
+    pub backups: Vec<&'a BackupMetadata>,
+}
+
+/// Output of `find --format json`
+#[derive(Debug, Serialize)]
+pub struct FindReport<'a> {
+    pub glob: &'a str,
+    pub count: usize,
+    /// Matching files, each with the backups holding it, oldest first
+    pub files: &'a [FoundFile],
+    /// Backups left out because their manifests couldn't be read
+    pub skipped: &'a [SkippedBackup],
+}
+
+/// Output of `verify --format json`
+#[derive(Debug, Serialize)]
+pub struct VerifyReport<'a> {
+    /// "quick" or "full"
+    pub mode: &'static str,
+    /// Overall outcome, same as the exit status
+    pub success: bool,
+    #[serde(flatten)]
+    pub result: &'a VerificationResult,
+    /// Files rewritten by `--repair`, before the result was re-verified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair: Option<&'a RepairReport>,
+}
+
+/// Output of `changes --format json`
+#[derive(Debug, Serialize)]
+pub struct ChangesReport<'a> {
+    /// No previous backup index exists; every file would be backed up
+    pub first_backup: bool,
+    pub summary: ChangeCounts,
+    /// Individual changes (empty with `--summary`)
+    pub changes: Vec<&'a FileChange>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ChangeCounts {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    pub metadata_changed: usize,
+    pub moved: usize,
+    pub total: usize,
+}
+
+impl ChangeCounts {
+    pub fn from_changes(changes: &[FileChange]) -> Self {
+        let mut counts = Self { total: changes.len(), ..Default::default() };
+        for change in changes {
+            match change.change_type {
+                ChangeType::Added => counts.added += 1,
+                ChangeType::Removed => counts.removed += 1,
+                ChangeType::Modified => counts.modified += 1,
+                ChangeType::MetadataChanged => counts.metadata_changed += 1,
+                ChangeType::Moved => counts.moved += 1,
+            }
+        }
+        counts
+    }
+}
+
+/// Output of `duplicates --format json`
+#[derive(Debug, Serialize)]
+pub struct DuplicatesReport<'a> {
+    /// False when the scan was interrupted; the figures then cover only the
+    /// files scanned
+    pub complete: bool,
+    pub files_scanned: u64,
+    pub bytes_scanned: u64,
+    pub total_blocks: u64,
+    pub unique_blocks: u64,
+    pub unique_size: u64,
+    pub space_saved: u64,
+    pub deduplication_ratio: f64,
+    /// Blocks saving the most space, largest saving first
+    pub top_blocks: Vec<DuplicateBlockEntry>,
+    /// Identical files saving the most space, largest saving first
+    pub top_files: Vec<DuplicateFilesEntry<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateBlockEntry {
+    pub hash: String,
+    pub size: usize,
+    pub occurrences: u32,
+    pub space_saved: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateFilesEntry<'a> {
+    pub hash: String,
+    pub size: u64,
+    pub space_saved: u64,
+    pub paths: &'a [std::path::PathBuf],
+}
+
+impl<'a> DuplicatesReport<'a> {
+    pub fn from_report(report: &'a DuplicationReport) -> Self {
+        Self {
+            complete: report.complete,
+            files_scanned: report.files_scanned,
+            bytes_scanned: report.bytes_scanned,
+            total_blocks: report.stats.total_blocks,
+            unique_blocks: report.stats.unique_blocks,
+            unique_size: report.stats.unique_size,
+            space_saved: report.stats.space_saved,
+            deduplication_ratio: report.stats.deduplication_ratio,
+            top_blocks: report.top_blocks.iter().map(|block| DuplicateBlockEntry {
+                hash: hex::encode(block.hash),
+                size: block.size,
+                occurrences: block.occurrences,
+                space_saved: block.space_saved(),
+            }).collect(),
+            top_files: report.top_files.iter().map(|files| DuplicateFilesEntry {
+                hash: hex::encode(files.hash),
+                size: files.size,
+                space_saved: files.space_saved(),
+                paths: &files.paths,
+            }).collect(),
+        }
+    }
+}
+
+/// Error output in JSON mode:
+/// `{"error": {"code", "message", "causes", "exit_code"}}`
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    /// Stable category name: general, config, auth, network or integrity
+    pub code: ErrorKind,
+    pub message: String,
+    pub causes: Vec<String>,
+    pub exit_code: i32,
+}
+
+impl ErrorReport {
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        Self {
+            error: ErrorBody {
+                code: error_kind(error),
+                message: error.to_string(),
+                causes: error.chain().skip(1).map(|cause| cause.to_string()).collect(),
+                exit_code: exit_code(error),
+            },
+        }
+    }
+}
+
+/// Drop change types excluded by `diff --filter` from the JSON diff
+pub fn filter_diff(diff: &mut BackupDiff, filter: Option<&[String]>) {
+    let Some(filter) = filter else { return };
+    let wants = |kind: &str| filter.iter().any(|t| t == kind);
+
+    if !wants("added") {
+        diff.files_added.clear();
+    }
+    if !wants("removed") {
+        diff.files_removed.clear();
+    }
+    if !wants("modified") {
+        diff.files_modified.clear();
+    }
+    if !wants("moved") {
+        diff.files_moved.clear();
+    }
+}
+
+/// Keep only the `top` modified files with the largest size change in the
+/// JSON diff, ordered by that change
+pub fn keep_largest_modifications(diff: &mut BackupDiff, top: usize) {
+    diff.files_modified = diff.largest_modifications(top).into_iter().cloned().collect();
+}
+
+/// Category of a failed command, from the outermost error in its chain
+/// that has one
+pub fn error_kind(error: &anyhow::Error) -> ErrorKind {
+    error.chain().find_map(ErrorKind::of_cause).unwrap_or(ErrorKind::General)
+}
+
+/// Process exit code for a failed command
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error_kind(error).exit_code()
+}
+
+/// Print a value as pretty JSON on stdout
+pub fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Print a command error as JSON on stdout, unless the command already
+/// reported its outcome (failed verification, doctor or config checks)
+pub fn print_json_error(error: &anyhow::Error) {
+    if error.is::<VerificationFailed>() || error.is::<DoctorFailed>() || error.is::<ConfigCheckFailed>() {
+        return;
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&ErrorReport::from_error(error)) {
+        println!("{}", json);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use skylock_backup::{BackupManifest, FileEntry, FileVerification};
+    use std::path::PathBuf;
+
+    fn to_value<T: Serialize>(value: &T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    fn manifest(id: &str, files: &[(&str, &str)]) -> BackupManifest {
+        let files: Vec<FileEntry> = files.iter().map(|(path, hash)| FileEntry {
+            local_path: PathBuf::from(path),
+            remote_path: format!("/skylock/backups/{}{}.enc", id, path),
+            size: 100,
+            hash: hash.to_string(),
+            compressed: false,
+            compression: None,
+            encrypted: true,
+            key_context: None,
+            timestamp: Utc::now(),
+            modified: None,
+            changed: None,
+            xattrs: Vec::new(),
+            blob_origin: None,
+            chunks: Vec::new(),
+            framing: None,
+            blob_hash: None,
+        }).collect();
+
+        BackupManifest {
+            schema_version: BackupManifest::SCHEMA_VERSION,
+            backup_id: id.to_string(),
+            timestamp: Utc::now(),
+            file_count: files.len(),
+            total_size: files.len() as u64 * 100,
+            files,
+            source_paths: vec![PathBuf::from("/data")],
+            base_backup_id: None,
+            encryption_version: "v2".to_string(),
+            kdf_params: None,
+            aead_algorithm: Default::default(),
+            hash_algorithm: Default::default(),
+            key_version: None,
+            signature: None,
+            backup_chain_version: 0,
+            encrypted_path_map: None,
+            storage_tier: Default::default(),
+            deleted_paths: Vec::new(),
+            consolidated_from: Vec::new(),
+            directories: Vec::new(),
+            tags: Vec::new(),
+            note: None,
+            skipped_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_list_json_shape() {
+        let backups = vec![BackupMetadata {
+            id: "20250101_020000".to_string(),
+            timestamp: Utc::now(),
+            source_paths: vec![PathBuf::from("/data")],
+            size: 1024,
+            is_vss: false,
+            storage_tier: Default::default(),
+            tags: Vec::new(),
+            note: None,
+        }];
+        let report = BackupListReport { count: backups.len(), backups: backups.iter().collect() };
+        let value = to_value(&report);
+
+        assert_eq!(value["count"], 1);
+        let backup = &value["backups"][0];
+        assert_eq!(backup["id"], "20250101_020000");
+        assert_eq!(backup["size"], 1024);
+        assert_eq!(backup["source_paths"], json!(["/data"]));
+        assert_eq!(backup["is_vss"], false);
+        assert!(backup["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_find_json_shape() {
+        let mut finder = skylock_backup::FileFinder::new(skylock_backup::PathGlob::new("/data/a"));
+        finder.scan(&manifest("old", &[("/data/a", "h1"), ("/data/b", "h2")]), true);
+        finder.scan(&manifest("new", &[("/data/b", "h2")]), true);
+        let found = finder.finish();
+        let value = to_value(&FindReport { glob: "/data/a", count: found.len(), files: &found, skipped: &[] });
+
+        assert_eq!(value["glob"], "/data/a");
+        assert_eq!(value["count"], 1);
+        assert_eq!(value["files"][0]["path"], "/data/a");
+        let versions = value["files"][0]["versions"].as_array().unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0]["backup_id"], "old");
+        assert_eq!(versions[0]["size"], 100);
+        assert_eq!(versions[0]["hash"], "h1");
+        assert!(versions[0]["backup_time"].is_string());
+        assert!(versions[0].get("modified").is_none());
+    }
+
+    #[test]
+    fn test_diff_json_shape() {
+        let old = manifest("old", &[("/data/a", "h1"), ("/data/b", "h2")]);
+        let new = manifest("new", &[("/data/a", "h1-changed"), ("/data/c", "h3")]);
+        let mut diff = BackupDiff::compare(&old, &new);
+        let value = to_value(&diff);
+
+        assert_eq!(value["backup_id_old"], "old");
+        assert_eq!(value["backup_id_new"], "new");
+        assert_eq!(value["summary"]["files_added_count"], 1);
+        assert_eq!(value["summary"]["files_removed_count"], 1);
+        assert_eq!(value["summary"]["files_modified_count"], 1);
+        assert_eq!(value["files_added"][0]["path"], "/data/c");
+        assert_eq!(value["files_removed"][0]["path"], "/data/b");
+        assert_eq!(value["files_modified"][0]["path"], "/data/a");
+
+        // --filter keeps the summary but drops unrequested listings
+        filter_diff(&mut diff, Some(&["added".to_string()]));
+        let value = to_value(&diff);
+        assert_eq!(value["files_added"].as_array().unwrap().len(), 1);
+        assert!(value["files_removed"].as_array().unwrap().is_empty());
+        assert_eq!(value["summary"]["files_removed_count"], 1);
+
+        // --top trims the modified listing to the largest changes
+        let mut diff = BackupDiff::compare(&old, &new);
+        keep_largest_modifications(&mut diff, 0);
+        assert!(to_value(&diff)["files_modified"].as_array().unwrap().is_empty());
+        assert_eq!(to_value(&diff)["summary"]["files_modified_count"], 1);
+    }
+
+    #[test]
+    fn test_verify_json_shape() {
+        let result = VerificationResult {
+            backup_id: "20250101_020000".to_string(),
+            manifest_valid: true,
+            total_files: 2,
+            files_exist: 1,
+            files_verified: 0,
+            files_with_errors: 1,
+            file_results: vec![FileVerification {
+                path: PathBuf::from("/data/missing"),
+                exists: false,
+                hash_verified: None,
+                error: Some("File not found".to_string()),
+                repairable: None,
+            }],
+            passed: false,
+            incomplete: false,
+        };
+        let value = to_value(&VerifyReport { mode: "quick", success: result.is_success(), result: &result, repair: None });
+
+        assert_eq!(value["mode"], "quick");
+        assert!(value.get("repair").is_none());
+        assert_eq!(value["success"], false);
+        assert_eq!(value["backup_id"], "20250101_020000");
+        assert_eq!(value["total_files"], 2);
+        assert_eq!(value["files_with_errors"], 1);
+        assert_eq!(value["file_results"][0]["path"], "/data/missing");
+        assert_eq!(value["file_results"][0]["exists"], false);
+        assert_eq!(value["file_results"][0]["hash_verified"], Value::Null);
+        assert!(value["file_results"][0].get("repairable").is_none());
+    }
+
+    #[test]
+    fn test_changes_json_shape() {
+        let changes = vec![
+            FileChange {
+                path: PathBuf::from("/data/new"),
+                change_type: ChangeType::Added,
+                old_info: None,
+                new_info: None,
+            },
+            FileChange {
+                path: PathBuf::from("/data/touched"),
+                change_type: ChangeType::MetadataChanged,
+                old_info: None,
+                new_info: None,
+            },
+        ];
+        let report = ChangesReport {
+            first_backup: false,
+            summary: ChangeCounts::from_changes(&changes),
+            changes: changes.iter().collect(),
+        };
+        let value = to_value(&report);
+
+        assert_eq!(value["first_backup"], false);
+        assert_eq!(value["summary"], json!({
+            "added": 1, "removed": 0, "modified": 0, "metadata_changed": 1, "moved": 0, "total": 2
+        }));
+        assert_eq!(value["changes"][0]["path"], "/data/new");
+        assert_eq!(value["changes"][0]["change_type"], "added");
+        assert_eq!(value["changes"][1]["change_type"], "metadata_changed");
+    }
+
+    #[test]
+    fn test_error_json_shape_and_exit_codes() {
+        let error = anyhow::anyhow!("connection refused").context("Failed to list backups");
+        let value = to_value(&ErrorReport::from_error(&error));
+
+        assert_eq!(value, json!({
+            "error": {
+                "code": "general",
+                "message": "Failed to list backups",
+                "causes": ["connection refused"],
+                "exit_code": EXIT_FAILURE,
+            }
+        }));
+
+        let failed = anyhow::Error::new(VerificationFailed("20250101_020000".to_string()));
+        assert_eq!(exit_code(&failed), EXIT_INTEGRITY);
+        assert_ne!(EXIT_INTEGRITY, EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_error_code_taken_from_wrapped_library_error() {
+        let storage = skylock_backup::SkylockError::Core(skylock_core::SkylockError::Storage(
+            skylock_core::StorageErrorType::AuthenticationFailed,
+        ));
+        let error = anyhow::Error::from(storage).context("Failed to list backups");
+        assert_eq!(exit_code(&error), EXIT_AUTH);
+        assert_eq!(to_value(&ErrorReport::from_error(&error))["error"]["code"], "auth");
+
+        let corrupt = anyhow::Error::from(skylock_backup::SkylockError::Integrity("chunk 0: hash mismatch".to_string()))
+            .context("Restore operation failed");
+        assert_eq!(error_kind(&corrupt), ErrorKind::Integrity);
+
+        let wrong_key = anyhow::Error::from(skylock_backup::SkylockError::WrongKey("backup 20250101_020000".to_string()))
+            .context("Restore operation failed");
+        assert_eq!(exit_code(&wrong_key), EXIT_AUTH);
+
+        let config = anyhow::Error::new(CliError::new(ErrorKind::Config, "Hetzner credentials required"));
+        assert_eq!(exit_code(&config), EXIT_CONFIG);
+        assert_eq!(
+            [EXIT_FAILURE, EXIT_CONFIG, EXIT_AUTH, EXIT_NETWORK, EXIT_INTEGRITY].iter().collect::<std::collections::HashSet<_>>().len(),
+            5
+        );
+    }
+}
@@ -11,6 +11,7 @@ use std::path::Path;
 pub mod windows;
 #[cfg(unix)]
 pub mod unix;
+pub mod service;
 
 /// Platform-specific backup functionality
 #[async_trait]
@@ -0,0 +1,434 @@
+//! Running the daemon as a platform service
+//!
+//! `skylock install-service` registers `skylock --config <file> --daemon`
+//! with the service manager of the platform it runs on:
+//!
+//! - Linux: a systemd user unit, started with the user's session (or at
+//!   boot once lingering is enabled with `loginctl enable-linger`)
+//! - macOS: a launchd agent, started at login
+//! - Windows: a Scheduled Task, started at boot as the installing user
+//!
+//! The daemon keeps to `backup.schedule` itself, so the service only has to
+//! start it and restart it after a crash. Every definition names the binary
+//! and config file by absolute path, because service managers start it
+//! from another working directory.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the systemd unit and the Scheduled Task
+pub const SERVICE_NAME: &str = "skylock";
+
+/// Label of the launchd agent
+pub const LAUNCHD_LABEL: &str = "dev.nullme.skylock";
+
+/// What the service runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSpec {
+    /// Absolute path of the skylock binary
+    pub binary: PathBuf,
+    /// Absolute path of the config file passed with `--config`
+    pub config: PathBuf,
+    /// `backup.schedule`, shown in the service description
+    pub schedule: String,
+    /// Account the Scheduled Task runs as (`DOMAIN\user`)
+    pub user: String,
+}
+
+impl ServiceSpec {
+    /// The running binary with `config` (default: the platform config file)
+    pub fn current(config: Option<PathBuf>, schedule: &str) -> Result<Self> {
+        let binary = std::env::current_exe().context("Failed to locate the skylock binary")?;
+        let config = config.unwrap_or_else(skylock_core::Config::default_path);
+        let config = if config.is_absolute() {
+            config
+        } else {
+            std::env::current_dir().context("Failed to resolve the config path")?.join(config)
+        };
+        let user = match (std::env::var("USERDOMAIN"), std::env::var("USERNAME").or_else(|_| std::env::var("USER"))) {
+            (Ok(domain), Ok(user)) => format!("{}\\{}", domain, user),
+            (_, Ok(user)) => user,
+            _ => String::new(),
+        };
+
+        Ok(Self {
+            binary: binary.canonicalize().unwrap_or(binary),
+            config,
+            schedule: schedule.to_string(),
+            user,
+        })
+    }
+
+    /// Arguments the service starts the binary with
+    pub fn args(&self) -> Vec<String> {
+        vec![
+            "--config".to_string(),
+            self.config.to_string_lossy().into_owned(),
+            "--daemon".to_string(),
+        ]
+    }
+
+    fn description(&self) -> String {
+        format!("Skylock backup daemon (schedule: {})", self.schedule)
+    }
+}
+
+/// A service manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceKind {
+    Systemd,
+    Launchd,
+    ScheduledTask,
+}
+
+impl ServiceKind {
+    /// The service manager of this platform
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            Self::ScheduledTask
+        } else if cfg!(target_os = "macos") {
+            Self::Launchd
+        } else {
+            Self::Systemd
+        }
+    }
+
+    /// The service definition for `spec`
+    pub fn render(self, spec: &ServiceSpec) -> String {
+        match self {
+            Self::Systemd => systemd_unit(spec),
+            Self::Launchd => launchd_plist(spec),
+            Self::ScheduledTask => scheduled_task_xml(spec),
+        }
+    }
+
+    /// Where the definition is installed; a Scheduled Task is kept by the
+    /// Task Scheduler itself
+    pub fn definition_path(self) -> Result<Option<PathBuf>> {
+        let dirs = directories::BaseDirs::new().context("Failed to locate the home directory")?;
+        Ok(match self {
+            Self::Systemd => Some(dirs.config_dir().join("systemd/user").join(format!("{}.service", SERVICE_NAME))),
+            Self::Launchd => Some(dirs.home_dir().join("Library/LaunchAgents").join(format!("{}.plist", LAUNCHD_LABEL))),
+            Self::ScheduledTask => None,
+        })
+    }
+}
+
+/// A systemd user unit for `spec`
+pub fn systemd_unit(spec: &ServiceSpec) -> String {
+    let exec = std::iter::once(spec.binary.to_string_lossy().into_owned())
+        .chain(spec.args())
+        .map(|arg| systemd_quote(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "[Unit]\n\
+         Description={}\n\
+         Wants=network-online.target\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=60\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        spec.description().replace('%', "%%"),
+        exec,
+    )
+}
+
+/// A launchd agent for `spec`
+pub fn launchd_plist(spec: &ServiceSpec) -> String {
+    let arguments: String = std::iter::once(spec.binary.to_string_lossy().into_owned())
+        .chain(spec.args())
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(&arg)))
+        .collect();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n\
+         <dict>\n\
+         \x20   <key>Label</key>\n\
+         \x20   <string>{}</string>\n\
+         \x20   <key>ProgramArguments</key>\n\
+         \x20   <array>\n\
+         {}\
+         \x20   </array>\n\
+         \x20   <key>RunAtLoad</key>\n\
+         \x20   <true/>\n\
+         \x20   <key>KeepAlive</key>\n\
+         \x20   <dict>\n\
+         \x20       <key>SuccessfulExit</key>\n\
+         \x20       <false/>\n\
+         \x20   </dict>\n\
+         \x20   <key>ThrottleInterval</key>\n\
+         \x20   <integer>60</integer>\n\
+         </dict>\n\
+         </plist>\n",
+        LAUNCHD_LABEL,
+        arguments,
+    )
+}
+
+/// A Task Scheduler definition for `spec`, for `schtasks /Create /XML`
+pub fn scheduled_task_xml(spec: &ServiceSpec) -> String {
+    let arguments = spec.args().iter().map(|arg| windows_quote(arg)).collect::<Vec<_>>().join(" ");
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\r\n\
+         <Task version=\"1.2\" xmlns=\"http://schemas.microsoft.com/windows/2004/02/mit/task\">\r\n\
+         \x20 <RegistrationInfo>\r\n\
+         \x20   <Description>{}</Description>\r\n\
+         \x20 </RegistrationInfo>\r\n\
+         \x20 <Triggers>\r\n\
+         \x20   <BootTrigger>\r\n\
+         \x20     <Enabled>true</Enabled>\r\n\
+         \x20   </BootTrigger>\r\n\
+         \x20 </Triggers>\r\n\
+         \x20 <Principals>\r\n\
+         \x20   <Principal id=\"Author\">\r\n\
+         \x20     <UserId>{}</UserId>\r\n\
+         \x20     <LogonType>S4U</LogonType>\r\n\
+         \x20     <RunLevel>LeastPrivilege</RunLevel>\r\n\
+         \x20   </Principal>\r\n\
+         \x20 </Principals>\r\n\
+         \x20 <Settings>\r\n\
+         \x20   <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>\r\n\
+         \x20   <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>\r\n\
+         \x20   <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>\r\n\
+         \x20   <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>\r\n\
+         \x20   <RestartOnFailure>\r\n\
+         \x20     <Interval>PT1M</Interval>\r\n\
+         \x20     <Count>3</Count>\r\n\
+         \x20   </RestartOnFailure>\r\n\
+         \x20 </Settings>\r\n\
+         \x20 <Actions Context=\"Author\">\r\n\
+         \x20   <Exec>\r\n\
+         \x20     <Command>{}</Command>\r\n\
+         \x20     <Arguments>{}</Arguments>\r\n\
+         \x20   </Exec>\r\n\
+         \x20 </Actions>\r\n\
+         </Task>\r\n",
+        xml_escape(&spec.description()),
+        xml_escape(&spec.user),
+        xml_escape(&spec.binary.to_string_lossy()),
+        xml_escape(&arguments),
+    )
+}
+
+/// Write the definition for `spec` and register it with the service
+/// manager, returning where the definition was written
+pub fn install(kind: ServiceKind, spec: &ServiceSpec) -> Result<Option<PathBuf>> {
+    let definition = kind.render(spec);
+    match kind.definition_path()? {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            std::fs::write(&path, definition)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            match kind {
+                ServiceKind::Systemd => {
+                    run("systemctl", &["--user", "daemon-reload"])?;
+                    run("systemctl", &["--user", "enable", "--now", &format!("{}.service", SERVICE_NAME)])?;
+                }
+                _ => {
+                    // Reloading replaces an agent that is already loaded
+                    let plist = path.to_string_lossy();
+                    let _ = run("launchctl", &["unload", &plist]);
+                    run("launchctl", &["load", "-w", &plist])?;
+                }
+            }
+            Ok(Some(path))
+        }
+        None => {
+            // schtasks reads the definition from a file, in UTF-16 as declared
+            let file = tempfile::Builder::new().prefix("skylock-task").suffix(".xml").tempfile()
+                .context("Failed to write the task definition")?;
+            let bytes: Vec<u8> = std::iter::once(0xFEFF)
+                .chain(definition.encode_utf16())
+                .flat_map(u16::to_le_bytes)
+                .collect();
+            std::fs::write(file.path(), bytes).context("Failed to write the task definition")?;
+            run("schtasks", &["/Create", "/TN", SERVICE_NAME, "/XML", &file.path().to_string_lossy(), "/F"])?;
+            Ok(None)
+        }
+    }
+}
+
+/// Stop the service and remove it from the service manager, returning the
+/// definition file removed, if any
+pub fn uninstall(kind: ServiceKind) -> Result<Option<PathBuf>> {
+    let path = kind.definition_path()?;
+    match kind {
+        ServiceKind::Systemd => {
+            let path = path.as_deref().filter(|path| path.exists());
+            let Some(path) = path else { bail!("No skylock service is installed") };
+            run("systemctl", &["--user", "disable", "--now", &format!("{}.service", SERVICE_NAME)])?;
+            remove_definition(path)?;
+            run("systemctl", &["--user", "daemon-reload"])?;
+        }
+        ServiceKind::Launchd => {
+            let path = path.as_deref().filter(|path| path.exists());
+            let Some(path) = path else { bail!("No skylock service is installed") };
+            let _ = run("launchctl", &["unload", "-w", &path.to_string_lossy()]);
+            remove_definition(path)?;
+        }
+        ServiceKind::ScheduledTask => {
+            run("schtasks", &["/Delete", "/TN", SERVICE_NAME, "/F"])?;
+        }
+    }
+    Ok(path)
+}
+
+fn remove_definition(path: &Path) -> Result<()> {
+    std::fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        bail!(
+            "`{} {}` failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Quote an `ExecStart=` argument; `%` starts a specifier in unit files
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%");
+    format!("\"{}\"", escaped)
+}
+
+/// Quote a command line argument the way Windows programs split them
+fn windows_quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ServiceSpec {
+        ServiceSpec {
+            binary: PathBuf::from("/opt/sky lock/bin/skylock"),
+            config: PathBuf::from("/home/me/.config/skylock/config.toml"),
+            schedule: "0 2 * * *".to_string(),
+            user: "HOST\\me".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_systemd_unit_runs_daemon_with_binary_and_config() {
+        let unit = systemd_unit(&spec());
+        assert!(unit.contains(
+            "ExecStart=\"/opt/sky lock/bin/skylock\" \"--config\" \"/home/me/.config/skylock/config.toml\" \"--daemon\"\n"
+        ));
+        assert!(unit.contains("Description=Skylock backup daemon (schedule: 0 2 * * *)\n"));
+        assert!(unit.contains("Restart=on-failure\n"));
+        assert!(unit.contains("[Install]\nWantedBy=default.target\n"));
+
+        // Specifiers and quotes in paths are escaped
+        let odd = ServiceSpec { config: PathBuf::from("/srv/100%/\"cfg\".toml"), ..spec() };
+        assert!(systemd_unit(&odd).contains("\"/srv/100%%/\\\"cfg\\\".toml\""));
+    }
+
+    #[test]
+    fn test_launchd_plist_runs_daemon_with_binary_and_config() {
+        let plist = launchd_plist(&spec());
+        assert!(plist.contains("<string>dev.nullme.skylock</string>"));
+        assert!(plist.contains(
+            "    <array>\n\
+             \x20       <string>/opt/sky lock/bin/skylock</string>\n\
+             \x20       <string>--config</string>\n\
+             \x20       <string>/home/me/.config/skylock/config.toml</string>\n\
+             \x20       <string>--daemon</string>\n\
+             \x20   </array>\n"
+        ));
+        assert!(plist.contains("<key>RunAtLoad</key>\n    <true/>"));
+
+        let odd = ServiceSpec { config: PathBuf::from("/Users/me/R&D <cfg>.toml"), ..spec() };
+        assert!(launchd_plist(&odd).contains("<string>/Users/me/R&amp;D &lt;cfg&gt;.toml</string>"));
+    }
+
+    #[test]
+    fn test_scheduled_task_runs_daemon_at_boot() {
+        let spec = ServiceSpec {
+            binary: PathBuf::from(r"C:\Program Files\Skylock\skylock.exe"),
+            config: PathBuf::from(r"C:\Users\me\AppData\Roaming\skylock\config.toml"),
+            ..spec()
+        };
+        let task = scheduled_task_xml(&spec);
+        assert!(task.contains("<BootTrigger>"));
+        assert!(task.contains("<UserId>HOST\\me</UserId>"));
+        assert!(task.contains(r"<Command>C:\Program Files\Skylock\skylock.exe</Command>"));
+        assert!(task.contains(r"<Arguments>--config C:\Users\me\AppData\Roaming\skylock\config.toml --daemon</Arguments>"));
+        assert!(task.contains("<Description>Skylock backup daemon (schedule: 0 2 * * *)</Description>"));
+        assert!(task.contains("<ExecutionTimeLimit>PT0S</ExecutionTimeLimit>"));
+
+        // Paths with spaces are quoted for the command line, then escaped
+        let spaced = ServiceSpec { config: PathBuf::from(r"C:\My Files\config.toml"), ..spec };
+        assert!(scheduled_task_xml(&spaced).contains(r"<Arguments>--config &quot;C:\My Files\config.toml&quot; --daemon</Arguments>"));
+    }
+
+    #[test]
+    fn test_windows_quote() {
+        assert_eq!(windows_quote("plain"), "plain");
+        assert_eq!(windows_quote(""), "\"\"");
+        assert_eq!(windows_quote(r"C:\a b\"), r#""C:\a b\\""#);
+        assert_eq!(windows_quote(r#"say "hi""#), r#""say \"hi\"""#);
+    }
+
+    #[test]
+    fn test_current_spec_uses_absolute_paths() {
+        let spec = ServiceSpec::current(Some(PathBuf::from("relative/config.toml")), "0 3 * * *").unwrap();
+        assert!(spec.binary.is_absolute());
+        assert!(spec.config.is_absolute());
+        assert!(spec.config.ends_with("relative/config.toml"));
+        assert_eq!(spec.args().last().map(String::as_str), Some("--daemon"));
+    }
+}
@@ -100,20 +100,7 @@ impl ErrorHandler {
     }
 
     pub fn format_file_size(bytes: u64) -> String {
-        const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
-        let mut size = bytes as f64;
-        let mut unit_index = 0;
-
-        while size >= 1024.0 && unit_index < UNITS.len() - 1 {
-            size /= 1024.0;
-            unit_index += 1;
-        }
-
-        if unit_index == 0 {
-            format!("{} {}", size as u64, UNITS[unit_index])
-        } else {
-            format!("{:.2} {}", size, UNITS[unit_index])
-        }
+        skylock_core::ByteSize(bytes).to_string()
     }
 
     pub fn format_duration(duration: std::time::Duration) -> String {
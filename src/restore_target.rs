@@ -0,0 +1,171 @@
+//! Where `skylock restore` puts files when no `--target` is given
+//!
+//! Restores land below `--output-dir`, else `backup.restore_dir`, else the
+//! current directory, in a directory named by `--name-template`. Templates
+//! may hold `/` to nest restores (`{source}/{date}`) and these placeholders:
+//!
+//! - `{backup_id}`: ID of the restored backup
+//! - `{date}`, `{time}`, `{timestamp}`: when the restore runs, as
+//!   `2024-01-31`, `020000` and `20240131_020000` (UTC)
+//! - `{source}`: last component of the backup's first source path
+//!
+//! Values can't add `/` or `..`, so the name always stays below the output
+//! directory. A target that already exists gets a `_2`, `_3`, … suffix
+//! rather than being restored into.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use std::path::{Component, Path, PathBuf};
+
+/// Name used when `--name-template` is not given
+pub const DEFAULT_NAME_TEMPLATE: &str = "restore_{timestamp}";
+
+/// Suffixes tried for a target that already exists before giving up
+const MAX_COLLISION_SUFFIX: u32 = 1000;
+
+/// Values substituted into a name template
+#[derive(Debug, Clone)]
+pub struct TemplateValues<'a> {
+    pub backup_id: &'a str,
+    pub now: DateTime<Utc>,
+    /// First source path of the backup, needed only for `{source}`
+    pub source: Option<&'a Path>,
+}
+
+/// Whether `template` uses `{source}`, which needs the backup's manifest
+pub fn needs_source(template: &str) -> bool {
+    template.contains("{source}")
+}
+
+/// Relative path `template` renders to for `values`
+///
+/// Fails on unknown or unclosed placeholders and on templates that would
+/// leave the output directory or render to nothing.
+pub fn render(template: &str, values: &TemplateValues) -> Result<PathBuf> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .ok_or_else(|| anyhow!("Invalid name template '{}': unclosed '{{'", template))?;
+        let placeholder = &rest[start + 1..start + end];
+        let value = match placeholder {
+            "backup_id" => values.backup_id.to_string(),
+            "date" => values.now.format("%Y-%m-%d").to_string(),
+            "time" => values.now.format("%H%M%S").to_string(),
+            "timestamp" => values.now.format("%Y%m%d_%H%M%S").to_string(),
+            "source" => source_name(values.source),
+            other => bail!(
+                "Invalid name template '{}': unknown placeholder {{{}}} (use {{backup_id}}, {{date}}, {{time}}, {{timestamp}} or {{source}})",
+                template, other
+            ),
+        };
+        rendered.push_str(&sanitize(&value));
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+
+    let path = PathBuf::from(&rendered);
+    if path.as_os_str().is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        bail!("Invalid name template '{}': renders to '{}', which is not a relative path below the output directory", template, rendered);
+    }
+    Ok(path)
+}
+
+/// `{source}` value: the last component of `source`, or "root"
+fn source_name(source: Option<&Path>) -> String {
+    source
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "root".to_string())
+}
+
+/// Replace characters that would split or escape a path component
+fn sanitize(value: &str) -> String {
+    let value: String = value.chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':') || c.is_control() { '_' } else { c })
+        .collect();
+    match value.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => value,
+    }
+}
+
+/// `parent/name`, with a numeric suffix if that already exists
+pub fn unused_target(parent: &Path, name: &Path) -> Result<PathBuf> {
+    let target = parent.join(name);
+    if !target.exists() {
+        return Ok(target);
+    }
+    let base = target.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    (2..=MAX_COLLISION_SUFFIX)
+        .map(|n| target.with_file_name(format!("{}_{}", base, n)))
+        .find(|candidate| !candidate.exists())
+        .ok_or_else(|| anyhow!("{} and its first {} numbered variants already exist", target.display(), MAX_COLLISION_SUFFIX - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn values(source: Option<&Path>) -> TemplateValues<'_> {
+        TemplateValues {
+            backup_id: "backup_20240130_020000",
+            now: Utc.with_ymd_and_hms(2024, 1, 31, 2, 3, 4).unwrap(),
+            source,
+        }
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let source = Path::new("/home/alice/Documents");
+        let render = |template| render(template, &values(Some(source))).unwrap();
+
+        assert_eq!(render(DEFAULT_NAME_TEMPLATE), PathBuf::from("restore_20240131_020304"));
+        assert_eq!(render("{backup_id}"), PathBuf::from("backup_20240130_020000"));
+        assert_eq!(render("{source}-{date}_{time}"), PathBuf::from("Documents-2024-01-31_020304"));
+        assert_eq!(render("{source}/{date}/{backup_id}"), PathBuf::from("Documents/2024-01-31/backup_20240130_020000"));
+        assert_eq!(render("plain name"), PathBuf::from("plain name"));
+
+        // Backups of "/" have no last component
+        assert_eq!(super::render("{source}", &values(Some(Path::new("/")))).unwrap(), PathBuf::from("root"));
+        assert_eq!(super::render("{source}", &values(None)).unwrap(), PathBuf::from("root"));
+        assert!(needs_source("{source}/{date}"));
+        assert!(!needs_source(DEFAULT_NAME_TEMPLATE));
+    }
+
+    #[test]
+    fn test_render_rejects_unsafe_templates() {
+        let invalid = |template: &str| render(template, &values(None)).unwrap_err().to_string();
+
+        assert!(invalid("{host}").contains("unknown placeholder {host}"));
+        assert!(invalid("restore_{date").contains("unclosed"));
+        for template in ["", "../{date}", "/tmp/{date}", "a/../../b", "./{date}"] {
+            assert!(invalid(template).contains("not a relative path"), "{}", template);
+        }
+
+        // Values can't add separators or parent references
+        let source = Path::new("..");
+        assert_eq!(render("{source}", &values(Some(source))).unwrap(), PathBuf::from("root"));
+        let values = TemplateValues { backup_id: "../../etc", ..values(None) };
+        assert_eq!(render("{backup_id}", &values).unwrap(), PathBuf::from(".._.._etc"));
+    }
+
+    #[test]
+    fn test_unused_target_handles_collisions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let name = Path::new("nested").join("restore_20240131");
+
+        let target = unused_target(dir.path(), &name).unwrap();
+        assert_eq!(target, dir.path().join("nested").join("restore_20240131"));
+
+        std::fs::create_dir_all(&target).unwrap();
+        let second = unused_target(dir.path(), &name).unwrap();
+        assert_eq!(second, dir.path().join("nested").join("restore_20240131_2"));
+
+        // Files count as taken too
+        std::fs::write(&second, b"").unwrap();
+        assert_eq!(unused_target(dir.path(), &name).unwrap(), dir.path().join("nested").join("restore_20240131_3"));
+    }
+}
@@ -0,0 +1,83 @@
+//! Cooperative shutdown of the daemon
+//!
+//! On Ctrl-C the running backup is asked to stop rather than killed: it
+//! finishes the files it is uploading, saves its resume point and returns.
+//! Only when that takes longer than the shutdown timeout is the task
+//! aborted, which leaves the resume point of the last checkpoint.
+
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// How long a backup may take to wind down before it is aborted
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the daemon's work ended on shutdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownOutcome {
+    /// The work stopped on its own within the timeout
+    Completed,
+    /// The timeout elapsed and the work was aborted
+    Aborted,
+}
+
+/// Coordinates stopping the daemon's background work
+pub struct ShutdownManager {
+    token: CancellationToken,
+    timeout: Duration,
+}
+
+impl ShutdownManager {
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_SHUTDOWN_TIMEOUT)
+    }
+
+    /// Manager that waits up to `timeout` for work to stop
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            token: CancellationToken::new(),
+            timeout,
+        }
+    }
+
+    /// Token cancelled when shutdown starts, for work to stop on
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Signal shutdown and wait for `task` to stop, aborting it once the
+    /// timeout elapses
+    pub async fn shutdown<T>(&self, mut task: JoinHandle<T>) -> ShutdownOutcome {
+        self.token.cancel();
+        match tokio::time::timeout(self.timeout, &mut task).await {
+            Ok(_) => {
+                info!("Background work stopped cleanly");
+                ShutdownOutcome::Completed
+            }
+            Err(_) => {
+                warn!("Background work still running after {:?}, aborting", self.timeout);
+                task.abort();
+                ShutdownOutcome::Aborted
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_waits_then_aborts() {
+        let manager = ShutdownManager::with_timeout(Duration::from_secs(5));
+        let token = manager.token();
+        let task = tokio::spawn(async move { token.cancelled().await });
+        assert_eq!(manager.shutdown(task).await, ShutdownOutcome::Completed);
+
+        // Work that ignores the token is aborted after the timeout
+        let manager = ShutdownManager::with_timeout(Duration::from_millis(50));
+        let task = tokio::spawn(tokio::time::sleep(Duration::from_secs(60)));
+        assert_eq!(manager.shutdown(task).await, ShutdownOutcome::Aborted);
+    }
+}
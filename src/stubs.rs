@@ -5,20 +5,6 @@ use anyhow::Result;
 use std::path::PathBuf;
 
 // Stubs for missing types
-pub struct CredentialManager {
-    key_path: PathBuf,
-}
-
-impl CredentialManager {
-    pub fn new(key_path: PathBuf) -> Result<Self> {
-        Ok(Self { key_path })
-    }
-    
-    pub async fn get_credential(&self, _name: &str) -> Result<String> {
-        Ok("stub-credential".to_string())
-    }
-}
-
 pub struct RecoveryManager {
     state_path: PathBuf,
 }
@@ -29,20 +15,12 @@ impl RecoveryManager {
     }
 }
 
-pub struct ShutdownManager;
-
-impl ShutdownManager {
-    pub fn new() -> Self {
-        Self
-    }
-}
-
 pub struct HetznerClient {
     webdav_client: skylock_hetzner::HetznerWebDAVClient,
 }
 
 impl HetznerClient {
-    pub fn new(config: skylock_core::HetznerConfig, _key: &str) -> Result<Self> {
+    pub fn new(config: skylock_core::HetznerConfig) -> Result<Self> {
         let webdav_config = skylock_hetzner::WebDAVConfig {
             base_url: format!("https://{}", config.endpoint),
             username: config.username,
@@ -78,24 +56,6 @@ impl HetznerClient {
     }
 }
 
-pub struct BackupManager;
-
-impl BackupManager {
-    pub fn new(_config: skylock_core::BackupConfig, _client: HetznerClient) -> Self {
-        Self
-    }
-    
-    pub async fn create_backup(&self) -> Result<BackupMetadata> {
-        Ok(BackupMetadata {
-            id: "stub-backup-id".to_string(),
-        })
-    }
-}
-
-pub struct BackupMetadata {
-    pub id: String,
-}
-
 pub struct SyncthingClient;
 
 impl SyncthingClient {
@@ -0,0 +1,377 @@
+//! Time-based filtering for backup listings
+//!
+//! Parses absolute (RFC 3339 or `YYYY-MM-DD`) and relative (`7d`, `24h`)
+//! times and narrows a backup list down by name pattern, tag, source path,
+//! time window and count. `skylock list` shows the matches and
+//! `skylock restore --latest` restores the newest one.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use skylock_backup::BackupMetadata;
+use std::path::{Path, PathBuf};
+
+/// Parse a point in time given as RFC 3339, a plain date (midnight UTC) or
+/// a duration before `now`
+///
+/// Relative forms are a number followed by a unit: `s`, `m`, `h`, `d` or
+/// `w` (e.g. `30m`, `24h`, `7d`, `2w`).
+pub fn parse_relative_time(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let input = input.trim();
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+
+    let invalid = || anyhow!(
+        "Invalid time '{}': use RFC 3339 (2024-01-31T02:00:00Z), a date (2024-01-31) or a relative time (24h, 7d)",
+        input
+    );
+    let unit_start = input.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = input.split_at(unit_start);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let unit_seconds: i64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    // Duration::seconds panics beyond i64::MAX milliseconds
+    let seconds = amount.checked_mul(unit_seconds)
+        .filter(|seconds| *seconds <= i64::MAX / 1000)
+        .ok_or_else(invalid)?;
+
+    now.checked_sub_signed(Duration::seconds(seconds)).ok_or_else(invalid)
+}
+
+/// Whether a backup ID matches a `--pattern`: a glob over the whole ID when
+/// it has `*` or `?`, otherwise a substring
+pub fn pattern_matches(pattern: &str, id: &str) -> bool {
+    if !pattern.contains(['*', '?']) {
+        return id.contains(pattern);
+    }
+
+    // Wildcard match, backtracking to the last `*`
+    let (pattern, id): (Vec<char>, Vec<char>) = (pattern.chars().collect(), id.chars().collect());
+    let (mut p, mut i) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while i < id.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == id[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((star_p, star_i)) = star {
+            p = star_p + 1;
+            i = star_i + 1;
+            star = Some((star_p, star_i + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Filters for `skylock list` and `skylock restore --latest`, applied together
+#[derive(Debug, Clone, Default)]
+pub struct BackupFilter {
+    /// Glob (with `*` or `?`) or substring the backup ID must match
+    pub pattern: Option<String>,
+    /// Only backups created at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only backups created at or before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Keep at most this many of the newest matching backups
+    pub limit: Option<usize>,
+    /// Tag the backup must carry
+    pub tag: Option<String>,
+    /// Path the backup's sources must cover
+    pub source: Option<PathBuf>,
+}
+
+impl BackupFilter {
+    /// Build a filter from command line arguments, resolving relative times
+    /// against `now`
+    pub fn from_args(
+        pattern: Option<String>,
+        since: Option<&str>,
+        until: Option<&str>,
+        limit: Option<usize>,
+        now: DateTime<Utc>,
+    ) -> Result<Self> {
+        let since = since.map(|s| parse_relative_time(s, now)).transpose()?;
+        let until = until.map(|u| parse_relative_time(u, now)).transpose()?;
+        if let (Some(since), Some(until)) = (since, until) {
+            if since > until {
+                return Err(anyhow!("--since ({}) is after --until ({})", since, until));
+            }
+        }
+        Ok(Self { pattern, since, until, limit, tag: None, source: None })
+    }
+
+    /// Only keep backups carrying `tag`
+    pub fn with_tag(mut self, tag: Option<String>) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Only keep backups with a source path that is `source` or one of its
+    /// parents, i.e. backups holding `source`
+    pub fn with_source(mut self, source: Option<PathBuf>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Whether any filter is set
+    pub fn is_active(&self) -> bool {
+        self.pattern.is_some() || self.since.is_some() || self.until.is_some() || self.limit.is_some()
+            || self.tag.is_some() || self.source.is_some()
+    }
+
+    /// Whether one of a backup's source paths holds `path`
+    fn covers(backup: &BackupMetadata, path: &Path) -> bool {
+        backup.source_paths.iter().any(|source| path.starts_with(source))
+    }
+
+    /// Whether a backup passes the pattern, tag, source and time window
+    pub fn matches(&self, backup: &BackupMetadata) -> bool {
+        self.pattern.as_ref().map_or(true, |p| pattern_matches(p, &backup.id))
+            && self.tag.as_ref().map_or(true, |tag| backup.has_tag(tag))
+            && self.source.as_ref().map_or(true, |source| Self::covers(backup, source))
+            && self.since.map_or(true, |since| backup.timestamp >= since)
+            && self.until.map_or(true, |until| backup.timestamp <= until)
+    }
+
+    /// Matching backups, newest first, cut to the limit
+    pub fn apply(&self, mut backups: Vec<BackupMetadata>) -> Vec<BackupMetadata> {
+        backups.retain(|backup| self.matches(backup));
+        backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        if let Some(limit) = self.limit {
+            backups.truncate(limit);
+        }
+        backups
+    }
+
+    /// Newest matching backup
+    pub fn latest(&self, backups: Vec<BackupMetadata>) -> Option<BackupMetadata> {
+        self.apply(backups).into_iter().next()
+    }
+
+    /// The filters in effect, for messages such as "no backup matches"
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(pattern) = &self.pattern {
+            parts.push(format!("pattern '{}'", pattern));
+        }
+        if let Some(tag) = &self.tag {
+            parts.push(format!("tag '{}'", tag));
+        }
+        if let Some(source) = &self.source {
+            parts.push(format!("source {}", source.display()));
+        }
+        if let Some(since) = self.since {
+            parts.push(format!("since {}", since.format("%Y-%m-%d %H:%M:%S UTC")));
+        }
+        if let Some(until) = self.until {
+            parts.push(format!("until {}", until.format("%Y-%m-%d %H:%M:%S UTC")));
+        }
+        if parts.is_empty() {
+            "no filters".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::path::PathBuf;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap()
+    }
+
+    fn backup(id: &str, hours_ago: i64) -> BackupMetadata {
+        BackupMetadata {
+            id: id.to_string(),
+            timestamp: now() - Duration::hours(hours_ago),
+            source_paths: vec![PathBuf::from("/data")],
+            size: 1024,
+            is_vss: false,
+            storage_tier: Default::default(),
+            tags: Vec::new(),
+            note: None,
+        }
+    }
+
+    fn ids(backups: &[BackupMetadata]) -> Vec<&str> {
+        backups.iter().map(|b| b.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_absolute_times() {
+        assert_eq!(
+            parse_relative_time("2024-01-31T02:00:00Z", now()).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 31, 2, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_relative_time("2024-01-31T04:00:00+02:00", now()).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 31, 2, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_relative_time("2024-01-31", now()).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_relative_times() {
+        assert_eq!(parse_relative_time("90s", now()).unwrap(), now() - Duration::seconds(90));
+        assert_eq!(parse_relative_time("30m", now()).unwrap(), now() - Duration::minutes(30));
+        assert_eq!(parse_relative_time("24h", now()).unwrap(), now() - Duration::hours(24));
+        assert_eq!(parse_relative_time(" 7d ", now()).unwrap(), now() - Duration::days(7));
+        assert_eq!(parse_relative_time("2w", now()).unwrap(), now() - Duration::weeks(2));
+        assert_eq!(parse_relative_time("0h", now()).unwrap(), now());
+    }
+
+    #[test]
+    fn test_parse_invalid_times() {
+        for input in ["", "7", "d", "7y", "-7d", "7 d", "yesterday", "2024-13-01", "99999999999999w"] {
+            assert!(parse_relative_time(input, now()).is_err(), "{} should be rejected", input);
+        }
+    }
+
+    fn backups() -> Vec<BackupMetadata> {
+        vec![
+            backup("backup_a", 1),
+            backup("backup_b", 30),
+            backup("backup_c", 72),
+            backup("backup_d", 200),
+            backup("manual_e", 50),
+        ]
+    }
+
+    #[test]
+    fn test_filter_by_time_window_and_limit() {
+        let filter = BackupFilter::from_args(None, Some("7d"), Some("24h"), None, now()).unwrap();
+        assert_eq!(ids(&filter.apply(backups())), vec!["backup_b", "manual_e", "backup_c"]);
+
+        // Filters compose with the pattern and the limit keeps the newest
+        let filter = BackupFilter::from_args(Some("backup".to_string()), Some("7d"), None, Some(2), now()).unwrap();
+        assert_eq!(ids(&filter.apply(backups())), vec!["backup_a", "backup_b"]);
+
+        // Bounds are inclusive
+        let filter = BackupFilter::from_args(None, Some("30h"), Some("30h"), None, now()).unwrap();
+        assert_eq!(ids(&filter.apply(backups())), vec!["backup_b"]);
+
+        let filter = BackupFilter::default();
+        assert!(!filter.is_active());
+        assert_eq!(filter.apply(backups()).len(), 5);
+    }
+
+    #[test]
+    fn test_filter_by_tag() {
+        let tagged = || {
+            let mut backups = backups();
+            backups[1].tags = vec!["pre-upgrade".to_string(), "keep".to_string()];
+            backups[3].tags = vec!["pre-upgrade".to_string()];
+            backups
+        };
+
+        let filter = BackupFilter::default().with_tag(Some("pre-upgrade".to_string()));
+        assert!(filter.is_active());
+        assert_eq!(ids(&filter.apply(tagged())), vec!["backup_b", "backup_d"]);
+
+        // Tags compose with the other filters
+        let filter = BackupFilter::from_args(None, Some("7d"), None, None, now()).unwrap()
+            .with_tag(Some("pre-upgrade".to_string()));
+        assert_eq!(ids(&filter.apply(tagged())), vec!["backup_b"]);
+
+        let filter = BackupFilter::default().with_tag(Some("missing".to_string()));
+        assert!(filter.apply(tagged()).is_empty());
+    }
+
+    #[test]
+    fn test_pattern_globs() {
+        assert!(pattern_matches("backup", "backup_20240101_020000"));
+        assert!(pattern_matches("0101", "backup_20240101_020000"));
+        assert!(pattern_matches("backup_2024*", "backup_20240101_020000"));
+        assert!(pattern_matches("*_02????", "backup_20240101_020000"));
+        assert!(pattern_matches("b*0*0", "backup_20240101_020000"));
+        assert!(!pattern_matches("backup_2023*", "backup_20240101_020000"));
+        // Globs match the whole ID
+        assert!(!pattern_matches("2024*", "backup_20240101_020000"));
+        assert!(!pattern_matches("backup_?", "backup_20240101_020000"));
+    }
+
+    #[test]
+    fn test_latest_selection() {
+        let backups = || {
+            let mut backups = vec![
+                backup("backup_20240615_110000", 1),
+                backup("backup_20240614_060000", 30),
+                backup("backup_20240613_220000", 38),
+                backup("manual_20240612_120000", 48),
+                backup("backup_20240610_120000", 120),
+            ];
+            backups[0].source_paths = vec![PathBuf::from("/home/alice/projects")];
+            backups[1].tags = vec!["pre-upgrade".to_string()];
+            backups[2].source_paths = vec![PathBuf::from("/etc"), PathBuf::from("/home")];
+            backups[3].tags = vec!["pre-upgrade".to_string()];
+            backups[4].source_paths = vec![PathBuf::from("/etc")];
+            backups
+        };
+        let latest = |filter: BackupFilter| filter.latest(backups()).map(|b| b.id);
+
+        // Newest overall, regardless of list order
+        let mut shuffled = backups();
+        shuffled.reverse();
+        assert_eq!(BackupFilter::default().latest(shuffled).unwrap().id, "backup_20240615_110000");
+
+        let tag = || BackupFilter::default().with_tag(Some("pre-upgrade".to_string()));
+        assert_eq!(latest(tag()).as_deref(), Some("backup_20240614_060000"));
+
+        // A source matches when a backed up path holds it
+        let source = |path: &str| BackupFilter::default().with_source(Some(PathBuf::from(path)));
+        assert_eq!(latest(source("/etc/nginx")).as_deref(), Some("backup_20240613_220000"));
+        assert_eq!(latest(source("/home/alice/projects/site")).as_deref(), Some("backup_20240615_110000"));
+        assert_eq!(latest(source("/home/bob")).as_deref(), Some("backup_20240613_220000"));
+        // Only the exact path components count: /etcetera isn't below /etc
+        assert_eq!(latest(source("/etcetera")).as_deref(), None);
+
+        let pattern = |p: &str| BackupFilter::from_args(Some(p.to_string()), None, None, None, now()).unwrap();
+        assert_eq!(latest(pattern("manual_*")).as_deref(), Some("manual_20240612_120000"));
+        assert_eq!(latest(pattern("*_1200??")).as_deref(), Some("manual_20240612_120000"));
+        assert_eq!(latest(pattern("backup_202406")).as_deref(), Some("backup_20240615_110000"));
+
+        // Filters combine: the newest /etc backup older than two days
+        let filter = BackupFilter::from_args(None, None, Some("2d"), None, now()).unwrap()
+            .with_source(Some(PathBuf::from("/etc")));
+        assert_eq!(latest(filter).as_deref(), Some("backup_20240610_120000"));
+        let filter = tag().with_source(Some(PathBuf::from("/etc")));
+        assert_eq!(latest(filter).as_deref(), None);
+
+        assert!(BackupFilter::default().latest(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(BackupFilter::default().describe(), "no filters");
+        let filter = BackupFilter::from_args(Some("daily_*".to_string()), None, None, None, now()).unwrap()
+            .with_tag(Some("keep".to_string()))
+            .with_source(Some(PathBuf::from("/etc")));
+        assert_eq!(filter.describe(), "pattern 'daily_*', tag 'keep', source /etc");
+    }
+
+    #[test]
+    fn test_since_after_until_rejected() {
+        assert!(BackupFilter::from_args(None, Some("1d"), Some("7d"), None, now()).is_err());
+    }
+}